@@ -68,15 +68,19 @@ pub struct Conditioner {
     pub(crate) jitter_ms: u16,
     /// Percentage of packet loss
     pub(crate) packet_loss: f32,
+    /// Percentage of duplicated packets
+    #[serde(default)]
+    pub(crate) packet_duplication: f32,
 }
 
 impl Conditioner {
     pub fn build(&self) -> LinkConditionerConfig {
-        LinkConditionerConfig {
-            incoming_latency: Duration::from_millis(self.latency_ms as u64),
-            incoming_jitter: Duration::from_millis(self.jitter_ms as u64),
-            incoming_loss: self.packet_loss,
-        }
+        LinkConditionerConfig::new(
+            Duration::from_millis(self.latency_ms as u64),
+            Duration::from_millis(self.jitter_ms as u64),
+            self.packet_loss,
+        )
+        .with_duplication(self.packet_duplication)
     }
 }
 
@@ -145,11 +149,14 @@ pub(crate) fn build_server_netcode_config(
     transport_config: server::ServerTransport,
 ) -> server::NetConfig {
     let conditioner = conditioner.map_or(None, |c| {
-        Some(LinkConditionerConfig {
-            incoming_latency: Duration::from_millis(c.latency_ms as u64),
-            incoming_jitter: Duration::from_millis(c.jitter_ms as u64),
-            incoming_loss: c.packet_loss,
-        })
+        Some(
+            LinkConditionerConfig::new(
+                Duration::from_millis(c.latency_ms as u64),
+                Duration::from_millis(c.jitter_ms as u64),
+                c.packet_loss,
+            )
+            .with_duplication(c.packet_duplication),
+        )
     });
     let netcode_config = server::NetcodeConfig::default()
         .with_protocol_id(shared.protocol_id)
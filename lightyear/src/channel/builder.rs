@@ -8,15 +8,22 @@ use crate::channel::receivers::sequenced_reliable::SequencedReliableReceiver;
 use crate::channel::receivers::sequenced_unreliable::SequencedUnreliableReceiver;
 use crate::channel::receivers::unordered_reliable::UnorderedReliableReceiver;
 use crate::channel::receivers::unordered_unreliable::UnorderedUnreliableReceiver;
+use crate::channel::receivers::ChannelReceive;
 use crate::channel::receivers::ChannelReceiver;
 use crate::channel::senders::reliable::ReliableSender;
 use crate::channel::senders::sequenced_unreliable::SequencedUnreliableSender;
 use crate::channel::senders::unordered_unreliable::UnorderedUnreliableSender;
 use crate::channel::senders::unordered_unreliable_with_acks::UnorderedUnreliableWithAcksSender;
+use crate::channel::senders::ChannelSend;
 use crate::channel::senders::ChannelSender;
 #[cfg(feature = "trace")]
 use crate::channel::stats::send::ChannelSendStats;
-use crate::prelude::ChannelKind;
+use crate::packet::error::PacketError;
+use crate::packet::message::MessageId;
+use crate::prelude::{ChannelKind, Tick};
+use crate::transport::middleware::compression::CompressionConfig;
+use bytes::Bytes;
+use tracing::error;
 
 /// A ChannelContainer is a struct that implements the [`Channel`] trait
 #[derive(Debug)]
@@ -114,6 +121,27 @@ impl ChannelContainer {
             sender_stats: ChannelSendStats::default(),
         }
     }
+
+    /// Buffer a message to be sent on this channel, compressing it first if the channel uses compression
+    pub(crate) fn buffer_send(
+        &mut self,
+        message: Bytes,
+        priority: f32,
+    ) -> Result<Option<MessageId>, PacketError> {
+        let message = self.setting.compression.compress(message)?;
+        Ok(self.sender.buffer_send(message, priority)?)
+    }
+
+    /// Read the next message received on this channel, decompressing it if the channel uses compression
+    pub(crate) fn read_message(&mut self) -> Option<(Tick, Bytes)> {
+        loop {
+            let (tick, message) = self.receiver.read_message()?;
+            match self.setting.compression.decompress(message) {
+                Ok(message) => return Some((tick, message)),
+                Err(e) => error!("could not decompress message: {:?}", e),
+            }
+        }
+    }
 }
 
 /// [`ChannelSettings`] are used to specify how the [`Channel`] behaves (reliability, ordering, direction)
@@ -125,6 +153,31 @@ pub struct ChannelSettings {
     pub send_frequency: Duration,
     /// Sets the priority of the channel. The final priority of a message will be `MessagePriority * ChannelPriority`
     pub priority: f32,
+    /// Compression applied to each message sent on this channel.
+    ///
+    /// This is independent of the compression of the packets that can be set in the io config;
+    /// it can be useful to compress only the channels that carry bulky data. For example, to compress
+    /// the world state but not the voice data, which is already compressed:
+    /// ```rust,ignore
+    /// app.add_channel::<WorldStateChannel>(ChannelSettings {
+    ///     mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+    ///     compression: CompressionConfig::Zstd { level: 3 },
+    ///     ..default()
+    /// });
+    /// app.add_channel::<VoiceChannel>(ChannelSettings {
+    ///     mode: ChannelMode::UnorderedUnreliable,
+    ///     ..default()
+    /// });
+    /// ```
+    /// The compression of the io config should then be left to [`CompressionConfig::None`]: a packet can contain
+    /// the messages of several channels, so the packet compression can't be disabled for a single channel.
+    pub compression: CompressionConfig,
+    /// If set, the server keeps the last `n` messages sent to each room on this channel
+    /// (with [`send_message_to_room`](crate::prelude::server::ConnectionManager::send_message_to_room)),
+    /// and sends them to the clients that join the room later (chat history, events of the current match, etc.)
+    ///
+    /// The channel should be reliable, so that the history is not lost.
+    pub replay_on_join: Option<usize>,
 }
 
 impl Default for ChannelSettings {
@@ -133,6 +186,8 @@ impl Default for ChannelSettings {
             mode: ChannelMode::UnorderedUnreliable,
             send_frequency: Duration::default(),
             priority: 1.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         }
     }
 }
@@ -147,7 +202,10 @@ pub enum ChannelMode {
     /// Messages may arrive out-of-order, or not at all
     UnorderedUnreliable,
     /// Same as unordered unreliable, but only the newest message is ever accepted, older messages
-    /// are ignored
+    /// are ignored (instead of being buffered).
+    ///
+    /// Useful for continuous state where only the latest value matters, such as analog inputs or
+    /// cursor positions
     SequencedUnreliable,
     /// Messages may arrive out-of-order, but we make sure (with retries, acks) that the message
     /// will arrive
@@ -191,12 +249,22 @@ pub enum ChannelDirection {
     Bidirectional,
 }
 
+/// Retransmission strategy of a reliable channel
+///
+/// New settings can be added without a breaking change, so the struct is created with
+/// [`ReliableSettings::new`] or [`ReliableSettings::default`] and configured with its builder methods.
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct ReliableSettings {
-    /// Duration to wait before resending a packet if it has not been acked
+    /// Duration to wait before resending a packet if it has not been acked, as a multiple of the RTT
     pub rtt_resend_factor: f32,
     /// Minimum duration to wait before resending a packet if it has not been acked
     pub rtt_resend_min_delay: Duration,
+    /// If set, the packets that have not been acked are resent at this fixed interval, instead of a
+    /// multiple of the RTT
+    pub resend_interval: Option<Duration>,
+    /// If set, the remote peer is disconnected when a message was resent this many times without being acked
+    pub max_retries: Option<u32>,
 }
 
 impl Default for ReliableSettings {
@@ -204,12 +272,39 @@ impl Default for ReliableSettings {
         Self {
             rtt_resend_factor: 1.5,
             rtt_resend_min_delay: Duration::default(),
+            resend_interval: None,
+            max_retries: None,
         }
     }
 }
 
 impl ReliableSettings {
+    /// Resend the packets that have not been acked after `rtt_resend_factor` times the RTT, and at least
+    /// after `rtt_resend_min_delay`
+    pub fn new(rtt_resend_factor: f32, rtt_resend_min_delay: Duration) -> Self {
+        Self {
+            rtt_resend_factor,
+            rtt_resend_min_delay,
+            ..Default::default()
+        }
+    }
+
+    /// Resend the packets that have not been acked at a fixed interval
+    pub fn with_resend_interval(mut self, interval: Duration) -> Self {
+        self.resend_interval = Some(interval);
+        self
+    }
+
+    /// Disconnect the remote peer when a message was resent `max_retries` times without being acked
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
     pub(crate) fn resend_delay(&self, rtt: Duration) -> Duration {
+        if let Some(interval) = self.resend_interval {
+            return interval;
+        }
         let delay = rtt.mul_f32(self.rtt_resend_factor);
         std::cmp::max(delay, self.rtt_resend_min_delay)
     }
@@ -240,7 +335,30 @@ pub struct PongChannel;
 /// Default channel to send inputs from client to server. This is a Sequenced Unreliable channel.
 pub struct InputChannel;
 
+#[derive(ChannelInternal)]
+/// Channel for high-rate, loss-tolerant state sent by the clients to the server (camera position,
+/// aim direction for spectators or kill-cams, etc.).
+///
+/// This is a Sequenced Unreliable channel: only the latest state matters, so the older messages are dropped.
+/// It is separate from the [`InputChannel`] and has a lower priority, so it never competes with the inputs.
+pub struct TelemetryChannel;
+
 #[derive(ChannelInternal)]
 /// Channel to send messages related to Authority transfers
 /// This is an Ordered Reliable channel
 pub struct AuthorityChannel;
+
+#[derive(ChannelInternal)]
+/// Channel used by the client to report its [`StaticBaseline`](crate::prelude::StaticBaseline) to the server
+/// This is an Unordered Reliable channel
+pub struct BaselineChannel;
+
+#[derive(ChannelInternal)]
+/// Channel used for connection-level control messages (for example server redirects)
+/// This is an Ordered Reliable channel
+pub struct ControlChannel;
+
+#[derive(ChannelInternal)]
+/// Channel used to replicate transient entities (see [`ReplicateTransient`](crate::prelude::server::ReplicateTransient))
+/// This is an Unordered Reliable channel
+pub struct TransientChannel;
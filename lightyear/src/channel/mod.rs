@@ -3,6 +3,4 @@
 pub mod builder;
 pub(crate) mod receivers;
 pub(crate) mod senders;
-
-#[cfg(feature = "trace")]
 pub mod stats;
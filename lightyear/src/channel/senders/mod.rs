@@ -4,6 +4,7 @@ use bytes::Bytes;
 use crossbeam_channel::Receiver;
 use enum_dispatch::enum_dispatch;
 
+use crate::channel::stats::retransmission::RetransmissionStats;
 use crate::packet::message::{MessageAck, MessageId, SendMessage};
 use crate::serialize::SerializationError;
 use crate::shared::ping::manager::PingManager;
@@ -56,8 +57,38 @@ pub trait ChannelSend {
     /// has been lost by the remote peer
     fn subscribe_nacks(&mut self) -> Receiver<MessageId>;
 
+    /// Set the maximum number of bytes of a message before it is fragmented.
+    ///
+    /// Returns the ids of the messages waiting to be resent that had to be fragmented again
+    /// because the fragment size was reduced.
+    fn set_fragment_size(&mut self, fragment_size: usize) -> Vec<MessageId>;
+
     /// Send nacks to the subscribers of nacks
     fn send_nacks(&mut self, nack: MessageId);
+
+    /// Statistics about the retransmitted messages.
+    ///
+    /// Returns None if the channel doesn't retransmit messages
+    fn retransmission_stats(&self) -> Option<RetransmissionStats> {
+        None
+    }
+
+    /// Returns true if some messages were buffered but not delivered yet.
+    ///
+    /// Only reliable channels keep track of the delivery of their messages
+    fn has_pending_messages(&self) -> bool {
+        false
+    }
+
+    /// Returns true if a message was resent more than the maximum number of retries of the channel
+    /// without being acked
+    fn retries_exhausted(&self) -> bool {
+        false
+    }
+
+    /// Called when a message of the channel is written in a packet, as opposed to held back by the
+    /// priority filter or the bandwidth quota
+    fn message_written(&mut self, _message_ack: &MessageAck) {}
 }
 
 /// Enum dispatch lets us derive ChannelSend on each enum variant
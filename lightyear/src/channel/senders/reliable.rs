@@ -5,11 +5,12 @@ use std::collections::{BTreeMap, HashSet};
 use bevy::utils::Duration;
 use bytes::Bytes;
 use crossbeam_channel::{Receiver, Sender};
-use tracing::trace;
+use tracing::{error, trace};
 
 use crate::channel::builder::ReliableSettings;
 use crate::channel::senders::fragment_sender::FragmentSender;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::retransmission::RetransmissionStats;
 use crate::packet::message::{FragmentData, MessageAck, MessageId, SendMessage, SingleData};
 use crate::serialize::SerializationError;
 use crate::shared::ping::manager::PingManager;
@@ -40,6 +41,10 @@ pub struct UnackedMessageWithPriority {
     pub unacked_message: UnackedMessage,
     pub base_priority: f32,
     pub accumulated_priority: f32,
+    /// Number of times the message was resent
+    pub retries: u32,
+    /// True if the message was collected to be resent, but not written in a packet yet
+    pub retry_pending: bool,
 }
 
 /// A sender that makes sure to resend messages until it receives an ack
@@ -76,6 +81,9 @@ pub struct ReliableSender {
     /// Factor that makes sure that the priority accumulates at the same right even the channel
     /// sends messages infrequently
     priority_multiplier: f32,
+    retransmission_stats: RetransmissionStats,
+    /// True if a message was resent more than [`ReliableSettings::max_retries`] times
+    retries_exhausted: bool,
 }
 
 impl ReliableSender {
@@ -99,6 +107,36 @@ impl ReliableSender {
             current_time: WrappedTime::default(),
             timer,
             priority_multiplier: 1.0,
+            retransmission_stats: RetransmissionStats::default(),
+            retries_exhausted: false,
+        }
+    }
+}
+
+impl ReliableSender {
+    /// Store a message until it is acked, splitting it into fragments if it's too big
+    fn unacked_message(
+        fragment_sender: &FragmentSender,
+        message_id: MessageId,
+        message: Bytes,
+    ) -> Result<UnackedMessage, SerializationError> {
+        if message.len() > fragment_sender.fragment_size {
+            let fragments = fragment_sender.build_fragments(message_id, None, message)?;
+            Ok(UnackedMessage::Fragmented(
+                fragments
+                    .into_iter()
+                    .map(|fragment| FragmentAck {
+                        data: fragment,
+                        acked: false,
+                        last_sent: None,
+                    })
+                    .collect(),
+            ))
+        } else {
+            Ok(UnackedMessage::Single {
+                bytes: message,
+                last_sent: None,
+            })
         }
     }
 }
@@ -127,32 +165,15 @@ impl ChannelSend for ReliableSender {
         priority: f32,
     ) -> Result<Option<MessageId>, SerializationError> {
         let message_id = self.next_send_message_id;
-        let unacked_message = if message.len() > self.fragment_sender.fragment_size {
-            let fragments = self
-                .fragment_sender
-                .build_fragments(message_id, None, message)?;
-            UnackedMessage::Fragmented(
-                fragments
-                    .into_iter()
-                    .map(|fragment| FragmentAck {
-                        data: fragment,
-                        acked: false,
-                        last_sent: None,
-                    })
-                    .collect(),
-            )
-        } else {
-            UnackedMessage::Single {
-                bytes: message,
-                last_sent: None,
-            }
-        };
+        let unacked_message = Self::unacked_message(&self.fragment_sender, message_id, message)?;
         let unacked_message_with_priority = UnackedMessageWithPriority {
             unacked_message,
             base_priority: priority,
             // store with 0.0 accumulated priority because priority gets accumulated when we collect the messages
             // for sending (even the first time the message is sent)
             accumulated_priority: 0.0,
+            retries: 0,
+            retry_pending: false,
         };
         self.unacked_messages
             .insert(message_id, unacked_message_with_priority);
@@ -215,6 +236,14 @@ impl ChannelSend for ReliableSender {
                                 priority: unacked_message_with_priority.accumulated_priority,
                             });
                             self.message_ids_to_send.insert(message_info);
+                            if let Some(previous) = last_sent {
+                                self.retransmission_stats.add_retransmission(
+                                    false,
+                                    retransmission_delay(previous, &self.current_time),
+                                );
+                                // the retry is only counted once the message is written in a packet
+                                unacked_message_with_priority.retry_pending = true;
+                            }
                             *last_sent = Some(self.current_time);
                         }
                     }
@@ -236,9 +265,16 @@ impl ChannelSend for ReliableSender {
                                     priority: unacked_message_with_priority.accumulated_priority,
                                 });
                                 self.message_ids_to_send.insert(message_info);
+                                if let Some(previous) = &f.last_sent {
+                                    self.retransmission_stats.add_retransmission(
+                                        true,
+                                        retransmission_delay(previous, &self.current_time),
+                                    );
+                                    unacked_message_with_priority.retry_pending = true;
+                                }
                                 f.last_sent = Some(self.current_time);
                             }
-                        })
+                        });
                 }
             }
         }
@@ -327,12 +363,90 @@ impl ChannelSend for ReliableSender {
         receiver
     }
 
+    /// The unacked messages that contain a message or a fragment bigger than the new fragment size
+    /// are fragmented again, so that they can still be sent after the maximum packet size was reduced.
+    fn set_fragment_size(&mut self, fragment_size: usize) -> Vec<MessageId> {
+        let reduced = fragment_size < self.fragment_sender.fragment_size;
+        self.fragment_sender.fragment_size = fragment_size;
+        if !reduced {
+            return vec![];
+        }
+        let mut refragmented = vec![];
+        for (message_id, unacked_message_with_priority) in self.unacked_messages.iter_mut() {
+            let bytes = match &unacked_message_with_priority.unacked_message {
+                UnackedMessage::Single { bytes, .. } if bytes.len() > fragment_size => {
+                    bytes.clone()
+                }
+                UnackedMessage::Fragmented(fragment_acks)
+                    if fragment_acks
+                        .iter()
+                        .any(|f| f.data.bytes.len() > fragment_size) =>
+                {
+                    let mut bytes = Vec::new();
+                    for f in fragment_acks {
+                        bytes.extend_from_slice(&f.data.bytes);
+                    }
+                    bytes.into()
+                }
+                _ => continue,
+            };
+            match Self::unacked_message(&self.fragment_sender, *message_id, bytes) {
+                Ok(unacked_message) => {
+                    trace!(?message_id, "Fragmented an unacked message again");
+                    unacked_message_with_priority.unacked_message = unacked_message;
+                    refragmented.push(*message_id);
+                }
+                Err(e) => {
+                    error!(
+                        ?message_id,
+                        "Could not fragment an unacked message again: {e:?}"
+                    );
+                }
+            }
+        }
+        refragmented
+    }
+
     /// Send nacks to the subscribers of nacks
     fn send_nacks(&mut self, nack: MessageId) {
         for sender in &self.nack_senders {
             sender.send(nack).unwrap();
         }
     }
+
+    fn retransmission_stats(&self) -> Option<RetransmissionStats> {
+        Some(self.retransmission_stats)
+    }
+
+    fn has_pending_messages(&self) -> bool {
+        !self.unacked_messages.is_empty()
+    }
+
+    fn retries_exhausted(&self) -> bool {
+        self.retries_exhausted
+    }
+
+    /// Count the retry of the message, if it was collected to be resent
+    fn message_written(&mut self, message_ack: &MessageAck) {
+        let Some(unacked_message) = self.unacked_messages.get_mut(&message_ack.message_id) else {
+            return;
+        };
+        if !std::mem::take(&mut unacked_message.retry_pending) {
+            return;
+        }
+        unacked_message.retries += 1;
+        if self
+            .reliable_settings
+            .max_retries
+            .is_some_and(|max_retries| unacked_message.retries > max_retries)
+        {
+            self.retries_exhausted = true;
+        }
+    }
+}
+
+fn retransmission_delay(last_sent: &WrappedTime, now: &WrappedTime) -> Duration {
+    now.to_duration().saturating_sub(last_sent.to_duration())
 }
 
 #[cfg(test)]
@@ -351,6 +465,7 @@ mod tests {
             ReliableSettings {
                 rtt_resend_factor: 1.5,
                 rtt_resend_min_delay: Duration::from_millis(100),
+                ..ReliableSettings::default()
             },
             Duration::default(),
         );
@@ -384,6 +499,15 @@ mod tests {
             }
         );
 
+        assert_eq!(
+            sender.retransmission_stats(),
+            Some(RetransmissionStats {
+                num_retransmitted_messages: 1,
+                num_retransmitted_fragments: 0,
+                total_retransmission_delay: Duration::from_millis(300),
+            })
+        );
+
         // Ack the first message
         sender.receive_ack(&MessageAck {
             message_id: MessageId(0),
@@ -396,4 +520,42 @@ mod tests {
         let (single, _) = sender.send_packet();
         assert_eq!(single.len(), 0);
     }
+
+    /// The messages are resent at a fixed interval, until the maximum number of retries is reached
+    #[test]
+    fn test_reliable_sender_max_retries() {
+        let mut sender = ReliableSender::new(
+            ReliableSettings::default()
+                .with_resend_interval(Duration::from_millis(50))
+                .with_max_retries(2),
+            Duration::default(),
+        );
+        // the RTT doesn't matter with a fixed interval
+        sender.current_rtt = Duration::from_secs(1);
+        sender.current_time = WrappedTime::new(0);
+        sender.buffer_send(Bytes::from("hello"), 1.0).unwrap();
+
+        let message_ack = MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        };
+        // the first send is not a retry
+        assert_eq!(sender.send_packet().0.len(), 1);
+        sender.message_written(&message_ack);
+        sender.current_time += Duration::from_millis(60);
+
+        // the resends held back by the priority filter or the bandwidth quota are not counted as retries
+        for _ in 0..6 {
+            assert_eq!(sender.send_packet().0.len(), 1);
+            sender.current_time += Duration::from_millis(60);
+        }
+        assert!(!sender.retries_exhausted());
+
+        for retries in 1..=4 {
+            assert_eq!(sender.send_packet().0.len(), 1);
+            sender.message_written(&message_ack);
+            sender.current_time += Duration::from_millis(60);
+            assert_eq!(sender.retries_exhausted(), retries > 2);
+        }
+    }
 }
@@ -116,6 +116,12 @@ impl ChannelSend for SequencedUnreliableSender {
         receiver
     }
 
+    fn set_fragment_size(&mut self, fragment_size: usize) -> Vec<MessageId> {
+        self.fragment_sender.fragment_size = fragment_size;
+        // the messages are not resent, so there are no messages to fragment again
+        vec![]
+    }
+
     /// Send nacks to the subscribers of nacks
     fn send_nacks(&mut self, nack: MessageId) {
         for sender in &self.nack_senders {
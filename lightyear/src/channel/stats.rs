@@ -1,3 +1,4 @@
+#[cfg(feature = "trace")]
 pub(crate) mod send {
     /// TODO: maybe this should be directly on the ChannelSender?
     #[derive(Default, Copy, Clone, Debug, PartialEq)]
@@ -29,3 +30,44 @@ pub(crate) mod send {
         }
     }
 }
+
+pub mod retransmission {
+    use bevy::utils::Duration;
+
+    /// Statistics about the messages that had to be resent on a reliable channel.
+    ///
+    /// They can be used to tune the [`ReliableSettings`](crate::channel::builder::ReliableSettings) of the channel.
+    #[derive(Default, Copy, Clone, Debug, PartialEq)]
+    pub struct RetransmissionStats {
+        /// Number of single (non-fragmented) messages that were resent
+        pub num_retransmitted_messages: usize,
+        /// Number of fragments that were resent
+        pub num_retransmitted_fragments: usize,
+        /// Sum of the time elapsed between the previous send and the retransmission
+        pub total_retransmission_delay: Duration,
+    }
+
+    impl RetransmissionStats {
+        pub(crate) fn add_retransmission(&mut self, fragment: bool, delay: Duration) {
+            if fragment {
+                self.num_retransmitted_fragments += 1;
+            } else {
+                self.num_retransmitted_messages += 1;
+            }
+            self.total_retransmission_delay += delay;
+        }
+
+        /// Total number of retransmissions (messages and fragments)
+        pub fn retransmissions(&self) -> usize {
+            self.num_retransmitted_messages + self.num_retransmitted_fragments
+        }
+
+        /// Average time elapsed between the previous send and the retransmission.
+        ///
+        /// Returns None if nothing was retransmitted.
+        pub fn average_retransmission_delay(&self) -> Option<Duration> {
+            let retransmissions = self.retransmissions();
+            (retransmissions > 0).then(|| self.total_retransmission_delay / retransmissions as u32)
+        }
+    }
+}
@@ -5,8 +5,10 @@ use std::fmt::Debug;
 
 use bevy::prelude::{Component, Entity, ReflectComponent};
 use bevy::reflect::Reflect;
+use bevy::utils::HashMap;
 
 use crate::prelude::{Message, Tick};
+use crate::protocol::component::ComponentKind;
 
 /// Marks an entity that directly applies the replication updates from the remote
 ///
@@ -26,6 +28,51 @@ pub struct Confirmed {
     pub tick: Tick,
 }
 
+/// When the current value of a replicated component was generated on the server and received on the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentArrival {
+    /// Server tick at which the value was generated
+    pub server_tick: Tick,
+    /// Client tick at which the value was received and applied
+    pub received_tick: Tick,
+}
+
+/// Keeps track of when the replicated components of an entity were last received.
+///
+/// It is added on the client to the entities that receive replication updates if
+/// [`ClientConfig::replication_ticks`](crate::prelude::client::ClientConfig::replication_ticks) is true, and can be used to implement
+/// custom smoothing, staleness indicators or authority arbitration.
+/// Only the inserts and updates that actually changed a component are recorded.
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct ReplicationTicks {
+    components: HashMap<ComponentKind, ComponentArrival>,
+}
+
+impl ReplicationTicks {
+    /// When the current value of the component `C` was generated and received
+    pub fn get<C: Component>(&self) -> Option<&ComponentArrival> {
+        self.components.get(&ComponentKind::of::<C>())
+    }
+
+    /// Number of ticks since the current value of the component `C` was generated on the server,
+    /// where `tick` is the current client tick
+    pub fn age<C: Component>(&self, tick: Tick) -> Option<i16> {
+        self.get::<C>().map(|arrival| tick - arrival.server_tick)
+    }
+
+    /// Client tick at which the entity last received an insert or update for one of its components
+    pub fn last_received(&self) -> Option<Tick> {
+        self.components
+            .values()
+            .map(|arrival| arrival.received_tick)
+            .max()
+    }
+
+    pub(crate) fn record(&mut self, kind: ComponentKind, arrival: ComponentArrival) {
+        self.components.insert(kind, arrival);
+    }
+}
+
 pub trait SyncComponent: Component + Clone + PartialEq + Message {}
 impl<T> SyncComponent for T where T: Component + Clone + PartialEq + Message {}
 
@@ -7,8 +7,13 @@ use nonzero_ext::nonzero;
 use crate::client::input::native::InputConfig;
 use crate::client::interpolation::plugin::InterpolationConfig;
 use crate::client::prediction::plugin::PredictionConfig;
+use crate::client::redirect::RedirectConfig;
+use crate::client::restart::RestartConfig;
+use crate::client::staleness::StalenessConfig;
 use crate::client::sync::SyncConfig;
 use crate::connection::client::NetConfig;
+use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::header::AckBitfieldSize;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
 use crate::shared::replication::plugin::ReplicationConfig;
@@ -26,6 +31,15 @@ pub struct NetcodeConfig {
     /// Set the duration in seconds after which the `ConnectToken` generated by the Client
     /// will expire. Set a negative value for the token to never expire.
     pub token_expire_secs: i32,
+    /// If true, the client sends the [hash of its protocol](crate::protocol::hash) in the connection request,
+    /// so that a server that [checks it](crate::prelude::server::NetcodeConfig::check_protocol_hash) can deny
+    /// the client if it uses a different protocol.
+    ///
+    /// The connection request then uses a different version than the standard netcode protocol, so only enable it
+    /// with lightyear servers. The default is false.
+    pub send_protocol_hash: bool,
+    /// Hash of the protocol of the client, computed from the registered types when the client connects
+    pub(crate) protocol_hash: Option<u64>,
 }
 
 impl Default for NetcodeConfig {
@@ -35,6 +49,8 @@ impl Default for NetcodeConfig {
             keepalive_packet_send_rate: 1.0 / 10.0,
             client_timeout_secs: -1,
             token_expire_secs: 30,
+            send_protocol_hash: false,
+            protocol_hash: None,
         }
     }
 }
@@ -44,6 +60,7 @@ impl NetcodeConfig {
         crate::connection::netcode::ClientConfig::default()
             .num_disconnect_packets(self.num_disconnect_packets)
             .packet_send_rate(self.keepalive_packet_send_rate)
+            .protocol_hash(self.protocol_hash)
     }
 }
 
@@ -60,6 +77,27 @@ pub struct PacketConfig {
     pub send_bandwidth_cap: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
     pub bandwidth_cap_enabled: bool,
+    /// If set, maximum number of bytes of messages that can be sent to the server on each tick.
+    ///
+    /// When the budget is reached, the messages of the channels with the highest
+    /// [priority](crate::prelude::ChannelSettings::priority) are sent first; the unreliable messages that
+    /// don't fit are dropped and the reliable ones are sent again later.
+    pub send_budget_per_tick: Option<u32>,
+    /// Number of packets that are acknowledged in each packet header.
+    ///
+    /// A wider window avoids spurious retransmits on high-tick-rate or high-loss connections,
+    /// at the cost of a bigger packet header.
+    pub ack_bitfield_size: AckBitfieldSize,
+    /// Maximum number of bytes in a packet. Messages that don't fit in a packet are split into fragments
+    /// that are reassembled by the remote peer.
+    ///
+    /// The value is clamped between [`MIN_PACKET_SIZE`](crate::packet::mtu::MIN_PACKET_SIZE) and [`MAX_PACKET_SIZE`].
+    pub max_packet_size: usize,
+    /// If true, the maximum packet size of each connection is reduced when large packets seem to be dropped
+    /// by the network (path MTU discovery), and is increased again over time.
+    ///
+    /// The default is false.
+    pub mtu_discovery: bool,
 }
 
 impl Default for PacketConfig {
@@ -69,6 +107,10 @@ impl Default for PacketConfig {
             // 56 KB/s bandwidth cap
             send_bandwidth_cap: Quota::per_second(nonzero!(56000u32)),
             bandwidth_cap_enabled: false,
+            send_budget_per_tick: None,
+            ack_bitfield_size: AckBitfieldSize::default(),
+            max_packet_size: MAX_PACKET_SIZE,
+            mtu_discovery: false,
         }
     }
 }
@@ -85,6 +127,26 @@ impl PacketConfig {
         self
     }
 
+    pub fn with_send_budget_per_tick(mut self, bytes: u32) -> Self {
+        self.send_budget_per_tick = Some(bytes);
+        self
+    }
+
+    pub fn with_ack_bitfield_size(mut self, ack_bitfield_size: AckBitfieldSize) -> Self {
+        self.ack_bitfield_size = ack_bitfield_size;
+        self
+    }
+
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn with_mtu_discovery(mut self, mtu_discovery: bool) -> Self {
+        self.mtu_discovery = mtu_discovery;
+        self
+    }
+
     pub fn enable_bandwidth_cap(mut self) -> Self {
         self.bandwidth_cap_enabled = true;
         self
@@ -124,4 +186,11 @@ pub struct ClientConfig {
     pub replication: ReplicationConfig,
     pub prediction: PredictionConfig,
     pub interpolation: InterpolationConfig,
+    pub redirect: RedirectConfig,
+    pub restart: RestartConfig,
+    pub staleness: StalenessConfig,
+    /// If true, the replicated entities get a [`ReplicationTicks`](crate::prelude::client::ReplicationTicks)
+    /// component that records when their components were generated and received. The ticks are always recorded
+    /// if [`StalenessConfig::threshold`] is set. The default is false (nothing is recorded)
+    pub replication_ticks: bool,
 }
@@ -1,34 +1,42 @@
 //! Specify how a Client sends/receives messages with a Server
+use std::sync::Arc;
+
 use bevy::ecs::component::Tick as BevyTick;
 use bevy::ecs::entity::MapEntities;
 use bevy::prelude::{Resource, World};
 use bevy::utils::{Duration, HashMap};
 use bytes::Bytes;
-use tracing::{debug, trace, trace_span};
+use tracing::{debug, error, trace, trace_span};
 
 use crate::channel::builder::{
-    EntityActionsChannel, EntityUpdatesChannel, PingChannel, PongChannel,
+    ControlChannel, EntityActionsChannel, EntityUpdatesChannel, PingChannel, PongChannel,
 };
 
-use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::retransmission::RetransmissionStats;
+use crate::client::components::{ComponentArrival, ReplicationTicks};
 use crate::client::config::ClientConfig;
 use crate::client::error::ClientError;
 use crate::client::sync::SyncConfig;
 use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::error::PacketError;
+use crate::packet::header::AckBitfieldSize;
 use crate::packet::message_manager::MessageManager;
+use crate::packet::mtu::MtuConfig;
 use crate::packet::packet_builder::{Payload, RecvPayload};
 use crate::packet::priority_manager::PriorityConfig;
 use crate::prelude::client::PredictionConfig;
 use crate::prelude::{Channel, ChannelKind, ClientId, Message, ReplicationConfig};
 use crate::protocol::channel::ChannelRegistry;
 use crate::protocol::component::ComponentRegistry;
-use crate::protocol::message::{MessageRegistry, MessageType};
+use crate::protocol::message::{MessageKind, MessageRegistry, MessageType, TraceId};
 use crate::protocol::registry::NetId;
+use crate::protocol::version::{RemoteVersions, SchemaVersions};
 use crate::serialize::reader::Reader;
 use crate::serialize::writer::Writer;
 use crate::serialize::{SerializationError, ToBytes};
 use crate::server::error::ServerError;
+use crate::server::pacing::RenderRateHint;
 use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::message::MessageSend;
 use crate::shared::ping::manager::{PingConfig, PingManager};
@@ -39,6 +47,9 @@ use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
 use crate::shared::replication::{EntityActionsMessage, EntityUpdatesMessage, ReplicationSend};
 use crate::shared::replication::{ReplicationPeer, ReplicationReceive};
+use crate::shared::rpc::{
+    Request, RequestId, RequestTracker, ResponseHandle, RpcRequest, RpcResponse,
+};
 use crate::shared::sets::ClientMarker;
 use crate::shared::tick_manager::Tick;
 use crate::shared::tick_manager::TickManager;
@@ -88,6 +99,14 @@ pub struct ConnectionManager {
     /// - in host server mode, we deserialize the bytes and push them to the server's Message Events queue directly
     /// - in non-host server mode, we buffer the bytes to the message manager as usual
     pub(crate) messages_to_send: Vec<(Bytes, ChannelKind)>,
+    /// True if the client is disconnected from the server. The connection manager is rebuilt when the client
+    /// starts connecting, so the messages sent while disconnected would never reach the server
+    pub(crate) disconnected: bool,
+    /// Requests sent to the server that are waiting for a response
+    pub(crate) requests: RequestTracker<()>,
+    /// True until the wire format versions of the server are received. Only the ping, pong and control channels
+    /// are read until then
+    awaiting_remote_versions: bool,
 }
 
 // NOTE: useful when we sometimes need to create a temporary fake ConnectionManager
@@ -107,6 +126,8 @@ impl Default for ConnectionManager {
             message_manager: MessageManager::new(
                 &ChannelRegistry::default(),
                 0.0,
+                AckBitfieldSize::default(),
+                MtuConfig::default(),
                 PriorityConfig::default(),
             ),
             delta_manager: DeltaManager::default(),
@@ -120,10 +141,26 @@ impl Default for ConnectionManager {
             received_messages: HashMap::default(),
             writer: Writer::with_capacity(0),
             messages_to_send: Vec::default(),
+            disconnected: true,
+            requests: RequestTracker::default(),
+            awaiting_remote_versions: false,
         }
     }
 }
 
+/// Buffer the [`SchemaVersions`] of the client, which must be the first message of the [`ControlChannel`]
+fn buffer_schema_versions(
+    versions: &SchemaVersions,
+    message_registry: &MessageRegistry,
+    message_manager: &mut MessageManager,
+    writer: &mut Writer,
+) -> Result<(), ClientError> {
+    NetworkTarget::None.to_bytes(writer)?;
+    message_registry.serialize(versions, writer, None)?;
+    message_manager.buffer_send(writer.split(), ChannelKind::of::<ControlChannel>())?;
+    Ok(())
+}
+
 impl ConnectionManager {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
@@ -132,11 +169,14 @@ impl ConnectionManager {
         channel_registry: &ChannelRegistry,
         client_config: &ClientConfig,
     ) -> Self {
-        let bandwidth_cap_enabled = client_config.packet.bandwidth_cap_enabled;
+        let bandwidth_cap_enabled = client_config.packet.bandwidth_cap_enabled
+            || client_config.packet.send_budget_per_tick.is_some();
         // create the message manager and the channels
         let mut message_manager = MessageManager::new(
             channel_registry,
             client_config.packet.nack_rtt_multiple,
+            client_config.packet.ack_bitfield_size,
+            client_config.packet.into(),
             client_config.packet.into(),
         );
         // get notified when a replication-update message gets acked/nacked
@@ -158,6 +198,20 @@ impl ConnectionManager {
             bandwidth_cap_enabled,
         );
         let replication_receiver = ReplicationReceiver::new();
+        // the versions are the first message of the control channel
+        let schema_versions = SchemaVersions::new(message_registry, component_registry);
+        let mut writer = Writer::with_capacity(MAX_PACKET_SIZE);
+        let _ = buffer_schema_versions(
+            &schema_versions,
+            message_registry,
+            &mut message_manager,
+            &mut writer,
+        )
+        .inspect_err(|e| error!("Could not send the wire format versions: {e:?}"));
+        let mut events = ConnectionEvents::default();
+        // the staleness of the entities is computed from their replication ticks
+        events.record_component_ticks =
+            client_config.replication_ticks || client_config.staleness.threshold.is_some();
         Self {
             component_registry: component_registry.clone(),
             message_registry: message_registry.clone(),
@@ -167,12 +221,15 @@ impl ConnectionManager {
             replication_receiver,
             ping_manager: PingManager::new(client_config.ping),
             sync_manager: SyncManager::new(client_config.sync, client_config.prediction),
-            events: ConnectionEvents::default(),
+            events,
             #[cfg(feature = "leafwing")]
             received_leafwing_input_messages: HashMap::default(),
             received_messages: HashMap::default(),
-            writer: Writer::with_capacity(MAX_PACKET_SIZE),
+            writer,
             messages_to_send: Vec::default(),
+            disconnected: true,
+            requests: RequestTracker::default(),
+            awaiting_remote_versions: !schema_versions.is_empty(),
         }
     }
 
@@ -182,6 +239,22 @@ impl ConnectionManager {
         self.sync_manager.is_synced()
     }
 
+    /// Estimated drift of the server clock relative to the client clock, as a fraction of the elapsed time.
+    ///
+    /// A negative value means that the clock of the client runs faster than the clock of the server.
+    /// The drift is compensated by adjusting the speed of the client, see [`SyncConfig::drift_window`](crate::prelude::client::SyncConfig::drift_window).
+    pub fn clock_drift(&self) -> f64 {
+        self.sync_manager.clock_drift()
+    }
+
+    /// Statistics about the messages that were retransmitted on the reliable channel `C`.
+    ///
+    /// Returns None if the channel is not reliable
+    pub fn retransmission_stats<C: Channel>(&self) -> Option<RetransmissionStats> {
+        self.message_manager
+            .retransmission_stats(&ChannelKind::of::<C>())
+    }
+
     /// Returns true if we received a new server packet on this frame
     pub(crate) fn received_new_server_tick(&self) -> bool {
         self.sync_manager.duration_since_latest_received_server_tick == Duration::default()
@@ -234,7 +307,24 @@ impl ConnectionManager {
         message.map_entities(mapper);
     }
 
+    /// Returns true if messages can be sent on the channel `C`: the channel is registered and the client
+    /// is connected (or connecting) to the server.
+    ///
+    /// This can be used to update the UI before trying to send a message.
+    pub fn can_send<C: Channel>(&self) -> bool {
+        !self.disconnected
+            && self
+                .message_manager
+                .channels
+                .contains_key(&ChannelKind::of::<C>())
+    }
+
     /// Send a [`Message`] to the server using a specific [`Channel`]
+    ///
+    /// The message is queued and will be sent with the next packets. Messages that don't fit in a single
+    /// packet are fragmented, and reassembled by the server.
+    ///
+    /// Returns [`ClientError::NotConnected`] if the client is disconnected: the message is not queued.
     pub fn send_message<C: Channel, M: Message>(
         &mut self,
         message: &mut M,
@@ -242,6 +332,51 @@ impl ConnectionManager {
         self.send_message_to_target::<C, M>(message, NetworkTarget::None)
     }
 
+    /// Send a [raw datagram](crate::packet::raw) of the given `kind` to the server, with the next packets.
+    ///
+    /// The datagrams are received on the server as [`RawDatagramEvent`](crate::prelude::server::RawDatagramEvent)s.
+    /// They are dropped in host-server mode.
+    pub fn send_raw(&mut self, kind: u8, payload: &[u8]) -> Result<(), ClientError> {
+        if self.disconnected {
+            return Err(ClientError::NotConnected);
+        }
+        self.message_manager.buffer_send_raw(kind, payload)?;
+        Ok(())
+    }
+
+    /// Let the server know at which rate (in frames per second) the client renders, so that it doesn't send
+    /// more component updates than the client can display (see [`crate::server::pacing`])
+    pub fn report_render_rate(&mut self, render_rate: f32) -> Result<(), ClientError> {
+        self.send_message::<ControlChannel, _>(&mut RenderRateHint { rate: render_rate })
+    }
+
+    /// Send a [`Request`] to the server.
+    ///
+    /// A [`ResponseEvent`](crate::prelude::client::ResponseEvent) with the id of the returned handle is emitted
+    /// when the server responds, or when the request times out.
+    pub fn send_request<C: Channel, R: Request>(
+        &mut self,
+        request: R,
+    ) -> Result<ResponseHandle<R::Response>, ClientError> {
+        let id = self.requests.next_id();
+        self.send_message::<C, _>(&mut RpcRequest { id, request })?;
+        self.requests.track::<R>(id, ());
+        Ok(ResponseHandle::new(id))
+    }
+
+    /// Respond to the [`RequestEvent`](crate::prelude::client::RequestEvent) with the id `request_id`
+    /// that was received from the server
+    pub fn send_response<C: Channel, R: Request>(
+        &mut self,
+        request_id: RequestId,
+        response: R::Response,
+    ) -> Result<(), ClientError> {
+        self.send_message::<C, _>(&mut RpcResponse::<R> {
+            id: request_id,
+            response,
+        })
+    }
+
     /// Send a [`Message`] to the server using a specific [`Channel`]
     ///
     /// The message will be sent to the server and re-broadcasted to all clients that match the [`NetworkTarget`]
@@ -250,7 +385,38 @@ impl ConnectionManager {
         message: &mut M,
         target: NetworkTarget,
     ) -> Result<(), ClientError> {
-        self.erased_send_message_to_target(message, ChannelKind::of::<C>(), target)
+        self.erased_send_message_to_target(message, ChannelKind::of::<C>(), target, None)
+    }
+
+    /// Send a [`Message`] to the server on the channel identified by `channel`.
+    ///
+    /// This is used to send messages on the channels registered at runtime with
+    /// [`ChannelRegistry::add_dynamic_channel`](crate::prelude::ChannelRegistry::add_dynamic_channel), which
+    /// don't have a [`Channel`] type.
+    pub fn send_message_on_channel<M: Message>(
+        &mut self,
+        message: &mut M,
+        channel: ChannelKind,
+    ) -> Result<(), ClientError> {
+        self.erased_send_message_to_target(message, channel, NetworkTarget::None, None)
+    }
+
+    /// Send a [`Message`] to the server using a specific [`Channel`], with a [`TraceId`] that will be
+    /// available in the [`MessageEvent`](crate::shared::events::components::MessageEvent) on the server.
+    ///
+    /// The message must have been registered with [`add_trace_id`](crate::protocol::message::MessageRegistration::add_trace_id),
+    /// otherwise the [`TraceId`] is ignored.
+    pub fn send_message_with_trace_id<C: Channel, M: Message>(
+        &mut self,
+        message: &mut M,
+        trace_id: TraceId,
+    ) -> Result<(), ClientError> {
+        self.erased_send_message_to_target(
+            message,
+            ChannelKind::of::<C>(),
+            NetworkTarget::None,
+            Some(trace_id),
+        )
     }
 
     /// Serialize a message and buffer it internally so that it can be sent later
@@ -259,15 +425,23 @@ impl ConnectionManager {
         message: &M,
         channel_kind: ChannelKind,
         target: NetworkTarget,
+        trace_id: Option<TraceId>,
     ) -> Result<(), ClientError> {
+        if self.disconnected {
+            return Err(ClientError::NotConnected);
+        }
+        if !self.message_manager.channels.contains_key(&channel_kind) {
+            return Err(PacketError::ChannelNotFound.into());
+        }
         // write the target first
         // NOTE: this is ok to do because most of the time (without rebroadcast, this just adds 1 byte)
         target.to_bytes(&mut self.writer)?;
         // then write the message
-        self.message_registry.serialize(
+        self.message_registry.serialize_with_trace_id(
             message,
             &mut self.writer,
             Some(&mut self.replication_receiver.remote_entity_map.local_to_remote),
+            trace_id,
         )?;
         let message_bytes = self.writer.split();
 
@@ -295,6 +469,7 @@ impl ConnectionManager {
         self.replication_sender.send_actions_messages(
             tick,
             bevy_tick,
+            false,
             &mut self.writer,
             &mut self.message_manager,
         )?;
@@ -315,6 +490,8 @@ impl ConnectionManager {
         local_client_id: ClientId,
         server_manager: &mut crate::server::connection::ConnectionManager,
     ) -> Result<(), ServerError> {
+        // there is no socket to send the raw datagrams on
+        self.message_manager.clear_raw_to_send();
         // go through messages_to_send, deserialize them and make the server receive them
         self.messages_to_send
             .drain(..)
@@ -388,11 +565,27 @@ impl ConnectionManager {
         tick_manager: &TickManager,
     ) -> Result<(), ClientError> {
         let _span = trace_span!("receive").entered();
+        let schema_versions_net_id = self
+            .message_registry
+            .kind_map
+            .net_id(&MessageKind::of::<SchemaVersions>())
+            .copied();
         self.message_manager
             .channels
             .iter_mut()
             .try_for_each(|(channel_kind, channel)| {
-                while let Some((tick, single_data)) = channel.receiver.read_message() {
+                // the values must not be read before we know which versions the server uses
+                if self.awaiting_remote_versions
+                    && ![
+                        ChannelKind::of::<PingChannel>(),
+                        ChannelKind::of::<PongChannel>(),
+                        ChannelKind::of::<ControlChannel>(),
+                    ]
+                    .contains(channel_kind)
+                {
+                    return Ok(());
+                }
+                while let Some((tick, single_data)) = channel.read_message() {
                     // let channel_name = self
                     //     .message_manager
                     //     .channel_registry
@@ -439,6 +632,23 @@ impl ConnectionManager {
                         // identify the type of message
                         let net_id = NetId::from_bytes(&mut reader)?;
                         let single_data = reader.consume();
+                        if Some(net_id) == schema_versions_net_id {
+                            self.awaiting_remote_versions = false;
+                            match RemoteVersions::read(
+                                single_data,
+                                &self.message_registry,
+                                &self.component_registry,
+                            ) {
+                                Ok(versions) => {
+                                    self.replication_receiver.remote_versions =
+                                        Some(Arc::new(versions));
+                                }
+                                Err(e) => error!(
+                                    "Could not read the wire format versions of the server: {e:?}"
+                                ),
+                            }
+                            continue;
+                        }
                         match self.message_registry.message_type(net_id) {
                             #[cfg(feature = "leafwing")]
                             MessageType::LeafwingInput => {
@@ -471,10 +681,36 @@ impl ConnectionManager {
                 tick_manager.tick(),
                 &mut self.events,
             );
+            if self.events.record_component_ticks {
+                self.record_component_arrivals(world, tick_manager.tick());
+            }
         }
         Ok(())
     }
 
+    /// Update the [`ReplicationTicks`] of the entities whose components were just inserted or updated
+    fn record_component_arrivals(&mut self, world: &mut World, received_tick: Tick) {
+        for (entity, net_id, server_tick) in std::mem::take(&mut self.events.component_ticks) {
+            let Some(kind) = self.component_registry.kind_map.kind(net_id).copied() else {
+                continue;
+            };
+            let Some(mut entity_mut) = world.get_entity_mut(entity) else {
+                continue;
+            };
+            let arrival = ComponentArrival {
+                server_tick,
+                received_tick,
+            };
+            if let Some(mut ticks) = entity_mut.get_mut::<ReplicationTicks>() {
+                ticks.record(kind, arrival);
+            } else {
+                let mut ticks = ReplicationTicks::default();
+                ticks.record(kind, arrival);
+                entity_mut.insert(ticks);
+            }
+        }
+    }
+
     /// Receive a message from the server
     pub(crate) fn receive_message(&mut self, mut reader: Reader) -> Result<(), SerializationError> {
         // identify the type of message
@@ -507,6 +743,9 @@ impl ConnectionManager {
         tick_manager: &TickManager,
         component_registry: &ComponentRegistry,
     ) -> Result<(), ClientError> {
+        let Some(packet) = self.message_manager.recv_raw(packet) else {
+            return Ok(());
+        };
         // receive the packets, buffer them, update any sender that were waiting for their sent messages to be acked
         let tick = self.message_manager.recv_packet(packet)?;
         debug!("Received server packet with tick: {:?}", tick);
@@ -551,7 +790,7 @@ impl MessageSend for ConnectionManager {
         channel_kind: ChannelKind,
         target: NetworkTarget,
     ) -> Result<(), ClientError> {
-        self.erased_send_message_to_target(message, channel_kind, target)
+        self.erased_send_message_to_target(message, channel_kind, target, None)
     }
 }
 
@@ -591,8 +830,13 @@ impl ReplicationSend for ConnectionManager {
 
 #[cfg(test)]
 mod tests {
-    use crate::prelude::{client, server, ClientConnectionManager, RemoteEntityMap};
-    use crate::tests::protocol::EntityMessage;
+    use bevy::utils::Duration;
+
+    use crate::prelude::{
+        client, server, ClientConnectionManager, RemoteEntityMap, SharedConfig, TickConfig,
+        TickManager,
+    };
+    use crate::tests::protocol::{ComponentSyncModeFull, ComponentSyncModeSimple, EntityMessage};
     use crate::tests::stepper::BevyStepper;
 
     /// Check that we can map entities from the local world to the remote world
@@ -644,4 +888,95 @@ mod tests {
         assert!(RemoteEntityMap::is_mapped(message.0));
         assert_eq!(RemoteEntityMap::mark_unmapped(message.0), server_entity);
     }
+
+    /// Check that the replication ticks are not recorded unless they are enabled in the config
+    #[test]
+    fn test_replication_ticks_disabled() {
+        let mut stepper = BevyStepper::default();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(1.0), server::Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+        assert!(stepper
+            .client_app
+            .world()
+            .get::<client::ReplicationTicks>(client_entity)
+            .is_none());
+    }
+
+    /// Check that the client keeps track of when each replicated component was generated and received
+    #[test]
+    fn test_replication_ticks() {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let client_config = client::ClientConfig {
+            replication_ticks: true,
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, client_config, frame_duration);
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(1.0), server::Replicate::default()))
+            .id();
+        stepper.frame_step();
+        let spawn_tick = stepper.server_app.world().resource::<TickManager>().tick();
+        stepper.frame_step();
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+        let ticks = stepper
+            .client_app
+            .world()
+            .get::<client::ReplicationTicks>(client_entity)
+            .expect("replication ticks were not added");
+        let arrival = *ticks.get::<ComponentSyncModeFull>().unwrap();
+        assert_eq!(arrival.server_tick, spawn_tick);
+        assert!(ticks.get::<ComponentSyncModeSimple>().is_none());
+
+        // update the component on the server
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentSyncModeFull(2.0));
+        stepper.frame_step();
+        let update_tick = stepper.server_app.world().resource::<TickManager>().tick();
+        stepper.frame_step();
+        let client_tick = stepper.client_app.world().resource::<TickManager>().tick();
+        let ticks = stepper
+            .client_app
+            .world()
+            .get::<client::ReplicationTicks>(client_entity)
+            .unwrap();
+        let updated = *ticks.get::<ComponentSyncModeFull>().unwrap();
+        assert_eq!(updated.server_tick, update_tick);
+        assert!(updated.received_tick > arrival.received_tick);
+        assert!(updated.received_tick <= client_tick);
+        assert_eq!(
+            ticks.age::<ComponentSyncModeFull>(client_tick),
+            Some(client_tick - update_tick)
+        );
+    }
 }
@@ -16,4 +16,7 @@ pub enum ClientError {
     MessageProtocolError(#[from] crate::protocol::message::MessageError),
     #[error(transparent)]
     ComponentProtocolError(#[from] crate::protocol::component::ComponentError),
+    /// The client is disconnected, so the message would be dropped when the client reconnects
+    #[error("the client is not connected to the server")]
+    NotConnected,
 }
@@ -14,11 +14,13 @@
 //! ```
 
 use bevy::app::{App, Plugin, PreUpdate};
-use bevy::prelude::{Component, Event, IntoSystemConfigs};
+use bevy::prelude::{Component, Event, EventWriter, IntoSystemConfigs, ResMut};
 
 use crate::client::connection::ConnectionManager;
-use crate::connection::client::DisconnectReason;
+use crate::connection::client::{ConnectionPhase, DisconnectReason};
+use crate::connection::server::DeniedReason;
 use crate::prelude::ClientId;
+use crate::shared::events::components::EventStamp;
 use crate::shared::events::plugin::EventsPlugin;
 use crate::shared::events::systems::push_component_events;
 use crate::shared::sets::{ClientMarker, InternalMainSet};
@@ -33,11 +35,37 @@ impl Plugin for ClientEventsPlugin {
             // EVENTS
             .add_event::<ConnectEvent>()
             .add_event::<DisconnectEvent>()
+            .add_event::<RejectEvent>()
+            .add_event::<ConnectionPhaseChanged>()
+            .add_event::<RawDatagramEvent>()
             // PLUGIN
-            .add_plugins(EventsPlugin::<ConnectionManager>::default());
+            .add_plugins(EventsPlugin::<ConnectionManager>::default())
+            // SYSTEMS
+            .add_systems(
+                PreUpdate,
+                emit_raw_datagram_events.in_set(InternalMainSet::<ClientMarker>::EmitEvents),
+            );
     }
 }
 
+/// Emit the raw datagrams received from the server
+fn emit_raw_datagram_events(
+    mut events: EventWriter<RawDatagramEvent>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    events.send_batch(
+        connection_manager
+            .message_manager
+            .received_raw
+            .drain(..)
+            .map(|(kind, payload)| RawDatagramEvent {
+                kind,
+                payload,
+                context: (),
+            }),
+    );
+}
+
 pub(crate) fn emit_replication_events<C: Component>(app: &mut App) {
     app.add_event::<ComponentUpdateEvent<C>>();
     app.add_event::<ComponentInsertEvent<C>>();
@@ -54,21 +82,57 @@ pub(crate) fn emit_replication_events<C: Component>(app: &mut App) {
 /// We keep this separate from the server's ConnectEvent so that we have different events emitted on the client
 /// and the server when running in HostServer mode
 #[derive(Event)]
-pub struct ConnectEvent(ClientId);
+pub struct ConnectEvent(ClientId, Option<EventStamp>);
 
 impl ConnectEvent {
     pub fn new(client_id: ClientId) -> Self {
-        Self(client_id)
+        Self(client_id, None)
+    }
+
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.1 = Some(stamp);
+        self
     }
+
     pub fn client_id(&self) -> ClientId {
         self.0
     }
+
+    /// Tick and time at which the connection was established
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.1
+    }
 }
 
 /// Bevy [`Event`] emitted on the client on the frame where the connection is disconnected
 #[derive(Event, Default)]
 pub struct DisconnectEvent {
     pub reason: Option<DisconnectReason>,
+    pub(crate) stamp: Option<EventStamp>,
+}
+
+impl DisconnectEvent {
+    /// Tick and time at which the connection was disconnected
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+}
+
+/// Bevy [`Event`] emitted on the client when the server refused the connection, with the reason why.
+///
+/// It is emitted alongside the [`DisconnectEvent`], whether the connection request was denied
+/// or the server rejected the client after it connected with
+/// [`ServerConnections::reject_connection`](crate::connection::server::ServerConnections::reject_connection).
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct RejectEvent {
+    pub reason: DeniedReason,
+}
+
+/// Bevy [`Event`] emitted on the client when the [`ConnectionPhase`] changes
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionPhaseChanged {
+    pub previous: ConnectionPhase,
+    pub current: ConnectionPhase,
 }
 
 /// Bevy [`Event`] emitted on the client to indicate the user input for the tick
@@ -77,6 +141,8 @@ pub type InputEvent<I> = crate::shared::events::components::InputEvent<I, ()>;
 pub type EntitySpawnEvent = crate::shared::events::components::EntitySpawnEvent<()>;
 /// Bevy [`Event`] emitted on the client when a EntityDespawn replication message is received
 pub type EntityDespawnEvent = crate::shared::events::components::EntityDespawnEvent<()>;
+/// Bevy [`Event`] emitted on the client once the replication messages of a server tick are applied
+pub type ReplicationAppliedEvent = crate::shared::events::components::ReplicationAppliedEvent<()>;
 /// Bevy [`Event`] emitted on the client when a ComponentUpdate replication message is received
 pub type ComponentUpdateEvent<C> = crate::shared::events::components::ComponentUpdateEvent<C, ()>;
 /// Bevy [`Event`] emitted on the client when a ComponentInsert replication message is received
@@ -85,3 +151,11 @@ pub type ComponentInsertEvent<C> = crate::shared::events::components::ComponentI
 pub type ComponentRemoveEvent<C> = crate::shared::events::components::ComponentRemoveEvent<C, ()>;
 /// Bevy [`Event`] emitted on the client when a (non-replication) message is received
 pub type MessageEvent<M> = crate::shared::events::components::MessageEvent<M, ()>;
+/// Bevy [`Event`] emitted on the client on the frame where a [raw datagram](crate::packet::raw) from the server
+/// is received
+pub type RawDatagramEvent = crate::shared::events::components::RawDatagramEvent<()>;
+/// Bevy [`Event`] emitted on the client when a [`Request`](crate::shared::rpc::Request) is received from the server
+pub type RequestEvent<R> = crate::shared::rpc::RequestEvent<R, ()>;
+/// Bevy [`Event`] emitted on the client when the server responded to a [`Request`](crate::shared::rpc::Request),
+/// or when the request timed out
+pub type ResponseEvent<R> = crate::shared::rpc::ResponseEvent<R, ()>;
@@ -0,0 +1,52 @@
+//! Handle the idle warnings on the client.
+//!
+//! When the server has an [idle kick policy](crate::prelude::server::IdleKickConfig), it warns the clients that
+//! haven't sent any message or input for a while that they are about to be kicked.
+//! The warning is emitted as an [`IdleWarningEvent`]; sending any message or input cancels the kick.
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::prelude::client::MessageEvent;
+use crate::prelude::is_host_server;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Message sent by the server to a client that is about to be kicked for being idle
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ServerIdleWarning {
+    pub(crate) kick_in: Duration,
+}
+
+/// Bevy [`Event`] emitted on the client when the server warns that the client will be kicked for being idle
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct IdleWarningEvent {
+    /// Time left before the kick, unless the client sends a message or an input
+    pub kick_in: Duration,
+}
+
+pub(crate) struct ClientIdlePlugin;
+
+impl Plugin for ClientIdlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<IdleWarningEvent>();
+        app.add_systems(
+            PreUpdate,
+            handle_idle_warnings
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Convert the idle warnings received from the server to events
+fn handle_idle_warnings(
+    mut messages: ResMut<Events<MessageEvent<ServerIdleWarning>>>,
+    mut events: EventWriter<IdleWarningEvent>,
+) {
+    for message in messages.drain() {
+        let kick_in = message.message.kick_in;
+        debug!(?kick_in, "The server will kick the client for being idle");
+        events.send(IdleWarningEvent { kick_in });
+    }
+}
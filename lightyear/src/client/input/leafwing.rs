@@ -735,7 +735,8 @@ fn receive_remote_player_input_messages<A: LeafwingUserAction>(
 
     if let Some(message_list) = connection.received_leafwing_input_messages.remove(&net) {
         for message_bytes in message_list {
-            let mut reader = Reader::from(message_bytes);
+            let mut reader = Reader::from(message_bytes)
+                .with_versions(connection.replication_receiver.remote_versions.clone());
             match message_registry.deserialize::<InputMessage<A>>(
                 &mut reader,
                 &mut connection
@@ -127,6 +127,14 @@ impl SharedIoConfig<ClientTransport> {
                 let decompressor = ZstdDecompressor::new();
                 receiver = Box::new(decompressor.wrap(receiver));
             }
+            #[cfg(feature = "zstd")]
+            CompressionConfig::ZstdDictionary { level, dictionary } => {
+                use crate::transport::middleware::PacketSenderWrapper;
+                let compressor = ZstdCompressor::with_dictionary(level, &dictionary)?;
+                sender = Box::new(compressor.wrap(sender));
+                let decompressor = ZstdDecompressor::with_dictionary(&dictionary)?;
+                receiver = Box::new(decompressor.wrap(receiver));
+            }
             #[cfg(feature = "lz4")]
             CompressionConfig::Lz4 => {
                 use crate::transport::middleware::PacketSenderWrapper;
@@ -138,6 +146,13 @@ impl SharedIoConfig<ClientTransport> {
                 receiver = Box::new(decompressor.wrap(receiver));
             }
         }
+        // the payloads are captured before they are compressed
+        #[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+        if let Some(capture_config) = self.capture {
+            use crate::transport::middleware::compression::zstd::capture::PayloadCapture;
+            use crate::transport::middleware::PacketSenderWrapper;
+            sender = Box::new(PayloadCapture::start(capture_config)?.wrap(sender));
+        }
         Ok(BaseIo {
             local_addr,
             sender,
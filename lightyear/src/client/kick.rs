@@ -0,0 +1,115 @@
+//! Handle kicks on the client.
+//!
+//! A server can disconnect a client and tell it why with
+//! [`ConnectionManager::kick`](crate::prelude::server::ConnectionManager::kick).
+//!
+//! The reason is sent in a reliable message before the disconnection. When the client gets disconnected,
+//! the [`DisconnectEvent`](crate::prelude::client::DisconnectEvent) contains
+//! [`DisconnectReason::Kicked`](crate::connection::client::DisconnectReason::Kicked) with that reason,
+//! instead of a generic disconnection or timeout.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::client::networking::update_connection_phase;
+use crate::prelude::client::MessageEvent;
+use crate::prelude::is_host_server;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Message sent by the server right before it disconnects the client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ServerKick {
+    pub(crate) reason: String,
+}
+
+/// Reason of the kick received from the server, reported when the connection is closed
+#[derive(Resource, Default)]
+pub(crate) struct KickReason(pub(crate) Option<String>);
+
+pub(crate) struct ClientKickPlugin;
+
+impl Plugin for ClientKickPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KickReason>();
+        app.add_systems(
+            PreUpdate,
+            handle_kick
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .before(update_connection_phase)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Remember the reason of the kick until the server closes the connection
+fn handle_kick(
+    mut messages: ResMut<Events<MessageEvent<ServerKick>>>,
+    mut kick_reason: ResMut<KickReason>,
+) {
+    for message in messages.drain() {
+        info!("Kicked by the server: {}", message.message.reason);
+        kick_reason.0 = Some(message.message.reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::networking::NetworkingState;
+    use crate::connection::client::DisconnectReason;
+    use crate::prelude::client::DisconnectEvent;
+    use crate::prelude::server::ConnectionManager;
+    use crate::prelude::ClientId;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Disconnections(Vec<Option<String>>);
+
+    #[test]
+    fn test_kick_with_reason() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<Disconnections>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<DisconnectEvent>,
+             mut disconnections: ResMut<Disconnections>| {
+                disconnections
+                    .0
+                    .extend(events.read().map(|event| match &event.reason {
+                        Some(DisconnectReason::Kicked(reason)) => Some(reason.clone()),
+                        _ => None,
+                    }));
+            },
+        );
+
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .kick(client_id, "AFK")
+            .unwrap();
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<Disconnections>().0,
+            vec![Some("AFK".to_string())]
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .is_err());
+    }
+}
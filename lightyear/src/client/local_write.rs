@@ -0,0 +1,266 @@
+//! Write components of server entities locally, see [`crate::shared::replication::local_write`]
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use tracing::{debug, error};
+
+use crate::channel::builder::ControlChannel;
+use crate::client::connection::ConnectionManager;
+use crate::prelude::client::MessageEvent;
+use crate::prelude::{is_host_server, ComponentRegistry};
+use crate::protocol::component::ComponentKind;
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::shared::replication::local_write::{LocalWrite, LocalWriteAck, LocalWrites};
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+pub trait LocalWriteCommandsExt {
+    /// Write the component on the entity immediately, and send the write to the server.
+    ///
+    /// Server updates for the component that were sent before the server applied the write are ignored,
+    /// so that the local value doesn't get corrected back and forth. The component must be registered in the protocol.
+    fn write_local<C: Component>(&mut self, component: C);
+}
+
+impl LocalWriteCommandsExt for EntityCommands<'_> {
+    fn write_local<C: Component>(&mut self, mut component: C) {
+        self.add(move |entity: Entity, world: &mut World| {
+            let kind = ComponentKind::of::<C>();
+            let Some(mut entity_mut) = world.get_entity_mut(entity) else {
+                return;
+            };
+            let command_id = match entity_mut.get_mut::<LocalWrites>() {
+                Some(mut writes) => writes.push(kind),
+                None => {
+                    let mut writes = LocalWrites::default();
+                    let command_id = writes.push(kind);
+                    entity_mut.insert(writes);
+                    command_id
+                }
+            };
+            world.resource_scope(|world, mut manager: Mut<ConnectionManager>| {
+                let mut writer = Writer::default();
+                if let Err(e) = world.resource::<ComponentRegistry>().serialize(
+                    &mut component,
+                    &mut writer,
+                    Some(
+                        &mut manager
+                            .replication_receiver
+                            .remote_entity_map
+                            .local_to_remote,
+                    ),
+                ) {
+                    error!("Could not serialize the local write: {e:?}");
+                    return;
+                }
+                let mut message = LocalWrite {
+                    entity,
+                    command_id,
+                    component: writer.split().to_vec(),
+                };
+                manager.map_entities_to_remote(&mut message);
+                if let Err(e) = manager.send_message::<ControlChannel, _>(&mut message) {
+                    error!("Could not send the local write: {e:?}");
+                }
+            });
+            world.entity_mut(entity).insert(component);
+        });
+    }
+}
+
+pub(crate) struct LocalWritePlugin;
+
+impl Plugin for LocalWritePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            handle_local_write_acks
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Update the [`LocalWrites`] when the server acknowledges a write,
+/// and apply the server's value if the write was refused
+fn handle_local_write_acks(world: &mut World) {
+    let acks: Vec<_> = world
+        .resource_mut::<Events<MessageEvent<LocalWriteAck>>>()
+        .drain()
+        .map(|event| event.message)
+        .collect();
+    if acks.is_empty() {
+        return;
+    }
+    world.resource_scope(|world, registry: Mut<ComponentRegistry>| {
+        world.resource_scope(|world, mut manager: Mut<ConnectionManager>| {
+            for ack in acks {
+                let Some(mut entity_mut) = world.get_entity_mut(ack.entity) else {
+                    continue;
+                };
+                let Some(mut writes) = entity_mut.get_mut::<LocalWrites>() else {
+                    continue;
+                };
+                if !writes.ack(ack.command_id, ack.tick, !ack.accepted) {
+                    continue;
+                }
+                if !ack.accepted {
+                    debug!(entity = ?ack.entity, "The server refused a local write");
+                }
+                if let Some(correction) = ack.correction {
+                    let manager = &mut *manager;
+                    if let Err(e) = registry.raw_write(
+                        &mut Reader::from(correction)
+                            .with_versions(manager.replication_receiver.remote_versions.clone()),
+                        &mut entity_mut,
+                        ack.tick,
+                        &mut manager
+                            .replication_receiver
+                            .remote_entity_map
+                            .remote_to_local,
+                        &mut manager.events,
+                    ) {
+                        error!("Could not apply the server's value after a refused write: {e:?}");
+                    }
+                }
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::{ControlledBy, Replicate};
+    use crate::prelude::{client, ClientId, NetworkTarget};
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn setup(controlled_by: NetworkTarget) -> (BevyStepper, Entity, Entity) {
+        let mut stepper = BevyStepper::default();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((
+                Replicate {
+                    controlled_by: ControlledBy {
+                        target: controlled_by,
+                        ..default()
+                    },
+                    ..default()
+                },
+                ComponentSyncModeFull(1.0),
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+        (stepper, server_entity, client_entity)
+    }
+
+    #[test]
+    fn test_local_write_echo_suppressed() {
+        let (mut stepper, server_entity, client_entity) =
+            setup(NetworkTarget::Single(ClientId::Netcode(TEST_CLIENT_ID)));
+
+        // the server sends an update that the client hasn't received yet when it writes locally
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentSyncModeFull(2.0));
+        stepper.frame_step();
+        stepper
+            .client_app
+            .world_mut()
+            .commands()
+            .entity(client_entity)
+            .write_local(ComponentSyncModeFull(5.0));
+        stepper.client_app.world_mut().flush();
+        for _ in 0..10 {
+            stepper.frame_step();
+            // the local write is never overwritten by the stale server update
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world()
+                    .get::<ComponentSyncModeFull>(client_entity),
+                Some(&ComponentSyncModeFull(5.0))
+            );
+        }
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .get::<ComponentSyncModeFull>(server_entity),
+            Some(&ComponentSyncModeFull(5.0))
+        );
+
+        // server updates are applied again once the server caught up with the local write
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentSyncModeFull(3.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_entity),
+            Some(&ComponentSyncModeFull(3.0))
+        );
+        assert!(!stepper
+            .client_app
+            .world()
+            .get::<LocalWrites>(client_entity)
+            .unwrap()
+            .is_pending::<ComponentSyncModeFull>());
+    }
+
+    #[test]
+    fn test_local_write_refused() {
+        let (mut stepper, server_entity, client_entity) = setup(NetworkTarget::None);
+
+        stepper
+            .client_app
+            .world_mut()
+            .commands()
+            .entity(client_entity)
+            .write_local(ComponentSyncModeFull(5.0));
+        stepper.client_app.world_mut().flush();
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_entity),
+            Some(&ComponentSyncModeFull(5.0))
+        );
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        // the write was refused and the client got the server's value back
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .get::<ComponentSyncModeFull>(server_entity),
+            Some(&ComponentSyncModeFull(1.0))
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_entity),
+            Some(&ComponentSyncModeFull(1.0))
+        );
+    }
+}
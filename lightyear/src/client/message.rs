@@ -3,7 +3,7 @@
 use bevy::prelude::{App, EventWriter, IntoSystemConfigs, PreUpdate, Res, ResMut};
 use byteorder::WriteBytesExt;
 use bytes::Bytes;
-use tracing::error;
+use tracing::{error, trace};
 
 use crate::client::connection::ConnectionManager;
 use crate::client::events::MessageEvent;
@@ -11,8 +11,11 @@ use crate::prelude::{client::is_connected, Message};
 use crate::protocol::message::{MessageKind, MessageRegistry};
 use crate::serialize::reader::Reader;
 use crate::serialize::{SerializationError, ToBytes};
+use crate::shared::events::components::EventStamp;
 use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::sets::{ClientMarker, InternalMainSet};
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::TimeManager;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ClientMessage {
@@ -50,6 +53,8 @@ fn read_message<M: Message>(
     message_registry: Res<MessageRegistry>,
     mut connection: ResMut<ConnectionManager>,
     mut event: EventWriter<MessageEvent<M>>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
 ) {
     let kind = MessageKind::of::<M>();
     let Some(net) = message_registry.kind_map.net_id(&kind).copied() else {
@@ -60,10 +65,12 @@ fn read_message<M: Message>(
         return;
     };
     if let Some(message_list) = connection.received_messages.remove(&net) {
+        let stamp = EventStamp::now(&tick_manager, &time_manager);
         for message in message_list {
-            let mut reader = Reader::from(message);
+            let mut reader = Reader::from(message)
+                .with_versions(connection.replication_receiver.remote_versions.clone());
             // we have to re-decode the net id
-            let Ok(message) = message_registry.deserialize::<M>(
+            let Ok((message, trace_id)) = message_registry.deserialize_with_trace_id::<M>(
                 &mut reader,
                 &mut connection
                     .replication_receiver
@@ -73,7 +80,16 @@ fn read_message<M: Message>(
                 error!("Could not deserialize message");
                 continue;
             };
-            event.send(MessageEvent::new(message, ()));
+            trace!(
+                ?trace_id,
+                "Received message: {:?}",
+                std::any::type_name::<M>()
+            );
+            event.send(
+                MessageEvent::new(message, ())
+                    .with_trace_id(trace_id)
+                    .with_stamp(stamp),
+            );
         }
     }
 }
@@ -193,9 +209,12 @@ pub(crate) fn add_client_receive_message_from_server<M: Message>(app: &mut App)
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::client::error::ClientError;
+    use crate::client::networking::ClientCommands;
     use crate::serialize::writer::Writer;
     use crate::tests::host_server_stepper::HostServerStepper;
     use crate::tests::protocol::{Channel1, StringMessage};
+    use crate::tests::stepper::BevyStepper;
     use bevy::prelude::{EventReader, Resource, Update};
 
     #[test]
@@ -250,4 +269,40 @@ mod tests {
         // verify that the server received the message
         assert_eq!(stepper.server_app.world().resource::<Counter>().0, 1);
     }
+
+    #[test]
+    fn client_send_message_while_disconnected() {
+        let mut stepper = BevyStepper::default();
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<crate::prelude::client::ConnectionManager>()
+            .can_send::<Channel1>());
+
+        stepper
+            .client_app
+            .world_mut()
+            .commands()
+            .disconnect_client();
+        stepper.frame_step();
+        let mut manager = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<crate::prelude::client::ConnectionManager>();
+        assert!(!manager.can_send::<Channel1>());
+        assert!(matches!(
+            manager.send_message::<Channel1, StringMessage>(&mut StringMessage("a".to_string())),
+            Err(ClientError::NotConnected)
+        ));
+        assert!(manager.messages_to_send.is_empty());
+
+        // messages can be sent again as soon as the client starts reconnecting
+        stepper.client_app.world_mut().commands().connect_client();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<crate::prelude::client::ConnectionManager>()
+            .can_send::<Channel1>());
+    }
 }
@@ -17,17 +17,28 @@ pub mod plugin;
 
 pub mod prediction;
 
+pub mod queue;
+
 pub mod sync;
 
 pub mod diagnostics;
 mod easings;
 
+pub mod idle;
 pub(crate) mod io;
+pub(crate) mod kick;
+pub mod local_write;
 pub(crate) mod message;
 pub mod networking;
+pub mod redirect;
 pub mod replication;
+pub mod restart;
+pub(crate) mod session;
+pub mod shutdown;
+pub mod staleness;
 
 pub mod error;
 pub mod run_conditions;
+pub mod transient;
 #[cfg(target_family = "wasm")]
 pub mod web;
@@ -5,24 +5,29 @@ use async_channel::TryRecvError;
 use bevy::ecs::system::{RunSystemOnce, SystemChangeTick};
 use bevy::prelude::ResMut;
 use bevy::prelude::*;
-use tracing::{error, trace};
+use tracing::{error, info, trace};
 
 use crate::client::config::ClientConfig;
 use crate::client::connection::ConnectionManager;
-use crate::client::events::{ConnectEvent, DisconnectEvent};
+use crate::client::events::{ConnectEvent, ConnectionPhaseChanged, DisconnectEvent, RejectEvent};
 use crate::client::interpolation::Interpolated;
 use crate::client::io::ClientIoEvent;
+use crate::client::kick::KickReason;
 use crate::client::networking::utils::AppStateExt;
 use crate::client::prediction::Predicted;
 use crate::client::replication::send::ReplicateToServer;
+use crate::client::restart::PendingRestart;
 use crate::client::run_conditions::is_disconnected;
 use crate::client::sync::SyncSet;
-use crate::connection::client::{ClientConnection, ConnectionState, DisconnectReason, NetClient};
+use crate::connection::client::{
+    ClientConnection, ConnectionPhase, ConnectionState, DisconnectReason, NetClient, NetConfig,
+};
 use crate::connection::server::IoConfig;
 use crate::prelude::{
-    is_host_server, ChannelRegistry, MainSet, MessageRegistry, TickManager, TimeManager,
+    is_host_server, ChannelRegistry, EventStamp, MainSet, MessageRegistry, TickManager, TimeManager,
 };
 use crate::protocol::component::ComponentRegistry;
+use crate::protocol::hash::protocol_hash;
 use crate::server::clients::ControlledEntities;
 use crate::shared::config::Mode;
 use crate::shared::replication::components::Replicated;
@@ -42,6 +47,7 @@ impl Plugin for ClientNetworkingPlugin {
             .init_state_without_entering(NetworkingState::Disconnected)
             // RESOURCE
             .init_resource::<HostServerMetadata>()
+            .init_resource::<ConnectionPhase>()
             // SYSTEM SETS
             .configure_sets(
                 PreUpdate,
@@ -93,6 +99,10 @@ impl Plugin for ClientNetworkingPlugin {
                     // TODO: update virtual time with Time<Real> so we have more accurate time at Send time.
                     sync_update.in_set(SyncSet),
                 ),
+            )
+            .add_systems(
+                PreUpdate,
+                update_connection_phase.after(InternalMainSet::<ClientMarker>::EmitEvents),
             );
 
         // CONNECTING
@@ -173,6 +183,13 @@ pub(crate) fn receive_packets(
         if state.get() != &NetworkingState::Disconnected {
             next_state.set(NetworkingState::Disconnected);
         }
+    } else if let Some(channel) = connection.message_manager.retries_exhausted() {
+        info!(
+            ?channel,
+            "A message was not acknowledged after the maximum number of retries, disconnecting"
+        );
+        netclient.disconnect_reason = Some(DisconnectReason::RetriesExhausted(channel));
+        next_state.set(NetworkingState::Disconnected);
     }
 
     // RECV PACKETS: buffer packets into message managers
@@ -300,6 +317,35 @@ pub enum NetworkingState {
     Connected,
 }
 
+/// Update the [`ConnectionPhase`] resource, and emit a [`ConnectionPhaseChanged`] event when it changes
+pub(crate) fn update_connection_phase(
+    netclient: Option<Res<ClientConnection>>,
+    pending_restart: Option<Res<PendingRestart>>,
+    kick_reason: Option<Res<KickReason>>,
+    mut phase: ResMut<ConnectionPhase>,
+    mut events: EventWriter<ConnectionPhaseChanged>,
+) {
+    let current = netclient.map_or(ConnectionPhase::Disconnected, |netclient| netclient.phase());
+    let current = match current {
+        ConnectionPhase::Connected if kick_reason.is_some_and(|kick| kick.0.is_some()) => {
+            ConnectionPhase::Disconnecting
+        }
+        ConnectionPhase::Connected => ConnectionPhase::Connected,
+        _ if pending_restart.is_some_and(|restart| restart.disconnected) => {
+            ConnectionPhase::Resuming
+        }
+        current => current,
+    };
+    if *phase != current {
+        trace!(previous = ?*phase, ?current, "connection phase changed");
+        events.send(ConnectionPhaseChanged {
+            previous: *phase,
+            current,
+        });
+        *phase = current;
+    }
+}
+
 /// Listen to [`ClientIoEvent`]s and update the [`IoState`] and [`NetworkingState`] accordingly
 fn listen_io_state(
     mut next_state: ResMut<NextState<NetworkingState>>,
@@ -356,6 +402,8 @@ fn on_connect(
     mut connect_event_writer: EventWriter<ConnectEvent>,
     mut commands: Commands,
     netcode: Res<ClientConnection>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
     mut query: Query<&mut ReplicateToServer>,
 ) {
     // Set all the ReplicateToServer ticks to changed, so that we replicate existing entities to the server
@@ -367,15 +415,18 @@ fn on_connect(
         "Running OnConnect schedule with client id: {:?}",
         netcode.id()
     );
-    connect_event_writer.send(ConnectEvent::new(netcode.id()));
+    let stamp = EventStamp::now(&tick_manager, &time_manager);
+    connect_event_writer.send(ConnectEvent::new(netcode.id()).with_stamp(stamp));
     // also trigger the event
-    commands.trigger(ConnectEvent::new(netcode.id()));
+    commands.trigger(ConnectEvent::new(netcode.id()).with_stamp(stamp));
 }
 
 /// Same as on-connect, but only runs if we are in host-server mode
 fn on_connect_host_server(
     mut commands: Commands,
     netcode: Res<ClientConnection>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
     mut metadata: ResMut<HostServerMetadata>,
     mut server_manager: ResMut<crate::server::connection::ConnectionManager>,
     mut connect_event_writer: EventWriter<ConnectEvent>,
@@ -383,23 +434,28 @@ fn on_connect_host_server(
     // spawn an entity for the client
     let client_entity = commands.spawn(ControlledEntities::default()).id();
     // start a server connection for that client (which will also send a ConnectEvent on the server)
-    server_manager.add(netcode.id(), client_entity);
+    server_manager.add(netcode.id(), client_entity, None);
     server_manager
         .connection_mut(netcode.id())
         .unwrap()
         .set_local_client();
     metadata.client_entity = Some(client_entity);
-    connect_event_writer.send(ConnectEvent::new(netcode.id()));
+    let stamp = EventStamp::now(&tick_manager, &time_manager);
+    connect_event_writer.send(ConnectEvent::new(netcode.id()).with_stamp(stamp));
     // also trigger the event
-    commands.trigger(ConnectEvent::new(netcode.id()));
+    commands.trigger(ConnectEvent::new(netcode.id()).with_stamp(stamp));
 }
 
 /// System that runs when we enter the Disconnected state
-/// Updates the DisconnectEvent events
+/// Updates the DisconnectEvent and RejectEvent events
 fn on_disconnect(
     mut connection_manager: ResMut<ConnectionManager>,
     mut disconnect_event_writer: EventWriter<DisconnectEvent>,
+    mut reject_event_writer: EventWriter<RejectEvent>,
     mut netclient: ResMut<ClientConnection>,
+    mut kick_reason: ResMut<KickReason>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
     mut commands: Commands,
     received_entities: Query<Entity, Or<(With<Replicated>, With<Predicted>, With<Interpolated>)>>,
 ) {
@@ -413,6 +469,7 @@ fn on_disconnect(
 
     // set synced to false
     connection_manager.sync_manager.synced = false;
+    connection_manager.disconnected = true;
 
     // try to disconnect again to close io tasks (in case the disconnection is from the io)
     let _ = netclient.disconnect();
@@ -420,21 +477,40 @@ fn on_disconnect(
     // no need to update the io state, because we will recreate a new `ClientConnection`
     // for the next connection attempt
     let reason = std::mem::take(&mut netclient.disconnect_reason);
-    disconnect_event_writer.send(DisconnectEvent { reason });
+    // a kick is followed by a disconnection from the server, report the reason of the kick instead
+    let reason = kick_reason
+        .0
+        .take()
+        .map(DisconnectReason::Kicked)
+        .or(reason);
+    if let Some(DisconnectReason::Denied(reason)) = &reason {
+        reject_event_writer.send(RejectEvent {
+            reason: reason.clone(),
+        });
+    }
+    disconnect_event_writer.send(DisconnectEvent {
+        reason,
+        stamp: Some(EventStamp::now(&tick_manager, &time_manager)),
+    });
     // commands.trigger(DisconnectEvent { reason });
     // TODO: remove ClientConnection and ConnectionManager resources?
 }
 
 fn on_disconnect_host_server(
     netcode: Res<ClientConnection>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
+    mut connection_manager: ResMut<ConnectionManager>,
     mut metadata: ResMut<HostServerMetadata>,
     mut server_disconnect_event_writer: ResMut<Events<crate::server::events::DisconnectEvent>>,
 ) {
+    connection_manager.disconnected = true;
     let client_id = netcode.id();
     if let Some(client_entity) = std::mem::take(&mut metadata.client_entity) {
         server_disconnect_event_writer.send(crate::server::events::DisconnectEvent {
             client_id,
             entity: client_entity,
+            stamp: Some(EventStamp::now(&tick_manager, &time_manager)),
         });
     }
 }
@@ -446,7 +522,17 @@ fn on_disconnect_host_server(
 /// - the client connection's internal time is up-to-date (otherwise it might not be, since we don't call `update` while disconnected)
 /// - we can take into account any changes to the client config
 fn rebuild_client_connection(world: &mut World) {
-    let client_config = world.resource::<ClientConfig>().clone();
+    let mut client_config = world.resource::<ClientConfig>().clone();
+    // send the hash of our protocol to the server, so that it can check that it uses the same protocol
+    if let NetConfig::Netcode { config, .. } = &mut client_config.net {
+        config.protocol_hash = config.send_protocol_hash.then(|| {
+            protocol_hash(
+                world.resource::<ChannelRegistry>(),
+                world.resource::<ComponentRegistry>(),
+                world.resource::<MessageRegistry>(),
+            )
+        });
+    }
     // if client_config.shared.mode == Mode::HostServer {
     //     assert!(
     //         matches!(client_config.net, NetConfig::Local { .. }),
@@ -495,6 +581,11 @@ fn connect(world: &mut World) {
         .inspect_err(|e| {
             error!("Error connecting client: {}", e);
         });
+    let disconnected = matches!(
+        world.resource::<ClientConnection>().state(),
+        ConnectionState::Disconnected { .. }
+    );
+    world.resource_mut::<ConnectionManager>().disconnected = disconnected;
     let config = world.resource::<ClientConfig>();
 
     if matches!(
@@ -516,6 +607,8 @@ pub trait ClientCommands {
     fn connect_client(&mut self);
 
     /// Disconnect the client
+    ///
+    /// This also cancels the automatic reconnection after a server restart.
     fn disconnect_client(&mut self);
 }
 
@@ -525,6 +618,7 @@ impl ClientCommands for Commands<'_, '_> {
     }
 
     fn disconnect_client(&mut self) {
+        self.remove_resource::<PendingRestart>();
         self.insert_resource(NextState::Pending(NetworkingState::Disconnected));
     }
 }
@@ -562,6 +656,8 @@ mod tests {
 
     use crate::{
         client::config::ClientConfig,
+        client::events::ConnectionPhaseChanged,
+        connection::client::ConnectionPhase,
         prelude::{client::ClientCommands, server::*, SharedConfig, TickConfig},
         tests::host_server_stepper::HostServerStepper,
     };
@@ -623,4 +719,69 @@ mod tests {
         stepper.frame_step();
         assert_eq!(stepper.server_app.world().resource::<CheckCounter>().0, 2); // 2 because local client as well as external client disconnect
     }
+
+    #[derive(Resource, Default)]
+    struct Phases(Vec<ConnectionPhase>);
+
+    fn record_phases(mut events: EventReader<ConnectionPhaseChanged>, mut phases: ResMut<Phases>) {
+        phases.0.extend(events.read().map(|event| event.current));
+    }
+
+    /// The phases of the handshake are observable, and a kick is reported as `Disconnecting`
+    #[test]
+    fn test_connection_phase_changes() {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = crate::tests::stepper::BevyStepper::new(
+            shared_config,
+            ClientConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .init_resource::<Phases>()
+            .add_systems(Update, record_phases);
+        stepper.init();
+        assert_eq!(
+            stepper.client_app.world().resource::<Phases>().0,
+            vec![
+                ConnectionPhase::Connecting,
+                ConnectionPhase::SendingChallenge,
+                ConnectionPhase::Connected
+            ]
+        );
+
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<Phases>()
+            .0
+            .clear();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .kick(
+                crate::prelude::ClientId::Netcode(crate::tests::stepper::TEST_CLIENT_ID),
+                "AFK",
+            )
+            .unwrap();
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<Phases>().0,
+            vec![
+                ConnectionPhase::Disconnecting,
+                ConnectionPhase::Disconnected
+            ]
+        );
+        assert_eq!(
+            *stepper.client_app.world().resource::<ConnectionPhase>(),
+            ConnectionPhase::Disconnected
+        );
+    }
 }
@@ -13,12 +13,20 @@ use bevy::prelude::*;
 
 use crate::client::diagnostics::ClientDiagnosticsPlugin;
 use crate::client::events::ClientEventsPlugin;
+use crate::client::idle::ClientIdlePlugin;
 use crate::client::interpolation::plugin::InterpolationPlugin;
+use crate::client::kick::ClientKickPlugin;
 use crate::client::networking::ClientNetworkingPlugin;
 use crate::client::prediction::plugin::PredictionPlugin;
+use crate::client::queue::ClientQueuePlugin;
+use crate::client::redirect::ClientRedirectPlugin;
 use crate::client::replication::{
     receive::ClientReplicationReceivePlugin, send::ClientReplicationSendPlugin,
 };
+use crate::client::restart::ClientRestartPlugin;
+use crate::client::session::ClientSessionPlugin;
+use crate::client::shutdown::ClientShutdownPlugin;
+use crate::client::staleness::ClientStalenessPlugin;
 use crate::shared::plugin::SharedPlugin;
 
 use super::config::ClientConfig;
@@ -58,6 +66,14 @@ impl PluginGroup for ClientPlugins {
             })
             .add(ClientEventsPlugin)
             .add(ClientNetworkingPlugin)
+            .add(ClientRedirectPlugin)
+            .add(ClientRestartPlugin)
+            .add(ClientKickPlugin)
+            .add(ClientSessionPlugin)
+            .add(ClientShutdownPlugin)
+            .add(ClientQueuePlugin)
+            .add(ClientIdlePlugin)
+            .add(ClientStalenessPlugin)
             .add(ClientDiagnosticsPlugin::default())
             .add(ClientReplicationReceivePlugin { tick_interval })
             .add(ClientReplicationSendPlugin { tick_interval })
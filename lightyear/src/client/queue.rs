@@ -0,0 +1,70 @@
+//! Handle the connection queue on the client.
+//!
+//! When the server reached its [maximum number of connections](crate::prelude::server::ServerConfig::max_connections),
+//! it can put the new clients in a queue instead of rejecting them.
+//! A queued client is connected to the server but doesn't receive any replication or message until a slot opens.
+//!
+//! While it waits, the client periodically receives its position in the queue as a [`QueuePositionEvent`],
+//! and a [`QueueAdmittedEvent`] is emitted when it gets admitted in the server.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::prelude::client::MessageEvent;
+use crate::prelude::is_host_server;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Message sent by the server to the clients that are waiting in the connection queue
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ServerQueueUpdate {
+    /// Position of the client in the queue (starting at 1)
+    Position(u32),
+    /// The client left the queue and was added to the server
+    Admitted,
+}
+
+/// Bevy [`Event`] emitted on the client when the server reports its position in the connection queue
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct QueuePositionEvent {
+    /// Position of the client in the queue. The client with position 1 is the next one to be admitted
+    pub position: u32,
+}
+
+/// Bevy [`Event`] emitted on the client when a slot opened on the server and the client left the connection queue
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct QueueAdmittedEvent;
+
+pub(crate) struct ClientQueuePlugin;
+
+impl Plugin for ClientQueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<QueuePositionEvent>();
+        app.add_event::<QueueAdmittedEvent>();
+        app.add_systems(
+            PreUpdate,
+            handle_queue_updates
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Convert the queue updates received from the server to events
+fn handle_queue_updates(
+    mut messages: ResMut<Events<MessageEvent<ServerQueueUpdate>>>,
+    mut position_events: EventWriter<QueuePositionEvent>,
+    mut admitted_events: EventWriter<QueueAdmittedEvent>,
+) {
+    for message in messages.drain() {
+        match message.message {
+            ServerQueueUpdate::Position(position) => {
+                debug!(?position, "Waiting in the connection queue");
+                position_events.send(QueuePositionEvent { position });
+            }
+            ServerQueueUpdate::Admitted => {
+                debug!("Admitted in the server");
+                admitted_events.send(QueueAdmittedEvent);
+            }
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Handle server redirects on the client.
+//!
+//! A server can ask a client to disconnect and connect to another server instead
+//! (for example a login server that redirects the client to a game server) with
+//! [`ConnectionManager::redirect`](crate::prelude::server::ConnectionManager::redirect).
+//!
+//! When the client receives the redirect, a [`RedirectEvent`] is emitted. If [`RedirectConfig::follow`]
+//! is true (the default), the client also updates its [`ClientConfig`] to use the new server address
+//! and connect token, disconnects from the current server and connects to the new one.
+use std::net::SocketAddr;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::client::config::ClientConfig;
+use crate::client::networking::{ClientCommands, NetworkingState};
+use crate::connection::client::{Authentication, NetConfig};
+use crate::connection::netcode::ConnectToken;
+use crate::prelude::client::MessageEvent;
+use crate::prelude::is_host_server;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Configuration of how the client handles server redirects
+#[derive(Clone, Copy, Debug, Reflect)]
+pub struct RedirectConfig {
+    /// If true, the client automatically connects to the new server when it receives a redirect.
+    /// If false, only the [`RedirectEvent`] is emitted.
+    pub follow: bool,
+}
+
+impl Default for RedirectConfig {
+    fn default() -> Self {
+        Self { follow: true }
+    }
+}
+
+/// Message sent by the server to ask the client to connect to another server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ServerRedirect {
+    pub(crate) server_addr: SocketAddr,
+    /// Serialized [`ConnectToken`] to use to connect to the new server
+    pub(crate) token: Option<Vec<u8>>,
+}
+
+/// Bevy [`Event`] emitted on the client when the server asks us to connect to another server
+#[derive(Event)]
+pub struct RedirectEvent {
+    /// Address of the new server
+    pub server_addr: SocketAddr,
+    /// Token to use to connect to the new server.
+    /// If None, the current [`Authentication`] should be re-used with the new address.
+    pub token: Option<ConnectToken>,
+}
+
+/// Marker resource indicating that we should connect again once the disconnection is complete
+#[derive(Resource)]
+struct PendingRedirect;
+
+pub(crate) struct ClientRedirectPlugin;
+
+impl Plugin for ClientRedirectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RedirectEvent>();
+        app.add_systems(
+            PreUpdate,
+            (
+                handle_redirect.after(InternalMainSet::<ClientMarker>::EmitEvents),
+                reconnect_after_redirect,
+            )
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Emit a [`RedirectEvent`] for each redirect received from the server,
+/// and update the [`ClientConfig`] so that the next connection goes to the new server
+fn handle_redirect(
+    mut commands: Commands,
+    mut messages: ResMut<Events<MessageEvent<ServerRedirect>>>,
+    mut events: EventWriter<RedirectEvent>,
+    mut config: ResMut<ClientConfig>,
+) {
+    for message in messages.drain() {
+        let ServerRedirect { server_addr, token } = message.message;
+        let token = match token.map(|bytes| ConnectToken::try_from_bytes(&bytes)) {
+            Some(Ok(token)) => Some(token),
+            Some(Err(e)) => {
+                error!("Received a redirect with an invalid connect token: {e:?}");
+                continue;
+            }
+            None => None,
+        };
+        if config.redirect.follow {
+            if update_auth(&mut config.net, server_addr, token.clone()) {
+                info!("Redirected by the server to {server_addr:?}");
+                commands.insert_resource(PendingRedirect);
+                commands.disconnect_client();
+            } else {
+                error!("Cannot follow the redirect to {server_addr:?}: a new connect token is required");
+            }
+        }
+        events.send(RedirectEvent { server_addr, token });
+    }
+}
+
+/// Update the authentication so that it targets the new server.
+/// Returns false if the redirect cannot be followed with the current [`NetConfig`]
+fn update_auth(net: &mut NetConfig, server_addr: SocketAddr, token: Option<ConnectToken>) -> bool {
+    let NetConfig::Netcode { auth, .. } = net else {
+        return false;
+    };
+    match (token, auth) {
+        (Some(token), auth) => {
+            *auth = Authentication::Token(token);
+            true
+        }
+        (
+            None,
+            Authentication::Manual {
+                server_addr: addr, ..
+            },
+        ) => {
+            *addr = server_addr;
+            true
+        }
+        // a connect token is only valid for the server addresses it was created for
+        (None, _) => false,
+    }
+}
+
+/// Connect to the new server once we are disconnected from the previous one
+fn reconnect_after_redirect(
+    mut commands: Commands,
+    pending: Option<Res<PendingRedirect>>,
+    state: Res<State<NetworkingState>>,
+) {
+    if pending.is_some() && state.get() == &NetworkingState::Disconnected {
+        commands.remove_resource::<PendingRedirect>();
+        commands.connect_client();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::state::app::StatesPlugin;
+    use bevy::utils::Duration;
+
+    use crate::client::networking::ClientCommands;
+    use crate::connection::netcode::{generate_key, Key};
+    use crate::prelude::server::ServerCommands;
+    use crate::prelude::{client, server, ClientId, SharedConfig, TickConfig};
+    use crate::tests::protocol::ProtocolPlugin;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+    use crate::transport::LOCAL_SOCKET;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Redirects(Vec<SocketAddr>);
+
+    /// Find a free local UDP address
+    fn free_addr() -> SocketAddr {
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    fn server_app(addr: SocketAddr, private_key: Key) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin));
+        let config = server::ServerConfig {
+            shared: SharedConfig {
+                tick: TickConfig::new(Duration::from_millis(10)),
+                ..default()
+            },
+            net: vec![server::NetConfig::Netcode {
+                config: server::NetcodeConfig::default().with_key(private_key),
+                io: server::IoConfig::from_transport(server::ServerTransport::UdpSocket(addr)),
+            }],
+            ..default()
+        };
+        app.add_plugins((server::ServerPlugins::new(config), ProtocolPlugin));
+        app
+    }
+
+    /// The client is redirected from a first server to a second server listening on another address
+    #[test]
+    fn test_follow_redirect() {
+        let private_key = generate_key();
+        let (first_addr, second_addr) = (free_addr(), free_addr());
+        let mut first_server = server_app(first_addr, private_key);
+        let mut second_server = server_app(second_addr, private_key);
+
+        let mut client_app = App::new();
+        client_app.add_plugins((MinimalPlugins, StatesPlugin));
+        let config = ClientConfig {
+            shared: SharedConfig {
+                tick: TickConfig::new(Duration::from_millis(10)),
+                ..default()
+            },
+            net: NetConfig::Netcode {
+                auth: Authentication::Manual {
+                    server_addr: first_addr,
+                    protocol_id: 0,
+                    private_key,
+                    client_id: TEST_CLIENT_ID,
+                },
+                config: Default::default(),
+                io: client::IoConfig::from_transport(client::ClientTransport::UdpSocket(
+                    free_addr(),
+                )),
+            },
+            ..default()
+        };
+        client_app.add_plugins((client::ClientPlugins::new(config), ProtocolPlugin));
+        client_app.init_resource::<Redirects>();
+        client_app.add_systems(
+            Update,
+            |mut events: EventReader<RedirectEvent>, mut redirects: ResMut<Redirects>| {
+                for event in events.read() {
+                    redirects.0.push(event.server_addr);
+                }
+            },
+        );
+
+        for app in [&mut first_server, &mut second_server, &mut client_app] {
+            app.finish();
+            app.cleanup();
+        }
+        first_server
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.start_server());
+        second_server
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.start_server());
+        client_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.connect_client());
+
+        fn connected(app: &App) -> bool {
+            app.world()
+                .resource::<server::ConnectionManager>()
+                .connection(ClientId::Netcode(TEST_CLIENT_ID))
+                .is_ok()
+        }
+        /// Update the apps until the condition on the servers is true
+        fn step(apps: [&mut App; 3], until: impl Fn(&App, &App) -> bool) -> bool {
+            let [client, first, second] = apps;
+            for _ in 0..200 {
+                client.update();
+                std::thread::sleep(Duration::from_millis(1));
+                first.update();
+                second.update();
+                std::thread::sleep(Duration::from_millis(1));
+                if until(first, second) {
+                    return true;
+                }
+            }
+            false
+        }
+        assert!(step(
+            [&mut client_app, &mut first_server, &mut second_server],
+            |first, _| connected(first)
+        ));
+
+        // redirect to the second server, with the same authentication
+        first_server
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .redirect(ClientId::Netcode(TEST_CLIENT_ID), second_addr, None)
+            .unwrap();
+        assert!(step(
+            [&mut client_app, &mut first_server, &mut second_server],
+            |first, second| !connected(first) && connected(second)
+        ));
+        assert_eq!(
+            client_app.world().resource::<Redirects>().0,
+            vec![second_addr]
+        );
+    }
+
+    #[test]
+    fn test_ignore_redirect() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ClientConfig>()
+            .redirect
+            .follow = false;
+
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .redirect(ClientId::Netcode(TEST_CLIENT_ID), LOCAL_SOCKET, None)
+            .unwrap();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Connected
+        );
+    }
+}
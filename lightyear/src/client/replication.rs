@@ -9,6 +9,8 @@ use crate::shared::sets::{ClientMarker, InternalReplicationSet};
 
 pub(crate) mod receive {
     use super::*;
+    use crate::client::local_write::LocalWritePlugin;
+    use crate::client::transient::TransientPlugin;
     use crate::prelude::client::MessageEvent;
     use crate::prelude::{
         client::{is_connected, is_synced},
@@ -27,7 +29,9 @@ pub(crate) mod receive {
             // PLUGIN
             app.add_plugins(ReplicationReceivePlugin::<ConnectionManager>::new(
                 self.tick_interval,
-            ));
+            ))
+            .add_plugins(LocalWritePlugin)
+            .add_plugins(TransientPlugin);
 
             app.configure_sets(
                 PostUpdate,
@@ -94,11 +98,15 @@ pub(crate) mod send {
         InitialReplicated, Replicating, ReplicationGroupId,
     };
 
+    use crate::channel::builder::BaselineChannel;
+    use crate::client::events::ConnectEvent;
     use crate::shared::replication::archetypes::{
         get_erased_component, ClientReplicatedArchetypes,
     };
     use crate::shared::replication::authority::HasAuthority;
+    use crate::shared::replication::baseline::{BaselineReport, StaticBaseline};
     use crate::shared::replication::error::ReplicationError;
+    use crate::shared::sets::InternalMainSet;
     use bevy::ecs::system::SystemChangeTick;
     use bevy::ptr::Ptr;
 
@@ -148,6 +156,12 @@ pub(crate) mod send {
                             .in_set(InternalReplicationSet::<ClientMarker>::AfterBuffer),
                         add_replicated_component_host_server.run_if(is_host_server),
                     ),
+                )
+                .add_systems(
+                    PreUpdate,
+                    send_baseline_report
+                        .run_if(not(is_host_server))
+                        .after(InternalMainSet::<ClientMarker>::EmitEvents),
                 );
 
             // TODO: since we use observers, we could buffer a component add/remove/add within a single replication interval!
@@ -159,6 +173,22 @@ pub(crate) mod send {
         }
     }
 
+    /// When we connect, let the server know which static baseline we have, so that it can skip
+    /// replicating the baseline entities if we already have them
+    fn send_baseline_report(
+        mut connect_events: EventReader<ConnectEvent>,
+        baseline: Option<Res<StaticBaseline>>,
+        mut connection_manager: ResMut<ConnectionManager>,
+    ) {
+        for _ in connect_events.read() {
+            let _ = connection_manager
+                .send_message::<BaselineChannel, _>(&mut BaselineReport {
+                    hash: baseline.as_ref().map(|baseline| baseline.hash),
+                })
+                .inspect_err(|e| error!("could not send baseline report: {:?}", e));
+        }
+    }
+
     /// Marker component that indicates that the entity should be replicated to the server
     ///
     /// If this component gets removed, we despawn the entity on the server.
@@ -404,14 +434,24 @@ pub(crate) mod send {
             return;
         }
         debug!(?entity, "Prepare entity spawn to server");
-        if let Some(TargetEntity::Preexisting(remote_entity)) = target_entity {
-            sender
-                .replication_sender
-                .prepare_entity_spawn_reuse(entity, group_id, *remote_entity);
-        } else {
-            sender
-                .replication_sender
-                .prepare_entity_spawn(entity, group_id);
+        match target_entity {
+            Some(TargetEntity::Preexisting(remote_entity)) => {
+                sender.replication_sender.prepare_entity_spawn_reuse(
+                    entity,
+                    group_id,
+                    *remote_entity,
+                );
+            }
+            Some(TargetEntity::Stable(stable_id)) => {
+                sender
+                    .replication_sender
+                    .prepare_entity_spawn_stable(entity, group_id, *stable_id);
+            }
+            _ => {
+                sender
+                    .replication_sender
+                    .prepare_entity_spawn(entity, group_id);
+            }
         }
         // also set the priority for the group when we spawn it
         sender
@@ -0,0 +1,328 @@
+//! Handle planned server restarts on the client.
+//!
+//! Before restarting, a server can warn its clients with
+//! [`ConnectionManager::notify_restart`](crate::prelude::server::ConnectionManager::notify_restart).
+//!
+//! When the client receives the notice, a [`RestartNoticeEvent`] is emitted. If [`RestartConfig::reconnect`]
+//! is true (the default), the client expects to be disconnected: as soon as the connection is lost, it connects again
+//! (with the connect token attached to the notice, if any) until the server is back or
+//! [`RestartConfig::reconnect_timeout`] has elapsed. A planned restart is then a short interruption instead of a
+//! timeout followed by a manual rejoin.
+//!
+//! The delay between two failed attempts starts at [`RestartConfig::reconnect_interval`] and doubles after each
+//! attempt, up to [`RestartConfig::max_reconnect_interval`].
+//! Disconnecting the client with [`ClientCommands::disconnect_client`] cancels the reconnection.
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::client::config::ClientConfig;
+use crate::client::networking::{update_connection_phase, ClientCommands, NetworkingState};
+use crate::connection::client::{Authentication, NetConfig};
+use crate::connection::netcode::ConnectToken;
+use crate::prelude::client::MessageEvent;
+use crate::prelude::is_host_server;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Configuration of how the client handles planned server restarts
+#[derive(Clone, Copy, Debug, Reflect)]
+pub struct RestartConfig {
+    /// If true, the client automatically reconnects when the server restarts after sending a restart notice.
+    /// If false, only the [`RestartNoticeEvent`] is emitted.
+    pub reconnect: bool,
+    /// How long after the announced restart time the client keeps trying to reconnect
+    pub reconnect_timeout: Duration,
+    /// Delay before retrying after the first failed reconnection attempt.
+    /// The delay doubles after each failed attempt.
+    pub reconnect_interval: Duration,
+    /// Maximum delay between two reconnection attempts
+    pub max_reconnect_interval: Duration,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            reconnect: true,
+            reconnect_timeout: Duration::from_secs(10),
+            reconnect_interval: Duration::from_millis(100),
+            max_reconnect_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Message sent by the server to warn the client that it is about to restart
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ServerRestart {
+    /// Time remaining before the restart
+    pub(crate) delay: Duration,
+    /// Serialized [`ConnectToken`] to use to reconnect after the restart
+    pub(crate) token: Option<Vec<u8>>,
+}
+
+/// Bevy [`Event`] emitted on the client when the server announces that it is about to restart
+#[derive(Event)]
+pub struct RestartNoticeEvent {
+    /// Time remaining before the restart
+    pub delay: Duration,
+    /// Token to use to reconnect after the restart.
+    /// If None, the current [`Authentication`] is re-used.
+    pub token: Option<ConnectToken>,
+}
+
+/// Resource present while the client expects the server to restart
+#[derive(Resource)]
+pub(crate) struct PendingRestart {
+    /// Time after which we stop trying to reconnect
+    deadline: Timer,
+    /// True once the connection to the server was lost
+    pub(crate) disconnected: bool,
+    /// Time to wait before the next reconnection attempt
+    retry: Timer,
+    /// Delay to wait after the next failed attempt
+    retry_delay: Duration,
+}
+
+pub(crate) struct ClientRestartPlugin;
+
+impl Plugin for ClientRestartPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RestartNoticeEvent>();
+        app.add_systems(
+            PreUpdate,
+            (
+                handle_restart_notice.after(InternalMainSet::<ClientMarker>::EmitEvents),
+                reconnect_after_restart,
+            )
+                .chain()
+                .before(update_connection_phase)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Emit a [`RestartNoticeEvent`] for each restart notice received from the server,
+/// and prepare the client to reconnect once the server is back
+fn handle_restart_notice(
+    mut commands: Commands,
+    mut messages: ResMut<Events<MessageEvent<ServerRestart>>>,
+    mut events: EventWriter<RestartNoticeEvent>,
+    mut config: ResMut<ClientConfig>,
+) {
+    for message in messages.drain() {
+        let ServerRestart { delay, token } = message.message;
+        let token = match token.map(|bytes| ConnectToken::try_from_bytes(&bytes)) {
+            Some(Ok(token)) => Some(token),
+            Some(Err(e)) => {
+                error!("Received a restart notice with an invalid connect token: {e:?}");
+                continue;
+            }
+            None => None,
+        };
+        if config.restart.reconnect {
+            if let (Some(token), NetConfig::Netcode { auth, .. }) = (token.clone(), &mut config.net)
+            {
+                *auth = Authentication::Token(token);
+            }
+            info!("The server will restart in {delay:?}");
+            commands.insert_resource(PendingRestart {
+                deadline: Timer::new(delay + config.restart.reconnect_timeout, TimerMode::Once),
+                disconnected: false,
+                retry: Timer::new(Duration::ZERO, TimerMode::Once),
+                retry_delay: config.restart.reconnect_interval,
+            });
+        }
+        events.send(RestartNoticeEvent { delay, token });
+    }
+}
+
+/// Connect again as soon as the connection to the restarting server is lost,
+/// then retry with an exponential backoff until the server is back
+fn reconnect_after_restart(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<ClientConfig>,
+    pending: Option<ResMut<PendingRestart>>,
+    state: Res<State<NetworkingState>>,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+    if pending.deadline.tick(time.delta()).finished() {
+        error!("Could not reconnect to the server after its restart");
+        commands.remove_resource::<PendingRestart>();
+        return;
+    }
+    match state.get() {
+        NetworkingState::Disconnected => {
+            if !pending.retry.tick(time.delta()).finished() {
+                return;
+            }
+            pending.disconnected = true;
+            pending.retry = Timer::new(pending.retry_delay, TimerMode::Once);
+            pending.retry_delay =
+                (pending.retry_delay * 2).min(config.restart.max_reconnect_interval);
+            commands.connect_client();
+        }
+        NetworkingState::Connected if pending.disconnected => {
+            info!("Reconnected to the server after its restart");
+            commands.remove_resource::<PendingRestart>();
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::events::ConnectionPhaseChanged;
+    use crate::connection::client::ConnectionPhase;
+    use crate::prelude::server::{self, ServerCommands};
+    use crate::prelude::ClientId;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Notices(Vec<Duration>);
+
+    #[derive(Resource, Default)]
+    struct Phases(Vec<ConnectionPhase>);
+
+    fn restart_server(stepper: &mut BevyStepper) {
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .notify_restart(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                Duration::from_millis(50),
+                None,
+            )
+            .unwrap();
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        stepper
+            .server_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.stop_server());
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.start_server());
+    }
+
+    fn client_state(stepper: &BevyStepper) -> NetworkingState {
+        *stepper
+            .client_app
+            .world()
+            .resource::<State<NetworkingState>>()
+            .get()
+    }
+
+    #[test]
+    fn test_reconnect_after_restart() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<Notices>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<RestartNoticeEvent>, mut notices: ResMut<Notices>| {
+                notices.0.extend(events.read().map(|event| event.delay));
+            },
+        );
+
+        stepper.client_app.init_resource::<Phases>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<ConnectionPhaseChanged>, mut phases: ResMut<Phases>| {
+                phases.0.extend(events.read().map(|event| event.current));
+            },
+        );
+
+        restart_server(&mut stepper);
+        assert_eq!(
+            stepper.client_app.world().resource::<Notices>().0,
+            vec![Duration::from_millis(50)]
+        );
+        for _ in 0..100 {
+            stepper.frame_step();
+            if client_state(&stepper) == NetworkingState::Connected {
+                break;
+            }
+        }
+        assert_eq!(client_state(&stepper), NetworkingState::Connected);
+        // the pending restart is cleared on the next frame
+        stepper.frame_step();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<server::ConnectionManager>()
+            .connection(ClientId::Netcode(TEST_CLIENT_ID))
+            .is_ok());
+        assert!(stepper
+            .client_app
+            .world()
+            .get_resource::<PendingRestart>()
+            .is_none());
+        let phases = &stepper.client_app.world().resource::<Phases>().0;
+        assert!(phases.contains(&ConnectionPhase::Resuming));
+        assert_eq!(phases.last(), Some(&ConnectionPhase::Connected));
+    }
+
+    #[test]
+    fn test_no_reconnect_after_restart() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ClientConfig>()
+            .restart
+            .reconnect = false;
+
+        restart_server(&mut stepper);
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+        assert_eq!(client_state(&stepper), NetworkingState::Disconnected);
+    }
+
+    /// A disconnection requested by the user after a restart notice is not overridden
+    #[test]
+    fn test_no_reconnect_after_user_disconnect() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .notify_restart(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                Duration::from_millis(50),
+                None,
+            )
+            .unwrap();
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world()
+            .get_resource::<PendingRestart>()
+            .is_some());
+
+        stepper
+            .client_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.disconnect_client());
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+        assert_eq!(client_state(&stepper), NetworkingState::Disconnected);
+        assert!(stepper
+            .client_app
+            .world()
+            .get_resource::<PendingRestart>()
+            .is_none());
+    }
+}
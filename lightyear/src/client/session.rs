@@ -0,0 +1,71 @@
+//! Prove to the server that the client owns the session that it resumes.
+//!
+//! When [`ServerConfig::session_resumption`](crate::prelude::server::ServerConfig::session_resumption) is set,
+//! the server sends a secret to each client that connects. The client keeps the secret across
+//! disconnections and sends it back right after it reconnects: only a client that knows the secret of the
+//! previous session can resume it (see [`crate::server::session`]).
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::channel::builder::ControlChannel;
+use crate::client::connection::ConnectionManager;
+use crate::client::networking::NetworkingState;
+use crate::prelude::client::{is_connected, MessageEvent};
+use crate::prelude::is_host_server;
+use crate::server::session::ResumeSession;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Message sent by the server when the client connects, with the secret that the client must send back
+/// to resume its session after a disconnection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct SessionSecret {
+    pub(crate) secret: u64,
+}
+
+/// Secret of the current session, kept across disconnections
+#[derive(Resource, Default)]
+pub(crate) struct ClientSession {
+    pub(crate) secret: Option<u64>,
+    /// True once the secret of the previous session was sent on the current connection
+    resume_sent: bool,
+}
+
+pub(crate) struct ClientSessionPlugin;
+
+impl Plugin for ClientSessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ClientSession>();
+        app.add_systems(
+            PreUpdate,
+            handle_session_secret
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(is_connected.and_then(not(is_host_server))),
+        );
+        app.add_systems(
+            OnEnter(NetworkingState::Disconnected),
+            |mut session: ResMut<ClientSession>| session.resume_sent = false,
+        );
+    }
+}
+
+/// Send the secret of the previous session once connected, then keep the secret of the new session
+fn handle_session_secret(
+    mut session: ResMut<ClientSession>,
+    mut messages: ResMut<Events<MessageEvent<SessionSecret>>>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    if !session.resume_sent {
+        session.resume_sent = true;
+        if let Some(secret) = session.secret {
+            let _ = connection_manager
+                .send_message::<ControlChannel, _>(&mut ResumeSession { secret })
+                .inspect_err(|e| {
+                    error!("Could not send the secret of the previous session: {e:?}")
+                });
+        }
+    }
+    for message in messages.drain() {
+        session.secret = Some(message.message.secret);
+    }
+}
@@ -0,0 +1,129 @@
+//! Handle server shutdowns on the client.
+//!
+//! A server that shuts down gracefully with
+//! [`ServerCommands::shutdown_server`](crate::prelude::server::ServerCommands::shutdown_server)
+//! warns its clients before closing the connections. The client emits a [`ShutdownNoticeEvent`], so that
+//! the game can tell the player that the server is going away instead of waiting for a timeout.
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::prelude::client::MessageEvent;
+use crate::prelude::is_host_server;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Message sent by the server when it starts shutting down
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ServerShutdown {
+    /// Maximum time before the server closes the connection
+    pub(crate) grace_period: Duration,
+}
+
+/// Bevy [`Event`] emitted on the client when the server announces that it is shutting down
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ShutdownNoticeEvent {
+    /// Maximum time before the server closes the connection
+    pub grace_period: Duration,
+}
+
+pub(crate) struct ClientShutdownPlugin;
+
+impl Plugin for ClientShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ShutdownNoticeEvent>();
+        app.add_systems(
+            PreUpdate,
+            handle_shutdown_notice
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Emit a [`ShutdownNoticeEvent`] for each shutdown notice received from the server
+fn handle_shutdown_notice(
+    mut messages: ResMut<Events<MessageEvent<ServerShutdown>>>,
+    mut events: EventWriter<ShutdownNoticeEvent>,
+) {
+    for message in messages.drain() {
+        let grace_period = message.message.grace_period;
+        info!("The server is shutting down in at most {grace_period:?}");
+        events.send(ShutdownNoticeEvent { grace_period });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::networking::NetworkingState;
+    use crate::prelude::server::{self, ServerCommands};
+    use crate::prelude::ClientId;
+    use crate::tests::protocol::{Channel1, StringMessage};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    #[test]
+    fn test_graceful_shutdown() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<Received>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut notices: EventReader<ShutdownNoticeEvent>,
+             mut messages: EventReader<MessageEvent<StringMessage>>,
+             mut received: ResMut<Received>| {
+                received.0.extend(
+                    notices
+                        .read()
+                        .map(|notice| format!("shutdown {:?}", notice.grace_period)),
+                );
+                received
+                    .0
+                    .extend(messages.read().map(|event| event.message().0.clone()));
+            },
+        );
+
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .send_message::<Channel1, _>(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                &mut StringMessage("last words".to_string()),
+            )
+            .unwrap();
+        stepper
+            .server_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| {
+                commands.shutdown_server(Duration::from_secs(5))
+            });
+        // the server stops as soon as the clients acknowledged the messages, before the end of the grace period
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<State<server::NetworkingState>>()
+                .get(),
+            &server::NetworkingState::Stopped
+        );
+        let received = &stepper.client_app.world().resource::<Received>().0;
+        assert!(received.contains(&"last words".to_string()));
+        assert!(received.contains(&format!("shutdown {:?}", Duration::from_secs(5))));
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Detect replicated entities that stopped receiving updates.
+//!
+//! When [`StalenessConfig::threshold`] is set, a replicated entity that hasn't received any update from the server
+//! for more than that many ticks (while still being replicated) gets the [`Stale`] marker component and an
+//! [`EntityStaleEvent`] is emitted. Games can use it to grey-out or freeze the stale remote players instead of
+//! extrapolating them forever. The marker is removed and an [`EntityRefreshedEvent`] is emitted as soon as
+//! the entity receives an update again.
+//!
+//! The server only sends the components that changed, so an entity that doesn't change on the server also
+//! becomes stale: this is mostly useful for entities that are updated continuously, such as players.
+use bevy::prelude::*;
+
+use crate::client::components::ReplicationTicks;
+use crate::prelude::client::is_connected;
+use crate::prelude::{is_host_server, Tick, TickManager};
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+use super::config::ClientConfig;
+
+/// Configuration of the detection of stale entities
+#[derive(Clone, Copy, Debug, Default, Reflect)]
+pub struct StalenessConfig {
+    /// Number of ticks without any update after which a replicated entity is considered stale.
+    /// Ticks wrap around, so values above [`MAX_STALENESS_THRESHOLD`] are clamped to it.
+    /// If None (the default), the staleness of entities is not tracked.
+    pub threshold: Option<u16>,
+}
+
+/// Maximum number of ticks that can separate two [`Tick`]s, which is the largest usable
+/// [`StalenessConfig::threshold`]
+pub const MAX_STALENESS_THRESHOLD: u16 = i16::MAX as u16;
+
+impl StalenessConfig {
+    pub fn with_threshold(mut self, threshold: u16) -> Self {
+        self.threshold = Some(threshold.min(MAX_STALENESS_THRESHOLD));
+        self
+    }
+}
+
+/// Returns true if more than `threshold` ticks elapsed between `last_update` and `tick`
+fn exceeds_threshold(tick: Tick, last_update: Tick, threshold: u16) -> bool {
+    // the wrapping difference is between i16::MIN and i16::MAX, so the clamped threshold always fits
+    let threshold = threshold.min(MAX_STALENESS_THRESHOLD) as i16;
+    tick - last_update > threshold
+}
+
+/// Marker component added on the replicated entities that haven't received any update for more than
+/// [`StalenessConfig::threshold`] ticks
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Stale;
+
+/// Bevy [`Event`] emitted on the client when a replicated entity becomes [`Stale`]
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct EntityStaleEvent {
+    pub entity: Entity,
+    /// Client tick at which the last update for the entity was received
+    pub last_update: Tick,
+}
+
+/// Bevy [`Event`] emitted on the client when a [`Stale`] entity receives an update again
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct EntityRefreshedEvent {
+    pub entity: Entity,
+}
+
+pub(crate) struct ClientStalenessPlugin;
+
+impl Plugin for ClientStalenessPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Stale>();
+        app.add_event::<EntityStaleEvent>();
+        app.add_event::<EntityRefreshedEvent>();
+        app.add_systems(
+            PreUpdate,
+            update_staleness
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(is_connected.and_then(not(is_host_server))),
+        );
+    }
+}
+
+/// Mark the entities that didn't receive any update for too long as [`Stale`]
+fn update_staleness(
+    mut commands: Commands,
+    config: Res<ClientConfig>,
+    tick_manager: Res<TickManager>,
+    query: Query<(Entity, &ReplicationTicks, Has<Stale>)>,
+    mut stale_events: EventWriter<EntityStaleEvent>,
+    mut refreshed_events: EventWriter<EntityRefreshedEvent>,
+) {
+    let Some(threshold) = config.staleness.threshold else {
+        return;
+    };
+    let tick = tick_manager.tick();
+    for (entity, ticks, is_stale) in query.iter() {
+        let Some(last_update) = ticks.last_received() else {
+            continue;
+        };
+        let stale = exceeds_threshold(tick, last_update, threshold);
+        if stale && !is_stale {
+            trace!(?entity, ?last_update, "Entity became stale");
+            commands.entity(entity).insert(Stale);
+            stale_events.send(EntityStaleEvent {
+                entity,
+                last_update,
+            });
+        } else if !stale && is_stale {
+            trace!(?entity, "Stale entity received an update");
+            commands.entity(entity).remove::<Stale>();
+            refreshed_events.send(EntityRefreshedEvent { entity });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client::ConnectionManager;
+    use crate::prelude::{server, SharedConfig, TickConfig};
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    #[test]
+    fn test_exceeds_threshold() {
+        assert!(!exceeds_threshold(Tick(10), Tick(0), 10));
+        assert!(exceeds_threshold(Tick(11), Tick(0), 10));
+        // the difference between the ticks wraps around
+        assert!(!exceeds_threshold(Tick(5), Tick(u16::MAX - 4), 10));
+        assert!(exceeds_threshold(Tick(5), Tick(u16::MAX - 5), 10));
+        // a threshold that doesn't fit in an i16 is clamped instead of marking every entity as stale
+        assert!(!exceeds_threshold(Tick(1), Tick(0), u16::MAX));
+        assert!(!exceeds_threshold(Tick(i16::MAX as u16), Tick(0), u16::MAX));
+        assert_eq!(
+            StalenessConfig::default()
+                .with_threshold(u16::MAX)
+                .threshold,
+            Some(MAX_STALENESS_THRESHOLD)
+        );
+    }
+
+    #[test]
+    fn test_stale_entity() {
+        let frame_duration = bevy::utils::Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            ClientConfig {
+                staleness: StalenessConfig::default().with_threshold(10),
+                ..default()
+            },
+            frame_duration,
+        );
+        stepper.init();
+        stepper.client_app.init_resource::<Received>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut stale: EventReader<EntityStaleEvent>,
+             mut refreshed: EventReader<EntityRefreshedEvent>,
+             mut received: ResMut<Received>| {
+                received.0.extend(stale.read().map(|_| "stale".to_string()));
+                received
+                    .0
+                    .extend(refreshed.read().map(|_| "refreshed".to_string()));
+            },
+        );
+
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(0.0), server::Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .expect("entity was not replicated to client");
+
+        // the entity doesn't receive any update
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world()
+            .get::<Stale>(client_entity)
+            .is_some());
+
+        // the entity receives an update again
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentSyncModeFull(1.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world()
+            .get::<Stale>(client_entity)
+            .is_none());
+        assert_eq!(
+            stepper.client_app.world().resource::<Received>().0,
+            vec!["stale".to_string(), "refreshed".to_string()]
+        );
+    }
+}
@@ -48,6 +48,14 @@ pub struct SyncConfig {
 
     // Integration
     pub server_time_estimate_smoothing: f32,
+
+    // Clock drift
+    /// Duration over which the drift between the clock of the client and the clock of the server is measured.
+    /// Longer windows are less sensitive to jitter
+    pub drift_window: Duration,
+    /// Maximum clock drift that is compensated, as a fraction of the elapsed time (0.01 = 1%).
+    /// Set to 0.0 to disable the drift compensation
+    pub max_drift: f32,
 }
 
 impl Default for SyncConfig {
@@ -61,6 +69,8 @@ impl Default for SyncConfig {
             speedup_factor: 1.05,
             // server_time_estimate_smoothing: 0.0,
             server_time_estimate_smoothing: 0.2,
+            drift_window: Duration::from_secs(30),
+            max_drift: 0.01,
         }
     }
 }
@@ -70,8 +80,21 @@ impl SyncConfig {
         self.speedup_factor = speedup_factor;
         self
     }
+
+    pub fn drift_window(mut self, drift_window: Duration) -> Self {
+        self.drift_window = drift_window;
+        self
+    }
+
+    pub fn max_drift(mut self, max_drift: f32) -> Self {
+        self.max_drift = max_drift;
+        self
+    }
 }
 
+/// Smoothing applied to the successive measures of the clock drift
+const DRIFT_SMOOTHING: f64 = 0.5;
+
 #[derive(Default)]
 pub struct SentPacketStore {
     buffer: ReadyBuffer<WrappedTime, PacketId>,
@@ -111,6 +134,14 @@ pub struct SyncManager {
     /// The Tick associated with the 'server_tick_generation' (it might not be the same as latest_received_server_tick
     /// because we update the generation only from pong messages)
     pub(crate) server_pong_tick: Tick,
+
+    // clock drift
+    /// Real time elapsed on the client since the connection
+    local_elapsed: Duration,
+    /// Server time and local elapsed time at the start of the current drift measure
+    drift_anchor: Option<(WrappedTime, Duration)>,
+    /// Estimated ratio between the speed of the server clock and the speed of the client clock
+    drift_ratio: f64,
 }
 
 // TODO: split into PredictionTime Manager, InterpolationTime Manager
@@ -130,6 +161,9 @@ impl SyncManager {
             new_latest_received_server_tick: false,
             server_pong_generation: 0,
             server_pong_tick: Tick(0),
+            local_elapsed: Duration::default(),
+            drift_anchor: None,
+            drift_ratio: 1.0,
         }
     }
 
@@ -147,8 +181,16 @@ impl SyncManager {
         // TODO: we are in PostUpdate, so this seems incorrect? this uses the previous-frame's delta,
         //  but instead we want to add the duration since the start of frame?
         self.duration_since_latest_received_server_tick += time_manager.delta();
-        self.server_time_estimate += time_manager.delta();
-        self.interpolation_time += time_manager.delta().mul_f32(self.interpolation_speed_ratio);
+        // the virtual time is sped up or slowed down to stay in sync, the drift is measured in real time
+        self.local_elapsed += time_manager
+            .delta()
+            .div_f32(time_manager.get_relative_speed());
+        // the server time and the interpolation time advance at the speed of the server clock
+        self.server_time_estimate += time_manager.delta().mul_f64(self.drift_ratio);
+        self.interpolation_time += time_manager
+            .delta()
+            .mul_f64(self.drift_ratio)
+            .mul_f32(self.interpolation_speed_ratio);
 
         // check if we are ready to finalize the handshake
         if !self.synced && ping_manager.sync_stats.len() >= self.config.handshake_pings as usize {
@@ -224,6 +266,44 @@ impl SyncManager {
         self.server_time_estimate
     }
 
+    /// Estimated drift of the server clock relative to the client clock, as a fraction of the elapsed time.
+    ///
+    /// A negative value means that the clock of the client runs faster than the clock of the server.
+    pub(crate) fn clock_drift(&self) -> f64 {
+        self.drift_ratio - 1.0
+    }
+
+    /// Compare the progress of the server time with the progress of the local time since the
+    /// last measure, and update the estimated drift if the measure is long enough
+    fn measure_drift(&mut self, server_time: WrappedTime) {
+        if self.config.max_drift <= 0.0 {
+            return;
+        }
+        let Some((anchor_server_time, anchor_local_elapsed)) = self.drift_anchor else {
+            self.drift_anchor = Some((server_time, self.local_elapsed));
+            return;
+        };
+        let local_elapsed = self.local_elapsed - anchor_local_elapsed;
+        if local_elapsed < self.config.drift_window {
+            return;
+        }
+        self.drift_anchor = Some((server_time, self.local_elapsed));
+        let Some(server_elapsed) = (server_time - anchor_server_time).num_nanoseconds() else {
+            return;
+        };
+        let max_drift = self.config.max_drift as f64;
+        let ratio = (server_elapsed as f64 / local_elapsed.as_nanos() as f64)
+            .clamp(1.0 - max_drift, 1.0 + max_drift);
+        // adjust gradually, so that a single noisy measure doesn't make the ticks jump
+        self.drift_ratio = self.drift_ratio * (1.0 - DRIFT_SMOOTHING) + ratio * DRIFT_SMOOTHING;
+        debug!(
+            ?ratio,
+            drift_ratio = ?self.drift_ratio,
+            ?local_elapsed,
+            "measured clock drift"
+        );
+    }
+
     fn server_latest_tick_generation(&self) -> u16 {
         // check if the latest_server_tick has crossed a generation compared to the latest pong tick
         if self.latest_received_server_tick.unwrap().0 < self.server_pong_tick.0 {
@@ -245,6 +325,9 @@ impl SyncManager {
             self.server_latest_tick_generation(),
             tick_duration,
         ) + self.duration_since_latest_received_server_tick;
+        if self.is_synced() {
+            self.measure_drift(new_server_time_estimate);
+        }
 
         // instead of just using the latest_received_server_tick, we apply some smoothing
         // (in case the latest server tick is wildly off-base)
@@ -466,39 +549,42 @@ impl SyncManager {
             return self.finalize(time_manager, tick_manager, ping_manager);
         }
 
-        time_manager.sync_relative_speed = if error > error_margin_time {
-            debug!(
-                ?rtt,
-                ?jitter,
-                ?current_prediction_time,
-                ?client_ideal_time,
-                latest_received_server_tick = ?self.latest_received_server_tick,
-                client_tick = ?tick_manager.tick(),
-                error_ms = ?error.num_milliseconds(),
-                error_margin_time_ms = ?error_margin_time.num_milliseconds(),
-                "Too far ahead of server! Slow down!",
-            );
-            // we are too far ahead of the server, slow down
-            1.0 / self.config.speedup_factor
-        } else if error < -error_margin_time {
-            debug!(
-                ?rtt,
-                ?jitter,
-                ?current_prediction_time,
-                ?client_ideal_time,
-                latest_received_server_tick = ?self.latest_received_server_tick,
-                client_tick = ?tick_manager.tick(),
-                error_ms = ?error.num_milliseconds(),
-                error_margin_time_ms = ?error_margin_time.num_milliseconds(),
-                "Too far behind of server! Speed up!",
-            );
-            // we are too far behind the server, speed up
-            1.0 * self.config.speedup_factor
-        } else {
-            // we are within margins
-            trace!("good speed");
-            1.0
-        };
+        // compensate the clock drift, on top of the speed adjustments that keep the ticks in sync
+        let drift_ratio = self.drift_ratio as f32;
+        time_manager.sync_relative_speed = drift_ratio
+            * if error > error_margin_time {
+                debug!(
+                    ?rtt,
+                    ?jitter,
+                    ?current_prediction_time,
+                    ?client_ideal_time,
+                    latest_received_server_tick = ?self.latest_received_server_tick,
+                    client_tick = ?tick_manager.tick(),
+                    error_ms = ?error.num_milliseconds(),
+                    error_margin_time_ms = ?error_margin_time.num_milliseconds(),
+                    "Too far ahead of server! Slow down!",
+                );
+                // we are too far ahead of the server, slow down
+                1.0 / self.config.speedup_factor
+            } else if error < -error_margin_time {
+                debug!(
+                    ?rtt,
+                    ?jitter,
+                    ?current_prediction_time,
+                    ?client_ideal_time,
+                    latest_received_server_tick = ?self.latest_received_server_tick,
+                    client_tick = ?tick_manager.tick(),
+                    error_ms = ?error.num_milliseconds(),
+                    error_margin_time_ms = ?error_margin_time.num_milliseconds(),
+                    "Too far behind of server! Speed up!",
+                );
+                // we are too far behind the server, speed up
+                1.0 * self.config.speedup_factor
+            } else {
+                // we are within margins
+                trace!("good speed");
+                1.0
+            };
         None
     }
 
@@ -559,6 +645,7 @@ impl SyncManager {
 #[cfg(test)]
 mod tests {
     use bevy::prelude::*;
+    use bevy::time::TimeUpdateStrategy;
     use bevy::utils::Duration;
 
     use crate::client::input::native::InputManager;
@@ -587,6 +674,46 @@ mod tests {
         }
     }
 
+    /// Check that the drift of the client clock is measured and compensated
+    #[test]
+    fn test_clock_drift_compensation() {
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            client::ClientConfig {
+                sync: SyncConfig::default().drift_window(Duration::from_secs(1)),
+                ..default()
+            },
+            frame_duration,
+        );
+        stepper.init();
+
+        // the clock of the client runs 0.5% faster than the clock of the server
+        for _ in 0..1000 {
+            stepper.advance_time(frame_duration);
+            stepper
+                .client_app
+                .insert_resource(TimeUpdateStrategy::ManualDuration(
+                    frame_duration.mul_f64(1.005),
+                ));
+            stepper.client_app.update();
+            stepper.server_app.update();
+        }
+        let connection = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>();
+        let drift = connection.clock_drift();
+        assert!((drift + 0.005).abs() < 0.001, "drift: {drift}");
+        assert!(connection.is_synced());
+        // the client still runs roughly rtt/2 ahead of the server
+        let tick_difference = stepper.client_tick() - stepper.server_tick();
+        assert!((0..10).contains(&tick_difference), "{tick_difference}");
+    }
+
     /// Check that after a big tick discrepancy between server/client, the client tick gets updated
     /// to match the server tick
     #[test]
@@ -0,0 +1,167 @@
+//! Spawn the transient entities sent by the server, see [`crate::shared::replication::transient`]
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use tracing::error;
+
+use crate::client::connection::ConnectionManager;
+use crate::prelude::client::MessageEvent;
+use crate::prelude::{is_host_server, ComponentRegistry, TickManager};
+use crate::serialize::reader::Reader;
+use crate::shared::replication::transient::TransientSpawn;
+use crate::shared::sets::{ClientMarker, InternalMainSet};
+
+/// Component added on the transient entities spawned by the server.
+///
+/// The entity is despawned once its lifetime is over.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Transient {
+    timer: Timer,
+}
+
+impl Transient {
+    /// How long until the entity is despawned
+    pub fn remaining(&self) -> Duration {
+        self.timer.remaining()
+    }
+}
+
+pub(crate) struct TransientPlugin;
+
+impl Plugin for TransientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (despawn_expired_transients, spawn_transients)
+                .chain()
+                .after(InternalMainSet::<ClientMarker>::EmitEvents)
+                .run_if(not(is_host_server)),
+        );
+    }
+}
+
+/// Spawn a local entity for each transient entity received from the server
+fn spawn_transients(world: &mut World) {
+    let spawns: Vec<_> = world
+        .resource_mut::<Events<MessageEvent<TransientSpawn>>>()
+        .drain()
+        .map(|event| event.message)
+        .collect();
+    if spawns.is_empty() {
+        return;
+    }
+    let tick = world.resource::<TickManager>().tick();
+    world.resource_scope(|world, registry: Mut<ComponentRegistry>| {
+        world.resource_scope(|world, mut manager: Mut<ConnectionManager>| {
+            let manager = &mut *manager;
+            for spawn in spawns {
+                let mut entity_mut = world.spawn(Transient {
+                    timer: Timer::new(spawn.lifetime, TimerMode::Once),
+                });
+                manager.events.push_spawn(entity_mut.id());
+                for component in spawn.components {
+                    if let Err(e) = registry.raw_write(
+                        &mut Reader::from(component)
+                            .with_versions(manager.replication_receiver.remote_versions.clone()),
+                        &mut entity_mut,
+                        tick,
+                        &mut manager
+                            .replication_receiver
+                            .remote_entity_map
+                            .remote_to_local,
+                        &mut manager.events,
+                    ) {
+                        error!("Could not write the transient component: {e:?}");
+                    }
+                }
+            }
+        });
+    });
+}
+
+fn despawn_expired_transients(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Transient)>,
+) {
+    for (entity, mut transient) in query.iter_mut() {
+        if transient.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::ReplicateTransient;
+    use crate::prelude::NetworkTarget;
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_transient_entity() {
+        let mut stepper = BevyStepper::default();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((
+                ReplicateTransient {
+                    target: NetworkTarget::All,
+                    lifetime: Duration::from_millis(100),
+                },
+                ComponentSyncModeFull(1.0),
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let client_entity = stepper
+            .client_app
+            .world_mut()
+            .query_filtered::<Entity, With<Transient>>()
+            .single(stepper.client_app.world());
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_entity),
+            Some(&ComponentSyncModeFull(1.0))
+        );
+        // the entity is not tracked by the replication systems
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .is_none());
+
+        // updates are not replicated
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentSyncModeFull(2.0));
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_entity),
+            Some(&ComponentSyncModeFull(1.0))
+        );
+
+        // the entity is despawned at the end of its lifetime
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world()
+            .get_entity(client_entity)
+            .is_none());
+    }
+}
@@ -30,6 +30,31 @@ pub enum ConnectionState {
     Connected,
 }
 
+/// Detailed phase of the connection of the client to the server.
+///
+/// Unlike [`NetworkingState`](crate::client::networking::NetworkingState), it tells where the connection
+/// is stuck during the handshake. It is available as a resource on the client, and a
+/// [`ConnectionPhaseChanged`](crate::client::events::ConnectionPhaseChanged) event is emitted
+/// every time it changes.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum ConnectionPhase {
+    /// The client is not connected and is not trying to connect
+    #[default]
+    Disconnected,
+    /// The client is sending connection requests and waits for the server to answer
+    Connecting,
+    /// The server answered the connection request with a challenge, and the client is sending
+    /// the challenge response
+    SendingChallenge,
+    /// The client is connected to the server
+    Connected,
+    /// The connection was lost after the server announced a restart, and the client is connecting again
+    /// (see [`restart`](crate::client::restart))
+    Resuming,
+    /// The server kicked the client and is about to close the connection
+    Disconnecting,
+}
+
 // TODO: add diagnostics methods?
 #[enum_dispatch]
 pub trait NetClient: Send + Sync {
@@ -48,6 +73,15 @@ pub trait NetClient: Send + Sync {
     /// Returns the [`ConnectionState`] of the client
     fn state(&self) -> ConnectionState;
 
+    /// Returns the [`ConnectionPhase`] of the client, as seen by the connection
+    fn phase(&self) -> ConnectionPhase {
+        match self.state() {
+            ConnectionState::Disconnected { .. } => ConnectionPhase::Disconnected,
+            ConnectionState::Connecting => ConnectionPhase::Connecting,
+            ConnectionState::Connected => ConnectionPhase::Connected,
+        }
+    }
+
     /// Update the connection state + internal bookkeeping (keep-alives, etc.)
     fn try_update(&mut self, delta_ms: f64) -> Result<(), ConnectionError>;
 
@@ -88,9 +122,17 @@ pub struct ClientConnection {
 
 /// Enumerates the possible reasons for a client to disconnect from the server
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum DisconnectReason {
     Transport(crate::transport::error::Error),
     Netcode(super::netcode::ClientState),
+    /// The server denied our connection request
+    Denied(super::server::DeniedReason),
+    /// The server kicked us, with the given reason
+    Kicked(String),
+    /// A message was not acknowledged by the server after the
+    /// [`max_retries`](crate::prelude::ReliableSettings::max_retries) of its channel
+    RetriesExhausted(crate::protocol::channel::ChannelKind),
     #[cfg(all(feature = "steam", not(target_family = "wasm")))]
     Steam(steamworks::networking_types::NetConnectionEnd),
 }
@@ -199,6 +241,10 @@ impl NetClient for ClientConnection {
         self.client.state()
     }
 
+    fn phase(&self) -> ConnectionPhase {
+        self.client.phase()
+    }
+
     fn try_update(&mut self, delta_ms: f64) -> Result<(), ConnectionError> {
         self.client.try_update(delta_ms)
     }
@@ -5,9 +5,10 @@ use tracing::{debug, error, info, trace};
 
 use crate::client::io::Io;
 use crate::connection::client::{
-    ConnectionError, ConnectionState, DisconnectReason, IoConfig, NetClient,
+    ConnectionError, ConnectionPhase, ConnectionState, DisconnectReason, IoConfig, NetClient,
 };
 use crate::connection::id;
+use crate::connection::server::DeniedReason;
 use crate::packet::packet_builder::RecvPayload;
 use crate::transport::io::IoState;
 use crate::transport::{PacketReceiver, PacketSender, LOCAL_SOCKET};
@@ -20,7 +21,7 @@ use super::{
         DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket, RequestPacket, ResponsePacket,
     },
     replay::ReplayProtection,
-    token::{ChallengeToken, ConnectToken},
+    token::ConnectToken,
     utils, ClientId, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC,
 };
 
@@ -56,6 +57,7 @@ type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send +
 pub struct ClientConfig<Ctx> {
     num_disconnect_packets: usize,
     packet_send_rate: f64,
+    protocol_hash: Option<u64>,
     context: Ctx,
     on_state_change: Option<Callback<Ctx>>,
 }
@@ -65,6 +67,7 @@ impl Default for ClientConfig<()> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            protocol_hash: None,
             context: (),
             on_state_change: None,
         }
@@ -81,6 +84,7 @@ impl<Ctx> ClientConfig<Ctx> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            protocol_hash: None,
             context: ctx,
             on_state_change: None,
         }
@@ -97,6 +101,15 @@ impl<Ctx> ClientConfig<Ctx> {
         self.packet_send_rate = rate_seconds;
         self
     }
+    /// Set the hash of the protocol that is sent to the server in the connection request, so that
+    /// the server can deny the clients that use an incompatible protocol. <br>
+    /// The connection request then uses the [`NETCODE_VERSION_PROTOCOL_HASH`](super::NETCODE_VERSION_PROTOCOL_HASH)
+    /// version, which the servers that implement the standard netcode protocol reject. <br>
+    /// The default is `None` (the standard connection request is sent).
+    pub fn protocol_hash(mut self, protocol_hash: Option<u64>) -> Self {
+        self.protocol_hash = protocol_hash;
+        self
+    }
     /// Set a callback that will be called when the client changes states.
     pub fn on_state_change<F>(mut self, cb: F) -> Self
     where
@@ -128,6 +141,7 @@ impl<Ctx> ClientConfig<Ctx> {
 ///  - If no payload or keep-alive packets are received from the server within the timeout period specified in the connect token,
 ///    the client transitions to `ConnectionTimedOut`.
 ///  - While `Connected`, if the client receives a disconnect packet from the server, it transitions to `Disconnected`.
+///    If it receives a denied packet (the server rejected the client), it transitions to `ConnectionDenied`.
 ///    If the client wishes to disconnect from the server,
 ///    it sends a number of redundant connection disconnect packets (default is 10, can be overridden in [`ClientConfig`])
 ///    before transitioning to `Disconnected`.
@@ -187,11 +201,13 @@ pub struct NetcodeClient<Ctx = ()> {
     server_addr_idx: usize,
     sequence: u64,
     challenge_token_sequence: u64,
-    challenge_token_data: [u8; ChallengeToken::SIZE],
+    challenge_token_data: Vec<u8>,
     token: ConnectToken,
     replay_protection: ReplayProtection,
     should_disconnect: bool,
     should_disconnect_state: ClientState,
+    /// Reason sent by the server when it denied our connection request, or rejected our connection
+    denied_reason: Option<DeniedReason>,
     packet_queue: VecDeque<RecvPayload>,
     buffer_pool: Pool<Vec<u8>>,
     cfg: ClientConfig<Ctx>,
@@ -222,11 +238,12 @@ impl<Ctx> NetcodeClient<Ctx> {
             server_addr_idx: 0,
             sequence: 0,
             challenge_token_sequence: 0,
-            challenge_token_data: [0u8; ChallengeToken::SIZE],
+            challenge_token_data: Vec::new(),
             token,
             replay_protection: ReplayProtection::new(),
             should_disconnect: false,
             should_disconnect_state: ClientState::Disconnected,
+            denied_reason: None,
             packet_queue: VecDeque::new(),
             buffer_pool: Pool::new(10, || vec![0u8; MAX_PKT_BUF_SIZE]),
             cfg,
@@ -328,11 +345,15 @@ impl<Ctx> NetcodeClient<Ctx> {
                     self.token.expire_timestamp,
                     self.token.nonce,
                     self.token.private_data,
+                    self.cfg.protocol_hash,
                 )
             }
             ClientState::SendingChallengeResponse => {
                 debug!("client sending connection response packet to server");
-                ResponsePacket::create(self.challenge_token_sequence, self.challenge_token_data)
+                ResponsePacket::create(
+                    self.challenge_token_sequence,
+                    self.challenge_token_data.clone(),
+                )
             }
             ClientState::Connected => {
                 trace!("client sending connection keep-alive packet to server");
@@ -376,7 +397,9 @@ impl<Ctx> NetcodeClient<Ctx> {
         match (packet, self.state) {
             (
                 Packet::Denied(pkt),
-                ClientState::SendingConnectionRequest | ClientState::SendingChallengeResponse,
+                ClientState::SendingConnectionRequest
+                | ClientState::SendingChallengeResponse
+                | ClientState::Connected,
             ) => {
                 error!(
                     "client connection denied by server. Reason: {:?}",
@@ -384,6 +407,7 @@ impl<Ctx> NetcodeClient<Ctx> {
                 );
                 self.should_disconnect = true;
                 self.should_disconnect_state = ClientState::ConnectionDenied;
+                self.denied_reason = Some(pkt.reason);
             }
             (Packet::Challenge(pkt), ClientState::SendingConnectionRequest) => {
                 debug!("client received connection challenge packet from server");
@@ -523,6 +547,7 @@ impl<Ctx> NetcodeClient<Ctx> {
     /// This function does not perform any IO, it only readies the client to send/receive packets on the next call to [`update`](NetcodeClient::update). <br>
     pub fn connect(&mut self) {
         self.reset_connection();
+        self.denied_reason = None;
         self.set_state(ClientState::SendingConnectionRequest);
         info!(
             "client connecting to server {} [{}/{}]",
@@ -627,6 +652,11 @@ impl<Ctx> NetcodeClient<Ctx> {
         Ok(())
     }
 
+    /// Returns the reason sent by the server if it denied the last connection request
+    pub fn denied_reason(&self) -> Option<&DeniedReason> {
+        self.denied_reason.as_ref()
+    }
+
     /// Gets the current state of the client.
     pub fn state(&self) -> ClientState {
         self.state
@@ -690,12 +720,30 @@ pub(crate) mod connection {
                     ConnectionState::Connecting
                 }
                 ClientState::Connected => ConnectionState::Connected,
+                ClientState::ConnectionDenied if self.client.denied_reason.is_some() => {
+                    ConnectionState::Disconnected {
+                        reason: self
+                            .client
+                            .denied_reason
+                            .clone()
+                            .map(DisconnectReason::Denied),
+                    }
+                }
                 _ => ConnectionState::Disconnected {
                     reason: Some(DisconnectReason::Netcode(self.client.state)),
                 },
             }
         }
 
+        fn phase(&self) -> ConnectionPhase {
+            match self.client.state() {
+                ClientState::SendingConnectionRequest => ConnectionPhase::Connecting,
+                ClientState::SendingChallengeResponse => ConnectionPhase::SendingChallenge,
+                ClientState::Connected => ConnectionPhase::Connected,
+                _ => ConnectionPhase::Disconnected,
+            }
+        }
+
         fn try_update(&mut self, delta_ms: f64) -> Result<(), ConnectionError> {
             let io = self.io.as_mut().ok_or(ConnectionError::IoNotInitialized)?;
             self.client
@@ -26,3 +26,14 @@ pub enum Error {
     #[error(transparent)]
     Transport(#[from] crate::transport::error::Error),
 }
+
+impl Error {
+    /// Kind of the io error that caused this error, or [`std::io::ErrorKind::Other`]
+    /// if the error was not caused by an io error
+    pub fn io_error_kind(&self) -> std::io::ErrorKind {
+        match self {
+            Error::Io(e) | Error::Transport(crate::transport::error::Error::Io(e)) => e.kind(),
+            _ => std::io::ErrorKind::Other,
+        }
+    }
+}
@@ -124,7 +124,11 @@ loop {
 pub use client::{connection::Client, ClientConfig, ClientState, NetcodeClient};
 pub use crypto::{generate_key, try_generate_key, Key};
 pub use error::{Error, Result};
-pub use server::{connection::Server, Callback, ClientId, NetcodeServer, ServerConfig};
+pub use server::{
+    connection::Server, Callback, ClientId, DuplicateLoginCallback, DuplicateLoginPolicy,
+    HandshakeRateLimit, NetcodeServer, SendErrorCallback, ServerConfig, SuspicionAction,
+    SuspicionCallback, SuspicionThreshold, ThrottleCallback,
+};
 pub use token::{ConnectToken, ConnectTokenBuilder, InvalidTokenError};
 
 mod bytes;
@@ -152,3 +156,8 @@ pub const CONNECT_TOKEN_BYTES: usize = 2048;
 pub const MAX_PACKET_SIZE: usize = 1200;
 /// The version of the netcode protocol implemented by this crate.
 pub const NETCODE_VERSION: &[u8; 13] = b"NETCODE 1.02\0";
+/// The version of the connection requests that are followed by the hash of the protocol of the client.
+///
+/// The clients only use it when they are configured to send their protocol hash, so that they stay
+/// compatible with the servers that implement the standard netcode protocol.
+pub const NETCODE_VERSION_PROTOCOL_HASH: &[u8; 13] = b"NETCODE 1.02H";
@@ -15,8 +15,8 @@ use super::{
     crypto::{self, Key},
     error::Error as NetcodeError,
     replay::ReplayProtection,
-    token::{ChallengeToken, ConnectTokenPrivate},
-    MAC_BYTES, MAX_PKT_BUF_SIZE, NETCODE_VERSION,
+    token::{ChallengeToken, ConnectTokenPrivate, StatelessChallengeToken},
+    MAC_BYTES, MAX_PKT_BUF_SIZE, NETCODE_VERSION, NETCODE_VERSION_PROTOCOL_HASH,
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -75,12 +75,32 @@ where
     }
 }
 
+/// Size of a connection request packet
+pub(crate) const REQUEST_PACKET_SIZE: usize = 1
+    + NETCODE_VERSION.len()
+    + 2 * size_of::<u64>()
+    + size_of::<XNonce>()
+    + ConnectTokenPrivate::SIZE;
+
+/// Maximum size of a challenge packet
+pub(crate) const CHALLENGE_PACKET_SIZE: usize =
+    1 + size_of::<u64>() + size_of::<u64>() + StatelessChallengeToken::SIZE + MAC_BYTES;
+
+// The connection request must be bigger than the challenge that the server sends back,
+// so that the server cannot be used to amplify a reflection attack
+const _: () = assert!(REQUEST_PACKET_SIZE >= CHALLENGE_PACKET_SIZE);
+
 pub struct RequestPacket {
     pub version_info: [u8; NETCODE_VERSION.len()],
     pub protocol_id: u64,
     pub expire_timestamp: u64,
     pub token_nonce: XNonce,
     pub token_data: Box<[u8; ConnectTokenPrivate::SIZE]>,
+    /// Hash of the channels, components and messages registered by the client
+    /// (see [`protocol_hash`](crate::protocol::hash::protocol_hash)).
+    ///
+    /// It is only written if the packet uses the [`NETCODE_VERSION_PROTOCOL_HASH`] version
+    pub protocol_hash: Option<u64>,
 }
 
 impl RequestPacket {
@@ -89,17 +109,25 @@ impl RequestPacket {
         expire_timestamp: u64,
         token_nonce: XNonce,
         token_data: [u8; ConnectTokenPrivate::SIZE],
+        protocol_hash: Option<u64>,
     ) -> Packet<'static> {
         Packet::Request(RequestPacket {
-            version_info: *NETCODE_VERSION,
+            version_info: if protocol_hash.is_some() {
+                *NETCODE_VERSION_PROTOCOL_HASH
+            } else {
+                *NETCODE_VERSION
+            },
             protocol_id,
             expire_timestamp,
             token_nonce,
             token_data: Box::new(token_data),
+            protocol_hash,
         })
     }
     pub fn validate(&self, protocol_id: u64, current_timestamp: u64) -> Result<(), Error> {
-        if &self.version_info != NETCODE_VERSION {
+        if &self.version_info != NETCODE_VERSION
+            && &self.version_info != NETCODE_VERSION_PROTOCOL_HASH
+        {
             return Err(Error::BadVersion);
         }
         if self.protocol_id != protocol_id {
@@ -136,6 +164,9 @@ impl Bytes for RequestPacket {
         writer.write_u64::<LittleEndian>(self.expire_timestamp)?;
         writer.write_all(&self.token_nonce)?;
         writer.write_all(&self.token_data[..])?;
+        if &self.version_info == NETCODE_VERSION_PROTOCOL_HASH {
+            writer.write_u64::<LittleEndian>(self.protocol_hash.unwrap_or_default())?;
+        }
         Ok(())
     }
 
@@ -149,12 +180,18 @@ impl Bytes for RequestPacket {
         let token_nonce = XNonce::from_slice(&nonce).to_owned();
         let mut token_data = [0; ConnectTokenPrivate::SIZE];
         reader.read_exact(&mut token_data)?;
+        let protocol_hash = if &version_info == NETCODE_VERSION_PROTOCOL_HASH {
+            Some(reader.read_u64::<LittleEndian>()?)
+        } else {
+            None
+        };
         Ok(Self {
             version_info,
             protocol_id,
             expire_timestamp,
             token_nonce,
             token_data: Box::new(token_data),
+            protocol_hash,
         })
     }
 }
@@ -192,6 +229,25 @@ impl Bytes for DeniedReason {
             DeniedReason::InvalidToken => {
                 writer.write_u8(5)?;
             }
+            DeniedReason::BadVersion => {
+                writer.write_u8(7)?;
+            }
+            DeniedReason::AuthFailed => {
+                writer.write_u8(8)?;
+            }
+            DeniedReason::AuthTimedOut => {
+                writer.write_u8(9)?;
+            }
+            DeniedReason::ProtocolMismatch => {
+                writer.write_u8(10)?;
+            }
+            DeniedReason::LoggedInElsewhere => {
+                writer.write_u8(11)?;
+            }
+            DeniedReason::BannedUntil(until) => {
+                writer.write_u8(12)?;
+                writer.write_u64::<LittleEndian>(*until)?;
+            }
             DeniedReason::Custom(reason) => {
                 writer.write_u8(6)?;
                 // the reason cannot exceed u8::MAX in size
@@ -235,6 +291,20 @@ impl Bytes for DeniedReason {
             let reason_str = String::from_utf8(string_buf)
                 .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid denied reason"))?;
             Ok(DeniedReason::Custom(reason_str))
+        } else if variant == 7 {
+            Ok(DeniedReason::BadVersion)
+        } else if variant == 8 {
+            Ok(DeniedReason::AuthFailed)
+        } else if variant == 9 {
+            Ok(DeniedReason::AuthTimedOut)
+        } else if variant == 10 {
+            Ok(DeniedReason::ProtocolMismatch)
+        } else if variant == 11 {
+            Ok(DeniedReason::LoggedInElsewhere)
+        } else if variant == 12 {
+            Ok(DeniedReason::BannedUntil(
+                reader.read_u64::<LittleEndian>()?,
+            ))
         } else {
             Err(io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -259,11 +329,12 @@ impl Bytes for DeniedPacket {
 
 pub struct ChallengePacket {
     pub sequence: u64,
-    pub token: [u8; ChallengeToken::SIZE],
+    /// Encrypted [`ChallengeToken`] or [`StatelessChallengeToken`]
+    pub token: Vec<u8>,
 }
 
 impl ChallengePacket {
-    pub fn create(sequence: u64, token_bytes: [u8; ChallengeToken::SIZE]) -> Packet<'static> {
+    pub fn create(sequence: u64, token_bytes: Vec<u8>) -> Packet<'static> {
         Packet::Challenge(ChallengePacket {
             sequence,
             token: token_bytes,
@@ -281,19 +352,33 @@ impl Bytes for ChallengePacket {
 
     fn read_from(reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
         let sequence = reader.read_u64::<LittleEndian>()?;
-        let mut token = [0; ChallengeToken::SIZE];
-        reader.read_exact(&mut token)?;
+        let token = read_challenge_token(reader)?;
         Ok(Self { sequence, token })
     }
 }
 
+/// Read the rest of the packet as an encrypted challenge token, which is either in the format of
+/// the standard netcode protocol or in the format of the stateless handshake
+fn read_challenge_token(reader: &mut impl Read) -> Result<Vec<u8>, io::Error> {
+    let mut token = Vec::with_capacity(StatelessChallengeToken::SIZE);
+    reader.read_to_end(&mut token)?;
+    if token.len() != ChallengeToken::SIZE && token.len() != StatelessChallengeToken::SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid challenge token size",
+        ));
+    }
+    Ok(token)
+}
+
 pub struct ResponsePacket {
     pub sequence: u64,
-    pub token: [u8; ChallengeToken::SIZE],
+    /// Encrypted [`ChallengeToken`] or [`StatelessChallengeToken`]
+    pub token: Vec<u8>,
 }
 
 impl ResponsePacket {
-    pub fn create(sequence: u64, token_bytes: [u8; ChallengeToken::SIZE]) -> Packet<'static> {
+    pub fn create(sequence: u64, token_bytes: Vec<u8>) -> Packet<'static> {
         Packet::Response(ResponsePacket {
             sequence,
             token: token_bytes,
@@ -311,8 +396,7 @@ impl Bytes for ResponsePacket {
 
     fn read_from(reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
         let sequence = reader.read_u64::<LittleEndian>()?;
-        let mut token = [0; ChallengeToken::SIZE];
-        reader.read_exact(&mut token)?;
+        let token = read_challenge_token(reader)?;
         Ok(Self { sequence, token })
     }
 }
@@ -497,6 +581,9 @@ impl<'p> Packet<'p> {
         }
         if prefix_byte == Packet::REQUEST {
             // connection request packet: first byte should be 0x00
+            if buf_len < REQUEST_PACKET_SIZE {
+                return Err(Error::TooSmall.into());
+            }
             let mut packet = RequestPacket::read_from(&mut cursor)?;
             packet.validate(protocol_id, timestamp)?;
             packet.decrypt_token_data(key)?;
@@ -535,8 +622,14 @@ impl<'p> Packet<'p> {
         let packet = match pkt_kind {
             Packet::REQUEST => Packet::Request(RequestPacket::read_from(&mut cursor)?),
             Packet::DENIED => Packet::Denied(DeniedPacket::read_from(&mut cursor)?),
-            Packet::CHALLENGE => Packet::Challenge(ChallengePacket::read_from(&mut cursor)?),
-            Packet::RESPONSE => Packet::Response(ResponsePacket::read_from(&mut cursor)?),
+            // the size of the challenge token depends on the handshake used by the server,
+            // so these packets are read from the decrypted data only
+            Packet::CHALLENGE => Packet::Challenge(ChallengePacket::read_from(
+                &mut &buf[decryption_start..decryption_end - MAC_BYTES],
+            )?),
+            Packet::RESPONSE => Packet::Response(ResponsePacket::read_from(
+                &mut &buf[decryption_start..decryption_end - MAC_BYTES],
+            )?),
             Packet::KEEP_ALIVE => Packet::KeepAlive(KeepAlivePacket::read_from(&mut cursor)?),
             Packet::DISCONNECT => Packet::Disconnect(DisconnectPacket::read_from(&mut cursor)?),
             Packet::PAYLOAD => {
@@ -613,11 +706,12 @@ mod tests {
             .unwrap();
 
         let packet = Packet::Request(RequestPacket {
-            version_info: *NETCODE_VERSION,
+            version_info: *NETCODE_VERSION_PROTOCOL_HASH,
             protocol_id,
             expire_timestamp,
             token_nonce: nonce,
             token_data: Box::new(token_data),
+            protocol_hash: Some(0xabcd),
         });
 
         let mut buf = [0u8; MAX_PACKET_SIZE];
@@ -639,10 +733,11 @@ mod tests {
             panic!("wrong packet type");
         };
 
-        assert_eq!(req_pkt.version_info, *NETCODE_VERSION);
+        assert_eq!(req_pkt.version_info, *NETCODE_VERSION_PROTOCOL_HASH);
         assert_eq!(req_pkt.protocol_id, protocol_id);
         assert_eq!(req_pkt.expire_timestamp, expire_timestamp);
         assert_eq!(req_pkt.token_nonce, nonce);
+        assert_eq!(req_pkt.protocol_hash, Some(0xabcd));
 
         let mut reader = std::io::Cursor::new(&req_pkt.token_data[..]);
         let connect_token_private = ConnectTokenPrivate::read_from(&mut reader).unwrap();
@@ -658,6 +753,78 @@ mod tests {
         assert_eq!(connect_token_private.user_data, user_data);
     }
 
+    /// Write a connection request with a token that expires at `expire_timestamp`, encrypted with `private_key`
+    fn write_request(
+        buf: &mut [u8],
+        protocol_id: u64,
+        expire_timestamp: u64,
+        private_key: &Key,
+    ) -> usize {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let token_data = ConnectTokenPrivate {
+            client_id: 0x1234,
+            timeout_seconds: -1,
+            server_addresses: AddressList::new("127.0.0.1:40002").unwrap(),
+            user_data: [0u8; USER_DATA_BYTES],
+            client_to_server_key: generate_key(),
+            server_to_client_key: generate_key(),
+        }
+        .encrypt(protocol_id, expire_timestamp, nonce, private_key)
+        .unwrap();
+        Packet::Request(RequestPacket {
+            version_info: *NETCODE_VERSION,
+            protocol_id,
+            expire_timestamp,
+            token_nonce: nonce,
+            token_data: Box::new(token_data),
+            protocol_hash: None,
+        })
+        .write(buf, 0, &generate_key(), protocol_id)
+        .unwrap()
+    }
+
+    /// Only the connect tokens that were issued with the private key of the server, and that are
+    /// not expired, are accepted
+    #[test]
+    fn request_packet_invalid_token() {
+        let private_key = generate_key();
+        let protocol_id = 0x1234_5678_9abc_def0;
+        let now = 100;
+        let read = |buf: &mut [u8]| {
+            Packet::read(buf, protocol_id, now, private_key, None, 0xff).map(|_| ())
+        };
+
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let size = write_request(&mut buf, protocol_id, now + 30, &private_key);
+        assert!(read(&mut buf[..size]).is_ok());
+        // the requests without protocol hash have the size of the standard netcode requests
+        assert_eq!(size, REQUEST_PACKET_SIZE);
+
+        // expired token
+        let size = write_request(&mut buf, protocol_id, now, &private_key);
+        assert!(matches!(
+            read(&mut buf[..size]),
+            Err(NetcodeError::Packet(Error::TokenExpired))
+        ));
+
+        // token issued with another private key
+        let size = write_request(&mut buf, protocol_id, now + 30, &generate_key());
+        assert!(matches!(
+            read(&mut buf[..size]),
+            Err(NetcodeError::Crypto(_))
+        ));
+
+        // the expire timestamp is authenticated, it cannot be extended by the client
+        let size = write_request(&mut buf, protocol_id, now + 30, &private_key);
+        let expire_start = 1 + NETCODE_VERSION.len() + size_of::<u64>();
+        buf[expire_start..expire_start + size_of::<u64>()]
+            .copy_from_slice(&(now + 3600).to_le_bytes());
+        assert!(matches!(
+            read(&mut buf[..size]),
+            Err(NetcodeError::Crypto(_))
+        ));
+    }
+
     #[test]
     fn denied_packet_custom_reason() {
         let packet_key = generate_key();
@@ -722,15 +889,42 @@ mod tests {
         assert_eq!(denied_pkt.reason, DeniedReason::ServerFull);
     }
 
+    #[test]
+    fn denied_reason_roundtrip() {
+        for reason in [
+            DeniedReason::ServerFull,
+            DeniedReason::Banned,
+            DeniedReason::BannedUntil(1_700_000_000),
+            DeniedReason::InternalError,
+            DeniedReason::AlreadyConnected,
+            DeniedReason::TokenAlreadyUsed,
+            DeniedReason::InvalidToken,
+            DeniedReason::BadVersion,
+            DeniedReason::AuthFailed,
+            DeniedReason::AuthTimedOut,
+            DeniedReason::ProtocolMismatch,
+            DeniedReason::LoggedInElsewhere,
+            DeniedReason::Custom(String::from("maintenance")),
+        ] {
+            let mut cursor = std::io::Cursor::new(Vec::new());
+            reason.write_to(&mut cursor).unwrap();
+            cursor.set_position(0);
+            assert_eq!(DeniedReason::read_from(&mut cursor).unwrap(), reason);
+        }
+    }
+
     #[test]
     pub fn challenge_packet() {
-        let token = [0u8; ChallengeToken::SIZE];
+        let token = vec![0u8; ChallengeToken::SIZE];
         let packet_key = generate_key();
         let protocol_id = 0x1234_5678_9abc_def0;
         let sequence = 0u64;
         let mut replay_protection = ReplayProtection::new();
 
-        let packet = Packet::Challenge(ChallengePacket { sequence, token });
+        let packet = Packet::Challenge(ChallengePacket {
+            sequence,
+            token: token.clone(),
+        });
 
         let mut buf = [0u8; MAX_PKT_BUF_SIZE];
         let size = packet
@@ -755,6 +949,33 @@ mod tests {
         assert_eq!(challenge_pkt.sequence, sequence);
     }
 
+    /// The challenge token of the stateless handshake is accepted, but not the tokens of other sizes
+    #[test]
+    pub fn challenge_packet_token_size() {
+        let packet_key = generate_key();
+        let protocol_id = 0x1234_5678_9abc_def0;
+        for (token_size, valid) in [
+            (StatelessChallengeToken::SIZE, true),
+            (ChallengeToken::SIZE - 1, false),
+            (ChallengeToken::SIZE + 1, false),
+        ] {
+            let token = vec![0u8; token_size];
+            let packet = ResponsePacket::create(0, token.clone());
+            let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+            let size = packet.write(&mut buf, 0, &packet_key, protocol_id).unwrap();
+
+            let packet = Packet::read(&mut buf[..size], protocol_id, 0, packet_key, None, 0xff);
+            match packet {
+                Ok(Packet::Response(response_pkt)) => {
+                    assert!(valid);
+                    assert_eq!(response_pkt.token, token);
+                }
+                Ok(_) => panic!("wrong packet type"),
+                Err(_) => assert!(!valid),
+            }
+        }
+    }
+
     #[test]
     pub fn keep_alive_packet() {
         let packet_key = generate_key();
@@ -847,4 +1068,44 @@ mod tests {
 
         assert_eq!(data_pkt.buf.len(), 100);
     }
+
+    /// Payload packets are encrypted and authenticated with the key of the connection
+    #[test]
+    pub fn payload_packet_encrypted() {
+        let packet_key = generate_key();
+        let protocol_id = 0x1234_5678_9abc_def0;
+        let payload = b"some secret game state".to_vec();
+        let packet = Packet::Payload(PayloadPacket { buf: &payload });
+
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let size = packet.write(&mut buf, 0, &packet_key, protocol_id).unwrap();
+        // the payload is not readable on the wire
+        assert!(!buf[..size]
+            .windows(payload.len())
+            .any(|window| window == payload.as_slice()));
+
+        // a packet that was tampered with is rejected
+        let mut tampered = buf;
+        tampered[size - 1] ^= 1;
+        assert!(Packet::read(
+            &mut tampered[..size],
+            protocol_id,
+            0,
+            packet_key,
+            Some(&mut ReplayProtection::new()),
+            0xff,
+        )
+        .is_err());
+
+        // a packet encrypted with another key is rejected
+        assert!(Packet::read(
+            &mut buf[..size],
+            protocol_id,
+            0,
+            generate_key(),
+            Some(&mut ReplayProtection::new()),
+            0xff,
+        )
+        .is_err());
+    }
 }
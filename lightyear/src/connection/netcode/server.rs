@@ -1,10 +1,11 @@
-use std::collections::{HashMap, VecDeque};
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem::size_of;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use bevy::prelude::Resource;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, warn};
 
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
@@ -12,10 +13,12 @@ use tracing::{instrument, Level};
 use crate::connection::id;
 use crate::connection::netcode::token::TOKEN_EXPIRE_SEC;
 use crate::connection::server::{
-    ConnectionRequestHandler, DefaultConnectionRequestHandler, DeniedReason, IoConfig, NetServer,
+    ConnectionRequestHandler, DefaultConnectionRequestHandler, DeniedReason, DenyPredicate,
+    IoConfig, NetServer,
 };
 use crate::packet::packet_builder::RecvPayload;
 use crate::server::config::NetcodeConfig;
+use crate::server::events::SentPacketKind;
 use crate::server::io::{Io, ServerIoEvent, ServerNetworkEventSender};
 use crate::transport::{PacketReceiver, PacketSender};
 
@@ -24,16 +27,23 @@ use super::{
     crypto::{self, Key},
     error::{Error, Result},
     packet::{
-        ChallengePacket, DeniedPacket, DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket,
-        RequestPacket, ResponsePacket,
+        self, ChallengePacket, DeniedPacket, DisconnectPacket, KeepAlivePacket, Packet,
+        PayloadPacket, RequestPacket, ResponsePacket,
     },
     replay::ReplayProtection,
-    token::{ChallengeToken, ConnectToken, ConnectTokenBuilder, ConnectTokenPrivate},
-    MAC_BYTES, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC,
+    token::{
+        ChallengeToken, ConnectToken, ConnectTokenBuilder, ConnectTokenPrivate,
+        StatelessChallengeToken,
+    },
+    MAC_BYTES, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, NETCODE_VERSION, PACKET_SEND_RATE_SEC,
+    USER_DATA_BYTES,
 };
 
 pub const MAX_CLIENTS: usize = 256;
 
+/// Maximum number of handshakes for which the server is waiting for the response to its challenge
+const MAX_PENDING_HANDSHAKES: usize = 4 * MAX_CLIENTS;
+
 const CLIENT_TIMEOUT_SECS: i32 = 10;
 
 #[derive(Clone, Copy)]
@@ -93,6 +103,10 @@ struct Connection {
     send_key: Key,
     receive_key: Key,
     sequence: u64,
+    /// Protocol id used by the client, which can be one of the compatible protocol ids of the server
+    protocol_id: u64,
+    /// User data of the connect token used by the client
+    user_data: [u8; USER_DATA_BYTES],
 }
 
 impl Connection {
@@ -148,12 +162,16 @@ impl ConnectionCache {
         timeout: i32,
         send_key: Key,
         receive_key: Key,
+        protocol_id: u64,
+        user_data: [u8; USER_DATA_BYTES],
     ) {
         if let Some((_, ref mut existing)) = self.find_by_addr(&addr) {
             existing.client_id = client_id;
             existing.timeout = timeout;
             existing.send_key = send_key;
             existing.receive_key = receive_key;
+            existing.protocol_id = protocol_id;
+            existing.user_data = user_data;
             existing.last_access_time = self.time;
             return;
         }
@@ -169,6 +187,8 @@ impl ConnectionCache {
             send_key,
             receive_key,
             sequence: 0,
+            protocol_id,
+            user_data,
         };
         self.clients.insert(client_id, conn);
         self.replay_protection
@@ -216,12 +236,141 @@ impl ConnectionCache {
 
 pub type Callback<Ctx> = Box<dyn FnMut(ClientId, SocketAddr, &mut Ctx) + Send + Sync + 'static>;
 
+pub type ThrottleCallback<Ctx> = Box<dyn FnMut(IpAddr, &mut Ctx) + Send + Sync + 'static>;
+/// Callback called with the id of the client and the hash of its protocol when a connection request
+/// is denied because of a protocol mismatch
+pub type ProtocolMismatchCallback<Ctx> =
+    Box<dyn FnMut(ClientId, Option<u64>, &mut Ctx) + Send + Sync + 'static>;
+/// Callback called with the id and the address of the client, the kind of packet and the error
+/// when a packet cannot be sent to a client
+pub type SendErrorCallback<Ctx> =
+    Box<dyn FnMut(ClientId, SocketAddr, SentPacketKind, &Error, &mut Ctx) + Send + Sync + 'static>;
+/// Callback called with the IP address and the number of malformed packets received from it when an address
+/// reaches the [`SuspicionThreshold`]
+pub type SuspicionCallback<Ctx> =
+    Box<dyn FnMut(IpAddr, u32, SuspicionAction, &mut Ctx) + Send + Sync + 'static>;
+/// Callback called with the id of the client, the address of the existing connection, the address of the
+/// new connection and the [`DuplicateLoginPolicy`] that was applied when a client connects with the id of
+/// a client that is already connected from another address
+pub type DuplicateLoginCallback<Ctx> = Box<
+    dyn FnMut(ClientId, SocketAddr, SocketAddr, DuplicateLoginPolicy, &mut Ctx)
+        + Send
+        + Sync
+        + 'static,
+>;
+
+/// Limit on the number of handshake packets (connection requests and challenge responses)
+/// that the server processes from a single IP address.
+///
+/// The packets above the limit are dropped before being decrypted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HandshakeRateLimit {
+    /// Maximum number of handshake packets processed for an IP address during a window
+    pub max_packets: u32,
+    /// Duration (in seconds) of the window
+    pub window_secs: f64,
+}
+
+impl Default for HandshakeRateLimit {
+    fn default() -> Self {
+        Self {
+            max_packets: 20,
+            window_secs: 1.0,
+        }
+    }
+}
+
+/// What the server does with an IP address that sent too many malformed packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SuspicionAction {
+    /// Only report the address
+    #[default]
+    Report,
+    /// Disconnect the clients connected from the address
+    Disconnect,
+    /// Disconnect the clients connected from the address, and ban it
+    Ban,
+}
+
+/// What the server does when a client connects with the id of a client that is already connected
+/// from another address (a second device, or a stolen connect token).
+///
+/// The connections are identified by the client id, so the two clients can't be connected at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateLoginPolicy {
+    /// Deny the new connection with [`DeniedReason::AlreadyConnected`]
+    #[default]
+    RejectNew,
+    /// Disconnect the existing client with [`DeniedReason::LoggedInElsewhere`], and accept the new one
+    KickOld,
+}
+
+/// Limit on the number of malformed packets (packets that cannot be read or decrypted) that the server
+/// accepts from a single IP address before taking action against it.
+///
+/// Only the packets received from the address of an established connection are counted: the source address of the
+/// handshake packets can be spoofed, so counting them would let anyone get another client disconnected or banned.
+/// Duplicated packets, expired connect tokens and unknown protocol ids can happen with honest clients,
+/// so they are not counted either.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SuspicionThreshold {
+    /// Number of malformed packets received from an IP address during a window before the action is taken
+    pub max_malformed_packets: u32,
+    /// Duration (in seconds) of the window
+    pub window_secs: f64,
+    pub action: SuspicionAction,
+}
+
+impl Default for SuspicionThreshold {
+    fn default() -> Self {
+        Self {
+            max_malformed_packets: 10,
+            window_secs: 10.0,
+            action: SuspicionAction::default(),
+        }
+    }
+}
+
+/// Number of handshake packets received from an IP address during the current window
+#[derive(Clone, Copy)]
+struct HandshakeWindow {
+    start: f64,
+    count: u32,
+}
+
+/// A client that was sent a challenge and hasn't answered it yet.
+///
+/// The key needed to decrypt the response is always stored, because the response is encrypted with the
+/// key of the client. <br>
+/// With a [stateless handshake](ServerConfig::stateless_handshake), the rest of the connection data is
+/// contained in the challenge token echoed by the client instead of being stored by the server.
+#[derive(Clone, Copy)]
+struct PendingHandshake {
+    receive_key: Key,
+    protocol_id: u64,
+    expire_timestamp: u64,
+    /// The data used to create the connection, if the handshake is not stateless
+    connection: Option<StatelessChallengeToken>,
+}
+
 /// Configuration for a server.
 ///
 /// * `num_disconnect_packets` - The number of redundant disconnect packets that will be sent to a client when the server is disconnecting it.
 /// * `keep_alive_send_rate` - The rate at which keep-alive packets will be sent to clients.
 /// * `on_connect` - A callback that will be called when a client is connected to the server.
 /// * `on_disconnect` - A callback that will be called when a client is disconnected from the server.
+/// * `handshake_rate_limit` - The maximum rate of handshake packets that will be processed for a single IP address.
+/// * `on_throttle` - A callback that will be called when the handshake packets of an IP address start being dropped.
+/// * `deny_predicate` - A predicate that can deny the connection requests based on the address of the client.
+/// * `compatible_protocol_ids` - Other protocol ids that the server accepts, in addition to its own protocol id.
+/// * `protocol_hash` - Hash of the protocol of the server. The connection requests with a different hash are denied.
+/// * `on_protocol_mismatch` - A callback that will be called when a connection request is denied because of its protocol hash.
+/// * `suspicion_threshold` - The number of malformed packets accepted from a single IP address before taking action against it.
+/// * `on_suspicious_activity` - A callback that will be called when an IP address reaches the suspicion threshold.
+/// * `on_send_error` - A callback that will be called when a packet cannot be sent to a client.
+/// * `duplicate_login_policy` - What the server does when a client connects with the id of a client that is already connected.
+/// * `on_duplicate_login` - A callback that will be called when the duplicate login policy is applied.
+/// * `stateless_handshake` - Whether the connection data is sent to the client in the challenge token instead of being stored by the server.
 ///
 /// # Example
 /// ```
@@ -245,10 +394,22 @@ pub struct ServerConfig<Ctx> {
     token_expire_secs: i32,
     client_timeout_secs: i32,
     connection_request_handler: Arc<dyn ConnectionRequestHandler>,
+    deny_predicate: Option<Arc<dyn DenyPredicate>>,
     server_addr: SocketAddr,
     context: Ctx,
     on_connect: Option<Callback<Ctx>>,
     on_disconnect: Option<Callback<Ctx>>,
+    handshake_rate_limit: Option<HandshakeRateLimit>,
+    on_throttle: Option<ThrottleCallback<Ctx>>,
+    compatible_protocol_ids: Vec<u64>,
+    protocol_hash: Option<u64>,
+    on_protocol_mismatch: Option<ProtocolMismatchCallback<Ctx>>,
+    suspicion_threshold: Option<SuspicionThreshold>,
+    on_suspicious_activity: Option<SuspicionCallback<Ctx>>,
+    on_send_error: Option<SendErrorCallback<Ctx>>,
+    duplicate_login_policy: DuplicateLoginPolicy,
+    on_duplicate_login: Option<DuplicateLoginCallback<Ctx>>,
+    stateless_handshake: bool,
 }
 
 impl Default for ServerConfig<()> {
@@ -259,10 +420,22 @@ impl Default for ServerConfig<()> {
             token_expire_secs: TOKEN_EXPIRE_SEC,
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
+            deny_predicate: None,
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
             context: (),
             on_connect: None,
             on_disconnect: None,
+            handshake_rate_limit: None,
+            on_throttle: None,
+            compatible_protocol_ids: vec![],
+            protocol_hash: None,
+            on_protocol_mismatch: None,
+            suspicion_threshold: None,
+            on_suspicious_activity: None,
+            on_send_error: None,
+            duplicate_login_policy: DuplicateLoginPolicy::default(),
+            on_duplicate_login: None,
+            stateless_handshake: false,
         }
     }
 }
@@ -280,10 +453,22 @@ impl<Ctx> ServerConfig<Ctx> {
             token_expire_secs: TOKEN_EXPIRE_SEC,
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
+            deny_predicate: None,
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
             context: ctx,
             on_connect: None,
             on_disconnect: None,
+            handshake_rate_limit: None,
+            on_throttle: None,
+            compatible_protocol_ids: vec![],
+            protocol_hash: None,
+            on_protocol_mismatch: None,
+            suspicion_threshold: None,
+            on_suspicious_activity: None,
+            on_send_error: None,
+            duplicate_login_policy: DuplicateLoginPolicy::default(),
+            on_duplicate_login: None,
+            stateless_handshake: false,
         }
     }
     /// Set the number of redundant disconnect packets that will be sent to a client when the server is disconnecting it. <br>
@@ -338,6 +523,109 @@ impl<Ctx> ServerConfig<Ctx> {
         self.on_disconnect = Some(Box::new(cb));
         self
     }
+    /// Limit the rate of handshake packets that will be processed for a single IP address. <br>
+    /// The default is `None` (no limit).
+    pub fn handshake_rate_limit(mut self, limit: Option<HandshakeRateLimit>) -> Self {
+        self.handshake_rate_limit = limit;
+        self
+    }
+    /// Provide a callback that will be called when the server starts dropping the handshake packets of an IP address
+    /// because of the [`HandshakeRateLimit`]. <br>
+    /// The callback is called at most once per rate limit window for each IP address.
+    pub fn on_throttle<F>(mut self, cb: F) -> Self
+    where
+        F: FnMut(IpAddr, &mut Ctx) + Send + Sync + 'static,
+    {
+        self.on_throttle = Some(Box::new(cb));
+        self
+    }
+    /// Provide a predicate that can deny the connection requests based on the address of the client,
+    /// before the handshake proceeds. <br>
+    /// The default is `None` (only the banned addresses are denied).
+    pub fn deny_predicate(mut self, predicate: Option<Arc<dyn DenyPredicate>>) -> Self {
+        self.deny_predicate = predicate;
+        self
+    }
+    /// Set the protocol ids that the server accepts in addition to its own protocol id. <br>
+    /// This lets clients that use an adjacent version of the protocol connect during a rolling upgrade.
+    /// Each connection keeps using the protocol id that the client connected with.
+    pub fn compatible_protocol_ids(mut self, protocol_ids: Vec<u64>) -> Self {
+        self.compatible_protocol_ids = protocol_ids;
+        self
+    }
+    /// Set the hash of the protocol of the server. <br>
+    /// The connection requests that use the protocol id of the server but a different protocol hash (or no protocol
+    /// hash) are denied with [`DeniedReason::ProtocolMismatch`]. The requests that use one of the
+    /// [compatible protocol ids](Self::compatible_protocol_ids) are not checked. <br>
+    /// The default is `None` (the protocol hash of the clients is not checked).
+    pub fn protocol_hash(mut self, protocol_hash: Option<u64>) -> Self {
+        self.protocol_hash = protocol_hash;
+        self
+    }
+    /// Provide a callback that will be called when a connection request is denied because of a protocol mismatch.
+    pub fn on_protocol_mismatch<F>(mut self, cb: F) -> Self
+    where
+        F: FnMut(ClientId, Option<u64>, &mut Ctx) + Send + Sync + 'static,
+    {
+        self.on_protocol_mismatch = Some(Box::new(cb));
+        self
+    }
+    /// Take action against the IP addresses that send too many malformed packets. <br>
+    /// The default is `None` (malformed packets are ignored).
+    pub fn suspicion_threshold(mut self, threshold: Option<SuspicionThreshold>) -> Self {
+        self.suspicion_threshold = threshold;
+        self
+    }
+    /// Provide a callback that will be called when an IP address reaches the [`SuspicionThreshold`]. <br>
+    /// The callback is called at most once per window for each IP address.
+    pub fn on_suspicious_activity<F>(mut self, cb: F) -> Self
+    where
+        F: FnMut(IpAddr, u32, SuspicionAction, &mut Ctx) + Send + Sync + 'static,
+    {
+        self.on_suspicious_activity = Some(Box::new(cb));
+        self
+    }
+    /// Provide a callback that will be called when a packet cannot be sent to a client,
+    /// including the packets of the handshake. <br>
+    /// The callback will be called with the client index and address, the kind of packet and the error.
+    pub fn on_send_error<F>(mut self, cb: F) -> Self
+    where
+        F: FnMut(ClientId, SocketAddr, SentPacketKind, &Error, &mut Ctx) + Send + Sync + 'static,
+    {
+        self.on_send_error = Some(Box::new(cb));
+        self
+    }
+    /// Set what the server does when a client connects with the id of a client that is already connected
+    /// from another address. <br>
+    /// The default is [`DuplicateLoginPolicy::RejectNew`].
+    pub fn duplicate_login_policy(mut self, policy: DuplicateLoginPolicy) -> Self {
+        self.duplicate_login_policy = policy;
+        self
+    }
+    /// Provide a callback that will be called when a client connects with the id of a client that is already
+    /// connected from another address. <br>
+    /// The callback will be called with the client index, the address of the existing connection, the address
+    /// of the new connection and the policy that was applied.
+    pub fn on_duplicate_login<F>(mut self, cb: F) -> Self
+    where
+        F: FnMut(ClientId, SocketAddr, SocketAddr, DuplicateLoginPolicy, &mut Ctx)
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.on_duplicate_login = Some(Box::new(cb));
+        self
+    }
+    /// Send the connection data to the client in the challenge token, instead of storing it until the client
+    /// answers the challenge. <br>
+    /// The challenge token is then bigger than the one of the standard netcode protocol, so only the lightyear
+    /// clients can connect to the server. The server still stores the key used to decrypt the response of each
+    /// client it sent a challenge to, for at most a few thousand clients at a time. <br>
+    /// The default is `false` (the challenge token of the standard netcode protocol is used).
+    pub fn stateless_handshake(mut self, stateless: bool) -> Self {
+        self.stateless_handshake = stateless;
+        self
+    }
 }
 
 /// The `netcode` server.
@@ -383,6 +671,19 @@ pub struct NetcodeServer<Ctx = ()> {
     protocol_id: u64,
     conn_cache: ConnectionCache,
     token_entries: TokenEntries,
+    handshake_windows: HashMap<IpAddr, HandshakeWindow>,
+    pending_handshakes: HashMap<SocketAddr, PendingHandshake>,
+    /// Number of malformed packets received from each IP address during the current window
+    suspicion_windows: HashMap<IpAddr, HandshakeWindow>,
+    /// Addresses whose clients must be disconnected because they sent too many malformed packets
+    suspects_to_disconnect: Vec<IpAddr>,
+    banned_addresses: HashSet<IpAddr>,
+    /// Clients that were disconnected during the current update because they logged in from another address.
+    /// The new client is only connected during a later update
+    kicked_duplicates: HashSet<ClientId>,
+    /// Clients that were denied because their id was already connected, so that their retries are only
+    /// reported once
+    rejected_duplicates: HashSet<(ClientId, SocketAddr)>,
     cfg: ServerConfig<Ctx>,
 }
 
@@ -401,6 +702,13 @@ impl NetcodeServer {
             challenge_key: crypto::generate_key(),
             conn_cache: ConnectionCache::new(0.0),
             token_entries: TokenEntries::new(),
+            handshake_windows: HashMap::new(),
+            pending_handshakes: HashMap::new(),
+            suspicion_windows: HashMap::new(),
+            suspects_to_disconnect: vec![],
+            banned_addresses: HashSet::new(),
+            kicked_duplicates: HashSet::new(),
+            rejected_duplicates: HashSet::new(),
             cfg: ServerConfig::default(),
         };
         // info!("server started on {}", server.io.local_addr());
@@ -435,6 +743,13 @@ impl<Ctx> NetcodeServer<Ctx> {
             challenge_key: crypto::generate_key(),
             conn_cache: ConnectionCache::new(0.0),
             token_entries: TokenEntries::new(),
+            handshake_windows: HashMap::new(),
+            pending_handshakes: HashMap::new(),
+            suspicion_windows: HashMap::new(),
+            suspects_to_disconnect: vec![],
+            banned_addresses: HashSet::new(),
+            kicked_duplicates: HashSet::new(),
+            rejected_duplicates: HashSet::new(),
             cfg,
         };
         // info!("server started on {}", server.addr());
@@ -458,6 +773,109 @@ impl<Ctx> NetcodeServer<Ctx> {
             cb(client_id, addr, &mut self.cfg.context)
         }
     }
+    fn on_duplicate_login(&mut self, client_id: ClientId, existing: SocketAddr, new: SocketAddr) {
+        // a denied client retries until it gives up, but it is only reported once
+        if self.cfg.duplicate_login_policy == DuplicateLoginPolicy::RejectNew
+            && !self.rejected_duplicates.insert((client_id, new))
+        {
+            return;
+        }
+        if let Some(cb) = self.cfg.on_duplicate_login.as_mut() {
+            cb(
+                client_id,
+                existing,
+                new,
+                self.cfg.duplicate_login_policy,
+                &mut self.cfg.context,
+            )
+        }
+    }
+    /// Returns true if the handshake packet received from `addr` should be dropped
+    /// because the IP address exceeded the [`HandshakeRateLimit`]
+    fn throttle_handshake(&mut self, addr: SocketAddr) -> bool {
+        let Some(limit) = self.cfg.handshake_rate_limit else {
+            return false;
+        };
+        let ip = addr.ip();
+        let window = self.handshake_windows.entry(ip).or_insert(HandshakeWindow {
+            start: self.time,
+            count: 0,
+        });
+        if self.time - window.start >= limit.window_secs {
+            window.start = self.time;
+            window.count = 0;
+        }
+        window.count = window.count.saturating_add(1);
+        if window.count <= limit.max_packets {
+            return false;
+        }
+        // only notify once per window
+        if window.count == limit.max_packets + 1 {
+            debug!("server is throttling the handshake packets from {ip}");
+            if let Some(cb) = self.cfg.on_throttle.as_mut() {
+                cb(ip, &mut self.cfg.context)
+            }
+        }
+        true
+    }
+    /// Count a malformed packet received from `addr`, and take action against the address
+    /// if it reached the [`SuspicionThreshold`]
+    fn record_malformed_packet(&mut self, addr: SocketAddr) {
+        let Some(threshold) = self.cfg.suspicion_threshold else {
+            return;
+        };
+        let ip = addr.ip();
+        let window = self.suspicion_windows.entry(ip).or_insert(HandshakeWindow {
+            start: self.time,
+            count: 0,
+        });
+        if self.time - window.start >= threshold.window_secs {
+            window.start = self.time;
+            window.count = 0;
+        }
+        window.count = window.count.saturating_add(1);
+        // only act once per window
+        if window.count != threshold.max_malformed_packets {
+            return;
+        }
+        let count = window.count;
+        warn!(
+            "server received {count} malformed packets from {ip}, action: {:?}",
+            threshold.action
+        );
+        match threshold.action {
+            SuspicionAction::Report => {}
+            SuspicionAction::Disconnect => self.suspects_to_disconnect.push(ip),
+            SuspicionAction::Ban => {
+                self.banned_addresses.insert(ip);
+                self.suspects_to_disconnect.push(ip);
+            }
+        }
+        if let Some(cb) = self.cfg.on_suspicious_activity.as_mut() {
+            cb(ip, count, threshold.action, &mut self.cfg.context)
+        }
+    }
+    /// The reason why the connection requests from `addr` must be denied, if any
+    fn deny_reason(&self, addr: SocketAddr) -> Option<DeniedReason> {
+        if self.banned_addresses.contains(&addr.ip()) {
+            return Some(DeniedReason::Banned);
+        }
+        self.cfg
+            .deny_predicate
+            .as_ref()
+            .and_then(|predicate| predicate.deny(addr))
+    }
+    /// The protocol id used by a connection request, if it is one of the protocol ids accepted by the server.
+    ///
+    /// Otherwise returns the protocol id of the server, so that the request is rejected.
+    fn request_protocol_id(&self, buf: &[u8]) -> u64 {
+        let offset = size_of::<u8>() + NETCODE_VERSION.len();
+        buf.get(offset..offset + size_of::<u64>())
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u64::from_le_bytes)
+            .filter(|protocol_id| self.cfg.compatible_protocol_ids.contains(protocol_id))
+            .unwrap_or(self.protocol_id)
+    }
     fn touch_client(&mut self, client_id: Option<ClientId>) -> Result<()> {
         let Some(id) = client_id else {
             return Ok(());
@@ -476,6 +894,7 @@ impl<Ctx> NetcodeServer<Ctx> {
         &mut self,
         addr: SocketAddr,
         packet: Packet,
+        now: u64,
         sender: &mut impl PacketSender,
     ) -> Result<()> {
         let client_id = self.conn_cache.find_by_addr(&addr).map(|(id, _)| id);
@@ -487,8 +906,8 @@ impl<Ctx> NetcodeServer<Ctx> {
                 .unwrap_or_else(|| addr.to_string())
         );
         match packet {
-            Packet::Request(packet) => self.process_connection_request(addr, packet, sender),
-            Packet::Response(packet) => self.process_connection_response(addr, packet, sender),
+            Packet::Request(packet) => self.process_connection_request(addr, packet, now, sender),
+            Packet::Response(packet) => self.process_connection_response(addr, packet, now, sender),
             Packet::KeepAlive(_) => self.touch_client(client_id),
             Packet::Payload(packet) => {
                 self.touch_client(client_id)?;
@@ -520,16 +939,44 @@ impl<Ctx> NetcodeServer<Ctx> {
     fn send_to_addr(
         &mut self,
         packet: Packet,
+        id: ClientId,
         addr: SocketAddr,
         key: Key,
+        protocol_id: u64,
         sender: &mut impl PacketSender,
     ) -> Result<()> {
         let mut buf = [0u8; MAX_PKT_BUF_SIZE];
-        let size = packet.write(&mut buf, self.sequence, &key, self.protocol_id)?;
-        sender.send(&buf[..size], &addr).map_err(Error::from)?;
+        let size = packet.write(&mut buf, self.sequence, &key, protocol_id)?;
+        if let Err(e) = sender.send(&buf[..size], &addr) {
+            return Err(self.report_send_error(&packet, id, addr, e.into()));
+        }
         self.sequence += 1;
         Ok(())
     }
+    /// Report a packet that could not be sent to a client
+    fn report_send_error(
+        &mut self,
+        packet: &Packet,
+        id: ClientId,
+        addr: SocketAddr,
+        error: Error,
+    ) -> Error {
+        warn!("server could not send {packet} to client {id}: {error}");
+        let kind = match packet {
+            Packet::Denied(_) => SentPacketKind::Denied,
+            Packet::Challenge(_) => SentPacketKind::Challenge,
+            Packet::KeepAlive(_) => SentPacketKind::KeepAlive,
+            Packet::Payload(_) => SentPacketKind::Payload,
+            Packet::Disconnect(_) => SentPacketKind::Disconnect,
+            Packet::Request(_) | Packet::Response(_) => {
+                unreachable!("the server doesn't send {packet}")
+            }
+        };
+        if let Some(cb) = self.cfg.on_send_error.as_mut() {
+            cb(id, addr, kind, &error, &mut self.cfg.context)
+        }
+        error
+    }
     fn send_to_client(
         &mut self,
         packet: Packet,
@@ -537,16 +984,17 @@ impl<Ctx> NetcodeServer<Ctx> {
         sender: &mut impl PacketSender,
     ) -> Result<()> {
         let mut buf = [0u8; MAX_PKT_BUF_SIZE];
-        let conn = &mut self
+        let conn = self.conn_cache.clients.get(&id).expect("invalid client id");
+        let size = packet.write(&mut buf, conn.sequence, &conn.send_key, conn.protocol_id)?;
+        let addr = conn.addr;
+        if let Err(e) = sender.send(&buf[..size], &addr) {
+            return Err(self.report_send_error(&packet, id, addr, e.into()));
+        }
+        let conn = self
             .conn_cache
             .clients
             .get_mut(&id)
             .expect("invalid client id");
-        let size = packet.write(&mut buf, conn.sequence, &conn.send_key, self.protocol_id)?;
-        sender
-            .send(&buf[..size], &conn.addr)
-            // .inspect_err(|e| error!("ERROR SENDING: {:?}", e))
-            .map_err(Error::from)?;
         conn.last_access_time = self.time;
         conn.last_send_time = self.time;
         conn.sequence += 1;
@@ -557,6 +1005,7 @@ impl<Ctx> NetcodeServer<Ctx> {
         &mut self,
         from_addr: SocketAddr,
         mut packet: RequestPacket,
+        now: u64,
         sender: &mut impl PacketSender,
     ) -> Result<()> {
         let mut reader = std::io::Cursor::new(&mut packet.token_data[..]);
@@ -564,6 +1013,18 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server ignored connection request. failed to read connect token");
             return Ok(());
         };
+        if let Some(denied_reason) = self.deny_reason(from_addr) {
+            debug!("server denied connection request from {from_addr}: {denied_reason:?}");
+            self.send_to_addr(
+                DeniedPacket::create(denied_reason),
+                token.client_id,
+                from_addr,
+                token.server_to_client_key,
+                packet.protocol_id,
+                sender,
+            )?;
+            return Ok(());
+        }
         // TODO: this doesn't work with local hosts because the local bind_addr is often 0.0.0.0, even though
         //  the tokens contain 127.0.0.1
         // if !token
@@ -586,13 +1047,27 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server ignored connection request. a client with this address is already connected");
             return Ok(());
         };
-        if self
+        if let Some(existing) = self
             .conn_cache
             .find_by_id(token.client_id)
-            .is_some_and(|conn| conn.is_connected())
+            .filter(|conn| conn.is_connected())
         {
-            debug!("server ignored connection request. a client with this id is already connected");
-            return Ok(());
+            // with `KickOld`, the existing client is only disconnected once the new client answers the challenge
+            if self.cfg.duplicate_login_policy == DuplicateLoginPolicy::RejectNew {
+                debug!(
+                    "server denied connection request. a client with this id is already connected"
+                );
+                self.on_duplicate_login(token.client_id, existing.addr, from_addr);
+                self.send_to_addr(
+                    DeniedPacket::create(DeniedReason::AlreadyConnected),
+                    token.client_id,
+                    from_addr,
+                    token.server_to_client_key,
+                    packet.protocol_id,
+                    sender,
+                )?;
+                return Ok(());
+            }
         };
         let entry = TokenEntry {
             time: self.time,
@@ -606,12 +1081,34 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server ignored connection request. connect token has already been used");
             return Ok(());
         };
+        if self.cfg.protocol_hash.is_some_and(|hash| {
+            packet.protocol_id == self.protocol_id && packet.protocol_hash != Some(hash)
+        }) {
+            debug!(
+                client_hash = ?packet.protocol_hash,
+                "server denied connection request. the client uses a different protocol"
+            );
+            if let Some(cb) = self.cfg.on_protocol_mismatch.as_mut() {
+                cb(token.client_id, packet.protocol_hash, &mut self.cfg.context)
+            }
+            self.send_to_addr(
+                DeniedPacket::create(DeniedReason::ProtocolMismatch),
+                token.client_id,
+                from_addr,
+                token.server_to_client_key,
+                packet.protocol_id,
+                sender,
+            )?;
+            return Ok(());
+        }
         if self.num_connected_clients() >= MAX_CLIENTS {
             debug!("server denied connection request. server is full");
             self.send_to_addr(
                 DeniedPacket::create(DeniedReason::ServerFull),
+                token.client_id,
                 from_addr,
                 token.server_to_client_key,
+                packet.protocol_id,
                 sender,
             )?;
             return Ok(());
@@ -624,31 +1121,64 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server denied connection request. handle_connection_request_fn returned false");
             self.send_to_addr(
                 DeniedPacket::create(denied_reason),
+                token.client_id,
                 from_addr,
                 token.server_to_client_key,
+                packet.protocol_id,
                 sender,
             )?;
             return Ok(());
         }
-        self.conn_cache.add(
-            token.client_id,
-            from_addr,
-            token.timeout_seconds,
-            token.server_to_client_key,
-            token.client_to_server_key,
-        );
-        let Ok(challenge_token_encrypted) = ChallengeToken {
+        // until the client answers the challenge, the server stores the key used to decrypt the response,
+        // and the information needed to create the connection unless it is sent in the challenge token
+        self.pending_handshakes
+            .retain(|_, pending| pending.expire_timestamp > now);
+        if self.pending_handshakes.len() >= MAX_PENDING_HANDSHAKES
+            && !self.pending_handshakes.contains_key(&from_addr)
+        {
+            debug!("server ignored connection request. too many pending handshakes");
+            return Ok(());
+        }
+        let connection = StatelessChallengeToken {
             client_id: token.client_id,
             user_data: token.user_data,
-        }
-        .encrypt(self.challenge_sequence, &self.challenge_key) else {
+            timeout_seconds: token.timeout_seconds,
+            client_to_server_key: token.client_to_server_key,
+            server_to_client_key: token.server_to_client_key,
+            expire_timestamp: packet.expire_timestamp,
+            protocol_id: packet.protocol_id,
+        };
+        self.pending_handshakes.insert(
+            from_addr,
+            PendingHandshake {
+                receive_key: token.client_to_server_key,
+                protocol_id: packet.protocol_id,
+                expire_timestamp: packet.expire_timestamp,
+                connection: (!self.cfg.stateless_handshake).then_some(connection),
+            },
+        );
+        let challenge_token_encrypted = if self.cfg.stateless_handshake {
+            connection
+                .encrypt(self.challenge_sequence, from_addr, &self.challenge_key)
+                .map(Vec::from)
+        } else {
+            ChallengeToken {
+                client_id: token.client_id,
+                user_data: token.user_data,
+            }
+            .encrypt(self.challenge_sequence, &self.challenge_key)
+            .map(Vec::from)
+        };
+        let Ok(challenge_token_encrypted) = challenge_token_encrypted else {
             debug!("server ignored connection request. failed to encrypt challenge token");
             return Ok(());
         };
         self.send_to_addr(
             ChallengePacket::create(self.challenge_sequence, challenge_token_encrypted),
+            token.client_id,
             from_addr,
             token.server_to_client_key,
+            packet.protocol_id,
             sender,
         )?;
         debug!("server sent connection challenge packet");
@@ -658,22 +1188,77 @@ impl<Ctx> NetcodeServer<Ctx> {
     fn process_connection_response(
         &mut self,
         from_addr: SocketAddr,
-        mut packet: ResponsePacket,
+        packet: ResponsePacket,
+        now: u64,
         sender: &mut impl PacketSender,
     ) -> Result<()> {
-        let Ok(challenge_token) =
-            ChallengeToken::decrypt(&mut packet.token, packet.sequence, &self.challenge_key)
-        else {
+        let Some(challenge_token) = self.read_challenge_token(from_addr, packet) else {
             debug!("server ignored connection response. failed to decrypt challenge token");
             return Ok(());
         };
+        if challenge_token.expire_timestamp <= now {
+            debug!("server ignored connection response. challenge token expired");
+            return Ok(());
+        }
+        // the address could have been banned since the challenge was sent
+        if let Some(denied_reason) = self.deny_reason(from_addr) {
+            debug!("server denied connection response from {from_addr}: {denied_reason:?}");
+            self.send_to_addr(
+                DeniedPacket::create(denied_reason),
+                challenge_token.client_id,
+                from_addr,
+                challenge_token.server_to_client_key,
+                challenge_token.protocol_id,
+                sender,
+            )?;
+            return Ok(());
+        }
         let id: ClientId = challenge_token.client_id;
-        let Some(conn) = self.conn_cache.find_by_id(id) else {
-            debug!("server ignored connection response. no packet send key");
+        if self
+            .conn_cache
+            .find_by_addr(&from_addr)
+            .is_some_and(|(_, conn)| conn.is_connected())
+        {
+            debug!("server ignored connection response. the client is already connected");
             return Ok(());
         };
-        if conn.is_connected() {
-            debug!("server ignored connection request. a client with this id is already connected");
+        if self.kicked_duplicates.contains(&id) {
+            debug!("server ignored connection response. the previous client with this id was disconnected during this update");
+            return Ok(());
+        }
+        if let Some(existing) = self
+            .conn_cache
+            .find_by_id(id)
+            .filter(|conn| conn.is_connected())
+        {
+            self.on_duplicate_login(id, existing.addr, from_addr);
+            match self.cfg.duplicate_login_policy {
+                DuplicateLoginPolicy::RejectNew => {
+                    debug!("server denied connection response. a client with this id is already connected");
+                    self.send_to_addr(
+                        DeniedPacket::create(DeniedReason::AlreadyConnected),
+                        challenge_token.client_id,
+                        from_addr,
+                        challenge_token.server_to_client_key,
+                        challenge_token.protocol_id,
+                        sender,
+                    )?;
+                }
+                DuplicateLoginPolicy::KickOld => {
+                    // the new client keeps sending responses until it is accepted, and the responses received
+                    // during this update are ignored, so it is connected during a later update: a connection
+                    // and a disconnection of the same id are never reported during the same update
+                    debug!(
+                        "server disconnecting client {id} because it logged in from {from_addr}"
+                    );
+                    self.close_connection(
+                        id,
+                        || DeniedPacket::create(DeniedReason::LoggedInElsewhere),
+                        sender,
+                    )?;
+                    self.kicked_duplicates.insert(id);
+                }
+            }
             return Ok(());
         };
 
@@ -681,16 +1266,24 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server denied connection response. server is full");
             self.send_to_addr(
                 DeniedPacket::create(DeniedReason::ServerFull),
+                challenge_token.client_id,
                 from_addr,
-                self.conn_cache
-                    .clients
-                    .get(&id)
-                    .expect("invalid client id")
-                    .send_key,
+                challenge_token.server_to_client_key,
+                challenge_token.protocol_id,
                 sender,
             )?;
             return Ok(());
         };
+        self.pending_handshakes.remove(&from_addr);
+        self.conn_cache.add(
+            id,
+            from_addr,
+            challenge_token.timeout_seconds,
+            challenge_token.server_to_client_key,
+            challenge_token.client_to_server_key,
+            challenge_token.protocol_id,
+            challenge_token.user_data,
+        );
         let client = self
             .conn_cache
             .clients
@@ -707,6 +1300,32 @@ impl<Ctx> NetcodeServer<Ctx> {
         self.on_connect(id, from_addr);
         Ok(())
     }
+    /// Decrypt the challenge token echoed by the client, and return the data used to create the connection
+    fn read_challenge_token(
+        &self,
+        from_addr: SocketAddr,
+        packet: ResponsePacket,
+    ) -> Option<StatelessChallengeToken> {
+        if self.cfg.stateless_handshake {
+            // the token can only be decrypted if the response comes from the address the challenge was sent to
+            let mut encrypted: [u8; StatelessChallengeToken::SIZE] =
+                packet.token.try_into().ok()?;
+            return StatelessChallengeToken::decrypt(
+                &mut encrypted,
+                packet.sequence,
+                from_addr,
+                &self.challenge_key,
+            )
+            .ok();
+        }
+        let mut encrypted: [u8; ChallengeToken::SIZE] = packet.token.try_into().ok()?;
+        let challenge_token =
+            ChallengeToken::decrypt(&mut encrypted, packet.sequence, &self.challenge_key).ok()?;
+        self.pending_handshakes
+            .get(&from_addr)
+            .and_then(|pending| pending.connection)
+            .filter(|connection| connection.client_id == challenge_token.client_id)
+    }
     fn check_for_timeouts(&mut self) {
         for id in self.conn_cache.ids() {
             let Some(client) = self.conn_cache.clients.get_mut(&id) else {
@@ -737,8 +1356,13 @@ impl<Ctx> NetcodeServer<Ctx> {
                 continue;
             }
 
-            self.send_to_client(KeepAlivePacket::create(id), id, io)?;
-            trace!("server sent connection keep-alive packet to client {id}");
+            // the failure is reported with the send error callback, the other clients still get their keep-alive
+            if self
+                .send_to_client(KeepAlivePacket::create(id), id, io)
+                .is_ok()
+            {
+                trace!("server sent connection keep-alive packet to client {id}");
+            }
         }
         Ok(())
     }
@@ -757,18 +1381,34 @@ impl<Ctx> NetcodeServer<Ctx> {
             // Too small to be a packet
             return Ok(());
         }
-        let (key, replay_protection) = match self.conn_cache.find_by_addr(&addr) {
+        let is_response = Packet::get_prefix(buf[0]).1 == Packet::RESPONSE;
+        if (buf[0] == Packet::REQUEST || is_response) && self.throttle_handshake(addr) {
+            trace!("server ignored handshake packet from throttled address {addr}");
+            return Ok(());
+        }
+        // only the packets sent to established connections are counted as malformed: the handshake packets
+        // are not authenticated, so anyone could spoof the address of another client
+        let (key, replay_protection, protocol_id, established) = match self
+            .conn_cache
+            .find_by_addr(&addr)
+        {
             // Regardless of whether an entry in the connection cache exists for the client or not,
             // if the packet is a connection request we need to use the server's private key to decrypt it.
-            _ if buf[0] == Packet::REQUEST => (self.private_key, None),
-            Some((client_id, _)) => (
+            _ if buf[0] == Packet::REQUEST => {
+                (self.private_key, None, self.request_protocol_id(buf), false)
+            }
+            // The challenge response is encrypted with the key of the connect token that the client sent
+            // in its connection request
+            _ if is_response && self.pending_handshakes.contains_key(&addr) => {
+                let pending = self.pending_handshakes[&addr];
+                (pending.receive_key, None, pending.protocol_id, false)
+            }
+            Some((client_id, conn)) => (
                 // If the packet is not a connection request, use the receive key to decrypt it.
-                self.conn_cache
-                    .clients
-                    .get(&client_id)
-                    .expect("client id not found")
-                    .receive_key,
+                conn.receive_key,
                 self.conn_cache.replay_protection.get_mut(&client_id),
+                conn.protocol_id,
+                true,
             ),
             None => {
                 // Not a connection request packet, and not a known client, so ignore
@@ -778,7 +1418,7 @@ impl<Ctx> NetcodeServer<Ctx> {
         };
         let packet = match Packet::read(
             buf,
-            self.protocol_id,
+            protocol_id,
             now,
             key,
             replay_protection,
@@ -787,14 +1427,29 @@ impl<Ctx> NetcodeServer<Ctx> {
             Ok(packet) => packet,
             Err(Error::Crypto(e)) => {
                 debug!(error = ?e, "server ignored packet because it failed to decrypt.");
+                if established {
+                    self.record_malformed_packet(addr);
+                }
+                return Ok(());
+            }
+            Err(Error::Packet(
+                packet::Error::AlreadyReceived(_)
+                | packet::Error::TokenExpired
+                | packet::Error::BadProtocolId { .. },
+            )) => {
+                // this can happen with honest clients, so it is not suspicious
+                debug!("server ignored packet from {addr}");
                 return Ok(());
             }
             Err(e) => {
                 error!("server ignored packet: {e}");
+                if established {
+                    self.record_malformed_packet(addr);
+                }
                 return Ok(());
             }
         };
-        self.process_packet(addr, packet, sender)
+        self.process_packet(addr, packet, now, sender)
     }
 
     fn recv_packets(
@@ -830,9 +1485,38 @@ impl<Ctx> NetcodeServer<Ctx> {
     pub fn try_update(&mut self, delta_ms: f64, io: &mut Io) -> Result<()> {
         self.time += delta_ms;
         self.conn_cache.update(delta_ms);
+        self.kicked_duplicates.clear();
+        let clients = &self.conn_cache.clients;
+        self.rejected_duplicates.retain(|(client_id, _)| {
+            clients
+                .get(client_id)
+                .is_some_and(|conn| conn.is_connected())
+        });
+        if let Some(limit) = self.cfg.handshake_rate_limit {
+            let time = self.time;
+            self.handshake_windows
+                .retain(|_, window| time - window.start < limit.window_secs);
+        }
+        if let Some(threshold) = self.cfg.suspicion_threshold {
+            let time = self.time;
+            self.suspicion_windows
+                .retain(|_, window| time - window.start < threshold.window_secs);
+        }
         let (sender, receiver) = io.split();
         self.check_for_timeouts();
         self.recv_packets(sender, receiver)?;
+        for ip in std::mem::take(&mut self.suspects_to_disconnect) {
+            let suspects: Vec<_> = self
+                .conn_cache
+                .clients
+                .values()
+                .filter(|conn| conn.addr.ip() == ip)
+                .map(|conn| conn.client_id)
+                .collect();
+            for client_id in suspects {
+                self.disconnect(client_id, io)?;
+            }
+        }
         self.send_packets(io)?;
         Ok(())
     }
@@ -944,6 +1628,23 @@ impl<Ctx> NetcodeServer<Ctx> {
     ///
     /// The server will send a number of redundant disconnect packets to the client, and then remove its connection info.
     pub fn disconnect(&mut self, client_id: ClientId, io: &mut Io) -> Result<()> {
+        self.close_connection(client_id, DisconnectPacket::create, io)
+    }
+
+    /// Rejects a connected client, and tells it why.
+    ///
+    /// Same as [`Server::disconnect`], but the client receives a denied packet with the `reason`
+    /// instead of a disconnect packet.
+    pub fn reject(&mut self, client_id: ClientId, reason: DeniedReason, io: &mut Io) -> Result<()> {
+        self.close_connection(client_id, || DeniedPacket::create(reason.clone()), io)
+    }
+
+    fn close_connection(
+        &mut self,
+        client_id: ClientId,
+        packet: impl Fn() -> Packet<'static>,
+        sender: &mut impl PacketSender,
+    ) -> Result<()> {
         let Some(conn) = self.conn_cache.clients.get_mut(&client_id) else {
             return Ok(());
         };
@@ -954,11 +1655,9 @@ impl<Ctx> NetcodeServer<Ctx> {
         debug!("server disconnecting client {client_id}");
         self.on_disconnect(client_id, addr);
         for _ in 0..self.cfg.num_disconnect_packets {
-            // self.send_to_client(DisconnectPacket::create(), client_id, io)?;
-
             // we do not use ? here because we want to continue even if the send fails
             let _ = self
-                .send_to_client(DisconnectPacket::create(), client_id, io)
+                .send_to_client(packet(), client_id, sender)
                 .inspect_err(|e| {
                     error!("server failed to send disconnect packet: {e}");
                 });
@@ -1011,6 +1710,51 @@ impl<Ctx> NetcodeServer<Ctx> {
             .count()
     }
 
+    /// Returns true if the client has completed the handshake
+    pub fn is_client_connected(&self, client_id: ClientId) -> bool {
+        self.conn_cache
+            .clients
+            .get(&client_id)
+            .is_some_and(|c| c.is_connected())
+    }
+
+    /// Gets the protocol id that clients must use to connect to this server
+    pub fn protocol_id(&self) -> u64 {
+        self.protocol_id
+    }
+
+    /// Gets the protocol id that a client used to connect.
+    ///
+    /// It is either the protocol id of the server or one of its compatible protocol ids.
+    pub fn client_protocol_id(&self, client_id: ClientId) -> Option<u64> {
+        self.conn_cache
+            .clients
+            .get(&client_id)
+            .map(|c| c.protocol_id)
+    }
+
+    /// Gets the user data of the connect token that the client used to connect
+    pub fn user_data(&self, client_id: ClientId) -> Option<[u8; USER_DATA_BYTES]> {
+        self.conn_cache.clients.get(&client_id).map(|c| c.user_data)
+    }
+
+    /// Ban an IP address: the connection requests coming from it are denied with [`DeniedReason::Banned`].
+    ///
+    /// The clients that are already connected from this address are not disconnected.
+    pub fn ban_address(&mut self, ip: IpAddr) {
+        self.banned_addresses.insert(ip);
+    }
+
+    /// Remove an IP address from the ban list
+    pub fn unban_address(&mut self, ip: IpAddr) {
+        self.banned_addresses.remove(&ip);
+    }
+
+    /// Returns true if the IP address is banned
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.banned_addresses.contains(&ip)
+    }
+
     /// Gets the address of a client.
     pub fn client_addr(&self, client_id: ClientId) -> Option<SocketAddr> {
         self.conn_cache.clients.get(&client_id).map(|c| c.addr)
@@ -1024,12 +1768,24 @@ impl<Ctx> NetcodeServer<Ctx> {
 
 pub(crate) mod connection {
     use super::*;
-    use crate::connection::server::ConnectionError;
+    use crate::connection::server::{ConnectionError, ConnectionInfo};
+    use crate::server::events::{DuplicateLoginEvent, SendErrorEvent};
     use core::result::Result;
     #[derive(Default)]
     pub(crate) struct NetcodeServerContext {
         pub(crate) connections: Vec<id::ClientId>,
         pub(crate) disconnections: Vec<id::ClientId>,
+        /// Number of disconnections that were reported during the last update. The ones after them
+        /// were requested between two updates (for example with `disconnect`) and still have to be reported
+        reported_disconnections: usize,
+        pub(crate) throttled: Vec<IpAddr>,
+        pub(crate) protocol_mismatches: Vec<(id::ClientId, Option<u64>)>,
+        pub(crate) suspicious: Vec<(IpAddr, u32, SuspicionAction)>,
+        pub(crate) send_errors: Vec<SendErrorEvent>,
+        /// Number of send errors that were reported during the last update. The ones after them happened
+        /// between two updates (when sending the payloads) and still have to be reported
+        reported_send_errors: usize,
+        pub(crate) duplicate_logins: Vec<DuplicateLoginEvent>,
         sender: Option<ServerNetworkEventSender>,
     }
 
@@ -1049,6 +1805,9 @@ pub(crate) mod connection {
                 .context
                 .sender
                 .clone_from(&io.context.event_sender);
+            // the clients disconnected when the server stopped are not reported after a restart
+            self.server.cfg.context.disconnections.clear();
+            self.server.cfg.context.reported_disconnections = 0;
             self.io = Some(io);
             Ok(())
         }
@@ -1084,6 +1843,22 @@ pub(crate) mod connection {
             }
         }
 
+        fn reject(
+            &mut self,
+            client_id: id::ClientId,
+            reason: DeniedReason,
+        ) -> Result<(), ConnectionError> {
+            match client_id {
+                id::ClientId::Netcode(id) => {
+                    if let Some(io) = self.io.as_mut() {
+                        self.server.reject(id, reason, io)?
+                    }
+                    Ok(())
+                }
+                _ => Err(ConnectionError::InvalidConnectionType),
+            }
+        }
+
         fn connected_client_ids(&self) -> Vec<id::ClientId> {
             self.server
                 .connected_client_ids()
@@ -1091,13 +1866,43 @@ pub(crate) mod connection {
                 .collect()
         }
 
+        fn connection_info(&self, client_id: id::ClientId) -> Option<ConnectionInfo> {
+            let id::ClientId::Netcode(id) = client_id else {
+                return None;
+            };
+            if !self.server.is_client_connected(id) {
+                return None;
+            }
+            Some(ConnectionInfo {
+                transport: self.io_config.transport.kind(),
+                protocol_id: self.server.client_protocol_id(id),
+                user_data: self.server.user_data(id),
+                compression: self.io_config.compression.clone(),
+                // netcode packets are always encrypted after the handshake
+                encrypted: true,
+                mtu: MAX_PACKET_SIZE,
+            })
+        }
+
         fn try_update(&mut self, delta_ms: f64) -> Result<(), ConnectionError> {
             let io = self.io.as_mut().ok_or(ConnectionError::IoNotInitialized)?;
             // reset the new connections/disconnections
-            self.server.cfg.context.connections.clear();
-            self.server.cfg.context.disconnections.clear();
+            let context = &mut self.server.cfg.context;
+            context.connections.clear();
+            context
+                .disconnections
+                .drain(..context.reported_disconnections);
+            context.throttled.clear();
+            context.protocol_mismatches.clear();
+            context.suspicious.clear();
+            context.send_errors.drain(..context.reported_send_errors);
+            context.duplicate_logins.clear();
 
             self.server.try_update(delta_ms, io)?;
+            self.server.cfg.context.reported_disconnections =
+                self.server.cfg.context.disconnections.len();
+            self.server.cfg.context.reported_send_errors =
+                self.server.cfg.context.send_errors.len();
             Ok(())
         }
 
@@ -1124,6 +1929,26 @@ pub(crate) mod connection {
             self.server.cfg.context.disconnections.clone()
         }
 
+        fn new_throttled_sources(&self) -> Vec<IpAddr> {
+            self.server.cfg.context.throttled.clone()
+        }
+
+        fn new_protocol_mismatches(&self) -> Vec<(id::ClientId, Option<u64>)> {
+            self.server.cfg.context.protocol_mismatches.clone()
+        }
+
+        fn new_suspicious_sources(&self) -> Vec<(IpAddr, u32, SuspicionAction)> {
+            self.server.cfg.context.suspicious.clone()
+        }
+
+        fn new_send_errors(&self) -> Vec<SendErrorEvent> {
+            self.server.cfg.context.send_errors.clone()
+        }
+
+        fn new_duplicate_logins(&self) -> Vec<DuplicateLoginEvent> {
+            self.server.cfg.context.duplicate_logins.clone()
+        }
+
         fn io(&self) -> Option<&Io> {
             self.io.as_ref()
         }
@@ -1151,11 +1976,44 @@ pub(crate) mod connection {
                             });
                     }
                     ctx.disconnections.push(id::ClientId::Netcode(id));
+                })
+                .on_throttle(|ip, ctx| {
+                    ctx.throttled.push(ip);
+                })
+                .on_protocol_mismatch(|id, protocol_hash, ctx| {
+                    ctx.protocol_mismatches
+                        .push((id::ClientId::Netcode(id), protocol_hash));
+                })
+                .on_suspicious_activity(|ip, malformed_packets, action, ctx| {
+                    ctx.suspicious.push((ip, malformed_packets, action));
+                })
+                .on_send_error(|id, addr, packet, error, ctx| {
+                    ctx.send_errors.push(SendErrorEvent {
+                        client_id: id::ClientId::Netcode(id),
+                        addr,
+                        packet,
+                        error: error.io_error_kind(),
+                    });
+                })
+                .on_duplicate_login(|id, existing_addr, new_addr, policy, ctx| {
+                    ctx.duplicate_logins.push(DuplicateLoginEvent {
+                        client_id: id::ClientId::Netcode(id),
+                        existing_addr,
+                        new_addr,
+                        policy,
+                    });
                 });
             cfg = cfg.keep_alive_send_rate(config.keep_alive_send_rate);
             cfg = cfg.num_disconnect_packets(config.num_disconnect_packets);
             cfg = cfg.client_timeout_secs(config.client_timeout_secs);
+            cfg = cfg.handshake_rate_limit(config.handshake_rate_limit);
+            cfg = cfg.suspicion_threshold(config.suspicion_threshold);
+            cfg = cfg.compatible_protocol_ids(config.compatible_protocol_ids);
+            cfg = cfg.protocol_hash(config.protocol_hash);
+            cfg = cfg.duplicate_login_policy(config.duplicate_login_policy);
+            cfg = cfg.stateless_handshake(config.stateless_handshake);
             cfg.connection_request_handler = config.connection_request_handler;
+            cfg = cfg.deny_predicate(config.deny_predicate);
             let server = NetcodeServer::with_config(config.protocol_id, config.private_key, cfg)
                 .expect("Could not create server netcode");
 
@@ -1166,6 +2024,32 @@ pub(crate) mod connection {
             }
         }
 
+        /// Ban an IP address: the connection requests coming from it are denied before the handshake proceeds,
+        /// and the clients that are connected from this address are disconnected.
+        pub fn ban_address(&mut self, ip: IpAddr) -> Result<(), ConnectionError> {
+            self.server.ban_address(ip);
+            let Some(io) = self.io.as_mut() else {
+                return Ok(());
+            };
+            let banned_clients: Vec<_> = self
+                .server
+                .conn_cache
+                .clients
+                .values()
+                .filter(|conn| conn.addr.ip() == ip)
+                .map(|conn| conn.client_id)
+                .collect();
+            for client_id in banned_clients {
+                self.server.disconnect(client_id, io)?;
+            }
+            Ok(())
+        }
+
+        /// Remove an IP address from the ban list
+        pub fn unban_address(&mut self, ip: IpAddr) {
+            self.server.unban_address(ip);
+        }
+
         /// Disconnect a client from the server
         /// (also adds the client_id to the list of newly disconnected clients)
         pub(crate) fn disconnect_by_addr(
@@ -1179,3 +2063,640 @@ pub(crate) mod connection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::netcode::{generate_key, utils};
+    use crate::transport::error::Result as TransportResult;
+
+    #[derive(Default)]
+    struct RecordSender(Vec<(Vec<u8>, SocketAddr)>);
+
+    impl PacketSender for RecordSender {
+        fn send(&mut self, payload: &[u8], address: &SocketAddr) -> TransportResult<()> {
+            self.0.push((payload.to_vec(), *address));
+            Ok(())
+        }
+    }
+
+    /// The standard netcode challenge token is used by default, and the server keeps the connection
+    /// data until the client answers the challenge from the same address
+    #[test]
+    fn test_handshake() {
+        check_handshake(false, ChallengeToken::SIZE);
+    }
+
+    /// With a stateless handshake, the server only stores the key of the handshake until the client
+    /// answers the challenge from the same address
+    #[test]
+    fn test_stateless_handshake() {
+        check_handshake(true, StatelessChallengeToken::SIZE);
+    }
+
+    /// Run a handshake and check that the challenge is smaller than the request
+    fn check_handshake(stateless: bool, challenge_token_size: usize) {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let spoofed_addr = SocketAddr::from(([127, 0, 0, 1], 2000));
+        let cfg = ServerConfig::default().stateless_handshake(stateless);
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+        let mut sender = RecordSender::default();
+        let token = ConnectToken::build("127.0.0.1:5000", protocol_id, 1, private_key)
+            .generate()
+            .unwrap();
+
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let request_size = RequestPacket::create(
+            token.protocol_id,
+            token.expire_timestamp,
+            token.nonce,
+            token.private_data,
+            None,
+        )
+        .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+        .unwrap();
+        server
+            .recv_packet(
+                &mut buf[..request_size],
+                utils::now(),
+                client_addr,
+                &mut sender,
+            )
+            .unwrap();
+        assert!(server.conn_cache.clients.is_empty());
+        assert_eq!(server.pending_handshakes.len(), 1);
+        assert_eq!(
+            server.pending_handshakes[&client_addr].connection.is_none(),
+            stateless
+        );
+        let (mut challenge, addr) = sender.0.pop().unwrap();
+        assert_eq!(addr, client_addr);
+        assert!(challenge.len() < request_size);
+
+        let Packet::Challenge(challenge) = Packet::read(
+            &mut challenge,
+            protocol_id,
+            utils::now(),
+            token.server_to_client_key,
+            None,
+            0xff,
+        )
+        .unwrap() else {
+            panic!("expected a challenge packet");
+        };
+        assert_eq!(challenge.token.len(), challenge_token_size);
+        let response = ResponsePacket::create(challenge.sequence, challenge.token);
+        let response_size = response
+            .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+            .unwrap();
+        // the response is encrypted like the other packets
+        assert_eq!(Packet::get_prefix(buf[0]), (1, Packet::RESPONSE));
+
+        // the response is ignored if it comes from another address
+        let mut spoofed = buf;
+        server
+            .recv_packet(
+                &mut spoofed[..response_size],
+                utils::now(),
+                spoofed_addr,
+                &mut sender,
+            )
+            .unwrap();
+        assert!(server.conn_cache.clients.is_empty());
+        assert!(sender.0.is_empty());
+
+        // the response is ignored once the token has expired
+        let mut expired = buf;
+        server
+            .recv_packet(
+                &mut expired[..response_size],
+                token.expire_timestamp,
+                client_addr,
+                &mut sender,
+            )
+            .unwrap();
+        assert!(server.conn_cache.clients.is_empty());
+
+        server
+            .recv_packet(
+                &mut buf[..response_size],
+                utils::now(),
+                client_addr,
+                &mut sender,
+            )
+            .unwrap();
+        assert_eq!(server.num_connected_clients(), 1);
+        assert!(server.conn_cache.find_by_addr(&client_addr).is_some());
+        assert!(server.pending_handshakes.is_empty());
+    }
+
+    /// The handshake packets above the rate limit are dropped, and the throttle callback
+    /// is called once per window
+    #[test]
+    fn test_handshake_rate_limit() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let other_addr = SocketAddr::from(([127, 0, 0, 2], 1000));
+        let cfg = ServerConfig::with_context(Vec::new())
+            .handshake_rate_limit(Some(HandshakeRateLimit {
+                max_packets: 2,
+                window_secs: 1.0,
+            }))
+            .on_throttle(|ip, ctx: &mut Vec<IpAddr>| ctx.push(ip));
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+        let mut sender = RecordSender::default();
+
+        let mut send_request = |server: &mut NetcodeServer<Vec<IpAddr>>, addr: SocketAddr| {
+            // a connect token can only be used from a single address
+            let token =
+                ConnectToken::build("127.0.0.1:5000", protocol_id, rand::random(), private_key)
+                    .generate()
+                    .unwrap();
+            let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+            let request_size = RequestPacket::create(
+                token.protocol_id,
+                token.expire_timestamp,
+                token.nonce,
+                token.private_data,
+                None,
+            )
+            .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+            .unwrap();
+            server
+                .recv_packet(&mut buf[..request_size], utils::now(), addr, &mut sender)
+                .unwrap();
+            std::mem::take(&mut sender.0).len()
+        };
+
+        assert_eq!(send_request(&mut server, client_addr), 1);
+        assert_eq!(send_request(&mut server, client_addr), 1);
+        // the requests above the limit are not answered
+        assert_eq!(send_request(&mut server, client_addr), 0);
+        assert_eq!(send_request(&mut server, client_addr), 0);
+        assert_eq!(server.cfg.context, vec![client_addr.ip()]);
+        // other addresses are not affected
+        assert_eq!(send_request(&mut server, other_addr), 1);
+
+        // the limit is reset in the next window
+        server.time += 1.0;
+        assert_eq!(send_request(&mut server, client_addr), 1);
+        assert_eq!(server.cfg.context, vec![client_addr.ip()]);
+    }
+
+    /// Build a connection request with a fresh connect token
+    fn connection_request(protocol_id: u64, private_key: Key) -> (ConnectToken, Vec<u8>) {
+        let token = ConnectToken::build("127.0.0.1:5000", protocol_id, rand::random(), private_key)
+            .generate()
+            .unwrap();
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size = RequestPacket::create(
+            token.protocol_id,
+            token.expire_timestamp,
+            token.nonce,
+            token.private_data,
+            None,
+        )
+        .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+        .unwrap();
+        (token, buf[..size].to_vec())
+    }
+
+    /// Send a connection request to the server and return the reason of the denial, if any.
+    ///
+    /// Panics if the server doesn't answer
+    fn denied_reason<Ctx>(
+        server: &mut NetcodeServer<Ctx>,
+        addr: SocketAddr,
+        protocol_id: u64,
+        private_key: Key,
+    ) -> Option<DeniedReason> {
+        let mut sender = RecordSender::default();
+        let (token, mut request) = connection_request(protocol_id, private_key);
+        server
+            .recv_packet(&mut request, utils::now(), addr, &mut sender)
+            .unwrap();
+        let (mut answer, _) = sender.0.pop().expect("the server should answer");
+        match Packet::read(
+            &mut answer,
+            protocol_id,
+            utils::now(),
+            token.server_to_client_key,
+            None,
+            0xff,
+        )
+        .unwrap()
+        {
+            Packet::Denied(denied) => Some(denied.reason),
+            Packet::Challenge(_) => None,
+            _ => panic!("unexpected packet"),
+        }
+    }
+
+    /// The address is banned once it sent enough malformed packets, and the callback is called once
+    #[test]
+    fn test_suspicion_threshold() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let cfg = ServerConfig::with_context(Vec::new())
+            .suspicion_threshold(Some(SuspicionThreshold {
+                max_malformed_packets: 3,
+                window_secs: 1.0,
+                action: SuspicionAction::Ban,
+            }))
+            .on_suspicious_activity(
+                |ip, count, action, ctx: &mut Vec<(IpAddr, u32, SuspicionAction)>| {
+                    ctx.push((ip, count, action))
+                },
+            );
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+        let mut sender = RecordSender::default();
+
+        // the handshake packets can be spoofed, so they are not counted
+        for _ in 0..5 {
+            let mut buf = [0u8; 1078];
+            buf[0] = Packet::REQUEST;
+            server
+                .recv_packet(&mut buf, utils::now(), client_addr, &mut sender)
+                .unwrap();
+        }
+        assert!(!server.is_banned(client_addr.ip()));
+
+        let (_, denied, _) = login(
+            &mut server,
+            7,
+            client_addr,
+            protocol_id,
+            private_key,
+            &mut sender,
+        );
+        assert_eq!(denied, None);
+        // a payload packet that cannot be decrypted, sent to an established connection
+        let mut send_garbage = |server: &mut NetcodeServer<_>| {
+            let mut buf = [0u8; 64];
+            buf[0] = (1 << 4) | Packet::PAYLOAD;
+            server
+                .recv_packet(&mut buf, utils::now(), client_addr, &mut sender)
+                .unwrap();
+        };
+        send_garbage(&mut server);
+        send_garbage(&mut server);
+        assert!(!server.is_banned(client_addr.ip()));
+        assert!(server.cfg.context.is_empty());
+        send_garbage(&mut server);
+        assert!(server.is_banned(client_addr.ip()));
+        send_garbage(&mut server);
+        assert_eq!(
+            server.cfg.context,
+            vec![(client_addr.ip(), 3, SuspicionAction::Ban)]
+        );
+    }
+
+    #[test]
+    fn test_ban_address() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let mut server = NetcodeServer::new(protocol_id, private_key).unwrap();
+
+        server.ban_address(client_addr.ip());
+        assert!(server.is_banned(client_addr.ip()));
+        assert_eq!(
+            denied_reason(&mut server, client_addr, protocol_id, private_key),
+            Some(DeniedReason::Banned)
+        );
+        // the ban applies to every port of the address
+        assert_eq!(
+            denied_reason(
+                &mut server,
+                SocketAddr::from(([127, 0, 0, 1], 2000)),
+                protocol_id,
+                private_key
+            ),
+            Some(DeniedReason::Banned)
+        );
+        // nothing is allocated for the banned client
+        assert!(server.conn_cache.clients.is_empty());
+        assert!(server.token_entries.inner.is_empty());
+
+        server.unban_address(client_addr.ip());
+        assert_eq!(
+            denied_reason(&mut server, client_addr, protocol_id, private_key),
+            None
+        );
+    }
+
+    #[derive(Debug)]
+    struct DenyPort(u16);
+
+    impl DenyPredicate for DenyPort {
+        fn deny(&self, addr: SocketAddr) -> Option<DeniedReason> {
+            (addr.port() == self.0).then(|| DeniedReason::Custom("port".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_deny_predicate() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let cfg = ServerConfig::default().deny_predicate(Some(Arc::new(DenyPort(1000))));
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+
+        assert_eq!(
+            denied_reason(
+                &mut server,
+                SocketAddr::from(([127, 0, 0, 1], 1000)),
+                protocol_id,
+                private_key
+            ),
+            Some(DeniedReason::Custom("port".to_string()))
+        );
+        assert_eq!(
+            denied_reason(
+                &mut server,
+                SocketAddr::from(([127, 0, 0, 1], 2000)),
+                protocol_id,
+                private_key
+            ),
+            None
+        );
+    }
+
+    struct FailingSender;
+
+    impl PacketSender for FailingSender {
+        fn send(&mut self, _: &[u8], _: &SocketAddr) -> TransportResult<()> {
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into())
+        }
+    }
+
+    /// A failed send is reported to the callback with the client and the kind of packet
+    #[test]
+    fn test_send_error_callback() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let cfg = ServerConfig::with_context(Vec::new()).on_send_error(
+            |id, addr, packet, error, ctx: &mut Vec<(ClientId, SocketAddr, SentPacketKind, _)>| {
+                ctx.push((id, addr, packet, error.io_error_kind()))
+            },
+        );
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+        let mut sender = RecordSender::default();
+
+        // connect the client
+        let (token, mut request) = connection_request(protocol_id, private_key);
+        server
+            .recv_packet(&mut request, utils::now(), client_addr, &mut sender)
+            .unwrap();
+        let (mut challenge, _) = sender.0.pop().unwrap();
+        let Packet::Challenge(challenge) = Packet::read(
+            &mut challenge,
+            protocol_id,
+            utils::now(),
+            token.server_to_client_key,
+            None,
+            0xff,
+        )
+        .unwrap() else {
+            panic!("expected a challenge packet");
+        };
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size = ResponsePacket::create(challenge.sequence, challenge.token)
+            .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+            .unwrap();
+        server
+            .recv_packet(&mut buf[..size], utils::now(), client_addr, &mut sender)
+            .unwrap();
+        let (id, _) = server.conn_cache.find_by_addr(&client_addr).unwrap();
+
+        assert!(server
+            .send_to_client(PayloadPacket::create(&[1, 2, 3]), id, &mut FailingSender)
+            .is_err());
+        assert_eq!(
+            server.cfg.context,
+            vec![(
+                id,
+                client_addr,
+                SentPacketKind::Payload,
+                std::io::ErrorKind::ConnectionRefused
+            )]
+        );
+        // the client is still connected, it's up to the user to disconnect it
+        assert!(server.conn_cache.find_by_id(id).unwrap().is_connected());
+    }
+
+    /// A failed send during the handshake is also reported to the callback
+    #[test]
+    fn test_handshake_send_error_callback() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let cfg = ServerConfig::with_context(Vec::new()).on_send_error(
+            |id, addr, packet, _, ctx: &mut Vec<(ClientId, SocketAddr, SentPacketKind)>| {
+                ctx.push((id, addr, packet))
+            },
+        );
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+
+        let token = ConnectToken::build("127.0.0.1:5000", protocol_id, 7, private_key)
+            .generate()
+            .unwrap();
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size = RequestPacket::create(
+            token.protocol_id,
+            token.expire_timestamp,
+            token.nonce,
+            token.private_data,
+            None,
+        )
+        .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+        .unwrap();
+        assert!(server
+            .recv_packet(
+                &mut buf[..size],
+                utils::now(),
+                client_addr,
+                &mut FailingSender
+            )
+            .is_err());
+        assert_eq!(
+            server.cfg.context,
+            vec![(7, client_addr, SentPacketKind::Challenge)]
+        );
+    }
+
+    /// Connect a client with the id `client_id` from `addr`, answering the challenge like a client would
+    /// (the response is resent until the server answers it).
+    ///
+    /// Returns the token of the client, the reason of the denial, if any, and the number of updates that the
+    /// server took to answer the response
+    fn login<Ctx>(
+        server: &mut NetcodeServer<Ctx>,
+        client_id: ClientId,
+        addr: SocketAddr,
+        protocol_id: u64,
+        private_key: Key,
+        sender: &mut RecordSender,
+    ) -> (ConnectToken, Option<DeniedReason>, usize) {
+        let token = ConnectToken::build("127.0.0.1:5000", protocol_id, client_id, private_key)
+            .generate()
+            .unwrap();
+        fn read(packet: &mut [u8], protocol_id: u64, key: Key) -> Packet<'_> {
+            Packet::read(packet, protocol_id, utils::now(), key, None, 0xff).unwrap()
+        }
+        let mut buf = [0u8; MAX_PKT_BUF_SIZE];
+        let size = RequestPacket::create(
+            token.protocol_id,
+            token.expire_timestamp,
+            token.nonce,
+            token.private_data,
+            None,
+        )
+        .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+        .unwrap();
+        server
+            .recv_packet(&mut buf[..size], utils::now(), addr, sender)
+            .unwrap();
+        let (mut answer, _) = sender.0.pop().expect("the server should answer");
+        let challenge = match read(&mut answer, protocol_id, token.server_to_client_key) {
+            Packet::Denied(denied) => return (token, Some(denied.reason), 0),
+            Packet::Challenge(challenge) => challenge,
+            _ => panic!("unexpected packet"),
+        };
+        let response = ResponsePacket::create(challenge.sequence, challenge.token);
+        // the client sends its response twice during each update, until it is answered
+        for update in 1..=2 {
+            for _ in 0..2 {
+                let size = response
+                    .write(&mut buf, 0, &token.client_to_server_key, protocol_id)
+                    .unwrap();
+                server
+                    .recv_packet(&mut buf[..size], utils::now(), addr, sender)
+                    .unwrap();
+                let Some(index) = sender.0.iter().position(|(_, to)| *to == addr) else {
+                    continue;
+                };
+                let (mut answer, _) = sender.0.remove(index);
+                return match read(&mut answer, protocol_id, token.server_to_client_key) {
+                    Packet::Denied(denied) => (token, Some(denied.reason), update),
+                    Packet::KeepAlive(_) => (token, None, update),
+                    _ => panic!("unexpected packet"),
+                };
+            }
+            server.kicked_duplicates.clear();
+        }
+        panic!("the server should answer the challenge response");
+    }
+
+    #[test]
+    fn test_duplicate_login_reject_new() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let first_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let second_addr = SocketAddr::from(([127, 0, 0, 2], 1000));
+        let cfg = ServerConfig::with_context(Vec::new()).on_duplicate_login(
+            |id, existing, new, policy, ctx: &mut Vec<_>| ctx.push((id, existing, new, policy)),
+        );
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+        let mut sender = RecordSender::default();
+
+        let (_, denied, _) = login(
+            &mut server,
+            7,
+            first_addr,
+            protocol_id,
+            private_key,
+            &mut sender,
+        );
+        assert_eq!(denied, None);
+        let (_, denied, _) = login(
+            &mut server,
+            7,
+            second_addr,
+            protocol_id,
+            private_key,
+            &mut sender,
+        );
+        assert_eq!(denied, Some(DeniedReason::AlreadyConnected));
+        // the retries of the denied client are not reported again
+        let (_, denied, _) = login(
+            &mut server,
+            7,
+            second_addr,
+            protocol_id,
+            private_key,
+            &mut sender,
+        );
+        assert_eq!(denied, Some(DeniedReason::AlreadyConnected));
+
+        assert_eq!(server.conn_cache.find_by_id(7).unwrap().addr, first_addr);
+        assert_eq!(
+            server.cfg.context,
+            vec![(7, first_addr, second_addr, DuplicateLoginPolicy::RejectNew)]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_login_kick_old() {
+        let protocol_id = 1;
+        let private_key = generate_key();
+        let first_addr = SocketAddr::from(([127, 0, 0, 1], 1000));
+        let second_addr = SocketAddr::from(([127, 0, 0, 2], 1000));
+        let cfg = ServerConfig::with_context(Vec::new())
+            .duplicate_login_policy(DuplicateLoginPolicy::KickOld)
+            .num_disconnect_packets(1)
+            .on_duplicate_login(|id, existing, new, policy, ctx: &mut Vec<_>| {
+                ctx.push((id, existing, new, policy))
+            });
+        let mut server = NetcodeServer::with_config(protocol_id, private_key, cfg).unwrap();
+        let mut sender = RecordSender::default();
+
+        let (first_token, denied, _) = login(
+            &mut server,
+            7,
+            first_addr,
+            protocol_id,
+            private_key,
+            &mut sender,
+        );
+        assert_eq!(denied, None);
+        let (_, denied, updates) = login(
+            &mut server,
+            7,
+            second_addr,
+            protocol_id,
+            private_key,
+            &mut sender,
+        );
+        assert_eq!(denied, None);
+        // the new client is only connected during the update after the one where the existing client is kicked
+        assert_eq!(updates, 2);
+
+        // the new client replaced the existing one
+        assert_eq!(server.num_connected_clients(), 1);
+        assert_eq!(server.conn_cache.find_by_id(7).unwrap().addr, second_addr);
+        assert!(server.conn_cache.find_by_addr(&first_addr).is_none());
+        let (mut kicked, addr) = sender.0.pop().unwrap();
+        assert_eq!(addr, first_addr);
+        let Packet::Denied(denied) = Packet::read(
+            &mut kicked,
+            protocol_id,
+            utils::now(),
+            first_token.server_to_client_key,
+            None,
+            0xff,
+        )
+        .unwrap() else {
+            panic!("expected a denied packet");
+        };
+        assert_eq!(denied.reason, DeniedReason::LoggedInElsewhere);
+        assert_eq!(
+            server.cfg.context,
+            vec![(7, first_addr, second_addr, DuplicateLoginPolicy::KickOld)]
+        );
+    }
+}
@@ -225,6 +225,10 @@ impl Bytes for ConnectTokenPrivate {
     }
 }
 
+/// The token sent by the server in the challenge packet, and echoed by the client in the response packet,
+/// in the format of the standard netcode protocol.
+///
+/// The server keeps the rest of the connection data until the client answers the challenge.
 pub struct ChallengeToken {
     pub client_id: u64,
     pub user_data: [u8; USER_DATA_BYTES],
@@ -271,6 +275,108 @@ impl Bytes for ChallengeToken {
     }
 }
 
+/// The challenge token used by the servers with a
+/// [stateless handshake](crate::connection::netcode::ServerConfig::stateless_handshake).
+///
+/// It contains everything the server needs to create the connection, so that the server doesn't keep the
+/// connection data of a client until the client proves that it can receive packets at its address. <br>
+/// It is bigger than the [`ChallengeToken`] of the standard netcode protocol, so only the lightyear clients
+/// can answer it.
+#[derive(Clone, Copy)]
+pub struct StatelessChallengeToken {
+    pub client_id: u64,
+    pub user_data: [u8; USER_DATA_BYTES],
+    pub timeout_seconds: i32,
+    pub client_to_server_key: Key,
+    pub server_to_client_key: Key,
+    /// Timestamp (in seconds since the unix epoch) after which the token is not accepted anymore
+    pub expire_timestamp: u64,
+    /// Protocol id used by the client in the connection request
+    pub protocol_id: u64,
+}
+
+impl StatelessChallengeToken {
+    pub const SIZE: usize = 384;
+    /// Encrypt the token. The token can only be decrypted for a response sent from `client_addr`.
+    pub fn encrypt(
+        &self,
+        sequence: u64,
+        client_addr: SocketAddr,
+        private_key: &Key,
+    ) -> Result<[u8; Self::SIZE], Error> {
+        let mut buf = [0u8; Self::SIZE]; // NOTE: token buffer needs 16-bytes overhead for auth tag
+        let mut cursor = io::Cursor::new(&mut buf[..]);
+        self.write_to(&mut cursor)?;
+        let aead = Self::aead(client_addr);
+        crypto::chacha_encrypt(&mut buf, Some(&aead), sequence, private_key)?;
+        Ok(buf)
+    }
+
+    pub fn decrypt(
+        encrypted: &mut [u8; Self::SIZE],
+        sequence: u64,
+        client_addr: SocketAddr,
+        private_key: &Key,
+    ) -> Result<Self, Error> {
+        let aead = Self::aead(client_addr);
+        crypto::chacha_decrypt(encrypted, Some(&aead), sequence, private_key)?;
+        let mut cursor = io::Cursor::new(&encrypted[..]);
+        Ok(Self::read_from(&mut cursor)?)
+    }
+
+    /// The address of the client is used as associated data
+    fn aead(client_addr: SocketAddr) -> Vec<u8> {
+        let mut aead = match client_addr.ip() {
+            std::net::IpAddr::V4(ip) => ip.octets().to_vec(),
+            std::net::IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        aead.extend_from_slice(&client_addr.port().to_le_bytes());
+        aead
+    }
+}
+
+impl Bytes for StatelessChallengeToken {
+    const SIZE: usize = size_of::<u64>()
+        + USER_DATA_BYTES
+        + size_of::<i32>()
+        + PRIVATE_KEY_BYTES * 2
+        + size_of::<u64>()
+        + size_of::<u64>();
+    type Error = io::Error;
+    fn write_to(&self, buf: &mut impl io::Write) -> Result<(), io::Error> {
+        buf.write_u64::<LittleEndian>(self.client_id)?;
+        buf.write_all(&self.user_data)?;
+        buf.write_i32::<LittleEndian>(self.timeout_seconds)?;
+        buf.write_all(&self.client_to_server_key)?;
+        buf.write_all(&self.server_to_client_key)?;
+        buf.write_u64::<LittleEndian>(self.expire_timestamp)?;
+        buf.write_u64::<LittleEndian>(self.protocol_id)?;
+        Ok(())
+    }
+
+    fn read_from(reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
+        let client_id = reader.read_u64::<LittleEndian>()?;
+        let mut user_data = [0; USER_DATA_BYTES];
+        reader.read_exact(&mut user_data)?;
+        let timeout_seconds = reader.read_i32::<LittleEndian>()?;
+        let mut client_to_server_key = [0; PRIVATE_KEY_BYTES];
+        reader.read_exact(&mut client_to_server_key)?;
+        let mut server_to_client_key = [0; PRIVATE_KEY_BYTES];
+        reader.read_exact(&mut server_to_client_key)?;
+        let expire_timestamp = reader.read_u64::<LittleEndian>()?;
+        let protocol_id = reader.read_u64::<LittleEndian>()?;
+        Ok(Self {
+            client_id,
+            user_data,
+            timeout_seconds,
+            client_to_server_key,
+            server_to_client_key,
+            expire_timestamp,
+            protocol_id,
+        })
+    }
+}
+
 /// A token containing all the information required for a client to connect to a server.
 ///
 /// The token should be provided to the client by some out-of-band method, such as a web service or a game server browser. <br>
@@ -601,6 +707,55 @@ mod tests {
         assert_eq!(challenge_token.user_data, user_data);
     }
 
+    #[test]
+    fn encrypt_decrypt_stateless_challenge_token() {
+        let private_key = crypto::generate_key();
+        let sequence = 1;
+        let client_id = 2;
+        let user_data = [0x11; USER_DATA_BYTES];
+
+        let client_to_server_key = crypto::generate_key();
+        let client_addr = SocketAddr::from(([127, 0, 0, 1], 1));
+
+        let challenge_token = StatelessChallengeToken {
+            client_id,
+            user_data,
+            timeout_seconds: 5,
+            client_to_server_key,
+            server_to_client_key: crypto::generate_key(),
+            expire_timestamp: 3,
+            protocol_id: 4,
+        };
+
+        let encrypted = challenge_token
+            .encrypt(sequence, client_addr, &private_key)
+            .unwrap();
+
+        // the token cannot be used from another address
+        assert!(StatelessChallengeToken::decrypt(
+            &mut encrypted.clone(),
+            sequence,
+            SocketAddr::from(([127, 0, 0, 1], 2)),
+            &private_key
+        )
+        .is_err());
+
+        let challenge_token = StatelessChallengeToken::decrypt(
+            &mut encrypted.clone(),
+            sequence,
+            client_addr,
+            &private_key,
+        )
+        .unwrap();
+
+        assert_eq!(challenge_token.client_id, client_id);
+        assert_eq!(challenge_token.user_data, user_data);
+        assert_eq!(challenge_token.timeout_seconds, 5);
+        assert_eq!(challenge_token.client_to_server_key, client_to_server_key);
+        assert_eq!(challenge_token.expire_timestamp, 3);
+        assert_eq!(challenge_token.protocol_id, 4);
+    }
+
     #[test]
     fn connect_token_read_write() {
         let private_key = crypto::generate_key();
@@ -1,13 +1,16 @@
-use bevy::prelude::Resource;
-use bevy::utils::HashMap;
+use bevy::prelude::{Resource, Timer};
+use bevy::utils::{Duration, HashMap};
 use enum_dispatch::enum_dispatch;
 #[cfg(all(feature = "steam", not(target_family = "wasm")))]
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use tracing::{debug, error};
 
 use crate::connection::id::ClientId;
+use crate::connection::netcode::{SuspicionAction, USER_DATA_BYTES};
 #[cfg(all(feature = "steam", not(target_family = "wasm")))]
 use crate::connection::steam::{server::SteamConfig, steamworks_client::SteamworksClient};
 use crate::packet::packet_builder::RecvPayload;
@@ -15,10 +18,15 @@ use crate::prelude::server::ServerTransport;
 #[cfg(all(feature = "steam", not(target_family = "wasm")))]
 use crate::prelude::LinkConditionerConfig;
 use crate::server::config::NetcodeConfig;
+use crate::server::events::{DuplicateLoginEvent, SendErrorEvent};
 use crate::server::io::Io;
 use crate::transport::config::SharedIoConfig;
+use crate::transport::middleware::compression::CompressionConfig;
 
-/// Reasons for denying a connection request
+/// Reasons for denying a connection request.
+///
+/// The reason is sent to the client, which receives it in the
+/// [`DisconnectEvent`](crate::prelude::client::DisconnectEvent) as [`DisconnectReason::Denied`](crate::connection::client::DisconnectReason::Denied)
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum DeniedReason {
     ServerFull,
@@ -27,6 +35,21 @@ pub enum DeniedReason {
     AlreadyConnected,
     TokenAlreadyUsed,
     InvalidToken,
+    /// The client uses a version of the game that is not compatible with the server
+    BadVersion,
+    /// The client could not be authenticated
+    AuthFailed,
+    /// The client was not accepted before the end of the
+    /// [authentication timeout](crate::prelude::server::AuthConfig::timeout)
+    AuthTimedOut,
+    /// The client registered different channels, components or messages than the server,
+    /// so they cannot deserialize each other's packets
+    ProtocolMismatch,
+    /// Another client connected with the same id, and the
+    /// [`DuplicateLoginPolicy`](crate::connection::netcode::DuplicateLoginPolicy) disconnected this one
+    LoggedInElsewhere,
+    /// The client is banned from the server until the given Unix timestamp (in seconds)
+    BannedUntil(u64),
     Custom(String),
 }
 
@@ -48,6 +71,47 @@ impl ConnectionRequestHandler for DefaultConnectionRequestHandler {
     }
 }
 
+/// Trait for rejecting clients based on their address, before the handshake proceeds.
+///
+/// It is consulted before the server allocates anything for the client, so it can be used
+/// to reject abusive hosts cheaply.
+pub trait DenyPredicate: Debug + Send + Sync {
+    /// Returns Some(reason) if the connection requests coming from `addr` must be denied.
+    fn deny(&self, addr: SocketAddr) -> Option<DeniedReason>;
+}
+
+/// Kind of transport used by a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransportKind {
+    UdpSocket,
+    WebTransport,
+    WebSocket,
+    Channels,
+    Steam,
+    Dummy,
+}
+
+/// Parameters of a connection that were negotiated during the handshake.
+///
+/// Can be used by the application to adapt the content it sends to the quality of the link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    /// Transport used by the connection
+    pub transport: TransportKind,
+    /// Protocol id that the client used to connect.
+    /// None if the connection type does not use a protocol id (for example steam)
+    pub protocol_id: Option<u64>,
+    /// User data of the connect token that the client used to connect.
+    /// None if the connection type does not use connect tokens (for example steam)
+    pub user_data: Option<[u8; USER_DATA_BYTES]>,
+    /// Compression applied to the packets of the connection
+    pub compression: CompressionConfig,
+    /// True if the packets are encrypted
+    pub encrypted: bool,
+    /// Maximum size (in bytes) of a packet sent over the connection
+    pub mtu: usize,
+}
+
 #[enum_dispatch]
 pub trait NetServer: Send + Sync {
     /// Start the server
@@ -64,9 +128,27 @@ pub trait NetServer: Send + Sync {
     /// Is also responsible for adding the client to the list of new disconnections.
     fn disconnect(&mut self, client_id: ClientId) -> Result<(), ConnectionError>;
 
+    /// Disconnect a specific client, and send it the reason why it was rejected.
+    ///
+    /// The client receives the reason in a [`RejectEvent`](crate::prelude::client::RejectEvent).
+    /// By default, the client is disconnected without a reason.
+    fn reject(&mut self, client_id: ClientId, reason: DeniedReason) -> Result<(), ConnectionError> {
+        let _ = reason;
+        self.disconnect(client_id)
+    }
+
     /// Return the list of connected clients
     fn connected_client_ids(&self) -> Vec<ClientId>;
 
+    /// Return the negotiated parameters of the connection with a client,
+    /// or None if the client is not connected.
+    ///
+    /// By default, the parameters are not available.
+    fn connection_info(&self, client_id: ClientId) -> Option<ConnectionInfo> {
+        let _ = client_id;
+        None
+    }
+
     /// Update the connection states + internal bookkeeping (keep-alives, etc.)
     fn try_update(&mut self, delta_ms: f64) -> Result<(), ConnectionError>;
 
@@ -80,6 +162,35 @@ pub trait NetServer: Send + Sync {
 
     fn new_disconnections(&self) -> Vec<ClientId>;
 
+    /// Return the IP addresses whose handshake packets started being dropped
+    /// because of the handshake rate limit during the last update
+    fn new_throttled_sources(&self) -> Vec<IpAddr> {
+        vec![]
+    }
+
+    /// Return the clients whose connection request was denied during the last update because they use
+    /// a different protocol than the server, along with the hash of their protocol (if they sent one)
+    fn new_protocol_mismatches(&self) -> Vec<(ClientId, Option<u64>)> {
+        vec![]
+    }
+
+    /// Return the IP addresses that reached the suspicion threshold during the last update, along with
+    /// the number of malformed packets they sent and the action that was taken against them
+    fn new_suspicious_sources(&self) -> Vec<(IpAddr, u32, SuspicionAction)> {
+        vec![]
+    }
+
+    /// Return the packets that could not be sent to the clients since the last update
+    fn new_send_errors(&self) -> Vec<SendErrorEvent> {
+        vec![]
+    }
+
+    /// Return the clients that connected with the id of a client that was already connected
+    /// during the last update
+    fn new_duplicate_logins(&self) -> Vec<DuplicateLoginEvent> {
+        vec![]
+    }
+
     fn io(&self) -> Option<&Io>;
 
     fn io_mut(&mut self) -> Option<&mut Io>;
@@ -168,8 +279,11 @@ impl NetConfig {
 
 type ServerConnectionIdx = usize;
 
-// TODO: add a way to get the server of a given type?
 /// On the server we allow the use of multiple types of ServerConnection at the same time
+/// (for example a UDP socket for native clients and a WebTransport server for browser clients,
+/// by providing several [`NetConfig`]s in the [`ServerConfig`](crate::prelude::server::ServerConfig)).
+/// Clients connected through any of them share the same world.
+///
 /// This resource holds the list of all the [`ServerConnection`]s, and maps client ids to the index of the server connection in the list
 #[derive(Resource)]
 pub struct ServerConnections {
@@ -177,6 +291,12 @@ pub struct ServerConnections {
     pub servers: Vec<ServerConnection>,
     /// Mapping from the connection's [`ClientId`] into the index of the [`ServerConnection`] in the `servers` list
     pub(crate) client_server_map: HashMap<ClientId, ServerConnectionIdx>,
+    /// Maximum time that a new client can wait for authentication. If None, clients are accepted as soon as they connect
+    pub(crate) auth_timeout: Option<Duration>,
+    /// Clients that are connected but were not yet accepted or rejected, with the time left to authenticate them
+    pub(crate) pending_auth: HashMap<ClientId, Timer>,
+    /// Clients that were accepted since the last update
+    pub(crate) accepted: Vec<ClientId>,
     /// Track whether the server is ready to listen to incoming connections
     is_listening: bool,
 }
@@ -191,10 +311,22 @@ impl ServerConnections {
         ServerConnections {
             servers,
             client_server_map: HashMap::default(),
+            auth_timeout: None,
+            pending_auth: HashMap::default(),
+            accepted: vec![],
             is_listening: false,
         }
     }
 
+    /// Keep the new clients in a pending state until they are accepted with [`accept_connection`](Self::accept_connection)
+    /// or rejected with [`reject_connection`](Self::reject_connection).
+    ///
+    /// The clients that are still pending after `timeout` are rejected with [`DeniedReason::AuthTimedOut`].
+    pub fn with_auth_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.auth_timeout = timeout;
+        self
+    }
+
     /// Start listening for client connections on all internal servers
     pub fn start(&mut self) -> Result<(), ConnectionError> {
         for server in &mut self.servers {
@@ -209,10 +341,44 @@ impl ServerConnections {
         for server in &mut self.servers {
             server.stop()?;
         }
+        self.pending_auth.clear();
+        self.accepted.clear();
         self.is_listening = false;
         Ok(())
     }
 
+    /// Accept a client that is waiting for authentication.
+    ///
+    /// The client is added to the server on the next update, which emits a
+    /// [`ConnectEvent`](crate::prelude::server::ConnectEvent).
+    pub fn accept_connection(&mut self, client_id: ClientId) -> Result<(), ConnectionError> {
+        self.pending_auth
+            .remove(&client_id)
+            .ok_or(ConnectionError::ConnectionNotFound)?;
+        self.accepted.push(client_id);
+        Ok(())
+    }
+
+    /// Returns true if the client is connected but was not yet accepted or rejected
+    pub fn is_pending_auth(&self, client_id: ClientId) -> bool {
+        self.pending_auth.contains_key(&client_id)
+    }
+
+    /// Reject the pending clients that were not authenticated in time
+    pub(crate) fn update_pending_auth(&mut self, delta: Duration) {
+        let timed_out: Vec<_> = self
+            .pending_auth
+            .iter_mut()
+            .filter_map(|(client_id, timer)| timer.tick(delta).finished().then_some(*client_id))
+            .collect();
+        for client_id in timed_out {
+            debug!(?client_id, "Client was not authenticated in time");
+            let _ = self
+                .reject_connection(client_id, DeniedReason::AuthTimedOut)
+                .inspect_err(|e| error!("Could not reject client {client_id:?}: {e:?}"));
+        }
+    }
+
     /// Disconnect a specific client
     pub fn disconnect(&mut self, client_id: ClientId) -> Result<(), ConnectionError> {
         self.client_server_map.get(&client_id).map_or(
@@ -227,6 +393,43 @@ impl ServerConnections {
         )
     }
 
+    /// Reject a specific client: it is disconnected, and receives the `reason` in a
+    /// [`RejectEvent`](crate::prelude::client::RejectEvent) instead of a generic disconnection.
+    ///
+    /// This is useful to refuse a client after it connected, for example if the password that it sent
+    /// in a message was wrong, or to refuse a client that is waiting for authentication.
+    pub fn reject_connection(
+        &mut self,
+        client_id: ClientId,
+        reason: DeniedReason,
+    ) -> Result<(), ConnectionError> {
+        self.pending_auth.remove(&client_id);
+        self.client_server_map
+            .get(&client_id)
+            .map_or(Err(ConnectionError::ConnectionNotFound), |&server_idx| {
+                self.servers[server_idx].reject(client_id, reason)
+            })
+    }
+
+    /// Get the negotiated parameters of the connection with a specific client
+    pub fn connection_info(&self, client_id: ClientId) -> Option<ConnectionInfo> {
+        self.client_server_map
+            .get(&client_id)
+            .and_then(|&server_idx| self.servers[server_idx].connection_info(client_id))
+    }
+
+    /// Index in [`servers`](ServerConnections::servers) of the [`ServerConnection`] that a client is connected through
+    pub fn server_idx(&self, client_id: ClientId) -> Option<usize> {
+        self.client_server_map.get(&client_id).copied()
+    }
+
+    /// Local addresses of the [`ServerConnection`]s that use an [`Io`]
+    pub fn local_addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.servers
+            .iter()
+            .filter_map(|server| server.io().map(|io| io.local_addr()))
+    }
+
     /// Returns true if the server is currently listening for client packets
     pub(crate) fn is_listening(&self) -> bool {
         self.is_listening
@@ -256,3 +459,140 @@ pub enum ConnectionError {
     #[cfg(all(feature = "steam", not(target_family = "wasm")))]
     SteamError(#[from] steamworks::SteamError),
 }
+
+impl ConnectionError {
+    /// True if a packet could not be sent by the transport, in which case the failure was already
+    /// emitted as a [`SendErrorEvent`]
+    pub(crate) fn is_send_error(&self) -> bool {
+        matches!(
+            self,
+            ConnectionError::Netcode(super::netcode::error::Error::Transport(_))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{EventReader, ResMut, State, Update};
+
+    use crate::client::networking::NetworkingState;
+    use crate::prelude::client::RejectEvent;
+    use crate::prelude::ClientId;
+    use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1, TEST_CLIENT_ID_2};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    /// A server that only implements the required methods
+    struct MinimalServer;
+
+    impl NetServer for MinimalServer {
+        fn start(&mut self) -> Result<(), ConnectionError> {
+            Ok(())
+        }
+        fn stop(&mut self) -> Result<(), ConnectionError> {
+            Ok(())
+        }
+        fn disconnect(&mut self, _: ClientId) -> Result<(), ConnectionError> {
+            Ok(())
+        }
+        fn connected_client_ids(&self) -> Vec<ClientId> {
+            vec![ClientId::Netcode(0)]
+        }
+        fn try_update(&mut self, _: f64) -> Result<(), ConnectionError> {
+            Ok(())
+        }
+        fn recv(&mut self) -> Option<(RecvPayload, ClientId)> {
+            None
+        }
+        fn send(&mut self, _: &[u8], _: ClientId) -> Result<(), ConnectionError> {
+            Ok(())
+        }
+        fn new_connections(&self) -> Vec<ClientId> {
+            vec![]
+        }
+        fn new_disconnections(&self) -> Vec<ClientId> {
+            vec![]
+        }
+        fn io(&self) -> Option<&Io> {
+            None
+        }
+        fn io_mut(&mut self) -> Option<&mut Io> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_default_connection_info() {
+        assert!(MinimalServer
+            .connection_info(ClientId::Netcode(0))
+            .is_none());
+    }
+
+    /// Clients connected through different server connections are handled by the same server
+    #[test]
+    fn test_multiple_server_connections() {
+        let stepper = MultiBevyStepper::default();
+        let server_connections = stepper.server_app.world().resource::<ServerConnections>();
+        assert_eq!(server_connections.local_addrs().count(), 2);
+        let server_1 = server_connections.server_idx(ClientId::Netcode(TEST_CLIENT_ID_1));
+        let server_2 = server_connections.server_idx(ClientId::Netcode(TEST_CLIENT_ID_2));
+        assert!(server_1.is_some() && server_2.is_some());
+        assert_ne!(server_1, server_2);
+        assert_eq!(
+            server_connections
+                .connection_info(ClientId::Netcode(TEST_CLIENT_ID_1))
+                .map(|info| info.transport),
+            Some(TransportKind::Channels)
+        );
+    }
+
+    #[derive(bevy::prelude::Resource, Default)]
+    struct Rejections(Vec<DeniedReason>);
+
+    /// A connected client that is rejected receives the reason in a `RejectEvent`
+    #[test]
+    fn test_reject_connection() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<Rejections>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<RejectEvent>, mut rejections: ResMut<Rejections>| {
+                rejections
+                    .0
+                    .extend(events.read().map(|event| event.reason.clone()));
+            },
+        );
+
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConnections>()
+            .reject_connection(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                DeniedReason::Custom("wrong password".to_string()),
+            )
+            .unwrap();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<Rejections>().0,
+            vec![DeniedReason::Custom("wrong password".to_string())]
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<crate::prelude::server::ConnectionManager>()
+            .connection(ClientId::Netcode(TEST_CLIENT_ID))
+            .is_err());
+    }
+}
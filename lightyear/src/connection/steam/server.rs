@@ -1,11 +1,13 @@
 use crate::connection::id::ClientId;
 use crate::connection::netcode::MAX_PACKET_SIZE;
 use crate::connection::server::{
-    ConnectionError, ConnectionRequestHandler, DefaultConnectionRequestHandler, NetServer,
+    ConnectionError, ConnectionInfo, ConnectionRequestHandler, DefaultConnectionRequestHandler,
+    DeniedReason, NetServer, TransportKind,
 };
 use crate::packet::packet_builder::RecvPayload;
 use crate::prelude::LinkConditionerConfig;
 use crate::server::io::Io;
+use crate::transport::middleware::compression::CompressionConfig;
 use bevy::utils::HashMap;
 use parking_lot::RwLock;
 use std::collections::VecDeque;
@@ -181,6 +183,23 @@ impl NetServer for Server {
         }
     }
 
+    fn reject(&mut self, client_id: ClientId, reason: DeniedReason) -> Result<(), ConnectionError> {
+        match client_id {
+            ClientId::Steam(id) => {
+                if let Some(connection) = self.connections.remove(&client_id) {
+                    let _ = connection.close(
+                        NetConnectionEnd::AppGeneric,
+                        Some(&format!("{reason:?}")),
+                        true,
+                    );
+                    self.new_disconnections.push(client_id);
+                }
+                Ok(())
+            }
+            _ => Err(ConnectionError::InvalidConnectionType),
+        }
+    }
+
     fn connected_client_ids(&self) -> Vec<ClientId> {
         self.connections.keys().cloned().collect()
     }
@@ -296,6 +315,21 @@ impl NetServer for Server {
         self.new_disconnections.clone()
     }
 
+    fn connection_info(&self, client_id: ClientId) -> Option<ConnectionInfo> {
+        if !self.connections.contains_key(&client_id) {
+            return None;
+        }
+        Some(ConnectionInfo {
+            transport: TransportKind::Steam,
+            protocol_id: None,
+            user_data: None,
+            compression: CompressionConfig::None,
+            // steam networking sockets are always encrypted
+            encrypted: true,
+            mtu: MAX_PACKET_SIZE,
+        })
+    }
+
     fn io(&self) -> Option<&Io> {
         None
     }
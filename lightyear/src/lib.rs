@@ -186,8 +186,9 @@ pub mod prelude {
 
     pub use crate::channel::builder::{
         Channel, ChannelBuilder, ChannelContainer, ChannelDirection, ChannelMode, ChannelSettings,
-        InputChannel, ReliableSettings,
+        InputChannel, ReliableSettings, TelemetryChannel,
     };
+    pub use crate::channel::stats::retransmission::RetransmissionStats;
     pub use crate::client::prediction::prespawn::PreSpawnedPlayerObject;
     pub use crate::connection::id::ClientId;
     pub use crate::connection::netcode::{generate_key, ConnectToken, Key};
@@ -195,18 +196,22 @@ pub mod prelude {
     pub use crate::inputs::leafwing::{input_message::InputMessage, LeafwingUserAction};
     pub use crate::inputs::native::UserAction;
     pub use crate::packet::error::PacketError;
-    pub use crate::packet::message::Message;
+    pub use crate::packet::header::AckBitfieldSize;
+    pub use crate::packet::message::{Message, MessageId};
     pub use crate::protocol::channel::{AppChannelExt, ChannelKind, ChannelRegistry};
     pub use crate::protocol::component::{AppComponentExt, ComponentRegistry, Linear};
-    pub use crate::protocol::message::{AppMessageExt, MessageRegistry};
+    pub use crate::protocol::message::{AppMessageExt, MessageKind, MessageRegistry, TraceId};
+    pub use crate::protocol::plugin::AppProtocolExt;
     pub use crate::protocol::serialize::AppSerializeExt;
     pub use crate::shared::config::{Mode, SharedConfig};
+    pub use crate::shared::events::components::EventStamp;
     #[cfg(feature = "leafwing")]
     pub use crate::shared::input::leafwing::LeafwingInputPlugin;
     pub use crate::shared::input::native::InputPlugin;
     pub use crate::shared::ping::manager::PingConfig;
     pub use crate::shared::plugin::{NetworkIdentity, SharedPlugin};
     pub use crate::shared::replication::authority::HasAuthority;
+    pub use crate::shared::replication::baseline::{Baseline, StaticBaseline};
     pub use crate::shared::replication::components::{
         DeltaCompression, DisabledComponent, NetworkRelevanceMode, OverrideTargetComponent,
         PrePredicted, ReplicateHierarchy, ReplicateOnceComponent, Replicated, Replicating,
@@ -217,15 +222,20 @@ pub mod prelude {
     pub use crate::shared::replication::network_target::NetworkTarget;
     pub use crate::shared::replication::plugin::ReplicationConfig;
     pub use crate::shared::replication::plugin::SendUpdatesMode;
+    pub use crate::shared::replication::plugin::UpdateRedundancyConfig;
     pub use crate::shared::replication::resources::{
         ReplicateResourceExt, ReplicateResourceMetadata, StopReplicateResourceExt,
     };
+    pub use crate::shared::replication::stable::{StableEntityMap, StableId};
+    pub use crate::shared::rpc::{Request, RequestId, ResponseHandle, RpcError};
     pub use crate::shared::run_conditions::*;
     pub use crate::shared::sets::{FixedUpdateSet, MainSet};
     pub use crate::shared::tick_manager::TickManager;
     pub use crate::shared::tick_manager::{Tick, TickConfig};
     pub use crate::shared::time_manager::TimeManager;
     pub use crate::transport::middleware::compression::CompressionConfig;
+    #[cfg(feature = "zstd")]
+    pub use crate::transport::middleware::compression::ZstdDictionary;
     pub use crate::transport::middleware::conditioner::LinkConditionerConfig;
 
     mod rename {
@@ -255,15 +265,19 @@ pub mod prelude {
 
     pub mod client {
         pub use crate::client::components::{
-            ComponentSyncMode, Confirmed, LerpFn, SyncComponent, SyncMetadata,
+            ComponentArrival, ComponentSyncMode, Confirmed, LerpFn, ReplicationTicks,
+            SyncComponent, SyncMetadata,
         };
         pub use crate::client::config::{ClientConfig, NetcodeConfig, PacketConfig};
         pub use crate::client::connection::ConnectionManager;
         pub use crate::client::error::ClientError;
         pub use crate::client::events::{
             ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            ConnectionPhaseChanged, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent,
+            InputEvent, MessageEvent, RawDatagramEvent, RejectEvent, ReplicationAppliedEvent,
+            RequestEvent, ResponseEvent,
         };
+        pub use crate::client::idle::IdleWarningEvent;
         #[cfg(feature = "leafwing")]
         pub use crate::client::input::leafwing::LeafwingInputConfig;
         pub use crate::client::input::native::{InputConfig, InputManager};
@@ -276,6 +290,7 @@ pub mod prelude {
         };
         pub use crate::client::io::config::ClientTransport;
         pub use crate::client::io::Io;
+        pub use crate::client::local_write::LocalWriteCommandsExt;
         pub use crate::client::networking::{ClientCommands, NetworkingState};
         pub use crate::client::plugin::ClientPlugins;
         pub use crate::client::prediction::correction::Correction;
@@ -284,46 +299,95 @@ pub mod prelude {
         pub use crate::client::prediction::plugin::{PredictionConfig, PredictionSet};
         pub use crate::client::prediction::rollback::{Rollback, RollbackState};
         pub use crate::client::prediction::Predicted;
+        pub use crate::client::queue::{QueueAdmittedEvent, QueuePositionEvent};
+        pub use crate::client::redirect::{RedirectConfig, RedirectEvent};
         pub use crate::client::replication::commands::DespawnReplicationCommandExt;
         pub use crate::client::replication::send::{Replicate, ReplicateToServer};
+        pub use crate::client::restart::{RestartConfig, RestartNoticeEvent};
         pub use crate::client::run_conditions::{is_connected, is_disconnected, is_synced};
+        pub use crate::client::shutdown::ShutdownNoticeEvent;
+        pub use crate::client::staleness::{
+            EntityRefreshedEvent, EntityStaleEvent, Stale, StalenessConfig,
+        };
         pub use crate::client::sync::SyncConfig;
+        pub use crate::client::transient::Transient;
         pub use crate::connection::client::{
-            Authentication, ClientConnection, IoConfig, NetClient, NetConfig,
+            Authentication, ClientConnection, ConnectionPhase, IoConfig, NetClient, NetConfig,
         };
         #[cfg(all(feature = "steam", not(target_family = "wasm")))]
         pub use crate::connection::steam::client::{SocketConfig, SteamConfig};
+        pub use crate::shared::replication::local_write::LocalWrites;
     }
     pub mod server {
         #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
         pub use wtransport::tls::Identity;
 
+        pub use crate::connection::netcode::{
+            DuplicateLoginPolicy, HandshakeRateLimit, SuspicionAction, SuspicionThreshold,
+        };
         pub use crate::connection::server::{
-            IoConfig, NetConfig, NetServer, ServerConnection, ServerConnections,
+            ConnectionInfo, IoConfig, NetConfig, NetServer, ServerConnection, ServerConnections,
+            TransportKind,
         };
         #[cfg(all(feature = "steam", not(target_family = "wasm")))]
         pub use crate::connection::steam::server::{SocketConfig, SteamConfig};
         pub use crate::server::clients::ControlledEntities;
-        pub use crate::server::config::{NetcodeConfig, PacketConfig, ServerConfig};
+        pub use crate::server::compatibility::ProtocolShim;
+        pub use crate::server::config::{AuthConfig, NetcodeConfig, PacketConfig, ServerConfig};
         pub use crate::server::connection::ConnectionManager;
+        pub use crate::server::diagnostics::{FrameBudgetReport, ServerDiagnosticsPlugin};
+        pub use crate::server::divergence::{DivergenceEvent, DivergenceKind, DivergencePlugin};
         pub use crate::server::error::ServerError;
+        pub use crate::server::error_events::{
+            ErrorEventConfig, ErrorSeverity, ServerErrorEvent, ServerErrors,
+        };
         pub use crate::server::events::{
-            ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            AuthRequestEvent, ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent,
+            ConnectEvent, DisconnectEvent, DuplicateLoginEvent, EntityDespawnEvent,
+            EntitySpawnEvent, HandshakeThrottledEvent, InputEvent, MessageDeliveredEvent,
+            MessageDroppedEvent, MessageEvent, ProtocolMismatchEvent, RawDatagramEvent,
+            ReplicationAppliedEvent, RequestEvent, ResponseEvent, SendErrorEvent,
+            SentPacketKind, SuspiciousActivityEvent,
         };
+        pub use crate::server::idle::{IdleKickConfig, IdleKickEvent};
+        pub use crate::server::input::{InputHook, InputVerdict};
         pub use crate::server::io::config::ServerTransport;
         pub use crate::server::io::Io;
         pub use crate::server::networking::{NetworkingState, ServerCommands};
+        pub use crate::server::pacing::RenderRatePacing;
+        pub use crate::server::pause::ServerPausedEvent;
         pub use crate::server::plugin::ServerPlugins;
-        pub use crate::server::relevance::immediate::RelevanceManager;
-        pub use crate::server::relevance::room::{RoomId, RoomManager};
+        pub use crate::server::pool::{EntityPool, PoolCommandsExt, Pooled};
+        pub use crate::server::queue::{
+            ClientQueuedEvent, ConnectionLimit, QueueSlotOpenedEvent, ServerFullPolicy,
+        };
+        pub use crate::server::relevance::distance::{
+            DistanceRelevancePlugin, RelevanceBand, RelevanceViewer,
+        };
+        pub use crate::server::relevance::immediate::{RelevanceManager, RelevanceQuery};
+        pub use crate::server::relevance::limit::{EntitiesHeldOutEvent, EntityLimitConfig};
+        pub use crate::server::relevance::room::{RoomId, RoomManager, RoomSilentEvent};
         pub use crate::server::replication::commands::AuthorityCommandExt;
         pub use crate::server::replication::commands::DespawnReplicationCommandExt;
+        pub use crate::server::replication::commands::PauseReplicationCommandExt;
         pub use crate::server::replication::{
-            send::{ControlledBy, Lifetime, Replicate, ServerFilter, SyncTarget},
+            send::{
+                ControlledBy, DefaultSyncTarget, Lifetime, Replicate, ReplicationPaused,
+                ServerFilter, SyncTarget,
+            },
             ReplicationSet, ServerReplicationSet,
         };
         pub use crate::server::run_conditions::{is_started, is_stopped};
+        pub use crate::server::session::{SessionResumedEvent, SessionResumptionConfig};
+        pub use crate::server::shard::ShardConfig;
+        pub use crate::server::snapshot::{EntitySnapshot, RoomSnapshot};
+        pub use crate::server::spectator::{SpectatorFrame, SpectatorMirror, SpectatorStreams};
+        pub use crate::server::tenant::{
+            TenantBudget, TenantBudgetKind, TenantId, TenantManager, TenantOverBudgetEvent,
+            TenantStats,
+        };
+        pub use crate::server::transient::ReplicateTransient;
+        pub use crate::server::world_view::{ClientWorldView, ComponentView, EntityView};
         pub use crate::shared::replication::authority::AuthorityPeer;
     }
 
@@ -351,6 +415,11 @@ pub mod shared;
 #[cfg(test)]
 pub(crate) mod tests;
 
+#[cfg(all(test, feature = "alloc_audit"))]
+#[global_allocator]
+static ALLOCATOR: shared::alloc_audit::CountingAllocator<std::alloc::System> =
+    shared::alloc_audit::CountingAllocator::new(std::alloc::System);
+
 /// Provides an abstraction over an unreliable transport
 pub mod transport;
 /// Extra utilities
@@ -12,4 +12,13 @@ pub enum PacketError {
     ChannelNotFound,
     #[error("receiver channel error: {0}")]
     ChannelReceiveError(#[from] ChannelReceiveError),
+    #[error("could not compress message: {0}")]
+    Compression(#[from] crate::transport::error::Error),
+    #[error(
+        "the kind of a raw datagram must be at most {}, got {0}",
+        crate::packet::raw::MAX_RAW_DATAGRAM_KIND
+    )]
+    InvalidRawDatagramKind(u8),
+    #[error("raw datagram of {0} bytes does not fit in a packet")]
+    RawDatagramTooLarge(usize),
 }
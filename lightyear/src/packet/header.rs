@@ -1,3 +1,4 @@
+use bevy::prelude::Reflect;
 use bevy::utils::HashMap;
 use byteorder::NetworkEndian;
 use byteorder::ReadBytesExt;
@@ -24,46 +25,89 @@ pub(crate) struct PacketHeader {
     pub(crate) packet_id: PacketId,
     /// Last ack-ed packet id received by the sender
     last_ack_packet_id: PacketId,
-    /// Bitfield of the last 32 packet ids before `ack_id`
+    /// Bitfield of the last 32 (or more, see [`AckBitfieldSize`]) packet ids before `ack_id`
     /// (this means that in total we send acks for 33 packet-ids)
     /// See more information at: [GafferOnGames](https://gafferongames.com/post/reliability_ordering_and_congestion_avoidance_over_udp/)
-    ack_bitfield: u32,
+    ack_bitfield: u128,
+    /// Number of bits of `ack_bitfield` that are written in the header
+    ack_bitfield_size: AckBitfieldSize,
     /// Current tick
     pub(crate) tick: Tick,
+    /// Flags of the fields that are truncated to their low byte
+    truncated: u8,
+}
+
+/// Bits of the first byte of the header that contain the packet type
+const PACKET_TYPE_MASK: u8 = 0b11;
+/// Flag set in the first byte of the header when the packet id is truncated
+const TRUNCATED_PACKET_ID: u8 = 1 << 2;
+/// Flag set in the first byte of the header when the ack id is truncated
+const TRUNCATED_ACK_ID: u8 = 1 << 3;
+/// Flag set in the first byte of the header when the tick is truncated
+const TRUNCATED_TICK: u8 = 1 << 6;
+
+/// A truncated field is decoded as the closest value to a reference, so the value must be less than
+/// this distance from any reference that the receiver can have
+const MAX_TRUNCATED_DIFF: i16 = 128;
+/// The ticks are not in the same order as the packet ids, so they keep a margin for the packets that are
+/// sent after this one but received before it
+const MAX_TRUNCATED_TICK_DIFF: u16 = 64;
+
+/// Returns the closest value to `reference` whose low byte is `low`
+fn untruncate(reference: u16, low: u8) -> u16 {
+    reference.wrapping_add_signed(low.wrapping_sub(reference as u8) as i8 as i16)
 }
 
 impl ToBytes for PacketHeader {
     fn len(&self) -> usize {
-        11
+        1 + self.field_len(TRUNCATED_PACKET_ID)
+            + self.field_len(TRUNCATED_ACK_ID)
+            + 4 * self.ack_bitfield_size.words() as usize
+            + self.field_len(TRUNCATED_TICK)
     }
 
     fn to_bytes<T: byteorder::WriteBytesExt>(
         &self,
         buffer: &mut T,
     ) -> Result<(), SerializationError> {
-        buffer.write_u8(self.packet_type as u8)?;
-        buffer.write_u16::<NetworkEndian>(self.packet_id.0)?;
-        buffer.write_u16::<NetworkEndian>(self.last_ack_packet_id.0)?;
-        buffer.write_u32::<NetworkEndian>(self.ack_bitfield)?;
-        buffer.write_u16::<NetworkEndian>(self.tick.0)?;
+        // the upper bits of the packet type byte contain the number of additional ack bitfield words,
+        // so that the default header is unchanged, and the flags of the truncated fields
+        let extra_words = self.ack_bitfield_size.words() - 1;
+        buffer.write_u8(self.packet_type as u8 | (extra_words << 4) | self.truncated)?;
+        self.write_field(TRUNCATED_PACKET_ID, self.packet_id.0, buffer)?;
+        self.write_field(TRUNCATED_ACK_ID, self.last_ack_packet_id.0, buffer)?;
+        for word in 0..self.ack_bitfield_size.words() {
+            buffer.write_u32::<NetworkEndian>((self.ack_bitfield >> (32 * word)) as u32)?;
+        }
+        self.write_field(TRUNCATED_TICK, self.tick.0, buffer)?;
         Ok(())
     }
 
+    /// Read the header. The truncated fields only contain their low byte, until they are recovered by
+    /// [`PacketHeaderManager::process_recv_packet_header`]
     fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
     where
         Self: Sized,
     {
         let packet_type = buffer.read_u8()?;
-        let packet_id = buffer.read_u16::<NetworkEndian>()?;
-        let last_ack_packet_id = buffer.read_u16::<NetworkEndian>()?;
-        let ack_bitfield = buffer.read_u32::<NetworkEndian>()?;
-        let tick = buffer.read_u16::<NetworkEndian>()?;
+        let ack_bitfield_size = AckBitfieldSize::from_words(((packet_type >> 4) & 0b11) + 1)
+            .ok_or(SerializationError::InvalidPacketType)?;
+        let truncated = packet_type & (TRUNCATED_PACKET_ID | TRUNCATED_ACK_ID | TRUNCATED_TICK);
+        let packet_id = Self::read_field(truncated, TRUNCATED_PACKET_ID, buffer)?;
+        let last_ack_packet_id = Self::read_field(truncated, TRUNCATED_ACK_ID, buffer)?;
+        let mut ack_bitfield = 0;
+        for word in 0..ack_bitfield_size.words() {
+            ack_bitfield |= (buffer.read_u32::<NetworkEndian>()? as u128) << (32 * word);
+        }
+        let tick = Self::read_field(truncated, TRUNCATED_TICK, buffer)?;
         Ok(Self {
-            packet_type: PacketType::try_from(packet_type)?,
+            packet_type: PacketType::try_from(packet_type & PACKET_TYPE_MASK)?,
             packet_id: PacketId(packet_id),
             last_ack_packet_id: PacketId(last_ack_packet_id),
             ack_bitfield,
+            ack_bitfield_size,
             tick: Tick(tick),
+            truncated,
         })
     }
 }
@@ -74,17 +118,91 @@ impl PacketHeader {
     ///
     /// i is 0-indexed. So 0 represents the first bit of the bitfield (starting from the right)
     fn get_bitfield_bit(&self, i: u8) -> bool {
-        debug_assert!(i < ACK_BITFIELD_SIZE);
+        debug_assert!(i < self.ack_bitfield_size.bits());
         self.ack_bitfield & (1 << i) != 0
     }
 
     pub fn get_packet_type(&self) -> PacketType {
         self.packet_type
     }
+
+    fn field_len(&self, flag: u8) -> usize {
+        if self.truncated & flag != 0 {
+            1
+        } else {
+            2
+        }
+    }
+
+    fn write_field<T: byteorder::WriteBytesExt>(
+        &self,
+        flag: u8,
+        value: u16,
+        buffer: &mut T,
+    ) -> Result<(), SerializationError> {
+        if self.truncated & flag != 0 {
+            buffer.write_u8(value as u8)?;
+        } else {
+            buffer.write_u16::<NetworkEndian>(value)?;
+        }
+        Ok(())
+    }
+
+    /// Read a field, which only contains the low byte if it is truncated
+    fn read_field(truncated: u8, flag: u8, buffer: &mut Reader) -> Result<u16, SerializationError> {
+        if truncated & flag != 0 {
+            Ok(buffer.read_u8()? as u16)
+        } else {
+            Ok(buffer.read_u16::<NetworkEndian>()?)
+        }
+    }
+}
+
+/// Number of packet ids before the last received packet id that are acked in each packet header.
+///
+/// A deeper window lets the remote know about packets that were received a while ago,
+/// which avoids spurious retransmits for high-tick-rate or high-loss connections, at the cost
+/// of a bigger header. The header describes its own window size, so the client and server don't need
+/// to use the same value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum AckBitfieldSize {
+    /// Ack the last 32 packets (4 bytes per header)
+    #[default]
+    Bits32,
+    /// Ack the last 64 packets (8 bytes per header)
+    Bits64,
+    /// Ack the last 128 packets (16 bytes per header)
+    Bits128,
+}
+
+impl AckBitfieldSize {
+    /// Number of packet ids that are acked in the bitfield
+    pub fn bits(self) -> u8 {
+        self.words() * 32
+    }
+
+    /// Number of u32 words used to write the bitfield
+    fn words(self) -> u8 {
+        match self {
+            AckBitfieldSize::Bits32 => 1,
+            AckBitfieldSize::Bits64 => 2,
+            AckBitfieldSize::Bits128 => 4,
+        }
+    }
+
+    fn from_words(words: u8) -> Option<Self> {
+        match words {
+            1 => Some(AckBitfieldSize::Bits32),
+            2 => Some(AckBitfieldSize::Bits64),
+            4 => Some(AckBitfieldSize::Bits128),
+            _ => None,
+        }
+    }
 }
 
-// we can only send acks for the last 32 packets ids before the last received packet
-const ACK_BITFIELD_SIZE: u8 = 32;
+// we keep track of the last 128 packets ids before the last received packet, which is the widest
+// ack bitfield that we can send
+const MAX_ACK_BITFIELD_SIZE: u8 = 128;
 // we can only buffer up to `MAX_SEND_PACKET_QUEUE_SIZE` packets for sending
 const MAX_SEND_PACKET_QUEUE_SIZE: u8 = 255;
 
@@ -95,9 +213,23 @@ const MIN_NACK_MILLIS: i64 = 10;
 /// maximum number of seconds after which we consider a packet lost
 const MAX_NACK_SECONDS: i64 = 3;
 
+/// Fields of the header of a sent packet, that the remote knows once the packet is acked
+#[derive(Debug, Default, Clone, Copy)]
+struct SentHeader {
+    tick: Tick,
+    last_ack_packet_id: Option<PacketId>,
+}
+
 /// Keeps track of sent and received packets to be able to write the packet headers correctly
 /// For more information: [GafferOnGames](https://gafferongames.com/post/reliability_ordering_and_congestion_avoidance_over_udp/)
-#[derive(Default, Debug)]
+///
+/// The packet id, the ack id and the tick of the header are truncated to their low byte when the receiver
+/// can recover them from a value that it already knows:
+/// - the packet id from the id of the last packet it received, which is at least our most recent acked packet
+/// - the tick from the tick of the last packet it received
+/// - the ack id from the most recent ack id that it received, which is at least the ack id of our most recent
+///   acked packet
+#[derive(Debug)]
 pub struct PacketHeaderManager {
     // Local packet id which we'll bump each time we send a new packet over the network.
     // (we always increment the packet_id, even when we resend a lost packet)
@@ -106,6 +238,10 @@ pub struct PacketHeaderManager {
     // so we can resend them when dropped
     // sent_packets_not_acked: HashSet<PacketId>,
     sent_packets_not_acked: HashMap<PacketId, WrappedTime>,
+    /// Most recent of our packets that was acked by the remote
+    last_acked_packet_id: Option<PacketId>,
+    /// Headers of the last 256 packets we sent, indexed by the low byte of their packet id
+    sent_headers: Box<[SentHeader; 256]>,
     stats_manager: PacketStatsManager,
 
     // channel to notify the sender of the packet_id of the packets that were delivered
@@ -113,8 +249,14 @@ pub struct PacketHeaderManager {
     // ack_notification_receiver: Receiver<PacketId>,
 
     // keep track of the packets that were received (last packet received and the
-    // `ACK_BITFIELD_SIZE` packets before that)
+    // `MAX_ACK_BITFIELD_SIZE` packets before that)
     recv_buffer: ReceiveBuffer,
+    /// Tick of the most recent packet received
+    last_recv_tick: Option<Tick>,
+    /// Most recent ack id received from the remote
+    last_recv_ack_packet_id: Option<PacketId>,
+    /// Number of packet ids that we ack in the headers we send
+    ack_bitfield_size: AckBitfieldSize,
     // copy of current time so that we don't pollute the function signatures to much
     current_time: WrappedTime,
     /// After how many multiples of RTT do we consider a packet to be lost?
@@ -125,7 +267,7 @@ pub struct PacketHeaderManager {
 }
 
 impl PacketHeaderManager {
-    pub(crate) fn new(nack_rtt_multiple: f32) -> Self {
+    pub(crate) fn new(nack_rtt_multiple: f32, ack_bitfield_size: AckBitfieldSize) -> Self {
         // let (ack_notification_sender, ack_notification_receiver) =
         //     crossbeam::channel::bounded(MAX_SEND_PACKET_QUEUE_SIZE as usize);
         Self {
@@ -133,7 +275,12 @@ impl PacketHeaderManager {
             stats_manager: PacketStatsManager::default(),
             // sent_packets_not_acked: HashSet::with_capacity(MAX_SEND_PACKET_QUEUE_SIZE as usize),
             sent_packets_not_acked: HashMap::new(),
+            last_acked_packet_id: None,
+            sent_headers: Box::new([SentHeader::default(); 256]),
             recv_buffer: ReceiveBuffer::new(),
+            last_recv_tick: None,
+            last_recv_ack_packet_id: None,
+            ack_bitfield_size,
             // ack_notification_sender,
             // ack_notification_receiver,
             current_time: WrappedTime::default(),
@@ -141,6 +288,11 @@ impl PacketHeaderManager {
         }
     }
 
+    /// Ratio of the sent packets that were lost recently
+    pub(crate) fn packet_loss(&self) -> f32 {
+        self.stats_manager.packet_loss()
+    }
+
     /// Internal bookkeeping.
     /// Returns a list of packets that are considered NACKed (i.e. acknowledged as losts)
     pub(crate) fn update(
@@ -190,13 +342,30 @@ impl PacketHeaderManager {
         self.next_packet_id = PacketId(self.next_packet_id.wrapping_add(1));
     }
 
-    /// Process the header of a received packet (update ack metadata)
+    /// Process the header of a received packet (recover the truncated fields and update ack metadata)
     ///
     /// Returns the list of packets that have been newly acked by the remote
-    pub(crate) fn process_recv_packet_header(&mut self, header: &PacketHeader) -> Vec<PacketId> {
+    pub(crate) fn process_recv_packet_header(
+        &mut self,
+        header: &mut PacketHeader,
+    ) -> Result<Vec<PacketId>, SerializationError> {
+        self.recover_truncated_fields(header)?;
         // update the receive buffer
         self.stats_manager.received_packet();
+        if self
+            .recv_buffer
+            .last_recv_packet_id
+            .map_or(true, |last| header.packet_id > last)
+        {
+            self.last_recv_tick = Some(header.tick);
+        }
         self.recv_buffer.recv_packet(header.packet_id);
+        if self
+            .last_recv_ack_packet_id
+            .map_or(true, |last| header.last_ack_packet_id > last)
+        {
+            self.last_recv_ack_packet_id = Some(header.last_ack_packet_id);
+        }
 
         let mut newly_acked_packets = Vec::new();
 
@@ -205,8 +374,11 @@ impl PacketHeaderManager {
         if let Some(packet) = self.update_sent_packets_not_acked(&header.last_ack_packet_id) {
             self.stats_manager.sent_packet_acked();
             newly_acked_packets.push(packet);
+            if self.last_acked_packet_id.map_or(true, |last| packet > last) {
+                self.last_acked_packet_id = Some(packet);
+            }
         }
-        for i in 1..=ACK_BITFIELD_SIZE {
+        for i in 1..=header.ack_bitfield_size.bits() {
             let packet_id = PacketId(header.last_ack_packet_id.wrapping_sub(i as u16));
             if header.get_bitfield_bit(i - 1) {
                 if let Some(packet) = self.update_sent_packets_not_acked(&packet_id) {
@@ -215,7 +387,66 @@ impl PacketHeaderManager {
                 }
             }
         }
-        newly_acked_packets
+        Ok(newly_acked_packets)
+    }
+
+    /// Replace the truncated fields of the header with the closest values to the ones we know
+    fn recover_truncated_fields(
+        &self,
+        header: &mut PacketHeader,
+    ) -> Result<(), SerializationError> {
+        let recover = |flag: u8, value: u16, reference: Option<u16>| {
+            if header.truncated & flag == 0 {
+                return Ok(value);
+            }
+            // the remote only truncates the fields once we acked one of its packets
+            let reference = reference.ok_or(SerializationError::InvalidValue)?;
+            Ok::<_, SerializationError>(untruncate(reference, value as u8))
+        };
+        header.packet_id = PacketId(recover(
+            TRUNCATED_PACKET_ID,
+            header.packet_id.0,
+            self.recv_buffer.last_recv_packet_id.map(|id| id.0),
+        )?);
+        header.last_ack_packet_id = PacketId(recover(
+            TRUNCATED_ACK_ID,
+            header.last_ack_packet_id.0,
+            self.last_recv_ack_packet_id.map(|id| id.0),
+        )?);
+        header.tick = Tick(recover(
+            TRUNCATED_TICK,
+            header.tick.0,
+            self.last_recv_tick.map(|tick| tick.0),
+        )?);
+        Ok(())
+    }
+
+    /// Flags of the fields of the next header that the remote can recover from their low byte
+    fn truncated_fields(&self, tick: Tick, last_ack_packet_id: Option<PacketId>) -> u8 {
+        let Some(acked) = self.last_acked_packet_id else {
+            return 0;
+        };
+        // the last packet received by the remote is between our last acked packet and this one
+        // (or slightly after, if the packets are reordered)
+        let in_flight = self.next_packet_id - acked;
+        if !(0..MAX_TRUNCATED_DIFF).contains(&in_flight) {
+            return 0;
+        }
+        let mut truncated = TRUNCATED_PACKET_ID;
+        if (0..in_flight).all(|i| {
+            (tick - self.sent_headers[(acked + i).0 as u8 as usize].tick).unsigned_abs()
+                < MAX_TRUNCATED_TICK_DIFF
+        }) {
+            truncated |= TRUNCATED_TICK;
+        }
+        // the remote received at least the ack id of our last acked packet
+        let acked_ack_id = self.sent_headers[acked.0 as u8 as usize].last_ack_packet_id;
+        if let (Some(ack_id), Some(acked_ack_id)) = (last_ack_packet_id, acked_ack_id) {
+            if (0..MAX_TRUNCATED_DIFF).contains(&(ack_id - acked_ack_id)) {
+                truncated |= TRUNCATED_ACK_ID;
+            }
+        }
+        truncated
     }
 
     /// Update the list of sent packets that have not been acked yet
@@ -235,21 +466,36 @@ impl PacketHeaderManager {
         None
     }
 
-    /// Prepare the header of the next packet to send
-    pub(crate) fn prepare_send_packet_header(&mut self, packet_type: PacketType) -> PacketHeader {
+    /// Prepare the header of the next packet to send, at the given tick
+    pub(crate) fn prepare_send_packet_header(
+        &mut self,
+        packet_type: PacketType,
+        tick: Tick,
+    ) -> PacketHeader {
+        let truncated = self.truncated_fields(tick, self.recv_buffer.last_recv_packet_id);
+        self.sent_headers[self.next_packet_id.0 as u8 as usize] = SentHeader {
+            tick,
+            last_ack_packet_id: self.recv_buffer.last_recv_packet_id,
+        };
         // if we didn't have a last packet id, start with the maximum value
         // (so that receiving 0 counts as an update)
         let last_ack_packet_id = match self.recv_buffer.last_recv_packet_id {
             Some(id) => id,
             None => PacketId(u16::MAX),
         };
+        // fragment packets are already as big as possible, so they only contain the default ack bitfield
+        let ack_bitfield_size = match packet_type {
+            PacketType::Data => self.ack_bitfield_size,
+            PacketType::DataFragment => AckBitfieldSize::Bits32,
+        };
         let outgoing_header = PacketHeader {
             packet_type,
             packet_id: self.next_packet_id,
             last_ack_packet_id,
-            ack_bitfield: self.recv_buffer.get_bitfield(),
-            // TODO: we send the tick, later. Seems a bit dangerous...
-            tick: Tick(0),
+            ack_bitfield: self.recv_buffer.get_bitfield(ack_bitfield_size),
+            ack_bitfield_size,
+            tick,
+            truncated,
         };
         // we build the header only when we actually send the packet, so computing the stats here is valid
         self.stats_manager.sent_packet();
@@ -266,9 +512,9 @@ impl PacketHeaderManager {
 pub struct ReceiveBuffer {
     /// The packet id of the most recent packet received
     last_recv_packet_id: Option<PacketId>,
-    /// Use a ring buffer of ACK_BITFIELD_SIZE to track if we received the last
-    /// ACK_BITFIELD_SIZE packets prior to the last received packet
-    buffer: ConstGenericRingBuffer<bool, { ACK_BITFIELD_SIZE as usize }>,
+    /// Use a ring buffer of MAX_ACK_BITFIELD_SIZE to track if we received the last
+    /// MAX_ACK_BITFIELD_SIZE packets prior to the last received packet
+    buffer: ConstGenericRingBuffer<bool, { MAX_ACK_BITFIELD_SIZE as usize }>,
 }
 
 impl Default for ReceiveBuffer {
@@ -296,7 +542,7 @@ impl ReceiveBuffer {
             return;
         }
 
-        let bitfield_size = ACK_BITFIELD_SIZE as i16;
+        let bitfield_size = MAX_ACK_BITFIELD_SIZE as i16;
         let diff = self.last_recv_packet_id.unwrap() - id;
         if diff > bitfield_size {
             return;
@@ -329,14 +575,16 @@ impl ReceiveBuffer {
     }
 
     /// Convert the Receive Buffer to the bitfield that we need to send in the PacketHeader
-    fn get_bitfield(&self) -> u32 {
-        let mut ack_bitfield: u32 = 0;
+    /// (only the `size` most recent packets are included)
+    fn get_bitfield(&self, size: AckBitfieldSize) -> u128 {
+        let mut ack_bitfield: u128 = 0;
         // mask starting from the left
-        let mut mask = 1 << (ACK_BITFIELD_SIZE - 1);
+        let mut mask = 1 << (size.bits() - 1);
 
         // iter goes from the item pushed the longest ago (to the left of the bitfield)
         // to the items pushed most recently (to the right of the bitfield)
-        for exists in self.buffer.iter() {
+        let skip = (MAX_ACK_BITFIELD_SIZE - size.bits()) as usize;
+        for exists in self.buffer.iter().skip(skip) {
             if *exists {
                 ack_bitfield |= mask;
             }
@@ -357,17 +605,20 @@ mod tests {
     fn test_recv_buffer() {
         let recv_buffer = ReceiveBuffer::new();
         assert_eq!(recv_buffer.last_recv_packet_id, None);
-        assert_eq!(recv_buffer.get_bitfield(), 0);
+        assert_eq!(recv_buffer.get_bitfield(AckBitfieldSize::Bits32), 0);
 
         // add a most recent packet, and perform some assertions
         fn add_most_recent_packet(
             mut buffer: ReceiveBuffer,
             id: u16,
-            expected_bitfield: u32,
+            expected_bitfield: u128,
         ) -> ReceiveBuffer {
             buffer.recv_packet(PacketId(id));
             assert_eq!(buffer.last_recv_packet_id, Some(PacketId(id)));
-            assert_eq!(buffer.get_bitfield(), expected_bitfield);
+            assert_eq!(
+                buffer.get_bitfield(AckBitfieldSize::Bits32),
+                expected_bitfield
+            );
             buffer
         }
 
@@ -377,31 +628,37 @@ mod tests {
         // receive one more packet with increment 1
         let recv_buffer = add_most_recent_packet(recv_buffer, 1, 1);
 
-        // receive a packet where the ACK_BITFIELD_SIZE > diff_id > 0
-        let recv_buffer = add_most_recent_packet(recv_buffer, 3, 0b0000_0110u32);
+        // receive a packet where the 32 > diff_id > 0
+        let recv_buffer = add_most_recent_packet(recv_buffer, 3, 0b0000_0110u128);
 
-        // receive another packet where the ACK_BITFIELD_SIZE > diff_id > 0
-        let mut recv_buffer = add_most_recent_packet(recv_buffer, 6, 0b0011_0100u32);
+        // receive another packet where the 32 > diff_id > 0
+        let mut recv_buffer = add_most_recent_packet(recv_buffer, 6, 0b0011_0100u128);
 
         // receive a packet which is in the past
-        // -ACK_BITFIELD_SIZE < diff_id < 0
+        // -32 < diff_id < 0
         recv_buffer.recv_packet(PacketId(2));
         assert_eq!(recv_buffer.last_recv_packet_id, Some(PacketId(6)));
-        assert_eq!(recv_buffer.get_bitfield(), 0b0011_1100u32);
+        assert_eq!(
+            recv_buffer.get_bitfield(AckBitfieldSize::Bits32),
+            0b0011_1100u128
+        );
 
         // receive a packet that is far ahead
-        // diff > ACK_BITFIELD_SIZE
+        // diff > 32
         let recv_buffer = add_most_recent_packet(recv_buffer, 50, 0);
 
         // receive a packet at the max far ahead
-        // diff == ACK_BITFIELD_SIZE
+        // diff == 32
         let mut recv_buffer = add_most_recent_packet(recv_buffer, 82, 1 << (32 - 1));
 
         // receive a packet that is too far in the past
-        // diff_id < -ACK_BITFIELD_SIZE
+        // diff_id < -32
         recv_buffer.recv_packet(PacketId(49));
         assert_eq!(recv_buffer.last_recv_packet_id, Some(PacketId(82)));
-        assert_eq!(recv_buffer.get_bitfield(), 1 << (32 - 1));
+        assert_eq!(
+            recv_buffer.get_bitfield(AckBitfieldSize::Bits32),
+            1 << (32 - 1)
+        );
     }
 
     #[test]
@@ -411,7 +668,9 @@ mod tests {
             packet_id: PacketId(27),
             last_ack_packet_id: PacketId(13),
             ack_bitfield: 3,
+            ack_bitfield_size: AckBitfieldSize::Bits32,
             tick: Tick(6),
+            truncated: 0,
         };
         let mut writer = Vec::new();
         header.to_bytes(&mut writer)?;
@@ -422,4 +681,117 @@ mod tests {
         assert_eq!(header, read_header);
         Ok(())
     }
+
+    #[test]
+    fn test_serde_wide_header() -> Result<(), SerializationError> {
+        let header = PacketHeader {
+            packet_type: PacketType::DataFragment,
+            packet_id: PacketId(27),
+            last_ack_packet_id: PacketId(13),
+            ack_bitfield: 1 << 100 | 1 << 40 | 3,
+            ack_bitfield_size: AckBitfieldSize::Bits128,
+            tick: Tick(6),
+            truncated: 0,
+        };
+        let mut writer = Vec::new();
+        header.to_bytes(&mut writer)?;
+        assert_eq!(writer.len(), 23);
+        assert_eq!(writer.len(), header.len());
+
+        let mut reader = writer.into();
+        let read_header = PacketHeader::from_bytes(&mut reader)?;
+        assert_eq!(header, read_header);
+        Ok(())
+    }
+
+    /// The packets that are further than 32 packets behind the last received packet
+    /// are only acked with a wider ack bitfield
+    #[test]
+    fn test_ack_bitfield_size() {
+        for (size, expected_acks) in [
+            (AckBitfieldSize::Bits32, vec![PacketId(100)]),
+            (AckBitfieldSize::Bits128, vec![PacketId(100), PacketId(0)]),
+        ] {
+            let mut sender = PacketHeaderManager::new(1.5, AckBitfieldSize::Bits32);
+            let mut receiver = PacketHeaderManager::new(1.5, size);
+            for i in 0..=100 {
+                let mut header = sender.prepare_send_packet_header(PacketType::Data, Tick(0));
+                if i == 0 || i == 100 {
+                    receiver.process_recv_packet_header(&mut header).unwrap();
+                }
+            }
+            let mut header = receiver.prepare_send_packet_header(PacketType::Data, Tick(0));
+            assert_eq!(header.len(), 7 + size.bits() as usize / 8);
+            assert_eq!(
+                sender.process_recv_packet_header(&mut header).unwrap(),
+                expected_acks
+            );
+        }
+    }
+
+    /// Send the header through the wire, and process it on the receiver
+    fn send_header(
+        sender: &mut PacketHeaderManager,
+        receiver: &mut PacketHeaderManager,
+        tick: Tick,
+    ) -> (PacketHeader, usize) {
+        let header = sender.prepare_send_packet_header(PacketType::Data, tick);
+        let mut writer = Vec::new();
+        header.to_bytes(&mut writer).unwrap();
+        assert_eq!(writer.len(), header.len());
+        let mut read_header = PacketHeader::from_bytes(&mut writer.into()).unwrap();
+        receiver
+            .process_recv_packet_header(&mut read_header)
+            .unwrap();
+        assert_eq!(read_header.packet_id, header.packet_id);
+        assert_eq!(read_header.last_ack_packet_id, header.last_ack_packet_id);
+        assert_eq!(read_header.tick, header.tick);
+        (read_header, header.len())
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        let mut client = PacketHeaderManager::new(1.5, AckBitfieldSize::Bits32);
+        let mut server = PacketHeaderManager::new(1.5, AckBitfieldSize::Bits32);
+        // start far from 0 to check the wrapping
+        client.next_packet_id = PacketId(u16::MAX - 10);
+        server.next_packet_id = PacketId(30_000);
+        let mut tick = Tick(u16::MAX - 20);
+
+        // nothing is acked yet: the header is complete
+        let (_, len) = send_header(&mut client, &mut server, tick);
+        assert_eq!(len, 11);
+        send_header(&mut server, &mut client, tick);
+        // the server acked the first client packet, which didn't ack any server packet
+        let (header, len) = send_header(&mut client, &mut server, tick);
+        assert_eq!(header.truncated, TRUNCATED_PACKET_ID | TRUNCATED_TICK);
+        assert_eq!(len, 9);
+        send_header(&mut server, &mut client, tick);
+        send_header(&mut client, &mut server, tick);
+        send_header(&mut server, &mut client, tick);
+
+        // in the steady state, all the fields are truncated
+        for _ in 0..50 {
+            tick += 1;
+            let (header, len) = send_header(&mut client, &mut server, tick);
+            assert_eq!(
+                header.truncated,
+                TRUNCATED_PACKET_ID | TRUNCATED_ACK_ID | TRUNCATED_TICK
+            );
+            assert_eq!(len, 8);
+            send_header(&mut server, &mut client, tick);
+        }
+
+        // the packets of the client are lost for a while: the client doesn't know what the server received
+        for _ in 0..MAX_TRUNCATED_DIFF {
+            client.prepare_send_packet_header(PacketType::Data, tick);
+        }
+        let (header, _) = send_header(&mut client, &mut server, tick);
+        assert_eq!(header.truncated, 0);
+
+        // a large jump of the tick is sent in full
+        send_header(&mut server, &mut client, tick);
+        let (header, _) = send_header(&mut client, &mut server, tick + 1000);
+        assert_eq!(header.truncated, TRUNCATED_PACKET_ID | TRUNCATED_ACK_ID);
+    }
 }
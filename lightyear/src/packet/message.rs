@@ -1,12 +1,16 @@
 /// Defines the [`Message`](message::Message) struct, which is a piece of serializable data
+use std::any::Any;
 use std::fmt::Debug;
 
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 use bytes::Bytes;
 
+use crate::protocol::message::MessageKind;
 use crate::protocol::EventContext;
 use crate::serialize::reader::Reader;
-use crate::serialize::varint::varint_len;
+use crate::serialize::varint::{
+    varint_len, zigzag_decode, zigzag_encode, VarIntReadExt, VarIntWriteExt,
+};
 use crate::serialize::{SerializationError, ToBytes};
 use crate::shared::tick_manager::Tick;
 use crate::utils::wrapping_id::wrapping_id;
@@ -19,8 +23,74 @@ wrapping_id!(MessageId);
 ///
 /// Every type that can be sent over the network must implement this trait.
 ///
-pub trait Message: EventContext {}
-impl<T: EventContext> Message for T {}
+/// A `Box<dyn Message>` can be inspected at runtime with [`kind`](dyn Message::kind),
+/// [`name`](dyn Message::name) and [`downcast_ref`](dyn Message::downcast_ref).
+pub trait Message: EventContext {
+    /// Name of the type of the message
+    fn message_name(&self) -> &'static str;
+
+    /// Access the message as [`Any`]
+    fn message_as_any(&self) -> &dyn Any;
+
+    /// Convert the boxed message to a boxed [`Any`]
+    fn message_into_any(self: Box<Self>) -> Box<dyn Any + Send + Sync>;
+}
+
+impl<T: EventContext> Message for T {
+    fn message_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn message_as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn message_into_any(self: Box<Self>) -> Box<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+impl dyn Message {
+    /// The [`MessageKind`] of the concrete type of the message
+    pub fn kind(&self) -> MessageKind {
+        MessageKind::from(self.message_as_any().type_id())
+    }
+
+    /// Name of the concrete type of the message
+    pub fn name(&self) -> &'static str {
+        self.message_name()
+    }
+
+    /// Returns true if the concrete type of the message is `M`
+    pub fn is<M: Message>(&self) -> bool {
+        self.message_as_any().is::<M>()
+    }
+
+    /// Returns a reference to the message if its concrete type is `M`
+    pub fn downcast_ref<M: Message>(&self) -> Option<&M> {
+        self.message_as_any().downcast_ref::<M>()
+    }
+
+    /// Converts the boxed message to its concrete type `M`, or returns it unchanged if the type is different
+    pub fn downcast<M: Message>(self: Box<Self>) -> Result<Box<M>, Box<dyn Message>> {
+        if self.is::<M>() {
+            Ok(self
+                .message_into_any()
+                .downcast::<M>()
+                .expect("the message has type M"))
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Debug for dyn Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Message")
+            .field("name", &self.name())
+            .finish_non_exhaustive()
+    }
+}
 
 #[cfg(not(feature = "big_messages"))]
 pub type FragmentIndex = u8;
@@ -120,38 +190,31 @@ pub struct SingleData {
     pub bytes: Bytes,
 }
 
+/// Varint tag of a message without id
+const NO_ID_TAG: u64 = 0;
+/// Varint tag of a message whose id is written in full after the tag
+const FULL_ID_TAG: u64 = 1;
+/// Varint tags from this value contain the (zigzag-encoded) difference between the message id and the
+/// id of the previous message of the same channel in the packet
+const DELTA_ID_TAG: u64 = 2;
+/// Largest varint that is written in 2 bytes: the delta encoding is only used if it is shorter than the full id
+const MAX_DELTA_ID_TAG: u64 = 16383;
+
 impl ToBytes for SingleData {
-    // TODO: how to avoid the option taking 1 byte?
+    /// Maximum number of bytes of the message: the id can be shorter if it is delta-encoded
     fn len(&self) -> usize {
         varint_len(self.bytes.len() as u64) + self.bytes.len() + self.id.map_or(1, |_| 3)
     }
 
     fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
-        if let Some(id) = self.id {
-            buffer.write_u8(1)?;
-            buffer.write_u16::<NetworkEndian>(id.0)?;
-        } else {
-            buffer.write_u8(0)?;
-        }
-        self.bytes.to_bytes(buffer)?;
-        // buffer.write_varint(self.bytes.len() as u64)?;
-        // buffer.write_all(self.bytes.as_ref())?;
-        Ok(())
+        self.to_bytes_after(None, buffer)
     }
 
     fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
     where
         Self: Sized,
     {
-        let id = if buffer.read_u8()? == 1 {
-            Some(MessageId(buffer.read_u16::<NetworkEndian>()?))
-        } else {
-            None
-        };
-        let bytes = Bytes::from_bytes(buffer)?;
-        // let len = buffer.read_varint()? as usize;
-        // let bytes = buffer.split_len(len);
-        Ok(Self { id, bytes })
+        Self::from_bytes_after(buffer, None)
     }
 }
 
@@ -159,6 +222,52 @@ impl SingleData {
     pub fn new(id: Option<MessageId>, bytes: Bytes) -> Self {
         Self { id, bytes }
     }
+
+    /// Write the message, delta-encoding its id against `previous`, the id of the previous message of the
+    /// same channel in the packet.
+    ///
+    /// The messages of a channel are usually sent in order, so the id takes 1 byte instead of 2.
+    pub(crate) fn to_bytes_after<T: WriteBytesExt>(
+        &self,
+        previous: Option<MessageId>,
+        buffer: &mut T,
+    ) -> Result<(), SerializationError> {
+        match (self.id, previous) {
+            (None, _) => buffer.write_varint(NO_ID_TAG)?,
+            (Some(id), previous) => {
+                let delta_tag = previous.map(|previous| {
+                    DELTA_ID_TAG + zigzag_encode(id.0.wrapping_sub(previous.0) as i16 as i64)
+                });
+                match delta_tag {
+                    Some(tag) if tag <= MAX_DELTA_ID_TAG => buffer.write_varint(tag)?,
+                    _ => {
+                        buffer.write_varint(FULL_ID_TAG)?;
+                        buffer.write_u16::<NetworkEndian>(id.0)?;
+                    }
+                }
+            }
+        }
+        self.bytes.to_bytes(buffer)?;
+        Ok(())
+    }
+
+    /// Read a message written with [`to_bytes_after`](Self::to_bytes_after)
+    pub(crate) fn from_bytes_after(
+        buffer: &mut Reader,
+        previous: Option<MessageId>,
+    ) -> Result<Self, SerializationError> {
+        let id = match buffer.read_varint()? {
+            NO_ID_TAG => None,
+            FULL_ID_TAG => Some(MessageId(buffer.read_u16::<NetworkEndian>()?)),
+            tag => {
+                let previous = previous.ok_or(SerializationError::InvalidValue)?;
+                let delta = zigzag_decode(tag - DELTA_ID_TAG) as i16;
+                Some(MessageId(previous.0.wrapping_add(delta as u16)))
+            }
+        };
+        let bytes = Bytes::from_bytes(buffer)?;
+        Ok(Self { id, bytes })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -262,6 +371,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_bytes_single_data_delta_id() {
+        for (previous, id, expected_id_len) in [
+            (1, 2, 1),
+            (5, 3, 1),
+            // the id wraps around
+            (u16::MAX, 0, 1),
+            (10, 5_000, 2),
+            // the delta is too big, the id is written in full
+            (0, 30_000, 3),
+        ] {
+            let data = SingleData::new(Some(MessageId(id)), vec![7u8; 10].into());
+            let mut writer = vec![];
+            data.to_bytes_after(Some(MessageId(previous)), &mut writer)
+                .unwrap();
+            assert_eq!(writer.len(), data.len() - 3 + expected_id_len);
+
+            let mut reader = writer.into();
+            let decoded =
+                SingleData::from_bytes_after(&mut reader, Some(MessageId(previous))).unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+
     #[test]
     fn test_to_bytes_fragment_data() {
         let bytes = Bytes::from(vec![0; 10]);
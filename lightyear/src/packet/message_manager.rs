@@ -1,7 +1,9 @@
 use byteorder::ReadBytesExt;
 use bytes::Bytes;
 use crossbeam_channel::{Receiver, Sender};
+use governor::{DefaultDirectRateLimiter, Quota};
 use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
 use tracing::trace;
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
@@ -9,21 +11,25 @@ use tracing::{instrument, Level};
 use crate::channel::builder::ChannelContainer;
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::retransmission::RetransmissionStats;
 #[cfg(feature = "trace")]
 use crate::channel::stats::send::ChannelSendStats;
 use crate::packet::error::PacketError;
-use crate::packet::header::PacketHeader;
+use crate::packet::header::{AckBitfieldSize, PacketHeader};
 use crate::packet::message::{
     FragmentData, MessageAck, MessageId, ReceiveMessage, SendMessage, SingleData,
 };
-use crate::packet::packet::PacketId;
+use crate::packet::mtu::{MtuConfig, PathMtu};
+use crate::packet::packet::{fragment_size, PacketId};
 use crate::packet::packet_builder::{PacketBuilder, Payload, RecvPayload};
 use crate::packet::packet_type::PacketType;
-use crate::packet::priority_manager::{PriorityConfig, PriorityManager};
+use crate::packet::priority_manager::{BandwidthLimiter, PriorityConfig, PriorityManager};
+use crate::packet::raw;
 use crate::protocol::channel::{ChannelId, ChannelKind, ChannelRegistry};
 use crate::protocol::registry::NetId;
 use crate::serialize::reader::Reader;
 use crate::serialize::{SerializationError, ToBytes};
+use crate::shared::alloc_audit::{allow_allocations, AllocationException};
 use crate::shared::ping::manager::PingManager;
 use crate::shared::tick_manager::Tick;
 use crate::shared::tick_manager::TickManager;
@@ -50,22 +56,65 @@ pub struct MessageManager {
     /// reliable senders can stop trying to send a message that has already been received
     packet_to_message_ack_map: HashMap<PacketId, Vec<(ChannelKind, MessageAck)>>,
     nack_senders: Vec<Sender<MessageId>>,
+    /// Maximum size of the packets sent on this connection
+    mtu: PathMtu,
+    /// Raw datagrams to send with the next packets
+    raw_to_send: Vec<Payload>,
+    /// Raw datagrams received (kind and payload)
+    pub(crate) received_raw: Vec<(u8, Bytes)>,
 }
 
 impl MessageManager {
     pub fn new(
         channel_registry: &ChannelRegistry,
         nack_rtt_multiple: f32,
+        ack_bitfield_size: AckBitfieldSize,
+        mtu_config: MtuConfig,
         priority_config: PriorityConfig,
     ) -> Self {
-        Self {
-            packet_manager: PacketBuilder::new(nack_rtt_multiple),
+        let mut manager = Self {
+            packet_manager: PacketBuilder::new(nack_rtt_multiple, ack_bitfield_size),
             priority_manager: PriorityManager::new(priority_config),
             channels: channel_registry.channels(),
             channel_registry: channel_registry.clone(),
             packet_to_message_ack_map: HashMap::new(),
             nack_senders: vec![],
+            mtu: PathMtu::new(mtu_config),
+            raw_to_send: vec![],
+            received_raw: vec![],
+        };
+        manager.set_max_packet_size(manager.mtu.max_packet_size());
+        manager
+    }
+
+    /// Current maximum number of bytes in the packets sent on this connection
+    pub fn max_packet_size(&self) -> usize {
+        self.packet_manager.max_packet_size
+    }
+
+    /// Update the maximum packet size, and the size of the fragments of the messages that will be buffered.
+    ///
+    /// The messages waiting to be resent that don't fit anymore are fragmented again by their channel.
+    fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.packet_manager.max_packet_size = max_packet_size;
+        let mut refragmented: Vec<(ChannelKind, MessageId)> = vec![];
+        for (channel_kind, channel) in self.channels.iter_mut() {
+            let message_ids = channel
+                .sender
+                .set_fragment_size(fragment_size(max_packet_size));
+            refragmented.extend(message_ids.into_iter().map(|id| (*channel_kind, id)));
+        }
+        if refragmented.is_empty() {
+            return;
         }
+        // the acks of the packets that contain the previous fragments of these messages
+        // don't match the new fragments anymore, ignore them
+        self.packet_to_message_ack_map.retain(|_, message_acks| {
+            message_acks.retain(|(channel_kind, message_ack)| {
+                !refragmented.contains(&(*channel_kind, message_ack.message_id))
+            });
+            !message_acks.is_empty()
+        });
     }
 
     pub(crate) fn get_replication_update_send_receiver(&mut self) -> Receiver<MessageId> {
@@ -88,6 +137,9 @@ impl MessageManager {
             .update(time_manager, ping_manager);
         // notify that some messages have been lost
         for lost_packet in lost_packets {
+            if let Some(max_packet_size) = self.mtu.on_lost(lost_packet) {
+                self.set_max_packet_size(max_packet_size);
+            }
             if let Some(message_map) = self.packet_to_message_ack_map.remove(&lost_packet) {
                 for (channel_kind, message_ack) in message_map {
                     let channel = self
@@ -145,7 +197,7 @@ impl MessageManager {
             .channels
             .get_mut(&channel_kind)
             .ok_or(PacketError::ChannelNotFound)?;
-        Ok(channel.sender.buffer_send(message, priority)?)
+        channel.buffer_send(message, priority)
     }
 
     /// Prepare buckets from the internal send buffers, and return the bytes to send
@@ -154,6 +206,40 @@ impl MessageManager {
     //  maybe be generic over a Context ?
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub fn send_packets(&mut self, current_tick: Tick) -> Result<Vec<Payload>, PacketError> {
+        let Some((single_data, fragment_data, num_bytes_added_to_limiter)) =
+            allow_allocations(AllocationException::PriorityFilter, || {
+                self.filter_data_to_send(current_tick)
+            })?
+        else {
+            // return early if there are no messages to send
+            return Ok(std::mem::take(&mut self.raw_to_send));
+        };
+        allow_allocations(AllocationException::PacketBuffers, || {
+            self.build_payloads(
+                current_tick,
+                single_data,
+                fragment_data,
+                num_bytes_added_to_limiter,
+            )
+        })
+    }
+
+    /// Collect the messages that are ready to be sent from all channels, and keep the ones that fit in
+    /// the bandwidth quota.
+    ///
+    /// Returns None if no channel has messages to send.
+    #[allow(clippy::type_complexity)]
+    fn filter_data_to_send(
+        &mut self,
+        current_tick: Tick,
+    ) -> Result<
+        Option<(
+            Vec<(ChannelId, VecDeque<SingleData>)>,
+            Vec<(ChannelId, VecDeque<FragmentData>)>,
+            u32,
+        )>,
+        PacketError,
+    > {
         // Step 1. Get the list of packets to send from all channels
         // for each channel, prepare packets using the buffered messages that are ready to be sent
         // TODO: iterate through the channels in order of channel priority? (with accumulation)
@@ -172,17 +258,27 @@ impl MessageManager {
                 has_data_to_send = true;
             }
         }
-        // return early if there are no messages to send
         if !has_data_to_send {
-            return Ok(vec![]);
+            return Ok(None);
         }
 
         // priority manager: get the list of messages we can send according to the rate limiter
         //  (the other messages are stored in an internal buffer)
-        let (single_data, fragment_data, num_bytes_added_to_limiter) = self
-            .priority_manager
-            .priority_filter(data_to_send, &self.channel_registry, current_tick);
+        Ok(Some(self.priority_manager.priority_filter(
+            data_to_send,
+            &self.channel_registry,
+            current_tick,
+        )))
+    }
 
+    /// Write the filtered messages in packets, and return the bytes to send
+    fn build_payloads(
+        &mut self,
+        current_tick: Tick,
+        single_data: Vec<(ChannelId, VecDeque<SingleData>)>,
+        fragment_data: Vec<(ChannelId, VecDeque<FragmentData>)>,
+        num_bytes_added_to_limiter: u32,
+    ) -> Result<Vec<Payload>, PacketError> {
         #[cfg(feature = "trace")]
         {
             // NOTE: we don't know the actual exact amount of bytes sent (because we don't take into account the ids, etc.),
@@ -236,8 +332,9 @@ impl MessageManager {
                         .ok_or(PacketError::ChannelNotFound)?;
                     let channel = self
                         .channels
-                        .get(channel_kind)
+                        .get_mut(channel_kind)
                         .ok_or(PacketError::ChannelNotFound)?;
+                    channel.sender.message_written(&message_ack);
                     if channel.setting.mode.is_watching_acks() {
                         trace!(
                             "Registering message ack (ChannelId:{:?} {:?}) for packet {:?}",
@@ -254,25 +351,46 @@ impl MessageManager {
                 })?;
 
             // Step 3. Get the packets to send over the network
+            self.mtu.on_sent(packet.packet_id, packet.payload.len());
             bytes.push(packet.payload);
         }
 
         // adjust the real amount of bytes that we sent through the limiter (to account for the actual packet size)
-        if self.priority_manager.config.enabled {
+        if self.priority_manager.is_limited() {
             let total_bytes_sent = bytes.iter().map(|b| b.len() as u32).sum::<u32>();
             if let Ok(remaining_bytes_to_add) =
                 (total_bytes_sent - num_bytes_added_to_limiter).try_into()
             {
-                let _ = self
-                    .priority_manager
-                    .limiter
-                    .check_n(remaining_bytes_to_add);
+                self.priority_manager.charge(remaining_bytes_to_add);
             }
         }
 
+        bytes.append(&mut self.raw_to_send);
         Ok(bytes)
     }
 
+    /// Buffer a [raw datagram](crate::packet::raw) that will be sent with the next packets
+    pub(crate) fn buffer_send_raw(&mut self, kind: u8, payload: &[u8]) -> Result<(), PacketError> {
+        let datagram = raw::write_raw_datagram(kind, payload, self.max_packet_size())?;
+        self.raw_to_send.push(datagram);
+        Ok(())
+    }
+
+    /// Drop the raw datagrams that were not sent yet
+    pub(crate) fn clear_raw_to_send(&mut self) {
+        self.raw_to_send.clear();
+    }
+
+    /// If the packet is a [raw datagram](crate::packet::raw), buffer it and return None.
+    /// Otherwise return the packet so that it can be processed with [`recv_packet`](Self::recv_packet)
+    pub(crate) fn recv_raw(&mut self, packet: RecvPayload) -> Option<RecvPayload> {
+        if !raw::is_raw_datagram(&packet) {
+            return Some(packet);
+        }
+        self.received_raw.push(raw::read_raw_datagram(packet));
+        None
+    }
+
     /// Process packet received over the network as raw bytes
     /// Update the acks, and put the messages from the packets in internal buffers
     /// Returns the tick of the packet
@@ -282,9 +400,7 @@ impl MessageManager {
         let mut cursor = Reader::from(packet);
 
         // Step 1. Parse the packet
-        let header = PacketHeader::from_bytes(&mut cursor)?;
-        let tick = header.tick;
-        trace!(?header);
+        let mut header = PacketHeader::from_bytes(&mut cursor)?;
 
         // TODO: if it's fragmented, put it in a buffer? while we wait for all the parts to be ready?
         //  maybe the channel can handle the fragmentation?
@@ -297,11 +413,16 @@ impl MessageManager {
         let acked_packets = self
             .packet_manager
             .header_manager
-            .process_recv_packet_header(&header);
+            .process_recv_packet_header(&mut header)?;
+        let tick = header.tick;
+        trace!(?header);
 
         // Step 3. Update the list of messages that have been acked
         for acked_packet in acked_packets {
             trace!("Acked packet {:?}", acked_packet);
+            if let Some(max_packet_size) = self.mtu.on_ack(acked_packet) {
+                self.set_max_packet_size(max_packet_size);
+            }
             if let Some(message_acks) = self.packet_to_message_ack_map.remove(&acked_packet) {
                 for (channel_kind, message_ack) in message_acks {
                     let channel_name = self
@@ -341,8 +462,10 @@ impl MessageManager {
             let channel_id = ChannelId::from_bytes(&mut cursor)?;
             let num_messages = cursor.read_u8().map_err(SerializationError::from)?;
             trace!(?channel_id, ?num_messages);
+            let mut previous_id = None;
             for i in 0..num_messages {
-                let single_data = SingleData::from_bytes(&mut cursor)?;
+                let single_data = SingleData::from_bytes_after(&mut cursor, previous_id)?;
+                previous_id = single_data.id.or(previous_id);
                 self.get_channel_mut(channel_id)?
                     .receiver
                     .buffer_recv(ReceiveMessage {
@@ -383,7 +506,7 @@ impl MessageManager {
             .iter_mut()
             .flat_map(move |(channel_kind, channel)| {
                 // TODO: this is broken, we need to call a read_message in a while loop !
-                channel.receiver.read_message().map(move |(tick, bytes)| {
+                channel.read_message().map(move |(tick, bytes)| {
                     trace!(?channel_kind, "reading message: {:?}", bytes);
                     // SAFETY: when we receive the message, we set the tick of the message to the header tick
                     // so every message has a tick
@@ -418,6 +541,72 @@ impl MessageManager {
             .ok_or(PacketError::ChannelNotFound)
     }
 
+    /// Ratio of the sent packets that were lost recently
+    pub(crate) fn packet_loss(&self) -> f32 {
+        self.packet_manager.header_manager.packet_loss()
+    }
+
+    /// Returns true if some messages could not be sent in the last packets because the bandwidth quota was reached
+    pub(crate) fn budget_exceeded(&self) -> bool {
+        self.priority_manager.budget_exceeded
+    }
+
+    /// Share a bandwidth quota with other connections, instead of using the quota of this connection
+    pub(crate) fn set_shared_limiter(&mut self, limiter: Option<Arc<DefaultDirectRateLimiter>>) {
+        self.priority_manager.shared_limiter = limiter;
+    }
+
+    /// Returns true if the messages are filtered by a bandwidth quota or a send budget, so that some of them
+    /// can be held back
+    pub(crate) fn is_limited(&self) -> bool {
+        self.priority_manager.is_limited()
+    }
+
+    /// Limit the outgoing bandwidth of this connection, on top of the other bandwidth quotas
+    pub(crate) fn set_max_outgoing_bandwidth(&mut self, quota: Option<Quota>) {
+        self.priority_manager.max_bandwidth_limiter = quota.map(BandwidthLimiter::new);
+    }
+
+    /// Returns true if the connection uses the given shared bandwidth quota
+    pub(crate) fn uses_shared_limiter(
+        &self,
+        limiter: Option<&Arc<DefaultDirectRateLimiter>>,
+    ) -> bool {
+        match (&self.priority_manager.shared_limiter, limiter) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if some reliable messages were not acknowledged by the remote peer yet
+    pub(crate) fn has_pending_messages(&self) -> bool {
+        self.channels
+            .values()
+            .any(|channel| channel.sender.has_pending_messages())
+    }
+
+    /// Returns a channel on which a message was resent more than the maximum number of retries
+    /// without being acked, if any
+    pub(crate) fn retries_exhausted(&self) -> Option<ChannelKind> {
+        self.channels
+            .iter()
+            .find(|(_, channel)| channel.sender.retries_exhausted())
+            .map(|(channel_kind, _)| *channel_kind)
+    }
+
+    /// Get the retransmission statistics of a given channel.
+    ///
+    /// Returns None if the channel doesn't exist or is not reliable
+    pub(crate) fn retransmission_stats(
+        &self,
+        channel_kind: &ChannelKind,
+    ) -> Option<RetransmissionStats> {
+        self.channels
+            .get(channel_kind)
+            .and_then(|channel| channel.sender.retransmission_stats())
+    }
+
     /// Get the ChannelSendStats of a given channel
     #[cfg(feature = "trace")]
     pub fn channel_send_stats<C: crate::prelude::Channel>(&self) -> Option<&ChannelSendStats> {
@@ -456,10 +645,20 @@ mod tests {
         });
 
         // Create message managers
-        let client_message_manager =
-            MessageManager::new(&channel_registry, 1.5, PriorityConfig::default());
-        let server_message_manager =
-            MessageManager::new(&channel_registry, 1.5, PriorityConfig::default());
+        let client_message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig::default(),
+            PriorityConfig::default(),
+        );
+        let server_message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig::default(),
+            PriorityConfig::default(),
+        );
         (client_message_manager, server_message_manager)
     }
 
@@ -545,6 +744,235 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    /// When the tick budget is reached, the messages of the channels with the highest priority are sent first
+    fn test_message_manager_tick_budget() -> Result<(), PacketError> {
+        let mut channel_registry = ChannelRegistry::default();
+        channel_registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+        channel_registry.add_channel::<Channel2>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliableWithAcks,
+            priority: 10.0,
+            ..default()
+        });
+        let mut client_message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig::default(),
+            PriorityConfig {
+                tick_budget: Some(60),
+                ..default()
+            },
+        );
+        let (_, mut server_message_manager) = setup();
+
+        let message: Bytes = vec![0; 50].into();
+        let channel_kind_1 = ChannelKind::of::<Channel1>();
+        let channel_kind_2 = ChannelKind::of::<Channel2>();
+        client_message_manager.buffer_send(message.clone(), channel_kind_1)?;
+        client_message_manager.buffer_send(message.clone(), channel_kind_2)?;
+        let payloads = client_message_manager.send_packets(Tick(0))?;
+        assert!(client_message_manager.budget_exceeded());
+
+        for payload in payloads {
+            server_message_manager.recv_packet(payload.into())?;
+        }
+        let data = MessageManager::collect_messages(server_message_manager.read_messages());
+        assert_eq!(data.get(&channel_kind_1), None);
+        assert_eq!(
+            data.get(&channel_kind_2).unwrap(),
+            &vec![(Tick(0), message.clone())]
+        );
+
+        // a message larger than the budget is still sent, as the first message of the tick
+        let large_message: Bytes = vec![1; 100].into();
+        client_message_manager.buffer_send(large_message.clone(), channel_kind_2)?;
+        let payloads = client_message_manager.send_packets(Tick(1))?;
+        assert!(!client_message_manager.budget_exceeded());
+        for payload in payloads {
+            server_message_manager.recv_packet(payload.into())?;
+        }
+        let data = MessageManager::collect_messages(server_message_manager.read_messages());
+        assert_eq!(
+            data.get(&channel_kind_2).unwrap(),
+            &vec![(Tick(1), large_message)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// The maximum outgoing bandwidth of a connection applies even if there is no bandwidth cap
+    fn test_message_manager_max_outgoing_bandwidth() -> Result<(), PacketError> {
+        let mut channel_registry = ChannelRegistry::default();
+        channel_registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+        channel_registry.add_channel::<Channel2>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliableWithAcks,
+            priority: 10.0,
+            ..default()
+        });
+        let mut client_message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig::default(),
+            PriorityConfig::default(),
+        );
+        client_message_manager
+            .set_max_outgoing_bandwidth(Some(Quota::per_second(nonzero_ext::nonzero!(80u32))));
+        let (_, mut server_message_manager) = setup();
+
+        let message: Bytes = vec![0; 50].into();
+        let channel_kind_1 = ChannelKind::of::<Channel1>();
+        let channel_kind_2 = ChannelKind::of::<Channel2>();
+        client_message_manager.buffer_send(message.clone(), channel_kind_1)?;
+        client_message_manager.buffer_send(message.clone(), channel_kind_2)?;
+        let payloads = client_message_manager.send_packets(Tick(0))?;
+        assert!(client_message_manager.budget_exceeded());
+
+        for payload in payloads {
+            server_message_manager.recv_packet(payload.into())?;
+        }
+        let data = MessageManager::collect_messages(server_message_manager.read_messages());
+        assert_eq!(data.get(&channel_kind_1), None);
+        assert_eq!(
+            data.get(&channel_kind_2).unwrap(),
+            &vec![(Tick(0), message.clone())]
+        );
+
+        // without the limit, the messages are sent right away
+        client_message_manager.set_max_outgoing_bandwidth(None);
+        client_message_manager.buffer_send(message.clone(), channel_kind_1)?;
+        client_message_manager.send_packets(Tick(1))?;
+        assert!(!client_message_manager.budget_exceeded());
+        Ok(())
+    }
+
+    #[test]
+    /// A message held back by the maximum outgoing bandwidth is not charged to the bandwidth quota
+    fn test_message_manager_max_outgoing_bandwidth_with_quota() -> Result<(), PacketError> {
+        let mut channel_registry = ChannelRegistry::default();
+        channel_registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+        let mut message_manager = MessageManager::new(
+            &channel_registry,
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig::default(),
+            PriorityConfig::default(),
+        );
+        let quota = Arc::new(DefaultDirectRateLimiter::direct(Quota::per_second(
+            nonzero_ext::nonzero!(200u32),
+        )));
+        message_manager.set_shared_limiter(Some(quota.clone()));
+        message_manager
+            .set_max_outgoing_bandwidth(Some(Quota::per_second(nonzero_ext::nonzero!(80u32))));
+
+        let message: Bytes = vec![0; 50].into();
+        let channel_kind = ChannelKind::of::<Channel1>();
+        message_manager.buffer_send(message.clone(), channel_kind)?;
+        message_manager.buffer_send(message.clone(), channel_kind)?;
+        message_manager.send_packets(Tick(0))?;
+        assert!(message_manager.budget_exceeded());
+
+        // only the message that was sent (and its packet header) was charged to the quota
+        assert!(quota
+            .check_n(nonzero_ext::nonzero!(120u32))
+            .unwrap()
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    /// Messages are fragmented to fit in the configured maximum packet size
+    fn test_message_manager_max_packet_size() -> Result<(), PacketError> {
+        let (_, mut server_message_manager) = setup();
+        let mut client_message_manager = MessageManager::new(
+            &server_message_manager.channel_registry.clone(),
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig {
+                max_packet_size: 600,
+                discovery: false,
+            },
+            PriorityConfig::default(),
+        );
+        assert_eq!(client_message_manager.max_packet_size(), 600);
+
+        let message = Bytes::from(vec![3; 1000]);
+        let channel_kind_1 = ChannelKind::of::<Channel1>();
+        client_message_manager.buffer_send(message.clone(), channel_kind_1)?;
+        let payloads = client_message_manager.send_packets(Tick(0))?;
+        assert_eq!(payloads.len(), 2);
+        assert!(payloads.iter().all(|payload| payload.len() <= 600));
+
+        // the receiver doesn't need to know the fragment size of the sender
+        for payload in payloads {
+            server_message_manager.recv_packet(payload.into())?;
+        }
+        let it = server_message_manager.read_messages();
+        let data = MessageManager::collect_messages(it);
+        assert_eq!(
+            data.get(&channel_kind_1).unwrap(),
+            &vec![(Tick(0), message)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    /// The reliable messages waiting to be resent are fragmented again when the maximum packet size is reduced
+    fn test_message_manager_refragment_after_mtu_drop() -> Result<(), PacketError> {
+        let mut channel_registry = ChannelRegistry::default();
+        channel_registry.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            ..default()
+        });
+        let new_manager = || {
+            MessageManager::new(
+                &channel_registry,
+                1.5,
+                AckBitfieldSize::default(),
+                MtuConfig::default(),
+                PriorityConfig::default(),
+            )
+        };
+        let mut client_message_manager = new_manager();
+        let mut server_message_manager = new_manager();
+        let channel_kind = ChannelKind::of::<Channel1>();
+        let single = Bytes::from(vec![1; 1000]);
+        let fragmented = Bytes::from(vec![2; 2000]);
+        client_message_manager.buffer_send(single.clone(), channel_kind)?;
+        client_message_manager.buffer_send(fragmented.clone(), channel_kind)?;
+        let payloads = client_message_manager.send_packets(Tick(0))?;
+        assert!(payloads.iter().any(|payload| payload.len() > 600));
+
+        // only the first packet is received, the other ones are dropped because they are too big
+        server_message_manager.recv_packet(payloads.into_iter().next().unwrap().into())?;
+        client_message_manager.set_max_packet_size(600);
+        // the acks of the previous fragments are ignored
+        assert!(client_message_manager.packet_to_message_ack_map.is_empty());
+
+        let payloads = client_message_manager.send_packets(Tick(1))?;
+        assert!(payloads.iter().all(|payload| payload.len() <= 600));
+        for payload in payloads {
+            server_message_manager.recv_packet(payload.into())?;
+        }
+        // `read_messages` only reads one message per channel
+        let received: Vec<Bytes> = (0..2)
+            .flat_map(|_| server_message_manager.read_messages().collect::<Vec<_>>())
+            .map(|(_, (_, bytes))| bytes)
+            .collect();
+        assert_eq!(received, vec![single, fragmented]);
+        Ok(())
+    }
+
     #[test]
     /// We want to test that we can send/receive messages over a connection
     fn test_message_manager_fragment_message() -> Result<(), PacketError> {
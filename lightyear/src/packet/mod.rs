@@ -27,6 +27,9 @@ pub(crate) mod message;
 /// Manages sending and receiving [`Packets`](packet::Packet) over the network
 pub mod message_manager;
 
+/// Discovers the maximum packet size that can be sent on a connection
+pub mod mtu;
+
 pub mod packet;
 
 pub(crate) mod error;
@@ -35,4 +38,5 @@ pub(crate) mod packet_builder;
 /// Defines the [`PacketType`](packet_type::PacketType) enum
 mod packet_type;
 pub(crate) mod priority_manager;
+pub mod raw;
 pub(crate) mod stats_manager;
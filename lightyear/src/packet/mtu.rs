@@ -0,0 +1,221 @@
+//! Discover the largest packet size that can be sent to the remote peer.
+//!
+//! Packets larger than the path MTU are silently dropped on some networks. Instead of relying on ICMP
+//! messages (which are often filtered), we use the packet acks: if several packets bigger than any packet
+//! that was acked so far are lost in a row, we assume that they are dropped because of their size (a 'black hole')
+//! and we reduce the maximum packet size to the largest size that is known to be delivered.
+//!
+//! Afterwards the maximum packet size is periodically raised again in small steps, so that we recover if the
+//! losses were caused by congestion rather than by the MTU.
+use bevy::utils::HashMap;
+use tracing::debug;
+
+use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::packet::packet::PacketId;
+
+/// Packets of this size should be delivered on any network (576 bytes IPv4 minimum reassembly size
+/// minus the IP and UDP headers)
+pub const MIN_PACKET_SIZE: usize = 508;
+
+/// Number of consecutive losses of large packets after which we reduce the maximum packet size
+const BLACK_HOLE_LOSSES: u8 = 3;
+
+/// Number of packets that must be acked before we try to increase the maximum packet size again
+const PROBE_INTERVAL: u32 = 256;
+
+/// Maximum number of acked packets between two probes
+const MAX_PROBE_INTERVAL: u32 = 256 * 64;
+
+/// By how many bytes we increase the maximum packet size when probing
+const PROBE_STEP: usize = 128;
+
+/// Configuration of the maximum size of the packets sent on a connection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MtuConfig {
+    /// Maximum number of bytes in a packet. Messages that don't fit in a packet are fragmented.
+    ///
+    /// The value is clamped between [`MIN_PACKET_SIZE`] and [`MAX_PACKET_SIZE`].
+    pub max_packet_size: usize,
+    /// If true, the maximum packet size of each connection is reduced when large packets seem to be dropped
+    /// by the network. The default is false.
+    pub discovery: bool,
+}
+
+impl Default for MtuConfig {
+    fn default() -> Self {
+        Self {
+            max_packet_size: MAX_PACKET_SIZE,
+            discovery: false,
+        }
+    }
+}
+
+impl From<crate::client::config::PacketConfig> for MtuConfig {
+    fn from(value: crate::client::config::PacketConfig) -> Self {
+        Self {
+            max_packet_size: value.max_packet_size,
+            discovery: value.mtu_discovery,
+        }
+    }
+}
+
+impl From<crate::server::config::PacketConfig> for MtuConfig {
+    fn from(value: crate::server::config::PacketConfig) -> Self {
+        Self {
+            max_packet_size: value.max_packet_size,
+            discovery: value.mtu_discovery,
+        }
+    }
+}
+
+/// Keeps track of the maximum packet size that can be sent to the remote peer
+#[derive(Debug)]
+pub(crate) struct PathMtu {
+    discovery: bool,
+    /// Configured maximum packet size
+    max: usize,
+    /// Current maximum packet size
+    current: usize,
+    /// Largest packet size that has been acked
+    confirmed: usize,
+    /// Size of the packets that are larger than `confirmed` and haven't been acked or lost yet
+    in_flight: HashMap<PacketId, usize>,
+    /// Number of packets larger than `confirmed` that were lost in a row
+    large_losses: u8,
+    /// Number of packets acked since the maximum packet size was last changed
+    acks_since_change: u32,
+    probe_interval: u32,
+}
+
+impl PathMtu {
+    pub(crate) fn new(config: MtuConfig) -> Self {
+        let max = config
+            .max_packet_size
+            .clamp(MIN_PACKET_SIZE, MAX_PACKET_SIZE);
+        Self {
+            discovery: config.discovery,
+            max,
+            current: max,
+            confirmed: MIN_PACKET_SIZE,
+            in_flight: HashMap::default(),
+            large_losses: 0,
+            acks_since_change: 0,
+            probe_interval: PROBE_INTERVAL,
+        }
+    }
+
+    /// Current maximum packet size
+    pub(crate) fn max_packet_size(&self) -> usize {
+        self.current
+    }
+
+    /// Keep track of a packet that was sent
+    pub(crate) fn on_sent(&mut self, packet_id: PacketId, size: usize) {
+        if self.discovery && size > self.confirmed {
+            self.in_flight.insert(packet_id, size);
+        }
+    }
+
+    /// Handle the ack of a packet.
+    ///
+    /// Returns the new maximum packet size if it changed
+    pub(crate) fn on_ack(&mut self, packet_id: PacketId) -> Option<usize> {
+        if !self.discovery {
+            return None;
+        }
+        if let Some(size) = self.in_flight.remove(&packet_id) {
+            self.confirmed = self.confirmed.max(size);
+            self.large_losses = 0;
+            if self.confirmed >= self.current {
+                // the probe succeeded
+                self.probe_interval = PROBE_INTERVAL;
+            }
+        }
+        self.acks_since_change += 1;
+        if self.current < self.max && self.acks_since_change >= self.probe_interval {
+            return Some(self.set_current((self.current + PROBE_STEP).min(self.max)));
+        }
+        None
+    }
+
+    /// Handle the loss of a packet.
+    ///
+    /// Returns the new maximum packet size if it changed
+    pub(crate) fn on_lost(&mut self, packet_id: PacketId) -> Option<usize> {
+        let size = self.in_flight.remove(&packet_id)?;
+        if size <= self.confirmed {
+            return None;
+        }
+        self.large_losses += 1;
+        if self.large_losses < BLACK_HOLE_LOSSES || self.current <= self.confirmed {
+            return None;
+        }
+        debug!(
+            lost_size = ?size,
+            new_max_packet_size = ?self.confirmed,
+            "large packets are being dropped, reducing the maximum packet size"
+        );
+        // back off before probing again
+        self.probe_interval = (self.probe_interval * 2).min(MAX_PROBE_INTERVAL);
+        Some(self.set_current(self.confirmed))
+    }
+
+    fn set_current(&mut self, size: usize) -> usize {
+        self.current = size;
+        self.large_losses = 0;
+        self.acks_since_change = 0;
+        // only the packets above the new size are useful to detect a black hole
+        self.in_flight.retain(|_, s| *s > self.confirmed);
+        size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_black_hole_detection() {
+        let mut mtu = PathMtu::new(MtuConfig {
+            discovery: true,
+            ..Default::default()
+        });
+        assert_eq!(mtu.max_packet_size(), MAX_PACKET_SIZE);
+
+        // small packets are delivered
+        mtu.on_sent(PacketId(0), 600);
+        assert_eq!(mtu.on_ack(PacketId(0)), None);
+
+        // large packets are dropped
+        for i in 1..BLACK_HOLE_LOSSES as u16 {
+            mtu.on_sent(PacketId(i), MAX_PACKET_SIZE);
+            assert_eq!(mtu.on_lost(PacketId(i)), None);
+        }
+        mtu.on_sent(PacketId(10), MAX_PACKET_SIZE);
+        assert_eq!(mtu.on_lost(PacketId(10)), Some(600));
+        assert_eq!(mtu.max_packet_size(), 600);
+
+        // the losses of small packets are ignored
+        mtu.on_sent(PacketId(11), 500);
+        assert_eq!(mtu.on_lost(PacketId(11)), None);
+
+        // probe a bigger size after enough packets are acked
+        for i in 0..2 * PROBE_INTERVAL - 1 {
+            assert_eq!(mtu.on_ack(PacketId(100 + i as u16)), None);
+        }
+        assert_eq!(mtu.on_ack(PacketId(0)), Some(600 + PROBE_STEP));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut mtu = PathMtu::new(MtuConfig {
+            max_packet_size: 100,
+            discovery: false,
+        });
+        assert_eq!(mtu.max_packet_size(), MIN_PACKET_SIZE);
+        for i in 0..10 {
+            mtu.on_sent(PacketId(i), MIN_PACKET_SIZE);
+            assert_eq!(mtu.on_lost(PacketId(i)), None);
+        }
+    }
+}
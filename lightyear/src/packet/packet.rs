@@ -1,6 +1,6 @@
 /// Defines the [`Packet`] struct
 use crate::connection::netcode::MAX_PACKET_SIZE;
-use crate::packet::message::MessageAck;
+use crate::packet::message::{FragmentIndex, MessageAck};
 use crate::packet::packet_builder::Payload;
 use crate::protocol::channel::ChannelId;
 use crate::serialize::ToBytes;
@@ -24,13 +24,23 @@ wrapping_id!(PacketId);
 /// Number of bytes to write the header
 const HEADER_BYTES: usize = 11;
 
-/// The maximum number of bytes for a message before it is fragmented
-/// MAX_PACKET_SIZE - HEADER_BYTES - 1 (channel_net_id) - 6 (message_id/fragment_id/num_fragments) - 2 (num bytes in fragment)
+/// The maximum number of bytes for a message before it is fragmented, for packets of `max_packet_size` bytes
+/// max_packet_size - HEADER_BYTES - 1 (channel_net_id) - 6 (message_id/fragment_id/num_fragments) - 2 (num bytes in fragment)
 #[cfg(feature = "big_messages")]
-pub(crate) const FRAGMENT_SIZE: usize = MAX_PACKET_SIZE - HEADER_BYTES - 9;
+pub(crate) const fn fragment_size(max_packet_size: usize) -> usize {
+    max_packet_size - HEADER_BYTES - 9
+}
 
 #[cfg(not(feature = "big_messages"))]
-pub(crate) const FRAGMENT_SIZE: usize = MAX_PACKET_SIZE - HEADER_BYTES - 7;
+pub(crate) const fn fragment_size(max_packet_size: usize) -> usize {
+    max_packet_size - HEADER_BYTES - 7
+}
+
+/// The maximum number of bytes for a message before it is fragmented
+pub(crate) const FRAGMENT_SIZE: usize = fragment_size(MAX_PACKET_SIZE);
+
+/// The maximum number of bytes of a message, which can be split in at most [`FragmentIndex::MAX`] fragments
+pub(crate) const MAX_MESSAGE_SIZE: usize = FRAGMENT_SIZE * FragmentIndex::MAX as usize;
 
 /// Data structure that will help us write the packet
 #[derive(Debug)]
@@ -41,12 +51,14 @@ pub(crate) struct Packet {
     pub(crate) packet_id: PacketId,
     // How many bytes we know we are going to have to write in the packet, but haven't written yet
     pub(crate) prewritten_size: usize,
+    /// Maximum number of bytes in the packet
+    pub(crate) max_size: usize,
 }
 
 impl Packet {
     /// Check that we can still fit some data in the buffer
     pub(crate) fn can_fit(&self, size: usize) -> bool {
-        self.payload.len() + size + self.prewritten_size <= MAX_PACKET_SIZE
+        self.payload.len() + size + self.prewritten_size <= self.max_size
     }
 
     /// Check if we can write a channel_id + the number of messages in the packet.
@@ -92,8 +104,10 @@ impl Packet {
         while cursor.has_remaining() {
             let channel_id = ChannelId::from_bytes(&mut cursor)?;
             let num_messages = cursor.read_varint()?;
+            let mut previous_id = None;
             for i in 0..num_messages {
-                let single_data = SingleData::from_bytes(&mut cursor)?;
+                let single_data = SingleData::from_bytes_after(&mut cursor, previous_id)?;
+                previous_id = single_data.id.or(previous_id);
                 res.entry(channel_id).or_default().push(single_data.bytes);
             }
         }
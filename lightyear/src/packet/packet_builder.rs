@@ -1,8 +1,8 @@
 //! Module to take a buffer of messages to send and build packets
 use crate::connection::netcode::MAX_PACKET_SIZE;
-use crate::packet::header::PacketHeaderManager;
+use crate::packet::header::{AckBitfieldSize, PacketHeaderManager};
 use crate::packet::message::{FragmentData, MessageAck, SingleData};
-use crate::packet::packet::{Packet, FRAGMENT_SIZE};
+use crate::packet::packet::Packet;
 use crate::packet::packet_type::PacketType;
 use crate::prelude::Tick;
 use crate::protocol::channel::ChannelId;
@@ -31,6 +31,8 @@ pub type RecvPayload = Bytes;
 pub(crate) struct PacketBuilder {
     pub(crate) header_manager: PacketHeaderManager,
     current_packet: Option<Packet>,
+    /// Maximum number of bytes in a packet
+    pub(crate) max_packet_size: usize,
     // Pre-allocated buffer to encode/decode without allocation.
     // TODO: should this be associated with Packet?
     // cursor: Vec<u8>,
@@ -41,10 +43,11 @@ pub(crate) struct PacketBuilder {
 }
 
 impl PacketBuilder {
-    pub fn new(nack_rtt_multiple: f32) -> Self {
+    pub fn new(nack_rtt_multiple: f32, ack_bitfield_size: AckBitfieldSize) -> Self {
         Self {
-            header_manager: PacketHeaderManager::new(nack_rtt_multiple),
+            header_manager: PacketHeaderManager::new(nack_rtt_multiple, ack_bitfield_size),
             current_packet: None,
+            max_packet_size: MAX_PACKET_SIZE,
             // cursor: Vec::with_capacity(PACKET_BUFFER_CAPACITY),
             // acks: Vec::new(),
 
@@ -57,7 +60,7 @@ impl PacketBuilder {
 
     // TODO: get the vec from a pool of preallocated buffers
     fn get_new_buffer(&self) -> Payload {
-        Vec::with_capacity(MAX_PACKET_SIZE)
+        Vec::with_capacity(self.max_packet_size)
     }
 
     /// Start building new packet, we start with an empty packet
@@ -69,17 +72,17 @@ impl PacketBuilder {
         let mut cursor = self.get_new_buffer();
 
         // write the header
-        let mut header = self
+        // the tick is the one at which the packet will be sent
+        let header = self
             .header_manager
-            .prepare_send_packet_header(PacketType::Data);
-        // set the tick at which the packet will be sent
-        header.tick = current_tick;
+            .prepare_send_packet_header(PacketType::Data, current_tick);
         header.to_bytes(&mut cursor)?;
         self.current_packet = Some(Packet {
             payload: cursor,
             message_acks: vec![],
             packet_id: header.packet_id,
             prewritten_size: 0,
+            max_size: self.max_packet_size,
         });
         Ok(())
     }
@@ -92,11 +95,10 @@ impl PacketBuilder {
     ) -> Result<(), SerializationError> {
         let mut cursor = self.get_new_buffer();
         // writer the header
-        let mut header = self
+        // the tick is the one at which the packet will be sent
+        let header = self
             .header_manager
-            .prepare_send_packet_header(PacketType::DataFragment);
-        // set the tick at which the packet will be sent
-        header.tick = current_tick;
+            .prepare_send_packet_header(PacketType::DataFragment, current_tick);
         header.to_bytes(&mut cursor)?;
         channel_id.to_bytes(&mut cursor)?;
         fragment_data.to_bytes(&mut cursor)?;
@@ -112,6 +114,7 @@ impl PacketBuilder {
             )],
             packet_id: header.packet_id,
             prewritten_size: 0,
+            max_size: self.max_packet_size,
         });
         Ok(())
 
@@ -172,7 +175,6 @@ impl PacketBuilder {
         // try to fill the packet with fragment messages first
         for (channel_id, mut fragment_messages) in fragment_data.into_iter() {
             while let Some(fragment_data) = fragment_messages.pop_front() {
-                debug_assert!(fragment_data.bytes.len() <= FRAGMENT_SIZE);
                 self.build_new_fragment_packet(channel_id, &fragment_data, current_tick)?;
                 if !fragment_data.is_last_fragment() {
                     // big fragment, write packet immediately
@@ -233,7 +235,8 @@ impl PacketBuilder {
         'out: while single_data_idx < single_data.len() {
             let (channel_id, single_messages) = &mut single_data[single_data_idx];
             // start a new packet if we aren't already writing one
-            if self.current_packet.is_none() {
+            let new_packet = self.current_packet.is_none();
+            if new_packet {
                 self.build_new_single_packet(current_tick)?;
             }
 
@@ -265,7 +268,11 @@ impl PacketBuilder {
                     break;
                 }
 
-                if packet.can_fit(single_messages[num_messages].len()) {
+                // a message that was buffered before the maximum packet size was reduced might not fit in
+                // an empty packet; we still send it to avoid getting stuck
+                if packet.can_fit(single_messages[num_messages].len())
+                    || (new_packet && num_messages == 0)
+                {
                     packet.prewritten_size += single_messages[num_messages].len();
                     num_messages += 1;
                 } else {
@@ -307,11 +314,15 @@ impl PacketBuilder {
             channel_id.to_bytes(&mut packet.payload)?;
             // write the number of messages for the current channel
             packet.payload.write_u8(*num_messages as u8).unwrap();
-            // write the messages
+            // write the messages, the ids are delta-encoded against the previous message of the channel
+            let mut previous_id = None;
             for _ in 0..*num_messages {
                 // TODO: deal with error
                 let message = messages.pop_front().unwrap();
-                message.to_bytes(&mut packet.payload).unwrap();
+                message
+                    .to_bytes_after(previous_id, &mut packet.payload)
+                    .unwrap();
+                previous_id = message.id.or(previous_id);
                 packet.prewritten_size = packet
                     .prewritten_size
                     .checked_sub(message.len())
@@ -427,6 +438,7 @@ mod tests {
 
     use crate::channel::senders::fragment_sender::FragmentSender;
     use crate::packet::message::MessageId;
+    use crate::packet::packet::FRAGMENT_SIZE;
     use crate::prelude::*;
 
     use super::*;
@@ -456,7 +468,7 @@ mod tests {
     #[test]
     fn test_pack_small_messages() -> Result<(), PacketError> {
         let channel_registry = get_channel_registry();
-        let mut manager = PacketBuilder::new(1.5);
+        let mut manager = PacketBuilder::new(1.5, AckBitfieldSize::default());
         let channel_kind1 = ChannelKind::of::<Channel1>();
         let channel_id1 = channel_registry.get_net_from_kind(&channel_kind1).unwrap();
         let channel_kind2 = ChannelKind::of::<Channel2>();
@@ -504,7 +516,7 @@ mod tests {
     #[test]
     fn test_pack_cannot_write_channel_id() -> Result<(), PacketError> {
         let channel_registry = get_channel_registry();
-        let mut manager = PacketBuilder::new(1.5);
+        let mut manager = PacketBuilder::new(1.5, AckBitfieldSize::default());
         let channel_kind1 = ChannelKind::of::<Channel1>();
         let channel_id1 = channel_registry.get_net_from_kind(&channel_kind1).unwrap();
         let channel_kind2 = ChannelKind::of::<Channel2>();
@@ -538,7 +550,7 @@ mod tests {
     #[test]
     fn test_pack_many_small_messages() -> Result<(), PacketError> {
         let channel_registry = get_channel_registry();
-        let mut manager = PacketBuilder::new(1.5);
+        let mut manager = PacketBuilder::new(1.5, AckBitfieldSize::default());
         let channel_kind1 = ChannelKind::of::<Channel1>();
         let channel_id1 = channel_registry.get_net_from_kind(&channel_kind1).unwrap();
         let channel_kind2 = ChannelKind::of::<Channel2>();
@@ -573,7 +585,7 @@ mod tests {
     #[test]
     fn test_pack_single_data_multiple_packets() -> Result<(), PacketError> {
         let channel_registry = get_channel_registry();
-        let mut manager = PacketBuilder::new(1.5);
+        let mut manager = PacketBuilder::new(1.5, AckBitfieldSize::default());
         let channel_kind1 = ChannelKind::of::<Channel1>();
         let channel_id1 = channel_registry.get_net_from_kind(&channel_kind1).unwrap();
         let channel_kind2 = ChannelKind::of::<Channel2>();
@@ -606,7 +618,7 @@ mod tests {
     #[test]
     fn test_pack_big_messages() -> Result<(), PacketError> {
         let channel_registry = get_channel_registry();
-        let mut manager = PacketBuilder::new(1.5);
+        let mut manager = PacketBuilder::new(1.5, AckBitfieldSize::default());
         let channel_kind1 = ChannelKind::of::<Channel1>();
         let channel_id1 = channel_registry.get_net_from_kind(&channel_kind1).unwrap();
         let channel_kind2 = ChannelKind::of::<Channel2>();
@@ -1,6 +1,7 @@
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, Instant};
 use std::collections::VecDeque;
 use std::num::NonZeroU32;
+use std::sync::Arc;
 
 use crossbeam_channel::{Receiver, Sender};
 use governor::{DefaultDirectRateLimiter, Quota};
@@ -29,6 +30,8 @@ pub struct PriorityConfig {
     pub bandwidth_quota: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
     pub enabled: bool,
+    /// Maximum number of bytes of messages that can be sent on each tick, independently of the bandwidth cap
+    pub tick_budget: Option<u32>,
 }
 
 // this is mostly for testing
@@ -38,6 +41,7 @@ impl Default for PriorityConfig {
             // 56 KB/s bandwidth cap
             bandwidth_quota: Quota::per_second(nonzero!(56000u32)),
             enabled: false,
+            tick_budget: None,
         }
     }
 }
@@ -47,6 +51,7 @@ impl From<crate::client::config::PacketConfig> for PriorityConfig {
         Self {
             bandwidth_quota: value.send_bandwidth_cap,
             enabled: value.bandwidth_cap_enabled,
+            tick_budget: value.send_budget_per_tick,
         }
     }
 }
@@ -56,6 +61,7 @@ impl From<crate::server::config::PacketConfig> for PriorityConfig {
         Self {
             bandwidth_quota: value.per_client_send_bandwidth_cap,
             enabled: value.bandwidth_cap_enabled,
+            tick_budget: value.per_client_send_budget_per_tick,
         }
     }
 }
@@ -65,6 +71,11 @@ pub(crate) struct PriorityManager {
     pub(crate) config: PriorityConfig,
     // TODO: can I do without this limiter?
     pub(crate) limiter: DefaultDirectRateLimiter,
+    /// Rate limiter shared with other connections (for example the connections of the same
+    /// [tenant](crate::server::tenant)). If set, it is used instead of `limiter`
+    pub(crate) shared_limiter: Option<Arc<DefaultDirectRateLimiter>>,
+    /// Maximum outgoing bandwidth of this connection, applied on top of the other quotas
+    pub(crate) max_bandwidth_limiter: Option<BandwidthLimiter>,
     // // Internal buffer of data that we want to send
     // // Reuse allocation across frames
     // data_to_send: BTreeMap<ChannelId, (VecDeque<SendMessage>, VecDeque<SendMessage>)>,
@@ -72,6 +83,9 @@ pub(crate) struct PriorityManager {
     // buffered_data: Vec<BufferedMessage>,
     /// List of senders to notify when a replication update message is actually sent (included in packet)
     replication_update_senders: Vec<Sender<MessageId>>,
+    /// True if some messages could not be sent during the last call to `priority_filter` because
+    /// the bandwidth quota was reached
+    pub(crate) budget_exceeded: bool,
 }
 
 impl PriorityManager {
@@ -79,9 +93,41 @@ impl PriorityManager {
         Self {
             config: config.clone(),
             limiter: DefaultDirectRateLimiter::direct(config.bandwidth_quota),
+            shared_limiter: None,
+            max_bandwidth_limiter: None,
             // data_to_send: BTreeMap::new(),
             // buffered_data: Vec::new(),
             replication_update_senders: Vec::new(),
+            budget_exceeded: false,
+        }
+    }
+
+    /// Returns true if the messages are filtered by a bandwidth quota
+    pub(crate) fn is_limited(&self) -> bool {
+        self.is_rate_limited() || self.config.tick_budget.is_some()
+    }
+
+    /// Returns true if the messages are filtered by a rate limiter
+    fn is_rate_limited(&self) -> bool {
+        self.config.enabled || self.shared_limiter.is_some() || self.max_bandwidth_limiter.is_some()
+    }
+
+    /// Bandwidth quota of this connection, if any
+    fn quota_limiter(&self) -> Option<&DefaultDirectRateLimiter> {
+        (self.config.enabled || self.shared_limiter.is_some())
+            .then(|| self.shared_limiter.as_deref().unwrap_or(&self.limiter))
+    }
+
+    /// Charge `bytes` that were sent without being checked to all the rate limiters, if they have enough
+    /// capacity left
+    pub(crate) fn charge(&mut self, bytes: NonZeroU32) {
+        if let Some(limiter) = self.quota_limiter() {
+            let _ = limiter.check_n(bytes);
+        }
+        if let Some(limiter) = self.max_bandwidth_limiter.as_mut() {
+            if limiter.check_n(bytes.get()) == Ok(true) {
+                limiter.consume(bytes.get());
+            }
         }
     }
 
@@ -110,7 +156,8 @@ impl PriorityManager {
     ) {
         // if the bandwidth quota is disabled, just pass all messages through
         // As an optimization: no need to send the tick of the message, it is the same as the header tick
-        if !self.config.enabled {
+        if !self.is_limited() {
+            self.budget_exceeded = false;
             let mut single_data = vec![];
             let mut fragment_data = vec![];
             for (net_id, (single, fragment)) in data {
@@ -182,22 +229,65 @@ impl PriorityManager {
         let mut single_data: HashMap<ChannelId, VecDeque<SingleData>> = HashMap::new();
         let mut fragment_data: HashMap<ChannelId, VecDeque<FragmentData>> = HashMap::new();
         let mut bytes_used = 0;
+        let rate_limited = self.is_rate_limited();
         while let Some(buffered_message) = all_messages.pop() {
             // we don't use the exact size of the message, but the size of the bytes
             // we will adjust for this later
             let message_bytes = buffered_message.data.len() as u32;
-            let nonzero_message_bytes = NonZeroU32::try_from(message_bytes).unwrap();
-            let Ok(result) = self.limiter.check_n(nonzero_message_bytes) else {
-                error!("the bandwidth does not have enough capacity for a message of this size!");
-                break;
-            };
-
             // above BYPASS_QUOTA_PRIORITY, we still send the message
-            if buffered_message.priority < BYPASS_QUOTA_PRIORITY {
-                let Ok(()) = result else {
+            let bypass_quota = buffered_message.priority >= BYPASS_QUOTA_PRIORITY;
+            // the first message of the tick is always sent, so that a message larger than the budget
+            // doesn't block the connection
+            if !bypass_quota
+                && bytes_used > 0
+                && self
+                    .config
+                    .tick_budget
+                    .is_some_and(|budget| bytes_used + message_bytes > budget)
+            {
+                debug!("Tick budget reached, no more messages can be sent this tick");
+                // put the message back so that it is counted as not sent
+                all_messages.push(buffered_message);
+                break;
+            }
+            if rate_limited {
+                let nonzero_message_bytes = NonZeroU32::try_from(message_bytes).unwrap();
+                let mut insufficient_capacity = false;
+                let mut quota_reached = false;
+                // the maximum bandwidth is checked first because it is only charged below, so that the
+                // quota is not charged for a message that is not sent
+                match self
+                    .max_bandwidth_limiter
+                    .as_mut()
+                    .map(|limiter| limiter.check_n(message_bytes))
+                {
+                    Some(Err(_)) => insufficient_capacity = true,
+                    Some(Ok(false)) => quota_reached = true,
+                    _ => {}
+                }
+                if !insufficient_capacity && (bypass_quota || !quota_reached) {
+                    if let Some(limiter) = self.quota_limiter() {
+                        match limiter.check_n(nonzero_message_bytes) {
+                            Err(_) => insufficient_capacity = true,
+                            Ok(Err(_)) => quota_reached = true,
+                            Ok(Ok(())) => {}
+                        }
+                    }
+                }
+                if insufficient_capacity {
+                    error!(
+                        "the bandwidth does not have enough capacity for a message of this size!"
+                    );
+                    break;
+                }
+                if !bypass_quota && quota_reached {
                     debug!("Bandwidth quota reached, no more messages can be sent this tick");
+                    all_messages.push(buffered_message);
                     break;
-                };
+                }
+                if let Some(limiter) = self.max_bandwidth_limiter.as_mut() {
+                    limiter.consume(message_bytes);
+                }
             }
             trace!(channel=?buffered_message.channel_net_id, "Sending message with priority {:?}", buffered_message.priority);
 
@@ -243,6 +333,7 @@ impl PriorityManager {
         //   - PROBLEM: we could have the entity action not get sent (bandwidth), and then the priority still drops because the entity update
         //     was sent right after...
         // - reliable entity actions:
+        self.budget_exceeded = !all_messages.is_empty();
         let num_messages_sent = single_data.values().map(|data| data.len()).sum::<usize>()
             + fragment_data.values().map(|data| data.len()).sum::<usize>();
         debug!(
@@ -258,3 +349,57 @@ impl PriorityManager {
         )
     }
 }
+
+/// Error returned when a message is larger than the burst size of a [`BandwidthLimiter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct InsufficientCapacity;
+
+/// Token bucket that limits the bytes sent per second.
+///
+/// Unlike the governor rate limiters, its capacity can be checked without consuming it, so that a message
+/// is only charged once all the rate limiters of the connection accepted it.
+#[derive(Debug)]
+pub(crate) struct BandwidthLimiter {
+    /// Bytes replenished per second
+    rate: f64,
+    /// Maximum number of bytes that can be sent at once
+    burst: f64,
+    available: f64,
+    last_update: Instant,
+}
+
+impl BandwidthLimiter {
+    pub(crate) fn new(quota: Quota) -> Self {
+        let burst = quota.burst_size().get() as f64;
+        Self {
+            rate: 1.0 / quota.replenish_interval().as_secs_f64(),
+            burst,
+            available: burst,
+            last_update: Instant::now(),
+        }
+    }
+
+    fn replenish(&mut self) {
+        let now = Instant::now();
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f64();
+        self.available = (self.available + elapsed * self.rate).min(self.burst);
+        self.last_update = now;
+    }
+
+    /// Returns true if `bytes` can be sent now, without consuming them
+    pub(crate) fn check_n(&mut self, bytes: u32) -> Result<bool, InsufficientCapacity> {
+        if bytes as f64 > self.burst {
+            return Err(InsufficientCapacity);
+        }
+        self.replenish();
+        Ok(bytes as f64 <= self.available)
+    }
+
+    /// Consume `bytes` that are sent
+    pub(crate) fn consume(&mut self, bytes: u32) {
+        self.replenish();
+        self.available = (self.available - bytes as f64).max(0.0);
+    }
+}
@@ -0,0 +1,146 @@
+//! Raw datagrams, sent alongside the lightyear packets on the same connection.
+//!
+//! The application can use them for its own datagram types (NAT keepalives, vendor telemetry, etc.) without
+//! going through the channels: they are not acked, not ordered, not fragmented and not limited by the bandwidth
+//! quotas.
+//!
+//! A raw datagram starts with a byte in a range that the lightyear packet headers never use, followed by the
+//! payload. The application picks the kind of each datagram (between 0 and [`MAX_RAW_DATAGRAM_KIND`]), so that
+//! the receiving side can tell its datagram types apart.
+use bytes::Bytes;
+
+use crate::packet::error::PacketError;
+use crate::packet::packet_builder::{Payload, RecvPayload};
+
+/// Flag set in the first byte of the raw datagrams.
+///
+/// The first byte of a lightyear packet contains the packet type in the lower bits and the number of extra
+/// ack bitfield words in the upper bits, so it is always below this value.
+const RAW_DATAGRAM_FLAG: u8 = 0x80;
+
+/// Maximum kind of a raw datagram
+pub const MAX_RAW_DATAGRAM_KIND: u8 = !RAW_DATAGRAM_FLAG;
+
+/// Returns true if the packet is a raw datagram
+pub(crate) fn is_raw_datagram(packet: &[u8]) -> bool {
+    packet
+        .first()
+        .is_some_and(|byte| byte & RAW_DATAGRAM_FLAG != 0)
+}
+
+/// Write a raw datagram of the given kind, that must fit in a packet of `max_packet_size` bytes
+pub(crate) fn write_raw_datagram(
+    kind: u8,
+    payload: &[u8],
+    max_packet_size: usize,
+) -> Result<Payload, PacketError> {
+    if kind > MAX_RAW_DATAGRAM_KIND {
+        return Err(PacketError::InvalidRawDatagramKind(kind));
+    }
+    if payload.len() + 1 > max_packet_size {
+        return Err(PacketError::RawDatagramTooLarge(payload.len()));
+    }
+    let mut datagram = Vec::with_capacity(payload.len() + 1);
+    datagram.push(RAW_DATAGRAM_FLAG | kind);
+    datagram.extend_from_slice(payload);
+    Ok(datagram)
+}
+
+/// Read the kind and the payload of a raw datagram
+pub(crate) fn read_raw_datagram(mut packet: RecvPayload) -> (u8, Bytes) {
+    let payload = packet.split_off(1);
+    (packet[0] & MAX_RAW_DATAGRAM_KIND, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packet::header::PacketHeaderManager;
+    use crate::packet::packet_type::PacketType;
+    use bevy::prelude::Events;
+
+    use crate::prelude::{client, server, AckBitfieldSize, ClientId};
+    use crate::serialize::writer::Writer;
+    use crate::serialize::ToBytes;
+    use crate::shared::tick_manager::Tick;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[test]
+    fn test_raw_datagram() {
+        let datagram = write_raw_datagram(5, b"ping", 1200).unwrap();
+        assert!(is_raw_datagram(&datagram));
+        assert_eq!(
+            read_raw_datagram(datagram.into()),
+            (5, Bytes::from_static(b"ping"))
+        );
+        assert!(matches!(
+            write_raw_datagram(MAX_RAW_DATAGRAM_KIND + 1, b"ping", 1200),
+            Err(PacketError::InvalidRawDatagramKind(_))
+        ));
+        assert!(matches!(
+            write_raw_datagram(0, &[0; 1200], 1200),
+            Err(PacketError::RawDatagramTooLarge(1200))
+        ));
+
+        // the lightyear packets are never mistaken for raw datagrams
+        for packet_type in [PacketType::Data, PacketType::DataFragment] {
+            for ack_bitfield_size in [AckBitfieldSize::Bits32, AckBitfieldSize::Bits128] {
+                let mut writer = Writer::default();
+                PacketHeaderManager::new(1.5, ack_bitfield_size)
+                    .prepare_send_packet_header(packet_type, Tick(0))
+                    .to_bytes(&mut writer)
+                    .unwrap();
+                assert!(!is_raw_datagram(&writer.to_bytes()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_send_raw() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ConnectionManager>()
+            .send_raw(3, b"keepalive")
+            .unwrap();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .send_raw(ClientId::Netcode(TEST_CLIENT_ID), 4, b"telemetry")
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let server_events = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<Events<server::RawDatagramEvent>>()
+            .drain()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            server_events,
+            vec![server::RawDatagramEvent {
+                kind: 3,
+                payload: Bytes::from_static(b"keepalive"),
+                context: ClientId::Netcode(TEST_CLIENT_ID),
+            }]
+        );
+        let client_events = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<Events<client::RawDatagramEvent>>()
+            .drain()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            client_events,
+            vec![client::RawDatagramEvent {
+                kind: 4,
+                payload: Bytes::from_static(b"telemetry"),
+                context: (),
+            }]
+        );
+    }
+}
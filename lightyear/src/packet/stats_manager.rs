@@ -124,6 +124,11 @@ pub(crate) mod packet {
             self.current_stats.num_sent_packets_acked += 1;
         }
 
+        /// Ratio of the sent packets that were lost over the stats buffer duration
+        pub(crate) fn packet_loss(&self) -> f32 {
+            self.final_stats.packet_loss
+        }
+
         /// Notify that we received a packet
         pub(crate) fn received_packet(&mut self) {
             #[cfg(feature = "metrics")]
@@ -1,3 +1,4 @@
+use crate::protocol::plugin::ProtocolPlugins;
 use bevy::app::App;
 use bevy::prelude::{Resource, TypePath};
 use bevy::utils::Duration;
@@ -5,24 +6,32 @@ use std::any::TypeId;
 use std::collections::HashMap;
 
 use crate::channel::builder::{
-    AuthorityChannel, Channel, ChannelBuilder, ChannelSettings, PongChannel,
+    AuthorityChannel, BaselineChannel, Channel, ChannelBuilder, ChannelSettings, ControlChannel,
+    PongChannel, TelemetryChannel, TransientChannel,
 };
 use crate::channel::builder::{
     ChannelContainer, EntityActionsChannel, EntityUpdatesChannel, InputChannel, PingChannel,
 };
-use crate::prelude::{ChannelMode, ReliableSettings};
+use crate::prelude::{ChannelMode, CompressionConfig, ReliableSettings};
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
 
 // TODO: derive Reflect once we reach bevy 0.14
 /// ChannelKind - internal wrapper around the type of the channel
 #[derive(Debug, Eq, Hash, Copy, Clone, PartialEq)]
-pub struct ChannelKind(TypeId);
+pub struct ChannelKind(ChannelKindId);
+
+/// Identifies a channel by its type, or by its index for the channels registered at runtime
+#[derive(Debug, Eq, Hash, Copy, Clone, PartialEq)]
+enum ChannelKindId {
+    Type(TypeId),
+    Dynamic(usize),
+}
 
 pub type ChannelId = NetId;
 
 impl ChannelKind {
     pub fn of<C: Channel>() -> Self {
-        Self(TypeId::of::<C>())
+        Self(ChannelKindId::Type(TypeId::of::<C>()))
     }
 }
 
@@ -30,7 +39,7 @@ impl TypeKind for ChannelKind {}
 
 impl From<TypeId> for ChannelKind {
     fn from(type_id: TypeId) -> Self {
-        Self(type_id)
+        Self(ChannelKindId::Type(type_id))
     }
 }
 
@@ -64,6 +73,8 @@ pub struct ChannelRegistry {
     pub(in crate::protocol) builder_map: HashMap<ChannelKind, ChannelBuilder>,
     pub(in crate::protocol) kind_map: TypeMapper<ChannelKind>,
     pub(in crate::protocol) name_map: HashMap<ChannelKind, String>,
+    /// Channels registered at runtime, by name
+    dynamic_map: HashMap<String, ChannelKind>,
     built: bool,
 }
 
@@ -73,6 +84,7 @@ impl ChannelRegistry {
             builder_map: HashMap::new(),
             kind_map: TypeMapper::new(),
             name_map: HashMap::new(),
+            dynamic_map: HashMap::new(),
             built: false,
         };
         registry.add_channel::<EntityUpdatesChannel>(ChannelSettings {
@@ -83,6 +95,8 @@ impl ChannelRegistry {
             // directly on the replication_sender
             send_frequency: Duration::default(),
             priority: 1.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         });
         registry.add_channel::<EntityActionsChannel>(ChannelSettings {
             mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
@@ -93,30 +107,72 @@ impl ChannelRegistry {
             send_frequency: Duration::default(),
             // we want to send the entity actions as soon as possible
             priority: 10.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         });
         registry.add_channel::<PingChannel>(ChannelSettings {
             mode: ChannelMode::SequencedUnreliable,
             send_frequency: Duration::default(),
             // we always want to include the ping in the packet
             priority: f32::INFINITY,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         });
         registry.add_channel::<PongChannel>(ChannelSettings {
             mode: ChannelMode::SequencedUnreliable,
             send_frequency: Duration::default(),
             // we always want to include the pong in the packet
             priority: f32::INFINITY,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         });
         registry.add_channel::<InputChannel>(ChannelSettings {
             mode: ChannelMode::UnorderedUnreliable,
             send_frequency: input_send_interval,
             // we always want to include the inputs in the packet
             priority: f32::INFINITY,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         });
         registry.add_channel::<AuthorityChannel>(ChannelSettings {
             mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
             send_frequency: Duration::default(),
             // we want to send the authority transfers as soon as possible
             priority: 10.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
+        });
+        registry.add_channel::<BaselineChannel>(ChannelSettings {
+            mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
+            send_frequency: Duration::default(),
+            // the report must arrive quickly so that the server can start replicating the baseline
+            priority: 10.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
+        });
+        registry.add_channel::<ControlChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            send_frequency: Duration::default(),
+            priority: 10.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
+        });
+        registry.add_channel::<TransientChannel>(ChannelSettings {
+            mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
+            send_frequency: Duration::default(),
+            // transient entities are short-lived, so they should be spawned as soon as possible
+            priority: 10.0,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
+        });
+        // registered last so that the ids of the other built-in channels don't change
+        registry.add_channel::<TelemetryChannel>(ChannelSettings {
+            mode: ChannelMode::SequencedUnreliable,
+            send_frequency: Duration::default(),
+            // the telemetry is the first to be dropped if the bandwidth is limited
+            priority: 0.5,
+            compression: CompressionConfig::None,
+            replay_on_join: None,
         });
         registry
     }
@@ -157,6 +213,43 @@ impl ChannelRegistry {
         self.name_map.insert(kind, name.to_string());
     }
 
+    /// Register a channel at runtime, without a [`Channel`] type (for example from a configuration file).
+    ///
+    /// The messages are sent on the channel with the methods that take a [`ChannelKind`], such as
+    /// [`send_message_to_target_on_channel`](crate::prelude::server::ConnectionManager::send_message_to_target_on_channel).
+    /// The client and the server must register the same dynamic channels, in the same order.
+    ///
+    /// Panics if a dynamic channel with the same name is already registered
+    pub fn add_dynamic_channel(
+        &mut self,
+        name: impl Into<String>,
+        settings: ChannelSettings,
+    ) -> ChannelKind {
+        let name = name.into();
+        if self.dynamic_map.contains_key(&name) {
+            panic!("Dynamic channel {name:?} already registered");
+        }
+        let kind = ChannelKind(ChannelKindId::Dynamic(self.dynamic_map.len()));
+        self.kind_map.add_kind(kind);
+        self.builder_map.insert(kind, ChannelBuilder { settings });
+        self.name_map.insert(kind, name.clone());
+        self.dynamic_map.insert(name, kind);
+        kind
+    }
+
+    /// Returns the kind of the dynamic channel registered with this name
+    pub fn dynamic_channel(&self, name: &str) -> Option<ChannelKind> {
+        self.dynamic_map.get(name).copied()
+    }
+
+    /// Assign an explicit network id to the channel `C`, so that it doesn't depend on the registration order.
+    ///
+    /// Panics if the channel is not registered or if the id is already used by another channel
+    /// (including the internal channels of lightyear, which are registered first)
+    pub fn set_net_id<C: Channel>(&mut self, net_id: ChannelId) {
+        self.kind_map.set_net_id::<C>(net_id);
+    }
+
     /// get the registered object for a given type
     pub fn get_builder_from_kind(&self, channel_kind: &ChannelKind) -> Option<&ChannelBuilder> {
         self.builder_map.get(channel_kind)
@@ -188,21 +281,55 @@ impl ChannelRegistry {
 /// Add a message to the list of messages that can be sent
 pub trait AppChannelExt {
     fn add_channel<C: Channel>(&mut self, settings: ChannelSettings);
+
+    /// Add a channel with an explicit network id, that doesn't depend on the registration order
+    fn add_channel_with_net_id<C: Channel>(&mut self, settings: ChannelSettings, net_id: ChannelId);
+
+    /// Add a channel at runtime, without a [`Channel`] type (see [`ChannelRegistry::add_dynamic_channel`])
+    fn add_dynamic_channel(
+        &mut self,
+        name: impl Into<String>,
+        settings: ChannelSettings,
+    ) -> ChannelKind;
 }
 
 impl AppChannelExt for App {
     fn add_channel<C: Channel>(&mut self, settings: ChannelSettings) {
+        ProtocolPlugins::record::<C>(self.world_mut());
         let mut registry = self.world_mut().resource_mut::<ChannelRegistry>();
         registry.add_channel::<C>(settings);
     }
+
+    fn add_channel_with_net_id<C: Channel>(
+        &mut self,
+        settings: ChannelSettings,
+        net_id: ChannelId,
+    ) {
+        self.add_channel::<C>(settings);
+        let mut registry = self.world_mut().resource_mut::<ChannelRegistry>();
+        registry.set_net_id::<C>(net_id);
+    }
+
+    fn add_dynamic_channel(
+        &mut self,
+        name: impl Into<String>,
+        settings: ChannelSettings,
+    ) -> ChannelKind {
+        let mut registry = self.world_mut().resource_mut::<ChannelRegistry>();
+        registry.add_dynamic_channel(name, settings)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use bevy::prelude::{default, TypePath};
+    use bevy::prelude::{default, EventReader, ResMut, TypePath, Update};
     use lightyear_macros::ChannelInternal;
 
     use crate::channel::builder::{ChannelMode, ChannelSettings};
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::{client, server, NetworkTarget, SharedConfig, TickConfig};
+    use crate::tests::protocol::StringMessage;
+    use crate::tests::stepper::BevyStepper;
 
     use super::*;
 
@@ -227,4 +354,111 @@ mod tests {
             ChannelMode::UnorderedUnreliable
         );
     }
+    #[test]
+    fn test_dynamic_channel() {
+        let mut registry = ChannelRegistry::default();
+        registry.add_channel::<MyChannel>(ChannelSettings::default());
+        let kind = registry.add_dynamic_channel(
+            "mod_chat",
+            ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            },
+        );
+        assert_eq!(registry.dynamic_channel("mod_chat"), Some(kind));
+        assert_eq!(registry.dynamic_channel("other"), None);
+        assert_ne!(kind, ChannelKind::of::<MyChannel>());
+        assert_eq!(registry.get_net_from_kind(&kind), Some(&1));
+        assert_eq!(registry.name(&kind), Some("mod_chat"));
+        assert!(registry
+            .get_builder_from_net_id(1)
+            .unwrap()
+            .settings
+            .mode
+            .is_reliable());
+    }
+
+    #[test]
+    fn test_send_on_dynamic_channel() {
+        #[derive(Resource, Default)]
+        struct Received(Vec<String>);
+
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            ClientConfig::default(),
+            frame_duration,
+        );
+        for app in [&mut stepper.client_app, &mut stepper.server_app] {
+            app.add_dynamic_channel(
+                "mod_chat",
+                ChannelSettings {
+                    mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                    ..default()
+                },
+            );
+            app.init_resource::<Received>();
+        }
+        stepper.client_app.add_systems(
+            Update,
+            |mut received: ResMut<Received>,
+             mut events: EventReader<client::MessageEvent<StringMessage>>| {
+                received
+                    .0
+                    .extend(events.read().map(|event| event.message().0.clone()));
+            },
+        );
+        stepper.server_app.add_systems(
+            Update,
+            |mut received: ResMut<Received>,
+             mut events: EventReader<server::MessageEvent<StringMessage>>| {
+                received
+                    .0
+                    .extend(events.read().map(|event| event.message().0.clone()));
+            },
+        );
+        stepper.init();
+
+        let kind = stepper
+            .server_app
+            .world()
+            .resource::<ChannelRegistry>()
+            .dynamic_channel("mod_chat")
+            .unwrap();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .send_message_to_target_on_channel(
+                &mut StringMessage("from server".to_string()),
+                kind,
+                NetworkTarget::All,
+            )
+            .unwrap();
+        let kind = stepper
+            .client_app
+            .world()
+            .resource::<ChannelRegistry>()
+            .dynamic_channel("mod_chat")
+            .unwrap();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ConnectionManager>()
+            .send_message_on_channel(&mut StringMessage("from client".to_string()), kind)
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.client_app.world().resource::<Received>().0,
+            vec!["from server".to_string()]
+        );
+        assert_eq!(
+            stepper.server_app.world().resource::<Received>().0,
+            vec!["from client".to_string()]
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use crate::protocol::plugin::ProtocolPlugins;
 use bevy::ecs::component::ComponentId;
 use bevy::ecs::entity::MapEntities;
 use std::any::TypeId;
@@ -5,8 +6,12 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use std::ops::{Add, Mul};
 
-use bevy::prelude::{App, Component, EntityWorldMut, Mut, Resource, TypePath, World};
+use bevy::prelude::{
+    App, AppTypeRegistry, Component, DetectChangesMut, EntityWorldMut, Mut, Resource, TypePath,
+    World,
+};
 use bevy::ptr::Ptr;
+use bevy::reflect::{FromReflect, GetTypeRegistration, TypeRegistryArc};
 use bevy::utils::HashMap;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -24,7 +29,7 @@ use crate::prelude::server::ServerConfig;
 use crate::prelude::{ChannelDirection, Message, Tick};
 use crate::protocol::delta::ErasedDeltaFns;
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
-use crate::protocol::serialize::{ErasedSerializeFns, SerializeFns};
+use crate::protocol::serialize::{ContextTransformFn, ErasedSerializeFns, MigrateFn, SerializeFns};
 use crate::serialize::reader::Reader;
 use crate::serialize::SerializationError;
 use crate::shared::events::connection::ConnectionEvents;
@@ -143,6 +148,8 @@ pub struct ComponentRegistry {
     prediction_map: HashMap<ComponentKind, PredictionMetadata>,
     serialize_fns_map: HashMap<ComponentKind, ErasedSerializeFns>,
     delta_fns_map: HashMap<ComponentKind, ErasedDeltaFns>,
+    /// Names registered with [`ComponentRegistration::with_name`]
+    protocol_names: HashMap<ComponentKind, &'static str>,
     pub(crate) kind_map: TypeMapper<ComponentKind>,
 }
 
@@ -238,6 +245,15 @@ impl ComponentRegistry {
         self.serialize_fns_map.get(&kind).unwrap().type_name
     }
 
+    /// Name of the component in the [protocol hash](crate::protocol::hash): the name registered with
+    /// [`ComponentRegistration::with_name`], or else the name of the type without its module path
+    pub fn protocol_name(&self, kind: ComponentKind) -> String {
+        self.protocol_names
+            .get(&kind)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| bevy::utils::get_short_name(self.name(kind)))
+    }
+
     pub fn is_registered<C: 'static>(&self) -> bool {
         self.kind_map.net_id(&ComponentKind::of::<C>()).is_some()
     }
@@ -285,6 +301,19 @@ impl ComponentRegistry {
             ErasedSerializeFns::new_custom_serde::<C>(serialize_fns),
         );
     }
+
+    pub(crate) fn register_component_reflect<
+        C: Message + FromReflect + TypePath + GetTypeRegistration,
+    >(
+        &mut self,
+        type_registry: TypeRegistryArc,
+    ) {
+        let component_kind = self.kind_map.add::<C>();
+        self.serialize_fns_map.insert(
+            component_kind,
+            ErasedSerializeFns::new_reflect::<C>(type_registry),
+        );
+    }
 }
 
 mod serialize {
@@ -313,6 +342,26 @@ mod serialize {
             erased_fns.add_map_entities::<C>();
         }
 
+        pub(crate) fn erased_fns_mut<C: 'static>(&mut self) -> &mut ErasedSerializeFns {
+            self.serialize_fns_map
+                .get_mut(&ComponentKind::of::<C>())
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Component {} is not part of the protocol",
+                        std::any::type_name::<C>()
+                    )
+                })
+        }
+
+        /// Iterate through the serialization functions of the components, with their network id
+        pub(crate) fn net_serialize_fns(
+            &self,
+        ) -> impl Iterator<Item = (ComponentNetId, &ErasedSerializeFns)> {
+            self.serialize_fns_map
+                .iter()
+                .filter_map(|(kind, fns)| Some((*self.kind_map.net_id(kind)?, fns)))
+        }
+
         /// Returns true if we have a registered `map_entities` function for this component type
         pub(crate) fn is_map_entities<C: 'static>(&self) -> bool {
             let kind = ComponentKind::of::<C>();
@@ -332,6 +381,14 @@ mod serialize {
             erased_fns.map_entities.is_some()
         }
 
+        /// Returns true if the component must be serialized separately for each connection, with the
+        /// [`SerializationContext`](crate::serialize::context::SerializationContext) of the connection
+        pub(crate) fn erased_is_context_dependent(&self, kind: ComponentKind) -> bool {
+            self.serialize_fns_map
+                .get(&kind)
+                .is_some_and(|erased_fns| erased_fns.context_dependent)
+        }
+
         pub(crate) fn serialize<C: 'static>(
             &self,
             component: &mut C,
@@ -566,6 +623,7 @@ mod replication {
     use crate::serialize::reader::Reader;
     use crate::serialize::ToBytes;
     use crate::shared::replication::entity_map::ReceiveEntityMap;
+    use crate::shared::replication::local_write::LocalWrites;
 
     impl ComponentRegistry {
         pub(crate) fn set_replication_fns<C: Component + PartialEq>(&mut self, world: &mut World) {
@@ -627,6 +685,22 @@ mod replication {
             trace!("Writing component {} to entity", std::any::type_name::<C>());
             let component = self.raw_deserialize::<C>(reader, net_id, entity_map)?;
             let entity = entity_world_mut.id();
+            // do not overwrite a local write with an update that the server sent before applying it
+            if entity_world_mut
+                .get_mut::<LocalWrites>()
+                .is_some_and(|mut writes| {
+                    writes
+                        .bypass_change_detection()
+                        .is_stale(ComponentKind::of::<C>(), tick)
+                })
+            {
+                trace!(
+                    ?entity,
+                    ?tick,
+                    "Ignoring a stale update because of a local write"
+                );
+                return Ok(());
+            }
             // TODO: should we send the event based on on the message type (Insert/Update) or based on whether the component was actually inserted?
             if let Some(mut c) = entity_world_mut.get_mut::<C>() {
                 // only apply the update if the component is different, to not trigger change detection
@@ -910,6 +984,20 @@ pub trait AppComponentExt {
         serialize_fns: SerializeFns<C>,
     ) -> ComponentRegistration<'_, C>;
 
+    /// Registers the component in the Registry: this component can now be sent over the network.
+    ///
+    /// The component is serialized using its [`Reflect`](bevy::reflect::Reflect) implementation and the
+    /// type data of the [`AppTypeRegistry`](bevy::prelude::AppTypeRegistry) of the app, so it doesn't need
+    /// to implement [`Serialize`] and [`DeserializeOwned`].
+    /// The fields that have the `ReflectSerialize` and `ReflectDeserialize` type data (i.e. `#[reflect(Serialize, Deserialize)]`)
+    /// are serialized with their serde implementation.
+    fn register_component_reflect<
+        C: Component + Message + PartialEq + FromReflect + TypePath + GetTypeRegistration,
+    >(
+        &mut self,
+        direction: ChannelDirection,
+    ) -> ComponentRegistration<'_, C>;
+
     /// Enable rollbacks for a component even if the component is not networked
     fn add_rollback<C: Component + PartialEq + Clone>(&mut self);
 
@@ -958,6 +1046,33 @@ pub struct ComponentRegistration<'a, C> {
 }
 
 impl<C> ComponentRegistration<'_, C> {
+    /// Assign an explicit network id to the component, so that it doesn't depend on the registration order.
+    ///
+    /// Panics if the id is already used by another component
+    pub fn with_net_id(self, net_id: ComponentNetId) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        registry.kind_map.set_net_id::<C>(net_id);
+        self
+    }
+
+    /// Name the component in the [protocol hash](crate::protocol::hash), instead of using the name of its type.
+    ///
+    /// The format of the type names is not guaranteed to be the same across compiler versions, so the components
+    /// should be named if the client and the server can be built with different compilers.
+    pub fn with_name(self, name: &'static str) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        registry
+            .protocol_names
+            .insert(ComponentKind::of::<C>(), name);
+        self
+    }
+
     /// Specify that the component contains entities which should be mapped from the remote world to the local world
     /// upon deserialization
     pub fn add_map_entities(self) -> Self
@@ -969,6 +1084,60 @@ impl<C> ComponentRegistration<'_, C> {
         self
     }
 
+    /// Serialize the component separately for each connection, so that its custom serialization functions
+    /// can read the [`SerializationContext`](crate::serialize::context::SerializationContext) of the connection
+    /// with [`Writer::context`](crate::serialize::writer::Writer::context)
+    pub fn serialize_with_context(self) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        registry.erased_fns_mut::<C>().set_context_dependent();
+        self
+    }
+
+    /// Adapt the component to the [`SerializationContext`](crate::serialize::context::SerializationContext)
+    /// of each connection: the transform is applied to a copy of the value before it is serialized.
+    ///
+    /// The transform is not applied to the diffs of the components that use delta compression.
+    pub fn add_context_transform(self, transform: ContextTransformFn<C>) -> Self
+    where
+        C: Clone + 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        registry
+            .erased_fns_mut::<C>()
+            .add_context_transform(transform);
+        self
+    }
+
+    /// Version the wire format of the component, so that the values written with an older version can still
+    /// be read with a [migration function](ComponentRegistration::add_migration).
+    ///
+    /// Each peer sends the versions of its types once when the connection starts, so the values doesn't carry
+    /// any version on the wire. A peer that doesn't register the component with a version is considered to use the
+    /// version 0.
+    pub fn with_version(self, version: u16) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        registry.erased_fns_mut::<C>().set_version(version);
+        self
+    }
+
+    /// Read the values that were written with an older version of the wire format.
+    ///
+    /// The values written with a newer version than the registered one are rejected.
+    pub fn add_migration(self, migrate: MigrateFn<C>) -> Self
+    where
+        C: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<ComponentRegistry>();
+        registry.erased_fns_mut::<C>().add_migration(migrate);
+        self
+    }
+
     /// Enable prediction systems for this component.
     /// You can specify the prediction [`ComponentSyncMode`]
     pub fn add_prediction(self, prediction_mode: ComponentSyncMode) -> Self
@@ -1038,6 +1207,22 @@ impl<C> ComponentRegistration<'_, C> {
         self
     }
 
+    /// Enable both prediction and interpolation for this component, using linear interpolation.
+    ///
+    /// Combined with [`DefaultSyncTarget::Ownership`](crate::prelude::server::DefaultSyncTarget::Ownership),
+    /// the component will be predicted on the clients that control the entity and interpolated on the other clients.
+    pub fn add_sync(self, sync_mode: ComponentSyncMode) -> Self
+    where
+        C: SyncComponent + Linear,
+    {
+        let registration = self.add_prediction(sync_mode).add_interpolation(sync_mode);
+        if sync_mode == ComponentSyncMode::Full {
+            registration.add_linear_interpolation_fn()
+        } else {
+            registration
+        }
+    }
+
     /// Add a `Interpolation` behaviour to this component.
     pub fn add_interpolation_fn(self, interpolation_fn: LerpFn<C>) -> Self
     where
@@ -1063,6 +1248,7 @@ impl AppComponentExt for App {
         &mut self,
         direction: ChannelDirection,
     ) -> ComponentRegistration<'_, C> {
+        ProtocolPlugins::record::<C>(self.world_mut());
         self.world_mut()
             .resource_scope(|world, mut registry: Mut<ComponentRegistry>| {
                 if !registry.is_registered::<C>() {
@@ -1083,6 +1269,7 @@ impl AppComponentExt for App {
         direction: ChannelDirection,
         serialize_fns: SerializeFns<C>,
     ) -> ComponentRegistration<'_, C> {
+        ProtocolPlugins::record::<C>(self.world_mut());
         self.world_mut()
             .resource_scope(|world, mut registry: Mut<ComponentRegistry>| {
                 if !registry.is_registered::<C>() {
@@ -1098,6 +1285,30 @@ impl AppComponentExt for App {
         }
     }
 
+    fn register_component_reflect<
+        C: Component + Message + PartialEq + FromReflect + TypePath + GetTypeRegistration,
+    >(
+        &mut self,
+        direction: ChannelDirection,
+    ) -> ComponentRegistration<'_, C> {
+        self.register_type::<C>();
+        let type_registry = self.world().resource::<AppTypeRegistry>().0.clone();
+        ProtocolPlugins::record::<C>(self.world_mut());
+        self.world_mut()
+            .resource_scope(|world, mut registry: Mut<ComponentRegistry>| {
+                if !registry.is_registered::<C>() {
+                    registry.register_component_reflect::<C>(type_registry);
+                }
+                registry.set_replication_fns::<C>(world);
+                debug!("register component {}", std::any::type_name::<C>());
+            });
+        register_component_send::<C>(self, direction);
+        ComponentRegistration {
+            app: self,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
     // TODO: move this away from protocol? since it doesn't even use the registry at all
     //  maybe put this in the PredictionPlugin?
     fn add_rollback<C: Component + PartialEq + Clone>(&mut self) {
@@ -1216,8 +1427,12 @@ impl From<TypeId> for ComponentKind {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prelude::{client, server, SharedConfig, TickConfig};
     use crate::serialize::writer::Writer;
     use crate::tests::protocol::*;
+    use crate::tests::stepper::BevyStepper;
+    use bevy::prelude::{default, Reflect};
+    use bevy::utils::Duration;
 
     #[test]
     fn test_custom_serde() {
@@ -1240,4 +1455,77 @@ mod tests {
             .unwrap();
         assert_eq!(component, read);
     }
+
+    #[derive(Component, Reflect, Debug, Clone, PartialEq)]
+    enum ComponentReflect {
+        Empty,
+        Named { name: String, values: Vec<u32> },
+    }
+
+    /// A component that only implements `Reflect` can be replicated
+    #[test]
+    fn test_register_component_reflect() {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), frame_duration);
+        stepper
+            .client_app
+            .register_component_reflect::<ComponentReflect>(ChannelDirection::ServerToClient);
+        stepper
+            .server_app
+            .register_component_reflect::<ComponentReflect>(ChannelDirection::ServerToClient);
+        stepper.init();
+
+        let component = ComponentReflect::Named {
+            name: "a".to_string(),
+            values: vec![1, 2],
+        };
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((server::Replicate::default(), component.clone()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentReflect>(client_entity),
+            Some(&component)
+        );
+
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentReflect::Empty);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentReflect>(client_entity),
+            Some(&ComponentReflect::Empty)
+        );
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<AppTypeRegistry>()
+            .read()
+            .get(TypeId::of::<ComponentReflect>())
+            .is_some());
+    }
 }
@@ -0,0 +1,328 @@
+//! Detect accidental changes of the wire format of the protocol.
+//!
+//! Peers that run different builds of the game can only talk to each other if they serialize
+//! the messages and components in exactly the same way. A change of a field, of the serialization
+//! functions or of the order in which the types are registered silently changes the bytes that are sent.
+//!
+//! A [`WireSnapshot`] records the serialized form of some example values, including the network id of
+//! their type. It can be compared against a golden file that is committed next to the tests:
+//!
+//! ```rust,ignore
+//! #[test]
+//! fn wire_format() {
+//!     let mut app = App::new();
+//!     app.add_plugins(ProtocolPlugin);
+//!     let mut snapshot = WireSnapshot::default();
+//!     snapshot
+//!         .add_message(app.world().resource::<MessageRegistry>(), "chat", &Chat("hello".to_string()))
+//!         .add_component(app.world().resource::<ComponentRegistry>(), "position", Position(1.0, 2.0));
+//!     snapshot.check(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/wire.golden"));
+//! }
+//! ```
+//!
+//! The check fails if the golden file doesn't exist. The golden file is created, or regenerated when the
+//! wire format is changed on purpose, by running the tests with the [`UPDATE_SNAPSHOTS_ENV_VAR`] environment variable set.
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Write as _};
+use std::path::Path;
+
+use bevy::prelude::Component;
+
+use crate::prelude::{ComponentRegistry, Message, MessageRegistry};
+use crate::serialize::writer::Writer;
+
+/// Environment variable that writes the golden files instead of comparing against them
+pub const UPDATE_SNAPSHOTS_ENV_VAR: &str = "UPDATE_SNAPSHOTS";
+
+#[derive(thiserror::Error, Debug)]
+pub enum GoldenError {
+    #[error("line {0} of the golden file is invalid")]
+    InvalidLine(usize),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Difference between a [`WireSnapshot`] and the golden snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireChange {
+    /// The entry is not present in the golden snapshot
+    Added { name: String },
+    /// The entry of the golden snapshot is not present anymore
+    Removed { name: String },
+    /// The serialized bytes are different
+    Changed {
+        name: String,
+        golden: Vec<u8>,
+        current: Vec<u8>,
+    },
+}
+
+impl Display for WireChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireChange::Added { name } => write!(f, "`{name}` was added"),
+            WireChange::Removed { name } => write!(f, "`{name}` was removed"),
+            WireChange::Changed {
+                name,
+                golden,
+                current,
+            } => write!(
+                f,
+                "`{name}` changed from {} to {}",
+                to_hex(golden),
+                to_hex(current)
+            ),
+        }
+    }
+}
+
+/// Serialized form of some example messages and components, indexed by a name
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WireSnapshot {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl WireSnapshot {
+    /// Record the serialized form of a message, as it is sent over the network.
+    ///
+    /// Panics if the message is not registered in the protocol.
+    pub fn add_message<M: Message>(
+        &mut self,
+        registry: &MessageRegistry,
+        name: impl Into<String>,
+        message: &M,
+    ) -> &mut Self {
+        let mut writer = Writer::default();
+        registry
+            .serialize(message, &mut writer, None)
+            .expect("could not serialize the message");
+        self.entries.insert(name.into(), writer.to_bytes().to_vec());
+        self
+    }
+
+    /// Record the serialized form of a component, as it is sent over the network.
+    ///
+    /// Panics if the component is not registered in the protocol.
+    pub fn add_component<C: Component>(
+        &mut self,
+        registry: &ComponentRegistry,
+        name: impl Into<String>,
+        mut component: C,
+    ) -> &mut Self {
+        let mut writer = Writer::default();
+        registry
+            .serialize(&mut component, &mut writer, None)
+            .expect("could not serialize the component");
+        self.entries.insert(name.into(), writer.to_bytes().to_vec());
+        self
+    }
+
+    /// Serialized bytes recorded for the entry `name`
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries.get(name).map(Vec::as_slice)
+    }
+
+    /// Format the snapshot as a golden file: one `name = hex bytes` line per entry, sorted by name
+    pub fn to_golden(&self) -> String {
+        let mut golden = String::new();
+        for (name, bytes) in &self.entries {
+            let _ = writeln!(golden, "{name} = {}", to_hex(bytes));
+        }
+        golden
+    }
+
+    /// Parse a golden file produced by [`WireSnapshot::to_golden`]
+    pub fn from_golden(golden: &str) -> Result<Self, GoldenError> {
+        let mut entries = BTreeMap::new();
+        for (i, line) in golden.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, hex) = line
+                .rsplit_once(" = ")
+                .ok_or(GoldenError::InvalidLine(i + 1))?;
+            let bytes = from_hex(hex.trim()).ok_or(GoldenError::InvalidLine(i + 1))?;
+            entries.insert(name.to_string(), bytes);
+        }
+        Ok(Self { entries })
+    }
+
+    /// List the differences between this snapshot and the golden snapshot
+    pub fn changes(&self, golden: &WireSnapshot) -> Vec<WireChange> {
+        let mut changes = vec![];
+        for (name, current) in &self.entries {
+            match golden.entries.get(name) {
+                None => changes.push(WireChange::Added { name: name.clone() }),
+                Some(golden) if golden != current => changes.push(WireChange::Changed {
+                    name: name.clone(),
+                    golden: golden.clone(),
+                    current: current.clone(),
+                }),
+                _ => {}
+            }
+        }
+        for name in golden.entries.keys() {
+            if !self.entries.contains_key(name) {
+                changes.push(WireChange::Removed { name: name.clone() });
+            }
+        }
+        changes
+    }
+
+    /// Compare the snapshot against the golden file at `path`.
+    ///
+    /// The golden file is written instead if the [`UPDATE_SNAPSHOTS_ENV_VAR`] environment variable is set.
+    ///
+    /// Panics if the golden file doesn't exist, or if the wire format is different from the golden file.
+    pub fn check(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if std::env::var_os(UPDATE_SNAPSHOTS_ENV_VAR).is_some() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).expect("could not create the golden directory");
+            }
+            std::fs::write(path, self.to_golden()).expect("could not write the golden file");
+            return;
+        }
+        if !path.exists() {
+            panic!(
+                "the golden file {} doesn't exist. Set {UPDATE_SNAPSHOTS_ENV_VAR}=1 to create it",
+                path.display()
+            );
+        }
+        let golden = std::fs::read_to_string(path)
+            .map_err(GoldenError::from)
+            .and_then(|golden| WireSnapshot::from_golden(&golden))
+            .unwrap_or_else(|e| panic!("could not read the golden file {}: {e}", path.display()));
+        let changes = self.changes(&golden);
+        if !changes.is_empty() {
+            let changes = changes
+                .iter()
+                .map(|change| format!("  {change}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            panic!(
+                "the wire format is different from the golden file {}:\n{changes}\n\
+                If the change is intended, set {UPDATE_SNAPSHOTS_ENV_VAR}=1 to update the golden file",
+                path.display()
+            );
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    fn snapshot(stepper: &BevyStepper) -> WireSnapshot {
+        let world = stepper.server_app.world();
+        let message_registry = world.resource::<MessageRegistry>();
+        let component_registry = world.resource::<ComponentRegistry>();
+        let mut snapshot = WireSnapshot::default();
+        snapshot
+            .add_message(
+                message_registry,
+                "message/string",
+                &StringMessage("hello".to_string()),
+            )
+            .add_component(
+                component_registry,
+                "component/full",
+                ComponentSyncModeFull(1.5),
+            )
+            .add_component(
+                component_registry,
+                "component/custom_serde",
+                ComponentSyncModeSimple(2.5),
+            )
+            .add_component(
+                component_registry,
+                "component/delta",
+                ComponentDeltaCompression(vec![1, 2, 300]),
+            );
+        snapshot
+    }
+
+    /// The wire format of the test protocol must not change by accident
+    #[test]
+    fn test_wire_format_golden() {
+        let stepper = BevyStepper::default();
+        snapshot(&stepper).check(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/tests/golden/protocol.golden"
+        ));
+    }
+
+    /// A missing golden file is not created silently
+    #[test]
+    #[should_panic(expected = "doesn't exist")]
+    fn test_missing_golden_file() {
+        let path = std::env::temp_dir().join("lightyear_missing.golden");
+        let _ = std::fs::remove_file(&path);
+        WireSnapshot::default().check(path);
+    }
+
+    #[test]
+    fn test_golden_roundtrip_and_changes() {
+        let stepper = BevyStepper::default();
+        let snapshot = snapshot(&stepper);
+        let golden = WireSnapshot::from_golden(&snapshot.to_golden()).unwrap();
+        assert_eq!(golden, snapshot);
+        assert!(snapshot.changes(&golden).is_empty());
+
+        let mut current = snapshot.clone();
+        current
+            .add_component(
+                stepper.server_app.world().resource::<ComponentRegistry>(),
+                "component/full",
+                ComponentSyncModeFull(2.0),
+            )
+            .add_component(
+                stepper.server_app.world().resource::<ComponentRegistry>(),
+                "component/once",
+                ComponentSyncModeOnce(1.0),
+            );
+        current.entries.remove("message/string");
+        let changes = current.changes(&golden);
+        assert_eq!(changes.len(), 3);
+        assert!(
+            matches!(&changes[0], WireChange::Changed { name, .. } if name == "component/full")
+        );
+        assert_eq!(
+            changes[1],
+            WireChange::Added {
+                name: "component/once".to_string()
+            }
+        );
+        assert_eq!(
+            changes[2],
+            WireChange::Removed {
+                name: "message/string".to_string()
+            }
+        );
+
+        assert!(matches!(
+            WireSnapshot::from_golden("name = 0"),
+            Err(GoldenError::InvalidLine(1))
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! Detect peers that use a different protocol.
+//!
+//! The network id of a channel, component or message depends on the order in which the types were registered,
+//! unless it was assigned explicitly (for example with
+//! [`ComponentRegistration::with_net_id`](crate::protocol::component::ComponentRegistration::with_net_id)).
+//! If the client and the server don't register exactly the same types with the same ids, they silently
+//! mis-deserialize each other's packets.
+//!
+//! To prevent this, a client with [`send_protocol_hash`](crate::prelude::client::NetcodeConfig::send_protocol_hash)
+//! sends a hash of its protocol in the connection request. A server with
+//! [`check_protocol_hash`](crate::prelude::server::NetcodeConfig::check_protocol_hash) denies the clients whose
+//! hash is different from its own with
+//! [`DeniedReason::ProtocolMismatch`](crate::connection::server::DeniedReason::ProtocolMismatch), and emits a
+//! [`ProtocolMismatchEvent`](crate::prelude::server::ProtocolMismatchEvent).
+//!
+//! The hash is computed from the network ids and the names of the registered types. Channels are named by their
+//! [`Channel::name`](crate::prelude::Channel::name); components and messages can be named with
+//! [`ComponentRegistration::with_name`](crate::protocol::component::ComponentRegistration::with_name) and
+//! [`MessageRegistration::with_name`](crate::protocol::message::MessageRegistration::with_name), otherwise the name of
+//! their type is used. The format of type names is not guaranteed to be the same across compiler versions, so
+//! name the components and messages if the client and the server can be built with different compilers.
+//!
+//! The clients that connect with one of the
+//! [compatible protocol ids](crate::prelude::server::NetcodeConfig::compatible_protocol_ids) are not checked,
+//! since their messages are converted by a [`ProtocolShim`](crate::prelude::server::ProtocolShim).
+use std::hash::{Hash, Hasher};
+
+use crate::prelude::{ChannelRegistry, ComponentRegistry, MessageRegistry};
+
+/// Hash of the network ids and names of the channels, components and messages registered in the protocol
+pub fn protocol_hash(
+    channels: &ChannelRegistry,
+    components: &ComponentRegistry,
+    messages: &MessageRegistry,
+) -> u64 {
+    // the TypeIds are different for every build, so only the registered names are hashed
+    let mut hasher = seahash::SeaHasher::new();
+    for (net_id, kind) in channels.kind_map.sorted() {
+        (net_id, channels.name(&kind)).hash(&mut hasher);
+    }
+    for (net_id, kind) in components.kind_map.sorted() {
+        (net_id, components.protocol_name(kind)).hash(&mut hasher);
+    }
+    for (net_id, kind) in messages.kind_map.sorted() {
+        (net_id, messages.protocol_name(kind)).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    use crate::client::networking::NetworkingState;
+    use crate::connection::client::DisconnectReason;
+    use crate::connection::server::DeniedReason;
+    use crate::prelude::client::{self, ClientConfig};
+    use crate::prelude::server::{self, ProtocolMismatchEvent, ServerConfig};
+    use crate::prelude::{AppMessageExt, ChannelDirection, ClientId, SharedConfig, TickConfig};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct ClientOnlyMessage;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct MessageA;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct MessageB;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    fn hash(app: &App) -> u64 {
+        protocol_hash(
+            app.world().resource::<ChannelRegistry>(),
+            app.world().resource::<ComponentRegistry>(),
+            app.world().resource::<MessageRegistry>(),
+        )
+    }
+
+    #[test]
+    fn test_same_protocol_hash() {
+        let stepper = BevyStepper::default();
+        assert_eq!(hash(&stepper.client_app), hash(&stepper.server_app));
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+    }
+
+    /// Start a client that registers a message that the server doesn't know about
+    fn mismatch_stepper(send_protocol_hash: bool, check_protocol_hash: bool) -> BevyStepper {
+        let frame_duration = bevy::utils::Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            ClientConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .register_message::<ClientOnlyMessage>(ChannelDirection::ClientToServer);
+        assert_ne!(hash(&stepper.client_app), hash(&stepper.server_app));
+        if let client::NetConfig::Netcode { config, .. } = &mut stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ClientConfig>()
+            .net
+        {
+            config.send_protocol_hash = send_protocol_hash;
+        }
+        for net_config in stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .net
+            .iter_mut()
+        {
+            #[allow(irrefutable_let_patterns)]
+            if let server::NetConfig::Netcode { config, .. } = net_config {
+                config.check_protocol_hash = check_protocol_hash;
+            }
+        }
+
+        stepper.client_app.init_resource::<Received>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<client::DisconnectEvent>, mut received: ResMut<Received>| {
+                received
+                    .0
+                    .extend(events.read().map(|event| format!("{:?}", event.reason)));
+            },
+        );
+        stepper.server_app.init_resource::<Received>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<ProtocolMismatchEvent>, mut received: ResMut<Received>| {
+                received.0.extend(
+                    events
+                        .read()
+                        .map(|event| format!("{:?} {:?}", event.client_id, event.protocol_hash)),
+                );
+            },
+        );
+        stepper.init();
+        stepper
+    }
+
+    fn assert_denied(stepper: &BevyStepper, protocol_hash: Option<u64>) {
+        assert_eq!(
+            stepper.client_app.world().resource::<Received>().0,
+            vec![format!(
+                "{:?}",
+                Some(DisconnectReason::Denied(DeniedReason::ProtocolMismatch))
+            )]
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+        assert_eq!(
+            stepper.server_app.world().resource::<Received>().0,
+            vec![format!(
+                "{:?} {:?}",
+                ClientId::Netcode(TEST_CLIENT_ID),
+                protocol_hash
+            )]
+        );
+    }
+
+    #[test]
+    fn test_protocol_mismatch() {
+        let stepper = mismatch_stepper(true, true);
+        assert_denied(&stepper, Some(hash(&stepper.client_app)));
+    }
+
+    /// A server that checks the protocol hash denies the clients that don't send one
+    #[test]
+    fn test_missing_protocol_hash() {
+        let stepper = mismatch_stepper(false, true);
+        assert_denied(&stepper, None);
+    }
+
+    /// The protocol hash is only checked if the server enables it
+    #[test]
+    fn test_protocol_hash_not_checked() {
+        let stepper = mismatch_stepper(true, false);
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<Received>()
+            .0
+            .is_empty());
+    }
+
+    /// Types registered with the same name have the same protocol hash
+    #[test]
+    fn test_registered_names() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .client_app
+            .register_message::<MessageA>(ChannelDirection::Bidirectional)
+            .with_name("message");
+        stepper
+            .server_app
+            .register_message::<MessageB>(ChannelDirection::Bidirectional)
+            .with_name("message");
+        assert_eq!(hash(&stepper.client_app), hash(&stepper.server_app));
+    }
+
+    #[test]
+    fn test_explicit_net_ids() {
+        let frame_duration = bevy::utils::Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            ClientConfig::default(),
+            frame_duration,
+        );
+        // the client and the server register the messages in a different order
+        stepper
+            .client_app
+            .register_message::<MessageA>(ChannelDirection::Bidirectional)
+            .with_net_id(100);
+        stepper
+            .client_app
+            .register_message::<MessageB>(ChannelDirection::Bidirectional)
+            .with_net_id(101);
+        stepper
+            .server_app
+            .register_message::<MessageB>(ChannelDirection::Bidirectional)
+            .with_net_id(101);
+        stepper
+            .server_app
+            .register_message::<MessageA>(ChannelDirection::Bidirectional)
+            .with_net_id(100);
+        assert_eq!(hash(&stepper.client_app), hash(&stepper.server_app));
+        stepper.init();
+
+        let registry = stepper.server_app.world().resource::<MessageRegistry>();
+        assert_eq!(
+            registry.net_id(crate::prelude::MessageKind::of::<MessageA>()),
+            Some(100)
+        );
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+    }
+}
@@ -1,27 +1,33 @@
+use crate::protocol::plugin::ProtocolPlugins;
 use bevy::ecs::entity::MapEntities;
 use std::any::TypeId;
 use std::fmt::Debug;
+use std::ptr::NonNull;
 
 use crate::client::config::ClientConfig;
 use crate::client::message::add_client_receive_message_from_server;
 use crate::prelude::{client, server};
-use bevy::prelude::{App, Resource, TypePath};
-use bevy::utils::HashMap;
+use bevy::prelude::{App, Reflect, Resource, TypePath};
+use bevy::ptr::Ptr;
+use bevy::utils::{HashMap, HashSet};
+use byteorder::WriteBytesExt;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error};
 
 use crate::packet::message::Message;
 use crate::prelude::server::ServerConfig;
 use crate::prelude::ChannelDirection;
 use crate::protocol::registry::{NetId, TypeKind, TypeMapper};
-use crate::protocol::serialize::{ErasedSerializeFns, SerializeFns};
+use crate::protocol::serialize::{ErasedSerializeFns, MigrateFn, SerializeFns};
 use crate::serialize::reader::Reader;
+use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
 use crate::serialize::writer::Writer;
-use crate::serialize::ToBytes;
+use crate::serialize::{SerializationError, ToBytes};
 use crate::server::message::add_server_receive_message_from_client;
 use crate::shared::replication::entity_map::{ReceiveEntityMap, SendEntityMap};
 use crate::shared::replication::resources::DespawnResource;
+use crate::shared::rpc::Request;
 
 #[derive(thiserror::Error, Debug)]
 pub enum MessageError {
@@ -33,6 +39,29 @@ pub enum MessageError {
     Serialization(#[from] crate::serialize::SerializationError),
 }
 
+/// Correlation id that can be attached to a message, to follow a single player action across
+/// the client, the game server and backend services (for example as the id of a distributed trace).
+///
+/// Only the messages registered with [`MessageRegistration::add_trace_id`] can carry a [`TraceId`].
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect,
+)]
+pub struct TraceId(pub u64);
+
+impl ToBytes for TraceId {
+    fn len(&self) -> usize {
+        varint_len(self.0)
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.0)
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+        Ok(Self(buffer.read_varint()?))
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum MessageType {
     /// This is a message for a [`LeafwingUserAction`](crate::inputs::leafwing::LeafwingUserAction)
@@ -99,6 +128,10 @@ pub(crate) enum MessageType {
 pub struct MessageRegistry {
     typed_map: HashMap<MessageKind, MessageType>,
     serialize_fns_map: HashMap<MessageKind, ErasedSerializeFns>,
+    /// Messages that are serialized with an optional [`TraceId`]
+    traced: HashSet<MessageKind>,
+    /// Names registered with [`MessageRegistration::with_name`]
+    protocol_names: HashMap<MessageKind, &'static str>,
     pub(crate) kind_map: TypeMapper<MessageKind>,
 }
 
@@ -188,6 +221,31 @@ pub struct MessageRegistration<'a, M> {
 }
 
 impl<M> MessageRegistration<'_, M> {
+    /// Assign an explicit network id to the message, so that it doesn't depend on the registration order.
+    ///
+    /// Panics if the id is already used by another message
+    pub fn with_net_id(self, net_id: NetId) -> Self
+    where
+        M: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<MessageRegistry>();
+        registry.kind_map.set_net_id::<M>(net_id);
+        self
+    }
+
+    /// Name the message in the [protocol hash](crate::protocol::hash), instead of using the name of its type.
+    ///
+    /// The format of the type names is not guaranteed to be the same across compiler versions, so the messages
+    /// should be named if the client and the server can be built with different compilers.
+    pub fn with_name(self, name: &'static str) -> Self
+    where
+        M: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<MessageRegistry>();
+        registry.protocol_names.insert(MessageKind::of::<M>(), name);
+        self
+    }
+
     /// Specify that the message contains entities which should be mapped from the remote world to the local world
     /// upon deserialization
     pub fn add_map_entities(self) -> Self
@@ -198,6 +256,47 @@ impl<M> MessageRegistration<'_, M> {
         registry.add_map_entities::<M>();
         self
     }
+
+    /// Version the wire format of the message, so that the messages written with an older version can still
+    /// be read with a [migration function](MessageRegistration::add_migration). For example during a rolling
+    /// upgrade, the new servers can read the messages of the clients that use the previous version.
+    ///
+    /// Each peer sends the versions of its types once when the connection starts, so the messages doesn't carry
+    /// any version on the wire. A peer that doesn't register the message with a version is considered to use the
+    /// version 0.
+    pub fn with_version(self, version: u16) -> Self
+    where
+        M: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<MessageRegistry>();
+        registry.erased_fns_mut::<M>().set_version(version);
+        self
+    }
+
+    /// Read the messages that were written with an older version of the wire format.
+    ///
+    /// The messages written with a newer version than the registered one are rejected.
+    pub fn add_migration(self, migrate: MigrateFn<M>) -> Self
+    where
+        M: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<MessageRegistry>();
+        registry.erased_fns_mut::<M>().add_migration(migrate);
+        self
+    }
+
+    /// Allow the message to carry a [`TraceId`], that is propagated to the
+    /// [`MessageEvent`](crate::shared::events::components::MessageEvent) on the receiving side.
+    ///
+    /// The messages of this type use one extra byte on the wire, even when they don't have a [`TraceId`].
+    pub fn add_trace_id(self) -> Self
+    where
+        M: 'static,
+    {
+        let mut registry = self.app.world_mut().resource_mut::<MessageRegistry>();
+        registry.traced.insert(MessageKind::of::<M>());
+        self
+    }
 }
 
 pub(crate) trait AppMessageInternalExt {
@@ -224,6 +323,7 @@ impl AppMessageInternalExt for App {
         direction: ChannelDirection,
         message_type: MessageType,
     ) -> MessageRegistration<'_, M> {
+        ProtocolPlugins::record::<M>(self.world_mut());
         let mut registry = self.world_mut().resource_mut::<MessageRegistry>();
         if !registry.is_registered::<M>() {
             registry.add_message::<M>(message_type);
@@ -242,6 +342,7 @@ impl AppMessageInternalExt for App {
         message_type: MessageType,
         serialize_fns: SerializeFns<M>,
     ) -> MessageRegistration<'_, M> {
+        ProtocolPlugins::record::<M>(self.world_mut());
         let mut registry = self.world_mut().resource_mut::<MessageRegistry>();
         if !registry.is_registered::<M>() {
             registry.add_message_custom_serde::<M>(message_type, serialize_fns);
@@ -290,6 +391,12 @@ pub trait AppMessageExt {
         direction: ChannelDirection,
         serialize_fns: SerializeFns<R>,
     );
+
+    /// Registers a [`Request`] in the Registry
+    ///
+    /// The request can be sent in the given direction, and its response in the opposite direction.
+    /// See [`rpc`](crate::shared::rpc) for more details.
+    fn register_request<R: Request>(&mut self, direction: ChannelDirection);
 }
 
 impl AppMessageExt for App {
@@ -328,6 +435,10 @@ impl AppMessageExt for App {
         self.register_message::<DespawnResource<R>>(direction);
         register_resource_send::<R>(self, direction)
     }
+
+    fn register_request<R: Request>(&mut self, direction: ChannelDirection) {
+        crate::shared::rpc::register_request::<R>(self, direction)
+    }
 }
 
 impl MessageRegistry {
@@ -381,6 +492,26 @@ impl MessageRegistry {
         erased_fns.add_map_entities::<M>();
     }
 
+    pub(crate) fn erased_fns_mut<M: 'static>(&mut self) -> &mut ErasedSerializeFns {
+        self.serialize_fns_map
+            .get_mut(&MessageKind::of::<M>())
+            .expect("the message is not part of the protocol")
+    }
+
+    /// Iterate through the serialization functions of the messages, with their network id
+    pub(crate) fn net_serialize_fns(&self) -> impl Iterator<Item = (NetId, &ErasedSerializeFns)> {
+        self.serialize_fns_map
+            .iter()
+            .filter_map(|(kind, fns)| Some((*self.kind_map.net_id(kind)?, fns)))
+    }
+
+    /// Returns true if the message was registered with a version
+    pub(crate) fn is_versioned<M: 'static>(&self) -> bool {
+        self.serialize_fns_map
+            .get(&MessageKind::of::<M>())
+            .is_some_and(|fns| fns.version.is_some())
+    }
+
     /// Returns true if we have a registered `map_entities` function for this message type
     pub(crate) fn is_map_entities<M: 'static>(&self) -> bool {
         let kind = MessageKind::of::<M>();
@@ -396,6 +527,17 @@ impl MessageRegistry {
         message: &M,
         writer: &mut Writer,
         entity_map: Option<&mut SendEntityMap>,
+    ) -> Result<(), MessageError> {
+        self.serialize_with_trace_id(message, writer, entity_map, None)
+    }
+
+    /// Serialize a message, with a [`TraceId`] if the message was registered with one.
+    pub(crate) fn serialize_with_trace_id<M: Message>(
+        &self,
+        message: &M,
+        writer: &mut Writer,
+        entity_map: Option<&mut SendEntityMap>,
+        trace_id: Option<TraceId>,
     ) -> Result<(), MessageError> {
         let kind = MessageKind::of::<M>();
         let erased_fns = self
@@ -404,6 +546,9 @@ impl MessageRegistry {
             .ok_or(MessageError::MissingSerializationFns)?;
         let net_id = self.kind_map.net_id(&kind).unwrap();
         net_id.to_bytes(writer)?;
+        if self.traced.contains(&kind) {
+            trace_id.to_bytes(writer)?;
+        }
         // SAFETY: the ErasedSerializeFns was created for the type M
         unsafe {
             erased_fns.serialize(message, writer, entity_map)?;
@@ -416,6 +561,16 @@ impl MessageRegistry {
         reader: &mut Reader,
         entity_map: &mut ReceiveEntityMap,
     ) -> Result<M, MessageError> {
+        self.deserialize_with_trace_id(reader, entity_map)
+            .map(|(message, _)| message)
+    }
+
+    /// Deserialize a message, and the [`TraceId`] that was attached to it, if any
+    pub(crate) fn deserialize_with_trace_id<M: Message>(
+        &self,
+        reader: &mut Reader,
+        entity_map: &mut ReceiveEntityMap,
+    ) -> Result<(M, Option<TraceId>), MessageError> {
         let net_id = NetId::from_bytes(reader)?;
         let kind = self
             .kind_map
@@ -425,8 +580,96 @@ impl MessageRegistry {
             .serialize_fns_map
             .get(kind)
             .ok_or(MessageError::MissingSerializationFns)?;
+        let trace_id = if self.traced.contains(kind) {
+            Option::<TraceId>::from_bytes(reader)?
+        } else {
+            None
+        };
         // SAFETY: the ErasedSerializeFns was created for the type M
-        unsafe { erased_fns.deserialize(reader, entity_map) }.map_err(Into::into)
+        let message = unsafe { erased_fns.deserialize(reader, entity_map) }?;
+        Ok((message, trace_id))
+    }
+
+    /// Iterate through the registered message kinds, in the order of their network ids
+    pub fn kinds(&self) -> impl Iterator<Item = MessageKind> + '_ {
+        self.kind_map.sorted().map(|(_, kind)| kind)
+    }
+
+    /// Name of the type of a registered message
+    pub fn name(&self, kind: MessageKind) -> Option<&'static str> {
+        self.serialize_fns_map.get(&kind).map(|fns| fns.type_name)
+    }
+
+    /// Name of the message in the [protocol hash](crate::protocol::hash): the name registered with
+    /// [`MessageRegistration::with_name`], or else the name of the type without its module path
+    pub fn protocol_name(&self, kind: MessageKind) -> Option<String> {
+        self.protocol_names
+            .get(&kind)
+            .map(|name| name.to_string())
+            .or_else(|| self.name(kind).map(bevy::utils::get_short_name))
+    }
+
+    /// Network id of a registered message
+    pub fn net_id(&self, kind: MessageKind) -> Option<NetId> {
+        self.kind_map.net_id(&kind).copied()
+    }
+
+    /// Serialize a message whose type is only known at runtime.
+    ///
+    /// The entities contained in the message are not mapped.
+    pub fn serialize_boxed(
+        &self,
+        message: &dyn Message,
+        writer: &mut Writer,
+    ) -> Result<(), MessageError> {
+        let kind = message.kind();
+        let erased_fns = self
+            .serialize_fns_map
+            .get(&kind)
+            .ok_or(MessageError::MissingSerializationFns)?;
+        let net_id = self.kind_map.net_id(&kind).unwrap();
+        net_id.to_bytes(writer)?;
+        if self.traced.contains(&kind) {
+            None::<TraceId>.to_bytes(writer)?;
+        }
+        // SAFETY: the pointer points to a value of the type for which the ErasedSerializeFns was created
+        let ptr = unsafe { Ptr::new(NonNull::from(message.message_as_any()).cast::<u8>()) };
+        unsafe {
+            (erased_fns.erased_serialize)(
+                erased_fns,
+                ptr,
+                writer,
+                Some(&mut SendEntityMap::default()),
+            )
+        }?;
+        Ok(())
+    }
+
+    /// Deserialize a message whose type is only known at runtime, for example for logging or tooling.
+    ///
+    /// The entities contained in the message are not mapped.
+    pub fn deserialize_boxed(
+        &self,
+        reader: &mut Reader,
+    ) -> Result<(Box<dyn Message>, Option<TraceId>), MessageError> {
+        let net_id = NetId::from_bytes(reader)?;
+        let kind = self
+            .kind_map
+            .kind(net_id)
+            .ok_or(MessageError::NotRegistered)?;
+        let erased_fns = self
+            .serialize_fns_map
+            .get(kind)
+            .ok_or(MessageError::MissingSerializationFns)?;
+        let trace_id = if self.traced.contains(kind) {
+            Option::<TraceId>::from_bytes(reader)?
+        } else {
+            None
+        };
+        // SAFETY: the function was created for the type of the message
+        let message =
+            unsafe { erased_fns.deserialize_boxed(reader, &mut ReceiveEntityMap::default()) }?;
+        Ok((message, trace_id))
     }
 }
 
@@ -451,10 +694,14 @@ impl From<TypeId> for MessageKind {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::component::ComponentRegistry;
+    use crate::protocol::version::SchemaVersions;
     use crate::tests::protocol::{
         deserialize_resource2, serialize_resource2, ComponentMapEntities, Resource1, Resource2,
     };
     use bevy::prelude::Entity;
+    use byteorder::ReadBytesExt;
+    use std::sync::Arc;
 
     #[test]
     fn test_serde() {
@@ -518,4 +765,197 @@ mod tests {
             .unwrap();
         assert_eq!(message, read);
     }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    enum EnumMessage {
+        Empty,
+        Move { x: u8, y: u8 },
+        Chat(String),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct GenericMessage<T: Clone + Send + Sync + 'static> {
+        value: T,
+    }
+
+    /// Enums with data-carrying variants and generic types only need the serde derives
+    #[test]
+    fn test_serde_enum_generic() {
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<EnumMessage>(MessageType::Normal);
+        registry.add_message::<GenericMessage<u8>>(MessageType::Normal);
+        registry.add_message::<GenericMessage<String>>(MessageType::Normal);
+
+        let message = EnumMessage::Move { x: 1, y: 2 };
+        let mut writer = Writer::default();
+        registry.serialize(&message, &mut writer, None).unwrap();
+        let data = writer.to_bytes();
+        // the net id and the variant tag each take a single byte
+        assert_eq!(data.len(), 4);
+        let mut reader = Reader::from(data);
+        let read = registry
+            .deserialize::<EnumMessage>(&mut reader, &mut ReceiveEntityMap::default())
+            .unwrap();
+        assert_eq!(message, read);
+
+        // each instantiation of a generic type is a different message
+        let message = GenericMessage {
+            value: "a".to_string(),
+        };
+        let mut writer = Writer::default();
+        registry.serialize(&message, &mut writer, None).unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        let read = registry
+            .deserialize::<GenericMessage<String>>(&mut reader, &mut ReceiveEntityMap::default())
+            .unwrap();
+        assert_eq!(message, read);
+        assert_ne!(
+            registry
+                .kind_map
+                .net_id(&MessageKind::of::<GenericMessage<u8>>()),
+            registry
+                .kind_map
+                .net_id(&MessageKind::of::<GenericMessage<String>>())
+        );
+    }
+
+    /// Messages can be inspected and (de)serialized without knowing their type at compile time
+    #[test]
+    fn test_boxed_message() {
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<Resource1>(MessageType::Normal);
+        registry.add_message::<EnumMessage>(MessageType::Normal);
+        registry.traced.insert(MessageKind::of::<EnumMessage>());
+
+        assert_eq!(
+            registry.kinds().collect::<Vec<_>>(),
+            vec![
+                MessageKind::of::<Resource1>(),
+                MessageKind::of::<EnumMessage>()
+            ]
+        );
+        assert_eq!(
+            registry.name(MessageKind::of::<EnumMessage>()),
+            Some(std::any::type_name::<EnumMessage>())
+        );
+        assert_eq!(registry.net_id(MessageKind::of::<EnumMessage>()), Some(1));
+
+        let message: Box<dyn Message> = Box::new(EnumMessage::Chat("a".to_string()));
+        assert_eq!(message.kind(), MessageKind::of::<EnumMessage>());
+        assert_eq!(message.name(), std::any::type_name::<EnumMessage>());
+        assert!(message.downcast_ref::<Resource1>().is_none());
+
+        let mut writer = Writer::default();
+        registry
+            .serialize_boxed(message.as_ref(), &mut writer)
+            .unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        let (read, trace_id) = registry.deserialize_boxed(&mut reader).unwrap();
+        assert_eq!(trace_id, None);
+        assert_eq!(
+            read.downcast_ref::<EnumMessage>(),
+            Some(&EnumMessage::Chat("a".to_string()))
+        );
+        let read = read.downcast::<Resource1>().unwrap_err();
+        assert_eq!(
+            *read.downcast::<EnumMessage>().unwrap(),
+            EnumMessage::Chat("a".to_string())
+        );
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Speed(u32);
+
+    /// The version 1 of the message stored the speed in a single byte
+    fn serialize_speed_v1(speed: &Speed, writer: &mut Writer) -> Result<(), SerializationError> {
+        writer.write_u8(speed.0 as u8)?;
+        Ok(())
+    }
+
+    fn deserialize_speed_v1(reader: &mut Reader) -> Result<Speed, SerializationError> {
+        Ok(Speed(reader.read_u8()? as u32))
+    }
+
+    fn migrate_speed(version: u16, reader: &mut Reader) -> Result<Speed, SerializationError> {
+        assert_eq!(version, 1);
+        deserialize_speed_v1(reader)
+    }
+
+    #[test]
+    fn test_message_version() {
+        let mut old_registry = MessageRegistry::default();
+        old_registry.add_message_custom_serde::<Speed>(
+            MessageType::Normal,
+            SerializeFns {
+                serialize: serialize_speed_v1,
+                deserialize: deserialize_speed_v1,
+                serialize_map_entities: None,
+            },
+        );
+        old_registry.erased_fns_mut::<Speed>().set_version(1);
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<Speed>(MessageType::Normal);
+        registry.erased_fns_mut::<Speed>().set_version(2);
+        registry
+            .erased_fns_mut::<Speed>()
+            .add_migration::<Speed>(migrate_speed);
+        let component_registry = ComponentRegistry::default();
+        // versions of the remote peer, as received when the connection starts
+        let old_versions = Some(Arc::new(
+            SchemaVersions::new(&old_registry, &component_registry)
+                .resolve(&registry, &component_registry),
+        ));
+        let new_versions = Some(Arc::new(
+            SchemaVersions::new(&registry, &component_registry)
+                .resolve(&old_registry, &component_registry),
+        ));
+
+        // the messages of the previous version are migrated
+        let mut writer = Writer::default();
+        old_registry
+            .serialize(&Speed(200), &mut writer, None)
+            .unwrap();
+        let data = writer.to_bytes();
+        let read = registry
+            .deserialize::<Speed>(
+                &mut Reader::from(data.clone()).with_versions(old_versions.clone()),
+                &mut ReceiveEntityMap::default(),
+            )
+            .unwrap();
+        assert_eq!(read, Speed(200));
+        let (read, _) = registry
+            .deserialize_boxed(&mut Reader::from(data).with_versions(old_versions))
+            .unwrap();
+        assert_eq!(read.downcast_ref::<Speed>(), Some(&Speed(200)));
+
+        // the messages of the current version are read directly, and the version is not written
+        let mut writer = Writer::default();
+        registry.serialize(&Speed(1000), &mut writer, None).unwrap();
+        let data = writer.to_bytes();
+        let mut unversioned = MessageRegistry::default();
+        unversioned.add_message::<Speed>(MessageType::Normal);
+        let mut writer = Writer::default();
+        unversioned
+            .serialize(&Speed(1000), &mut writer, None)
+            .unwrap();
+        assert_eq!(data, writer.to_bytes());
+        let read = registry
+            .deserialize::<Speed>(
+                &mut Reader::from(data.clone()),
+                &mut ReceiveEntityMap::default(),
+            )
+            .unwrap();
+        assert_eq!(read, Speed(1000));
+
+        // the messages of a newer version are rejected
+        assert!(matches!(
+            old_registry.deserialize::<Speed>(
+                &mut Reader::from(data).with_versions(new_versions),
+                &mut ReceiveEntityMap::default()
+            ),
+            Err(MessageError::Serialization(
+                SerializationError::UnsupportedVersion(2)
+            ))
+        ));
+    }
 }
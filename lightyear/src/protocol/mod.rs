@@ -20,10 +20,20 @@ pub(crate) mod component;
 pub(crate) mod message;
 
 pub(crate) mod delta;
+/// Snapshot the serialized form of the protocol to detect accidental wire format changes
+pub mod golden;
+/// Hash the protocol to detect peers that use a different protocol
+pub mod hash;
+/// Assemble the protocol from multiple independent protocol plugins
+pub mod plugin;
 /// Provides a mapping from a type to a unique identifier that can be serialized
 pub(crate) mod registry;
 pub(crate) mod serialize;
 pub use serialize::SerializeFns;
+/// Detect the misconfigurations of the protocol when the server starts
+pub mod validate;
+/// Exchange the wire format versions of the types with the remote peer
+pub(crate) mod version;
 
 /// Data that can be used in an Event
 /// Same as `Event`, but we implement it automatically for all compatible types
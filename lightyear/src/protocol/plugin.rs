@@ -0,0 +1,241 @@
+//! Assemble the protocol from multiple independent parts.
+//!
+//! A [`ProtocolPlugin`] registers some channels, messages and components. Protocol plugins can be provided
+//! by different teams or by third-party crates, and are added to the app with
+//! [`AppProtocolExt::add_protocol_plugin`].
+//!
+//! The network ids of the registered types depend on the order of registration. To make sure that every peer
+//! uses the same ids, the protocol plugins are not built in the order in which they were added, but in the order
+//! of their [`name`](ProtocolPlugin::name), after all the other plugins have been built.
+//!
+//! Two protocol plugins cannot register the same type, or have the same name.
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bevy::prelude::{App, Resource, World};
+use bevy::utils::HashMap;
+
+/// A part of the protocol, that registers some channels, messages and components
+///
+/// ```rust,ignore
+/// use lightyear::protocol::plugin::ProtocolPlugin;
+///
+/// struct InventoryProtocol;
+///
+/// impl ProtocolPlugin for InventoryProtocol {
+///     fn name(&self) -> &'static str {
+///         "inventory"
+///     }
+///
+///     fn build(&self, app: &mut App) {
+///         app.register_message::<InventoryUpdate>(ChannelDirection::ServerToClient);
+///         app.register_component::<Inventory>(ChannelDirection::ServerToClient);
+///     }
+/// }
+///
+/// app.add_protocol_plugin(InventoryProtocol);
+/// ```
+///
+/// Note that the plugins added by a protocol plugin are not finished by bevy, so input plugins
+/// should be added as regular plugins.
+pub trait ProtocolPlugin: Send + Sync + 'static {
+    /// Unique name of the plugin, that determines the order in which the protocol plugins are registered
+    fn name(&self) -> &'static str;
+
+    /// Register the channels, messages and components of the plugin
+    fn build(&self, app: &mut App);
+}
+
+/// The protocol plugins that were added to the app
+#[derive(Resource, Default)]
+pub(crate) struct ProtocolPlugins {
+    plugins: BTreeMap<&'static str, Arc<dyn ProtocolPlugin>>,
+    /// The plugin that is currently being built
+    current: Option<&'static str>,
+    /// The plugin that registered each type
+    owners: HashMap<TypeId, &'static str>,
+}
+
+impl ProtocolPlugins {
+    /// Build all the protocol plugins, in the order of their names
+    pub(crate) fn build(app: &mut App) {
+        let Some(plugins) = app
+            .world()
+            .get_resource::<ProtocolPlugins>()
+            .map(|p| p.plugins.clone())
+        else {
+            return;
+        };
+        for (name, plugin) in plugins {
+            app.world_mut().resource_mut::<ProtocolPlugins>().current = Some(name);
+            plugin.build(app);
+        }
+        app.world_mut().resource_mut::<ProtocolPlugins>().current = None;
+    }
+
+    /// Keep track of the protocol plugin that registered the type `T`.
+    ///
+    /// Panics if the type was already registered by another protocol plugin
+    pub(crate) fn record<T: 'static>(world: &mut World) {
+        let Some(mut plugins) = world.get_resource_mut::<ProtocolPlugins>() else {
+            return;
+        };
+        let Some(current) = plugins.current else {
+            return;
+        };
+        let owner = *plugins.owners.entry(TypeId::of::<T>()).or_insert(current);
+        if owner != current {
+            panic!(
+                "{} is registered by both the protocol plugins `{}` and `{}`",
+                std::any::type_name::<T>(),
+                owner,
+                current
+            );
+        }
+    }
+}
+
+pub trait AppProtocolExt {
+    /// Add a [`ProtocolPlugin`] to the protocol.
+    ///
+    /// The plugin is built once all the other plugins have been built.
+    /// Panics if a protocol plugin with the same name was already added.
+    fn add_protocol_plugin(&mut self, plugin: impl ProtocolPlugin) -> &mut Self;
+}
+
+impl AppProtocolExt for App {
+    fn add_protocol_plugin(&mut self, plugin: impl ProtocolPlugin) -> &mut Self {
+        let mut plugins = self
+            .world_mut()
+            .get_resource_or_insert_with(ProtocolPlugins::default);
+        let name = plugin.name();
+        if plugins.plugins.insert(name, Arc::new(plugin)).is_some() {
+            panic!("A protocol plugin named `{}` was already added", name);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::default;
+    use bevy::utils::Duration;
+    use lightyear_macros::ChannelInternal;
+    use serde::{Deserialize, Serialize};
+
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::*;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct MessageA;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct MessageB;
+
+    #[derive(ChannelInternal, bevy::prelude::Reflect)]
+    struct ChannelA;
+
+    struct PluginA;
+
+    impl ProtocolPlugin for PluginA {
+        fn name(&self) -> &'static str {
+            "a"
+        }
+
+        fn build(&self, app: &mut App) {
+            app.add_channel::<ChannelA>(ChannelSettings {
+                mode: ChannelMode::OrderedReliable(default()),
+                ..default()
+            });
+            app.register_message::<MessageA>(ChannelDirection::Bidirectional);
+        }
+    }
+
+    struct PluginB;
+
+    impl ProtocolPlugin for PluginB {
+        fn name(&self) -> &'static str {
+            "b"
+        }
+
+        fn build(&self, app: &mut App) {
+            app.register_message::<MessageB>(ChannelDirection::Bidirectional);
+        }
+    }
+
+    struct PluginCollision;
+
+    impl ProtocolPlugin for PluginCollision {
+        fn name(&self) -> &'static str {
+            "collision"
+        }
+
+        fn build(&self, app: &mut App) {
+            app.register_message::<MessageA>(ChannelDirection::Bidirectional);
+        }
+    }
+
+    fn stepper() -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..default()
+        };
+        BevyStepper::new(shared_config, ClientConfig::default(), frame_duration)
+    }
+
+    fn message_net_ids(app: &App) -> (u16, u16) {
+        let registry = app.world().resource::<MessageRegistry>();
+        let net_id = |kind| *registry.kind_map.net_id(&kind).unwrap();
+        (
+            net_id(crate::protocol::message::MessageKind::of::<MessageA>()),
+            net_id(crate::protocol::message::MessageKind::of::<MessageB>()),
+        )
+    }
+
+    /// The protocol plugins are registered in the same order on the client and the server,
+    /// regardless of the order in which they were added
+    #[test]
+    fn test_protocol_plugins_order() {
+        let mut stepper = stepper();
+        stepper.client_app.add_protocol_plugin(PluginA);
+        stepper.client_app.add_protocol_plugin(PluginB);
+        stepper.server_app.add_protocol_plugin(PluginB);
+        stepper.server_app.add_protocol_plugin(PluginA);
+        stepper.init();
+
+        assert_eq!(
+            message_net_ids(&stepper.client_app),
+            message_net_ids(&stepper.server_app)
+        );
+        let (a, b) = message_net_ids(&stepper.client_app);
+        assert!(a < b);
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ChannelRegistry>()
+            .get_net_from_kind(&ChannelKind::of::<ChannelA>())
+            .is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "is registered by both the protocol plugins `a` and `collision`")]
+    fn test_protocol_plugins_collision() {
+        let mut stepper = stepper();
+        stepper.server_app.add_protocol_plugin(PluginCollision);
+        stepper.server_app.add_protocol_plugin(PluginA);
+        stepper.init();
+    }
+
+    #[test]
+    #[should_panic(expected = "A protocol plugin named `a` was already added")]
+    fn test_protocol_plugins_duplicate_name() {
+        let mut app = App::new();
+        app.add_protocol_plugin(PluginA);
+        app.add_protocol_plugin(PluginA);
+    }
+}
@@ -30,8 +30,12 @@ impl ToBytes for NetId {
 pub trait TypeKind: From<TypeId> + Copy + PartialEq + Eq + Hash {}
 
 /// Struct to map a type to an id that can be serialized over the network
+///
+/// By default the ids are assigned in the order of registration. A type can also be given an explicit id
+/// with [`TypeMapper::set_net_id`], so that its id doesn't depend on the registration order.
 #[derive(Clone, Debug, PartialEq)]
 pub struct TypeMapper<K: TypeKind> {
+    /// The next id to try when registering a type without an explicit id
     pub(crate) next_net_id: NetId,
     pub(crate) kind_map: HashMap<K, NetId>,
     pub(crate) id_map: HashMap<NetId, K>,
@@ -58,11 +62,53 @@ impl<K: TypeKind> TypeMapper<K> {
         if self.kind_map.contains_key(&kind) {
             panic!("Type {:?} already registered", std::any::type_name::<T>());
         }
+        self.add_kind(kind);
+        kind
+    }
+
+    /// Register a kind that doesn't correspond to a type, and assign it the next network id
+    pub(crate) fn add_kind(&mut self, kind: K) {
+        // skip the ids that were assigned explicitly
+        while self.id_map.contains_key(&self.next_net_id) {
+            self.next_net_id += 1;
+        }
         let net_id = self.next_net_id;
         self.kind_map.insert(kind, net_id);
         self.id_map.insert(net_id, kind);
         self.next_net_id += 1;
-        kind
+    }
+
+    /// Assign an explicit network id to a registered type
+    ///
+    /// Panics if the type is not registered, or if the id is already used by another type
+    pub fn set_net_id<T: 'static>(&mut self, net_id: NetId) {
+        let kind = K::from(TypeId::of::<T>());
+        let Some(previous) = self.kind_map.get(&kind).copied() else {
+            panic!("Type {:?} is not registered", std::any::type_name::<T>());
+        };
+        if previous == net_id {
+            return;
+        }
+        if self.id_map.contains_key(&net_id) {
+            panic!(
+                "Cannot assign the network id {net_id} to {:?}: it is already used by another type",
+                std::any::type_name::<T>()
+            );
+        }
+        self.id_map.remove(&previous);
+        self.id_map.insert(net_id, kind);
+        self.kind_map.insert(kind, net_id);
+    }
+
+    /// Iterate through the registered kinds, in the order of their network ids
+    pub(crate) fn sorted(&self) -> impl Iterator<Item = (NetId, K)> {
+        let mut entries = self
+            .id_map
+            .iter()
+            .map(|(net_id, kind)| (*net_id, *kind))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|(net_id, _)| *net_id);
+        entries.into_iter()
     }
 
     pub fn kind(&self, net_id: NetId) -> Option<&K> {
@@ -1,12 +1,17 @@
 use crate::prelude::{ComponentRegistry, Message, MessageRegistry};
+use crate::serialize::context::SerializationContext;
 use crate::serialize::{reader::Reader, writer::Writer, SerializationError};
 use crate::shared::replication::entity_map::{EntityMap, ReceiveEntityMap, SendEntityMap};
 use bevy::app::App;
 use bevy::ecs::entity::MapEntities;
 use bevy::ptr::{Ptr, PtrMut};
+use bevy::reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer};
+use bevy::reflect::{FromReflect, GetTypeRegistration, TypePath, TypeRegistry, TypeRegistryArc};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::any::TypeId;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
 
 /// Stores function pointers related to serialization and deserialization
 #[derive(Clone, Debug, PartialEq)]
@@ -17,13 +22,59 @@ pub struct ErasedSerializeFns {
     pub serialize: unsafe fn(),
     pub erased_serialize: ErasedSerializeFn,
     pub deserialize: unsafe fn(),
+    /// Erased [`ErasedDeserializeBoxedFn`]
+    pub(crate) deserialize_boxed: ErasedFnPtr,
     pub erased_clone: Option<unsafe fn()>,
     pub map_entities: Option<ErasedMapEntitiesFn>,
     pub send_map_entities: Option<ErasedSendMapEntitiesFn>,
     pub receive_map_entities: Option<ErasedReceiveMapEntitiesFn>,
+    /// Version of the wire format of the type, sent once to the remote peer (see [`SchemaVersions`](crate::protocol::version::SchemaVersions))
+    pub(crate) version: Option<u16>,
+    /// Erased [`MigrateFn`] used to read the values written with an older version
+    pub(crate) migrate: Option<ErasedFnPtr>,
+    /// If true, the type is serialized separately for each connection, with the [`SerializationContext`]
+    /// of the connection
+    pub(crate) context_dependent: bool,
+    /// Erased [`ContextTransformFn`] applied to a copy of the value before it is serialized for a connection
+    pub(crate) context_transform: Option<ErasedFnPtr>,
+    /// If set, the type is serialized using its reflection data, and `serialize`/`deserialize` are
+    /// erased [`ReflectSerializeFn`]/[`ReflectDeserializeFn`]
+    pub(crate) reflect_registry: Option<ReflectRegistry>,
+}
+
+/// Type-erased function pointer, which is ignored when comparing two [`ErasedSerializeFns`]: the addresses of
+/// function pointers are not guaranteed to be unique
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ErasedFnPtr(unsafe fn());
+
+impl PartialEq for ErasedFnPtr {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// The [`TypeRegistry`] of the app (see [`AppTypeRegistry`](bevy::prelude::AppTypeRegistry)), used to
+/// serialize the types registered with their reflection data
+#[derive(Clone)]
+pub(crate) struct ReflectRegistry(pub(crate) TypeRegistryArc);
+
+impl Debug for ReflectRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReflectRegistry").finish()
+    }
+}
+
+impl PartialEq for ReflectRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.internal, &other.0.internal)
+    }
 }
 
 /// Controls how a type (resources/components/messages) is serialized and deserialized
+///
+/// By default, the types are serialized with their serde implementation (see [`SerializeFns::serde`]),
+/// so any type that derives [`Serialize`] and [`Deserialize`](serde::Deserialize) can be registered directly.
+/// Other serialization functions can be provided with the `custom_serde` registration methods.
 pub struct SerializeFns<M> {
     /// Called to serialize the type into the writer
     pub serialize: SerializeFn<M>,
@@ -43,10 +94,31 @@ type ErasedSerializeFn = unsafe fn(
     entity_map: Option<&mut SendEntityMap>,
 ) -> Result<(), SerializationError>;
 
+/// Deserialize a value as a [`Box<dyn Message>`]
+type ErasedDeserializeBoxedFn = unsafe fn(
+    erased_serialize_fn: &ErasedSerializeFns,
+    reader: &mut Reader,
+    entity_map: &mut ReceiveEntityMap,
+) -> Result<Box<dyn Message>, SerializationError>;
+
+/// Type of the function that reads a value written with an older version of the wire format.
+///
+/// It receives the version with which the value was written.
+pub type MigrateFn<M> = fn(version: u16, reader: &mut Reader) -> Result<M, SerializationError>;
+
 /// Type of the serialize function without entity mapping
 type SerializeFn<M> = fn(message: &M, writer: &mut Writer) -> Result<(), SerializationError>;
 /// Type of the deserialize function without entity mapping
 type DeserializeFn<M> = fn(reader: &mut Reader) -> Result<M, SerializationError>;
+/// Type of the serialize function of the types serialized with their reflection data
+type ReflectSerializeFn<M> =
+    fn(registry: &TypeRegistry, message: &M, writer: &mut Writer) -> Result<(), SerializationError>;
+/// Type of the deserialize function of the types serialized with their reflection data
+type ReflectDeserializeFn<M> =
+    fn(registry: &TypeRegistry, reader: &mut Reader) -> Result<M, SerializationError>;
+
+/// Type of the function that adapts a value to the [`SerializationContext`] of the connection it is sent to
+pub type ContextTransformFn<M> = fn(value: &mut M, context: &SerializationContext);
 
 type CloneFn<M> = fn(&M) -> M;
 
@@ -75,22 +147,26 @@ unsafe fn erased_serialize_fn<M: Message>(
     writer: &mut Writer,
     entity_map: Option<&mut SendEntityMap>,
 ) -> Result<(), SerializationError> {
-    let typed_serialize_fns = erased_serialize_fn.typed::<M>();
-    if let Some(map_entities) = erased_serialize_fn.send_map_entities {
-        let serialize_map_entities = typed_serialize_fns.serialize_map_entities.unwrap();
-        serialize_map_entities(
-            message.deref::<M>(),
-            writer,
-            entity_map.expect("EntityMap is required to serialize this message"),
-            std::mem::transmute(erased_serialize_fn.erased_clone.unwrap()),
-            map_entities,
-            typed_serialize_fns.serialize,
+    // SAFETY: the Ptr was created for the message of type M
+    erased_serialize_fn.serialize(message.deref::<M>(), writer, entity_map)
+}
+
+/// SAFETY: the ErasedSerializeFns must be created for the type M
+unsafe fn erased_deserialize_boxed_fn<M: Message>(
+    erased_serialize_fn: &ErasedSerializeFns,
+    reader: &mut Reader,
+    entity_map: &mut ReceiveEntityMap,
+) -> Result<Box<dyn Message>, SerializationError> {
+    let message = erased_serialize_fn.deserialize::<M>(reader, entity_map)?;
+    Ok(Box::new(message))
+}
+
+fn erased_deserialize_boxed<M: Message>() -> ErasedFnPtr {
+    ErasedFnPtr(unsafe {
+        std::mem::transmute::<ErasedDeserializeBoxedFn, unsafe fn()>(
+            erased_deserialize_boxed_fn::<M>,
         )
-    } else {
-        // SAFETY: the Ptr was created for the message of type M
-        let message = message.deref::<M>();
-        (typed_serialize_fns.serialize)(message, writer)
-    }
+    })
 }
 
 /// Default serialize function using bincode
@@ -110,6 +186,51 @@ fn default_deserialize<M: Message + DeserializeOwned>(
     Ok(data)
 }
 
+/// Serialize function using the reflection data of the type
+fn reflect_serialize<M: Message + FromReflect + TypePath>(
+    registry: &TypeRegistry,
+    message: &M,
+    buffer: &mut Writer,
+) -> Result<(), SerializationError> {
+    let serializer = TypedReflectSerializer::new(message, registry);
+    let _ = bincode::serde::encode_into_std_write(serializer, buffer, bincode::config::standard())?;
+    Ok(())
+}
+
+/// Deserialize function using the reflection data of the type
+fn reflect_deserialize<M: Message + FromReflect + TypePath>(
+    registry: &TypeRegistry,
+    buffer: &mut Reader,
+) -> Result<M, SerializationError> {
+    let registration = registry
+        .get(TypeId::of::<M>())
+        .ok_or(SerializationError::InvalidValue)?;
+    let deserializer = TypedReflectDeserializer::new(registration, registry);
+    let (value, len) = bincode::serde::seed_decode_from_slice(
+        deserializer,
+        buffer.remaining_slice(),
+        bincode::config::standard(),
+    )?;
+    buffer.advance(len);
+    M::from_reflect(value.as_ref()).ok_or(SerializationError::InvalidValue)
+}
+
+impl<M: Message + Serialize + DeserializeOwned> SerializeFns<M> {
+    /// Serialize the type with its serde implementation, using the compact `bincode` format
+    /// (integers are encoded as variable-length integers).
+    ///
+    /// This is the serialization used by default by
+    /// [`register_message`](crate::prelude::AppMessageExt::register_message) and
+    /// [`register_component`](crate::prelude::AppComponentExt::register_component).
+    pub fn serde() -> Self {
+        Self {
+            serialize: default_serialize::<M>,
+            deserialize: default_deserialize::<M>,
+            serialize_map_entities: None,
+        }
+    }
+}
+
 pub(crate) fn serialize_map_entities<M>(
     message: &M,
     writer: &mut Writer,
@@ -158,21 +279,23 @@ unsafe fn erased_receive_map_entities<M: MapEntities + 'static>(
 
 impl ErasedSerializeFns {
     pub(crate) fn new<M: Message + Serialize + DeserializeOwned>() -> Self {
-        let serialize_fns = SerializeFns {
-            serialize: default_serialize::<M>,
-            deserialize: default_deserialize::<M>,
-            serialize_map_entities: None,
-        };
+        let serialize_fns = SerializeFns::<M>::serde();
         Self {
             type_id: TypeId::of::<M>(),
             type_name: std::any::type_name::<M>(),
             erased_serialize: erased_serialize_fn::<M>,
+            deserialize_boxed: erased_deserialize_boxed::<M>(),
             serialize: unsafe { std::mem::transmute(serialize_fns.serialize) },
             deserialize: unsafe { std::mem::transmute(serialize_fns.deserialize) },
             erased_clone: None,
             map_entities: None,
             send_map_entities: None,
             receive_map_entities: None,
+            version: None,
+            migrate: None,
+            context_dependent: false,
+            context_transform: None,
+            reflect_registry: None,
         }
     }
 
@@ -181,12 +304,50 @@ impl ErasedSerializeFns {
             type_id: TypeId::of::<M>(),
             type_name: std::any::type_name::<M>(),
             erased_serialize: erased_serialize_fn::<M>,
+            deserialize_boxed: erased_deserialize_boxed::<M>(),
             serialize: unsafe { std::mem::transmute(serialize_fns.serialize) },
             deserialize: unsafe { std::mem::transmute(serialize_fns.deserialize) },
             erased_clone: None,
             map_entities: None,
             send_map_entities: None,
             receive_map_entities: None,
+            version: None,
+            migrate: None,
+            context_dependent: false,
+            context_transform: None,
+            reflect_registry: None,
+        }
+    }
+
+    /// Serialize the type using its [`Reflect`](bevy::reflect::Reflect) implementation, so that it doesn't
+    /// need to implement [`Serialize`] and [`DeserializeOwned`].
+    ///
+    /// The type is registered in the `registry`, which should be the [`AppTypeRegistry`](bevy::prelude::AppTypeRegistry)
+    /// of the app. The type and its fields are serialized with their own serde implementation if they have
+    /// the `ReflectSerialize` and `ReflectDeserialize` type data (i.e. `#[reflect(Serialize, Deserialize)]`),
+    /// which can be used to provide custom serializers for some of the fields.
+    pub(crate) fn new_reflect<M: Message + FromReflect + TypePath + GetTypeRegistration>(
+        registry: TypeRegistryArc,
+    ) -> Self {
+        registry.write().register::<M>();
+        let serialize: ReflectSerializeFn<M> = reflect_serialize::<M>;
+        let deserialize: ReflectDeserializeFn<M> = reflect_deserialize::<M>;
+        Self {
+            type_id: TypeId::of::<M>(),
+            type_name: std::any::type_name::<M>(),
+            erased_serialize: erased_serialize_fn::<M>,
+            deserialize_boxed: erased_deserialize_boxed::<M>(),
+            serialize: unsafe { std::mem::transmute(serialize) },
+            deserialize: unsafe { std::mem::transmute(deserialize) },
+            erased_clone: None,
+            map_entities: None,
+            send_map_entities: None,
+            receive_map_entities: None,
+            version: None,
+            migrate: None,
+            context_dependent: false,
+            context_transform: None,
+            reflect_registry: Some(ReflectRegistry(registry)),
         }
     }
 
@@ -228,6 +389,57 @@ impl ErasedSerializeFns {
         self.erased_clone = Some(unsafe { std::mem::transmute(clone_fn) });
     }
 
+    /// Deserialize a value as a [`Box<dyn Message>`]
+    ///
+    /// SAFETY: the ErasedSerializeFns must be created for the type of the value
+    pub(crate) unsafe fn deserialize_boxed(
+        &self,
+        reader: &mut Reader,
+        entity_map: &mut ReceiveEntityMap,
+    ) -> Result<Box<dyn Message>, SerializationError> {
+        let deserialize_boxed: ErasedDeserializeBoxedFn =
+            std::mem::transmute(self.deserialize_boxed.0);
+        deserialize_boxed(self, reader, entity_map)
+    }
+
+    pub(crate) fn set_version(&mut self, version: u16) {
+        self.version = Some(version);
+    }
+
+    pub(crate) fn add_migration<M: 'static>(&mut self, migrate: MigrateFn<M>) {
+        self.migrate = Some(ErasedFnPtr(unsafe {
+            std::mem::transmute::<MigrateFn<M>, unsafe fn()>(migrate)
+        }));
+    }
+
+    pub(crate) fn set_context_dependent(&mut self) {
+        self.context_dependent = true;
+    }
+
+    pub(crate) fn add_context_transform<M: Clone + 'static>(
+        &mut self,
+        transform: ContextTransformFn<M>,
+    ) {
+        self.context_dependent = true;
+        self.context_transform = Some(ErasedFnPtr(unsafe {
+            std::mem::transmute::<ContextTransformFn<M>, unsafe fn()>(transform)
+        }));
+        let clone_fn: fn(&M) -> M = erased_clone::<M>;
+        self.erased_clone = Some(unsafe { std::mem::transmute(clone_fn) });
+    }
+
+    /// Returns a copy of the value adapted to the context of the writer, if the type has a context transform
+    ///
+    /// SAFETY: the ErasedSerializeFns must be created for the type M
+    unsafe fn transform_for_context<M: 'static>(&self, message: &M, writer: &Writer) -> Option<M> {
+        let transform: ContextTransformFn<M> = std::mem::transmute(self.context_transform?.0);
+        let context = writer.context()?;
+        let clone_fn: CloneFn<M> = std::mem::transmute(self.erased_clone?);
+        let mut value = clone_fn(message);
+        transform(&mut value, context);
+        Some(value)
+    }
+
     pub(crate) fn map_entities<M: 'static>(&self, message: &mut M, entity_map: &mut EntityMap) {
         let ptr = PtrMut::from(message);
         if let Some(map_entities_fn) = self.map_entities {
@@ -248,12 +460,32 @@ impl ErasedSerializeFns {
         entity_map: Option<&mut SendEntityMap>,
     ) -> Result<(), SerializationError> {
         let fns = unsafe { self.typed::<M>() };
-        if let Some(map_entities) = self.send_map_entities {
+        let transformed = self.transform_for_context(message, writer);
+        let message = transformed.as_ref().unwrap_or(message);
+        let entity_map = self.send_map_entities.map(|map_entities| {
+            (
+                map_entities,
+                entity_map.expect("EntityMap is required to serialize this message"),
+            )
+        });
+        if let Some(ReflectRegistry(registry)) = &self.reflect_registry {
+            let serialize: ReflectSerializeFn<M> = std::mem::transmute(self.serialize);
+            return match entity_map {
+                Some((map_entities, entity_map)) => {
+                    let clone_fn: CloneFn<M> = std::mem::transmute(self.erased_clone.unwrap());
+                    let mut message = clone_fn(message);
+                    map_entities(PtrMut::from(&mut message), entity_map);
+                    serialize(&registry.read(), &message, writer)
+                }
+                None => serialize(&registry.read(), message, writer),
+            };
+        }
+        if let Some((map_entities, entity_map)) = entity_map {
             let serialize_map_entities = fns.serialize_map_entities.unwrap();
             serialize_map_entities(
                 message,
                 writer,
-                entity_map.expect("EntityMap is required to serialize this message"),
+                entity_map,
                 std::mem::transmute(self.erased_clone.unwrap()),
                 map_entities,
                 fns.serialize,
@@ -263,6 +495,23 @@ impl ErasedSerializeFns {
         }
     }
 
+    /// Deserialize a value written with the current version of the type
+    ///
+    /// SAFETY: the ErasedSerializeFns must be created for the type M
+    unsafe fn deserialize_value<M: 'static>(
+        &self,
+        fns: &SerializeFns<M>,
+        reader: &mut Reader,
+    ) -> Result<M, SerializationError> {
+        match &self.reflect_registry {
+            Some(ReflectRegistry(registry)) => {
+                let deserialize: ReflectDeserializeFn<M> = std::mem::transmute(self.deserialize);
+                deserialize(&registry.read(), reader)
+            }
+            None => (fns.deserialize)(reader),
+        }
+    }
+
     /// Deserialize the message value from the reader
     ///
     /// SAFETY: the ErasedSerializeFns must be created for the type M
@@ -272,7 +521,24 @@ impl ErasedSerializeFns {
         entity_map: &mut ReceiveEntityMap,
     ) -> Result<M, SerializationError> {
         let fns = unsafe { self.typed::<M>() };
-        let mut message = (fns.deserialize)(reader)?;
+        let mut message = match self.version {
+            None => self.deserialize_value(&fns, reader)?,
+            Some(current) => {
+                // the values that are not received from a remote peer use the current version
+                let version = reader.remote_version(self.type_id).unwrap_or(current);
+                if version == current {
+                    self.deserialize_value(&fns, reader)?
+                } else {
+                    // values written with a newer version cannot be read
+                    let migrate = self
+                        .migrate
+                        .filter(|_| version < current)
+                        .ok_or(SerializationError::UnsupportedVersion(version as u64))?;
+                    let migrate: MigrateFn<M> = unsafe { std::mem::transmute(migrate.0) };
+                    migrate(version, reader)?
+                }
+            }
+        };
         if let Some(map_entities) = self.receive_map_entities {
             map_entities(PtrMut::from(&mut message), entity_map);
         }
@@ -301,13 +567,16 @@ impl AppSerializeExt for App {
 
 #[cfg(test)]
 mod tests {
-    use crate::protocol::serialize::{erased_serialize_fn, ErasedSerializeFns};
+    use crate::protocol::serialize::{erased_serialize_fn, ErasedSerializeFns, SerializeFns};
     use crate::serialize::reader::Reader;
     use crate::serialize::writer::Writer;
     use crate::shared::replication::authority::AuthorityChange;
     use crate::shared::replication::entity_map::{ReceiveEntityMap, SendEntityMap};
     use bevy::prelude::Entity;
+    use bevy::prelude::Reflect;
     use bevy::ptr::Ptr;
+    use bevy::reflect::{ReflectDeserialize, ReflectSerialize, TypeRegistryArc};
+    use serde::{Deserialize, Serialize};
 
     #[test]
     fn test_erased_serde() {
@@ -337,6 +606,73 @@ mod tests {
         assert_eq!(new_message, message);
     }
 
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct SerdeMessage {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn test_serde_fns() {
+        let fns = SerializeFns::<SerdeMessage>::serde();
+        let message = SerdeMessage {
+            id: 3,
+            name: "a".to_string(),
+        };
+        let mut writer = Writer::default();
+        (fns.serialize)(&message, &mut writer).unwrap();
+        let data = writer.to_bytes();
+        // the integers are encoded as varints
+        assert_eq!(data.len(), 3);
+        assert_eq!((fns.deserialize)(&mut Reader::from(data)).unwrap(), message);
+    }
+
+    #[derive(Reflect, Debug, Clone, PartialEq)]
+    struct ReflectMessage {
+        value: f32,
+        #[reflect(ignore)]
+        skipped: u8,
+        nested: Option<ReflectNested>,
+    }
+
+    /// Uses its serde implementation instead of the reflection data
+    #[derive(Reflect, Serialize, Deserialize, Debug, Clone, PartialEq)]
+    #[reflect(Serialize, Deserialize)]
+    struct ReflectNested(Vec<String>);
+
+    #[test]
+    fn test_reflect_serde() {
+        let registry =
+            ErasedSerializeFns::new_reflect::<ReflectMessage>(TypeRegistryArc::default());
+        let message = ReflectMessage {
+            value: 1.0,
+            skipped: 2,
+            nested: Some(ReflectNested(vec!["a".to_string()])),
+        };
+        let mut writer = Writer::default();
+        unsafe { registry.serialize(&message, &mut writer, None) }.unwrap();
+        // write a second value to check that the reader is advanced correctly
+        unsafe { registry.serialize(&message, &mut writer, None) }.unwrap();
+
+        let data = writer.to_bytes();
+        let mut reader = Reader::from(data);
+        for _ in 0..2 {
+            let new_message = unsafe {
+                registry
+                    .deserialize::<ReflectMessage>(&mut reader, &mut ReceiveEntityMap::default())
+            }
+            .unwrap();
+            assert_eq!(
+                new_message,
+                ReflectMessage {
+                    skipped: 0,
+                    ..message.clone()
+                }
+            );
+        }
+        assert!(!reader.has_remaining());
+    }
+
     #[test]
     fn test_erased_serde_map_entities() {
         let mut registry = ErasedSerializeFns::new::<AuthorityChange>();
@@ -0,0 +1,284 @@
+//! Detect the misconfigurations of the protocol when the server starts.
+//!
+//! Some invalid settings are accepted when the protocol is registered, but only fail later at runtime: a
+//! channel with a `NaN` priority panics the first time its messages are sorted under a bandwidth cap, a reliable
+//! channel with a zero resend delay floods the connection, a keep-alive interval longer than the client timeout
+//! disconnects the idle clients, etc.
+//!
+//! [`validate_protocol`] checks the registered channels and the [`ServerConfig`], and returns the list of all the
+//! issues that were found. It runs automatically when the server starts: if any issue is found, the server is not
+//! started and the issues are emitted as a critical [`ServerErrorEvent`](crate::server::error_events::ServerErrorEvent).
+//! It can also be called in a test, to make sure that the protocol of a game stays valid:
+//! ```rust,ignore
+//! validate_protocol(app.world().resource::<ChannelRegistry>(), app.world().resource::<ServerConfig>()).unwrap();
+//! ```
+//!
+//! Registering the same channel, component or message twice is already rejected when it is registered.
+use std::fmt::{Display, Formatter};
+
+use bevy::utils::Duration;
+
+use crate::channel::builder::ChannelMode;
+use crate::connection::server::NetConfig;
+use crate::prelude::server::ServerConfig;
+use crate::prelude::ChannelRegistry;
+
+/// A misconfiguration of the protocol
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ProtocolIssue {
+    #[error("channel {channel}: the priority must be positive, got {priority}")]
+    InvalidChannelPriority { channel: String, priority: f32 },
+    #[error("channel {channel}: the messages are replayed to the clients that join a room, but the channel is not reliable")]
+    ReplayOnUnreliableChannel { channel: String },
+    #[error("channel {channel}: the messages are replayed to the clients that join a room, but the channel is sequenced so only the last one is received")]
+    ReplayOnSequencedChannel { channel: String },
+    #[error("channel {channel}: the resend delay is zero, so the unacked messages are resent on every frame")]
+    ZeroResendDelay { channel: String },
+    #[error("the tick duration ({tick_duration:?}) is not shorter than the client timeout ({client_timeout:?})")]
+    TickLongerThanTimeout {
+        tick_duration: Duration,
+        client_timeout: Duration,
+    },
+    #[error("the keep-alive interval ({keep_alive_interval:?}) is not shorter than the client timeout ({client_timeout:?}), so the idle clients are disconnected")]
+    KeepAliveLongerThanTimeout {
+        keep_alive_interval: Duration,
+        client_timeout: Duration,
+    },
+}
+
+/// Error returned by [`validate_protocol`], with all the issues that were found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolValidationError {
+    pub issues: Vec<ProtocolIssue>,
+}
+
+impl Display for ProtocolValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the protocol has {} issue(s):", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "\n- {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProtocolValidationError {}
+
+/// Check the channels of the protocol and the timing settings of the server
+pub fn validate_protocol(
+    channels: &ChannelRegistry,
+    config: &ServerConfig,
+) -> Result<(), ProtocolValidationError> {
+    let mut issues = vec![];
+    for (_, kind) in channels.kind_map.sorted() {
+        let Some(builder) = channels.get_builder_from_kind(&kind) else {
+            continue;
+        };
+        let channel = channels.name(&kind).unwrap_or("unknown").to_string();
+        let settings = &builder.settings;
+        // an infinite priority is valid: the messages of the channel are always sent first
+        if settings.priority.is_nan() || settings.priority <= 0.0 {
+            issues.push(ProtocolIssue::InvalidChannelPriority {
+                channel: channel.clone(),
+                priority: settings.priority,
+            });
+        }
+        let reliable_settings = match &settings.mode {
+            ChannelMode::UnorderedReliable(reliable_settings)
+            | ChannelMode::SequencedReliable(reliable_settings)
+            | ChannelMode::OrderedReliable(reliable_settings) => Some(reliable_settings),
+            _ => None,
+        };
+        if settings.replay_on_join.is_some() && reliable_settings.is_none() {
+            issues.push(ProtocolIssue::ReplayOnUnreliableChannel {
+                channel: channel.clone(),
+            });
+        }
+        // the history is sent to the joining client, but a sequenced channel drops the older messages
+        if settings.replay_on_join.is_some_and(|history| history > 1)
+            && matches!(
+                settings.mode,
+                ChannelMode::SequencedReliable(_) | ChannelMode::SequencedUnreliable
+            )
+        {
+            issues.push(ProtocolIssue::ReplayOnSequencedChannel {
+                channel: channel.clone(),
+            });
+        }
+        if let Some(reliable_settings) = reliable_settings {
+            // the delay is the smallest for an RTT of 0, and only grows with the RTT if the factor is positive
+            if reliable_settings.resend_delay(Duration::ZERO).is_zero()
+                && (reliable_settings.resend_interval.is_some()
+                    || reliable_settings.rtt_resend_factor <= 0.0)
+            {
+                issues.push(ProtocolIssue::ZeroResendDelay { channel });
+            }
+        }
+    }
+
+    let tick_duration = config.shared.tick.tick_duration;
+    for net_config in &config.net {
+        #[allow(irrefutable_let_patterns)]
+        let NetConfig::Netcode { config, .. } = net_config
+        else {
+            continue;
+        };
+        // a negative timeout means that the clients never time out
+        let Ok(client_timeout) = u64::try_from(config.client_timeout_secs).map(Duration::from_secs)
+        else {
+            continue;
+        };
+        if tick_duration >= client_timeout {
+            issues.push(ProtocolIssue::TickLongerThanTimeout {
+                tick_duration,
+                client_timeout,
+            });
+        }
+        let keep_alive_interval =
+            Duration::try_from_secs_f64(config.keep_alive_send_rate).unwrap_or(Duration::MAX);
+        if keep_alive_interval >= client_timeout {
+            issues.push(ProtocolIssue::KeepAliveLongerThanTimeout {
+                keep_alive_interval,
+                client_timeout,
+            });
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ProtocolValidationError { issues })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channel::builder::{ChannelSettings, ReliableSettings};
+    use crate::prelude::server::NetcodeConfig;
+    use crate::prelude::{SharedConfig, TickConfig};
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::{Commands, EventReader, Last, ResMut, Resource, State};
+
+    use crate::prelude::server::{NetworkingState, ServerCommands, ServerConnections};
+    use crate::server::error_events::{ErrorSeverity, ServerErrorEvent};
+    use crate::tests::protocol::{Channel1, Channel2, ReliableChannel};
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_protocol() {
+        let mut channels = ChannelRegistry::default();
+        channels.add_channel::<Channel1>(ChannelSettings {
+            mode: ChannelMode::UnorderedReliable(ReliableSettings::default()),
+            replay_on_join: Some(10),
+            ..Default::default()
+        });
+        let config = ServerConfig {
+            shared: SharedConfig {
+                tick: TickConfig::new(Duration::from_millis(16)),
+                ..Default::default()
+            },
+            net: vec![NetConfig::Netcode {
+                config: NetcodeConfig::default(),
+                io: Default::default(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(validate_protocol(&channels, &config), Ok(()));
+
+        channels.add_channel::<Channel2>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::new(0.0, Duration::ZERO)),
+            priority: f32::NAN,
+            replay_on_join: Some(10),
+            ..Default::default()
+        });
+        channels.add_channel::<ReliableChannel>(ChannelSettings {
+            mode: ChannelMode::SequencedReliable(ReliableSettings::default()),
+            replay_on_join: Some(10),
+            ..Default::default()
+        });
+        let config = ServerConfig {
+            net: vec![NetConfig::Netcode {
+                config: NetcodeConfig {
+                    keep_alive_send_rate: 5.0,
+                    ..Default::default()
+                },
+                io: Default::default(),
+            }],
+            ..config
+        };
+        let issues = validate_protocol(&channels, &config).unwrap_err().issues;
+        assert_eq!(issues.len(), 4);
+        assert!(matches!(
+            issues[0],
+            ProtocolIssue::InvalidChannelPriority { .. }
+        ));
+        assert!(matches!(issues[1], ProtocolIssue::ZeroResendDelay { .. }));
+        assert!(matches!(
+            issues[2],
+            ProtocolIssue::ReplayOnSequencedChannel { .. }
+        ));
+        assert!(matches!(
+            issues[3],
+            ProtocolIssue::KeepAliveLongerThanTimeout { .. }
+        ));
+    }
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<ServerErrorEvent>);
+
+    /// The server refuses to start with an invalid protocol
+    #[test]
+    fn test_invalid_protocol_is_not_started() {
+        let mut stepper = BevyStepper::default();
+        stepper.server_app.init_resource::<Received>();
+        stepper.server_app.add_systems(
+            Last,
+            |mut events: EventReader<ServerErrorEvent>, mut received: ResMut<Received>| {
+                received.0.extend(events.read().cloned());
+            },
+        );
+        stepper
+            .server_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.stop_server());
+        stepper.frame_step();
+        #[allow(irrefutable_let_patterns)]
+        if let NetConfig::Netcode { config, .. } = &mut stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .net[0]
+        {
+            config.keep_alive_send_rate = 1000.0;
+        }
+        stepper
+            .server_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.start_server());
+        for _ in 0..3 {
+            stepper.frame_step();
+        }
+
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Stopped
+        );
+        assert!(!stepper
+            .server_app
+            .world()
+            .resource::<ServerConnections>()
+            .is_listening());
+        // the client that was connected before the restart can also produce errors
+        let received = &stepper.server_app.world().resource::<Received>().0;
+        assert!(received
+            .iter()
+            .any(|event| event.severity == ErrorSeverity::Critical
+                && event.error.contains("keep-alive interval")));
+    }
+}
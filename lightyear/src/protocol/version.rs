@@ -0,0 +1,160 @@
+//! Exchange the wire format versions of the messages and components with the remote peer
+//!
+//! The values of a type registered with a version (see
+//! [`MessageRegistration::with_version`](crate::protocol::message::MessageRegistration::with_version)) are written
+//! without any version information. Instead, each peer sends the versions of all its types once, in a
+//! [`SchemaVersions`] message, as the first message of the [`ControlChannel`](crate::channel::builder::ControlChannel)
+//! of the connection. The values received from the peer are then read with the migration function of their type
+//! if the peer uses an older version.
+//!
+//! A peer that uses types registered with a version doesn't read any other channel of the connection until it
+//! receives the versions of the remote peer, so that no value is read with the wrong version.
+use std::any::TypeId;
+
+use bevy::utils::HashMap;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::component::ComponentRegistry;
+use crate::protocol::message::{MessageError, MessageRegistry};
+use crate::protocol::registry::NetId;
+use crate::protocol::serialize::ErasedSerializeFns;
+use crate::serialize::reader::Reader;
+use crate::shared::replication::entity_map::ReceiveEntityMap;
+
+/// Versions of the types registered with a version, sent once to the remote peer when the connection starts
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct SchemaVersions {
+    messages: Vec<(NetId, u16)>,
+    components: Vec<(NetId, u16)>,
+}
+
+impl SchemaVersions {
+    pub(crate) fn new(
+        message_registry: &MessageRegistry,
+        component_registry: &ComponentRegistry,
+    ) -> Self {
+        Self {
+            messages: versions(message_registry.net_serialize_fns()),
+            components: versions(component_registry.net_serialize_fns()),
+        }
+    }
+
+    /// Returns true if no type is registered with a version
+    pub(crate) fn is_empty(&self) -> bool {
+        self.messages.is_empty() && self.components.is_empty()
+    }
+
+    /// Resolve the versions of the remote peer to the local types
+    pub(crate) fn resolve(
+        &self,
+        message_registry: &MessageRegistry,
+        component_registry: &ComponentRegistry,
+    ) -> RemoteVersions {
+        let mut versions = HashMap::default();
+        resolve(
+            &self.messages,
+            message_registry.net_serialize_fns(),
+            &mut versions,
+        );
+        resolve(
+            &self.components,
+            component_registry.net_serialize_fns(),
+            &mut versions,
+        );
+        RemoteVersions(versions)
+    }
+}
+
+fn versions<'a>(
+    serialize_fns: impl Iterator<Item = (NetId, &'a ErasedSerializeFns)>,
+) -> Vec<(NetId, u16)> {
+    let mut versions: Vec<_> = serialize_fns
+        .filter_map(|(net_id, fns)| Some((net_id, fns.version?)))
+        .collect();
+    versions.sort_unstable();
+    versions
+}
+
+fn resolve<'a>(
+    remote: &[(NetId, u16)],
+    serialize_fns: impl Iterator<Item = (NetId, &'a ErasedSerializeFns)>,
+    versions: &mut HashMap<TypeId, u16>,
+) {
+    let type_ids: HashMap<NetId, TypeId> = serialize_fns
+        .map(|(net_id, fns)| (net_id, fns.type_id))
+        .collect();
+    versions.extend(
+        remote
+            .iter()
+            .filter_map(|(net_id, version)| Some((*type_ids.get(net_id)?, *version))),
+    );
+}
+
+/// Wire format versions used by the remote peer
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct RemoteVersions(HashMap<TypeId, u16>);
+
+impl RemoteVersions {
+    /// Read the [`SchemaVersions`] message received from the remote peer
+    pub(crate) fn read(
+        message: Bytes,
+        message_registry: &MessageRegistry,
+        component_registry: &ComponentRegistry,
+    ) -> Result<Self, MessageError> {
+        let versions = message_registry.deserialize::<SchemaVersions>(
+            &mut Reader::from(message),
+            &mut ReceiveEntityMap::default(),
+        )?;
+        Ok(versions.resolve(message_registry, component_registry))
+    }
+
+    /// Version of the type used by the remote peer. A type that the peer didn't register with a version
+    /// uses the version 0
+    pub(crate) fn get(&self, type_id: TypeId) -> u16 {
+        self.0.get(&type_id).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::message::MessageType;
+    use crate::serialize::writer::Writer;
+    use crate::tests::protocol::{StringMessage, TracedMessage};
+
+    fn registry(versions: [Option<u16>; 2]) -> MessageRegistry {
+        let mut registry = MessageRegistry::default();
+        registry.add_message::<SchemaVersions>(MessageType::Normal);
+        registry.add_message::<StringMessage>(MessageType::Normal);
+        registry.add_message::<TracedMessage>(MessageType::Normal);
+        if let Some(version) = versions[0] {
+            registry
+                .erased_fns_mut::<StringMessage>()
+                .set_version(version);
+        }
+        if let Some(version) = versions[1] {
+            registry
+                .erased_fns_mut::<TracedMessage>()
+                .set_version(version);
+        }
+        registry
+    }
+
+    #[test]
+    fn test_exchange_versions() {
+        let component_registry = ComponentRegistry::default();
+        let remote = registry([None, Some(1)]);
+        let local = registry([Some(2), Some(3)]);
+        assert!(SchemaVersions::new(&registry([None, None]), &component_registry).is_empty());
+
+        let versions = SchemaVersions::new(&remote, &component_registry);
+        let mut writer = Writer::default();
+        remote.serialize(&versions, &mut writer, None).unwrap();
+        let versions =
+            RemoteVersions::read(writer.to_bytes(), &local, &component_registry).unwrap();
+        assert_eq!(versions.get(TypeId::of::<TracedMessage>()), 1);
+        // the remote peer didn't register a version for the type
+        assert_eq!(versions.get(TypeId::of::<StringMessage>()), 0);
+    }
+}
@@ -0,0 +1,115 @@
+//! Context of the connection for which values are serialized
+//!
+//! Each connection can carry a small [`SerializationContext`] (locale, platform, build flags) so that the payloads
+//! can differ between connections: censored names in some regions, platform-specific ids, etc.
+//!
+//! When a value is serialized for a single connection, the context of that connection is available to the
+//! custom serialization functions with [`Writer::context`](crate::serialize::writer::Writer::context).
+//! Components can also register a [`ContextTransformFn`](crate::protocol::serialize::ContextTransformFn) with
+//! `ComponentRegistration::add_context_transform`, which adapts a copy of the value to the context before it is
+//! serialized.
+use serde::{Deserialize, Serialize};
+
+/// Per-connection settings available during serialization
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SerializationContext {
+    /// Locale of the remote peer (for example `fr-FR`)
+    pub locale: Option<String>,
+    /// Platform of the remote peer
+    pub platform: Option<String>,
+    /// Application-defined build flags
+    pub flags: u64,
+}
+
+impl SerializationContext {
+    pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    pub fn with_platform(mut self, platform: impl Into<String>) -> Self {
+        self.platform = Some(platform.into());
+        self
+    }
+
+    pub fn with_flags(mut self, flags: u64) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Returns true if all the bits of `flag` are set
+    pub fn has_flag(&self, flag: u64) -> bool {
+        self.flags & flag == flag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::{ConnectionManager, Replicate};
+    use crate::prelude::{client, ClientId};
+    use crate::tests::protocol::ComponentContextTransform;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn client_value(stepper: &BevyStepper, server_entity: bevy::prelude::Entity) -> f32 {
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+        stepper
+            .client_app
+            .world()
+            .get::<ComponentContextTransform>(client_entity)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_context_transform() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .set_serialization_context(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                SerializationContext::default()
+                    .with_locale("fr-FR")
+                    .with_flags(10),
+            )
+            .unwrap();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentContextTransform(1.0), Replicate::default()))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        // the value is adapted to the context of the client, but not on the server
+        assert_eq!(client_value(&stepper, server_entity), 11.0);
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .get::<ComponentContextTransform>(server_entity)
+                .unwrap()
+                .0,
+            1.0
+        );
+
+        stepper
+            .server_app
+            .world_mut()
+            .get_mut::<ComponentContextTransform>(server_entity)
+            .unwrap()
+            .0 = 2.0;
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(client_value(&stepper, server_entity), 12.0);
+    }
+}
@@ -7,7 +7,9 @@ use bytes::Bytes;
 use hashbrown::HashMap;
 use std::hash::{BuildHasher, Hash};
 
+pub mod context;
 pub mod reader;
+mod types;
 pub(crate) mod varint;
 pub mod writer;
 
@@ -30,6 +32,22 @@ pub enum SerializationError {
     BincodeDecode(#[from] bincode::error::DecodeError),
     #[error("The message is too big ({0} bytes) to be sent. We can split a message only up to 256 fragments.")]
     MessageTooBig(usize),
+    #[error("The value was written with the version {0} of its wire format, which cannot be read")]
+    UnsupportedVersion(u64),
+}
+
+impl SerializationError {
+    /// Maximum length of the bounded [`Writer`](writer::Writer) that the value didn't fit in, if the
+    /// error is an overflow
+    pub fn overflow(&self) -> Option<usize> {
+        match self {
+            SerializationError::Io(err) => err
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<writer::WriterOverflow>())
+                .map(|overflow| overflow.0),
+            _ => None,
+        }
+    }
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -1,62 +1,95 @@
 use bytes::{Buf, Bytes};
+use std::any::TypeId;
 use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use crate::protocol::version::RemoteVersions;
 
 #[derive(Clone)]
-pub struct Reader(Cursor<Bytes>);
+pub struct Reader {
+    cursor: Cursor<Bytes>,
+    /// Wire format versions of the remote peer that wrote the bytes, if they were received from a peer
+    versions: Option<Arc<RemoteVersions>>,
+}
 
 impl From<Bytes> for Reader {
     fn from(value: Bytes) -> Self {
         // TODO: check that this has no cost
-        Self(Cursor::new(value))
+        Self {
+            cursor: Cursor::new(value),
+            versions: None,
+        }
     }
 }
 
 impl From<Vec<u8>> for Reader {
     fn from(value: Vec<u8>) -> Self {
-        Self(Cursor::new(value.into()))
+        Self::from(Bytes::from(value))
     }
 }
 
 impl Seek for Reader {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        self.0.seek(pos)
+        self.cursor.seek(pos)
     }
 }
 
 impl Read for Reader {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.0.read(buf)
+        self.cursor.read(buf)
     }
 }
 
 impl Reader {
+    /// Read the values with the wire format versions of the remote peer that wrote them
+    pub(crate) fn with_versions(mut self, versions: Option<Arc<RemoteVersions>>) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Version of the type used by the remote peer that wrote the bytes, or `None` if the bytes were not
+    /// received from a peer
+    pub(crate) fn remote_version(&self, type_id: TypeId) -> Option<u16> {
+        self.versions.as_ref().map(|versions| versions.get(type_id))
+    }
+
     /// Returns the underlying RawData
     pub(crate) fn consume(self) -> Bytes {
-        self.0.into_inner()
+        self.cursor.into_inner()
     }
 
     pub(crate) fn len(&self) -> usize {
-        self.0.get_ref().len()
+        self.cursor.get_ref().len()
     }
 
     /// Split of the next `len` bytes from the reader into a separate Bytes.
     ///
     /// This doesn't allocate and just increases some reference counts. O(1) cost.
     pub(crate) fn split_len(&mut self, len: usize) -> Bytes {
-        let current_pos = self.0.position() as usize;
+        let current_pos = self.cursor.position() as usize;
         let new_pos = current_pos + len;
         // slice off the subset into a separate Bytes
-        let bytes = self.0.get_ref().slice(current_pos..new_pos);
+        let bytes = self.cursor.get_ref().slice(current_pos..new_pos);
         // increment the position
-        self.0.set_position(new_pos as u64);
+        self.cursor.set_position(new_pos as u64);
         bytes
     }
 
     pub(crate) fn has_remaining(&self) -> bool {
-        self.0.has_remaining()
+        self.cursor.has_remaining()
     }
 
     pub(crate) fn remaining(&self) -> usize {
-        self.0.remaining()
+        self.cursor.remaining()
+    }
+
+    /// The bytes that haven't been read yet
+    pub(crate) fn remaining_slice(&self) -> &[u8] {
+        self.cursor.chunk()
+    }
+
+    /// Skip the next `len` bytes
+    pub(crate) fn advance(&mut self, len: usize) {
+        self.cursor.advance(len)
     }
 }
@@ -0,0 +1,208 @@
+//! [`ToBytes`] implementations for common types of the ecosystem, so that they can be used in custom
+//! serialization functions without writing wrappers.
+//!
+//! The implementations for [`Uuid`](uuid::Uuid) and [`SmolStr`](smol_str::SmolStr) require the `uuid` and
+//! `smol_str` features.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use bevy::math::{Quat, Vec2, Vec3, Vec4};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+
+use crate::serialize::reader::Reader;
+use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
+use crate::serialize::{SerializationError, ToBytes};
+
+/// Read a length-prefixed byte string, checking that the length doesn't exceed the remaining bytes
+fn read_len_prefixed(buffer: &mut Reader) -> Result<Bytes, SerializationError> {
+    let len = buffer.read_varint()? as usize;
+    if len > buffer.remaining() {
+        return Err(SerializationError::InvalidValue);
+    }
+    Ok(buffer.split_len(len))
+}
+
+/// The seconds and the nanoseconds are written as varints
+impl ToBytes for Duration {
+    fn len(&self) -> usize {
+        varint_len(self.as_secs()) + varint_len(self.subsec_nanos() as u64)
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.as_secs())?;
+        buffer.write_varint(self.subsec_nanos() as u64)?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+        let secs = buffer.read_varint()?;
+        let nanos = buffer.read_varint()?;
+        if nanos >= 1_000_000_000 {
+            return Err(SerializationError::InvalidValue);
+        }
+        Ok(Duration::new(secs, nanos as u32))
+    }
+}
+
+impl ToBytes for String {
+    fn len(&self) -> usize {
+        varint_len(self.len() as u64) + self.len()
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.len() as u64)?;
+        buffer.write_all(self.as_bytes())?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+        let bytes = read_len_prefixed(buffer)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SerializationError::InvalidValue)
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "smol_str")))]
+#[cfg(feature = "smol_str")]
+impl ToBytes for smol_str::SmolStr {
+    fn len(&self) -> usize {
+        varint_len(self.len() as u64) + self.len()
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.len() as u64)?;
+        buffer.write_all(self.as_bytes())?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+        let bytes = read_len_prefixed(buffer)?;
+        // short strings are stored inline without allocating
+        std::str::from_utf8(&bytes)
+            .map(smol_str::SmolStr::new)
+            .map_err(|_| SerializationError::InvalidValue)
+    }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "uuid")))]
+#[cfg(feature = "uuid")]
+impl ToBytes for uuid::Uuid {
+    fn len(&self) -> usize {
+        16
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_u128::<NetworkEndian>(self.as_u128())?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+        Ok(uuid::Uuid::from_u128(buffer.read_u128::<NetworkEndian>()?))
+    }
+}
+
+macro_rules! impl_to_bytes_f32s {
+    ($type:ty, $n:literal) => {
+        impl ToBytes for $type {
+            fn len(&self) -> usize {
+                4 * $n
+            }
+
+            fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+                for value in self.to_array() {
+                    buffer.write_f32::<NetworkEndian>(value)?;
+                }
+                Ok(())
+            }
+
+            fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+                let mut values = [0.0; $n];
+                for value in values.iter_mut() {
+                    *value = buffer.read_f32::<NetworkEndian>()?;
+                }
+                Ok(Self::from_array(values))
+            }
+        }
+    };
+}
+
+impl_to_bytes_f32s!(Vec2, 2);
+impl_to_bytes_f32s!(Vec3, 3);
+impl_to_bytes_f32s!(Vec4, 4);
+impl_to_bytes_f32s!(Quat, 4);
+
+impl<K: ToBytes + Ord, V: ToBytes> ToBytes for BTreeMap<K, V> {
+    fn len(&self) -> usize {
+        varint_len(self.len() as u64) + self.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>()
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.len() as u64)?;
+        self.iter().try_for_each(|(k, v)| {
+            k.to_bytes(buffer)?;
+            v.to_bytes(buffer)?;
+            Ok::<(), SerializationError>(())
+        })?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError> {
+        let len = buffer.read_varint()? as usize;
+        let mut res = BTreeMap::new();
+        for _ in 0..len {
+            let key = K::from_bytes(buffer)?;
+            let value = V::from_bytes(buffer)?;
+            res.insert(key, value);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::io::Write;
+
+    use crate::serialize::writer::Writer;
+
+    use super::*;
+
+    fn roundtrip<M: ToBytes + PartialEq + Debug>(value: M) {
+        let mut writer = Writer::default();
+        value.to_bytes(&mut writer).unwrap();
+        let bytes = writer.to_bytes();
+        assert_eq!(bytes.len(), value.len());
+        let mut reader = Reader::from(bytes);
+        assert_eq!(M::from_bytes(&mut reader).unwrap(), value);
+        assert!(!reader.has_remaining());
+    }
+
+    #[test]
+    fn test_ecosystem_types() {
+        roundtrip(Duration::from_millis(1500));
+        roundtrip("hello".to_string());
+        roundtrip(Vec3::new(1.0, -2.5, 3.0));
+        roundtrip(Quat::from_rotation_y(1.0));
+        roundtrip(BTreeMap::from([
+            (Duration::from_secs(1), Vec2::X),
+            (Duration::from_secs(2), Vec2::Y),
+        ]));
+        #[cfg(feature = "uuid")]
+        roundtrip(uuid::Uuid::from_u128(0x1234_5678_9abc_def0));
+        #[cfg(feature = "smol_str")]
+        roundtrip(smol_str::SmolStr::new("world"));
+    }
+
+    #[test]
+    fn test_invalid_string() {
+        let mut writer = Writer::default();
+        // the length is bigger than the remaining bytes
+        writer.write_varint(10).unwrap();
+        writer.write_all(b"abc").unwrap();
+        let mut reader = Reader::from(writer.to_bytes());
+        assert!(matches!(
+            String::from_bytes(&mut reader),
+            Err(SerializationError::InvalidValue)
+        ));
+    }
+}
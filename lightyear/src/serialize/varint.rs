@@ -32,6 +32,17 @@ pub const fn varint_parse_len(first: u8) -> usize {
     }
 }
 
+/// Map a signed integer to an unsigned integer, so that values close to 0 (positive or negative)
+/// have a short variable-length encoding: 0 -> 0, -1 -> 1, 1 -> 2, -2 -> 3, ...
+pub const fn zigzag_encode(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+/// Inverse of [`zigzag_encode`]
+pub const fn zigzag_decode(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
 pub trait VarIntWriteExt: WriteBytesExt {
     /// Write a variable length integer to the writer, in network byte order
     fn write_varint(&mut self, value: u64) -> Result<(), SerializationError> {
@@ -47,7 +58,7 @@ pub trait VarIntWriteExt: WriteBytesExt {
                 self.write_u32::<NetworkEndian>(val)?;
             }
             8 => {
-                let val = value | 0xc0_0000_0000_0000;
+                let val = value | 0xc000_0000_0000_0000;
                 self.write_u64::<NetworkEndian>(val)?;
             }
             _ => return Err(std::io::Error::other("value is too large for varint").into()),
@@ -91,9 +102,30 @@ impl<T: ReadBytesExt + Seek> VarIntReadExt for T {}
 
 #[cfg(test)]
 mod tests {
-    use crate::serialize::varint::{VarIntReadExt, VarIntWriteExt};
+    use crate::serialize::varint::{zigzag_decode, zigzag_encode, VarIntReadExt, VarIntWriteExt};
     use std::io::Cursor;
 
+    #[test]
+    fn test_zigzag() {
+        assert_eq!(zigzag_encode(0), 0);
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(zigzag_encode(-2), 3);
+        for v in [
+            0,
+            1,
+            -1,
+            63,
+            -64,
+            i16::MAX as i64,
+            i16::MIN as i64,
+            i64::MAX,
+            i64::MIN,
+        ] {
+            assert_eq!(zigzag_decode(zigzag_encode(v)), v);
+        }
+    }
+
     #[test]
     fn test_varint_len_1() {
         // TEST WITH 1
@@ -144,4 +176,17 @@ mod tests {
         let read_val = reader.read_varint().unwrap();
         assert_eq!(val, read_val);
     }
+
+    #[test]
+    fn test_varint_len_8() {
+        let mut writer = vec![];
+
+        let val = 1 << 31;
+        writer.write_varint(val).unwrap();
+        assert_eq!(writer.len(), 8);
+
+        let mut reader = Cursor::new(writer);
+        let read_val = reader.read_varint().unwrap();
+        assert_eq!(val, read_val);
+    }
 }
@@ -5,19 +5,52 @@
 //!
 //! The idea is that we have one allocation under the [`BytesMut`], when we finish writing a message,
 //! we can split the message of as a separate [`Bytes`], but
+//!
+//! By default the writer grows as needed. A writer created with [`Writer::with_max_len`] is bounded: a single write
+//! that would go past the maximum length fails without writing anything, and
+//! [`SerializationError::overflow`](crate::serialize::SerializationError::overflow) returns the maximum length.
+//! A value serialized with several writes can still be partially written when one of them fails; serialize it
+//! with [`Writer::write_or_rollback`] to remove the bytes of the failed value.
+//!
+//! When a value is serialized for a single connection, the writer holds the [`SerializationContext`] of that
+//! connection until the value is split off.
 use bytes::{BufMut, Bytes, BytesMut};
 use std::io::Write;
+use std::sync::Arc;
+
+use crate::serialize::context::SerializationContext;
 
 #[derive(Debug)]
-pub struct Writer(bytes::buf::Writer<BytesMut>);
+pub struct Writer {
+    inner: bytes::buf::Writer<BytesMut>,
+    /// Maximum number of bytes that can be written, if the writer is bounded
+    max_len: Option<usize>,
+    /// Context of the connection for which the current value is serialized
+    context: Option<Arc<SerializationContext>>,
+}
+
+/// Error returned by a bounded [`Writer`] when a write would go past its maximum length
+#[derive(thiserror::Error, Debug)]
+#[error("The writer can't hold more than {0} bytes")]
+pub(crate) struct WriterOverflow(pub(crate) usize);
 
 impl Write for Writer {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.0.write(buf)
+        if let Some(max_len) = self.max_len {
+            // reject the whole write, the bytes of the previous writes of the value are removed by
+            // `write_or_rollback`
+            if self.len() + buf.len() > max_len {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    WriterOverflow(max_len),
+                ));
+            }
+        }
+        self.inner.write(buf)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.0.flush()
+        self.inner.flush()
     }
 }
 
@@ -29,15 +62,70 @@ impl Default for Writer {
 }
 impl Writer {
     pub(crate) fn with_capacity(capacity: usize) -> Self {
-        Self(BytesMut::with_capacity(capacity).writer())
+        Self {
+            inner: BytesMut::with_capacity(capacity).writer(),
+            max_len: None,
+            context: None,
+        }
+    }
+
+    /// Create a bounded writer that returns an error instead of writing more than `max_len` bytes
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            inner: BytesMut::with_capacity(max_len).writer(),
+            max_len: Some(max_len),
+            context: None,
+        }
+    }
+
+    /// Maximum number of bytes that can be written, or `None` if the writer grows as needed
+    pub fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// Run `f` to serialize a value, and remove the bytes it wrote if it fails
+    ///
+    /// With a bounded writer, this guarantees that a value that doesn't fit is not partially written.
+    pub fn write_or_rollback<R, E>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let checkpoint = self.len();
+        let result = f(self);
+        if result.is_err() {
+            self.inner.get_mut().truncate(checkpoint);
+        }
+        result
+    }
+
+    /// Number of bytes written since the last split
+    pub fn len(&self) -> usize {
+        self.inner.get_ref().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Context of the connection for which the current value is serialized.
+    ///
+    /// `None` if the value is serialized once for several connections, or if the connection has no context.
+    pub fn context(&self) -> Option<&SerializationContext> {
+        self.context.as_deref()
+    }
+
+    /// Set the context used to serialize the next value, until it is split off
+    pub(crate) fn set_context(&mut self, context: Option<Arc<SerializationContext>>) {
+        self.context = context;
     }
 
     // TODO: how do reduce capacity over time?
     /// Split the current bytes written as a separate [`Bytes`].
     ///
-    /// Retains any additional capacity. O(1) operation.
+    /// Retains any additional capacity, and clears the [`SerializationContext`]. O(1) operation.
     pub(crate) fn split(&mut self) -> Bytes {
-        self.0.get_mut().split().freeze()
+        self.context = None;
+        self.inner.get_mut().split().freeze()
     }
 
     // TODO: normally there is no need to reset, because once all the messages that have been split
@@ -46,11 +134,50 @@ impl Writer {
     //  senders, think about what to do for that! Maybe do a clone there to drop the message?
     /// Reset the writer but keeps the underlying allocation
     pub(crate) fn reset(&mut self) {
-        self.0.get_mut().clear();
+        self.inner.get_mut().clear();
     }
 
     /// Consume the writer to get the RawData
     pub(crate) fn to_bytes(self) -> Bytes {
-        self.0.into_inner().into()
+        self.inner.into_inner().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{NetworkEndian, WriteBytesExt};
+
+    use crate::serialize::SerializationError;
+
+    use super::*;
+
+    #[test]
+    fn test_bounded_writer() {
+        let mut writer = Writer::with_max_len(4);
+        writer.write_u16::<NetworkEndian>(1).unwrap();
+        // the write doesn't fit: nothing is written
+        let err = writer.write_u32::<NetworkEndian>(1).unwrap_err();
+        assert_eq!(SerializationError::from(err).overflow(), Some(4));
+        assert_eq!(writer.len(), 2);
+        // a value written with several writes is removed as a whole
+        let err = writer
+            .write_or_rollback(|writer| {
+                writer.write_u8(1)?;
+                writer.write_u16::<NetworkEndian>(1)?;
+                Ok::<_, SerializationError>(())
+            })
+            .unwrap_err();
+        assert_eq!(err.overflow(), Some(4));
+        assert_eq!(writer.len(), 2);
+        writer.write_u16::<NetworkEndian>(1).unwrap();
+        assert_eq!(writer.split().len(), 4);
+        // the maximum length applies to the bytes written since the last split
+        writer.write_u32::<NetworkEndian>(1).unwrap();
+
+        // the default writer grows as needed
+        let mut writer = Writer::default();
+        writer.write_all(&[0; 100]).unwrap();
+        assert_eq!(writer.max_len(), None);
+        assert_eq!(writer.to_bytes().len(), 100);
     }
 }
@@ -29,8 +29,10 @@ mod systems {
     use super::*;
     use crate::prelude::server::ControlledBy;
     use crate::server::clients::ControlledEntities;
+    use crate::server::config::ServerConfig;
     use crate::server::connection::ConnectionManager;
     use crate::server::events::DisconnectEvent;
+    use crate::server::session::{despawn_client, SuspendedSessions};
     use tracing::{debug, trace};
 
     // TODO: remove entity in ControlledEntities lists after the component gets updated
@@ -74,8 +76,13 @@ mod systems {
         trigger: Trigger<OnRemove, ControlledBy>,
         query: Query<&ControlledBy>,
         mut client_query: Query<&mut ControlledEntities>,
-        sender: Res<ConnectionManager>,
+        // the ConnectionManager can be missing if the entity is despawned while it is taken out of the world:
+        // the entity is then removed by `reconcile_despawned_entities`
+        sender: Option<Res<ConnectionManager>>,
     ) {
+        let Some(sender) = sender else {
+            return;
+        };
         // OnRemove observers trigger before the actual removal
         let entity = trigger.entity();
         if let Ok(controlled_by) = query.get(entity) {
@@ -103,36 +110,28 @@ mod systems {
 
     /// When a client disconnects, we despawn all the entities it controlled if the lifetime
     /// is SesssionBased
+    ///
+    /// If session resumption is enabled, this is delayed until the session expires.
     pub(super) fn handle_client_disconnect(
         trigger: Trigger<DisconnectEvent>,
         mut commands: Commands,
+        config: Res<ServerConfig>,
+        mut sessions: ResMut<SuspendedSessions>,
         client_query: Query<&ControlledEntities>,
     ) {
+        if sessions.suspend(&config, trigger.event()).is_some() {
+            debug!(client_id = ?trigger.event().client_id, "Keeping the session of the disconnected client");
+            return;
+        }
         // TODO: should directly we use the client entity as the trigger entity?
         let client_entity = trigger.event().entity;
         let client_id = trigger.event().client_id;
-        // despawn all the controlled entities for the disconnected client
-        if let Ok(controlled_entities) = client_query.get(client_entity) {
-            debug!(
-                "Despawning all entities controlled by disconnected client {:?}",
-                client_id
-            );
-            for (entity, lifetime) in controlled_entities.iter() {
-                if lifetime == &Lifetime::SessionBased {
-                    trace!(
-                        "Despawning entity {entity:?} controlled by disconnected client {:?}",
-                        client_id
-                    );
-                    if let Some(command) = commands.get_entity(*entity) {
-                        command.despawn_recursive();
-                    }
-                }
-            }
-        }
-        // despawn the entity itself
-        if let Some(command) = commands.get_entity(client_entity) {
-            command.despawn_recursive();
-        };
+        despawn_client(
+            &mut commands,
+            client_id,
+            client_entity,
+            client_query.get(client_entity).ok(),
+        );
     }
 }
 
@@ -0,0 +1,224 @@
+//! Accept clients that use an adjacent version of the protocol
+//!
+//! To upgrade a fleet without disconnecting every player at the same time, the new servers can accept the
+//! clients of the previous version:
+//! - the netcode server accepts the previous protocol id with [`NetcodeConfig::compatible_protocol_ids`](crate::server::config::NetcodeConfig::compatible_protocol_ids).
+//!   Each connection keeps the protocol id that the client connected with, which is available in the
+//!   [`ConnectionInfo`](crate::connection::server::ConnectionInfo) and with [`Connection::protocol_id`](crate::server::connection::Connection::protocol_id)
+//! - a [`ProtocolShim`] registered for that protocol id in [`ServerConfig::protocol_shims`](crate::server::config::ServerConfig::protocol_shims)
+//!   converts the messages exchanged with those clients between the two wire formats.
+//!
+//! Clients that connect with a compatible protocol id for which no shim is registered exchange messages
+//! unchanged, which is enough if the messages didn't change between the two versions.
+//!
+//! The replication messages are not converted: the replicated components must keep the same wire format
+//! between two adjacent versions, or be registered with a version and a migration function
+//! (see [`ComponentRegistration::with_version`](crate::protocol::component::ComponentRegistration::with_version)).
+//! Individual messages can be versioned in the same way with
+//! [`MessageRegistration::with_version`](crate::protocol::message::MessageRegistration::with_version),
+//! which doesn't require a shim.
+//!
+//! The versions of those types are exchanged in a message when the client connects. The server waits for
+//! the versions of a client before reading its other messages, except for the clients that use a shim, since
+//! the shim might not convert that message: the values that they send before their versions are read with
+//! the current version.
+use std::fmt::Debug;
+
+use bytes::Bytes;
+
+/// Converts the messages exchanged with the clients that use another version of the protocol.
+///
+/// The bytes of a message are the ones produced by the [`MessageRegistry`](crate::prelude::MessageRegistry):
+/// the network id of the message followed by the serialized message. A shim can for example use
+/// a [`MessageRegistry`](crate::prelude::MessageRegistry) built from the previous protocol to decode them.
+pub trait ProtocolShim: Debug + Send + Sync + 'static {
+    /// Convert a message received from a client that uses the other protocol version to the current wire format.
+    ///
+    /// Returns None if the message has no equivalent in the current protocol, in which case it is dropped.
+    fn upgrade_message(&self, message: Bytes) -> Option<Bytes>;
+
+    /// Convert a message that is about to be sent to a client that uses the other protocol version.
+    ///
+    /// Returns None if the message has no equivalent in the other protocol, in which case it is not sent.
+    fn downgrade_message(&self, message: Bytes) -> Option<Bytes>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bevy::prelude::{EventReader, ResMut, Resource, Update};
+
+    use crate::prelude::client::{Authentication, ClientConfig, NetConfig};
+    use crate::prelude::server::{ConnectionManager, ServerConfig, ServerConnections};
+    use crate::prelude::{ClientId, MessageRegistry};
+    use crate::serialize::writer::Writer;
+    use crate::tests::protocol::{Channel1, StringMessage};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    /// Replaces every message with a fixed message
+    #[derive(Debug)]
+    struct ReplaceShim {
+        upgraded: Bytes,
+        downgraded: Bytes,
+    }
+
+    impl ProtocolShim for ReplaceShim {
+        fn upgrade_message(&self, _: Bytes) -> Option<Bytes> {
+            Some(self.upgraded.clone())
+        }
+
+        fn downgrade_message(&self, _: Bytes) -> Option<Bytes> {
+            Some(self.downgraded.clone())
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedStrings(Vec<String>);
+
+    fn serialize(registry: &MessageRegistry, message: &str) -> Bytes {
+        let mut writer = Writer::default();
+        registry
+            .serialize(&StringMessage(message.to_string()), &mut writer, None)
+            .unwrap();
+        writer.to_bytes()
+    }
+
+    /// A client that uses a compatible protocol id can connect, and its messages go through the shim
+    #[test]
+    fn test_compatible_protocol_shim() {
+        let mut stepper = BevyStepper::default();
+        stepper.stop();
+
+        let registry = stepper.server_app.world().resource::<MessageRegistry>();
+        let shim = ReplaceShim {
+            upgraded: serialize(registry, "upgraded"),
+            downgraded: serialize(registry, "downgraded"),
+        };
+        let mut server_config = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>();
+        for net_config in &mut server_config.net {
+            #[allow(irrefutable_let_patterns)]
+            let crate::connection::server::NetConfig::Netcode { config, .. } = net_config
+            else {
+                unreachable!()
+            };
+            config.compatible_protocol_ids = vec![1];
+        }
+        server_config.protocol_shims.insert(1, Arc::new(shim));
+        let mut client_config = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ClientConfig>();
+        let NetConfig::Netcode {
+            auth: Authentication::Manual { protocol_id, .. },
+            ..
+        } = &mut client_config.net
+        else {
+            unreachable!()
+        };
+        *protocol_id = 1;
+
+        stepper.start();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ServerConnections>()
+                .connection_info(client_id)
+                .expect("the client should be connected")
+                .protocol_id,
+            Some(1)
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .connection(client_id)
+                .unwrap()
+                .protocol_id(),
+            Some(1)
+        );
+
+        stepper.client_app.init_resource::<ReceivedStrings>();
+        stepper.server_app.init_resource::<ReceivedStrings>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut received: ResMut<ReceivedStrings>,
+             mut events: EventReader<
+                crate::client::events::MessageEvent<StringMessage>,
+            >| {
+                received
+                    .0
+                    .extend(events.read().map(|event| event.message().0.clone()));
+            },
+        );
+        stepper.server_app.add_systems(
+            Update,
+            |mut received: ResMut<ReceivedStrings>,
+             mut events: EventReader<
+                crate::server::events::MessageEvent<StringMessage>,
+            >| {
+                received
+                    .0
+                    .extend(events.read().map(|event| event.message().0.clone()));
+            },
+        );
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .send_message::<Channel1, StringMessage>(client_id, &mut StringMessage("a".to_string()))
+            .unwrap();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<crate::prelude::client::ConnectionManager>()
+            .send_message::<Channel1, StringMessage>(&mut StringMessage("b".to_string()))
+            .unwrap();
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<ReceivedStrings>().0,
+            vec!["downgraded".to_string()]
+        );
+        assert_eq!(
+            stepper.server_app.world().resource::<ReceivedStrings>().0,
+            vec!["upgraded".to_string()]
+        );
+    }
+
+    /// A client that uses a protocol id that the server doesn't accept cannot connect
+    #[test]
+    fn test_incompatible_protocol_id() {
+        let mut stepper = BevyStepper::default();
+        stepper.stop();
+        let mut client_config = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ClientConfig>();
+        let NetConfig::Netcode {
+            auth: Authentication::Manual { protocol_id, .. },
+            ..
+        } = &mut client_config.net
+        else {
+            unreachable!()
+        };
+        *protocol_id = 1;
+
+        stepper.start();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(ClientId::Netcode(TEST_CLIENT_ID))
+            .is_err());
+    }
+}
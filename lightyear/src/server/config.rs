@@ -1,14 +1,28 @@
 //! Defines server-specific configuration options
 use bevy::prelude::Resource;
+use bevy::utils::{Duration, HashMap};
 use governor::Quota;
 use nonzero_ext::nonzero;
 use std::sync::Arc;
 
-use crate::connection::netcode::{Key, PRIVATE_KEY_BYTES};
+use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::connection::netcode::{
+    DuplicateLoginPolicy, HandshakeRateLimit, Key, SuspicionThreshold, PRIVATE_KEY_BYTES,
+};
 use crate::connection::server::{
-    ConnectionRequestHandler, DefaultConnectionRequestHandler, NetConfig,
+    ConnectionRequestHandler, DefaultConnectionRequestHandler, DenyPredicate, NetConfig,
 };
+use crate::packet::header::AckBitfieldSize;
 use crate::prelude::ReplicationConfig;
+use crate::server::compatibility::ProtocolShim;
+use crate::server::error_events::ErrorEventConfig;
+use crate::server::idle::IdleKickConfig;
+use crate::server::pacing::RenderRatePacing;
+use crate::server::queue::ConnectionLimit;
+use crate::server::relevance::limit::EntityLimitConfig;
+use crate::server::replication::send::DefaultSyncTarget;
+use crate::server::session::SessionResumptionConfig;
+use crate::server::shard::ShardConfig;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
 
@@ -24,6 +38,47 @@ pub struct NetcodeConfig {
     pub private_key: Key,
     /// A closure that will be used to accept or reject incoming connections
     pub connection_request_handler: Arc<dyn ConnectionRequestHandler>,
+    /// Rejects connection requests based on the address of the client, before the handshake proceeds.
+    ///
+    /// Banned addresses are always rejected, see [`Server::ban_address`](crate::connection::netcode::Server::ban_address).
+    pub deny_predicate: Option<Arc<dyn DenyPredicate>>,
+    /// Limit on the rate of handshake packets that the server processes from a single IP address,
+    /// so that a single host cannot flood the handshake path.
+    ///
+    /// A [`HandshakeThrottledEvent`](crate::prelude::server::HandshakeThrottledEvent) is emitted when
+    /// the packets of an IP address start being dropped. The default is `None` (no limit).
+    pub handshake_rate_limit: Option<HandshakeRateLimit>,
+    /// Number of malformed packets that the server accepts from a single IP address before disconnecting
+    /// or banning it.
+    ///
+    /// A [`SuspiciousActivityEvent`](crate::prelude::server::SuspiciousActivityEvent) is emitted when
+    /// an IP address reaches the threshold. The default is `None` (malformed packets are ignored).
+    pub suspicion_threshold: Option<SuspicionThreshold>,
+    /// Protocol ids that the server accepts in addition to `protocol_id`, so that the clients of an adjacent
+    /// version can connect during a rolling upgrade.
+    ///
+    /// See [`compatibility`](crate::server::compatibility) for how to convert the messages of those clients.
+    pub compatible_protocol_ids: Vec<u64>,
+    /// What the server does when a client connects with the id of a client that is already connected
+    /// from another address.
+    ///
+    /// A [`DuplicateLoginEvent`](crate::prelude::server::DuplicateLoginEvent) is emitted when the policy
+    /// is applied. The default is [`DuplicateLoginPolicy::RejectNew`].
+    pub duplicate_login_policy: DuplicateLoginPolicy,
+    /// If true, the server denies the clients that don't send the same [protocol hash](crate::protocol::hash)
+    /// as its own with [`DeniedReason::ProtocolMismatch`](crate::connection::server::DeniedReason::ProtocolMismatch).
+    ///
+    /// The clients must enable [`send_protocol_hash`](crate::prelude::client::NetcodeConfig::send_protocol_hash).
+    /// The default is false.
+    pub check_protocol_hash: bool,
+    /// If true, the server sends the connection data to the clients in the challenge token instead of
+    /// storing it until they answer the challenge
+    /// (see [`ServerConfig::stateless_handshake`](crate::connection::netcode::ServerConfig::stateless_handshake)).
+    ///
+    /// Only the lightyear clients can connect to the server. The default is false.
+    pub stateless_handshake: bool,
+    /// Hash of the protocol of the server, computed from the registered types when the server starts
+    pub(crate) protocol_hash: Option<u64>,
 }
 
 impl Default for NetcodeConfig {
@@ -35,6 +90,14 @@ impl Default for NetcodeConfig {
             protocol_id: 0,
             private_key: [0; PRIVATE_KEY_BYTES],
             connection_request_handler: Arc::new(DefaultConnectionRequestHandler),
+            deny_predicate: None,
+            handshake_rate_limit: None,
+            suspicion_threshold: None,
+            compatible_protocol_ids: vec![],
+            duplicate_login_policy: DuplicateLoginPolicy::default(),
+            check_protocol_hash: false,
+            stateless_handshake: false,
+            protocol_hash: None,
         }
     }
 }
@@ -53,6 +116,41 @@ impl NetcodeConfig {
         self.client_timeout_secs = client_timeout_secs;
         self
     }
+
+    pub fn with_handshake_rate_limit(mut self, limit: HandshakeRateLimit) -> Self {
+        self.handshake_rate_limit = Some(limit);
+        self
+    }
+
+    pub fn with_suspicion_threshold(mut self, threshold: SuspicionThreshold) -> Self {
+        self.suspicion_threshold = Some(threshold);
+        self
+    }
+
+    pub fn with_deny_predicate(mut self, predicate: Arc<dyn DenyPredicate>) -> Self {
+        self.deny_predicate = Some(predicate);
+        self
+    }
+
+    pub fn with_compatible_protocol_ids(mut self, protocol_ids: Vec<u64>) -> Self {
+        self.compatible_protocol_ids = protocol_ids;
+        self
+    }
+
+    pub fn with_duplicate_login_policy(mut self, policy: DuplicateLoginPolicy) -> Self {
+        self.duplicate_login_policy = policy;
+        self
+    }
+
+    pub fn with_check_protocol_hash(mut self, check_protocol_hash: bool) -> Self {
+        self.check_protocol_hash = check_protocol_hash;
+        self
+    }
+
+    pub fn with_stateless_handshake(mut self, stateless_handshake: bool) -> Self {
+        self.stateless_handshake = stateless_handshake;
+        self
+    }
 }
 
 /// Configuration related to sending packets
@@ -67,6 +165,27 @@ pub struct PacketConfig {
     pub per_client_send_bandwidth_cap: Quota,
     /// If false, there is no bandwidth cap and all messages are sent as soon as possible
     pub bandwidth_cap_enabled: bool,
+    /// If set, maximum number of bytes of messages that can be sent to each client on each tick.
+    ///
+    /// When the budget is reached, the messages of the channels with the highest
+    /// [priority](crate::prelude::ChannelSettings::priority) are sent first; the unreliable messages that
+    /// don't fit are dropped and the reliable ones are sent again later.
+    pub per_client_send_budget_per_tick: Option<u32>,
+    /// Number of packets that are acknowledged in each packet header.
+    ///
+    /// A wider window avoids spurious retransmits on high-tick-rate or high-loss connections,
+    /// at the cost of a bigger packet header.
+    pub ack_bitfield_size: AckBitfieldSize,
+    /// Maximum number of bytes in a packet. Messages that don't fit in a packet are split into fragments
+    /// that are reassembled by the remote peer.
+    ///
+    /// The value is clamped between [`MIN_PACKET_SIZE`](crate::packet::mtu::MIN_PACKET_SIZE) and [`MAX_PACKET_SIZE`].
+    pub max_packet_size: usize,
+    /// If true, the maximum packet size of each connection is reduced when large packets seem to be dropped
+    /// by the network (path MTU discovery), and is increased again over time.
+    ///
+    /// The default is false.
+    pub mtu_discovery: bool,
 }
 
 impl Default for PacketConfig {
@@ -76,6 +195,10 @@ impl Default for PacketConfig {
             // 56 KB/s bandwidth cap
             per_client_send_bandwidth_cap: Quota::per_second(nonzero!(56000u32)),
             bandwidth_cap_enabled: false,
+            per_client_send_budget_per_tick: None,
+            ack_bitfield_size: AckBitfieldSize::default(),
+            max_packet_size: MAX_PACKET_SIZE,
+            mtu_discovery: false,
         }
     }
 }
@@ -92,12 +215,63 @@ impl PacketConfig {
         self
     }
 
+    pub fn with_send_budget_per_tick(mut self, bytes: u32) -> Self {
+        self.per_client_send_budget_per_tick = Some(bytes);
+        self
+    }
+
+    pub fn with_ack_bitfield_size(mut self, ack_bitfield_size: AckBitfieldSize) -> Self {
+        self.ack_bitfield_size = ack_bitfield_size;
+        self
+    }
+
+    pub fn with_max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn with_mtu_discovery(mut self, mtu_discovery: bool) -> Self {
+        self.mtu_discovery = mtu_discovery;
+        self
+    }
+
     pub fn enable_bandwidth_cap(mut self) -> Self {
         self.bandwidth_cap_enabled = true;
         self
     }
 }
 
+/// Configuration of the authentication of new clients.
+///
+/// When it is enabled, a client that completes the handshake is not added to the server right away: an
+/// [`AuthRequestEvent`](crate::prelude::server::AuthRequestEvent) is emitted, and the client waits (over as many
+/// frames as needed) until it is accepted with
+/// [`ServerConnections::accept_connection`](crate::connection::server::ServerConnections::accept_connection)
+/// or rejected with [`ServerConnections::reject_connection`](crate::connection::server::ServerConnections::reject_connection).
+///
+/// The connection is kept alive while the client waits, and the packets that it sends are dropped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuthConfig {
+    /// Maximum time that a client can wait for authentication before being rejected with
+    /// [`DeniedReason::AuthTimedOut`](crate::connection::server::DeniedReason::AuthTimedOut)
+    pub timeout: Duration,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
 /// Configuration for the server plugin.
 ///
 /// The [`ServerConfig`] is a bevy Resource. You can access it in your systems using `Res<ServerConfig>`.
@@ -113,17 +287,66 @@ pub struct ServerConfig {
     pub packet: PacketConfig,
     pub replication: ReplicationConfig,
     pub ping: PingConfig,
+    /// Which clients predict or interpolate the replicated entities that don't specify a
+    /// [`SyncTarget`](crate::prelude::server::SyncTarget)
+    pub default_sync_target: DefaultSyncTarget,
+    /// Converts the messages of the clients that connected with one of the
+    /// [compatible protocol ids](NetcodeConfig::compatible_protocol_ids), indexed by protocol id
+    pub protocol_shims: HashMap<u64, Arc<dyn ProtocolShim>>,
+    /// If set, new clients must be accepted explicitly before being added to the server. The default is `None`
+    /// (clients are accepted as soon as they connect)
+    pub auth: Option<AuthConfig>,
+    /// If set, the clients that disconnect can reconnect and [resume their session](crate::server::session)
+    /// before the timeout. The default is `None` (the state of a client is cleaned up as soon as it disconnects)
+    pub session_resumption: Option<SessionResumptionConfig>,
+    /// If set, limits the number of clients that can be connected at the same time. The default is `None`
+    /// (no limit other than the one of the transport)
+    pub max_connections: Option<ConnectionLimit>,
+    /// If set, limits the number of entities replicated to each client at the same time, see
+    /// [`crate::server::relevance::limit`]. The default is `None` (all the relevant entities are replicated)
+    pub entity_limit: Option<EntityLimitConfig>,
+    /// If set, the clients that don't send any message or input for some time are kicked.
+    /// The default is `None` (idle clients stay connected)
+    pub idle_kick: Option<IdleKickConfig>,
+    /// If set, the component updates are sent to each client at most at the render rate that it reported, see
+    /// [`crate::server::pacing`]. The default is `None` (the updates are sent at the replication send interval)
+    pub render_rate_pacing: Option<RenderRatePacing>,
+    /// Rate limit of the [`ServerErrorEvent`](crate::prelude::server::ServerErrorEvent)s
+    pub error_events: ErrorEventConfig,
+    /// If set, the server doesn't catch up on the ticks it missed when a frame comes more than this
+    /// after the previous one (for example after being stopped at a breakpoint), see [`crate::server::pause`].
+    /// The default is `None` (the missed ticks are run, up to the max delta of the virtual time)
+    pub pause_recovery: Option<Duration>,
+    /// If set, the packets of the clients are prepared in parallel, partitioned between
+    /// [worker shards](crate::server::shard). The default is `None` (the packets are prepared on the main thread)
+    pub worker_shards: Option<ShardConfig>,
+    /// If true, the server records the entities and component values that were replicated to each client, see
+    /// [`crate::server::world_view`]. The default is false (nothing is recorded)
+    pub client_world_view: bool,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::client::networking::NetworkingState;
-    use crate::connection::server::DeniedReason;
-    use crate::prelude::ClientId;
+    use crate::connection::server::{DeniedReason, ServerConnections, TransportKind};
+    use crate::prelude::server::{AuthRequestEvent, ConnectionManager};
+    use crate::prelude::{client, ClientId};
+    use crate::transport::middleware::compression::CompressionConfig;
 
+    use crate::client::networking::ClientCommands;
+    use crate::connection::client::DisconnectReason;
+    use crate::connection::netcode::DuplicateLoginPolicy;
+    use crate::connection::netcode::{ConnectToken, USER_DATA_BYTES};
+    use crate::prelude::client::DisconnectEvent;
+    use crate::prelude::client::{InterpolationConfig, PredictionConfig, SyncConfig};
+    use crate::prelude::server::{IoConfig, ServerCommands, ServerTransport};
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::multi_stepper::MultiBevyStepper;
     use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
-    use bevy::prelude::State;
+    use crate::transport::LOCAL_SOCKET;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::{default, Commands, EventReader, ResMut, Resource, State, Update};
     use std::fmt::Debug;
     use std::sync::Arc;
 
@@ -171,4 +394,347 @@ mod tests {
             &NetworkingState::Disconnected
         );
     }
+
+    #[derive(Debug, Clone)]
+    struct BanRequestHandler;
+
+    impl ConnectionRequestHandler for BanRequestHandler {
+        fn handle_request(&self, client_id: ClientId) -> Option<DeniedReason> {
+            Some(DeniedReason::BannedUntil(10))
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct DeniedReasons(Vec<DeniedReason>);
+
+    #[test]
+    fn test_denied_reason_sent_to_client() {
+        let mut stepper = BevyStepper::default();
+        stepper.stop();
+        for netconfig in &mut stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .net
+        {
+            netconfig.set_connection_request_handler(Arc::new(BanRequestHandler));
+        }
+        stepper.client_app.init_resource::<DeniedReasons>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<DisconnectEvent>, mut reasons: ResMut<DeniedReasons>| {
+                for event in events.read() {
+                    if let Some(DisconnectReason::Denied(reason)) = &event.reason {
+                        reasons.0.push(reason.clone());
+                    }
+                }
+            },
+        );
+
+        stepper.start();
+
+        assert_eq!(
+            stepper.client_app.world().resource::<DeniedReasons>().0,
+            vec![DeniedReason::BannedUntil(10)]
+        );
+    }
+
+    #[test]
+    fn test_connection_info() {
+        let mut stepper = BevyStepper::default();
+
+        let server_connections = stepper.server_app.world().resource::<ServerConnections>();
+        let info = server_connections
+            .connection_info(ClientId::Netcode(TEST_CLIENT_ID))
+            .expect("the client should be connected");
+        assert_eq!(info.transport, TransportKind::Channels);
+        assert_eq!(info.protocol_id, Some(0));
+        assert_eq!(info.compression, CompressionConfig::None);
+        assert!(info.encrypted);
+        assert!(server_connections
+            .connection_info(ClientId::Netcode(TEST_CLIENT_ID + 1))
+            .is_none());
+
+        // the info is not available anymore once the client disconnects
+        stepper.stop();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ServerConnections>()
+            .connection_info(ClientId::Netcode(TEST_CLIENT_ID))
+            .is_none());
+    }
+
+    #[derive(Resource, Default)]
+    struct AuthRequests(Vec<ClientId>);
+
+    fn enable_auth(stepper: &mut BevyStepper, auth: AuthConfig) {
+        stepper.stop();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .auth = Some(auth);
+        stepper.server_app.init_resource::<AuthRequests>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<AuthRequestEvent>, mut requests: ResMut<AuthRequests>| {
+                requests
+                    .0
+                    .extend(events.read().map(|event| event.client_id));
+            },
+        );
+    }
+
+    #[derive(Resource, Default)]
+    struct AuthUserData(Vec<Option<[u8; USER_DATA_BYTES]>>);
+
+    /// The user data of the connect token is included in the authentication request
+    #[test]
+    fn test_auth_request_user_data() {
+        let mut stepper = BevyStepper::default();
+        enable_auth(&mut stepper, AuthConfig::default());
+        stepper.server_app.init_resource::<AuthUserData>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<AuthRequestEvent>, mut user_data: ResMut<AuthUserData>| {
+                user_data
+                    .0
+                    .extend(events.read().map(|event| event.user_data));
+            },
+        );
+        let server_config = stepper.server_app.world().resource::<ServerConfig>();
+        #[allow(irrefutable_let_patterns)]
+        let NetConfig::Netcode { config, .. } = &server_config.net[0] else {
+            unreachable!()
+        };
+        let token = ConnectToken::build(LOCAL_SOCKET, 0, TEST_CLIENT_ID, config.private_key)
+            .user_data([7; USER_DATA_BYTES])
+            .generate()
+            .unwrap();
+        let mut client_config = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ClientConfig>();
+        let client::NetConfig::Netcode { auth, .. } = &mut client_config.net else {
+            unreachable!()
+        };
+        *auth = client::Authentication::Token(token);
+        stepper.start();
+
+        assert_eq!(
+            stepper.server_app.world().resource::<AuthUserData>().0,
+            vec![Some([7; USER_DATA_BYTES])]
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ServerConnections>()
+                .connection_info(ClientId::Netcode(TEST_CLIENT_ID))
+                .and_then(|info| info.user_data),
+            Some([7; USER_DATA_BYTES])
+        );
+    }
+
+    /// A client can wait for authentication over many frames before being accepted
+    #[test]
+    fn test_accept_pending_connection() {
+        let mut stepper = BevyStepper::default();
+        enable_auth(&mut stepper, AuthConfig::default());
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        stepper.start();
+
+        assert_eq!(
+            stepper.server_app.world().resource::<AuthRequests>().0,
+            vec![client_id]
+        );
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ServerConnections>()
+            .is_pending_auth(client_id));
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .is_err());
+        // the connection did not expire while waiting
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Connected
+        );
+
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConnections>()
+            .accept_connection(client_id)
+            .unwrap();
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .is_ok());
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+    }
+
+    /// A client that is not accepted before the timeout is rejected
+    #[test]
+    fn test_pending_connection_timeout() {
+        let mut stepper = BevyStepper::default();
+        enable_auth(
+            &mut stepper,
+            AuthConfig::default().with_timeout(Duration::from_millis(200)),
+        );
+        stepper.client_app.init_resource::<DeniedReasons>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<DisconnectEvent>, mut reasons: ResMut<DeniedReasons>| {
+                for event in events.read() {
+                    if let Some(DisconnectReason::Denied(reason)) = &event.reason {
+                        reasons.0.push(reason.clone());
+                    }
+                }
+            },
+        );
+        stepper.start();
+
+        assert_eq!(
+            stepper.client_app.world().resource::<DeniedReasons>().0,
+            vec![DeniedReason::AuthTimedOut]
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+        assert!(!stepper
+            .server_app
+            .world()
+            .resource::<ServerConnections>()
+            .is_pending_auth(ClientId::Netcode(TEST_CLIENT_ID)));
+    }
+
+    /// With [`DuplicateLoginPolicy::KickOld`], the client that logs in again replaces the existing client,
+    /// including in the [`ConnectionManager`]
+    #[test]
+    fn test_duplicate_login_kick_old() {
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = MultiBevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            frame_duration,
+        );
+        // both clients connect to the same netcode server with the same id
+        let (server_transport, client_transports) = ServerTransport::loopback(2);
+        let mut server_config = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>();
+        #[allow(irrefutable_let_patterns)]
+        let NetConfig::Netcode { config, .. } = &server_config.net[0] else {
+            unreachable!()
+        };
+        let config = config
+            .clone()
+            .with_duplicate_login_policy(DuplicateLoginPolicy::KickOld);
+        let private_key = config.private_key;
+        server_config.net = vec![NetConfig::Netcode {
+            config,
+            io: IoConfig::from_transport(server_transport),
+        }];
+        for (client_app, transport) in [&mut stepper.client_app_1, &mut stepper.client_app_2]
+            .into_iter()
+            .zip(client_transports)
+        {
+            client_app
+                .world_mut()
+                .resource_mut::<client::ClientConfig>()
+                .net = client::NetConfig::Netcode {
+                auth: client::Authentication::Manual {
+                    server_addr: LOCAL_SOCKET,
+                    protocol_id: 0,
+                    private_key,
+                    client_id: TEST_CLIENT_ID,
+                },
+                config: default(),
+                io: client::IoConfig::from_transport(transport),
+            };
+        }
+        for app in [
+            &mut stepper.server_app,
+            &mut stepper.client_app_1,
+            &mut stepper.client_app_2,
+        ] {
+            app.finish();
+            app.cleanup();
+        }
+        stepper
+            .server_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.start_server());
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        stepper
+            .client_app_1
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.connect_client());
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app_1
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+        // the same client logs in again from another address
+        stepper
+            .client_app_2
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.connect_client());
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+
+        assert_eq!(
+            stepper
+                .client_app_1
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+        assert!(stepper
+            .client_app_2
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .is_ok());
+    }
 }
@@ -1,27 +1,40 @@
 //! Specify how a Server sends/receives messages with a Client
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
 use bevy::ecs::component::Tick as BevyTick;
 use bevy::ecs::entity::{EntityHash, MapEntities};
 use bevy::prelude::{Component, Entity, Resource, World};
 use bevy::ptr::Ptr;
-use bevy::utils::{Duration, HashMap};
+use bevy::utils::{Duration, HashMap, HashSet};
 use bytes::Bytes;
-use hashbrown::hash_map::Entry;
-use tracing::{debug, info, info_span, trace, trace_span};
+use crossbeam_channel::Receiver;
+use governor::Quota;
+use tracing::{debug, error, info, info_span, trace, trace_span};
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
 
 use crate::channel::builder::{
-    EntityActionsChannel, EntityUpdatesChannel, PingChannel, PongChannel,
+    ControlChannel, EntityActionsChannel, EntityUpdatesChannel, PingChannel, PongChannel,
 };
 
-use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stats::retransmission::RetransmissionStats;
+use crate::client::idle::ServerIdleWarning;
+use crate::client::kick::ServerKick;
 use crate::client::message::ClientMessage;
+use crate::client::queue::ServerQueueUpdate;
+use crate::client::redirect::ServerRedirect;
+use crate::client::restart::ServerRestart;
 use crate::connection::id::ClientId;
-use crate::connection::netcode::MAX_PACKET_SIZE;
+use crate::connection::netcode::{ConnectToken, MAX_PACKET_SIZE};
+use crate::packet::error::PacketError;
+use crate::packet::message::MessageId;
 use crate::packet::message_manager::MessageManager;
 use crate::packet::packet_builder::{Payload, RecvPayload};
-use crate::prelude::server::{DisconnectEvent, RoomId, RoomManager};
+use crate::prelude::server::{ControlledBy, DisconnectEvent, RoomId, RoomManager};
 use crate::prelude::{
     Channel, ChannelKind, Message, PreSpawnedPlayerObject, ReplicationConfig, ReplicationGroup,
     ShouldBePredicted,
@@ -30,19 +43,31 @@ use crate::protocol::channel::ChannelRegistry;
 use crate::protocol::component::{
     ComponentError, ComponentKind, ComponentNetId, ComponentRegistry,
 };
-use crate::protocol::message::{MessageError, MessageRegistry, MessageType};
+use crate::protocol::message::{MessageError, MessageKind, MessageRegistry, MessageType, TraceId};
 use crate::protocol::registry::NetId;
+use crate::protocol::version::{RemoteVersions, SchemaVersions};
+use crate::serialize::context::SerializationContext;
 use crate::serialize::reader::Reader;
 use crate::serialize::writer::Writer;
 use crate::serialize::{SerializationError, ToBytes};
+use crate::server::compatibility::ProtocolShim;
 use crate::server::config::PacketConfig;
 use crate::server::error::ServerError;
-use crate::server::events::{ConnectEvent, ServerEvents};
-use crate::server::relevance::error::RelevanceError;
+use crate::server::events::{
+    ConnectEvent, MessageDeliveredEvent, MessageDroppedEvent, ServerEvents,
+};
+use crate::server::idle::IdleKickConfig;
+use crate::server::pacing::{RenderRatePacing, UpdatePacing};
+use crate::server::shard::ShardedConnections;
+use crate::server::world_view::ClientWorldView;
+use crate::shared::alloc_audit::{allow_allocations, AllocationException};
+#[cfg(feature = "alloc_audit")]
+use crate::shared::alloc_audit::{count_allocations, AllocationCounter, AllocationCounts};
 use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::message::MessageSend;
 use crate::shared::ping::manager::{PingConfig, PingManager};
 use crate::shared::ping::message::{Ping, Pong};
+use crate::shared::replication::baseline::{BaselineReport, BaselineState};
 use crate::shared::replication::components::ReplicationGroupId;
 use crate::shared::replication::delta::DeltaManager;
 use crate::shared::replication::network_target::NetworkTarget;
@@ -50,16 +75,23 @@ use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
 use crate::shared::replication::{EntityActionsMessage, EntityUpdatesMessage, ReplicationPeer};
 use crate::shared::replication::{ReplicationReceive, ReplicationSend};
+use crate::shared::rpc::{
+    Request, RequestId, RequestTracker, ResponseHandle, RpcRequest, RpcResponse,
+};
 use crate::shared::sets::ServerMarker;
 use crate::shared::tick_manager::Tick;
 use crate::shared::tick_manager::TickManager;
-use crate::shared::time_manager::TimeManager;
+use crate::shared::time_manager::{TimeManager, WrappedTime};
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
 
 #[derive(Resource)]
 pub struct ConnectionManager {
-    pub(crate) connections: HashMap<ClientId, Connection>,
+    /// Connections of the clients, owned by the [worker shards](crate::server::shard)
+    pub(crate) connections: ShardedConnections,
+    /// Connections of the clients that are waiting for a slot to open, in order of arrival
+    /// (see [`ConnectionLimit`](crate::prelude::server::ConnectionLimit))
+    pub(crate) queue: VecDeque<Connection>,
     pub(crate) message_registry: MessageRegistry,
     channel_registry: ChannelRegistry,
     pub(crate) events: ServerEvents,
@@ -68,12 +100,31 @@ pub struct ConnectionManager {
     // list of clients that connected since the last time we sent replication messages
     // (we want to keep track of them because we need to replicate the entire world state to them)
     pub(crate) new_clients: Vec<ClientId>,
+    // list of clients for which we learned since the last time we sent replication messages that
+    // they don't have the static baseline (we need to replicate the baseline entities to them)
+    pub(crate) new_baseline_clients: Vec<ClientId>,
     pub(crate) writer: Writer,
+    /// Last messages sent to each room on the channels with [`ChannelSettings::replay_on_join`](crate::prelude::ChannelSettings::replay_on_join), in order
+    room_history: HashMap<RoomId, HashMap<ChannelKind, VecDeque<RoomHistoryEntry>>>,
+    /// Messages of the clients that disconnected before acknowledging them
+    dropped_messages: Vec<MessageDroppedEvent>,
 
     // CONFIG
     replication_config: ReplicationConfig,
     packet_config: PacketConfig,
     ping_config: PingConfig,
+    protocol_shims: HashMap<u64, Arc<dyn ProtocolShim>>,
+    /// If true, the [`ClientWorldView`] of each client is recorded
+    pub(crate) client_world_view: bool,
+    /// Serialized [`SchemaVersions`] message, sent to each client as the first message of the [`ControlChannel`]
+    schema_versions: Bytes,
+    /// True if the protocol has types registered with a version, in which case the messages of a client are
+    /// not read until its versions are received
+    await_remote_versions: bool,
+    /// Requests sent to the clients that are waiting for a response
+    pub(crate) requests: RequestTracker<ClientId>,
+    #[cfg(feature = "alloc_audit")]
+    pub(crate) allocations: AllocationCounts,
 }
 
 // This is useful in cases where we need to temporarily store a fake ConnectionManager
@@ -85,6 +136,7 @@ impl Default for ConnectionManager {
             ReplicationConfig::default(),
             PacketConfig::default(),
             PingConfig::default(),
+            HashMap::default(),
         )
     }
 }
@@ -96,31 +148,63 @@ impl ConnectionManager {
         replication_config: ReplicationConfig,
         packet_config: PacketConfig,
         ping_config: PingConfig,
+        protocol_shims: HashMap<u64, Arc<dyn ProtocolShim>>,
     ) -> Self {
         Self {
-            connections: HashMap::default(),
+            connections: ShardedConnections::default(),
+            queue: VecDeque::default(),
             message_registry,
             channel_registry,
             events: ServerEvents::new(),
             delta_manager: DeltaManager::default(),
             new_clients: vec![],
+            new_baseline_clients: vec![],
             writer: Writer::with_capacity(MAX_PACKET_SIZE),
+            room_history: HashMap::default(),
+            dropped_messages: vec![],
             replication_config,
             packet_config,
             ping_config,
+            protocol_shims,
+            client_world_view: false,
+            schema_versions: Bytes::new(),
+            await_remote_versions: false,
+            requests: RequestTracker::default(),
+            #[cfg(feature = "alloc_audit")]
+            allocations: AllocationCounts::default(),
         }
     }
 
+    /// Number of heap allocations performed by the networking operations during the last frame
+    #[cfg(feature = "alloc_audit")]
+    pub fn allocations(&self) -> &AllocationCounts {
+        &self.allocations
+    }
+
     /// Return the [`Entity`] associated with the given [`ClientId`]
     pub fn client_entity(&self, client_id: ClientId) -> Result<Entity, ServerError> {
         self.connection(client_id).map(|c| c.entity)
     }
 
+    /// Use another entity as the client entity of a connected client
+    pub(crate) fn set_client_entity(&mut self, client_id: ClientId, entity: Entity) {
+        if let Some(connection) = self.connections.get_mut(&client_id) {
+            connection.entity = entity;
+        }
+    }
+
     /// Return the list of connected [`ClientId`]s
     pub fn connected_clients(&self) -> impl Iterator<Item = ClientId> + '_ {
         self.connections.keys().copied()
     }
 
+    /// Iterate through the [`Connection`]s of all the connected clients
+    pub fn connections(&self) -> impl Iterator<Item = (ClientId, &Connection)> {
+        self.connections
+            .iter()
+            .map(|(client_id, connection)| (*client_id, connection))
+    }
+
     // TODO: we need `&mut self` because MapEntities requires `&mut EntityMapper` even though it's not needed here
     /// Convert entities in the message to be compatible with the remote world of the provided client
     pub fn map_entities_to_remote<M: Message + MapEntities>(
@@ -146,21 +230,204 @@ impl ConnectionManager {
         self.erased_send_message_to_target(message, ChannelKind::of::<C>(), target)
     }
 
+    /// Queues up a message to be sent to all clients matching the specific [`NetworkTarget`], with a [`TraceId`]
+    /// that will be available in the [`MessageEvent`](crate::shared::events::components::MessageEvent) on the clients.
+    ///
+    /// The message must have been registered with [`add_trace_id`](crate::protocol::message::MessageRegistration::add_trace_id),
+    /// otherwise the [`TraceId`] is ignored.
+    pub fn send_message_to_target_with_trace_id<C: Channel, M: Message>(
+        &mut self,
+        message: &mut M,
+        target: NetworkTarget,
+        trace_id: TraceId,
+    ) -> Result<(), ServerError> {
+        self.erased_send_message_to_target_with_trace_id(
+            message,
+            ChannelKind::of::<C>(),
+            target,
+            Some(trace_id),
+        )
+    }
+
+    /// Queues up a message to be sent to all clients matching the specific [`NetworkTarget`], on the channel
+    /// identified by `channel`.
+    ///
+    /// This is used to send messages on the channels registered at runtime with
+    /// [`ChannelRegistry::add_dynamic_channel`](crate::prelude::ChannelRegistry::add_dynamic_channel), which
+    /// don't have a [`Channel`] type.
+    pub fn send_message_to_target_on_channel<M: Message>(
+        &mut self,
+        message: &mut M,
+        channel: ChannelKind,
+        target: NetworkTarget,
+    ) -> Result<(), ServerError> {
+        self.erased_send_message_to_target(message, channel, target)
+    }
+
+    /// Queues up a message to be sent to all clients, except the given ones.
+    ///
+    /// This is useful to notify the other clients of an action without echoing it back to the client that
+    /// triggered it.
+    pub fn send_message_to_all_except<C: Channel, M: Message>(
+        &mut self,
+        message: &mut M,
+        except: impl IntoIterator<Item = ClientId>,
+    ) -> Result<(), ServerError> {
+        let target = NetworkTarget::AllExcept(except.into_iter().collect());
+        self.send_message_to_target::<C, M>(message, target)
+    }
+
     /// Send a message to all clients in a room
+    ///
+    /// If the channel has [`ChannelSettings::replay_on_join`](crate::prelude::ChannelSettings::replay_on_join), the message is kept in the room's history
+    /// and will be sent to the clients that join the room later.
     pub fn send_message_to_room<C: Channel, M: Message>(
         &mut self,
         message: &mut M,
         room_id: RoomId,
         room_manager: &RoomManager,
     ) -> Result<(), ServerError> {
-        let room = room_manager
-            .get_room(room_id)
-            .ok_or::<ServerError>(RelevanceError::RoomIdNotFound(room_id).into())?;
-        let target = NetworkTarget::Only(room.clients.iter().copied().collect());
-        self.send_message_to_target::<C, M>(message, target)
+        self.send_room_message::<C, M>(message, room_id, NetworkTarget::None, room_manager)
+    }
+
+    /// Send a message to all clients in a room, except the clients that control an entity.
+    ///
+    /// This is useful to notify the other clients in the room of an action triggered by a client,
+    /// by using the [`ControlledBy`] component of the client's entity.
+    pub fn send_message_to_room_except_owner<C: Channel, M: Message>(
+        &mut self,
+        message: &mut M,
+        room_id: RoomId,
+        controlled_by: &ControlledBy,
+        room_manager: &RoomManager,
+    ) -> Result<(), ServerError> {
+        self.send_room_message::<C, M>(message, room_id, controlled_by.target.clone(), room_manager)
+    }
+
+    /// Send a message to all clients in a room, except the given ones.
+    ///
+    /// The history of the room is handled like in [`send_message_to_room`](Self::send_message_to_room), but the
+    /// excluded clients don't receive the message either if they join the room later.
+    pub fn send_message_to_room_except<C: Channel, M: Message>(
+        &mut self,
+        message: &mut M,
+        room_id: RoomId,
+        except: impl IntoIterator<Item = ClientId>,
+        room_manager: &RoomManager,
+    ) -> Result<(), ServerError> {
+        let except = NetworkTarget::Only(except.into_iter().collect());
+        self.send_room_message::<C, M>(message, room_id, except, room_manager)
+    }
+
+    /// Send a message to the clients of the room that are not targeted by `except`
+    fn send_room_message<C: Channel, M: Message>(
+        &mut self,
+        message: &mut M,
+        room_id: RoomId,
+        except: NetworkTarget,
+        room_manager: &RoomManager,
+    ) -> Result<(), ServerError> {
+        let room = room_manager.try_room(room_id)?;
+        let clients: Vec<ClientId> = room
+            .clients
+            .iter()
+            .filter(|client_id| !except.targets(client_id))
+            .copied()
+            .collect();
+        let channel = ChannelKind::of::<C>();
+        let replay_on_join = self
+            .channel_registry
+            .get_builder_from_kind(&channel)
+            .and_then(|builder| builder.settings.replay_on_join);
+        // messages that map entities are serialized differently for each client, so they can't be replayed
+        let Some(capacity) =
+            replay_on_join.filter(|_| !self.message_registry.is_map_entities::<M>())
+        else {
+            return self.send_message_to_target::<C, M>(message, NetworkTarget::Only(clients));
+        };
+        self.message_registry
+            .serialize(message, &mut self.writer, None)?;
+        let message_bytes = self.writer.split();
+        let history = self
+            .room_history
+            .entry(room_id)
+            .or_default()
+            .entry(channel)
+            .or_default();
+        if capacity > 0 {
+            if history.len() == capacity {
+                history.pop_front();
+            }
+            history.push_back(RoomHistoryEntry {
+                message: message_bytes.clone(),
+                except,
+            });
+        }
+        // the clients that joined the room since the last send will receive the message with the history
+        let target = NetworkTarget::Only(
+            clients
+                .into_iter()
+                .filter(|client_id| !room_manager.is_joining(*client_id, room_id))
+                .collect(),
+        );
+        self.buffer_message_bytes(message_bytes, channel, target)
+    }
+
+    /// Forget the messages kept for the clients that join the room (for example when a new match starts)
+    pub fn clear_room_history(&mut self, room_id: RoomId) {
+        self.room_history.remove(&room_id);
+    }
+
+    /// Send the history of the room to a client that joined it
+    pub(crate) fn replay_room_history(
+        &mut self,
+        client_id: ClientId,
+        room_id: RoomId,
+    ) -> Result<(), ServerError> {
+        let Some(history) = self.room_history.get(&room_id) else {
+            return Ok(());
+        };
+        let Some(connection) = self.connections.get_mut(&client_id) else {
+            return Ok(());
+        };
+        for (channel, entries) in history.iter() {
+            for entry in entries
+                .iter()
+                .filter(|entry| !entry.except.targets(&client_id))
+            {
+                if connection.is_local_client() {
+                    connection
+                        .local_messages_to_send
+                        .push(entry.message.clone());
+                } else {
+                    connection.buffer_message(entry.message.clone(), *channel)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a [raw datagram](crate::packet::raw) of the given `kind` to a client, with the next packets.
+    ///
+    /// The datagrams are received on the client as [`RawDatagramEvent`](crate::prelude::client::RawDatagramEvent)s.
+    /// They are not sent to the local client in host-server mode.
+    pub fn send_raw(
+        &mut self,
+        client_id: ClientId,
+        kind: u8,
+        payload: &[u8],
+    ) -> Result<(), ServerError> {
+        let connection = self.connection_mut(client_id)?;
+        if connection.is_local_client() {
+            return Ok(());
+        }
+        connection.message_manager.buffer_send_raw(kind, payload)?;
+        Ok(())
     }
 
     /// Queues up a message to be sent to a client
+    ///
+    /// Messages that don't fit in a single packet are fragmented, and reassembled by the client.
     pub fn send_message<C: Channel, M: Message>(
         &mut self,
         client_id: ClientId,
@@ -169,6 +436,389 @@ impl ConnectionManager {
         self.send_message_to_target::<C, M>(message, NetworkTarget::Single(client_id))
     }
 
+    /// Queues up a message to be sent to a client, and track its delivery
+    ///
+    /// On channels that track acknowledgements (reliable channels and
+    /// [`UnorderedUnreliableWithAcks`](crate::prelude::ChannelMode::UnorderedUnreliableWithAcks)), returns the
+    /// [`MessageId`] of the message: a [`MessageDeliveredEvent`] with this id is emitted once the client
+    /// acknowledged the message, or a [`MessageDroppedEvent`] if the message can't be delivered anymore.
+    /// Returns `None` on the other channels, or if the client is not connected.
+    pub fn send_message_with_id<C: Channel, M: Message>(
+        &mut self,
+        client_id: ClientId,
+        message: &mut M,
+    ) -> Result<Option<MessageId>, ServerError> {
+        let Some(connection) = self.connections.get_mut(&client_id) else {
+            return Ok(None);
+        };
+        let entity_map = self.message_registry.is_map_entities::<M>().then_some(
+            &mut connection
+                .replication_receiver
+                .remote_entity_map
+                .local_to_remote,
+        );
+        self.writer
+            .set_context(connection.serialization_context.clone());
+        self.message_registry
+            .serialize(message, &mut self.writer, entity_map)?;
+        let message_bytes = self.writer.split();
+        if connection.is_local_client() {
+            connection.local_messages_to_send.push(message_bytes);
+            return Ok(None);
+        }
+        let channel = ChannelKind::of::<C>();
+        let message_id = connection.buffer_message(message_bytes, channel)?;
+        if let Some(message_id) = message_id {
+            connection.track_delivery(channel, message_id);
+        }
+        Ok(message_id)
+    }
+
+    /// Queues up a message to be sent to several clients.
+    ///
+    /// The message is serialized once and the same bytes are buffered for every client (unless the message
+    /// contains entities that must be mapped for each client), which is cheaper than calling
+    /// [`send_message`](Self::send_message) for each client. The clients that are not connected are ignored,
+    /// and a client that is listed several times receives the message once.
+    pub fn send_message_to_clients<C: Channel, M: Message>(
+        &mut self,
+        clients: impl IntoIterator<Item = ClientId>,
+        message: &mut M,
+    ) -> Result<(), ServerError> {
+        let target = NetworkTarget::Only(clients.into_iter().collect());
+        self.send_message_to_target::<C, M>(message, target)
+    }
+
+    /// Send a [`Request`] to a client.
+    ///
+    /// A [`ResponseEvent`](crate::prelude::server::ResponseEvent) with the id of the returned handle is emitted
+    /// when the client responds, or when the request times out.
+    pub fn send_request<C: Channel, R: Request>(
+        &mut self,
+        client_id: ClientId,
+        request: R,
+    ) -> Result<ResponseHandle<R::Response>, ServerError> {
+        let id = self.requests.next_id();
+        self.send_message::<C, _>(client_id, &mut RpcRequest { id, request })?;
+        self.requests.track::<R>(id, client_id);
+        Ok(ResponseHandle::new(id))
+    }
+
+    /// Respond to the [`RequestEvent`](crate::prelude::server::RequestEvent) with the id `request_id`
+    /// that was received from a client
+    pub fn send_response<C: Channel, R: Request>(
+        &mut self,
+        client_id: ClientId,
+        request_id: RequestId,
+        response: R::Response,
+    ) -> Result<(), ServerError> {
+        self.send_message::<C, _>(
+            client_id,
+            &mut RpcResponse::<R> {
+                id: request_id,
+                response,
+            },
+        )
+    }
+
+    /// Queues up a message to be sent to a client when the server reaches the given tick.
+    ///
+    /// The message is held on the server and buffered in the channel at `tick`, which is useful
+    /// to reveal some information to many clients at the same time (countdowns, etc.).
+    /// If `tick` has already been reached, the message is sent during the next send.
+    pub fn send_message_at_tick<C: Channel, M: Message>(
+        &mut self,
+        client_id: ClientId,
+        tick: Tick,
+        message: &mut M,
+    ) -> Result<(), ServerError> {
+        let connection = self
+            .connections
+            .get_mut(&client_id)
+            .ok_or(ServerError::ClientIdNotFound(client_id))?;
+        let entity_map = if self.message_registry.is_map_entities::<M>() {
+            Some(
+                &mut connection
+                    .replication_receiver
+                    .remote_entity_map
+                    .local_to_remote,
+            )
+        } else {
+            None
+        };
+        self.writer
+            .set_context(connection.serialization_context.clone());
+        self.message_registry
+            .serialize(message, &mut self.writer, entity_map)?;
+        let message_bytes = self.writer.split();
+        connection
+            .scheduled_messages
+            .push((tick, message_bytes, ChannelKind::of::<C>()));
+        Ok(())
+    }
+
+    /// Ask a client to disconnect and connect to another server instead.
+    ///
+    /// If `token` is None, the client will re-use its current authentication with the new address
+    /// (this only works if the client uses [`Authentication::Manual`](crate::prelude::client::Authentication::Manual)).
+    pub fn redirect(
+        &mut self,
+        client_id: ClientId,
+        server_addr: SocketAddr,
+        token: Option<ConnectToken>,
+    ) -> Result<(), ServerError> {
+        let token = token
+            .map(|token| token.try_into_bytes().map(|bytes| bytes.to_vec()))
+            .transpose()
+            .map_err(SerializationError::from)?;
+        self.send_message::<ControlChannel, _>(
+            client_id,
+            &mut ServerRedirect { server_addr, token },
+        )
+    }
+
+    /// Warn a client that the server is about to restart in `delay`.
+    ///
+    /// The client reconnects on its own once the server is back (see [`RestartConfig`](crate::prelude::client::RestartConfig)).
+    /// If `token` is None, the client will re-use its current authentication to reconnect.
+    pub fn notify_restart(
+        &mut self,
+        client_id: ClientId,
+        delay: Duration,
+        token: Option<ConnectToken>,
+    ) -> Result<(), ServerError> {
+        let token = token
+            .map(|token| token.try_into_bytes().map(|bytes| bytes.to_vec()))
+            .transpose()
+            .map_err(SerializationError::from)?;
+        self.send_message::<ControlChannel, _>(client_id, &mut ServerRestart { delay, token })
+    }
+
+    /// Disconnect a client and tell it why.
+    ///
+    /// The `reason` is sent in a reliable message, and the client is disconnected once it acknowledged
+    /// the message (or after [`KICK_ACK_TIMEOUT`] if the acknowledgement doesn't arrive).
+    /// The client receives the reason in [`DisconnectReason::Kicked`](crate::connection::client::DisconnectReason::Kicked).
+    pub fn kick(
+        &mut self,
+        client_id: ClientId,
+        reason: impl Into<String>,
+    ) -> Result<(), ServerError> {
+        let connection = self
+            .connections
+            .get_mut(&client_id)
+            .ok_or(ServerError::ClientIdNotFound(client_id))?;
+        self.message_registry.serialize(
+            &ServerKick {
+                reason: reason.into(),
+            },
+            &mut self.writer,
+            None,
+        )?;
+        let message = self.writer.split();
+        if connection.is_local_client() {
+            // the local client receives the message immediately
+            connection.local_messages_to_send.push(message);
+            connection.pending_kick = Some(PendingKick {
+                message_id: None,
+                acks: None,
+                deadline: connection.current_time,
+            });
+            return Ok(());
+        }
+        let channel_kind = ChannelKind::of::<ControlChannel>();
+        let acks = connection
+            .message_manager
+            .channels
+            .get_mut(&channel_kind)
+            .ok_or(PacketError::ChannelNotFound)?
+            .sender
+            .subscribe_acks();
+        let message_id = connection
+            .message_manager
+            .buffer_send(message, channel_kind)?;
+        connection.pending_kick = Some(PendingKick {
+            message_id,
+            acks: Some(acks),
+            deadline: connection.current_time + KICK_ACK_TIMEOUT,
+        });
+        Ok(())
+    }
+
+    /// Update the render rate reported by a client, see [`crate::server::pacing`]
+    pub(crate) fn set_render_rate(
+        &mut self,
+        client_id: ClientId,
+        render_rate: f32,
+        pacing: Option<&RenderRatePacing>,
+    ) -> Result<(), ServerError> {
+        self.connection_mut(client_id)?
+            .pacing
+            .set_render_rate(render_rate, pacing);
+        Ok(())
+    }
+
+    /// Attach a [`SerializationContext`] to the connection of a client, so that the payloads sent to that client
+    /// can be adapted to its locale, platform, etc. (see [`crate::serialize::context`])
+    pub fn set_serialization_context(
+        &mut self,
+        client_id: ClientId,
+        context: SerializationContext,
+    ) -> Result<(), ServerError> {
+        self.connection_mut(client_id)?.serialization_context = Some(Arc::new(context));
+        Ok(())
+    }
+
+    /// Limit the outgoing bandwidth of a client to `bytes_per_sec`, for example to avoid flooding the
+    /// players on a mobile connection. The limit applies on top of
+    /// [`PacketConfig::per_client_send_bandwidth_cap`](crate::prelude::server::PacketConfig::per_client_send_bandwidth_cap):
+    /// the messages that don't fit are sent later, by order of priority.
+    pub fn set_max_outgoing_bandwidth(
+        &mut self,
+        client_id: ClientId,
+        bytes_per_sec: NonZeroU32,
+    ) -> Result<(), ServerError> {
+        let connection = self.connection_mut(client_id)?;
+        connection
+            .message_manager
+            .set_max_outgoing_bandwidth(Some(Quota::per_second(bytes_per_sec)));
+        // the replication updates are now only considered sent once they fit in the bandwidth
+        connection
+            .replication_sender
+            .set_bandwidth_cap_enabled(true);
+        Ok(())
+    }
+
+    /// Remove the limit set with [`set_max_outgoing_bandwidth`](Self::set_max_outgoing_bandwidth)
+    pub fn clear_max_outgoing_bandwidth(&mut self, client_id: ClientId) -> Result<(), ServerError> {
+        let connection = self.connection_mut(client_id)?;
+        connection.message_manager.set_max_outgoing_bandwidth(None);
+        // the replication updates are considered sent once buffered again, unless another quota applies
+        let limited = connection.message_manager.is_limited();
+        connection
+            .replication_sender
+            .set_bandwidth_cap_enabled(limited);
+        Ok(())
+    }
+
+    /// Disconnect a client once it acknowledged all the reliable messages that were sent to it
+    /// (for example to deliver the final rewards or the reason of a ban before disconnecting), or
+    /// after `timeout` if some messages are still not acknowledged.
+    pub fn disconnect_after_flush(
+        &mut self,
+        client_id: ClientId,
+        timeout: Duration,
+    ) -> Result<(), ServerError> {
+        let connection = self
+            .connections
+            .get_mut(&client_id)
+            .ok_or(ServerError::ClientIdNotFound(client_id))?;
+        connection.flush_deadline = Some(connection.current_time + timeout);
+        Ok(())
+    }
+
+    /// Returns true if some reliable messages were not acknowledged by the clients yet
+    pub(crate) fn has_pending_messages(&self) -> bool {
+        self.connections
+            .values()
+            .any(|connection| connection.message_manager.has_pending_messages())
+    }
+
+    /// Warn the clients that are about to be kicked for being idle, and kick the ones that have been idle
+    /// for longer than the timeout.
+    ///
+    /// Returns the clients that were kicked
+    pub(crate) fn kick_idle_clients(
+        &mut self,
+        config: &IdleKickConfig,
+    ) -> Result<Vec<ClientId>, ServerError> {
+        let mut warned = vec![];
+        let mut kicked = vec![];
+        for (client_id, connection) in self.connections.iter_mut() {
+            if connection.is_local_client() || connection.pending_kick.is_some() {
+                continue;
+            }
+            let idle_time = connection.idle_time();
+            if idle_time >= config.timeout {
+                kicked.push(*client_id);
+            } else if let Some(warning) = config.warning {
+                if !connection.idle_warned && idle_time + warning >= config.timeout {
+                    connection.idle_warned = true;
+                    warned.push((*client_id, config.timeout - idle_time));
+                }
+            }
+        }
+        for (client_id, kick_in) in warned {
+            self.send_message::<ControlChannel, _>(client_id, &mut ServerIdleWarning { kick_in })?;
+        }
+        for client_id in kicked.iter() {
+            self.kick(*client_id, config.reason.clone())?;
+        }
+        Ok(kicked)
+    }
+
+    /// Clients that can now be disconnected:
+    /// - the clients that were kicked, once they received the reason of the kick or didn't acknowledge it in time
+    /// - the clients disconnected with [`disconnect_after_flush`](Self::disconnect_after_flush), once they
+    ///   acknowledged all the reliable messages or at the deadline
+    /// - the clients that did not acknowledge a message after the
+    ///   [`max_retries`](crate::prelude::ReliableSettings::max_retries) of its channel
+    pub(crate) fn clients_to_disconnect(&mut self) -> Vec<ClientId> {
+        self.connections
+            .iter_mut()
+            .filter_map(|(client_id, connection)| {
+                let flushed = connection.flush_deadline.is_some_and(|deadline| {
+                    connection.is_local_client()
+                        || !connection.message_manager.has_pending_messages()
+                        || connection.current_time >= deadline
+                });
+                let kicked = connection.pending_kick.as_ref().is_some_and(|kick| {
+                    let acked = kick.acks.as_ref().is_some_and(|acks| {
+                        acks.try_iter()
+                            .any(|message_id| Some(message_id) == kick.message_id)
+                    });
+                    acked || connection.current_time >= kick.deadline
+                });
+                let unresponsive = !connection.is_local_client()
+                    && connection
+                        .message_manager
+                        .retries_exhausted()
+                        .inspect(|channel| {
+                            info!(?client_id, ?channel, "A message was not acknowledged after the maximum number of retries");
+                        })
+                        .is_some();
+                (flushed || kicked || unresponsive).then_some(*client_id)
+            })
+            .collect()
+    }
+
+    /// Messages sent with [`send_message_with_id`](Self::send_message_with_id) that were acknowledged or dropped since
+    /// the last call
+    pub(crate) fn poll_deliveries(
+        &mut self,
+    ) -> (Vec<MessageDeliveredEvent>, Vec<MessageDroppedEvent>) {
+        let mut delivered = vec![];
+        let mut dropped = std::mem::take(&mut self.dropped_messages);
+        for (client_id, connection) in self.connections.iter_mut() {
+            connection.delivery.poll(|channel, message_id, acked| {
+                if acked {
+                    delivered.push(MessageDeliveredEvent {
+                        client_id: *client_id,
+                        channel,
+                        message_id,
+                    });
+                } else {
+                    dropped.push(MessageDroppedEvent {
+                        client_id: *client_id,
+                        channel,
+                        message_id,
+                    });
+                }
+            });
+        }
+        (delivered, dropped)
+    }
+
     /// Update the priority of a `ReplicationGroup` that is replicated to a given client
     pub fn update_priority(
         &mut self,
@@ -194,6 +844,10 @@ impl ConnectionManager {
         target: NetworkTarget,
     ) -> Box<dyn Iterator<Item = ClientId>> {
         // TODO: avoid extra allocations ...
+        allow_allocations(AllocationException::Targets, || self.targets(target))
+    }
+
+    fn targets(&self, target: NetworkTarget) -> Box<dyn Iterator<Item = ClientId>> {
         match target {
             NetworkTarget::All => {
                 // TODO: maybe only send stuff when the client is time-synced ?
@@ -253,51 +907,260 @@ impl ConnectionManager {
         time_manager: &TimeManager,
         tick_manager: &TickManager,
     ) {
-        self.connections.values_mut().for_each(|connection| {
-            connection.update(world_tick, time_manager, tick_manager);
-        });
+        self.connections
+            .values_mut()
+            .chain(self.queue.iter_mut())
+            .for_each(|connection| {
+                connection.update(world_tick, time_manager, tick_manager);
+            });
     }
 
     /// Add a new [`Connection`] to the list of connections with the given [`ClientId`]
-    pub(crate) fn add(&mut self, client_id: ClientId, client_entity: Entity) {
-        if let Entry::Vacant(e) = self.connections.entry(client_id) {
-            #[cfg(feature = "metrics")]
-            metrics::gauge!("connected_clients").increment(1.0);
+    pub(crate) fn add(
+        &mut self,
+        client_id: ClientId,
+        client_entity: Entity,
+        protocol_id: Option<u64>,
+    ) {
+        if self.connections.contains_key(&client_id) {
+            info!("Client {} was already in the connections list", client_id);
+            return;
+        }
+        let connection = self.new_connection(client_id, client_entity, protocol_id);
+        self.insert_connection(connection);
+    }
 
-            info!("New connection from id: {}", client_id);
-            let connection = Connection::new(
-                client_id,
-                client_entity,
-                &self.channel_registry,
-                self.replication_config,
-                self.packet_config,
-                self.ping_config,
+    fn new_connection(
+        &self,
+        client_id: ClientId,
+        client_entity: Entity,
+        protocol_id: Option<u64>,
+    ) -> Connection {
+        let mut connection = Connection::new(
+            client_id,
+            client_entity,
+            &self.channel_registry,
+            self.replication_config,
+            self.packet_config,
+            self.ping_config,
+        );
+        connection.protocol_id = protocol_id;
+        connection.protocol_shim = protocol_id.and_then(|id| self.protocol_shims.get(&id).cloned());
+        connection.world_view = ClientWorldView::new(self.client_world_view);
+        if !self.schema_versions.is_empty() {
+            let _ = connection
+                .message_manager
+                .buffer_send(
+                    self.schema_versions.clone(),
+                    ChannelKind::of::<ControlChannel>(),
+                )
+                .inspect_err(|e| error!("Could not send the wire format versions: {e:?}"));
+        }
+        // the messages converted by a shim don't necessarily include the versions of the client
+        connection.awaiting_remote_versions =
+            self.await_remote_versions && connection.protocol_shim.is_none();
+        connection
+    }
+
+    /// Set the wire format versions of the protocol, which are sent to each client when it connects
+    pub(crate) fn set_schema_versions(
+        &mut self,
+        versions: &SchemaVersions,
+    ) -> Result<(), ServerError> {
+        self.message_registry
+            .serialize(versions, &mut self.writer, None)?;
+        self.schema_versions = self.writer.split();
+        self.await_remote_versions = !versions.is_empty();
+        Ok(())
+    }
+
+    fn insert_connection(&mut self, connection: Connection) {
+        #[cfg(feature = "metrics")]
+        metrics::gauge!("connected_clients").increment(1.0);
+
+        let client_id = connection.client_id;
+        info!("New connection from id: {}", client_id);
+        self.events.add_connect_event(ConnectEvent {
+            client_id,
+            entity: connection.entity,
+            stamp: None,
+        });
+        self.new_clients.push(client_id);
+        self.connections.insert(client_id, connection);
+    }
+
+    /// Position (starting at 1) of a client in the connection queue, or None if the client is not queued
+    pub fn queue_position(&self, client_id: ClientId) -> Option<usize> {
+        self.queue
+            .iter()
+            .position(|connection| connection.client_id == client_id)
+            .map(|idx| idx + 1)
+    }
+
+    /// Return the list of [`ClientId`]s waiting in the connection queue, in order
+    pub fn queued_clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.queue.iter().map(|connection| connection.client_id)
+    }
+
+    /// Place a newly connected client at the end of the connection queue and send it its position.
+    ///
+    /// Returns the position of the client in the queue
+    pub(crate) fn enqueue(
+        &mut self,
+        client_id: ClientId,
+        protocol_id: Option<u64>,
+    ) -> Result<usize, ServerError> {
+        info!("Server is full, client {} is queued", client_id);
+        // the client entity is only spawned when the client leaves the queue
+        let mut connection = self.new_connection(client_id, Entity::PLACEHOLDER, protocol_id);
+        let position = self.queue.len() + 1;
+        Self::buffer_queue_update(
+            &self.message_registry,
+            &mut self.writer,
+            &mut connection,
+            ServerQueueUpdate::Position(position as u32),
+        )?;
+        self.queue.push_back(connection);
+        Ok(position)
+    }
+
+    /// Remove a client from the connection queue. Returns false if the client was not queued
+    pub(crate) fn dequeue(&mut self, client_id: ClientId) -> bool {
+        let len = self.queue.len();
+        self.queue
+            .retain(|connection| connection.client_id != client_id);
+        self.queue.len() != len
+    }
+
+    /// Add the first client of the connection queue to the server
+    pub(crate) fn admit_next(&mut self, client_entity: Entity) -> Result<(), ServerError> {
+        let Some(mut connection) = self.queue.pop_front() else {
+            return Ok(());
+        };
+        connection.entity = client_entity;
+        Self::buffer_queue_update(
+            &self.message_registry,
+            &mut self.writer,
+            &mut connection,
+            ServerQueueUpdate::Admitted,
+        )?;
+        self.insert_connection(connection);
+        Ok(())
+    }
+
+    /// Send their current position to all the clients in the connection queue
+    pub(crate) fn report_queue_positions(&mut self) -> Result<(), ServerError> {
+        self.queue
+            .iter_mut()
+            .enumerate()
+            .try_for_each(|(idx, connection)| {
+                Self::buffer_queue_update(
+                    &self.message_registry,
+                    &mut self.writer,
+                    connection,
+                    ServerQueueUpdate::Position(idx as u32 + 1),
+                )
+            })
+    }
+
+    fn buffer_queue_update(
+        message_registry: &MessageRegistry,
+        writer: &mut Writer,
+        connection: &mut Connection,
+        update: ServerQueueUpdate,
+    ) -> Result<(), ServerError> {
+        message_registry.serialize(&update, writer, None)?;
+        connection
+            .message_manager
+            .buffer_send(writer.split(), ChannelKind::of::<ControlChannel>())?;
+        Ok(())
+    }
+
+    /// Get the entities and the component values that were replicated to a client.
+    ///
+    /// This can be compared with the server's world to diagnose replication issues.
+    /// The view is empty unless [`ServerConfig::client_world_view`](crate::prelude::server::ServerConfig::client_world_view) is true.
+    pub fn client_world_view(&self, client_id: ClientId) -> Result<&ClientWorldView, ServerError> {
+        Ok(&self.connection(client_id)?.world_view)
+    }
+
+    /// Last time that we received a packet from the client
+    pub(crate) fn last_heard(&self, client_id: ClientId) -> Option<WrappedTime> {
+        self.connections
+            .get(&client_id)
+            .and_then(|connection| connection.last_heard)
+    }
+
+    /// Update the [`BaselineState`] of a client after receiving its [`BaselineReport`](crate::shared::replication::baseline::BaselineReport)
+    pub(crate) fn receive_baseline_report(
+        &mut self,
+        client_id: ClientId,
+        matched: bool,
+    ) -> Result<(), ServerError> {
+        let connection = self.connection_mut(client_id)?;
+        if connection.baseline != BaselineState::Pending {
+            return Ok(());
+        }
+        if matched {
+            debug!(
+                ?client_id,
+                "Client has the static baseline, skipping its replication"
             );
-            self.events.add_connect_event(ConnectEvent {
-                client_id,
-                entity: client_entity,
-            });
-            self.new_clients.push(client_id);
-            e.insert(connection);
+            connection.baseline = BaselineState::Matched;
         } else {
-            info!("Client {} was already in the connections list", client_id);
+            debug!(
+                ?client_id,
+                "Client does not have the static baseline, replicating it"
+            );
+            connection.baseline = BaselineState::Required;
+            self.new_baseline_clients.push(client_id);
         }
+        Ok(())
+    }
+
+    /// Target containing all the clients to which the [`Baseline`](crate::prelude::Baseline) entities
+    /// must be replicated
+    pub(crate) fn baseline_target(&self) -> NetworkTarget {
+        allow_allocations(AllocationException::Targets, || {
+            NetworkTarget::from(
+                self.connections
+                    .iter()
+                    .filter(|(_, connection)| connection.baseline == BaselineState::Required)
+                    .map(|(client_id, _)| *client_id)
+                    .collect::<Vec<_>>(),
+            )
+        })
     }
 
     /// Remove the connection associated with the given [`ClientId`],
-    /// and returns the [`Entity`] associated with the client
-    pub(crate) fn remove(&mut self, client_id: ClientId) -> Entity {
+    /// and returns the [`Entity`] associated with the client, or an error if the client is not connected
+    pub(crate) fn remove(&mut self, client_id: ClientId) -> Result<Entity, ServerError> {
+        let entity = self.client_entity(client_id)?;
         #[cfg(feature = "metrics")]
         metrics::gauge!("connected_clients").decrement(1.0);
 
         info!("Client {} disconnected", client_id);
-        let entity = self
-            .client_entity(client_id)
-            .expect("client entity not found");
-        self.events
-            .add_disconnect_event(DisconnectEvent { client_id, entity });
-        self.connections.remove(&client_id);
-        entity
+        self.events.add_disconnect_event(DisconnectEvent {
+            client_id,
+            entity,
+            stamp: None,
+        });
+        if let Some(connection) = self.connections.remove(&client_id) {
+            // the messages that were not acknowledged yet will never be
+            self.dropped_messages
+                .extend(
+                    connection
+                        .delivery
+                        .pending
+                        .into_iter()
+                        .map(|(channel, message_id)| MessageDroppedEvent {
+                            client_id,
+                            channel,
+                            message_id,
+                        }),
+                );
+        }
+        Ok(entity)
     }
 
     pub(crate) fn buffer_message_bytes(
@@ -330,15 +1193,18 @@ impl ConnectionManager {
         message: &M,
         channel: ChannelKind,
         target: NetworkTarget,
+        trace_id: Option<TraceId>,
     ) -> Result<(), ServerError> {
         self.connections
             .iter_mut()
             .filter(|(id, _)| target.targets(id))
             .try_for_each(|(_, c)| {
-                self.message_registry.serialize(
+                self.writer.set_context(c.serialization_context.clone());
+                self.message_registry.serialize_with_trace_id(
                     message,
                     &mut self.writer,
                     Some(&mut c.replication_receiver.remote_entity_map.local_to_remote),
+                    trace_id,
                 )?;
                 let message_bytes = self.writer.split();
                 // for local clients, we don't want to buffer messages in the MessageManager since
@@ -362,18 +1228,50 @@ impl ConnectionManager {
         message: &M,
         channel_kind: ChannelKind,
         target: NetworkTarget,
+    ) -> Result<(), ServerError> {
+        self.erased_send_message_to_target_with_trace_id(message, channel_kind, target, None)
+    }
+
+    pub(crate) fn erased_send_message_to_target_with_trace_id<M: Message>(
+        &mut self,
+        message: &M,
+        channel_kind: ChannelKind,
+        target: NetworkTarget,
+        trace_id: Option<TraceId>,
     ) -> Result<(), ServerError> {
         if self.message_registry.is_map_entities::<M>() {
-            self.buffer_map_entities_message(message, channel_kind, target)?;
+            self.buffer_map_entities_message(message, channel_kind, target, trace_id)?;
         } else {
-            self.message_registry
-                .serialize(message, &mut self.writer, None)?;
+            self.message_registry.serialize_with_trace_id(
+                message,
+                &mut self.writer,
+                None,
+                trace_id,
+            )?;
             let message_bytes = self.writer.split();
             self.buffer_message_bytes(message_bytes, channel_kind, target)?;
         }
         Ok(())
     }
 
+    /// Buffer the scheduled messages that must be sent at `tick`
+    pub(crate) fn release_scheduled_messages(&mut self, tick: Tick) -> Result<(), ServerError> {
+        self.connections.values_mut().try_for_each(|c| {
+            let (ready, pending) = std::mem::take(&mut c.scheduled_messages)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(message_tick, _, _)| *message_tick <= tick);
+            c.scheduled_messages = pending;
+            ready.into_iter().try_for_each(|(_, message, channel)| {
+                if c.is_local_client() {
+                    c.local_messages_to_send.push(message);
+                } else {
+                    c.buffer_message(message, channel)?;
+                }
+                Ok(())
+            })
+        })
+    }
+
     /// Buffer all the replication messages to send.
     /// Keep track of the bevy Change Tick: when a message is acked, we know that we only have to send
     /// the updates since that Change Tick
@@ -385,9 +1283,18 @@ impl ConnectionManager {
         time_manager: &TimeManager,
     ) -> Result<(), ServerError> {
         let _span = info_span!("buffer_replication_messages").entered();
-        self.connections
+        #[cfg(feature = "alloc_audit")]
+        let counter = AllocationCounter::start();
+        let result = self
+            .connections
             .values_mut()
-            .try_for_each(move |c| c.buffer_replication_messages(tick, bevy_tick, time_manager))
+            .try_for_each(move |c| c.buffer_replication_messages(tick, bevy_tick, time_manager));
+        #[cfg(feature = "alloc_audit")]
+        {
+            // the updates were collected by the `replicate` system earlier in the frame
+            self.allocations.replication += counter.finish();
+        }
+        result
     }
 
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
@@ -398,6 +1305,38 @@ impl ConnectionManager {
         message_registry: &MessageRegistry,
         time_manager: &TimeManager,
         tick_manager: &TickManager,
+    ) -> Result<(), ServerError> {
+        #[cfg(feature = "alloc_audit")]
+        {
+            let (result, allocations) = count_allocations(|| {
+                self.receive_connections(
+                    world,
+                    component_registry,
+                    message_registry,
+                    time_manager,
+                    tick_manager,
+                )
+            });
+            self.allocations.receive = allocations;
+            result
+        }
+        #[cfg(not(feature = "alloc_audit"))]
+        self.receive_connections(
+            world,
+            component_registry,
+            message_registry,
+            time_manager,
+            tick_manager,
+        )
+    }
+
+    fn receive_connections(
+        &mut self,
+        world: &mut World,
+        component_registry: &ComponentRegistry,
+        message_registry: &MessageRegistry,
+        time_manager: &TimeManager,
+        tick_manager: &TickManager,
     ) -> Result<(), ServerError> {
         let mut messages_to_rebroadcast = vec![];
         // TODO: do this in parallel
@@ -443,6 +1382,8 @@ impl ConnectionManager {
             .ok_or::<ServerError>(ComponentError::NotRegistered.into())?;
         // TODO: add SendEntityMap here!
         // We store the Bytes in a hashmap, maybe more efficient to write the replication message directly?
+        let context = self.connection(client_id)?.serialization_context.clone();
+        self.writer.set_context(context);
         component_registry.serialize(data, &mut self.writer, None)?;
         let raw_data = self.writer.split();
         self.connection_mut(client_id)?
@@ -454,7 +1395,7 @@ impl ConnectionManager {
 
 /// Wrapper that handles the connection between the server and a client
 pub struct Connection {
-    client_id: ClientId,
+    pub(crate) client_id: ClientId,
     /// We create one entity per connected client, so that users
     /// can store metadata about the client using the ECS
     entity: Entity,
@@ -478,6 +1419,88 @@ pub struct Connection {
     is_local_client: bool,
     /// Messages to send to the local client (we don't buffer them in the MessageManager because there is no io)
     pub(crate) local_messages_to_send: Vec<Bytes>,
+    /// Messages that are held until the server reaches the given tick
+    pub(crate) scheduled_messages: Vec<(Tick, Bytes, ChannelKind)>,
+    /// Whether the [`Baseline`](crate::prelude::Baseline) entities must be replicated to this client
+    pub(crate) baseline: BaselineState,
+    /// Protocol id that the client used to connect
+    protocol_id: Option<u64>,
+    /// Converts the messages if the client uses another version of the protocol
+    protocol_shim: Option<Arc<dyn ProtocolShim>>,
+    /// Last time we received a packet from this client
+    pub(crate) last_heard: Option<WrappedTime>,
+    /// Last time we received a message or an input from this client
+    last_active: Option<WrappedTime>,
+    /// True if the client was warned that it will be kicked for being idle
+    idle_warned: bool,
+    /// Time at which the connection was established
+    connected_at: Option<WrappedTime>,
+    // copy of the current time, updated every frame
+    current_time: WrappedTime,
+    /// Entities and components that were sent to this client
+    pub(crate) world_view: ClientWorldView,
+    /// Set when the client was kicked, until the client is disconnected
+    pending_kick: Option<PendingKick>,
+    /// Deadline of [`ConnectionManager::disconnect_after_flush`], until the client is disconnected
+    flush_deadline: Option<WrappedTime>,
+    /// Messages sent with [`ConnectionManager::send_message_with_id`] that were not acknowledged yet
+    delivery: DeliveryTracker,
+    /// Schedule of the component updates, paced to the render rate of the client
+    pacing: UpdatePacing,
+    /// Context used to serialize the values sent to the client
+    serialization_context: Option<Arc<SerializationContext>>,
+    /// True until the wire format versions of the client are received. Only the ping, pong and control channels
+    /// are read until then
+    awaiting_remote_versions: bool,
+}
+
+/// Maximum time that the server waits for a kicked client to acknowledge the reason of the kick,
+/// before disconnecting it
+pub const KICK_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Message kept in the history of a room, to be replayed to the clients that join it
+#[derive(Debug)]
+struct RoomHistoryEntry {
+    message: Bytes,
+    /// Clients that were excluded from the message, and that don't receive it when they join the room
+    except: NetworkTarget,
+}
+
+/// A kick that is waiting for the client to acknowledge the message containing the reason
+struct PendingKick {
+    message_id: Option<MessageId>,
+    acks: Option<Receiver<MessageId>>,
+    deadline: WrappedTime,
+}
+
+/// Tracks the acknowledgements of the messages sent with [`ConnectionManager::send_message_with_id`]
+#[derive(Default)]
+struct DeliveryTracker {
+    /// Receivers of the acks of each channel, and of the nacks if the channel doesn't retransmit the
+    /// lost messages
+    receivers: HashMap<ChannelKind, (Receiver<MessageId>, Option<Receiver<MessageId>>)>,
+    pending: HashSet<(ChannelKind, MessageId)>,
+}
+
+impl DeliveryTracker {
+    /// Call `f` with `true` for each pending message that was acked, and `false` for each pending message
+    /// that was lost
+    ///
+    /// The receivers are always drained, so that they don't keep the acks of the messages that are not tracked
+    fn poll(&mut self, mut f: impl FnMut(ChannelKind, MessageId, bool)) {
+        for (channel, (acks, nacks)) in self.receivers.iter() {
+            let nacks = nacks.iter().flat_map(|nacks| nacks.try_iter());
+            for (message_id, acked) in acks
+                .try_iter()
+                .map(|id| (id, true))
+                .chain(nacks.map(|id| (id, false)))
+            {
+                if self.pending.remove(&(*channel, message_id)) {
+                    f(*channel, message_id, acked);
+                }
+            }
+        }
+    }
 }
 
 impl Connection {
@@ -489,11 +1512,14 @@ impl Connection {
         packet_config: PacketConfig,
         ping_config: PingConfig,
     ) -> Self {
-        let bandwidth_cap_enabled = packet_config.bandwidth_cap_enabled;
+        let bandwidth_cap_enabled = packet_config.bandwidth_cap_enabled
+            || packet_config.per_client_send_budget_per_tick.is_some();
         // create the message manager and the channels
         let mut message_manager = MessageManager::new(
             channel_registry,
             packet_config.nack_rtt_multiple,
+            packet_config.ack_bitfield_size,
+            packet_config.into(),
             packet_config.into(),
         );
         // get notified about acks/nacks for replication-update messages
@@ -531,12 +1557,30 @@ impl Connection {
             messages_to_rebroadcast: vec![],
             is_local_client: false,
             local_messages_to_send: vec![],
+            scheduled_messages: vec![],
+            baseline: BaselineState::default(),
+            protocol_id: None,
+            protocol_shim: None,
+            last_heard: None,
+            last_active: None,
+            idle_warned: false,
+            connected_at: None,
+            current_time: WrappedTime::default(),
+            world_view: ClientWorldView::default(),
+            pending_kick: None,
+            flush_deadline: None,
+            delivery: DeliveryTracker::default(),
+            pacing: UpdatePacing::default(),
+            serialization_context: None,
+            awaiting_remote_versions: false,
         }
     }
 
     /// Update the connection to make clear that it corresponds to the local client
     pub(crate) fn set_local_client(&mut self) {
         self.is_local_client = true;
+        // the local client shares the protocol of the server
+        self.awaiting_remote_versions = false;
     }
 
     /// Returns true if this connection corresponds to the local client in HostServer mode
@@ -544,6 +1588,15 @@ impl Connection {
         self.is_local_client
     }
 
+    /// Protocol id that the client used to connect.
+    ///
+    /// It can differ from the protocol id of the server if the server accepts
+    /// [compatible protocol ids](crate::server::config::NetcodeConfig::compatible_protocol_ids).
+    /// None if the connection type does not use a protocol id.
+    pub fn protocol_id(&self) -> Option<u64> {
+        self.protocol_id
+    }
+
     /// Return the latest estimate of rtt
     pub fn rtt(&self) -> Duration {
         self.ping_manager.rtt()
@@ -554,13 +1607,71 @@ impl Connection {
         self.ping_manager.jitter()
     }
 
+    /// Time elapsed since we last received a packet from the client
+    pub fn last_heard(&self) -> Duration {
+        self.last_heard.map_or(Duration::ZERO, |last_heard| {
+            (self.current_time - last_heard)
+                .to_std()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Time elapsed since we last received a message or an input from the client.
+    ///
+    /// Pings, acks and the other packets that are sent automatically don't count.
+    pub fn idle_time(&self) -> Duration {
+        self.last_active.map_or(Duration::ZERO, |last_active| {
+            (self.current_time - last_active)
+                .to_std()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Time at which the connection was established, as measured by the server's [`TimeManager`]
+    pub fn connected_at(&self) -> WrappedTime {
+        self.connected_at.unwrap_or(self.current_time)
+    }
+
+    /// Time elapsed since the connection was established
+    pub fn connection_age(&self) -> Duration {
+        (self.current_time - self.connected_at())
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Render rate (in frames per second) reported by the client, see [`crate::server::pacing`]
+    pub fn render_rate(&self) -> Option<f32> {
+        self.pacing.render_rate
+    }
+
+    /// Context used to serialize the values sent to the client, see [`crate::serialize::context`]
+    pub fn serialization_context(&self) -> Option<&SerializationContext> {
+        self.serialization_context.as_deref()
+    }
+
+    /// Statistics about the messages that were retransmitted on the reliable channel `C`.
+    ///
+    /// Returns None if the channel is not reliable
+    pub fn retransmission_stats<C: Channel>(&self) -> Option<RetransmissionStats> {
+        self.message_manager
+            .retransmission_stats(&ChannelKind::of::<C>())
+    }
+
     pub(crate) fn update(
         &mut self,
         world_tick: BevyTick,
         time_manager: &TimeManager,
         tick_manager: &TickManager,
     ) {
+        self.current_time = time_manager.current_time();
+        self.connected_at.get_or_insert(self.current_time);
+        // the client counts as heard from when the connection is established
+        self.last_heard.get_or_insert(self.current_time);
+        self.last_active.get_or_insert(self.current_time);
         if self.is_local_client() {
+            // the local client does not send packets, but it is always active
+            self.last_heard = Some(time_manager.current_time());
+            self.last_active = Some(time_manager.current_time());
             return;
         }
         self.message_manager
@@ -569,11 +1680,14 @@ impl Connection {
         self.ping_manager.update(time_manager);
     }
 
+    /// Buffer a message to be sent to the client, and return the id of the message if the channel assigns one
+    ///
+    /// Returns `None` if the message was dropped because it doesn't exist in the client's protocol.
     pub(crate) fn buffer_message(
         &mut self,
         message: Bytes,
         channel: ChannelKind,
-    ) -> Result<(), ServerError> {
+    ) -> Result<Option<MessageId>, ServerError> {
         // TODO: i know channel names never change so i should be able to get them as static
         // TODO: just have a channel registry enum as well?
         let channel_name = self
@@ -582,8 +1696,50 @@ impl Connection {
             .name(&channel)
             .ok_or::<ServerError>(MessageError::NotRegistered.into())?;
         // message.emit_send_logs(&channel_name);
-        self.message_manager.buffer_send(message, channel)?;
-        Ok(())
+        let message = match &self.protocol_shim {
+            Some(shim) => match shim.downgrade_message(message) {
+                Some(message) => message,
+                None => {
+                    trace!(
+                        ?channel,
+                        "message has no equivalent in the client's protocol"
+                    );
+                    return Ok(None);
+                }
+            },
+            None => message,
+        };
+        Ok(self.message_manager.buffer_send(message, channel)?)
+    }
+
+    /// Report the delivery of the message with [`MessageDeliveredEvent`] or [`MessageDroppedEvent`]
+    fn track_delivery(&mut self, channel: ChannelKind, message_id: MessageId) {
+        let Some(reliable) = self
+            .message_manager
+            .channel_registry
+            .get_builder_from_kind(&channel)
+            .map(|builder| &builder.settings.mode)
+            .filter(|mode| mode.is_watching_acks())
+            .map(|mode| mode.is_reliable())
+        else {
+            return;
+        };
+        if !self.delivery.receivers.contains_key(&channel) {
+            let Some(sender) = self
+                .message_manager
+                .channels
+                .get_mut(&channel)
+                .map(|channel| &mut channel.sender)
+            else {
+                return;
+            };
+            // reliable channels retransmit the lost messages, so a nack doesn't mean that the message is dropped
+            let nacks = (!reliable).then(|| sender.subscribe_nacks());
+            self.delivery
+                .receivers
+                .insert(channel, (sender.subscribe_acks(), nacks));
+        }
+        self.delivery.pending.insert((channel, message_id));
     }
 
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
@@ -593,38 +1749,50 @@ impl Connection {
         bevy_tick: BevyTick,
         time_manager: &TimeManager,
     ) -> Result<(), ServerError> {
+        // the component updates are not collected when the client is paced out (see `prepare_component_update`)
+        let updates_skipped = !self.pacing.is_due(self.current_time);
+        if !updates_skipped {
+            self.pacing.on_send(self.current_time);
+        }
         self.replication_sender.accumulate_priority(time_manager);
-        self.replication_sender.send_actions_messages(
-            tick,
-            bevy_tick,
-            &mut self.writer,
-            &mut self.message_manager,
-        )?;
-        self.replication_sender.send_updates_messages(
-            tick,
-            bevy_tick,
-            &mut self.writer,
-            &mut self.message_manager,
-        )?;
-        Ok(())
+        allow_allocations(AllocationException::ReplicationBuffers, || {
+            self.replication_sender.send_actions_messages(
+                tick,
+                bevy_tick,
+                updates_skipped,
+                &mut self.writer,
+                &mut self.message_manager,
+            )?;
+            self.replication_sender.send_updates_messages(
+                tick,
+                bevy_tick,
+                &mut self.writer,
+                &mut self.message_manager,
+            )?;
+            Ok(())
+        })
     }
 
     fn send_ping(&mut self, ping: Ping) -> Result<(), ServerError> {
         trace!("Sending ping {:?}", ping);
-        ping.to_bytes(&mut self.writer)?;
-        let message_bytes = self.writer.split();
-        self.message_manager
-            .buffer_send(message_bytes, ChannelKind::of::<PingChannel>())?;
-        Ok(())
+        allow_allocations(AllocationException::Ping, || {
+            ping.to_bytes(&mut self.writer)?;
+            let message_bytes = self.writer.split();
+            self.message_manager
+                .buffer_send(message_bytes, ChannelKind::of::<PingChannel>())?;
+            Ok(())
+        })
     }
 
     fn send_pong(&mut self, pong: Pong) -> Result<(), ServerError> {
         trace!("Sending pong {:?}", pong);
-        pong.to_bytes(&mut self.writer)?;
-        let message_bytes = self.writer.split();
-        self.message_manager
-            .buffer_send(message_bytes, ChannelKind::of::<PongChannel>())?;
-        Ok(())
+        allow_allocations(AllocationException::Ping, || {
+            pong.to_bytes(&mut self.writer)?;
+            let message_bytes = self.writer.split();
+            self.message_manager
+                .buffer_send(message_bytes, ChannelKind::of::<PongChannel>())?;
+            Ok(())
+        })
     }
 
     /// Send packets that are ready to be sent
@@ -672,11 +1840,30 @@ impl Connection {
         tick_manager: &TickManager,
     ) -> Result<ConnectionEvents, ServerError> {
         let _span = trace_span!("receive").entered();
+        let baseline_report_net_id = message_registry
+            .kind_map
+            .net_id(&MessageKind::of::<BaselineReport>())
+            .copied();
+        let schema_versions_net_id = message_registry
+            .kind_map
+            .net_id(&MessageKind::of::<SchemaVersions>())
+            .copied();
         self.message_manager
             .channels
             .iter_mut()
             .try_for_each(|(channel_kind, channel)| {
-                while let Some((tick, single_data)) = channel.receiver.read_message() {
+                // the values must not be read before we know which versions the client uses
+                if self.awaiting_remote_versions
+                    && ![
+                        ChannelKind::of::<PingChannel>(),
+                        ChannelKind::of::<PongChannel>(),
+                        ChannelKind::of::<ControlChannel>(),
+                    ]
+                    .contains(channel_kind)
+                {
+                    return Ok(());
+                }
+                while let Some((tick, single_data)) = channel.read_message() {
                     // let channel_name = self
                     //     .message_manager
                     //     .channel_registry
@@ -691,14 +1878,18 @@ impl Connection {
                         let ping = Ping::from_bytes(&mut reader)?;
                         // prepare a pong in response (but do not send yet, because we need
                         // to set the correct send time)
-                        self.ping_manager
-                            .buffer_pending_pong(&ping, time_manager.current_time());
+                        allow_allocations(AllocationException::Ping, || {
+                            self.ping_manager
+                                .buffer_pending_pong(&ping, time_manager.current_time())
+                        });
                         trace!("buffer pong");
                     } else if channel_kind == &ChannelKind::of::<PongChannel>() {
                         let pong = Pong::from_bytes(&mut reader)?;
                         // process the pong
-                        self.ping_manager
-                            .process_pong(&pong, time_manager.current_time());
+                        allow_allocations(AllocationException::Ping, || {
+                            self.ping_manager
+                                .process_pong(&pong, time_manager.current_time())
+                        });
                     } else if channel_kind == &ChannelKind::of::<EntityActionsChannel>() {
                         let actions = EntityActionsMessage::from_bytes(&mut reader)?;
                         trace!(?tick, ?actions, "received replication actions message");
@@ -717,9 +1908,40 @@ impl Connection {
                         //  instead just read the bytes for the target!!
                         let ClientMessage { message, target } =
                             ClientMessage::from_bytes(&mut reader)?;
+                        let message = match &self.protocol_shim {
+                            Some(shim) => match shim.upgrade_message(message) {
+                                Some(message) => message,
+                                None => {
+                                    trace!(
+                                        ?channel_kind,
+                                        "message has no equivalent in the current protocol"
+                                    );
+                                    continue;
+                                }
+                            },
+                            None => message,
+                        };
 
                         let mut reader = Reader::from(message);
                         let net_id = NetId::from_bytes(&mut reader)?;
+                        if Some(net_id) == schema_versions_net_id {
+                            self.awaiting_remote_versions = false;
+                            match RemoteVersions::read(
+                                reader.consume(),
+                                message_registry,
+                                component_registry,
+                            ) {
+                                Ok(versions) => {
+                                    self.replication_receiver.remote_versions =
+                                        Some(Arc::new(versions));
+                                }
+                                Err(e) => error!(
+                                    client_id = ?self.client_id,
+                                    "Could not read the wire format versions of the client: {e:?}"
+                                ),
+                            }
+                            continue;
+                        }
                         // we are also sending target and channel kind so the message can be
                         // rebroadcasted to other clients after we have converted the entities from the
                         // client World to the server World
@@ -728,6 +1950,11 @@ impl Connection {
                         //  or it matters for input messages?
                         // TODO: avoid clone with Arc<[u8]>?
                         let data = (reader.consume(), target, *channel_kind);
+                        // the baseline report is sent automatically, so it doesn't count as activity
+                        if Some(net_id) != baseline_report_net_id {
+                            self.last_active = Some(time_manager.current_time());
+                            self.idle_warned = false;
+                        }
                         match message_registry.message_type(net_id) {
                             #[cfg(feature = "leafwing")]
                             MessageType::LeafwingInput => self
@@ -815,6 +2042,9 @@ impl Connection {
         component_registry: &ComponentRegistry,
         delta_manager: &mut DeltaManager,
     ) -> Result<(), ServerError> {
+        let Some(packet) = self.message_manager.recv_raw(packet) else {
+            return Ok(());
+        };
         // receive the packets, buffer them, update any sender that were waiting for their sent messages to be acked
         let tick = self.message_manager.recv_packet(packet)?;
         // notify the replication sender that some sent messages were received
@@ -823,12 +2053,21 @@ impl Connection {
         debug!("Received server packet with tick: {:?}", tick);
         Ok(())
     }
+
+    /// Drop the messages received from a client that is waiting in the connection queue
+    pub(crate) fn discard_received_messages(&mut self) {
+        self.message_manager
+            .channels
+            .values_mut()
+            .for_each(|channel| while channel.read_message().is_some() {});
+        self.message_manager.received_raw.clear();
+    }
 }
 
 impl ConnectionManager {
     pub(crate) fn prepare_entity_despawn(
         &mut self,
-        mut entity: Entity,
+        local_entity: Entity,
         group_id: ReplicationGroupId,
         target: NetworkTarget,
     ) -> Result<(), ServerError> {
@@ -839,15 +2078,16 @@ impl ConnectionManager {
             //     "Send entity despawn for tick {:?}",
             //     self.tick_manager.tick()
             // );
+            let connection = self.connection_mut(client_id)?;
+            connection.world_view.despawn(local_entity);
 
             // convert the entity to a network entity (possibly mapped)
-            entity = self
-                .connection_mut(client_id)?
+            let entity = connection
                 .replication_receiver
                 .remote_entity_map
-                .to_remote(entity);
+                .to_remote(local_entity);
 
-            self.connection_mut(client_id)?
+            connection
                 .replication_sender
                 .prepare_entity_despawn(entity, group_id);
             Ok(())
@@ -856,19 +2096,23 @@ impl ConnectionManager {
 
     pub(crate) fn prepare_component_remove(
         &mut self,
-        mut entity: Entity,
+        local_entity: Entity,
         kind: ComponentNetId,
+        component_kind: ComponentKind,
         group: &ReplicationGroup,
         target: NetworkTarget,
     ) -> Result<(), ServerError> {
-        let group_id = group.group_id(Some(entity));
-        debug!(?entity, ?kind, "Sending RemoveComponent");
+        let group_id = group.group_id(Some(local_entity));
+        debug!(?local_entity, ?kind, "Sending RemoveComponent");
         self.connected_targets(target).try_for_each(|client_id| {
-            entity = self
-                .connection_mut(client_id)?
+            let connection = self.connection_mut(client_id)?;
+            connection
+                .world_view
+                .remove_component(local_entity, component_kind);
+            let entity = connection
                 .replication_receiver
                 .remote_entity_map
-                .to_remote(entity);
+                .to_remote(local_entity);
             // TODO: I don't think it's actually correct to only correct the changes since that action.
             //  what if we do:
             //  - Frame 1: update is ACKED
@@ -876,7 +2120,7 @@ impl ConnectionManager {
             //  - Frame 3: action
             //  - Frame 4: send
             //  then we won't send the frame-2 update because we only collect changes since frame 3
-            self.connection_mut(client_id)?
+            connection
                 .replication_sender
                 .prepare_component_remove(entity, group_id, kind);
             Ok(())
@@ -937,9 +2181,12 @@ impl ConnectionManager {
             );
         }
 
-        // there is no entity mapping, so we can serialize the component once for all clients
+        // there is no entity mapping and no per-connection context, so we can serialize the component once
+        // for all clients
         let mut raw_data: Option<Bytes> = None;
-        if !component_registry.erased_is_map_entities(kind) {
+        let per_client = component_registry.erased_is_map_entities(kind)
+            || component_registry.erased_is_context_dependent(kind);
+        if !per_client {
             if delta_compression {
                 // SAFETY: the component_data corresponds to the kind
                 unsafe {
@@ -960,6 +2207,7 @@ impl ConnectionManager {
             };
             raw_data = Some(self.writer.split());
         }
+        let local_entity = entity;
         self.connected_targets(actual_target)
             .try_for_each(|client_id| {
                 // convert the entity to a network entity (in case we need to map it)
@@ -972,7 +2220,9 @@ impl ConnectionManager {
                 // there is entity mapping, so we might need to serialize the component differently for each client
                 // (although most of the time there is not mapping done on the send side)
                 // It would be nice if we could check ahead of time if there is any mapping that needs to be done
-                if raw_data.is_none() {
+                if per_client {
+                    let context = self.connection(client_id)?.serialization_context.clone();
+                    self.writer.set_context(context);
                     if delta_compression {
                         // SAFETY: the component_data corresponds to the kind
                         unsafe {
@@ -1026,9 +2276,17 @@ impl ConnectionManager {
                 //     .entry(group)
                 //     .or_default()
                 //     .update_collect_changes_since_this_tick(system_current_tick);
-                self.connection_mut(client_id)?
+                let raw_data = raw_data.clone().unwrap();
+                let connection = self.connection_mut(client_id)?;
+                connection.world_view.write_component(
+                    local_entity,
+                    kind,
+                    tick,
+                    (!delta_compression).then(|| raw_data.clone()),
+                );
+                connection
                     .replication_sender
-                    .prepare_component_insert(entity, group_id, raw_data.clone().unwrap());
+                    .prepare_component_insert(entity, group_id, raw_data);
                 Ok(())
             })
     }
@@ -1051,6 +2309,10 @@ impl ConnectionManager {
         let mut existing_bytes: Option<Bytes> = None;
         self.connected_targets(target).try_for_each(|client_id| {
             let connection = self.connections.get_mut(&client_id).ok_or(ServerError::ClientIdNotFound(client_id))?;
+            // the client doesn't render fast enough to need this update, it will be included in the next one
+            if !connection.pacing.is_due(connection.current_time) {
+                return Ok(());
+            }
             let send_tick = connection
                 .replication_sender
                 .group_channels
@@ -1079,16 +2341,22 @@ impl ConnectionManager {
 
 
                 if delta_compression {
+                    connection.world_view.write_component(entity, kind, tick, None);
                     connection.replication_sender.prepare_delta_component_update(entity, group_id, kind, component, registry, &mut self.writer, &mut self.delta_manager, tick, &mut connection.replication_receiver.remote_entity_map)?;
                 } else {
                     // we serialize once and re-use the result for all clients
                     // serialize only if there is at least one client that needs the update
-                    if existing_bytes.is_none() || registry.erased_is_map_entities(kind) {
+                    let context_dependent = registry.erased_is_context_dependent(kind);
+                    if existing_bytes.is_none() || registry.erased_is_map_entities(kind) || context_dependent {
+                        if context_dependent {
+                            self.writer.set_context(connection.serialization_context.clone());
+                        }
                         registry.erased_serialize(component, &mut self.writer, kind, Some(&mut connection.replication_receiver.remote_entity_map.local_to_remote))?;
                         // we re-serialize every time if there is entity mapping
                         existing_bytes = Some(self.writer.split());
                     }
                     let raw_data = existing_bytes.clone().unwrap();
+                    connection.world_view.write_component(entity, kind, tick, Some(raw_data.clone()));
                     // use the network entity
                     let entity = connection
                         .replication_receiver
@@ -1178,3 +2446,222 @@ impl ReplicationSend for ConnectionManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{EventReader, ResMut, State, Update};
+
+    use crate::client::networking::NetworkingState;
+    use crate::prelude::client;
+    use crate::tests::protocol::{ReliableChannel, StringMessage};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct ReceivedStrings(Vec<String>);
+
+    #[test]
+    fn test_disconnect_after_flush() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<ReceivedStrings>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<client::MessageEvent<StringMessage>>,
+             mut received: ResMut<ReceivedStrings>| {
+                received
+                    .0
+                    .extend(events.read().map(|event| event.message().0.clone()));
+            },
+        );
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let mut manager = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>();
+        for message in ["banned", "final rewards"] {
+            manager
+                .send_message::<ReliableChannel, _>(
+                    client_id,
+                    &mut StringMessage(message.to_string()),
+                )
+                .unwrap();
+        }
+        manager
+            .disconnect_after_flush(client_id, Duration::from_secs(1))
+            .unwrap();
+
+        // the client is not disconnected before the messages are acknowledged
+        stepper.frame_step();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .is_ok());
+
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<ReceivedStrings>().0,
+            vec!["banned", "final rewards"]
+        );
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .is_err());
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+    }
+
+    #[test]
+    fn test_last_heard() {
+        let mut stepper = BevyStepper::default();
+        let connection = stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(ClientId::Netcode(TEST_CLIENT_ID))
+            .unwrap();
+        let connected_at = connection.connected_at();
+        assert!(connection.last_heard() <= stepper.frame_duration);
+
+        // the client stops sending packets
+        for _ in 0..10 {
+            stepper.advance_time(stepper.frame_duration);
+            stepper.server_app.update();
+        }
+        let connection = stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(ClientId::Netcode(TEST_CLIENT_ID))
+            .unwrap();
+        assert!(connection.last_heard() >= stepper.frame_duration * 9);
+        assert_eq!(connection.connected_at(), connected_at);
+        assert!(connection.connection_age() >= stepper.frame_duration * 10);
+    }
+
+    #[test]
+    fn test_clear_max_outgoing_bandwidth() {
+        let mut stepper = BevyStepper::default();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let mut manager = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>();
+        manager
+            .set_max_outgoing_bandwidth(client_id, NonZeroU32::new(1000).unwrap())
+            .unwrap();
+        assert!(manager
+            .connection(client_id)
+            .unwrap()
+            .replication_sender
+            .is_bandwidth_cap_enabled());
+
+        // without any other quota, the replication updates are considered sent once buffered again
+        manager.clear_max_outgoing_bandwidth(client_id).unwrap();
+        assert!(!manager
+            .connection(client_id)
+            .unwrap()
+            .replication_sender
+            .is_bandwidth_cap_enabled());
+    }
+
+    #[test]
+    fn test_message_delivery() {
+        #[derive(Resource, Default)]
+        struct Delivered(Vec<MessageId>);
+
+        let mut stepper = BevyStepper::default();
+        stepper.server_app.init_resource::<Delivered>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<MessageDeliveredEvent>, mut delivered: ResMut<Delivered>| {
+                delivered
+                    .0
+                    .extend(events.read().map(|event| event.message_id));
+            },
+        );
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let message_id = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .send_message_with_id::<ReliableChannel, _>(
+                client_id,
+                &mut StringMessage("trade".to_string()),
+            )
+            .unwrap()
+            .unwrap();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.server_app.world().resource::<Delivered>().0,
+            vec![message_id]
+        );
+
+        // the acks of the messages whose delivery is not tracked are not kept
+        for _ in 0..5 {
+            stepper
+                .server_app
+                .world_mut()
+                .resource_mut::<ConnectionManager>()
+                .send_message::<ReliableChannel, _>(
+                    client_id,
+                    &mut StringMessage("chat".to_string()),
+                )
+                .unwrap();
+            stepper.frame_step();
+        }
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let connection = stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .unwrap();
+        let (acks, _) = &connection.delivery.receivers[&ChannelKind::of::<ReliableChannel>()];
+        assert!(acks.is_empty());
+        assert_eq!(
+            stepper.server_app.world().resource::<Delivered>().0.len(),
+            1
+        );
+
+        // the messages that are not acknowledged when the client disconnects are dropped
+        let mut manager = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>();
+        let message_id = manager
+            .send_message_with_id::<ReliableChannel, _>(
+                client_id,
+                &mut StringMessage("offer".to_string()),
+            )
+            .unwrap()
+            .unwrap();
+        manager.remove(client_id).unwrap();
+        let (delivered, dropped) = manager.poll_deliveries();
+        assert!(delivered.is_empty());
+        assert_eq!(
+            dropped,
+            vec![MessageDroppedEvent {
+                client_id,
+                channel: ChannelKind::of::<ReliableChannel>(),
+                message_id,
+            }]
+        );
+    }
+}
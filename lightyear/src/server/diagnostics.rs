@@ -0,0 +1,147 @@
+//! Measure how much of the network budget the server uses on each tick.
+//!
+//! The [`FrameBudgetReport`] resource is updated every time the server sends packets. It can be read directly,
+//! or through the bevy [`Diagnostics`] registered by the [`ServerDiagnosticsPlugin`], to plan the capacity of a
+//! server (how many players fit in one instance) from measured data.
+use bevy::app::{App, Plugin, PostUpdate};
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::{IntoSystemConfigs, Res, Resource};
+use bevy::utils::HashMap;
+
+use crate::connection::server::TransportKind;
+use crate::prelude::Tick;
+use crate::server::networking::send;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+/// Network budget used by the server during the last tick where packets were sent
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct FrameBudgetReport {
+    /// Tick at which the packets were sent
+    pub tick: Tick,
+    /// Number of entities that had replication data to send, summed over all the connections
+    pub entities_considered: usize,
+    /// Number of entities whose replication data was included in a packet, summed over all the connections.
+    ///
+    /// Entity actions are sent reliably, so they always count as sent.
+    pub entities_sent: usize,
+    /// Bytes sent, for each kind of transport
+    pub bytes_sent: HashMap<TransportKind, usize>,
+    /// Number of connections that could not send all their messages because the bandwidth cap was reached
+    pub connections_over_budget: usize,
+    /// Number of connections that sent all their messages
+    pub connections_within_budget: usize,
+}
+
+impl FrameBudgetReport {
+    /// Total number of bytes sent over all transports
+    pub fn total_bytes_sent(&self) -> usize {
+        self.bytes_sent.values().sum()
+    }
+}
+
+/// Plugin that maintains the [`FrameBudgetReport`] and registers the corresponding [`Diagnostics`]
+pub struct ServerDiagnosticsPlugin {
+    pub history_len: usize,
+}
+
+impl Default for ServerDiagnosticsPlugin {
+    fn default() -> Self {
+        Self { history_len: 60 }
+    }
+}
+
+impl ServerDiagnosticsPlugin {
+    /// Number of entities that had replication data to send
+    pub const ENTITIES_CONSIDERED: DiagnosticPath =
+        DiagnosticPath::const_new("replication.entities_considered");
+
+    /// Number of entities whose replication data was sent
+    pub const ENTITIES_SENT: DiagnosticPath =
+        DiagnosticPath::const_new("replication.entities_sent");
+
+    /// Bytes sent per tick over all transports
+    pub const BYTES_SENT: DiagnosticPath = DiagnosticPath::const_new("send.bytes_per_tick");
+
+    /// Number of connections that reached their bandwidth cap
+    pub const CONNECTIONS_OVER_BUDGET: DiagnosticPath =
+        DiagnosticPath::const_new("send.connections_over_budget");
+
+    fn add_measurements(report: Res<FrameBudgetReport>, mut diagnostics: Diagnostics) {
+        diagnostics.add_measurement(&Self::ENTITIES_CONSIDERED, || {
+            report.entities_considered as f64
+        });
+        diagnostics.add_measurement(&Self::ENTITIES_SENT, || report.entities_sent as f64);
+        diagnostics.add_measurement(&Self::BYTES_SENT, || report.total_bytes_sent() as f64);
+        diagnostics.add_measurement(&Self::CONNECTIONS_OVER_BUDGET, || {
+            report.connections_over_budget as f64
+        });
+    }
+}
+
+impl Plugin for ServerDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameBudgetReport>();
+        for path in [
+            Self::ENTITIES_CONSIDERED,
+            Self::ENTITIES_SENT,
+            Self::CONNECTIONS_OVER_BUDGET,
+        ] {
+            app.register_diagnostic(
+                Diagnostic::new(path)
+                    .with_suffix("")
+                    .with_max_history_length(self.history_len),
+            );
+        }
+        app.register_diagnostic(
+            Diagnostic::new(Self::BYTES_SENT)
+                .with_suffix("B")
+                .with_max_history_length(self.history_len),
+        );
+        app.add_systems(
+            PostUpdate,
+            Self::add_measurements
+                .after(send)
+                .in_set(InternalMainSet::<ServerMarker>::Send),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::Replicate;
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_frame_budget_report() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(1.0), Replicate::default()));
+        stepper.frame_step();
+
+        let report = stepper.server_app.world().resource::<FrameBudgetReport>();
+        assert_eq!(report.entities_considered, 1);
+        assert_eq!(report.entities_sent, 1);
+        assert!(report.bytes_sent[&TransportKind::Channels] > 0);
+        assert_eq!(report.connections_within_budget, 1);
+        assert_eq!(report.connections_over_budget, 0);
+
+        // nothing to replicate on the next tick
+        stepper.frame_step();
+        let report = stepper.server_app.world().resource::<FrameBudgetReport>();
+        assert_eq!(report.entities_considered, 0);
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<bevy::diagnostic::DiagnosticsStore>()
+                .get(&ServerDiagnosticsPlugin::ENTITIES_CONSIDERED)
+                .and_then(|diagnostic| diagnostic.value()),
+            Some(0.0)
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Detect entities whose replicated state has diverged from the server's world.
+//!
+//! The server keeps a record of what was replicated to each client (see [`ClientWorldView`](crate::server::world_view::ClientWorldView)).
+//! Because of bugs (for example a stuck channel or a missing ack), this record can drift away from the
+//! server's world without any error being reported.
+//!
+//! The [`DivergencePlugin`] periodically compares the per-client records with the server's world, and emits a
+//! [`DivergenceEvent`] for each entity that has been diverging for longer than the configured threshold:
+//! - the entity is still replicated to the client but doesn't exist on the server anymore
+//! - a component is still replicated to the client but was removed on the server
+//! - updates are being sent to the client for the entity's replication group but none of them have been acked
+//!
+//! The plugin is not part of the [`ServerPlugins`](crate::prelude::server::ServerPlugins) and must be added manually.
+//! It enables [`ServerConfig::client_world_view`](crate::prelude::server::ServerConfig::client_world_view), which it relies on:
+//! ```rust,ignore
+//! app.add_plugins(DivergencePlugin {
+//!     check_interval: Duration::from_secs(1),
+//!     threshold: Duration::from_secs(5),
+//! });
+//! ```
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_timer;
+use bevy::utils::{Duration, HashMap};
+use tracing::trace;
+
+use crate::prelude::server::{is_started, ConnectionManager, ServerConfig};
+use crate::prelude::{ClientId, ReplicationGroup, Tick, TimeManager};
+use crate::protocol::component::ComponentKind;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+use crate::shared::time_manager::WrappedTime;
+
+/// Plugin that periodically checks if the state replicated to each client has diverged from the server's world
+#[derive(Debug, Clone)]
+pub struct DivergencePlugin {
+    /// How often we compare the replicated state with the server's world
+    pub check_interval: Duration,
+    /// How long an entity must be diverging before a [`DivergenceEvent`] is emitted
+    pub threshold: Duration,
+}
+
+impl Default for DivergencePlugin {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(1),
+            threshold: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How the replicated state of an entity diverged from the server's world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DivergenceKind {
+    /// The entity is replicated to the client but doesn't exist on the server anymore
+    Despawned,
+    /// The component is replicated to the client but isn't present on the server's entity anymore
+    ComponentRemoved(ComponentKind),
+    /// Updates were sent for the entity's replication group but none of them have been acked
+    Unacked {
+        /// The last tick for which an update of the group was acked
+        last_acked: Option<Tick>,
+    },
+}
+
+/// Event emitted on the server when the state replicated to a client has been diverging
+/// from the server's world for longer than [`DivergencePlugin::threshold`].
+///
+/// The event is emitted again on every check as long as the divergence persists.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct DivergenceEvent {
+    pub client_id: ClientId,
+    /// The server entity
+    pub entity: Entity,
+    pub kind: DivergenceKind,
+    /// How long the entity has been diverging
+    pub diverged_for: Duration,
+}
+
+#[derive(Resource, Debug, Default)]
+struct DivergenceDetector {
+    threshold: Duration,
+    diverging: HashMap<(ClientId, Entity, DivergenceKind), WrappedTime>,
+}
+
+impl Plugin for DivergencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DivergenceEvent>();
+        app.insert_resource(DivergenceDetector {
+            threshold: self.threshold,
+            ..default()
+        });
+        app.add_systems(
+            PostUpdate,
+            detect_divergence
+                .after(InternalMainSet::<ServerMarker>::Send)
+                .run_if(is_started.and_then(on_timer(self.check_interval))),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        // the per-client records are only kept if the view is enabled
+        if let Some(mut config) = app.world_mut().get_resource_mut::<ServerConfig>() {
+            config.client_world_view = true;
+        }
+    }
+}
+
+fn detect_divergence(world: &mut World) {
+    world.resource_scope(|world, mut detector: Mut<DivergenceDetector>| {
+        let now = world.resource::<TimeManager>().current_time();
+        let manager = world.resource::<ConnectionManager>();
+        let mut observed = Vec::new();
+        for (client_id, connection) in manager.connections.iter() {
+            for (entity, view) in connection.world_view.entities() {
+                let Some(entity_ref) = world.get_entity(entity) else {
+                    observed.push((*client_id, entity, DivergenceKind::Despawned));
+                    continue;
+                };
+                for kind in view.components.keys() {
+                    let present = world
+                        .components()
+                        .get_id(kind.0)
+                        .is_some_and(|id| entity_ref.contains_id(id));
+                    if !present {
+                        observed.push((
+                            *client_id,
+                            entity,
+                            DivergenceKind::ComponentRemoved(*kind),
+                        ));
+                    }
+                }
+                if let Some(group) = entity_ref.get::<ReplicationGroup>() {
+                    let group_id = group.group_id(Some(entity));
+                    let sender = &connection.replication_sender;
+                    if sender.has_pending_updates(group_id) {
+                        let last_acked = sender
+                            .group_channels
+                            .get(&group_id)
+                            .and_then(|channel| channel.ack_tick);
+                        observed.push((*client_id, entity, DivergenceKind::Unacked { last_acked }));
+                    }
+                }
+            }
+        }
+
+        // forget about the divergences that have been resolved. For unacked updates, the key
+        // contains the last acked tick so receiving a new ack also resets the divergence
+        let mut diverging = HashMap::default();
+        for key in observed {
+            let since = detector.diverging.get(&key).copied().unwrap_or(now);
+            diverging.insert(key, since);
+        }
+        detector.diverging = diverging;
+
+        let threshold = detector.threshold;
+        let events: Vec<_> = detector
+            .diverging
+            .iter()
+            .filter_map(|((client_id, entity, kind), since)| {
+                let diverged_for = now.to_duration().saturating_sub(since.to_duration());
+                (diverged_for >= threshold).then_some(DivergenceEvent {
+                    client_id: *client_id,
+                    entity: *entity,
+                    kind: *kind,
+                    diverged_for,
+                })
+            })
+            .collect();
+        for event in events {
+            trace!(?event, "Replicated state diverged from the server's world");
+            world.send_event(event);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::server::Replicate;
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Divergences(Vec<DivergenceEvent>);
+
+    fn setup() -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), frame_duration);
+        stepper.server_app.add_plugins(DivergencePlugin {
+            check_interval: Duration::default(),
+            threshold: Duration::from_millis(100),
+        });
+        stepper.server_app.init_resource::<Divergences>();
+        stepper.server_app.add_systems(
+            Last,
+            |mut events: EventReader<DivergenceEvent>, mut divergences: ResMut<Divergences>| {
+                divergences.0.extend(events.read().cloned());
+            },
+        );
+        stepper.init();
+        stepper
+    }
+
+    #[test]
+    fn test_no_divergence() {
+        let mut stepper = setup();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(0.0)))
+            .id();
+        for i in 0..30 {
+            stepper
+                .server_app
+                .world_mut()
+                .entity_mut(server_entity)
+                .insert(ComponentSyncModeFull(i as f32));
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<Divergences>()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn test_despawned_divergence() {
+        let mut stepper = setup();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        // simulate a bug where the despawn was never replicated to the client
+        let ghost = stepper.server_app.world_mut().spawn_empty().id();
+        stepper.server_app.world_mut().despawn(ghost);
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .connection_mut(client_id)
+            .unwrap()
+            .world_view
+            .spawn(ghost);
+
+        // below the threshold, nothing is reported
+        stepper.frame_step();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<Divergences>()
+            .0
+            .is_empty());
+
+        for _ in 0..15 {
+            stepper.frame_step();
+        }
+        let divergences = &stepper.server_app.world().resource::<Divergences>().0;
+        assert!(!divergences.is_empty());
+        assert!(divergences.iter().all(|event| event.client_id == client_id
+            && event.entity == ghost
+            && event.kind == DivergenceKind::Despawned
+            && event.diverged_for >= Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,238 @@
+//! Report the errors that happen on the server as events.
+//!
+//! The errors (malformed packets, io failures, packets from unknown clients, etc.) are emitted as
+//! [`ServerErrorEvent`]s, which are separate from the connection and message events. The stream is rate-limited
+//! by the [`ErrorEventConfig`] so that a flood of malformed packets cannot produce an unbounded number of events:
+//! when more errors are reported than the quota allows, the most severe ones are emitted first and the others are
+//! dropped. The number of dropped errors is available with [`ServerErrors::dropped`].
+use bevy::prelude::*;
+use governor::{DefaultDirectRateLimiter, Quota};
+use nonzero_ext::nonzero;
+use tracing::debug;
+
+use crate::connection::id::ClientId;
+use crate::server::config::ServerConfig;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+/// Maximum number of errors that can be waiting to be emitted. Past this, only the critical errors are kept
+const MAX_PENDING_ERRORS: usize = 256;
+
+/// Severity of an error reported by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum ErrorSeverity {
+    /// The server ignored some invalid input, for example a malformed packet
+    Warning,
+    /// An operation failed, for example sending packets to a client
+    Error,
+    /// The server cannot keep running normally, for example because its io failed
+    Critical,
+}
+
+/// Bevy [`Event`] emitted on the server when an error happens
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ServerErrorEvent {
+    pub severity: ErrorSeverity,
+    /// The client that caused the error, if any
+    pub client_id: Option<ClientId>,
+    pub error: String,
+}
+
+/// Configuration of the [`ServerErrorEvent`] stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorEventConfig {
+    /// Maximum rate at which the errors are emitted
+    pub quota: Quota,
+    /// The errors that are less severe than this are not emitted
+    pub min_severity: ErrorSeverity,
+}
+
+impl Default for ErrorEventConfig {
+    fn default() -> Self {
+        Self {
+            quota: Quota::per_second(nonzero!(20u32)),
+            min_severity: ErrorSeverity::Warning,
+        }
+    }
+}
+
+impl ErrorEventConfig {
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn with_min_severity(mut self, min_severity: ErrorSeverity) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+}
+
+/// Resource that buffers the errors reported during the frame until they are emitted as [`ServerErrorEvent`]s
+#[derive(Resource, Default)]
+pub struct ServerErrors {
+    pending: Vec<ServerErrorEvent>,
+    limiter: Option<(Quota, DefaultDirectRateLimiter)>,
+    dropped: u64,
+}
+
+impl std::fmt::Debug for ServerErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerErrors")
+            .field("pending", &self.pending)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+impl ServerErrors {
+    /// Report an error, which will be emitted as a [`ServerErrorEvent`] if the rate limit allows it
+    pub fn report(
+        &mut self,
+        severity: ErrorSeverity,
+        client_id: Option<ClientId>,
+        error: impl ToString,
+    ) {
+        if self.pending.len() >= MAX_PENDING_ERRORS && severity < ErrorSeverity::Critical {
+            self.dropped += 1;
+            return;
+        }
+        self.pending.push(ServerErrorEvent {
+            severity,
+            client_id,
+            error: error.to_string(),
+        });
+    }
+
+    /// Total number of errors that were not emitted because of the rate limit
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Emit the pending errors, from the most severe to the least severe, until the quota is reached
+    fn flush(&mut self, config: &ErrorEventConfig, events: &mut EventWriter<ServerErrorEvent>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if self
+            .limiter
+            .as_ref()
+            .map_or(true, |(quota, _)| *quota != config.quota)
+        {
+            self.limiter = Some((config.quota, DefaultDirectRateLimiter::direct(config.quota)));
+        }
+        let (_, limiter) = self.limiter.as_ref().unwrap();
+        // the sort is stable so the errors of the same severity keep their order
+        self.pending
+            .sort_by_key(|event| std::cmp::Reverse(event.severity));
+        let mut dropped = 0;
+        for event in self.pending.drain(..) {
+            if event.severity < config.min_severity {
+                continue;
+            }
+            if limiter.check().is_ok() {
+                events.send(event);
+            } else {
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            debug!(
+                ?dropped,
+                "Too many errors reported, some error events were dropped"
+            );
+            self.dropped += dropped;
+        }
+    }
+}
+
+pub(crate) struct ServerErrorsPlugin;
+
+impl Plugin for ServerErrorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ErrorSeverity>();
+        app.init_resource::<ServerErrors>();
+        app.add_event::<ServerErrorEvent>();
+        // the errors are emitted after the packets are received, and after they are sent
+        app.add_systems(
+            PreUpdate,
+            flush_errors.after(InternalMainSet::<ServerMarker>::EmitEvents),
+        );
+        app.add_systems(
+            PostUpdate,
+            flush_errors.after(InternalMainSet::<ServerMarker>::Send),
+        );
+    }
+}
+
+fn flush_errors(
+    config: Res<ServerConfig>,
+    mut errors: ResMut<ServerErrors>,
+    mut events: EventWriter<ServerErrorEvent>,
+) {
+    errors.flush(&config.error_events, &mut events);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<ServerErrorEvent>);
+
+    #[test]
+    fn test_error_events_are_rate_limited_by_severity() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .error_events = ErrorEventConfig::default()
+            .with_quota(Quota::per_hour(nonzero!(2u32)))
+            .with_min_severity(ErrorSeverity::Error);
+        stepper.server_app.init_resource::<Received>();
+        stepper.server_app.add_systems(
+            Last,
+            |mut events: EventReader<ServerErrorEvent>, mut received: ResMut<Received>| {
+                received.0.extend(events.read().cloned());
+            },
+        );
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let mut errors = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerErrors>();
+        for i in 0..3 {
+            errors.report(ErrorSeverity::Error, Some(client_id), format!("error {i}"));
+        }
+        errors.report(ErrorSeverity::Warning, Some(client_id), "warning");
+        errors.report(ErrorSeverity::Critical, None, "critical");
+        stepper.frame_step();
+
+        // the critical error goes first, the warning is filtered out
+        assert_eq!(
+            stepper.server_app.world().resource::<Received>().0,
+            vec![
+                ServerErrorEvent {
+                    severity: ErrorSeverity::Critical,
+                    client_id: None,
+                    error: "critical".to_string(),
+                },
+                ServerErrorEvent {
+                    severity: ErrorSeverity::Error,
+                    client_id: Some(client_id),
+                    error: "error 0".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ServerErrors>()
+                .dropped(),
+            2
+        );
+    }
+}
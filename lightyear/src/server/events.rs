@@ -2,17 +2,24 @@
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::*;
 use bevy::utils::HashMap;
+use std::net::{IpAddr, SocketAddr};
 
 use crate::connection::id::ClientId;
+use crate::connection::netcode::{DuplicateLoginPolicy, SuspicionAction, USER_DATA_BYTES};
+use crate::packet::message::MessageId;
 use crate::prelude::ComponentRegistry;
+use crate::protocol::channel::ChannelKind;
 use crate::server::connection::ConnectionManager;
+use crate::shared::events::components::EventStamp;
 use crate::shared::events::connection::{
     ConnectionEvents, IterComponentInsertEvent, IterComponentRemoveEvent, IterComponentUpdateEvent,
-    IterEntityDespawnEvent, IterEntitySpawnEvent,
+    IterEntityDespawnEvent, IterEntitySpawnEvent, IterReplicationAppliedEvent,
 };
 use crate::shared::events::plugin::EventsPlugin;
 use crate::shared::events::systems::push_component_events;
 use crate::shared::sets::{InternalMainSet, ServerMarker};
+use crate::shared::tick_manager::{Tick, TickManager};
+use crate::shared::time_manager::TimeManager;
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
 
@@ -26,13 +33,27 @@ impl Plugin for ServerEventsPlugin {
             // EVENTS
             .add_event::<ConnectEvent>()
             .add_event::<DisconnectEvent>()
+            .add_event::<HandshakeThrottledEvent>()
+            .add_event::<ProtocolMismatchEvent>()
+            .add_event::<SuspiciousActivityEvent>()
+            .add_event::<SendErrorEvent>()
+            .add_event::<DuplicateLoginEvent>()
+            .add_event::<AuthRequestEvent>()
+            .add_event::<MessageDeliveredEvent>()
+            .add_event::<MessageDroppedEvent>()
+            .add_event::<RawDatagramEvent>()
             // PLUGIN
             .add_plugins(EventsPlugin::<ConnectionManager>::default())
             // SYSTEMS
             .add_systems(
                 PreUpdate,
                 // TODO: check if this should be between Receive and EmitEvents
-                emit_connect_events.in_set(InternalMainSet::<ServerMarker>::EmitEvents),
+                (
+                    emit_connect_events,
+                    emit_delivery_events,
+                    emit_raw_datagram_events,
+                )
+                    .in_set(InternalMainSet::<ServerMarker>::EmitEvents),
             );
     }
 }
@@ -43,12 +64,16 @@ fn emit_connect_events(
     mut connect_events: EventWriter<ConnectEvent>,
     mut disconnect_events: EventWriter<DisconnectEvent>,
     mut connection_manager: ResMut<ConnectionManager>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
 ) {
     // EVENTS: Write the received events into bevy events
     if !connection_manager.events.is_empty() {
+        let stamp = Some(EventStamp::now(&tick_manager, &time_manager));
         // Connection / Disconnection events
         if connection_manager.events.has_connections() {
-            for connect_event in connection_manager.events.iter_connections() {
+            for mut connect_event in connection_manager.events.iter_connections() {
+                connect_event.stamp = stamp;
                 debug!("Client connected event: {}", connect_event.client_id);
                 connect_events.send(connect_event);
                 // TODO: trigger all events in batch? https://github.com/bevyengine/bevy/pull/13953
@@ -60,7 +85,8 @@ fn emit_connect_events(
         }
 
         if connection_manager.events.has_disconnections() {
-            for disconnect_event in connection_manager.events.iter_disconnections() {
+            for mut disconnect_event in connection_manager.events.iter_disconnections() {
+                disconnect_event.stamp = stamp;
                 debug!("Client disconnected event: {}", disconnect_event.client_id);
                 disconnect_events.send(disconnect_event);
                 // TODO: trigger all events in batch? https://github.com/bevyengine/bevy/pull/13953
@@ -188,6 +214,16 @@ impl IterEntitySpawnEvent<ClientId> for ServerEvents {
     }
 }
 
+impl IterReplicationAppliedEvent<ClientId> for ServerEvents {
+    fn iter_replication_applied(&mut self) -> Box<dyn Iterator<Item = (Tick, ClientId)> + '_> {
+        Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
+            let ticks = events.iter_replication_applied().map(|(tick, _)| tick);
+            let client_ids = std::iter::once(*client_id).cycle();
+            ticks.zip(client_ids)
+        }))
+    }
+}
+
 impl IterEntityDespawnEvent<ClientId> for ServerEvents {
     fn into_iter_entity_despawn(&mut self) -> Box<dyn Iterator<Item = (Entity, ClientId)> + '_> {
         Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
@@ -254,6 +290,41 @@ impl IterComponentInsertEvent<ClientId> for ServerEvents {
 pub struct ConnectEvent {
     pub client_id: ClientId,
     pub entity: Entity,
+    pub(crate) stamp: Option<EventStamp>,
+}
+
+impl ConnectEvent {
+    /// Tick and time at which the client was connected
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+}
+
+/// Emit the delivery status of the messages sent with [`ConnectionManager::send_message_with_id`]
+fn emit_delivery_events(
+    mut delivered_events: EventWriter<MessageDeliveredEvent>,
+    mut dropped_events: EventWriter<MessageDroppedEvent>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    let (delivered, dropped) = connection_manager.poll_deliveries();
+    delivered_events.send_batch(delivered);
+    dropped_events.send_batch(dropped);
+}
+
+/// Emit the raw datagrams received from the clients
+fn emit_raw_datagram_events(
+    mut events: EventWriter<RawDatagramEvent>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    for (client_id, connection) in connection_manager.connections.iter_mut() {
+        events.send_batch(connection.message_manager.received_raw.drain(..).map(
+            |(kind, payload)| RawDatagramEvent {
+                kind,
+                payload,
+                context: *client_id,
+            },
+        ));
+    }
 }
 
 /// Bevy [`Event`] emitted on the server on the frame where a client is disconnected
@@ -261,14 +332,140 @@ pub struct ConnectEvent {
 pub struct DisconnectEvent {
     pub client_id: ClientId,
     pub entity: Entity,
+    pub(crate) stamp: Option<EventStamp>,
+}
+
+impl DisconnectEvent {
+    /// Tick and time at which the client was disconnected
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+}
+
+/// Bevy [`Event`] emitted on the server on the frame where the handshake packets of an IP address
+/// start being dropped because of the [`HandshakeRateLimit`](crate::connection::netcode::HandshakeRateLimit)
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct HandshakeThrottledEvent {
+    pub addr: IpAddr,
+}
+
+/// Bevy [`Event`] emitted on the server when an IP address sent enough malformed packets to reach the
+/// [`SuspicionThreshold`](crate::connection::netcode::SuspicionThreshold)
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct SuspiciousActivityEvent {
+    pub addr: IpAddr,
+    /// Number of malformed packets received from the address during the window
+    pub malformed_packets: u32,
+    /// Action that was taken against the address
+    pub action: SuspicionAction,
+}
+
+/// Bevy [`Event`] emitted on the server when a packet could not be sent to a client.
+///
+/// The other packets of the client are still sent, so it's up to the user to disconnect the clients
+/// whose sends keep failing.
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct SendErrorEvent {
+    pub client_id: ClientId,
+    /// Address of the client
+    pub addr: SocketAddr,
+    /// Kind of packet that could not be sent
+    pub packet: SentPacketKind,
+    /// Kind of the io error returned by the transport, or [`std::io::ErrorKind::Other`]
+    pub error: std::io::ErrorKind,
+}
+
+/// Kind of packet that the server could not send to a client
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SentPacketKind {
+    /// The connection request of the client was denied
+    Denied,
+    /// Challenge of the handshake
+    Challenge,
+    KeepAlive,
+    Payload,
+    Disconnect,
+}
+
+/// Bevy [`Event`] emitted on the server when a client acknowledged a message sent with
+/// [`ConnectionManager::send_message_with_id`]
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct MessageDeliveredEvent {
+    pub client_id: ClientId,
+    pub channel: ChannelKind,
+    /// Id returned by [`ConnectionManager::send_message_with_id`]
+    pub message_id: MessageId,
 }
 
+/// Bevy [`Event`] emitted on the server when a message sent with [`ConnectionManager::send_message_with_id`] will
+/// never be acknowledged: the client disconnected before acknowledging it, or the message was lost on a
+/// channel that doesn't retransmit messages.
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct MessageDroppedEvent {
+    pub client_id: ClientId,
+    pub channel: ChannelKind,
+    /// Id returned by [`ConnectionManager::send_message_with_id`]
+    pub message_id: MessageId,
+}
+
+/// Bevy [`Event`] emitted on the server when a client connects with the id of a client that is already
+/// connected from another address.
+///
+/// The [`DuplicateLoginPolicy`] decides which of the two clients stays connected.
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct DuplicateLoginEvent {
+    pub client_id: ClientId,
+    /// Address of the client that was already connected
+    pub existing_addr: SocketAddr,
+    /// Address of the new client
+    pub new_addr: SocketAddr,
+    /// Policy that was applied
+    pub policy: DuplicateLoginPolicy,
+}
+
+/// Bevy [`Event`] emitted on the server when the connection request of a client is denied because it uses
+/// a [different protocol](crate::protocol::hash) than the server
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct ProtocolMismatchEvent {
+    pub client_id: ClientId,
+    /// Hash of the protocol of the client, or None if the client didn't send one
+    pub protocol_hash: Option<u64>,
+}
+
+/// Bevy [`Event`] emitted on the server on the frame where a client completes the handshake, if
+/// [authentication](crate::prelude::server::AuthConfig) is enabled.
+///
+/// The client must then be accepted with
+/// [`ServerConnections::accept_connection`](crate::connection::server::ServerConnections::accept_connection)
+/// or rejected with [`ServerConnections::reject_connection`](crate::connection::server::ServerConnections::reject_connection).
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct AuthRequestEvent {
+    pub client_id: ClientId,
+    /// User data of the connect token that the client used to connect, which can be validated by the
+    /// authentication backend. None if the connection type does not use connect tokens (for example steam)
+    pub user_data: Option<[u8; USER_DATA_BYTES]>,
+    pub(crate) stamp: Option<EventStamp>,
+}
+
+impl AuthRequestEvent {
+    /// Tick and time at which the client completed the handshake
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+}
+
+/// Bevy [`Event`] emitted on the server on the frame where a [raw datagram](crate::packet::raw) from a client
+/// is received
+pub type RawDatagramEvent = crate::shared::events::components::RawDatagramEvent<ClientId>;
 /// Bevy [`Event`] emitted on the server on the frame where an input message from a client is received
 pub type InputEvent<I> = crate::shared::events::components::InputEvent<I, ClientId>;
 /// Bevy [`Event`] emitted on the server on the frame where a EntitySpawn replication message is received
 pub type EntitySpawnEvent = crate::shared::events::components::EntitySpawnEvent<ClientId>;
 /// Bevy [`Event`] emitted on the server on the frame where a EntityDepawn replication message is received
 pub type EntityDespawnEvent = crate::shared::events::components::EntityDespawnEvent<ClientId>;
+/// Bevy [`Event`] emitted on the server once the replication messages of a remote tick from a client are applied
+pub type ReplicationAppliedEvent =
+    crate::shared::events::components::ReplicationAppliedEvent<ClientId>;
 /// Bevy [`Event`] emitted on the server on the frame where a ComponentUpdate replication message is received
 pub type ComponentUpdateEvent<C> =
     crate::shared::events::components::ComponentUpdateEvent<C, ClientId>;
@@ -282,16 +479,86 @@ pub type ComponentRemoveEvent<C> =
 /// Bevy [`Event`] emitted on the server on the frame where a (non-replication) message is received
 pub type MessageEvent<M> = crate::shared::events::components::MessageEvent<M, ClientId>;
 
+/// Bevy [`Event`] emitted on the server when a [`Request`](crate::shared::rpc::Request) is received from a client
+pub type RequestEvent<R> = crate::shared::rpc::RequestEvent<R, ClientId>;
+
+/// Bevy [`Event`] emitted on the server when a client responded to a [`Request`](crate::shared::rpc::Request),
+/// or when the request timed out
+pub type ResponseEvent<R> = crate::shared::rpc::ResponseEvent<R, ClientId>;
+
 #[cfg(test)]
 mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::{Commands, EventReader, ResMut, Resource, Update};
+
+    use crate::prelude::client::{self, ClientCommands};
+    use crate::prelude::server::Replicate;
     use crate::prelude::Tick;
     use crate::protocol::channel::ChannelKind;
     use crate::tests::protocol::{
         Channel1, Channel2, ComponentSyncModeFull, ComponentSyncModeOnce, StringMessage,
     };
+    use crate::tests::stepper::BevyStepper;
 
     use super::*;
 
+    #[derive(Resource, Default)]
+    struct Stamps(Vec<Option<EventStamp>>);
+
+    /// The replication and connection events are stamped with the tick and time at which they were emitted
+    #[test]
+    fn test_event_stamps() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<Stamps>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut stamps: ResMut<Stamps>,
+             mut spawns: EventReader<client::EntitySpawnEvent>,
+             mut inserts: EventReader<client::ComponentInsertEvent<ComponentSyncModeFull>>,
+             mut applied: EventReader<client::ReplicationAppliedEvent>,
+             mut disconnects: EventReader<client::DisconnectEvent>| {
+                stamps.0.extend(spawns.read().map(|event| event.stamp()));
+                stamps.0.extend(inserts.read().map(|event| event.stamp()));
+                stamps.0.extend(applied.read().map(|event| event.stamp()));
+                stamps
+                    .0
+                    .extend(disconnects.read().map(|event| event.stamp()));
+            },
+        );
+        stepper.server_app.init_resource::<Stamps>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut stamps: ResMut<Stamps>, mut disconnects: EventReader<DisconnectEvent>| {
+                stamps
+                    .0
+                    .extend(disconnects.read().map(|event| event.stamp()));
+            },
+        );
+
+        stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(1.0)));
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        stepper
+            .client_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.disconnect_client());
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+
+        // spawn, insert, replication applied and disconnection
+        let stamps = &stepper.client_app.world().resource::<Stamps>().0;
+        assert_eq!(stamps.len(), 4);
+        assert!(stamps.iter().all(Option::is_some));
+        let stamps = &stepper.server_app.world().resource::<Stamps>().0;
+        assert_eq!(stamps.len(), 1);
+        assert!(stamps[0].is_some());
+    }
+
     #[test]
     fn test_iter_component_removes() {
         let client_1 = ClientId::Netcode(1);
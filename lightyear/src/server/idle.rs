@@ -0,0 +1,213 @@
+//! Disconnect the clients that stay idle for too long.
+//!
+//! When [`ServerConfig::idle_kick`] is set, the server keeps track of the last time that each client sent a
+//! message or an input (pings, acks and other internal packets don't count, see
+//! [`Connection::idle_time`](crate::server::connection::Connection::idle_time)).
+//!
+//! A client that stays idle for [`IdleKickConfig::timeout`] is [kicked](ConnectionManager::kick) with
+//! [`IdleKickConfig::reason`], and an [`IdleKickEvent`] is emitted. If [`IdleKickConfig::warning`] is set,
+//! the client receives an [`IdleWarningEvent`](crate::prelude::client::IdleWarningEvent) that long before the kick.
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use tracing::error;
+
+use crate::connection::id::ClientId;
+use crate::server::config::ServerConfig;
+use crate::server::connection::ConnectionManager;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+/// Policy to disconnect the clients that don't send any message or input
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdleKickConfig {
+    /// Time without any message or input after which the client is kicked
+    pub timeout: Duration,
+    /// If set, the client is warned this long before the kick
+    pub warning: Option<Duration>,
+    /// Reason of the kick sent to the client
+    pub reason: String,
+}
+
+impl IdleKickConfig {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            warning: None,
+            reason: "idle for too long".to_string(),
+        }
+    }
+
+    /// Warn the clients `warning` before they get kicked
+    pub fn with_warning(mut self, warning: Duration) -> Self {
+        self.warning = Some(warning);
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = reason.into();
+        self
+    }
+}
+
+/// Bevy [`Event`] emitted on the server when a client gets kicked for being idle
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct IdleKickEvent {
+    pub client_id: ClientId,
+}
+
+pub(crate) struct IdleKickPlugin;
+
+impl Plugin for IdleKickPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<IdleKickEvent>();
+        app.add_systems(
+            PostUpdate,
+            kick_idle_clients.before(InternalMainSet::<ServerMarker>::Send),
+        );
+    }
+}
+
+/// Warn and kick the clients that have been idle for too long
+fn kick_idle_clients(
+    config: Res<ServerConfig>,
+    connection_manager: Option<ResMut<ConnectionManager>>,
+    mut events: EventWriter<IdleKickEvent>,
+) {
+    let Some(idle_kick) = config.idle_kick.as_ref() else {
+        return;
+    };
+    let Some(mut connection_manager) = connection_manager else {
+        return;
+    };
+    match connection_manager.kick_idle_clients(idle_kick) {
+        Ok(kicked) => {
+            events.send_batch(
+                kicked
+                    .into_iter()
+                    .map(|client_id| IdleKickEvent { client_id }),
+            );
+        }
+        Err(e) => error!("Could not kick the idle clients: {e:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::networking::NetworkingState;
+    use crate::connection::client::DisconnectReason;
+    use crate::prelude::client::{self, IdleWarningEvent};
+    use crate::prelude::{ClientId, SharedConfig, TickConfig};
+    use crate::tests::protocol::{Channel1, StringMessage};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    fn setup() -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            client::ClientConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .idle_kick = Some(
+            IdleKickConfig::new(Duration::from_millis(500))
+                .with_warning(Duration::from_millis(200))
+                .with_reason("afk"),
+        );
+        stepper.client_app.init_resource::<Received>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut warnings: EventReader<IdleWarningEvent>,
+             mut disconnections: EventReader<client::DisconnectEvent>,
+             mut received: ResMut<Received>| {
+                received.0.extend(
+                    warnings
+                        .read()
+                        .map(|event| format!("warning {:?}", event.kick_in)),
+                );
+                received
+                    .0
+                    .extend(disconnections.read().map(|event| match &event.reason {
+                        Some(DisconnectReason::Kicked(reason)) => format!("kicked {reason}"),
+                        reason => format!("disconnected {reason:?}"),
+                    }));
+            },
+        );
+        stepper.server_app.init_resource::<Received>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<IdleKickEvent>, mut received: ResMut<Received>| {
+                received.0.extend(
+                    events
+                        .read()
+                        .map(|event| format!("kicked {:?}", event.client_id)),
+                );
+            },
+        );
+        stepper.init();
+        stepper
+    }
+
+    #[test]
+    fn test_idle_client_is_warned_then_kicked() {
+        let mut stepper = setup();
+        for _ in 0..60 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<Received>().0,
+            vec![
+                format!("warning {:?}", Duration::from_millis(200)),
+                "kicked afk".to_string()
+            ]
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<State<NetworkingState>>()
+                .get(),
+            &NetworkingState::Disconnected
+        );
+        assert_eq!(
+            stepper.server_app.world().resource::<Received>().0,
+            vec![format!("kicked {:?}", ClientId::Netcode(TEST_CLIENT_ID))]
+        );
+    }
+
+    #[test]
+    fn test_active_client_is_not_kicked() {
+        let mut stepper = setup();
+        for i in 0..100 {
+            if i % 20 == 0 {
+                stepper
+                    .client_app
+                    .world_mut()
+                    .resource_mut::<client::ConnectionManager>()
+                    .send_message::<Channel1, _>(&mut StringMessage("hello".to_string()))
+                    .unwrap();
+            }
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<Received>()
+            .0
+            .is_empty());
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+    }
+}
@@ -8,11 +8,14 @@ use leafwing_input_manager::prelude::*;
 
 use crate::inputs::leafwing::LeafwingUserAction;
 use crate::prelude::server::MessageEvent;
-use crate::prelude::{server::is_started, InputMessage, MessageRegistry, Mode, TickManager};
+use crate::prelude::{
+    server::is_started, ClientId, InputMessage, MessageRegistry, Mode, TickManager,
+};
 use crate::protocol::message::MessageKind;
 use crate::serialize::reader::Reader;
 use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
+use crate::server::input::{InputHook, InputVerdict};
 use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::sets::{InternalMainSet, ServerMarker};
 
@@ -28,6 +31,10 @@ impl<A> Default for LeafwingInputPlugin<A> {
     }
 }
 
+/// Client that sent the last input message for the entity, which is passed to the [`InputHook`]
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct InputSender(ClientId);
+
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum InputSystemSet {
     /// Add the ActionDiffBuffers to new entities that have an [`ActionState`]
@@ -95,7 +102,7 @@ fn receive_input_message<A: LeafwingUserAction>(
     message_registry: Res<MessageRegistry>,
     mut connection_manager: ResMut<ConnectionManager>,
     // TODO: currently we do not handle entities that are controlled by multiple clients
-    mut query: Query<Option<&mut InputBuffer<A>>>,
+    mut query: Query<(Option<&mut InputBuffer<A>>, Option<&InputSender>)>,
     mut commands: Commands,
     mut events: EventWriter<MessageEvent<InputMessage<A>>>,
 ) {
@@ -112,7 +119,8 @@ fn receive_input_message<A: LeafwingUserAction>(
     for (client_id, connection) in connection_manager.connections.iter_mut() {
         if let Some(message_list) = connection.received_leafwing_input_messages.remove(&net) {
             for (message_bytes, target, channel_kind) in message_list {
-                let mut reader = Reader::from(message_bytes);
+                let mut reader = Reader::from(message_bytes)
+                    .with_versions(connection.replication_receiver.remote_versions.clone());
                 match message_registry.deserialize::<InputMessage<A>>(
                     &mut reader,
                     &mut connection
@@ -134,7 +142,12 @@ fn receive_input_message<A: LeafwingUserAction>(
                                     // TODO Don't update input buffer if inputs arrived too late?
                                     debug!("received input for entity: {:?}", entity);
 
-                                    if let Ok(buffer) = query.get_mut(*entity) {
+                                    if let Ok((buffer, sender)) = query.get_mut(*entity) {
+                                        if sender != Some(&InputSender(*client_id)) {
+                                            commands
+                                                .entity(*entity)
+                                                .insert(InputSender(*client_id));
+                                        }
                                         if let Some(mut buffer) = buffer {
                                             debug!(
                                                 ?target,
@@ -207,18 +220,39 @@ fn receive_input_message<A: LeafwingUserAction>(
 /// Read the InputState for the current tick from the buffer, and use them to update the ActionState
 fn update_action_state<A: LeafwingUserAction>(
     tick_manager: Res<TickManager>,
+    mut input_hook: Option<ResMut<InputHook<ActionState<A>>>>,
     // global_input_buffer: Res<InputBuffer<A>>,
     // global_action_state: Option<ResMut<ActionState<A>>>,
-    mut action_state_query: Query<(Entity, &mut ActionState<A>, &mut InputBuffer<A>)>,
+    mut action_state_query: Query<(
+        Entity,
+        &mut ActionState<A>,
+        &mut InputBuffer<A>,
+        Option<&InputSender>,
+    )>,
 ) {
     let tick = tick_manager.tick();
 
-    for (entity, mut action_state, mut input_buffer) in action_state_query.iter_mut() {
+    for (entity, mut action_state, mut input_buffer, sender) in action_state_query.iter_mut() {
         // We only apply the ActionState from the buffer if we have one.
         // If we don't (because the input packet is late or lost), we won't do anything.
         // This is equivalent to considering that the player will keep playing the last action they played.
         if let Some(action) = input_buffer.get(tick) {
-            *action_state = action.clone();
+            let mut action = action.clone();
+            if let (Some(hook), Some(sender)) = (input_hook.as_mut(), sender) {
+                match hook.run(sender.0, tick, &mut action) {
+                    InputVerdict::Keep => {}
+                    InputVerdict::Modify => {
+                        debug!(?entity, ?tick, "ActionState modified by the input hook");
+                    }
+                    InputVerdict::Drop => {
+                        // handled like a missing input: keep the last applied ActionState
+                        debug!(?entity, ?tick, "ActionState dropped by the input hook");
+                        input_buffer.pop(tick - 1);
+                        continue;
+                    }
+                }
+            }
+            *action_state = action;
             debug!(?tick, ?entity, pressed = ?action_state.get_pressed(), "action state after update. Input Buffer: {}", input_buffer.as_ref());
             // remove all the previous values
             // we keep the current value in the InputBuffer so that if future messages are lost, we can still
@@ -234,8 +268,8 @@ mod tests {
     use crate::inputs::leafwing::input_buffer::InputBuffer;
     use leafwing_input_manager::prelude::ActionState;
 
-    use crate::prelude::client;
     use crate::prelude::server::*;
+    use crate::prelude::{client, Tick};
     use crate::tests::protocol::*;
     use crate::tests::stepper::BevyStepper;
 
@@ -341,4 +375,72 @@ mod tests {
             .unwrap()
             .released(&LeafwingInput1::Jump));
     }
+
+    #[test]
+    fn test_leafwing_input_hook() {
+        let mut stepper = BevyStepper::default();
+        let dropped = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = dropped.clone();
+        // drop every ActionState where Jump is pressed
+        stepper
+            .server_app
+            .insert_resource(InputHook::<ActionState<LeafwingInput1>>::new(
+                move |_: ClientId, _: Tick, action_state: &mut ActionState<LeafwingInput1>| {
+                    if action_state.pressed(&LeafwingInput1::Jump) {
+                        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        return InputVerdict::Drop;
+                    }
+                    InputVerdict::Keep
+                },
+            ));
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((
+                ActionState::<LeafwingInput1>::default(),
+                Replicate::default(),
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+        stepper
+            .client_app
+            .world_mut()
+            .entity_mut(client_entity)
+            .insert(InputMap::<LeafwingInput1>::new([(
+                LeafwingInput1::Jump,
+                KeyCode::KeyA,
+            )]));
+        stepper.frame_step();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyA);
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        // the input reached the server, but was never applied
+        assert!(stepper
+            .server_app
+            .world()
+            .entity(server_entity)
+            .get::<InputSender>()
+            .is_some());
+        assert!(dropped.load(std::sync::atomic::Ordering::Relaxed) > 0);
+        assert!(!stepper
+            .server_app
+            .world()
+            .get::<ActionState<LeafwingInput1>>(server_entity)
+            .unwrap()
+            .pressed(&LeafwingInput1::Jump));
+    }
 }
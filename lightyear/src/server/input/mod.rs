@@ -1,5 +1,66 @@
+//! Handles the inputs received from the clients
+//!
+//! The inputs can be sanitized before the gameplay systems read them, by inserting an [`InputHook`] in the
+//! server app:
+//! ```rust,ignore
+//! use lightyear::prelude::*;
+//! use lightyear::prelude::server::{InputHook, InputVerdict};
+//!
+//! let max_speed = 10.0;
+//! server_app.insert_resource(InputHook::<MyInput>::new(move |_: ClientId, _: Tick, input: &mut MyInput| {
+//!     if input.speed > max_speed {
+//!         input.speed = max_speed;
+//!         return InputVerdict::Modify;
+//!     }
+//!     InputVerdict::Keep
+//! }));
+//! ```
+//! With leafwing, the hook receives the [`ActionState`](leafwing_input_manager::prelude::ActionState) of the
+//! tick: insert an `InputHook<ActionState<A>>` instead.
+use bevy::prelude::Resource;
+
+use crate::prelude::{ClientId, Tick};
+
 pub mod native;
 
 #[cfg_attr(docsrs, doc(cfg(feature = "leafwing")))]
 #[cfg(feature = "leafwing")]
 pub mod leafwing;
+
+/// Decision of an [`InputHook`] about an input received from a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputVerdict {
+    /// Apply the input as it was received
+    Keep,
+    /// The hook modified the input in place, apply the modified input
+    Modify,
+    /// Discard the input: the tick is handled as if the input was missing, so the last input that was
+    /// applied for the client is used instead
+    Drop,
+}
+
+/// Function that is called on each input received from a client, on the tick where it is about to be
+/// applied.
+///
+/// It can be used to sanitize or clamp the inputs (anti-cheat, accessibility limits) in a single place.
+/// The native inputs go through the hook before they are emitted as [`InputEvent`](crate::server::events::InputEvent)s,
+/// and the leafwing inputs before they are written to the `ActionState` of the entity.
+/// The inputs of the local client in host-server mode don't go through the hook.
+#[derive(Resource)]
+pub struct InputHook<I> {
+    hook: Box<dyn FnMut(ClientId, Tick, &mut I) -> InputVerdict + Send + Sync>,
+}
+
+impl<I> InputHook<I> {
+    pub fn new(
+        hook: impl FnMut(ClientId, Tick, &mut I) -> InputVerdict + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            hook: Box::new(hook),
+        }
+    }
+
+    pub(crate) fn run(&mut self, client_id: ClientId, tick: Tick, input: &mut I) -> InputVerdict {
+        (self.hook)(client_id, tick, input)
+    }
+}
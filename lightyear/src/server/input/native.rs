@@ -5,11 +5,14 @@ use bevy::utils::HashMap;
 use crate::inputs::native::input_buffer::InputBuffer;
 use crate::inputs::native::InputMessage;
 use crate::prelude::server::DisconnectEvent;
-use crate::prelude::{server::is_started, ClientId, MessageRegistry, TickManager, UserAction};
+use crate::prelude::{
+    server::is_started, ClientId, MessageRegistry, Tick, TickManager, UserAction,
+};
 use crate::protocol::message::MessageKind;
 use crate::serialize::reader::Reader;
 use crate::server::connection::ConnectionManager;
 use crate::server::events::InputEvent;
+use crate::server::input::{InputHook, InputVerdict};
 use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::sets::{InternalMainSet, ServerMarker};
 
@@ -113,7 +116,8 @@ fn receive_input_message<A: UserAction>(
     for (client_id, connection) in connection_manager.connections.iter_mut() {
         if let Some(message_list) = connection.received_input_messages.remove(&net) {
             for (message_bytes, target, channel_kind) in message_list {
-                let mut reader = Reader::from(message_bytes);
+                let mut reader = Reader::from(message_bytes)
+                    .with_versions(connection.replication_receiver.remote_versions.clone());
                 match message_registry.deserialize::<InputMessage<A>>(
                     &mut reader,
                     &mut connection
@@ -153,6 +157,7 @@ fn receive_input_message<A: UserAction>(
 // Do it in this system because we want an input for every tick
 fn write_input_event<A: UserAction>(
     tick_manager: Res<TickManager>,
+    mut input_hook: Option<ResMut<InputHook<A>>>,
     mut input_buffers: ResMut<InputBuffers<A>>,
     mut input_events: EventWriter<InputEvent<A>>,
 ) {
@@ -162,7 +167,24 @@ fn write_input_event<A: UserAction>(
         .iter_mut()
         .for_each(move |(client_id, (last_input, input_buffer))| {
             debug!(?input_buffer, ?tick, ?client_id, "input buffer for client");
-            let received_input = input_buffer.pop(tick);
+            let mut received_input = input_buffer.pop(tick);
+            if let (Some(hook), Some(input)) = (input_hook.as_mut(), received_input.as_mut()) {
+                match hook.run(*client_id, tick, input) {
+                    InputVerdict::Keep => {}
+                    InputVerdict::Modify => {
+                        debug!(
+                            ?client_id,
+                            ?tick,
+                            ?input,
+                            "Input modified by the input hook"
+                        );
+                    }
+                    InputVerdict::Drop => {
+                        debug!(?client_id, ?tick, ?input, "Input dropped by the input hook");
+                        received_input = None;
+                    }
+                }
+            }
             let fallback = received_input.is_none();
 
             // NOTE: if there is no input for this tick, we should use the last input that we have
@@ -195,3 +217,72 @@ fn write_input_event<A: UserAction>(
 fn clear_input_events<A: UserAction>(mut input_events: EventReader<InputEvent<A>>) {
     input_events.clear();
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::input::native::InputSystemSet;
+    use crate::prelude::client::InputManager;
+    use crate::tests::protocol::MyInput;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn press_input(
+        mut input_manager: ResMut<InputManager<MyInput>>,
+        tick_manager: Res<TickManager>,
+    ) {
+        // alternate between an input that gets clamped and an input that gets dropped
+        let value = if tick_manager.tick().0 % 2 == 0 {
+            10
+        } else {
+            -1
+        };
+        input_manager.add_input(MyInput(value), tick_manager.tick());
+    }
+
+    /// Hook that drops the negative inputs and clamps the inputs above `max`
+    fn sanitize(max: i16) -> InputHook<MyInput> {
+        InputHook::new(move |client_id: ClientId, _: Tick, input: &mut MyInput| {
+            assert_eq!(client_id, ClientId::Netcode(TEST_CLIENT_ID));
+            if input.0 < 0 {
+                return InputVerdict::Drop;
+            }
+            if input.0 > max {
+                input.0 = max;
+                return InputVerdict::Modify;
+            }
+            InputVerdict::Keep
+        })
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedInputs(Vec<MyInput>);
+
+    fn receive_input(
+        mut received: ResMut<ReceivedInputs>,
+        mut events: EventReader<InputEvent<MyInput>>,
+    ) {
+        received
+            .0
+            .extend(events.read().filter_map(|event| *event.input()));
+    }
+
+    #[test]
+    fn test_input_hook() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.add_systems(
+            FixedPreUpdate,
+            press_input.in_set(InputSystemSet::BufferInputs),
+        );
+        stepper.server_app.insert_resource(sanitize(5));
+        stepper.server_app.init_resource::<ReceivedInputs>();
+        stepper.server_app.add_systems(FixedUpdate, receive_input);
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        let received = &stepper.server_app.world().resource::<ReceivedInputs>().0;
+        assert!(!received.is_empty());
+        // the dropped inputs are replaced by the last clamped input
+        assert!(received.iter().all(|input| *input == MyInput(5)));
+    }
+}
@@ -1,4 +1,6 @@
 use super::*;
+use crate::client::io::config::ClientTransport;
+use crate::connection::server::TransportKind;
 use crate::prelude::CompressionConfig;
 use crate::server::io::transport::{ServerTransportBuilder, ServerTransportBuilderEnum};
 use crate::transport::channels::Channels;
@@ -81,6 +83,19 @@ impl Clone for ServerTransport {
 }
 
 impl ServerTransport {
+    /// Kind of transport, exposed in the [`ConnectionInfo`](crate::connection::server::ConnectionInfo)
+    pub(crate) fn kind(&self) -> TransportKind {
+        match self {
+            ServerTransport::UdpSocket(_) => TransportKind::UdpSocket,
+            #[cfg(all(feature = "webtransport", not(target_family = "wasm")))]
+            ServerTransport::WebTransportServer { .. } => TransportKind::WebTransport,
+            #[cfg(all(feature = "websocket", not(target_family = "wasm")))]
+            ServerTransport::WebSocketServer { .. } => TransportKind::WebSocket,
+            ServerTransport::Channels { .. } => TransportKind::Channels,
+            ServerTransport::Dummy => TransportKind::Dummy,
+        }
+    }
+
     fn build(self) -> ServerTransportBuilderEnum {
         match self {
             ServerTransport::UdpSocket(addr) => {
@@ -108,6 +123,32 @@ impl ServerTransport {
     }
 }
 
+impl ServerTransport {
+    /// Create the transports of a server and of `num_clients` clients that run in the same process.
+    ///
+    /// Packets are exchanged through memory channels instead of the OS network stack, which is useful
+    /// to run a local server in singleplayer mode, or for deterministic tests.
+    /// The clients must use [`LOCAL_SOCKET`](crate::transport::LOCAL_SOCKET) as the server address.
+    pub fn loopback(num_clients: u16) -> (ServerTransport, Vec<ClientTransport>) {
+        let (channels, clients) = (1..=num_clients)
+            .map(|port| {
+                let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
+                let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
+                // the address is only used by the server to identify the client
+                let client_addr = SocketAddr::new(IpAddr::from([127, 0, 0, 1]), port);
+                (
+                    (client_addr, to_server_recv, from_server_send),
+                    ClientTransport::LocalChannel {
+                        recv: from_server_recv,
+                        send: to_server_send,
+                    },
+                )
+            })
+            .unzip();
+        (ServerTransport::Channels { channels }, clients)
+    }
+}
+
 impl Default for ServerTransport {
     fn default() -> Self {
         ServerTransport::UdpSocket(SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 0))
@@ -137,6 +178,14 @@ impl SharedIoConfig<ServerTransport> {
                 let decompressor = ZstdDecompressor::new();
                 receiver = Box::new(decompressor.wrap(receiver));
             }
+            #[cfg(feature = "zstd")]
+            CompressionConfig::ZstdDictionary { level, dictionary } => {
+                use crate::transport::middleware::PacketSenderWrapper;
+                let compressor = ZstdCompressor::with_dictionary(level, &dictionary)?;
+                sender = Box::new(compressor.wrap(sender));
+                let decompressor = ZstdDecompressor::with_dictionary(&dictionary)?;
+                receiver = Box::new(decompressor.wrap(receiver));
+            }
             #[cfg(feature = "lz4")]
             CompressionConfig::Lz4 => {
                 use crate::transport::middleware::PacketSenderWrapper;
@@ -148,6 +197,13 @@ impl SharedIoConfig<ServerTransport> {
                 receiver = Box::new(decompressor.wrap(receiver));
             }
         }
+        // the payloads are captured before they are compressed
+        #[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+        if let Some(capture_config) = self.capture {
+            use crate::transport::middleware::compression::zstd::capture::PayloadCapture;
+            use crate::transport::middleware::PacketSenderWrapper;
+            sender = Box::new(PayloadCapture::start(capture_config)?.wrap(sender));
+        }
         Ok(BaseIo {
             local_addr,
             sender,
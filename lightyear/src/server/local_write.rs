@@ -0,0 +1,119 @@
+//! Apply the component writes sent by clients, see [`crate::shared::replication::local_write`]
+use bevy::prelude::*;
+use tracing::{debug, error};
+
+use crate::channel::builder::ControlChannel;
+use crate::prelude::server::{is_started, ConnectionManager, ControlledBy, MessageEvent};
+use crate::prelude::{ComponentRegistry, NetworkTarget, TickManager};
+use crate::protocol::component::ComponentNetId;
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::serialize::ToBytes;
+use crate::shared::replication::local_write::{LocalWrite, LocalWriteAck};
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+pub(crate) struct LocalWritePlugin;
+
+impl Plugin for LocalWritePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            apply_local_writes
+                .after(InternalMainSet::<ServerMarker>::EmitEvents)
+                .run_if(is_started),
+        );
+    }
+}
+
+/// Apply the writes of clients on the entities they control, and acknowledge them.
+///
+/// Writes on entities that are not controlled by the client are refused, and the server's value
+/// of the component is sent back instead.
+fn apply_local_writes(world: &mut World) {
+    let writes: Vec<_> = world
+        .resource_mut::<Events<MessageEvent<LocalWrite>>>()
+        .drain()
+        .collect();
+    if writes.is_empty() {
+        return;
+    }
+    let tick = world.resource::<TickManager>().tick();
+    world.resource_scope(|world, registry: Mut<ComponentRegistry>| {
+        world.resource_scope(|world, mut manager: Mut<ConnectionManager>| {
+            for MessageEvent {
+                message: write,
+                context: client_id,
+                ..
+            } in writes
+            {
+                let Ok(connection) = manager.connection_mut(client_id) else {
+                    continue;
+                };
+                let Some(mut entity_mut) = world.get_entity_mut(write.entity) else {
+                    continue;
+                };
+                let allowed = entity_mut
+                    .get::<ControlledBy>()
+                    .is_some_and(|controlled_by| controlled_by.targets(&client_id));
+                let correction = if allowed {
+                    if let Err(e) = registry.raw_write(
+                        &mut Reader::from(write.component)
+                            .with_versions(connection.replication_receiver.remote_versions.clone()),
+                        &mut entity_mut,
+                        tick,
+                        &mut connection
+                            .replication_receiver
+                            .remote_entity_map
+                            .remote_to_local,
+                        &mut connection.events,
+                    ) {
+                        error!(?client_id, "Could not apply the local write: {e:?}");
+                    }
+                    None
+                } else {
+                    debug!(
+                        ?client_id,
+                        entity = ?write.entity,
+                        "Refused a local write on an entity that is not controlled by the client"
+                    );
+                    let mut reader = Reader::from(write.component);
+                    let kind = ComponentNetId::from_bytes(&mut reader)
+                        .ok()
+                        .and_then(|net_id| registry.kind_map.kind(net_id).copied());
+                    let mut writer = Writer::default();
+                    kind.and_then(|kind| {
+                        let component_id = registry.replication_map.get(&kind)?.component_id;
+                        let component = entity_mut.get_by_id(component_id)?;
+                        registry
+                            .erased_serialize(
+                                component,
+                                &mut writer,
+                                kind,
+                                Some(
+                                    &mut connection
+                                        .replication_receiver
+                                        .remote_entity_map
+                                        .local_to_remote,
+                                ),
+                            )
+                            .ok()?;
+                        Some(writer.split().to_vec())
+                    })
+                };
+                let mut ack = LocalWriteAck {
+                    entity: write.entity,
+                    command_id: write.command_id,
+                    tick,
+                    accepted: allowed,
+                    correction,
+                };
+                if let Err(e) = manager.send_message_to_target::<ControlChannel, _>(
+                    &mut ack,
+                    NetworkTarget::Single(client_id),
+                ) {
+                    error!(?client_id, "Could not acknowledge the local write: {e:?}");
+                }
+            }
+        });
+    });
+}
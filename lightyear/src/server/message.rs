@@ -5,8 +5,11 @@ use crate::protocol::message::{MessageKind, MessageRegistry};
 use crate::serialize::reader::Reader;
 use crate::server::connection::ConnectionManager;
 use crate::server::events::MessageEvent;
+use crate::shared::events::components::EventStamp;
 use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::sets::{InternalMainSet, ServerMarker};
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::TimeManager;
 use bevy::app::{App, PreUpdate};
 use bevy::prelude::{EventWriter, IntoSystemConfigs, Res, ResMut};
 use tracing::{error, trace};
@@ -16,6 +19,8 @@ fn read_message<M: Message>(
     message_registry: Res<MessageRegistry>,
     mut connection_manager: ResMut<ConnectionManager>,
     mut event: EventWriter<MessageEvent<M>>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
 ) {
     let kind = MessageKind::of::<M>();
     let Some(net) = message_registry.kind_map.net_id(&kind).copied() else {
@@ -25,30 +30,60 @@ fn read_message<M: Message>(
         );
         return;
     };
+    let stamp = EventStamp::now(&tick_manager, &time_manager);
     // re-borrow to allow split borrows
     let connection_manager = connection_manager.deref_mut();
     for (client_id, connection) in connection_manager.connections.iter_mut() {
         if let Some(message_list) = connection.received_messages.remove(&net) {
             for (message_bytes, target, channel_kind) in message_list {
-                let mut reader = Reader::from(message_bytes);
-                match message_registry.deserialize::<M>(
+                let mut reader = Reader::from(message_bytes)
+                    .with_versions(connection.replication_receiver.remote_versions.clone());
+                match message_registry.deserialize_with_trace_id::<M>(
                     &mut reader,
                     &mut connection
                         .replication_receiver
                         .remote_entity_map
                         .remote_to_local,
                 ) {
-                    Ok(message) => {
+                    Ok((message, trace_id)) => {
                         // rebroadcast
                         if target != NetworkTarget::None {
-                            connection.messages_to_rebroadcast.push((
-                                reader.consume(),
-                                target,
-                                channel_kind,
-                            ));
+                            // the bytes of a versioned message are written with the version of the sender,
+                            // so the message is written again with the version of the server
+                            let bytes = if message_registry.is_versioned::<M>() {
+                                match message_registry.serialize_with_trace_id(
+                                    &message,
+                                    &mut connection_manager.writer,
+                                    None,
+                                    trace_id,
+                                ) {
+                                    Ok(()) => Some(connection_manager.writer.split()),
+                                    Err(e) => {
+                                        error!("Could not rebroadcast the message: {e:?}");
+                                        None
+                                    }
+                                }
+                            } else {
+                                Some(reader.consume())
+                            };
+                            if let Some(bytes) = bytes {
+                                connection.messages_to_rebroadcast.push((
+                                    bytes,
+                                    target,
+                                    channel_kind,
+                                ));
+                            }
                         }
-                        event.send(MessageEvent::new(message, *client_id));
-                        trace!("Received message: {:?}", std::any::type_name::<M>());
+                        event.send(
+                            MessageEvent::new(message, *client_id)
+                                .with_trace_id(trace_id)
+                                .with_stamp(stamp),
+                        );
+                        trace!(
+                            ?trace_id,
+                            "Received message: {:?}",
+                            std::any::type_name::<M>()
+                        );
                     }
                     Err(e) => {
                         error!(
@@ -180,11 +215,19 @@ pub(crate) fn add_server_receive_message_from_client<M: Message>(app: &mut App)
 
 #[cfg(test)]
 mod tests {
-    use crate::prelude::NetworkTarget;
+    use crate::prelude::server::{ConnectionManager, ControlledBy, RoomId, RoomManager};
+    use crate::prelude::{
+        ClientId, EventStamp, NetworkTarget, TelemetryChannel, TickManager, TraceId,
+    };
+    use crate::shared::time_manager::TimeManager;
     use crate::tests::host_server_stepper::HostServerStepper;
-    use crate::tests::protocol::{Channel1, StringMessage};
+    use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1, TEST_CLIENT_ID_2};
+    use crate::tests::protocol::{
+        Channel1, HistoryChannel, ReliableChannel, StringMessage, TracedMessage,
+    };
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
     use bevy::app::Update;
-    use bevy::prelude::{EventReader, ResMut, Resource};
+    use bevy::prelude::{EventReader, Mut, Res, ResMut, Resource};
 
     #[derive(Resource, Default)]
     struct Counter(usize);
@@ -234,4 +277,458 @@ mod tests {
         // verify that the other client received the message
         assert_eq!(stepper.client_app.world().resource::<Counter>().0, 1);
     }
+
+    /// The server holds a scheduled message until it reaches the tick of the message
+    #[test]
+    fn server_send_message_at_tick() {
+        let mut stepper = HostServerStepper::default();
+
+        stepper.server_app.init_resource::<Counter>();
+        stepper.client_app.init_resource::<Counter>();
+        stepper.server_app.add_systems(Update, count_messages);
+        stepper.client_app.add_systems(Update, count_messages);
+
+        let tick = stepper.server_app.world().resource::<TickManager>().tick() + 5;
+        let client_ids: Vec<_> = stepper
+            .server_app
+            .world()
+            .resource::<crate::prelude::server::ConnectionManager>()
+            .connected_clients()
+            .collect();
+        assert_eq!(client_ids.len(), 2);
+        for client_id in client_ids {
+            stepper
+                .server_app
+                .world_mut()
+                .resource_mut::<crate::prelude::server::ConnectionManager>()
+                .send_message_at_tick::<Channel1, StringMessage>(
+                    client_id,
+                    tick,
+                    &mut StringMessage("a".to_string()),
+                )
+                .unwrap();
+        }
+        for _ in 0..4 {
+            stepper.frame_step();
+        }
+        assert_eq!(stepper.server_app.world().resource::<Counter>().0, 0);
+        assert_eq!(stepper.client_app.world().resource::<Counter>().0, 0);
+
+        for _ in 0..4 {
+            stepper.frame_step();
+        }
+        assert_eq!(stepper.server_app.world().resource::<Counter>().0, 1);
+        assert_eq!(stepper.client_app.world().resource::<Counter>().0, 1);
+    }
+
+    #[derive(Resource, Default)]
+    struct Received(usize);
+
+    fn receive_messages(
+        mut received: ResMut<Received>,
+        mut events: EventReader<crate::client::events::MessageEvent<StringMessage>>,
+    ) {
+        received.0 += events.read().count();
+    }
+
+    /// The clients that control the entity do not receive the message sent to the room
+    #[test]
+    fn server_send_message_to_room_except_owner() {
+        let mut stepper = MultiBevyStepper::default();
+        stepper.client_app_1.init_resource::<Received>();
+        stepper.client_app_2.init_resource::<Received>();
+        stepper.client_app_1.add_systems(Update, receive_messages);
+        stepper.client_app_2.add_systems(Update, receive_messages);
+
+        let room_id = RoomId(0);
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_client(ClientId::Netcode(TEST_CLIENT_ID_1), room_id);
+        room_manager.add_client(ClientId::Netcode(TEST_CLIENT_ID_2), room_id);
+        let controlled_by = ControlledBy {
+            target: NetworkTarget::Single(ClientId::Netcode(TEST_CLIENT_ID_1)),
+            ..Default::default()
+        };
+        stepper.server_app.world_mut().resource_scope(
+            |world, mut manager: Mut<ConnectionManager>| {
+                manager
+                    .send_message_to_room_except_owner::<Channel1, StringMessage>(
+                        &mut StringMessage("a".to_string()),
+                        room_id,
+                        &controlled_by,
+                        world.resource::<RoomManager>(),
+                    )
+                    .unwrap();
+            },
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+
+        assert_eq!(stepper.client_app_1.world().resource::<Received>().0, 0);
+        assert_eq!(stepper.client_app_2.world().resource::<Received>().0, 1);
+    }
+
+    /// The excluded clients do not receive the message, both when it is sent to all clients and to a room
+    #[test]
+    fn server_send_message_except() {
+        let mut stepper = MultiBevyStepper::default();
+        stepper.client_app_1.init_resource::<ReceivedStrings>();
+        stepper.client_app_2.init_resource::<ReceivedStrings>();
+        stepper
+            .client_app_1
+            .add_systems(Update, receive_client_strings);
+        stepper
+            .client_app_2
+            .add_systems(Update, receive_client_strings);
+
+        let room_id = RoomId(0);
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_client(ClientId::Netcode(TEST_CLIENT_ID_1), room_id);
+        room_manager.add_client(ClientId::Netcode(TEST_CLIENT_ID_2), room_id);
+        stepper.server_app.world_mut().resource_scope(
+            |world, mut manager: Mut<ConnectionManager>| {
+                manager
+                    .send_message_to_all_except::<Channel1, StringMessage>(
+                        &mut StringMessage("a".to_string()),
+                        [ClientId::Netcode(TEST_CLIENT_ID_1)],
+                    )
+                    .unwrap();
+                manager
+                    .send_message_to_room_except::<Channel1, StringMessage>(
+                        &mut StringMessage("b".to_string()),
+                        room_id,
+                        [ClientId::Netcode(TEST_CLIENT_ID_2)],
+                        world.resource::<RoomManager>(),
+                    )
+                    .unwrap();
+            },
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+
+        assert_eq!(
+            stepper.client_app_1.world().resource::<ReceivedStrings>().0,
+            vec!["b"]
+        );
+        assert_eq!(
+            stepper.client_app_2.world().resource::<ReceivedStrings>().0,
+            vec!["a"]
+        );
+    }
+
+    /// The excluded clients don't receive the message from the history of the room when they join it again
+    #[test]
+    fn server_send_message_to_room_except_history() {
+        let mut stepper = MultiBevyStepper::default();
+        stepper.client_app_1.init_resource::<ReceivedStrings>();
+        stepper.client_app_2.init_resource::<ReceivedStrings>();
+        stepper
+            .client_app_1
+            .add_systems(Update, receive_client_strings);
+        stepper
+            .client_app_2
+            .add_systems(Update, receive_client_strings);
+
+        let room_id = RoomId(0);
+        let client_1 = ClientId::Netcode(TEST_CLIENT_ID_1);
+        let client_2 = ClientId::Netcode(TEST_CLIENT_ID_2);
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_client(client_1, room_id);
+        room_manager.add_client(client_2, room_id);
+        stepper.frame_step();
+        stepper.server_app.world_mut().resource_scope(
+            |world, mut manager: Mut<ConnectionManager>| {
+                manager
+                    .send_message_to_room_except::<HistoryChannel, StringMessage>(
+                        &mut StringMessage("a".to_string()),
+                        room_id,
+                        [client_2],
+                        world.resource::<RoomManager>(),
+                    )
+                    .unwrap();
+                manager
+                    .send_message_to_room::<HistoryChannel, StringMessage>(
+                        &mut StringMessage("b".to_string()),
+                        room_id,
+                        world.resource::<RoomManager>(),
+                    )
+                    .unwrap();
+            },
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+
+        // both clients leave the room and join it again
+        for client_id in [client_1, client_2] {
+            stepper
+                .server_app
+                .world_mut()
+                .resource_mut::<RoomManager>()
+                .remove_client(client_id, room_id);
+        }
+        stepper.frame_step();
+        for client_id in [client_1, client_2] {
+            stepper
+                .server_app
+                .world_mut()
+                .resource_mut::<RoomManager>()
+                .add_client(client_id, room_id);
+        }
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+
+        assert_eq!(
+            stepper.client_app_1.world().resource::<ReceivedStrings>().0,
+            vec!["a", "b", "a", "b"]
+        );
+        assert_eq!(
+            stepper.client_app_2.world().resource::<ReceivedStrings>().0,
+            vec!["b", "b"]
+        );
+    }
+
+    /// The message is sent to each of the clients, and the clients that are not connected are ignored
+    #[test]
+    fn server_send_message_to_clients() {
+        let mut stepper = MultiBevyStepper::default();
+        stepper.client_app_1.init_resource::<Received>();
+        stepper.client_app_2.init_resource::<Received>();
+        stepper.client_app_1.add_systems(Update, receive_messages);
+        stepper.client_app_2.add_systems(Update, receive_messages);
+
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ConnectionManager>()
+            .send_message_to_clients::<Channel1, StringMessage>(
+                [
+                    ClientId::Netcode(TEST_CLIENT_ID_1),
+                    ClientId::Netcode(TEST_CLIENT_ID_2),
+                    ClientId::Netcode(1000),
+                    // the message is only sent once to a client that is listed twice
+                    ClientId::Netcode(TEST_CLIENT_ID_1),
+                ],
+                &mut StringMessage("a".to_string()),
+            )
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+
+        assert_eq!(stepper.client_app_1.world().resource::<Received>().0, 1);
+        assert_eq!(stepper.client_app_2.world().resource::<Received>().0, 1);
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedStrings(Vec<String>);
+
+    fn receive_client_strings(
+        mut received: ResMut<ReceivedStrings>,
+        mut events: EventReader<crate::client::events::MessageEvent<StringMessage>>,
+    ) {
+        received
+            .0
+            .extend(events.read().map(|event| event.message().0.clone()));
+    }
+
+    fn receive_server_strings(
+        mut received: ResMut<ReceivedStrings>,
+        mut events: EventReader<crate::server::events::MessageEvent<StringMessage>>,
+    ) {
+        received
+            .0
+            .extend(events.read().map(|event| event.message().0.clone()));
+    }
+
+    /// Messages bigger than a packet are fragmented and reassembled transparently
+    #[test]
+    fn send_large_message_reliable() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<ReceivedStrings>();
+        stepper.server_app.init_resource::<ReceivedStrings>();
+        stepper
+            .client_app
+            .add_systems(Update, receive_client_strings);
+        stepper
+            .server_app
+            .add_systems(Update, receive_server_strings);
+
+        let message: String = (0..10_000)
+            .map(|i| char::from(b'a' + (i % 26) as u8))
+            .collect();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<crate::prelude::server::ConnectionManager>()
+            .send_message::<ReliableChannel, StringMessage>(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                &mut StringMessage(message.clone()),
+            )
+            .unwrap();
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<crate::prelude::client::ConnectionManager>()
+            .send_message::<ReliableChannel, StringMessage>(&mut StringMessage(message.clone()))
+            .unwrap();
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app.world().resource::<ReceivedStrings>().0,
+            vec![message.clone()]
+        );
+        assert_eq!(
+            stepper.server_app.world().resource::<ReceivedStrings>().0,
+            vec![message]
+        );
+    }
+
+    /// The clients can stream their state to the server on the telemetry channel
+    #[test]
+    fn send_telemetry() {
+        let mut stepper = BevyStepper::default();
+        stepper.server_app.init_resource::<ReceivedStrings>();
+        stepper
+            .server_app
+            .add_systems(Update, receive_server_strings);
+
+        for i in 0..5 {
+            stepper
+                .client_app
+                .world_mut()
+                .resource_mut::<crate::prelude::client::ConnectionManager>()
+                .send_message::<TelemetryChannel, StringMessage>(&mut StringMessage(format!(
+                    "aim {i}"
+                )))
+                .unwrap();
+            stepper.frame_step();
+        }
+        stepper.frame_step();
+        assert_eq!(
+            stepper.server_app.world().resource::<ReceivedStrings>().0,
+            (0..5).map(|i| format!("aim {i}")).collect::<Vec<_>>()
+        );
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedTraceIds(Vec<Option<TraceId>>);
+
+    fn receive_client_trace_ids(
+        mut received: ResMut<ReceivedTraceIds>,
+        mut events: EventReader<crate::client::events::MessageEvent<TracedMessage>>,
+    ) {
+        received
+            .0
+            .extend(events.read().map(|event| event.trace_id()));
+    }
+
+    fn receive_server_trace_ids(
+        mut received: ResMut<ReceivedTraceIds>,
+        mut events: EventReader<crate::server::events::MessageEvent<TracedMessage>>,
+    ) {
+        received
+            .0
+            .extend(events.read().map(|event| event.trace_id()));
+    }
+
+    /// The trace id of a message is propagated to the receiving side
+    #[test]
+    fn send_message_with_trace_id() {
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<ReceivedTraceIds>();
+        stepper.server_app.init_resource::<ReceivedTraceIds>();
+        stepper
+            .client_app
+            .add_systems(Update, receive_client_trace_ids);
+        stepper
+            .server_app
+            .add_systems(Update, receive_server_trace_ids);
+
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<crate::prelude::client::ConnectionManager>()
+            .send_message_with_trace_id::<Channel1, TracedMessage>(
+                &mut TracedMessage("a".to_string()),
+                TraceId(7),
+            )
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(
+            stepper.server_app.world().resource::<ReceivedTraceIds>().0,
+            vec![Some(TraceId(7))]
+        );
+
+        // the server forwards the trace id, and messages sent without one have no trace id
+        let mut manager = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<crate::prelude::server::ConnectionManager>();
+        manager
+            .send_message_to_target_with_trace_id::<Channel1, TracedMessage>(
+                &mut TracedMessage("a".to_string()),
+                NetworkTarget::All,
+                TraceId(7),
+            )
+            .unwrap();
+        manager
+            .send_message::<Channel1, TracedMessage>(
+                ClientId::Netcode(TEST_CLIENT_ID),
+                &mut TracedMessage("b".to_string()),
+            )
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+        let mut received = stepper
+            .client_app
+            .world()
+            .resource::<ReceivedTraceIds>()
+            .0
+            .clone();
+        received.sort();
+        assert_eq!(received, vec![None, Some(TraceId(7))]);
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedStamps(Vec<(Option<EventStamp>, EventStamp)>);
+
+    /// Store the stamp of the received messages, along with the current tick and time
+    fn receive_server_stamps(
+        mut received: ResMut<ReceivedStamps>,
+        mut events: EventReader<crate::server::events::MessageEvent<TracedMessage>>,
+        tick_manager: Res<TickManager>,
+        time_manager: Res<TimeManager>,
+    ) {
+        let now = EventStamp::now(&tick_manager, &time_manager);
+        received
+            .0
+            .extend(events.read().map(|event| (event.stamp(), now)));
+    }
+
+    /// The messages are stamped with the server tick and time at which they were received
+    #[test]
+    fn message_event_stamp() {
+        let mut stepper = BevyStepper::default();
+        stepper.server_app.init_resource::<ReceivedStamps>();
+        stepper
+            .server_app
+            .add_systems(Update, receive_server_stamps);
+
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<crate::prelude::client::ConnectionManager>()
+            .send_message::<Channel1, TracedMessage>(&mut TracedMessage("a".to_string()))
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+        let received = &stepper.server_app.world().resource::<ReceivedStamps>().0;
+        assert_eq!(received.len(), 1);
+        let (stamp, now) = received[0];
+        let stamp = stamp.unwrap();
+        assert_eq!(stamp.time, now.time);
+        // the events are emitted in PreUpdate, before the tick is incremented in FixedUpdate
+        assert_eq!(stamp.tick + 1, now.tick);
+    }
 }
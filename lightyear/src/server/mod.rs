@@ -3,25 +3,45 @@
 //! # Server
 //! The server module contains all the code that is used to run the server.
 
+pub mod compatibility;
 pub mod config;
 
 pub mod connection;
+pub mod diagnostics;
+pub mod divergence;
 
 pub mod error;
+pub mod error_events;
 
 pub mod events;
 
+pub mod idle;
+
 pub mod input;
 
 pub(crate) mod io;
+pub(crate) mod local_write;
 
+pub mod pacing;
+pub mod pause;
 pub mod plugin;
 
+pub mod pool;
+
 pub(crate) mod message;
 pub(crate) mod prediction;
 
+pub mod queue;
+
 pub mod clients;
 pub(crate) mod networking;
 pub mod relevance;
 pub mod replication;
 pub mod run_conditions;
+pub mod session;
+pub mod shard;
+pub mod snapshot;
+pub mod spectator;
+pub mod tenant;
+pub mod transient;
+pub mod world_view;
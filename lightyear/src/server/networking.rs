@@ -1,20 +1,42 @@
 //! Defines the server bevy systems and run conditions
-use crate::connection::server::{IoConfig, NetServer, ServerConnection, ServerConnections};
+use crate::channel::builder::ControlChannel;
+use crate::client::shutdown::ServerShutdown;
+use crate::connection::id::ClientId;
+use crate::connection::server::{
+    DeniedReason, IoConfig, NetServer, ServerConnection, ServerConnections,
+};
+use crate::packet::packet_builder::Payload;
 use crate::prelude::{
     is_host_server, server::is_started, ChannelRegistry, MainSet, MessageRegistry, TickManager,
     TimeManager,
 };
 use crate::protocol::component::ComponentRegistry;
+use crate::protocol::hash::protocol_hash;
+use crate::protocol::validate::validate_protocol;
+use crate::protocol::version::SchemaVersions;
 use crate::serialize::reader::Reader;
 use crate::server::clients::ControlledEntities;
 use crate::server::config::ServerConfig;
-use crate::server::connection::ConnectionManager;
+use crate::server::connection::{Connection, ConnectionManager};
+use crate::server::diagnostics::FrameBudgetReport;
 use crate::server::error::ServerError;
+use crate::server::error_events::{ErrorSeverity, ServerErrors};
+use crate::server::events::{
+    AuthRequestEvent, DuplicateLoginEvent, HandshakeThrottledEvent, ProtocolMismatchEvent,
+    SendErrorEvent, SuspiciousActivityEvent,
+};
 use crate::server::io::ServerIoEvent;
+use crate::server::queue::{ClientQueuedEvent, QueueSlotOpenedEvent, ServerFullPolicy};
+use crate::server::tenant::{TenantManager, TenantOverBudgetEvent};
+#[cfg(feature = "alloc_audit")]
+use crate::shared::alloc_audit::{AllocationCounter, Allocations};
+use crate::shared::events::components::EventStamp;
+use crate::shared::replication::network_target::NetworkTarget;
 use crate::shared::sets::{InternalMainSet, ServerMarker};
 use async_channel::TryRecvError;
 use bevy::ecs::system::{RunSystemOnce, SystemChangeTick};
 use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
 use tracing::{debug, error, trace};
 
 /// Plugin handling the server networking systems: sending/receiving packets to clients
@@ -51,13 +73,18 @@ impl Plugin for ServerNetworkingPlugin {
             // SYSTEMS //
             .add_systems(
                 PreUpdate,
-                (receive_packets, receive)
+                (receive_packets, receive, disconnect_closing_clients)
                     .chain()
                     .in_set(InternalMainSet::<ServerMarker>::Receive),
             )
             .add_systems(
                 PostUpdate,
-                (send, send_host_server.run_if(is_host_server))
+                (
+                    release_scheduled_messages,
+                    (send, send_host_server.run_if(is_host_server)),
+                    finish_shutdown,
+                )
+                    .chain()
                     .in_set(InternalMainSet::<ServerMarker>::Send),
             );
 
@@ -84,6 +111,24 @@ pub(crate) fn receive_packets(
     mut connection_manager: ResMut<ConnectionManager>,
     mut networking_state: ResMut<NextState<NetworkingState>>,
     mut netservers: ResMut<ServerConnections>,
+    (
+        mut throttled_events,
+        mut mismatch_events,
+        mut suspicious_events,
+        mut send_error_events,
+        mut duplicate_login_events,
+    ): (
+        EventWriter<HandshakeThrottledEvent>,
+        EventWriter<ProtocolMismatchEvent>,
+        EventWriter<SuspiciousActivityEvent>,
+        EventWriter<SendErrorEvent>,
+        EventWriter<DuplicateLoginEvent>,
+    ),
+    mut auth_events: EventWriter<AuthRequestEvent>,
+    mut queued_events: EventWriter<ClientQueuedEvent>,
+    mut slot_opened_events: EventWriter<QueueSlotOpenedEvent>,
+    config: Res<ServerConfig>,
+    mut errors: ResMut<ServerErrors>,
     mut time_manager: ResMut<TimeManager>,
     tick_manager: Res<TickManager>,
     virtual_time: Res<Time<Virtual>>,
@@ -115,6 +160,11 @@ pub(crate) fn receive_packets(
                             }
                             ServerIoEvent::ServerDisconnected(e) => {
                                 error!("Disconnect server because of io error: {:?}", e);
+                                errors.report(
+                                    ErrorSeverity::Critical,
+                                    None,
+                                    format!("io error: {e:?}"),
+                                );
                                 networking_state.set(NetworkingState::Stopped);
                             }
                             _ => {}
@@ -126,16 +176,52 @@ pub(crate) fn receive_packets(
             }
         }
 
-        let _ = netserver
-            .try_update(delta.as_secs_f64())
-            .map_err(|e| error!("Error updating netcode server: {:?}", e));
+        let _ = netserver.try_update(delta.as_secs_f64()).map_err(|e| {
+            error!("Error updating netcode server: {:?}", e);
+            errors.report(ErrorSeverity::Error, None, e);
+        });
         for client_id in netserver.new_connections().iter().copied() {
             netservers.client_server_map.insert(client_id, server_idx);
-            // spawn an entity for the client
-            let client_entity = commands
-                .spawn((ControlledEntities::default(), Name::new("Client")))
-                .id();
-            connection_manager.add(client_id, client_entity);
+            if let Some(timeout) = netservers.auth_timeout {
+                // the client is only added once it is accepted
+                netservers
+                    .pending_auth
+                    .insert(client_id, Timer::new(timeout, TimerMode::Once));
+                auth_events.send(AuthRequestEvent {
+                    client_id,
+                    user_data: netserver
+                        .connection_info(client_id)
+                        .and_then(|info| info.user_data),
+                    stamp: Some(EventStamp::now(&tick_manager, &time_manager)),
+                });
+                continue;
+            }
+            add_client(
+                &mut commands,
+                &mut connection_manager,
+                &config,
+                &mut queued_events,
+                netserver,
+                client_id,
+            );
+        }
+        for addr in netserver.new_throttled_sources() {
+            throttled_events.send(HandshakeThrottledEvent { addr });
+        }
+        for (addr, malformed_packets, action) in netserver.new_suspicious_sources() {
+            suspicious_events.send(SuspiciousActivityEvent {
+                addr,
+                malformed_packets,
+                action,
+            });
+        }
+        send_error_events.send_batch(netserver.new_send_errors());
+        duplicate_login_events.send_batch(netserver.new_duplicate_logins());
+        for (client_id, protocol_hash) in netserver.new_protocol_mismatches() {
+            mismatch_events.send(ProtocolMismatchEvent {
+                client_id,
+                protocol_hash,
+            });
         }
         // handle disconnections
 
@@ -145,14 +231,25 @@ pub(crate) fn receive_packets(
                 #[allow(irrefutable_let_patterns)]
                 if let ServerConnection::Netcode(server) = netserver {
                     error!("Disconnecting client {addr:?} because of io error");
+                    errors.report(
+                        ErrorSeverity::Error,
+                        None,
+                        format!("io error for client {addr:?}"),
+                    );
                     let _ = server.disconnect_by_addr(addr);
                 }
             })
         }
         // disconnects because we received a disconnect message
         for client_id in netserver.new_disconnections().iter().copied() {
+            netservers.pending_auth.remove(&client_id);
             if netservers.client_server_map.remove(&client_id).is_some() {
-                connection_manager.remove(client_id);
+                // the client might have disconnected before being authenticated or while queued
+                if connection_manager.dequeue(client_id) {
+                    debug!(?client_id, "Queued client disconnected");
+                } else {
+                    let _ = connection_manager.remove(client_id);
+                }
                 // NOTE: we don't despawn the entity right away to let the user react to
                 // the disconnect event
                 // TODO: use observers/component_hooks to react automatically on the client despawn?
@@ -163,6 +260,36 @@ pub(crate) fn receive_packets(
         }
     }
 
+    // add the clients that were authenticated, and reject the ones that took too long
+    for client_id in std::mem::take(&mut netservers.accepted) {
+        let Some(&server_idx) = netservers.client_server_map.get(&client_id) else {
+            continue;
+        };
+        add_client(
+            &mut commands,
+            &mut connection_manager,
+            &config,
+            &mut queued_events,
+            &mut netservers.servers[server_idx],
+            client_id,
+        );
+    }
+    netservers.update_pending_auth(delta);
+
+    // admit the queued clients if some slots opened
+    if let Some(limit) = config.max_connections {
+        while connection_manager.connections.len() < limit.max_connections {
+            let Some(client_id) = connection_manager.queued_clients().next() else {
+                break;
+            };
+            let entity = spawn_client_entity(&mut commands);
+            let _ = connection_manager
+                .admit_next(entity)
+                .inspect_err(|e| error!("Could not admit queued client {client_id:?}: {e:?}"));
+            slot_opened_events.send(QueueSlotOpenedEvent { client_id, entity });
+        }
+    }
+
     // update connections
     connection_manager.update(
         system_change_tick.this_run(),
@@ -179,28 +306,126 @@ pub(crate) fn receive_packets(
             // packets from a client
             // TODO: use connection to apply on BOTH message manager and replication manager
             if let Some(connection) = connection_manager.connections.get_mut(&client_id) {
-                connection
+                connection.last_heard = Some(time_manager.current_time());
+                // a malformed packet is dropped instead of stopping the server
+                let _ = connection
                     .recv_packet(
                         payload,
                         tick_manager.as_ref(),
                         component_registry.as_ref(),
                         &mut connection_manager.delta_manager,
                     )
-                    .expect("could not receive packet");
+                    .inspect_err(|e| {
+                        errors.report(ErrorSeverity::Warning, Some(client_id), e);
+                    });
+            } else if let Some(connection) = connection_manager
+                .queue
+                .iter_mut()
+                .find(|connection| connection.client_id == client_id)
+            {
+                // we only read the acks of the queued clients, their messages are ignored
+                connection.last_heard = Some(time_manager.current_time());
+                let _ = connection
+                    .recv_packet(
+                        payload,
+                        tick_manager.as_ref(),
+                        component_registry.as_ref(),
+                        &mut connection_manager.delta_manager,
+                    )
+                    .inspect_err(|e| {
+                        errors.report(ErrorSeverity::Warning, Some(client_id), e);
+                    });
+                connection.discard_received_messages();
             } else {
                 // it's still possible to receive some packets from a client that just disconnected.
                 // (multiple packets arrived at the same time from that client)
-                if netserver.new_disconnections().contains(&client_id) {
+                if netserver.new_disconnections().contains(&client_id)
+                    || netservers.pending_auth.contains_key(&client_id)
+                {
                     trace!("received packet from client that just got disconnected. Ignoring.");
                     // we ignore packets from disconnected clients
                     // this is not an error
                     continue;
                 } else {
                     error!("Received packet from unknown client: {}", client_id);
+                    errors.report(
+                        ErrorSeverity::Warning,
+                        Some(client_id),
+                        "received packet from unknown client",
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Add a newly connected client to the server, and spawn its entity.
+///
+/// If the server is full, the client is rejected or queued instead, depending on the [`ServerFullPolicy`]
+fn add_client(
+    commands: &mut Commands,
+    connection_manager: &mut ConnectionManager,
+    config: &ServerConfig,
+    queued_events: &mut EventWriter<ClientQueuedEvent>,
+    netserver: &mut ServerConnection,
+    client_id: ClientId,
+) {
+    let protocol_id = netserver
+        .connection_info(client_id)
+        .and_then(|info| info.protocol_id);
+    if let Some(limit) = config.max_connections {
+        // clients cannot skip the queue, even if a slot just opened
+        let full = connection_manager.connections.len() >= limit.max_connections
+            || !connection_manager.queue.is_empty();
+        if full {
+            match limit.when_full {
+                ServerFullPolicy::Queue { max_queue_size, .. }
+                    if connection_manager.queue.len() < max_queue_size =>
+                {
+                    match connection_manager.enqueue(client_id, protocol_id) {
+                        Ok(position) => {
+                            queued_events.send(ClientQueuedEvent {
+                                client_id,
+                                position,
+                            });
+                        }
+                        Err(e) => error!("Could not queue client {client_id:?}: {e:?}"),
+                    }
+                }
+                // the clients are also rejected when the queue is full
+                _ => {
+                    debug!(?client_id, "Server is full, rejecting client");
+                    let _ = netserver
+                        .reject(client_id, DeniedReason::ServerFull)
+                        .inspect_err(|e| error!("Could not reject client {client_id:?}: {e:?}"));
                 }
             }
+            return;
         }
     }
+    let client_entity = spawn_client_entity(commands);
+    connection_manager.add(client_id, client_entity, protocol_id);
+}
+
+/// Spawn the entity of a new client
+fn spawn_client_entity(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((ControlledEntities::default(), Name::new("Client")))
+        .id()
+}
+
+/// Disconnect the kicked clients once they received the reason of the kick, and the clients
+/// disconnected with [`ConnectionManager::disconnect_after_flush`] once their messages were delivered
+fn disconnect_closing_clients(
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut netservers: ResMut<ServerConnections>,
+) {
+    for client_id in connection_manager.clients_to_disconnect() {
+        debug!(?client_id, "Disconnecting client");
+        let _ = netservers.disconnect(client_id).inspect_err(|e| {
+            error!("Could not disconnect client {client_id:?}: {e:?}");
+        });
+    }
 }
 
 /// Read from internal buffers and apply the changes to the world
@@ -233,9 +458,38 @@ pub(crate) fn receive(
         )
         .unwrap_or_else(|e| {
             error!("Error during receive: {}", e);
+            // SAFETY: the errors are not accessed by `connection_manager.receive`
+            if let Some(mut errors) = unsafe { unsafe_world.get_resource_mut::<ServerErrors>() } {
+                errors.report(ErrorSeverity::Error, None, e);
+            }
+        });
+}
+
+/// Buffer the messages that were scheduled with [`ConnectionManager::send_message_at_tick`]
+pub(crate) fn release_scheduled_messages(
+    mut connection_manager: ResMut<ConnectionManager>,
+    tick_manager: Res<TickManager>,
+) {
+    connection_manager
+        .release_scheduled_messages(tick_manager.tick())
+        .unwrap_or_else(|e| {
+            error!("Error releasing scheduled messages: {}", e);
         });
 }
 
+/// Packets of a client prepared by a worker shard, waiting to be sent to the io
+struct PreparedPackets {
+    client_id: ClientId,
+    payloads: Result<Vec<Payload>, ServerError>,
+    send_time: Duration,
+    entities_considered: usize,
+    entities_sent: usize,
+    budget_exceeded: bool,
+    /// Allocations performed while preparing the packets, on the thread of the shard
+    #[cfg(feature = "alloc_audit")]
+    allocations: Allocations,
+}
+
 // or do additional send stuff here
 pub(crate) fn send(
     change_tick: SystemChangeTick,
@@ -243,32 +497,163 @@ pub(crate) fn send(
     mut connection_manager: ResMut<ConnectionManager>,
     tick_manager: Res<TickManager>,
     time_manager: Res<TimeManager>,
+    mut report: Option<ResMut<FrameBudgetReport>>,
+    mut tenants: ResMut<TenantManager>,
+    mut over_budget_events: EventWriter<TenantOverBudgetEvent>,
+    mut errors: ResMut<ServerErrors>,
+    config: Res<ServerConfig>,
 ) {
     trace!("Send packets to clients");
+    if let Some(report) = report.as_mut() {
+        **report = FrameBudgetReport {
+            tick: tick_manager.tick(),
+            ..default()
+        };
+    }
+    tenants.reset_stats();
     // SEND_PACKETS: send buffered packets to io
     let span = info_span!("send_packets").entered();
-    connection_manager
+    for (client_id, connection) in connection_manager
         .connections
         .iter_mut()
         .filter(|(_, connection)| !connection.is_local_client())
-        .try_for_each(|(client_id, connection)| {
-            let client_span =
-                info_span!("send_packets_to_client", client_id = ?client_id).entered();
+    {
+        // the connections of a tenant share its bandwidth quota
+        let limiter = tenants.client_limiter(*client_id);
+        if !connection.message_manager.uses_shared_limiter(limiter) {
+            connection
+                .message_manager
+                .set_shared_limiter(limiter.cloned());
+            // the replication updates are only considered sent once they fit in the bandwidth
+            let limited = connection.message_manager.is_limited();
+            connection
+                .replication_sender
+                .set_bandwidth_cap_enabled(limited);
+        }
+    }
+    // the packets of each shard are prepared in parallel, only the io is accessed from the main thread
+    let num_shards = config.worker_shards.map_or(1, |shards| shards.num_shards);
+    connection_manager.connections.set_num_shards(num_shards);
+    let prepare = |client_id, connection: &mut Connection| {
+        if connection.is_local_client() {
+            return None;
+        }
+        let _client_span = info_span!("send_packets_to_client", client_id = ?client_id).entered();
+        let start = Instant::now();
+        #[cfg(feature = "alloc_audit")]
+        let counter = AllocationCounter::start();
+        let payloads = connection.send_packets(&time_manager, &tick_manager);
+        let (entities_considered, entities_sent) = connection.replication_sender.take_frame_stats();
+        Some(PreparedPackets {
+            client_id,
+            payloads,
+            send_time: start.elapsed(),
+            entities_considered,
+            entities_sent,
+            budget_exceeded: connection.message_manager.budget_exceeded(),
+            #[cfg(feature = "alloc_audit")]
+            allocations: counter.finish(),
+        })
+    };
+    let prepared = connection_manager.connections.par_map(prepare);
+    #[cfg(feature = "alloc_audit")]
+    {
+        connection_manager.allocations.send = Allocations::default();
+        for prepared in &prepared {
+            connection_manager.allocations.send += prepared.allocations;
+        }
+    }
+    // reborrow trick to enable split borrows
+    let netservers = &mut *netservers;
+    for prepared in prepared {
+        let client_id = prepared.client_id;
+        let Some(netserver) = netservers
+            .client_server_map
+            .get(&client_id)
+            .and_then(|idx| netservers.servers.get_mut(*idx))
+        else {
+            error!(
+                "Error sending packets: {}",
+                ServerError::ServerConnectionNotFound
+            );
+            errors.report(
+                ErrorSeverity::Error,
+                Some(client_id),
+                ServerError::ServerConnectionNotFound,
+            );
+            continue;
+        };
+        let payloads = match prepared.payloads {
+            Ok(payloads) => payloads,
+            Err(e) => {
+                error!("Error sending packets: {}", e);
+                errors.report(ErrorSeverity::Error, Some(client_id), e);
+                continue;
+            }
+        };
+        // only the packets that were actually sent are counted
+        let mut bytes_sent = 0;
+        let mut packets_sent = 0;
+        // a failed send must not prevent sending to the other clients
+        for packet_byte in payloads {
+            if let Err(e) = netserver.send(packet_byte.as_slice(), client_id) {
+                // the failures of the transport were already emitted as a SendErrorEvent
+                if !e.is_send_error() {
+                    errors.report(ErrorSeverity::Error, Some(client_id), e);
+                }
+                break;
+            }
+            bytes_sent += packet_byte.len();
+            packets_sent += 1;
+        }
+        if let Some(stats) = tenants.client_stats_mut(client_id) {
+            stats.connected_clients += 1;
+            stats.send_time += prepared.send_time;
+            stats.bytes_sent += bytes_sent;
+            stats.packets_sent += packets_sent;
+            stats.entities_sent += prepared.entities_sent;
+            if prepared.budget_exceeded {
+                stats.connections_over_budget += 1;
+            }
+        }
+        if let Some(report) = report.as_mut() {
+            report.entities_considered += prepared.entities_considered;
+            report.entities_sent += prepared.entities_sent;
+            if let Some(info) = netserver.connection_info(client_id) {
+                *report.bytes_sent.entry(info.transport).or_default() += bytes_sent;
+            }
+            if prepared.budget_exceeded {
+                report.connections_over_budget += 1;
+            } else {
+                report.connections_within_budget += 1;
+            }
+        }
+    }
+    tenants.check_budgets(&mut over_budget_events);
+    // the queued clients only receive their position in the queue
+    connection_manager
+        .queue
+        .iter_mut()
+        .try_for_each(|connection| {
             let netserver_idx = *netservers
                 .client_server_map
-                .get(client_id)
+                .get(&connection.client_id)
                 .ok_or(ServerError::ServerConnectionNotFound)?;
             let netserver = netservers
                 .servers
                 .get_mut(netserver_idx)
                 .ok_or(ServerError::ServerConnectionNotFound)?;
-            for packet_byte in connection.send_packets(&time_manager, &tick_manager)? {
-                netserver.send(packet_byte.as_slice(), *client_id)?;
+            for packet_byte in connection
+                .message_manager
+                .send_packets(tick_manager.tick())?
+            {
+                netserver.send(packet_byte.as_slice(), connection.client_id)?;
             }
             Ok(())
         })
         .unwrap_or_else(|e: ServerError| {
-            error!("Error sending packets: {}", e);
+            error!("Error sending packets to queued clients: {}", e);
+            errors.report(ErrorSeverity::Error, None, e);
         });
 }
 
@@ -311,16 +696,37 @@ pub enum NetworkingState {
 /// - we can take into account any changes to the server config
 fn rebuild_server_connections(world: &mut World) {
     debug!("Rebuild server connection");
-    let server_config = world.resource::<ServerConfig>().clone();
+    let mut server_config = world.resource::<ServerConfig>().clone();
+    // deny the clients that use a different protocol, if enabled
+    let hash = protocol_hash(
+        world.resource::<ChannelRegistry>(),
+        world.resource::<ComponentRegistry>(),
+        world.resource::<MessageRegistry>(),
+    );
+    for net_config in server_config.net.iter_mut() {
+        #[allow(irrefutable_let_patterns)]
+        if let crate::connection::server::NetConfig::Netcode { config, .. } = net_config {
+            config.protocol_hash = config.check_protocol_hash.then_some(hash);
+        }
+    }
 
     // insert a new connection manager (to reset message numbers, ping manager, etc.)
-    let connection_manager = ConnectionManager::new(
+    let mut connection_manager = ConnectionManager::new(
         world.resource::<MessageRegistry>().clone(),
         world.resource::<ChannelRegistry>().clone(),
         server_config.replication,
         server_config.packet,
         server_config.ping,
+        server_config.protocol_shims,
     );
+    connection_manager.client_world_view = server_config.client_world_view;
+    let schema_versions = SchemaVersions::new(
+        world.resource::<MessageRegistry>(),
+        world.resource::<ComponentRegistry>(),
+    );
+    if let Err(e) = connection_manager.set_schema_versions(&schema_versions) {
+        error!("Could not serialize the wire format versions of the protocol: {e:?}");
+    }
     // // make sure the previous replication metadata is ported over to the new manager
     // if let Some(mut previous_manager) = world.get_resource_mut::<ConnectionManager>() {
     //     connection_manager.replicate_component_cache =
@@ -329,11 +735,14 @@ fn rebuild_server_connections(world: &mut World) {
     world.insert_resource(connection_manager);
 
     // rebuild the server connections and insert them
-    let server_connections = ServerConnections::new(server_config.net);
+    let server_connections = ServerConnections::new(server_config.net)
+        .with_auth_timeout(server_config.auth.map(|auth| auth.timeout));
     world.insert_resource(server_connections);
 }
 
 /// System that runs when we enter the Started state
+/// - validate the protocol: if it is invalid, the server goes back to the Stopped state and the issues are
+///   emitted as a critical [`ServerErrorEvent`](crate::server::error_events::ServerErrorEvent)
 /// - rebuild the server connections resource from the latest `ServerConfig`
 /// - rebuild the server connection manager
 /// - start listening on the server connections
@@ -343,6 +752,18 @@ fn on_start(world: &mut World) {
         return;
     }
 
+    // refuse to start with a misconfiguration that would only fail later at runtime
+    if let Err(e) = validate_protocol(
+        world.resource::<ChannelRegistry>(),
+        world.resource::<ServerConfig>(),
+    ) {
+        error!("The server was not started: {e}");
+        if let Some(mut errors) = world.get_resource_mut::<ServerErrors>() {
+            errors.report(ErrorSeverity::Critical, None, e);
+        }
+        world.insert_resource(NextState::Pending(NetworkingState::Stopped));
+        return;
+    }
     rebuild_server_connections(world);
     let _ = world
         .resource_mut::<ServerConnections>()
@@ -358,10 +779,45 @@ fn on_stop(mut server_connections: ResMut<ServerConnections>) {
         .inspect_err(|e| error!("Error stopping server connections: {:?}", e));
 }
 
+/// Resource present while the server is shutting down gracefully
+#[derive(Resource)]
+struct PendingShutdown {
+    /// Time after which the server stops even if some messages were not delivered
+    deadline: Timer,
+}
+
+/// Stop the server once the clients received all the reliable messages, or once the grace period is over
+fn finish_shutdown(
+    mut commands: Commands,
+    time: Res<Time>,
+    pending: Option<ResMut<PendingShutdown>>,
+    connection_manager: Res<ConnectionManager>,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+    let expired = pending.deadline.tick(time.delta()).finished();
+    if expired || !connection_manager.has_pending_messages() {
+        debug!(
+            ?expired,
+            "Stopping the server after the shutdown grace period"
+        );
+        commands.remove_resource::<PendingShutdown>();
+        commands.stop_server();
+    }
+}
+
 pub trait ServerCommands {
     fn start_server(&mut self);
 
     fn stop_server(&mut self);
+
+    /// Stop the server gracefully.
+    ///
+    /// The clients receive a [`ShutdownNoticeEvent`](crate::prelude::client::ShutdownNoticeEvent), then the
+    /// server keeps running until all the reliable messages are delivered (or until `grace_period` is over)
+    /// before disconnecting every client and stopping.
+    fn shutdown_server(&mut self, grace_period: Duration);
 }
 
 impl ServerCommands for Commands<'_, '_> {
@@ -372,4 +828,20 @@ impl ServerCommands for Commands<'_, '_> {
     fn stop_server(&mut self) {
         self.insert_resource(NextState::Pending(NetworkingState::Stopped));
     }
+
+    fn shutdown_server(&mut self, grace_period: Duration) {
+        self.add(move |world: &mut World| {
+            if let Some(mut connection_manager) = world.get_resource_mut::<ConnectionManager>() {
+                let _ = connection_manager
+                    .send_message_to_target::<ControlChannel, _>(
+                        &mut ServerShutdown { grace_period },
+                        NetworkTarget::All,
+                    )
+                    .inspect_err(|e| error!("Could not send the shutdown notice: {e:?}"));
+            }
+            world.insert_resource(PendingShutdown {
+                deadline: Timer::new(grace_period, TimerMode::Once),
+            });
+        });
+    }
 }
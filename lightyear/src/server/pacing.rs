@@ -0,0 +1,238 @@
+//! Pace the replication updates of each client to the rate at which it renders.
+//!
+//! A client that renders at 30 fps can't display more than 30 updates per second, so sending it the
+//! updates of a 60 Hz server wastes half of the bandwidth. Clients can report their render rate with
+//! [`ConnectionManager::report_render_rate`](crate::prelude::client::ConnectionManager::report_render_rate);
+//! when [`ServerConfig::render_rate_pacing`] is set, the server then sends the component updates to that
+//! client at most at the reported rate.
+//!
+//! Only the component updates are paced: the entity spawns/despawns and the component inserts/removals are
+//! sent as usual. The updates that are skipped are not lost, the next update contains all the changes since
+//! the last one that was sent.
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::prelude::server::{is_started, MessageEvent};
+use crate::server::config::ServerConfig;
+use crate::server::connection::ConnectionManager;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+use crate::shared::time_manager::WrappedTime;
+
+/// Policy to pace the replication updates to the render rate reported by the clients
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderRatePacing {
+    /// The updates are sent at least at this rate (in Hz), whatever the rate reported by the client
+    pub min_update_rate: f32,
+}
+
+impl Default for RenderRatePacing {
+    fn default() -> Self {
+        Self {
+            min_update_rate: 10.0,
+        }
+    }
+}
+
+impl RenderRatePacing {
+    /// Interval between two updates to a client that renders at `render_rate` fps
+    fn update_interval(&self, render_rate: f32) -> Duration {
+        let rate = render_rate.max(self.min_update_rate);
+        if rate.is_finite() && rate > 0.0 {
+            Duration::from_secs_f64(1.0 / rate as f64)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Message sent by a client to report its render rate (in frames per second)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RenderRateHint {
+    pub(crate) rate: f32,
+}
+
+/// Schedule of the component updates of a connection
+#[derive(Debug, Default)]
+pub(crate) struct UpdatePacing {
+    /// Render rate reported by the client
+    pub(crate) render_rate: Option<f32>,
+    /// Minimum interval between two updates, if the updates are paced
+    interval: Option<Duration>,
+    /// Time at which the next updates can be sent
+    next_send: Option<WrappedTime>,
+}
+
+impl UpdatePacing {
+    pub(crate) fn set_render_rate(&mut self, render_rate: f32, pacing: Option<&RenderRatePacing>) {
+        self.render_rate = Some(render_rate);
+        self.interval = pacing.map(|pacing| pacing.update_interval(render_rate));
+    }
+
+    /// Returns true if the component updates can be sent at `now`
+    pub(crate) fn is_due(&self, now: WrappedTime) -> bool {
+        match (self.interval, self.next_send) {
+            // a quarter of the interval of tolerance, so that the jitter of the frames doesn't make us
+            // skip an update
+            (Some(interval), Some(next_send)) => now + interval / 4 >= next_send,
+            _ => true,
+        }
+    }
+
+    /// Schedule the next updates after updates were sent at `now`
+    pub(crate) fn on_send(&mut self, now: WrappedTime) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        // keep a regular rhythm, unless we are late by more than an interval
+        self.next_send = Some(match self.next_send {
+            Some(next_send) if next_send + interval >= now => next_send + interval,
+            _ => now + interval,
+        });
+    }
+}
+
+pub(crate) struct RenderRatePacingPlugin;
+
+impl Plugin for RenderRatePacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            handle_render_rate_hints
+                .run_if(is_started)
+                .after(InternalMainSet::<ServerMarker>::EmitEvents),
+        );
+    }
+}
+
+/// Update the pacing of the clients that reported their render rate
+fn handle_render_rate_hints(
+    config: Res<ServerConfig>,
+    mut messages: ResMut<Events<MessageEvent<RenderRateHint>>>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    for message in messages.drain() {
+        let _ = connection_manager
+            .set_render_rate(
+                message.context,
+                message.message.rate,
+                config.render_rate_pacing.as_ref(),
+            )
+            .inspect_err(|e| error!("could not handle render rate hint: {:?}", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client;
+    use crate::prelude::server::Replicate;
+    use crate::prelude::ClientId;
+    use crate::tests::protocol::{ComponentSyncModeFull, ComponentSyncModeSimple};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[test]
+    fn test_update_pacing() {
+        let mut pacing = UpdatePacing::default();
+        pacing.set_render_rate(30.0, Some(&RenderRatePacing::default()));
+        let frame = Duration::from_nanos(16_666_667);
+        let mut sent = 0;
+        let mut now = WrappedTime::default();
+        for _ in 0..60 {
+            if pacing.is_due(now) {
+                pacing.on_send(now);
+                sent += 1;
+            }
+            now += frame;
+        }
+        assert_eq!(sent, 30);
+
+        // the minimum rate applies when the client renders very slowly
+        pacing.set_render_rate(1.0, Some(&RenderRatePacing::default()));
+        assert_eq!(pacing.interval, Some(Duration::from_millis(100)));
+        // without pacing, the updates are sent every time
+        pacing.set_render_rate(1.0, None);
+        assert!(pacing.is_due(WrappedTime::default()));
+    }
+
+    #[test]
+    fn test_report_render_rate() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .render_rate_pacing = Some(RenderRatePacing::default());
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ConnectionManager>()
+            .report_render_rate(30.0)
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+        let connection_manager = stepper.server_app.world().resource::<ConnectionManager>();
+        let connection = connection_manager
+            .connection(ClientId::Netcode(TEST_CLIENT_ID))
+            .unwrap();
+        assert_eq!(connection.render_rate(), Some(30.0));
+    }
+
+    /// The component updates skipped by the pacing are still sent when the actions of their group are sent
+    /// during the same frame
+    #[test]
+    fn test_paced_updates_with_actions() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .render_rate_pacing = Some(RenderRatePacing::default());
+        stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ConnectionManager>()
+            .report_render_rate(10.0)
+            .unwrap();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(0.0)))
+            .id();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let client_entity = stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .unwrap();
+
+        // the updates are sent every 10 frames, so some of these frames are paced out
+        for value in 1..=10 {
+            let mut entity = stepper.server_app.world_mut().entity_mut(server_entity);
+            entity.get_mut::<ComponentSyncModeFull>().unwrap().0 = value as f32;
+            // insert or remove a component, so that the actions of the group are sent during the same frame
+            if value % 2 == 1 {
+                entity.insert(ComponentSyncModeSimple(value as f32));
+            } else {
+                entity.remove::<ComponentSyncModeSimple>();
+            }
+            for _ in 0..20 {
+                stepper.frame_step();
+            }
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world()
+                    .get::<ComponentSyncModeFull>(client_entity),
+                Some(&ComponentSyncModeFull(value as f32))
+            );
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Recover from long pauses of the server process.
+//!
+//! When the server process is paused (stopped at a breakpoint, laptop going to sleep, etc.), bevy runs
+//! all the fixed-update ticks that were missed during the pause as soon as the server resumes, up to
+//! the [maximum delta](Time::<Virtual>::max_delta) of the virtual time, and the clients receive a burst of
+//! updates.
+//!
+//! When [`ServerConfig::pause_recovery`] is set (which is meant for development), a frame that comes more than
+//! that threshold after the previous one doesn't advance the virtual time: the server resumes from the tick it
+//! was paused at instead of catching up, and the clients resynchronize their ticks with the server.
+//! A [`ServerPausedEvent`] is emitted with the duration of the pause.
+use bevy::prelude::*;
+use bevy::time::{TimeSystem, TimeUpdateStrategy};
+use bevy::utils::{Duration, Instant};
+use tracing::info;
+
+use crate::server::config::ServerConfig;
+
+/// Bevy [`Event`] emitted on the server when it resumes from a pause that was longer than
+/// [`ServerConfig::pause_recovery`]
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ServerPausedEvent {
+    /// Real time elapsed since the previous frame
+    pub duration: Duration,
+}
+
+/// True if the virtual time was paused to skip the catch-up ticks of the current frame
+#[derive(Resource, Default)]
+struct SkippingPause(bool);
+
+pub(crate) struct PauseRecoveryPlugin;
+
+impl Plugin for PauseRecoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ServerPausedEvent>();
+        app.init_resource::<SkippingPause>();
+        app.add_systems(
+            First,
+            (
+                skip_pause.before(TimeSystem),
+                resume_after_pause.after(TimeSystem),
+            ),
+        );
+    }
+}
+
+/// Instant of the current frame, as it will be computed by bevy's [`TimeSystem`]
+fn frame_instant(strategy: &TimeUpdateStrategy, real_time: &Time<Real>) -> Option<Instant> {
+    match strategy {
+        TimeUpdateStrategy::Automatic => Some(Instant::now()),
+        TimeUpdateStrategy::ManualInstant(instant) => Some(*instant),
+        TimeUpdateStrategy::ManualDuration(duration) => {
+            real_time.last_update().map(|last| last + *duration)
+        }
+    }
+}
+
+/// Pause the virtual time for the current frame if the previous frame was too long ago
+fn skip_pause(
+    config: Res<ServerConfig>,
+    strategy: Option<Res<TimeUpdateStrategy>>,
+    real_time: Res<Time<Real>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut skipping: ResMut<SkippingPause>,
+    mut events: EventWriter<ServerPausedEvent>,
+) {
+    let Some(threshold) = config.pause_recovery else {
+        return;
+    };
+    if virtual_time.is_paused() {
+        return;
+    }
+    let Some((last, now)) = real_time.last_update().zip(frame_instant(
+        strategy
+            .as_deref()
+            .unwrap_or(&TimeUpdateStrategy::Automatic),
+        &real_time,
+    )) else {
+        return;
+    };
+    let duration = now.saturating_duration_since(last);
+    if duration > threshold {
+        info!(
+            ?duration,
+            "The server was paused, skipping the ticks that were missed"
+        );
+        virtual_time.pause();
+        skipping.0 = true;
+        events.send(ServerPausedEvent { duration });
+    }
+}
+
+fn resume_after_pause(
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut skipping: ResMut<SkippingPause>,
+) {
+    if std::mem::take(&mut skipping.0) {
+        virtual_time.unpause();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client;
+    use crate::prelude::server::ConnectionManager;
+    use crate::prelude::TickManager;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<ServerPausedEvent>);
+
+    fn pause(pause_recovery: Option<Duration>) -> (BevyStepper, i16) {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .pause_recovery = pause_recovery;
+        stepper.server_app.init_resource::<Received>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<ServerPausedEvent>, mut received: ResMut<Received>| {
+                received.0.extend(events.read().copied());
+            },
+        );
+        let tick = stepper.server_app.world().resource::<TickManager>().tick();
+        // the server is stopped at a breakpoint, the client keeps running
+        let pause = Duration::from_secs(10);
+        stepper.advance_time(pause);
+        stepper.client_app.update();
+        stepper.server_app.update();
+        let ticks = stepper.server_app.world().resource::<TickManager>().tick() - tick;
+        (stepper, ticks)
+    }
+
+    #[test]
+    fn test_pause_catch_up() {
+        let (stepper, ticks) = pause(None);
+        // the server catches up on the missed ticks, up to the max delta of the virtual time
+        assert!(ticks > 1);
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<Received>()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pause_recovery() {
+        let (mut stepper, ticks) = pause(Some(Duration::from_secs(1)));
+        assert_eq!(ticks, 0);
+        assert_eq!(
+            stepper.server_app.world().resource::<Received>().0,
+            vec![ServerPausedEvent {
+                duration: Duration::from_secs(10)
+            }]
+        );
+        assert!(!stepper
+            .server_app
+            .world()
+            .resource::<Time<Virtual>>()
+            .is_paused());
+
+        // the client is still connected, and resynchronizes with the server
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .connected_clients()
+                .count(),
+            1
+        );
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+    }
+}
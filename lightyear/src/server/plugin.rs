@@ -12,13 +12,22 @@ use crate::server::clients::ClientsMetadataPlugin;
 use bevy::app::PluginGroupBuilder;
 use bevy::prelude::*;
 
+use crate::server::diagnostics::ServerDiagnosticsPlugin;
+use crate::server::error_events::ServerErrorsPlugin;
 use crate::server::events::ServerEventsPlugin;
+use crate::server::idle::IdleKickPlugin;
 use crate::server::networking::ServerNetworkingPlugin;
+use crate::server::pacing::RenderRatePacingPlugin;
+use crate::server::pause::PauseRecoveryPlugin;
+use crate::server::queue::ConnectionQueuePlugin;
 use crate::server::relevance::immediate::NetworkRelevancePlugin;
+use crate::server::relevance::limit::EntityLimitPlugin;
 use crate::server::relevance::room::RoomPlugin;
 use crate::server::replication::{
     receive::ServerReplicationReceivePlugin, send::ServerReplicationSendPlugin,
 };
+use crate::server::session::SessionPlugin;
+use crate::server::tenant::TenantPlugin;
 use crate::shared::plugin::SharedPlugin;
 
 use super::config::ServerConfig;
@@ -35,6 +44,7 @@ use super::config::ServerConfig;
 ///   disabled if you don't need client to server replication.
 /// - [`ServerReplicationSendPlugin`]: Handles the replication of entities and resources from the server to the client. This can be
 ///   disabled if you don't need server to client replication.
+/// - [`ServerDiagnosticsPlugin`]: Measures the network budget used on each tick. Can be disabled if you don't need it.
 pub struct ServerPlugins {
     pub config: ServerConfig,
 }
@@ -63,7 +73,16 @@ impl PluginGroup for ServerPlugins {
             .add(ServerNetworkingPlugin)
             .add(NetworkRelevancePlugin)
             .add(RoomPlugin)
+            .add(EntityLimitPlugin)
             .add(ClientsMetadataPlugin)
+            .add(SessionPlugin)
+            .add(ConnectionQueuePlugin)
+            .add(IdleKickPlugin)
+            .add(RenderRatePacingPlugin)
+            .add(PauseRecoveryPlugin)
+            .add(TenantPlugin)
+            .add(ServerErrorsPlugin)
+            .add(ServerDiagnosticsPlugin::default())
             .add(ServerReplicationReceivePlugin { tick_interval })
             .add(ServerReplicationSendPlugin { tick_interval })
     }
@@ -0,0 +1,228 @@
+//! Recycle the network identity of replicated entities that are spawned and despawned frequently.
+//!
+//! Short-lived entities like projectiles create a lot of replication traffic: each of them allocates a new
+//! [`ReplicationGroup`] channel on every connection, and the client spawns and despawns a new entity every time.
+//!
+//! Entities spawned with [`PoolCommandsExt::spawn_pooled`] get a [`StableId`] and a [`ReplicationGroup`] id from the
+//! [`EntityPool`]. When the entity is despawned, its id is returned to the pool and given to the next pooled entity, so that:
+//! - the per-connection replication group channels are reused instead of being allocated again
+//! - if a pooled entity is despawned and another one is spawned in the same tick, the client reuses its existing
+//!   entity instead of despawning and spawning one (see [`TargetEntity::Stable`])
+//!
+//! The pool hands out ids starting from [`EntityPool::first_id`]; the [`StableId`]s and [`ReplicationGroup`] ids
+//! that you use for other entities should not overlap with that range.
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::prelude::{ReplicationGroup, StableId, TargetEntity};
+
+/// Pool of ids for the entities spawned with [`PoolCommandsExt::spawn_pooled`]
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct EntityPool {
+    first_id: u32,
+    next_id: u32,
+    /// Ids of the despawned entities, the most recently released id is reused first
+    free: Vec<u32>,
+}
+
+impl Default for EntityPool {
+    fn default() -> Self {
+        Self::new(1 << 31)
+    }
+}
+
+impl EntityPool {
+    /// Create a pool that hands out ids starting from `first_id`.
+    ///
+    /// The ids are used as [`ReplicationGroup`] ids; since they fit in a u32 they can never
+    /// be equal to the id of a group created from an entity.
+    pub fn new(first_id: u32) -> Self {
+        Self {
+            first_id,
+            next_id: first_id,
+            free: Vec::new(),
+        }
+    }
+
+    /// The first id handed out by the pool
+    pub fn first_id(&self) -> u32 {
+        self.first_id
+    }
+
+    /// Number of ids that are currently used by pooled entities
+    pub fn len(&self) -> usize {
+        (self.next_id - self.first_id) as usize - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn acquire(&mut self) -> u32 {
+        self.free.pop().unwrap_or_else(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+            id
+        })
+    }
+
+    fn release(&mut self, id: u32) {
+        self.free.push(id);
+    }
+}
+
+/// Component added on the entities spawned with [`PoolCommandsExt::spawn_pooled`]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pooled {
+    id: u32,
+}
+
+impl Pooled {
+    /// The [`StableId`] used to replicate the entity
+    pub fn stable_id(&self) -> StableId {
+        StableId(self.id as u64)
+    }
+}
+
+pub trait PoolCommandsExt {
+    /// Spawn a replicated entity that reuses the network identity of a despawned pooled entity.
+    ///
+    /// The bundle should contain a [`Replicate`](crate::prelude::server::Replicate) bundle.
+    /// The [`TargetEntity`] and the id of the [`ReplicationGroup`] of the entity are set by the pool.
+    fn spawn_pooled<B: Bundle>(&mut self, bundle: B) -> EntityCommands<'_>;
+}
+
+impl PoolCommandsExt for Commands<'_, '_> {
+    fn spawn_pooled<B: Bundle>(&mut self, bundle: B) -> EntityCommands<'_> {
+        let mut entity_commands = self.spawn(bundle);
+        entity_commands.add(|entity: Entity, world: &mut World| {
+            let id = world.resource_mut::<EntityPool>().acquire();
+            let Some(mut entity_mut) = world.get_entity_mut(entity) else {
+                world.resource_mut::<EntityPool>().release(id);
+                return;
+            };
+            let group = entity_mut
+                .get::<ReplicationGroup>()
+                .cloned()
+                .unwrap_or_default()
+                .set_id(id as u64);
+            entity_mut.insert((
+                Pooled { id },
+                TargetEntity::Stable(StableId(id as u64)),
+                group,
+            ));
+        });
+        entity_commands
+    }
+}
+
+pub(crate) struct EntityPoolPlugin;
+
+impl Plugin for EntityPoolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EntityPool>();
+        app.observe(release_pooled);
+    }
+}
+
+/// Return the id of a despawned pooled entity to the pool
+fn release_pooled(
+    trigger: Trigger<OnRemove, Pooled>,
+    query: Query<&Pooled>,
+    mut pool: ResMut<EntityPool>,
+) {
+    if let Ok(pooled) = query.get(trigger.entity()) {
+        pool.release(pooled.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client;
+    use crate::prelude::server::Replicate;
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    fn spawn_pooled(stepper: &mut BevyStepper, value: f32) -> Entity {
+        let entity = stepper
+            .server_app
+            .world_mut()
+            .commands()
+            .spawn_pooled((Replicate::default(), ComponentSyncModeFull(value)))
+            .id();
+        stepper.server_app.world_mut().flush();
+        entity
+    }
+
+    fn client_entity(stepper: &BevyStepper, server_entity: Entity) -> Option<Entity> {
+        stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+    }
+
+    #[test]
+    fn test_pooled_entities() {
+        let mut stepper = BevyStepper::default();
+        let first = spawn_pooled(&mut stepper, 1.0);
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_first = client_entity(&stepper, first).expect("entity was not replicated");
+        let stable_id = *stepper.server_app.world().get::<Pooled>(first).unwrap();
+
+        // despawn and spawn a pooled entity in the same tick: the id and the client entity are reused
+        stepper.server_app.world_mut().despawn(first);
+        let second = spawn_pooled(&mut stepper, 2.0);
+        assert_eq!(
+            stepper.server_app.world().get::<Pooled>(second),
+            Some(&stable_id)
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+        assert_eq!(client_entity(&stepper, second), Some(client_first));
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_first),
+            Some(&ComponentSyncModeFull(2.0))
+        );
+
+        // the despawn is replicated if the id is not reused right away
+        stepper.server_app.world_mut().despawn(second);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(stepper
+            .client_app
+            .world()
+            .get_entity(client_first)
+            .is_none());
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<EntityPool>()
+            .is_empty());
+
+        // the replication group channel is reused for the next pooled entity
+        let third = spawn_pooled(&mut stepper, 3.0);
+        assert_eq!(
+            stepper.server_app.world().get::<Pooled>(third),
+            Some(&stable_id)
+        );
+        stepper.frame_step();
+        stepper.frame_step();
+        let client_third = client_entity(&stepper, third).expect("entity was not replicated");
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentSyncModeFull>(client_third),
+            Some(&ComponentSyncModeFull(3.0))
+        );
+    }
+}
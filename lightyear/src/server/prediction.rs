@@ -71,13 +71,16 @@ pub(crate) fn handle_pre_predicted(
         commands.add(move |world: &mut World| {
             // update the mapping so that when we send updates, the server entity gets mapped
             // to the client's confirmed entity
-            world
+            // the client may have disconnected before the command is applied
+            if let Ok(connection) = world
                 .resource_mut::<ServerConnectionManager>()
                 .connection_mut(sending_client)
-                .unwrap()
-                .replication_receiver
-                .remote_entity_map
-                .insert(confirmed_entity, local_entity)
+            {
+                connection
+                    .replication_receiver
+                    .remote_entity_map
+                    .insert(confirmed_entity, local_entity);
+            }
         })
     }
 }
@@ -0,0 +1,323 @@
+//! Limit the number of clients connected to the server.
+//!
+//! When [`ServerConfig::max_connections`] is set and the server is full, new clients are either
+//! rejected with [`DeniedReason::ServerFull`](crate::connection::server::DeniedReason::ServerFull),
+//! or placed in a queue, depending on the [`ServerFullPolicy`]. Clients are also rejected when the queue
+//! itself is full.
+//!
+//! A queued client stays connected but doesn't receive any replication or message; the server periodically
+//! sends it its position in the queue (see [`QueuePositionEvent`](crate::prelude::client::QueuePositionEvent)).
+//! When a slot opens, the first client of the queue is added to the server as if it just connected:
+//! a [`ConnectEvent`](crate::prelude::server::ConnectEvent) and a [`QueueSlotOpenedEvent`] are emitted.
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use tracing::error;
+
+use crate::connection::id::ClientId;
+use crate::server::config::ServerConfig;
+use crate::server::connection::ConnectionManager;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+/// Maximum number of clients connected to the server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectionLimit {
+    /// Maximum number of clients that can be connected at the same time
+    pub max_connections: usize,
+    /// What happens to the clients that connect while the server is full
+    pub when_full: ServerFullPolicy,
+}
+
+impl ConnectionLimit {
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            max_connections,
+            when_full: ServerFullPolicy::Reject,
+        }
+    }
+
+    /// Place the clients that connect while the server is full in a queue, and report their position
+    /// every `report_interval`
+    pub fn with_queue(mut self, report_interval: Duration) -> Self {
+        self.when_full = ServerFullPolicy::Queue {
+            report_interval,
+            max_queue_size: DEFAULT_MAX_QUEUE_SIZE,
+        };
+        self
+    }
+
+    /// Set the maximum number of clients in the queue. Does nothing if the clients are not queued
+    pub fn with_max_queue_size(mut self, max_queue_size: usize) -> Self {
+        if let ServerFullPolicy::Queue {
+            max_queue_size: size,
+            ..
+        } = &mut self.when_full
+        {
+            *size = max_queue_size;
+        }
+        self
+    }
+}
+
+/// Default maximum number of clients waiting in the connection queue
+pub const DEFAULT_MAX_QUEUE_SIZE: usize = 128;
+
+/// What happens to the clients that connect while the server is full
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ServerFullPolicy {
+    /// Reject the client with [`DeniedReason::ServerFull`](crate::connection::server::DeniedReason::ServerFull)
+    #[default]
+    Reject,
+    /// Keep the client in a queue until a slot opens
+    Queue {
+        /// Interval at which the queued clients receive their position in the queue
+        report_interval: Duration,
+        /// Maximum number of clients in the queue. The clients that connect while the queue is full are rejected
+        /// with [`DeniedReason::ServerFull`](crate::connection::server::DeniedReason::ServerFull).
+        ///
+        /// The default is [`DEFAULT_MAX_QUEUE_SIZE`].
+        max_queue_size: usize,
+    },
+}
+
+/// Bevy [`Event`] emitted on the server when a client connects while the server is full
+/// and is placed in the connection queue
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ClientQueuedEvent {
+    pub client_id: ClientId,
+    /// Position of the client in the queue (starting at 1)
+    pub position: usize,
+}
+
+/// Bevy [`Event`] emitted on the server when a slot opened and a queued client was added to the server
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct QueueSlotOpenedEvent {
+    pub client_id: ClientId,
+    /// The client entity that was spawned for the client
+    pub entity: Entity,
+}
+
+pub(crate) struct ConnectionQueuePlugin;
+
+impl Plugin for ConnectionQueuePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ClientQueuedEvent>();
+        app.add_event::<QueueSlotOpenedEvent>();
+        app.add_systems(
+            PostUpdate,
+            report_queue_positions.before(InternalMainSet::<ServerMarker>::Send),
+        );
+    }
+}
+
+/// Periodically send their position in the queue to the queued clients
+fn report_queue_positions(
+    time: Res<Time<Real>>,
+    config: Res<ServerConfig>,
+    mut since_last_report: Local<Duration>,
+    connection_manager: Option<ResMut<ConnectionManager>>,
+) {
+    let Some(ServerFullPolicy::Queue {
+        report_interval, ..
+    }) = config.max_connections.map(|limit| limit.when_full)
+    else {
+        return;
+    };
+    let Some(mut connection_manager) = connection_manager else {
+        return;
+    };
+    *since_last_report += time.delta();
+    if *since_last_report < report_interval {
+        return;
+    }
+    *since_last_report = Duration::ZERO;
+    let _ = connection_manager
+        .report_queue_positions()
+        .inspect_err(|e| error!("Could not report the queue positions: {e:?}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::networking::{ClientCommands, NetworkingState};
+    use crate::connection::client::DisconnectReason;
+    use crate::connection::server::DeniedReason;
+    use crate::prelude::client::{
+        self, InterpolationConfig, PredictionConfig, QueueAdmittedEvent, QueuePositionEvent,
+        SyncConfig,
+    };
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<String>);
+
+    /// Start a server that accepts a single client, with two clients trying to connect
+    fn setup(limit: ConnectionLimit) -> MultiBevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = MultiBevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .max_connections = Some(limit);
+        for client_app in [&mut stepper.client_app_1, &mut stepper.client_app_2] {
+            client_app.init_resource::<Received>();
+            client_app.add_systems(
+                Update,
+                |mut positions: EventReader<QueuePositionEvent>,
+                 mut admitted: EventReader<QueueAdmittedEvent>,
+                 mut disconnections: EventReader<client::DisconnectEvent>,
+                 mut received: ResMut<Received>| {
+                    received.0.extend(
+                        positions
+                            .read()
+                            .map(|event| format!("position {}", event.position)),
+                    );
+                    received
+                        .0
+                        .extend(admitted.read().map(|_| "admitted".to_string()));
+                    received.0.extend(disconnections.read().filter_map(
+                        |event| match &event.reason {
+                            Some(DisconnectReason::Denied(DeniedReason::ServerFull)) => {
+                                Some("server full".to_string())
+                            }
+                            _ => None,
+                        },
+                    ));
+                },
+            );
+        }
+        stepper.init();
+        stepper
+    }
+
+    /// Returns the (connected, other) client apps
+    fn client_apps(stepper: &mut MultiBevyStepper) -> (&mut App, &mut App) {
+        let connected = stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connected_clients()
+            .collect::<Vec<_>>();
+        assert_eq!(connected.len(), 1);
+        if connected[0] == ClientId::Netcode(TEST_CLIENT_ID_1) {
+            (&mut stepper.client_app_1, &mut stepper.client_app_2)
+        } else {
+            (&mut stepper.client_app_2, &mut stepper.client_app_1)
+        }
+    }
+
+    #[test]
+    fn test_reject_when_full() {
+        let mut stepper = setup(ConnectionLimit::new(1));
+        let (connected, rejected) = client_apps(&mut stepper);
+        assert!(connected
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+        assert_eq!(
+            rejected.world().resource::<Received>().0,
+            vec!["server full".to_string()]
+        );
+        assert_eq!(
+            rejected.world().resource::<State<NetworkingState>>().get(),
+            &NetworkingState::Disconnected
+        );
+    }
+
+    #[test]
+    fn test_reject_when_queue_full() {
+        let mut stepper = setup(
+            ConnectionLimit::new(1)
+                .with_queue(Duration::from_millis(100))
+                .with_max_queue_size(0),
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .queued_clients()
+                .count(),
+            0
+        );
+        let (_, rejected) = client_apps(&mut stepper);
+        assert_eq!(
+            rejected.world().resource::<Received>().0,
+            vec!["server full".to_string()]
+        );
+        assert_eq!(
+            rejected.world().resource::<State<NetworkingState>>().get(),
+            &NetworkingState::Disconnected
+        );
+    }
+
+    #[test]
+    fn test_queue_until_slot_opens() {
+        let mut stepper = setup(ConnectionLimit::new(1).with_queue(Duration::from_millis(100)));
+        stepper.server_app.init_resource::<Received>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<QueueSlotOpenedEvent>, mut received: ResMut<Received>| {
+                received.0.extend(
+                    events
+                        .read()
+                        .map(|event| format!("slot opened {:?}", event.client_id)),
+                );
+            },
+        );
+        let connection_manager = stepper.server_app.world().resource::<ConnectionManager>();
+        let queued = connection_manager.queued_clients().collect::<Vec<_>>();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(connection_manager.queue_position(queued[0]), Some(1));
+
+        let (connected, waiting) = client_apps(&mut stepper);
+        // the queued client is connected but doesn't get admitted in the server
+        assert_eq!(
+            waiting.world().resource::<State<NetworkingState>>().get(),
+            &NetworkingState::Connected
+        );
+        assert!(!waiting
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+        let received = &waiting.world().resource::<Received>().0;
+        assert!(received.len() > 1);
+        assert!(received.iter().all(|event| event == "position 1"));
+
+        // a slot opens when the connected client leaves
+        connected.world_mut().commands().disconnect_client();
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        let connection_manager = stepper.server_app.world().resource::<ConnectionManager>();
+        assert_eq!(
+            connection_manager.connected_clients().collect::<Vec<_>>(),
+            queued
+        );
+        assert_eq!(connection_manager.queued_clients().count(), 0);
+        assert_eq!(
+            stepper.server_app.world().resource::<Received>().0,
+            vec![format!("slot opened {:?}", queued[0])]
+        );
+        let (admitted, _) = client_apps(&mut stepper);
+        assert_eq!(
+            admitted.world().resource::<Received>().0.last(),
+            Some(&"admitted".to_string())
+        );
+        assert!(admitted
+            .world()
+            .resource::<client::ConnectionManager>()
+            .is_synced());
+    }
+}
@@ -0,0 +1,456 @@
+/*! Distance-based network relevance
+
+Entities that have a [`RelevanceBand`] (and are replicated with
+[`NetworkRelevanceMode::InterestManagement`](crate::prelude::NetworkRelevanceMode::InterestManagement)) become relevant
+to a client when one of the client's [`RelevanceViewer`]s comes within [`RelevanceBand::enter_radius`] of them, and stop
+being relevant when all the viewers are further than [`RelevanceBand::exit_radius`].
+
+Using an exit radius larger than the enter radius (hysteresis) prevents an entity that hovers around the boundary from
+being despawned and respawned on the client every time it crosses it. Each class of entities can use its own band:
+
+```rust
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use lightyear::prelude::server::*;
+
+fn spawn(mut commands: Commands) {
+    // the player's entity is the point of view of the client
+    commands.spawn((
+        RelevanceViewer(ClientId::Netcode(1)),
+        TransformBundle::default(),
+    ));
+    // large entities are visible from further away
+    commands.spawn((
+        Replicate {
+            relevance_mode: NetworkRelevanceMode::InterestManagement,
+            ..default()
+        },
+        RelevanceBand::new(200.0, 220.0),
+        TransformBundle::from_transform(Transform::from_xyz(100.0, 0.0, 0.0)),
+    ));
+}
+```
+
+The distances are computed from the [`GlobalTransform`]s of the viewers and the entities, after they are propagated.
+
+The [`DistanceRelevancePlugin`] is not part of the [`ServerPlugins`](crate::prelude::server::ServerPlugins), it must be
+added to the server app:
+
+```rust,ignore
+app.add_plugins(DistanceRelevancePlugin::default());
+```
+
+The entities are bucketed in a grid of [`DistanceRelevancePlugin::cell_size`], so that each viewer only checks the
+entities of the cells around it. The cell size should be in the order of the enter radii of the bands.
+*/
+use bevy::ecs::entity::EntityHashSet;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy::utils::HashMap;
+
+use crate::prelude::{server::is_started, ClientId};
+use crate::server::relevance::immediate::{NetworkRelevanceSet, RelevanceManager};
+use crate::shared::sets::{InternalReplicationSet, ServerMarker};
+
+/// Distances at which an entity gains and loses relevance for the clients
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct RelevanceBand {
+    /// The entity becomes relevant to a client when one of its viewers is within this distance
+    pub enter_radius: f32,
+    /// The entity stops being relevant to a client when all its viewers are further than this distance
+    pub exit_radius: f32,
+}
+
+impl RelevanceBand {
+    /// Create a band with hysteresis. The exit radius can't be smaller than the enter radius.
+    pub fn new(enter_radius: f32, exit_radius: f32) -> Self {
+        Self {
+            enter_radius,
+            exit_radius: exit_radius.max(enter_radius),
+        }
+    }
+
+    /// Create a band without hysteresis
+    pub fn radius(radius: f32) -> Self {
+        Self::new(radius, radius)
+    }
+}
+
+/// Marks an entity whose position is a point of view of a client for the [`RelevanceBand`]s.
+///
+/// A client can have multiple viewers: an entity is relevant if it is in range of any of them.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct RelevanceViewer(pub ClientId);
+
+/// Entities that are in the band of each client
+#[derive(Resource, Default, Debug)]
+struct InRange(HashMap<ClientId, EntityHashSet>);
+
+/// Entities with a [`RelevanceBand`], bucketed by the cell of the grid that contains them
+#[derive(Resource, Debug)]
+struct RelevanceGrid {
+    cell_size: f32,
+    cells: HashMap<IVec3, Vec<Entity>>,
+    /// Largest enter radius of the bands
+    max_enter_radius: f32,
+}
+
+impl RelevanceGrid {
+    fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            max_enter_radius: 0.0,
+        }
+    }
+
+    fn cell(&self, position: Vec3) -> IVec3 {
+        (position / self.cell_size).floor().as_ivec3()
+    }
+
+    fn clear(&mut self) {
+        // keep the allocations of the cells that are still used
+        self.cells.retain(|_, entities| {
+            let used = !entities.is_empty();
+            entities.clear();
+            used
+        });
+        self.max_enter_radius = 0.0;
+    }
+
+    fn insert(&mut self, entity: Entity, position: Vec3, band: &RelevanceBand) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push(entity);
+        self.max_enter_radius = self.max_enter_radius.max(band.enter_radius);
+    }
+
+    /// Call `f` on the entities of the cells that could be within an enter radius of the `position`
+    fn for_each_candidate(&self, position: Vec3, mut f: impl FnMut(Entity)) {
+        let min = self.cell(position - Vec3::splat(self.max_enter_radius));
+        let max = self.cell(position + Vec3::splat(self.max_enter_radius));
+        let size = (max - min + IVec3::ONE).as_i64vec3();
+        // if the range covers more cells than the ones that are occupied, go through the occupied cells instead
+        if size.x * size.y * size.z > self.cells.len() as i64 {
+            self.cells
+                .iter()
+                .filter(|(cell, _)| cell.cmpge(min).all() && cell.cmple(max).all())
+                .for_each(|(_, entities)| entities.iter().copied().for_each(&mut f));
+            return;
+        }
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    if let Some(entities) = self.cells.get(&IVec3::new(x, y, z)) {
+                        entities.iter().copied().for_each(&mut f);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Plugin that updates the relevance of the entities with a [`RelevanceBand`]
+#[derive(Debug, Clone)]
+pub struct DistanceRelevancePlugin {
+    /// Size of the cells of the grid in which the entities are bucketed
+    pub cell_size: f32,
+}
+
+impl Default for DistanceRelevancePlugin {
+    fn default() -> Self {
+        Self { cell_size: 100.0 }
+    }
+}
+
+impl Plugin for DistanceRelevancePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<RelevanceBand>();
+        app.register_type::<RelevanceViewer>();
+        app.init_resource::<InRange>();
+        app.insert_resource(RelevanceGrid::new(self.cell_size));
+        app.add_systems(
+            PostUpdate,
+            update_distance_relevance
+                .after(TransformSystem::TransformPropagate)
+                .before(NetworkRelevanceSet::UpdateRelevance)
+                .in_set(InternalReplicationSet::<ServerMarker>::SendMessages)
+                .run_if(is_started),
+        );
+    }
+}
+
+fn update_distance_relevance(
+    viewers: Query<(&RelevanceViewer, &GlobalTransform)>,
+    entities: Query<(Entity, &RelevanceBand, &GlobalTransform)>,
+    mut grid: ResMut<RelevanceGrid>,
+    mut in_range: ResMut<InRange>,
+    mut relevance_manager: ResMut<RelevanceManager>,
+) {
+    let mut positions: HashMap<ClientId, Vec<Vec3>> = HashMap::default();
+    for (viewer, transform) in viewers.iter() {
+        positions
+            .entry(viewer.0)
+            .or_default()
+            .push(transform.translation());
+    }
+    // the clients that don't have a viewer anymore lose the entities that were in range
+    in_range.0.retain(|client_id, in_range| {
+        if positions.contains_key(client_id) {
+            return true;
+        }
+        for entity in in_range.drain() {
+            relevance_manager.lose_relevance(*client_id, entity);
+        }
+        false
+    });
+    if positions.is_empty() {
+        return;
+    }
+    grid.clear();
+    for (entity, band, transform) in entities.iter() {
+        grid.insert(entity, transform.translation(), band);
+    }
+    let distance_squared = |positions: &[Vec3], transform: &GlobalTransform| {
+        positions
+            .iter()
+            .map(|position| position.distance_squared(transform.translation()))
+            .fold(f32::INFINITY, f32::min)
+    };
+    for (client_id, positions) in positions.iter() {
+        let in_range = in_range.0.entry(*client_id).or_default();
+        // the entities that were in range lose relevance when they are despawned, lose their band,
+        // or when all the viewers are outside of their exit radius
+        in_range.retain(|entity| {
+            let keep = entities.get(*entity).is_ok_and(|(_, band, transform)| {
+                distance_squared(positions, transform) <= band.exit_radius * band.exit_radius
+            });
+            if !keep {
+                relevance_manager.lose_relevance(*client_id, *entity);
+            }
+            keep
+        });
+        for position in positions {
+            grid.for_each_candidate(*position, |entity| {
+                if in_range.contains(&entity) {
+                    return;
+                }
+                let Ok((_, band, transform)) = entities.get(entity) else {
+                    return;
+                };
+                if distance_squared(positions, transform) <= band.enter_radius * band.enter_radius {
+                    in_range.insert(entity);
+                    relevance_manager.gain_relevance(*client_id, entity);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::prelude::server::Replicate;
+    use crate::prelude::NetworkRelevanceMode;
+    use crate::prelude::{client, SharedConfig, TickConfig};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn setup(cell_size: f32) -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            client::ClientConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .add_plugins((TransformPlugin, DistanceRelevancePlugin { cell_size }));
+        stepper.init();
+        stepper
+    }
+
+    fn is_in_range(stepper: &BevyStepper, entity: Entity) -> bool {
+        stepper
+            .server_app
+            .world()
+            .resource::<InRange>()
+            .0
+            .get(&ClientId::Netcode(TEST_CLIENT_ID))
+            .is_some_and(|in_range| in_range.contains(&entity))
+    }
+
+    fn is_replicated(stepper: &BevyStepper, server_entity: Entity) -> bool {
+        stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .is_some_and(|client_entity| {
+                stepper
+                    .client_app
+                    .world()
+                    .get_entity(client_entity)
+                    .is_some()
+            })
+    }
+
+    fn move_to(stepper: &mut BevyStepper, entity: Entity, x: f32) {
+        *stepper
+            .server_app
+            .world_mut()
+            .get_mut::<GlobalTransform>(entity)
+            .unwrap() = GlobalTransform::from_translation(Vec3::new(x, 0.0, 0.0));
+        stepper.frame_step();
+        stepper.frame_step();
+    }
+
+    #[test]
+    fn test_relevance_band_hysteresis() {
+        let mut stepper = setup(100.0);
+        stepper.server_app.world_mut().spawn((
+            RelevanceViewer(ClientId::Netcode(TEST_CLIENT_ID)),
+            GlobalTransform::IDENTITY,
+        ));
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((
+                Replicate {
+                    relevance_mode: NetworkRelevanceMode::InterestManagement,
+                    ..default()
+                },
+                RelevanceBand::new(10.0, 15.0),
+                GlobalTransform::from_translation(Vec3::new(12.0, 0.0, 0.0)),
+            ))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        // between the two radii, but was never in range
+        assert!(!is_replicated(&stepper, server_entity));
+
+        move_to(&mut stepper, server_entity, 9.0);
+        assert!(is_replicated(&stepper, server_entity));
+
+        // hovering around the enter radius doesn't despawn the entity
+        move_to(&mut stepper, server_entity, 12.0);
+        assert!(is_replicated(&stepper, server_entity));
+        move_to(&mut stepper, server_entity, 9.0);
+        assert!(is_replicated(&stepper, server_entity));
+
+        move_to(&mut stepper, server_entity, 16.0);
+        assert!(!is_replicated(&stepper, server_entity));
+    }
+
+    fn candidates(grid: &RelevanceGrid, position: Vec3) -> Vec<Entity> {
+        let mut candidates = vec![];
+        grid.for_each_candidate(position, |entity| candidates.push(entity));
+        candidates.sort();
+        candidates
+    }
+
+    #[test]
+    fn test_grid_candidates() {
+        let band = RelevanceBand::radius(10.0);
+        let mut grid = RelevanceGrid::new(10.0);
+        // more occupied cells than cells in range: only the cells in range are visited
+        for i in -15..=15 {
+            grid.insert(
+                Entity::from_raw((i + 15) as u32),
+                Vec3::new(i as f32 * 10.0, 0.0, 0.0),
+                &band,
+            );
+        }
+        let expected = vec![
+            Entity::from_raw(14),
+            Entity::from_raw(15),
+            Entity::from_raw(16),
+        ];
+        assert_eq!(candidates(&grid, Vec3::splat(5.0)), expected);
+
+        // fewer occupied cells than cells in range: the occupied cells are filtered
+        let mut grid = RelevanceGrid::new(10.0);
+        for i in [-15, -1, 0, 1, 15] {
+            grid.insert(
+                Entity::from_raw((i + 15) as u32),
+                Vec3::new(i as f32 * 10.0, 0.0, 0.0),
+                &band,
+            );
+        }
+        assert_eq!(candidates(&grid, Vec3::splat(5.0)), expected);
+    }
+
+    /// The viewer only checks the cells around it, but finds the entities in the neighbouring cells
+    #[test]
+    fn test_relevance_grid() {
+        let mut stepper = setup(5.0);
+        stepper.server_app.world_mut().spawn((
+            RelevanceViewer(ClientId::Netcode(TEST_CLIENT_ID)),
+            TransformBundle::from_transform(Transform::from_xyz(4.0, 0.0, 0.0)),
+        ));
+        let spawn = |stepper: &mut BevyStepper, x: f32| {
+            stepper
+                .server_app
+                .world_mut()
+                .spawn((
+                    Replicate {
+                        relevance_mode: NetworkRelevanceMode::InterestManagement,
+                        ..default()
+                    },
+                    RelevanceBand::radius(10.0),
+                    TransformBundle::from_transform(Transform::from_xyz(x, 0.0, 0.0)),
+                ))
+                .id()
+        };
+        let near = spawn(&mut stepper, -5.5);
+        let far = spawn(&mut stepper, 15.0);
+        let very_far = spawn(&mut stepper, 100.0);
+        stepper.frame_step();
+        assert!(is_in_range(&stepper, near));
+        assert!(!is_in_range(&stepper, far));
+        assert!(!is_in_range(&stepper, very_far));
+    }
+
+    /// The relevance is computed from the transforms of the current frame
+    #[test]
+    fn test_relevance_after_transform_propagation() {
+        let mut stepper = setup(100.0);
+        stepper.server_app.world_mut().spawn((
+            RelevanceViewer(ClientId::Netcode(TEST_CLIENT_ID)),
+            TransformBundle::default(),
+        ));
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((
+                Replicate {
+                    relevance_mode: NetworkRelevanceMode::InterestManagement,
+                    ..default()
+                },
+                RelevanceBand::radius(10.0),
+                TransformBundle::from_transform(Transform::from_xyz(20.0, 0.0, 0.0)),
+            ))
+            .id();
+        stepper.frame_step();
+        assert!(!is_in_range(&stepper, server_entity));
+
+        stepper
+            .server_app
+            .world_mut()
+            .get_mut::<Transform>(server_entity)
+            .unwrap()
+            .translation
+            .x = 5.0;
+        stepper.frame_step();
+        assert!(is_in_range(&stepper, server_entity));
+    }
+}
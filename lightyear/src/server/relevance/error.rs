@@ -1,7 +1,14 @@
+use bevy::prelude::Entity;
+
 use crate::prelude::server::RoomId;
+use crate::prelude::ClientId;
 
 #[derive(thiserror::Error, Debug)]
 pub enum RelevanceError {
     #[error("room id {0:?} was not found")]
     RoomIdNotFound(RoomId),
+    #[error("client {0:?} is not in room {1:?}")]
+    ClientNotInRoom(ClientId, RoomId),
+    #[error("entity {0:?} is not in room {1:?}")]
+    EntityNotInRoom(Entity, RoomId),
 }
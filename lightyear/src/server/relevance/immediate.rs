@@ -20,14 +20,20 @@ fn my_system(
     // you can update the relevance like so
     relevance_manager.gain_relevance(ClientId::Netcode(1), Entity::PLACEHOLDER);
     relevance_manager.lose_relevance(ClientId::Netcode(2), Entity::PLACEHOLDER);
+    // or make the entity relevant only for a limited duration
+    relevance_manager.gain_relevance_for(ClientId::Netcode(3), Entity::PLACEHOLDER, std::time::Duration::from_secs(3));
 }
 ```
 */
 use crate::prelude::{server::is_started, ClientId};
+use crate::server::connection::ConnectionManager;
+use crate::server::relevance::room::RoomManager;
 use crate::shared::sets::{InternalReplicationSet, ServerMarker};
-use bevy::ecs::entity::EntityHashSet;
+use crate::shared::time_manager::TimeManager;
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy::utils::HashMap;
+use bevy::utils::{Duration, HashMap};
 use tracing::trace;
 
 /// Event related to [`Entities`](Entity) which are relevant to a client
@@ -39,6 +45,12 @@ pub(crate) enum ClientRelevance {
     Lost,
     /// the entity was already replicated to the client, and still is
     Maintained,
+    /// the entity is relevant to the client, but is not replicated because of the
+    /// [`EntityLimitConfig`](crate::server::relevance::limit::EntityLimitConfig)
+    HeldOut,
+    /// the entity was replicated to the client, but is now held out because of the
+    /// [`EntityLimitConfig`](crate::server::relevance::limit::EntityLimitConfig)
+    Evicted,
 }
 
 #[derive(Component, Clone, Default, PartialEq, Debug, Reflect)]
@@ -57,14 +69,17 @@ struct RelevanceEvents {
 
 /// Resource that manages the network relevance of entities for clients
 ///
-/// You can call the two functions
+/// You can call the functions
 /// - [`gain_relevance`](RelevanceManager::gain_relevance)
+/// - [`gain_relevance_for`](RelevanceManager::gain_relevance_for)
 /// - [`lose_relevance`](RelevanceManager::lose_relevance)
 ///
 /// to update the relevance of an entity for a given client.
 #[derive(Resource, Debug, Default)]
 pub struct RelevanceManager {
     events: RelevanceEvents,
+    /// Remaining duration of the temporary relevances
+    temporary: HashMap<ClientId, EntityHashMap<Duration>>,
 }
 
 impl RelevanceManager {
@@ -72,6 +87,36 @@ impl RelevanceManager {
     ///
     /// The relevance status gets cached and will be maintained until is it changed.
     pub fn gain_relevance(&mut self, client: ClientId, entity: Entity) -> &mut Self {
+        self.clear_temporary(client, entity);
+        self.gain_relevance_internal(client, entity)
+    }
+
+    /// Gain relevance of an entity for a given client, for a limited duration.
+    ///
+    /// The entity automatically loses relevance once the duration has elapsed (for example
+    /// to reveal an enemy to a client for a few seconds). Calling this again while the entity is
+    /// temporarily relevant resets the remaining duration; calling [`gain_relevance`](RelevanceManager::gain_relevance)
+    /// or [`lose_relevance`](RelevanceManager::lose_relevance) cancels the expiration.
+    pub fn gain_relevance_for(
+        &mut self,
+        client: ClientId,
+        entity: Entity,
+        duration: Duration,
+    ) -> &mut Self {
+        self.temporary
+            .entry(client)
+            .or_default()
+            .insert(entity, duration);
+        self.gain_relevance_internal(client, entity)
+    }
+
+    /// Lost relevance of an entity for a given client
+    pub fn lose_relevance(&mut self, client: ClientId, entity: Entity) -> &mut Self {
+        self.clear_temporary(client, entity);
+        self.lose_relevance_internal(client, entity)
+    }
+
+    fn gain_relevance_internal(&mut self, client: ClientId, entity: Entity) -> &mut Self {
         self.events.lost.entry(client).and_modify(|set| {
             set.remove(&entity);
         });
@@ -79,8 +124,7 @@ impl RelevanceManager {
         self
     }
 
-    /// Lost relevance of an entity for a given client
-    pub fn lose_relevance(&mut self, client: ClientId, entity: Entity) -> &mut Self {
+    fn lose_relevance_internal(&mut self, client: ClientId, entity: Entity) -> &mut Self {
         self.events.gained.entry(client).and_modify(|set| {
             set.remove(&entity);
         });
@@ -88,6 +132,60 @@ impl RelevanceManager {
         self
     }
 
+    fn clear_temporary(&mut self, client: ClientId, entity: Entity) {
+        if let Some(entities) = self.temporary.get_mut(&client) {
+            entities.remove(&entity);
+            if entities.is_empty() {
+                self.temporary.remove(&client);
+            }
+        }
+    }
+
+    /// Drop the temporary relevances that can no longer expire normally: the ones of clients that
+    /// disconnected, or of entities that were despawned or stopped using interest management
+    fn clear_stale_temporary(
+        &mut self,
+        is_connected: impl Fn(ClientId) -> bool,
+        is_managed: impl Fn(Entity) -> bool,
+    ) {
+        self.temporary.retain(|client, entities| {
+            if !is_connected(*client) {
+                return false;
+            }
+            entities.retain(|entity, _| is_managed(*entity));
+            !entities.is_empty()
+        });
+    }
+
+    /// Advance the timers of the temporary relevances, and lose relevance for the ones that expired
+    fn expire_temporary(&mut self, delta: Duration) {
+        let mut expired = vec![];
+        self.temporary.retain(|client, entities| {
+            entities.retain(|entity, remaining| {
+                *remaining = remaining.saturating_sub(delta);
+                if remaining.is_zero() {
+                    expired.push((*client, *entity));
+                    return false;
+                }
+                true
+            });
+            !entities.is_empty()
+        });
+        for (client, entity) in expired {
+            trace!("temporary relevance of entity {entity:?} for client {client:?} expired");
+            self.lose_relevance_internal(client, entity);
+        }
+    }
+
+    /// Remaining duration of the relevance of an entity for a client, if it was made relevant
+    /// with [`gain_relevance_for`](RelevanceManager::gain_relevance_for)
+    pub fn temporary_relevance(&self, client: ClientId, entity: Entity) -> Option<Duration> {
+        self.temporary
+            .get(&client)
+            .and_then(|entities| entities.get(&entity))
+            .copied()
+    }
+
     // NOTE: this might not be needed because we drain the event cache every Send update
     // /// Remove all relevance events for a given client when they disconnect
     // ///
@@ -98,6 +196,83 @@ impl RelevanceManager {
     // }
 }
 
+/// [`SystemParam`] to inspect which entities with [`NetworkRelevanceMode::InterestManagement`](crate::prelude::NetworkRelevanceMode::InterestManagement)
+/// are relevant to a client.
+///
+/// The relevance is updated when the replication messages are buffered, so changes made
+/// with the [`RelevanceManager`] or the [`RoomManager`] are visible after the next send.
+#[derive(SystemParam)]
+pub struct RelevanceQuery<'w, 's> {
+    relevance: Query<'w, 's, (Entity, &'static CachedNetworkRelevance)>,
+    room_manager: Option<Res<'w, RoomManager>>,
+}
+
+impl RelevanceQuery<'_, '_> {
+    /// Returns true if the entity is relevant to the client
+    pub fn is_relevant(&self, client: ClientId, entity: Entity) -> bool {
+        self.relevance.get(entity).is_ok_and(|(_, cache)| {
+            cache.clients_cache.get(&client).is_some_and(|relevance| {
+                matches!(
+                    relevance,
+                    ClientRelevance::Gained | ClientRelevance::Maintained
+                )
+            })
+        })
+    }
+
+    /// Returns true if the entity is relevant to the client, but is not replicated to it because of the
+    /// [`EntityLimitConfig`](crate::server::relevance::limit::EntityLimitConfig)
+    pub fn is_held_out(&self, client: ClientId, entity: Entity) -> bool {
+        self.relevance.get(entity).is_ok_and(|(_, cache)| {
+            cache.clients_cache.get(&client).is_some_and(|relevance| {
+                matches!(
+                    relevance,
+                    ClientRelevance::HeldOut | ClientRelevance::Evicted
+                )
+            })
+        })
+    }
+
+    /// Iterate through the entities that are relevant to the client
+    pub fn relevant_entities(&self, client: ClientId) -> impl Iterator<Item = Entity> + '_ {
+        self.relevance
+            .iter()
+            .filter(move |(entity, _)| self.is_relevant(client, *entity))
+            .map(|(entity, _)| entity)
+    }
+
+    /// Entities that are relevant to the client because they share a [`Room`](crate::server::relevance::room::Room) with it
+    pub fn room_entities(&self, client: ClientId) -> EntityHashSet {
+        let Some(room_manager) = self.room_manager.as_ref() else {
+            return EntityHashSet::default();
+        };
+        room_manager
+            .client_rooms(client)
+            .filter_map(|room_id| room_manager.get_room(room_id))
+            .flat_map(|room| room.entities.iter().copied())
+            .collect()
+    }
+
+    /// Entities that are relevant to the client without sharing a room with it
+    /// (i.e. that were made relevant with the [`RelevanceManager`])
+    pub fn explicit_entities(&self, client: ClientId) -> EntityHashSet {
+        let room_entities = self.room_entities(client);
+        self.relevant_entities(client)
+            .filter(|entity| !room_entities.contains(entity))
+            .collect()
+    }
+
+    /// Entities that are not relevant to the client (i.e. that are not in any of its rooms
+    /// and were not made relevant with the [`RelevanceManager`], or that lost relevance)
+    pub fn excluded_entities(&self, client: ClientId) -> EntityHashSet {
+        self.relevance
+            .iter()
+            .filter(|(entity, _)| !self.is_relevant(client, *entity))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+}
+
 pub(super) mod systems {
     use super::*;
 
@@ -150,6 +325,32 @@ pub(super) mod systems {
         }
     }
 
+    /// Lose relevance for the entities whose temporary relevance has expired
+    pub fn expire_temporary_relevance(
+        mut manager: ResMut<RelevanceManager>,
+        time_manager: Res<TimeManager>,
+    ) {
+        if manager.temporary.is_empty() {
+            return;
+        }
+        manager.expire_temporary(time_manager.delta());
+    }
+
+    /// At the end of the frame, drop the temporary relevances of disconnected clients and despawned entities
+    pub fn clear_stale_temporary_relevance(
+        mut manager: ResMut<RelevanceManager>,
+        connection_manager: Res<ConnectionManager>,
+        relevance: Query<(), With<CachedNetworkRelevance>>,
+    ) {
+        if manager.temporary.is_empty() {
+            return;
+        }
+        manager.clear_stale_temporary(
+            |client| connection_manager.connection(client).is_ok(),
+            |entity| relevance.contains(entity),
+        );
+    }
+
     /// System that updates the relevance cache of each Entity based on the relevance events.
     pub fn update_relevance_from_events(
         mut manager: ResMut<RelevanceManager>,
@@ -164,9 +365,16 @@ pub(super) mod systems {
                 if let Ok(mut cache) = relevance.get_mut(entity) {
                     // Only lose relevance if the client was visible to the entity
                     // (to avoid multiple despawn messages)
-                    if let Some(vis) = cache.clients_cache.get_mut(&client) {
-                        trace!("lose relevance for entity {entity:?} and client {client:?}");
-                        *vis = ClientRelevance::Lost;
+                    match cache.clients_cache.get(&client) {
+                        // the entity was never spawned on the client
+                        Some(ClientRelevance::HeldOut) => {
+                            cache.clients_cache.remove(&client);
+                        }
+                        Some(_) => {
+                            trace!("lose relevance for entity {entity:?} and client {client:?}");
+                            cache.clients_cache.insert(client, ClientRelevance::Lost);
+                        }
+                        None => {}
                     }
                 }
             });
@@ -191,6 +399,7 @@ pub(super) mod systems {
     /// After replication, update the Replication Cache:
     /// - Relevance Gained becomes Relevance Maintained
     /// - Relevance Lost gets removed from the cache
+    /// - Relevance Evicted becomes Relevance HeldOut
     pub fn update_cached_relevance(mut query: Query<(Entity, &mut CachedNetworkRelevance)>) {
         for (entity, mut replicate) in query.iter_mut() {
             replicate
@@ -207,7 +416,11 @@ pub(super) mod systems {
                         trace!("remove client {client_id:?} and entity {entity:?} from relevance cache");
                         false
                     }
-                    ClientRelevance::Maintained => true,
+                    ClientRelevance::Evicted => {
+                        *relevance = ClientRelevance::HeldOut;
+                        true
+                    }
+                    ClientRelevance::Maintained | ClientRelevance::HeldOut => true,
                 });
             // error!("replicate.clients_cache: {0:?}", replicate.clients_cache);
         }
@@ -263,6 +476,13 @@ impl Plugin for NetworkRelevancePlugin {
             (
                 systems::add_cached_network_relevance
                     .in_set(InternalReplicationSet::<ServerMarker>::BeforeBuffer),
+                // the timers must be updated every frame, not only on send frames
+                systems::expire_temporary_relevance
+                    .run_if(is_started)
+                    .before(NetworkRelevanceSet::UpdateRelevance),
+                systems::clear_stale_temporary_relevance
+                    .run_if(is_started)
+                    .after(NetworkRelevanceSet::RelevanceCleanup),
                 systems::update_relevance_from_events.in_set(NetworkRelevanceSet::UpdateRelevance),
                 systems::update_cached_relevance.in_set(NetworkRelevanceSet::RelevanceCleanup),
             ),
@@ -394,4 +614,56 @@ mod tests {
             &ClientRelevance::Maintained
         );
     }
+
+    /// An entity gains relevance temporarily, and loses it when the duration expires
+    #[test]
+    fn test_temporary_relevance() {
+        let mut manager = RelevanceManager::default();
+        let entity = Entity::from_raw(1);
+        let client = ClientId::Netcode(1);
+
+        manager.gain_relevance_for(client, entity, Duration::from_secs(3));
+        assert!(manager.events.gained[&client].contains(&entity));
+        manager.events.gained.clear();
+
+        // the duration has not elapsed yet
+        manager.expire_temporary(Duration::from_secs(2));
+        assert!(manager.events.lost.is_empty());
+
+        manager.expire_temporary(Duration::from_secs(1));
+        assert!(manager.events.lost[&client].contains(&entity));
+        assert!(manager.temporary.is_empty());
+    }
+
+    /// The temporary relevances of disconnected clients and despawned entities are dropped
+    #[test]
+    fn test_clear_stale_temporary_relevance() {
+        let mut manager = RelevanceManager::default();
+        let (entity, despawned) = (Entity::from_raw(1), Entity::from_raw(2));
+        let (client, disconnected) = (ClientId::Netcode(1), ClientId::Netcode(2));
+
+        manager.gain_relevance_for(client, entity, Duration::from_secs(3));
+        manager.gain_relevance_for(client, despawned, Duration::from_secs(3));
+        manager.gain_relevance_for(disconnected, entity, Duration::from_secs(3));
+        manager.clear_stale_temporary(|c| c == client, |e| e == entity);
+
+        assert_eq!(manager.temporary.len(), 1);
+        assert_eq!(
+            manager.temporary[&client].keys().collect::<Vec<_>>(),
+            vec![&entity]
+        );
+    }
+
+    /// Calling gain_relevance cancels the expiration of a temporary relevance
+    #[test]
+    fn test_temporary_relevance_made_permanent() {
+        let mut manager = RelevanceManager::default();
+        let entity = Entity::from_raw(1);
+        let client = ClientId::Netcode(1);
+
+        manager.gain_relevance_for(client, entity, Duration::from_secs(3));
+        manager.gain_relevance(client, entity);
+        manager.expire_temporary(Duration::from_secs(5));
+        assert!(manager.events.lost.is_empty());
+    }
 }
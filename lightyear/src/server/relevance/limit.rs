@@ -0,0 +1,233 @@
+/*! Limit on the number of entities replicated to each client
+
+When [`ServerConfig::entity_limit`](crate::prelude::server::ServerConfig::entity_limit) is set, at most
+[`EntityLimitConfig::max_entities`] entities with
+[`NetworkRelevanceMode::InterestManagement`](crate::prelude::NetworkRelevanceMode::InterestManagement) are replicated
+to each client at the same time.
+
+When more entities are relevant to a client, the ones with the lowest [`ReplicationGroup`] priority are held out: they
+are not spawned on the client (or are despawned if they were already replicated) until enough room is available again.
+The entities that keep being replicated get their usual update rate, instead of all the entities sharing a bandwidth
+that is too small for them.
+
+An [`EntitiesHeldOutEvent`] is emitted when entities start being held out, and
+[`RelevanceQuery::is_held_out`](crate::prelude::server::RelevanceQuery::is_held_out) tells if an entity is currently
+held out for a client.
+
+The entities replicated with [`NetworkRelevanceMode::All`](crate::prelude::NetworkRelevanceMode::All) are always
+replicated and don't count towards the limit.
+*/
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use tracing::debug;
+
+use crate::prelude::{server::is_started, ClientId, ReplicationGroup};
+use crate::server::config::ServerConfig;
+use crate::server::relevance::immediate::{
+    systems::update_relevance_from_events, CachedNetworkRelevance, ClientRelevance,
+    NetworkRelevanceSet,
+};
+
+/// Maximum number of entities replicated to each client
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntityLimitConfig {
+    /// Maximum number of entities with interest management that are replicated to a client at the same time
+    pub max_entities: usize,
+}
+
+impl EntityLimitConfig {
+    pub fn new(max_entities: usize) -> Self {
+        Self { max_entities }
+    }
+}
+
+/// Bevy [`Event`] emitted on the server when entities that are relevant to a client start being held out
+/// because of the [`EntityLimitConfig`]
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct EntitiesHeldOutEvent {
+    pub client_id: ClientId,
+    /// Entities that started being held out, including the ones that were despawned on the client to make
+    /// room for entities with a higher priority
+    pub entities: Vec<Entity>,
+    /// Total number of entities held out for the client
+    pub held_out: usize,
+}
+
+pub(crate) struct EntityLimitPlugin;
+
+impl Plugin for EntityLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EntitiesHeldOutEvent>();
+        app.add_systems(
+            PostUpdate,
+            enforce_entity_limit
+                .after(update_relevance_from_events)
+                .in_set(NetworkRelevanceSet::UpdateRelevance)
+                .run_if(is_started),
+        );
+    }
+}
+
+/// Hold out the lowest-priority entities of the clients that have more relevant entities than the limit,
+/// and replicate the held out entities again once there is room for them
+fn enforce_entity_limit(
+    config: Res<ServerConfig>,
+    mut query: Query<(
+        Entity,
+        &mut CachedNetworkRelevance,
+        Option<&ReplicationGroup>,
+    )>,
+    mut events: EventWriter<EntitiesHeldOutEvent>,
+) {
+    let Some(EntityLimitConfig { max_entities }) = config.entity_limit else {
+        return;
+    };
+    // (entity, priority, already replicated) of the relevant entities of each client
+    let mut candidates: HashMap<ClientId, Vec<(Entity, f32, bool)>> = HashMap::default();
+    for (entity, cache, group) in query.iter() {
+        let priority = group.map_or(1.0, |g| g.priority());
+        for (client_id, relevance) in cache.clients_cache.iter() {
+            let replicated = match relevance {
+                ClientRelevance::Lost => continue,
+                ClientRelevance::Maintained => true,
+                ClientRelevance::Gained | ClientRelevance::HeldOut | ClientRelevance::Evicted => {
+                    false
+                }
+            };
+            candidates
+                .entry(*client_id)
+                .or_default()
+                .push((entity, priority, replicated));
+        }
+    }
+    for (client_id, mut entities) in candidates {
+        if entities.len() > max_entities {
+            // on equal priority, the entities that are already replicated stay replicated
+            entities.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.2.cmp(&a.2)));
+        }
+        let mut held_out = vec![];
+        for (i, (entity, _, _)) in entities.iter().enumerate() {
+            let Ok((_, mut cache, _)) = query.get_mut(*entity) else {
+                continue;
+            };
+            let relevance = cache.clients_cache.get(&client_id).copied();
+            let new_relevance = match (relevance, i < max_entities) {
+                (Some(ClientRelevance::HeldOut), true) => ClientRelevance::Gained,
+                (Some(ClientRelevance::Gained), false) => {
+                    held_out.push(*entity);
+                    ClientRelevance::HeldOut
+                }
+                (Some(ClientRelevance::Maintained), false) => {
+                    held_out.push(*entity);
+                    ClientRelevance::Evicted
+                }
+                _ => continue,
+            };
+            cache.clients_cache.insert(client_id, new_relevance);
+        }
+        if !held_out.is_empty() {
+            debug!(
+                ?client_id,
+                "{} entities held out because of the entity limit",
+                held_out.len()
+            );
+            events.send(EntitiesHeldOutEvent {
+                client_id,
+                entities: held_out,
+                held_out: entities.len().saturating_sub(max_entities),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client;
+    use crate::prelude::server::{RelevanceManager, Replicate};
+    use crate::prelude::NetworkRelevanceMode;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn is_replicated(stepper: &BevyStepper, server_entity: Entity) -> bool {
+        stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(server_entity)
+            .is_some_and(|client_entity| {
+                stepper
+                    .client_app
+                    .world()
+                    .get_entity(client_entity)
+                    .is_some()
+            })
+    }
+
+    fn spawn(stepper: &mut BevyStepper, priority: f32) -> Entity {
+        let entity = stepper
+            .server_app
+            .world_mut()
+            .spawn(Replicate {
+                relevance_mode: NetworkRelevanceMode::InterestManagement,
+                group: ReplicationGroup::default().set_priority(priority),
+                ..default()
+            })
+            .id();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RelevanceManager>()
+            .gain_relevance(ClientId::Netcode(TEST_CLIENT_ID), entity);
+        entity
+    }
+
+    #[test]
+    fn test_entity_limit() {
+        #[derive(Resource, Default)]
+        struct HeldOut(Vec<Entity>);
+
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .entity_limit = Some(EntityLimitConfig::new(2));
+        stepper.server_app.init_resource::<HeldOut>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<EntitiesHeldOutEvent>, mut held_out: ResMut<HeldOut>| {
+                for event in events.read() {
+                    held_out.0.extend(event.entities.iter().copied());
+                }
+            },
+        );
+        let low = spawn(&mut stepper, 1.0);
+        let medium = spawn(&mut stepper, 2.0);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(is_replicated(&stepper, low));
+        assert!(is_replicated(&stepper, medium));
+
+        // the lowest-priority entity is evicted to make room for the new entity
+        let high = spawn(&mut stepper, 3.0);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(!is_replicated(&stepper, low));
+        assert!(is_replicated(&stepper, medium));
+        assert!(is_replicated(&stepper, high));
+        assert_eq!(
+            stepper.server_app.world().resource::<HeldOut>().0,
+            vec![low]
+        );
+
+        // the held out entity is replicated again once there is room for it
+        stepper.server_app.world_mut().despawn(high);
+        stepper.frame_step();
+        stepper.frame_step();
+        assert!(is_replicated(&stepper, low));
+        assert!(is_replicated(&stepper, medium));
+    }
+}
@@ -1,4 +1,6 @@
+pub mod distance;
 pub mod immediate;
+pub mod limit;
 
 pub mod error;
 pub mod room;
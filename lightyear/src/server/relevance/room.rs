@@ -41,16 +41,19 @@ use bevy::app::App;
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::*;
 use bevy::reflect::Reflect;
-use bevy::utils::{HashMap, HashSet};
+use bevy::utils::{Duration, HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
-use tracing::trace;
+use tracing::{error, trace};
 
 use crate::connection::id::ClientId;
 use crate::prelude::server::is_started;
+use crate::server::connection::ConnectionManager;
+use crate::shared::time_manager::{TimeManager, WrappedTime};
 
+use crate::server::relevance::error::RelevanceError;
 use crate::server::relevance::immediate::{NetworkRelevanceSet, RelevanceManager};
-use crate::shared::sets::{InternalReplicationSet, ServerMarker};
+use crate::shared::sets::{InternalMainSet, InternalReplicationSet, ServerMarker};
 
 type EntityHashMap<K, V> = hashbrown::HashMap<K, V, EntityHash>;
 type EntityHashSet<K> = hashbrown::HashSet<K, EntityHash>;
@@ -99,6 +102,32 @@ struct RoomData {
     entity_to_rooms: EntityHashMap<Entity, HashSet<RoomId>>,
     /// Mapping from [`RoomId`] to the [`Room`]
     rooms: HashMap<RoomId, Room>,
+    /// Liveness tracking for the rooms that have a silence timeout
+    liveness: HashMap<RoomId, RoomLiveness>,
+}
+
+/// Tracks whether any client in a room is still active
+#[derive(Debug)]
+struct RoomLiveness {
+    /// Duration of silence after which a [`RoomSilentEvent`] is emitted
+    timeout: Duration,
+    /// Last time we heard from any client in the room
+    last_active: Option<WrappedTime>,
+    /// True if the [`RoomSilentEvent`] was already emitted for the current period of silence
+    silent: bool,
+}
+
+/// Event emitted when no client in a [`Room`] has been heard from for the room's silence timeout
+/// (see [`RoomManager::set_silence_timeout`]).
+///
+/// This usually means that the match is abandoned and that the room can be cleaned up.
+/// The event is emitted only once per period of silence: it will be emitted again only
+/// if a client of the room becomes active and then silent again.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct RoomSilentEvent {
+    pub room_id: RoomId,
+    /// How long the room has been silent for
+    pub silent_for: Duration,
 }
 
 /// A [`Room`] is a data structure that is used to perform interest management.
@@ -139,6 +168,8 @@ impl Plugin for RoomPlugin {
     fn build(&self, app: &mut App) {
         // RESOURCES
         app.init_resource::<RoomManager>();
+        // EVENTS
+        app.add_event::<RoomSilentEvent>();
         // SETS
         app.configure_sets(
             PostUpdate,
@@ -159,9 +190,17 @@ impl Plugin for RoomPlugin {
         app.add_systems(
             PostUpdate,
             (
-                systems::buffer_room_relevance_events
-                    .in_set(RoomSystemSets::UpdateReplicationCaches),
-            ),
+                systems::replay_room_history,
+                systems::buffer_room_relevance_events,
+            )
+                .chain()
+                .in_set(RoomSystemSets::UpdateReplicationCaches),
+        );
+        app.add_systems(
+            PreUpdate,
+            systems::check_room_liveness
+                .after(InternalMainSet::<ServerMarker>::Receive)
+                .run_if(is_started),
         );
         app.observe(systems::handle_client_disconnect);
         app.observe(systems::clean_entity_despawns);
@@ -193,8 +232,24 @@ impl RoomManager {
     }
 
     /// Remove a client from the [`Room`]
+    ///
+    /// Does nothing if the client is not in the room, see [`RoomManager::try_remove_client`].
     pub fn remove_client(&mut self, client_id: ClientId, room_id: RoomId) {
-        self.remove_client_internal(room_id, client_id)
+        let _ = self.try_remove_client(client_id, room_id);
+    }
+
+    /// Remove a client from the [`Room`], or return an error if the room does not exist
+    /// or does not contain the client
+    pub fn try_remove_client(
+        &mut self,
+        client_id: ClientId,
+        room_id: RoomId,
+    ) -> Result<(), RelevanceError> {
+        if !self.try_room(room_id)?.clients.contains(&client_id) {
+            return Err(RelevanceError::ClientNotInRoom(client_id, room_id));
+        }
+        self.remove_client_internal(room_id, client_id);
+        Ok(())
     }
 
     /// Add an entity to the [`Room`]
@@ -203,8 +258,24 @@ impl RoomManager {
     }
 
     /// Remove an entity from the [`Room`]
+    ///
+    /// Does nothing if the entity is not in the room, see [`RoomManager::try_remove_entity`].
     pub fn remove_entity(&mut self, entity: Entity, room_id: RoomId) {
-        self.remove_entity_internal(room_id, entity)
+        let _ = self.try_remove_entity(entity, room_id);
+    }
+
+    /// Remove an entity from the [`Room`], or return an error if the room does not exist
+    /// or does not contain the entity
+    pub fn try_remove_entity(
+        &mut self,
+        entity: Entity,
+        room_id: RoomId,
+    ) -> Result<(), RelevanceError> {
+        if !self.try_room(room_id)?.entities.contains(&entity) {
+            return Err(RelevanceError::EntityNotInRoom(entity, room_id));
+        }
+        self.remove_entity_internal(room_id, entity);
+        Ok(())
     }
 
     /// Returns true if the [`Room`] contains the [`ClientId`]
@@ -217,16 +288,70 @@ impl RoomManager {
         self.has_entity_internal(room_id, entity)
     }
 
+    /// Emit a [`RoomSilentEvent`] when no client in the room has been heard from for the duration `timeout`.
+    ///
+    /// An empty room counts as silent.
+    pub fn set_silence_timeout(&mut self, room_id: RoomId, timeout: Duration) {
+        self.data.rooms.entry(room_id).or_default();
+        self.data.liveness.insert(
+            room_id,
+            RoomLiveness {
+                timeout,
+                last_active: None,
+                silent: false,
+            },
+        );
+    }
+
+    /// Stop tracking the liveness of the room
+    pub fn clear_silence_timeout(&mut self, room_id: RoomId) {
+        self.data.liveness.remove(&room_id);
+    }
+
     /// Get a room by its [`RoomId`]
     pub fn get_room(&self, room_id: RoomId) -> Option<&Room> {
         self.data.rooms.get(&room_id)
     }
 
+    /// Get a room by its [`RoomId`], or an error if the room does not exist
+    pub fn try_room(&self, room_id: RoomId) -> Result<&Room, RelevanceError> {
+        self.data
+            .rooms
+            .get(&room_id)
+            .ok_or(RelevanceError::RoomIdNotFound(room_id))
+    }
+
     /// Get a room by its [`RoomId`]
     ///
-    /// Panics if the room does not exist.
+    /// Panics if the room does not exist, use [`RoomManager::try_room`] if the [`RoomId`] might be stale.
     pub fn room(&self, room_id: RoomId) -> &Room {
-        self.data.rooms.get(&room_id).unwrap()
+        self.try_room(room_id).unwrap()
+    }
+
+    /// Iterate through all the rooms
+    pub fn rooms(&self) -> impl Iterator<Item = (RoomId, &Room)> {
+        self.data
+            .rooms
+            .iter()
+            .map(|(room_id, room)| (*room_id, room))
+    }
+
+    /// Returns true if the client joined the room since the last time the room events were processed
+    pub(crate) fn is_joining(&self, client_id: ClientId, room_id: RoomId) -> bool {
+        self.events
+            .client_enter_room
+            .get(&client_id)
+            .is_some_and(|rooms| rooms.contains(&room_id))
+    }
+
+    /// Iterate through the rooms that a client is in
+    pub fn client_rooms(&self, client_id: ClientId) -> impl Iterator<Item = RoomId> + '_ {
+        self.data
+            .client_to_rooms
+            .get(&client_id)
+            .into_iter()
+            .flatten()
+            .copied()
     }
 
     fn add_client_internal(&mut self, room_id: RoomId, client_id: ClientId) {
@@ -399,15 +524,45 @@ impl RoomEvents {
 pub(super) mod systems {
     use super::*;
     use crate::prelude::ReplicationGroup;
+    use crate::server::config::ServerConfig;
     use crate::server::events::DisconnectEvent;
+    use crate::server::session::SuspendedSessions;
     use bevy::prelude::Trigger;
 
     /// Clear the internal room buffers when a client disconnects
+    ///
+    /// If session resumption is enabled, the rooms are remembered so that the client can rejoin them.
     pub fn handle_client_disconnect(
         trigger: Trigger<DisconnectEvent>,
+        config: Res<ServerConfig>,
+        mut sessions: ResMut<SuspendedSessions>,
         mut room_manager: ResMut<RoomManager>,
     ) {
-        room_manager.client_disconnect(trigger.event().client_id);
+        let client_id = trigger.event().client_id;
+        sessions.suspend_rooms(
+            &config,
+            trigger.event(),
+            room_manager.client_rooms(client_id),
+        );
+        room_manager.client_disconnect(client_id);
+    }
+
+    /// Send the history of the room to the clients that joined it
+    pub fn replay_room_history(
+        room_manager: Res<RoomManager>,
+        mut connection_manager: ResMut<ConnectionManager>,
+    ) {
+        for (client_id, rooms) in room_manager.events.iter_client_enter_room() {
+            for room_id in rooms {
+                if let Err(e) = connection_manager.replay_room_history(*client_id, *room_id) {
+                    error!(
+                        ?client_id,
+                        ?room_id,
+                        "Could not send the room history: {e:?}"
+                    );
+                }
+            }
+        }
     }
 
     // TODO: (perf) split this into 4 separate functions that access RoomManager in parallel?
@@ -431,7 +586,10 @@ pub(super) mod systems {
         for (entity, rooms) in room_manager.events.entity_leave_room.drain() {
             // for each room left, update the entity's client relevance list if the client was in the room
             rooms.into_iter().for_each(|room_id| {
-                let room = room_manager.data.rooms.get(&room_id).unwrap();
+                // the room may not exist anymore if the event is stale
+                let Some(room) = room_manager.data.rooms.get(&room_id) else {
+                    return;
+                };
                 room.clients.iter().for_each(|client_id| {
                     trace!("entity {entity:?} left room {room:?}. Sending lost relevance to client {client_id:?}");
                     relevance_manager.lose_relevance(*client_id, entity);
@@ -442,7 +600,10 @@ pub(super) mod systems {
         for (entity, rooms) in room_manager.events.entity_enter_room.drain() {
             // for each room joined, update the entity's client relevance list
             rooms.into_iter().for_each(|room_id| {
-                let room = room_manager.data.rooms.get(&room_id).unwrap();
+                // the room may not exist anymore if the event is stale
+                let Some(room) = room_manager.data.rooms.get(&room_id) else {
+                    return;
+                };
                 room.clients.iter().for_each(|client_id| {
                     trace!("entity {entity:?} joined room {room:?}. Sending gained relevance to client {client_id:?}");
                     relevance_manager.gain_relevance(*client_id, entity);
@@ -452,7 +613,10 @@ pub(super) mod systems {
         // client left room: update all the entities that are in that room
         for (client_id, rooms) in room_manager.events.client_leave_room.drain() {
             rooms.into_iter().for_each(|room_id| {
-                let room = room_manager.data.rooms.get(&room_id).unwrap();
+                // the room may not exist anymore if the event is stale
+                let Some(room) = room_manager.data.rooms.get(&room_id) else {
+                    return;
+                };
                 room.entities.iter().for_each(|entity| {
                     trace!("client {client_id:?} left room {room:?}. Sending lost relevance to entity {entity:?}");
                     relevance_manager.lose_relevance(client_id, *entity);
@@ -462,7 +626,10 @@ pub(super) mod systems {
         // client joined room: update all the entities that are in that room
         for (client_id, rooms) in room_manager.events.client_enter_room.drain() {
             rooms.into_iter().for_each(|room_id| {
-                let room = room_manager.data.rooms.get(&room_id).unwrap();
+                // the room may not exist anymore if the event is stale
+                let Some(room) = room_manager.data.rooms.get(&room_id) else {
+                    return;
+                };
                 room.entities.iter().for_each(|entity| {
                     trace!("client {client_id:?} joined room {room:?}. Sending gained relevance to entity {entity:?}");
                     relevance_manager.gain_relevance(client_id, *entity);
@@ -471,6 +638,46 @@ pub(super) mod systems {
         }
     }
 
+    /// Emit a [`RoomSilentEvent`] for the rooms where no client has been heard from for longer than the room's timeout
+    pub fn check_room_liveness(
+        mut room_manager: ResMut<RoomManager>,
+        connection_manager: Res<ConnectionManager>,
+        time_manager: Res<TimeManager>,
+        mut events: EventWriter<RoomSilentEvent>,
+    ) {
+        let now = time_manager.current_time();
+        let data = &mut room_manager.data;
+        for (room_id, liveness) in data.liveness.iter_mut() {
+            let last_heard = data.rooms.get(room_id).and_then(|room| {
+                room.clients
+                    .iter()
+                    .filter_map(|client_id| connection_manager.last_heard(*client_id))
+                    .max()
+            });
+            let last_active = match (liveness.last_active, last_heard) {
+                (Some(last_active), Some(last_heard)) => last_active.max(last_heard),
+                (last_active, last_heard) => last_active.or(last_heard).unwrap_or(now),
+            };
+            if liveness
+                .last_active
+                .is_some_and(|previous| last_active > previous)
+            {
+                // a client was heard from, the room is not silent anymore
+                liveness.silent = false;
+            }
+            liveness.last_active = Some(last_active);
+            let silent_for = now.to_duration().saturating_sub(last_active.to_duration());
+            if !liveness.silent && silent_for >= liveness.timeout {
+                trace!(?room_id, ?silent_for, "Room is silent");
+                liveness.silent = true;
+                events.send(RoomSilentEvent {
+                    room_id: *room_id,
+                    silent_for,
+                });
+            }
+        }
+    }
+
     /// Clear out the room metadata for any entity that was ever replicated
     pub fn clean_entity_despawns(
         // we use the removal of ReplicationGroup to detect if the entity was despawned
@@ -483,24 +690,76 @@ pub(super) mod systems {
 
 #[cfg(test)]
 mod tests {
-    use bevy::ecs::system::RunSystemOnce;
+    use bevy::ecs::system::{RunSystemOnce, SystemState};
     use bevy::prelude::Events;
     use bevy::utils::HashMap;
 
     use crate::prelude::client::*;
-    use crate::prelude::server::Replicate;
+    use crate::prelude::server::{RelevanceQuery, Replicate};
     use crate::prelude::*;
     use crate::server::relevance::immediate::systems::{
         add_cached_network_relevance, update_relevance_from_events,
     };
     use crate::server::relevance::immediate::{CachedNetworkRelevance, ClientRelevance};
     use crate::shared::replication::components::NetworkRelevanceMode;
-    use crate::tests::stepper::BevyStepper;
+    use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1, TEST_CLIENT_ID_2};
+    use crate::tests::protocol::{HistoryChannel, StringMessage};
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
 
     use super::systems::buffer_room_relevance_events;
 
     use super::*;
 
+    #[test]
+    fn test_try_room() {
+        let mut manager = RoomManager::default();
+        assert!(matches!(
+            manager.try_room(RoomId(0)),
+            Err(RelevanceError::RoomIdNotFound(RoomId(0)))
+        ));
+        manager.add_client(ClientId::Netcode(111), RoomId(0));
+        assert!(manager
+            .try_room(RoomId(0))
+            .unwrap()
+            .clients
+            .contains(&ClientId::Netcode(111)));
+    }
+
+    #[test]
+    fn test_try_remove() {
+        let mut manager = RoomManager::default();
+        let entity = Entity::from_raw(1);
+        assert!(matches!(
+            manager.try_remove_client(ClientId::Netcode(111), RoomId(0)),
+            Err(RelevanceError::RoomIdNotFound(RoomId(0)))
+        ));
+        assert!(matches!(
+            manager.try_remove_entity(entity, RoomId(0)),
+            Err(RelevanceError::RoomIdNotFound(RoomId(0)))
+        ));
+        // removing from a room that does not exist doesn't create it
+        manager.remove_client(ClientId::Netcode(111), RoomId(0));
+        manager.remove_entity(entity, RoomId(0));
+        assert!(manager.get_room(RoomId(0)).is_none());
+
+        manager.add_client(ClientId::Netcode(111), RoomId(0));
+        assert!(matches!(
+            manager.try_remove_client(ClientId::Netcode(222), RoomId(0)),
+            Err(RelevanceError::ClientNotInRoom(
+                ClientId::Netcode(222),
+                RoomId(0)
+            ))
+        ));
+        assert!(matches!(
+            manager.try_remove_entity(entity, RoomId(0)),
+            Err(RelevanceError::EntityNotInRoom(e, RoomId(0))) if e == entity
+        ));
+        manager
+            .try_remove_client(ClientId::Netcode(111), RoomId(0))
+            .unwrap();
+        assert!(!manager.has_client_id(ClientId::Netcode(111), RoomId(0)));
+    }
+
     #[test]
     // client is in a room
     // we add an entity to that room, then we remove it
@@ -1159,5 +1418,198 @@ mod tests {
         );
     }
 
+    #[derive(Resource, Default)]
+    struct SilentRooms(Vec<RoomId>);
+
+    #[test]
+    fn test_room_silence() {
+        let mut stepper = BevyStepper::default();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let room_id = RoomId(0);
+        stepper.server_app.init_resource::<SilentRooms>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<RoomSilentEvent>, mut rooms: ResMut<SilentRooms>| {
+                rooms.0.extend(events.read().map(|event| event.room_id));
+            },
+        );
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_client(client_id, room_id);
+        room_manager.set_silence_timeout(room_id, Duration::from_millis(100));
+
+        // the client keeps sending packets, so the room is not silent
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<SilentRooms>()
+            .0
+            .is_empty());
+
+        // the client disconnects: the room becomes silent after the timeout
+        stepper
+            .client_app
+            .world_mut()
+            .run_system_once(|mut commands: Commands| commands.disconnect_client());
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        // the event is only emitted once
+        assert_eq!(
+            stepper.server_app.world().resource::<SilentRooms>().0,
+            vec![room_id]
+        );
+    }
+
+    #[derive(Resource, Default)]
+    struct ReceivedStrings(Vec<String>);
+
+    fn receive_strings(
+        mut received: ResMut<ReceivedStrings>,
+        mut events: EventReader<crate::client::events::MessageEvent<StringMessage>>,
+    ) {
+        received
+            .0
+            .extend(events.read().map(|event| event.message().0.clone()));
+    }
+
+    fn send_to_room(stepper: &mut MultiBevyStepper, room_id: RoomId, message: &str) {
+        let world = stepper.server_app.world_mut();
+        world.resource_scope(|world, room_manager: Mut<RoomManager>| {
+            world
+                .resource_mut::<crate::server::connection::ConnectionManager>()
+                .send_message_to_room::<HistoryChannel, _>(
+                    &mut StringMessage(message.to_string()),
+                    room_id,
+                    &room_manager,
+                )
+                .unwrap();
+        });
+    }
+
+    /// Clients that join a room receive the last messages sent to the room on the channels
+    /// with `replay_on_join`
+    #[test]
+    fn test_room_history() {
+        let mut stepper = MultiBevyStepper::default();
+        for client_app in [&mut stepper.client_app_1, &mut stepper.client_app_2] {
+            client_app.init_resource::<ReceivedStrings>();
+            client_app.add_systems(Update, receive_strings);
+        }
+        let room_id = RoomId(1);
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RoomManager>()
+            .add_client(ClientId::Netcode(TEST_CLIENT_ID_1), room_id);
+        stepper.frame_step();
+        for message in ["a", "b", "c"] {
+            send_to_room(&mut stepper, room_id, message);
+            stepper.frame_step();
+        }
+
+        // the second client joins while a message is sent to the room
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RoomManager>()
+            .add_client(ClientId::Netcode(TEST_CLIENT_ID_2), room_id);
+        send_to_room(&mut stepper, room_id, "d");
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.client_app_1.world().resource::<ReceivedStrings>().0,
+            vec!["a", "b", "c", "d"]
+        );
+        // only the last 2 messages are kept, and "d" is not received twice
+        assert_eq!(
+            stepper.client_app_2.world().resource::<ReceivedStrings>().0,
+            vec!["c", "d"]
+        );
+    }
+
     // TODO: check that entity despawn/client disconnect cleans the room metadata
+
+    #[test]
+    fn test_iter_rooms() {
+        let mut room_manager = RoomManager::default();
+        let client_id = ClientId::Netcode(111);
+        room_manager.add_client(client_id, RoomId(0));
+        room_manager.add_client(client_id, RoomId(1));
+        room_manager.add_entity(Entity::from_raw(1), RoomId(2));
+
+        let mut rooms: Vec<_> = room_manager.rooms().map(|(room_id, _)| room_id).collect();
+        rooms.sort_by_key(|room_id| room_id.0);
+        assert_eq!(rooms, vec![RoomId(0), RoomId(1), RoomId(2)]);
+
+        let mut client_rooms: Vec<_> = room_manager.client_rooms(client_id).collect();
+        client_rooms.sort_by_key(|room_id| room_id.0);
+        assert_eq!(client_rooms, vec![RoomId(0), RoomId(1)]);
+        assert_eq!(room_manager.client_rooms(ClientId::Netcode(112)).count(), 0);
+    }
+
+    #[test]
+    fn test_relevance_query() {
+        let mut stepper = BevyStepper::default();
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let room_id = RoomId(0);
+        let spawn = |stepper: &mut BevyStepper| {
+            stepper
+                .server_app
+                .world_mut()
+                .spawn(Replicate {
+                    relevance_mode: NetworkRelevanceMode::InterestManagement,
+                    ..Default::default()
+                })
+                .id()
+        };
+        let room_entity = spawn(&mut stepper);
+        let explicit_entity = spawn(&mut stepper);
+        let hidden_entity = spawn(&mut stepper);
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_client(client_id, room_id);
+        room_manager.add_entity(room_entity, room_id);
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RelevanceManager>()
+            .gain_relevance(client_id, explicit_entity);
+        stepper.frame_step();
+
+        let mut system_state: SystemState<RelevanceQuery> =
+            SystemState::new(stepper.server_app.world_mut());
+        let query = system_state.get(stepper.server_app.world());
+        assert!(query.is_relevant(client_id, room_entity));
+        assert!(query.is_relevant(client_id, explicit_entity));
+        assert!(!query.is_relevant(client_id, hidden_entity));
+        assert_eq!(query.relevant_entities(client_id).count(), 2);
+        assert_eq!(
+            query.room_entities(client_id),
+            EntityHashSet::from_iter([room_entity])
+        );
+        assert_eq!(
+            query.explicit_entities(client_id),
+            EntityHashSet::from_iter([explicit_entity])
+        );
+        assert_eq!(
+            query.excluded_entities(client_id),
+            EntityHashSet::from_iter([hidden_entity])
+        );
+
+        // entities that lose relevance are excluded
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RelevanceManager>()
+            .lose_relevance(client_id, explicit_entity);
+        stepper.frame_step();
+        let query = system_state.get(stepper.server_app.world());
+        assert_eq!(
+            query.excluded_entities(client_id),
+            EntityHashSet::from_iter([explicit_entity, hidden_entity])
+        );
+    }
 }
@@ -25,6 +25,9 @@ pub type ReplicationSet = InternalReplicationSet<ServerMarker>;
 
 pub(crate) mod receive {
     use super::*;
+    use crate::prelude::server::MessageEvent;
+    use crate::server::local_write::LocalWritePlugin;
+    use crate::shared::replication::baseline::{BaselineReport, StaticBaseline};
 
     #[derive(Default)]
     pub struct ServerReplicationReceivePlugin {
@@ -38,18 +41,44 @@ pub(crate) mod receive {
                 .add_plugins(ReplicationReceivePlugin::<ConnectionManager>::new(
                     self.tick_interval,
                 ))
+                .add_plugins(LocalWritePlugin)
                 // SETS
                 .configure_sets(
                     PreUpdate,
                     ServerReplicationSet::ClientReplication
                         .run_if(is_started)
                         .after(InternalMainSet::<ServerMarker>::EmitEvents),
+                )
+                // SYSTEMS
+                .add_systems(
+                    PreUpdate,
+                    handle_baseline_report
+                        .run_if(is_started)
+                        .after(InternalMainSet::<ServerMarker>::EmitEvents),
                 );
         }
     }
+
+    /// Check if the clients that just connected have the same static baseline as the server
+    fn handle_baseline_report(
+        baseline: Option<Res<StaticBaseline>>,
+        mut messages: ResMut<Events<MessageEvent<BaselineReport>>>,
+        mut connection_manager: ResMut<ConnectionManager>,
+    ) {
+        for message in messages.drain() {
+            let matched = baseline
+                .as_ref()
+                .is_some_and(|baseline| message.message.hash == Some(baseline.hash));
+            let _ = connection_manager
+                .receive_baseline_report(message.context, matched)
+                .inspect_err(|e| error!("could not handle baseline report: {:?}", e));
+        }
+    }
 }
 
 pub(crate) mod send {
+    use std::borrow::Cow;
+
     use super::*;
     use crate::prelude::server::AuthorityCommandExt;
     use crate::prelude::{
@@ -58,13 +87,18 @@ pub(crate) mod send {
         TargetEntity, Tick, TickManager, TimeManager,
     };
     use crate::protocol::component::ComponentKind;
+    use crate::server::clients::ControlledEntities;
     use crate::server::error::ServerError;
+    use crate::server::pool::EntityPoolPlugin;
     use crate::server::prediction::handle_pre_predicted;
     use crate::server::relevance::immediate::{CachedNetworkRelevance, ClientRelevance};
+    use crate::server::spectator::SpectatorPlugin;
+    use crate::server::transient::TransientPlugin;
     use crate::shared::replication::archetypes::{
         get_erased_component, ServerReplicatedArchetypes,
     };
     use crate::shared::replication::authority::{AuthorityPeer, HasAuthority};
+    use crate::shared::replication::baseline::Baseline;
     use crate::shared::replication::components::{
         Cached, Controlled, InitialReplicated, Replicating, ReplicationGroupId, ReplicationTarget,
         ShouldBeInterpolated,
@@ -91,11 +125,15 @@ pub(crate) mod send {
             app
                 // REFLECTION
                 .register_type::<Replicate>()
+                .register_type::<ReplicationPaused>()
                 // PLUGIN
                 .add_plugins(ReplicationSendPlugin::<ConnectionManager>::new(
                     self.tick_interval,
                     send_interval,
                 ))
+                .add_plugins(EntityPoolPlugin)
+                .add_plugins(TransientPlugin)
+                .add_plugins(SpectatorPlugin)
                 // SYSTEM SETS
                 .configure_sets(
                     PostUpdate,
@@ -122,6 +160,9 @@ pub(crate) mod send {
                     replicate
                         .in_set(InternalReplicationSet::<ServerMarker>::BufferEntityUpdates)
                         .in_set(InternalReplicationSet::<ServerMarker>::BufferComponentUpdates),
+                    reconcile_despawned_entities
+                        .after(replicate)
+                        .in_set(InternalReplicationSet::<ServerMarker>::BufferEntityUpdates),
                     (
                         handle_replication_target_update,
                         buffer_replication_messages,
@@ -137,6 +178,7 @@ pub(crate) mod send {
                     .run_if(is_host_server),
             );
 
+            app.init_resource::<PendingDespawns>();
             app.observe(replicate_entity_local_despawn);
             app.observe(add_has_authority_component);
             app.observe(handle_pre_predicted);
@@ -163,6 +205,55 @@ pub(crate) mod send {
         pub interpolation: NetworkTarget,
     }
 
+    impl SyncTarget {
+        /// Predict the entity on the clients that control it, and interpolate it on all the other clients
+        pub fn from_ownership(controlled_by: &ControlledBy) -> Self {
+            let mut interpolation = NetworkTarget::All;
+            interpolation.exclude(&controlled_by.target);
+            Self {
+                prediction: controlled_by.target.clone(),
+                interpolation,
+            }
+        }
+
+        /// Returns the [`SyncTarget`] that should be used for the entity.
+        ///
+        /// A [`SyncTarget`] left to its default value is replaced by the [`DefaultSyncTarget`]
+        pub(crate) fn resolve(
+            &self,
+            default_sync_target: DefaultSyncTarget,
+            controlled_by: Option<&ControlledBy>,
+        ) -> Cow<'_, SyncTarget> {
+            match default_sync_target {
+                DefaultSyncTarget::Ownership if self == &SyncTarget::default() => Cow::Owned(
+                    SyncTarget::from_ownership(controlled_by.unwrap_or(&ControlledBy::default())),
+                ),
+                _ => Cow::Borrowed(self),
+            }
+        }
+    }
+
+    /// Marker component that stops the server from sending updates for the entity.
+    ///
+    /// The entity stays spawned on the clients, and despawns are still replicated.
+    /// Clients that receive the entity spawn while it is paused still receive its current components.
+    /// Use [`PauseReplicationCommandExt`](crate::prelude::server::PauseReplicationCommandExt) to pause
+    /// or resume the replication of an entity.
+    #[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+    #[reflect(Component)]
+    pub struct ReplicationPaused;
+
+    /// Which clients predict or interpolate the entities whose [`SyncTarget`] is not set explicitly
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+    pub enum DefaultSyncTarget {
+        /// The entities are neither predicted nor interpolated
+        #[default]
+        None,
+        /// The entities are predicted by the clients that control them (see [`ControlledBy`]),
+        /// and interpolated by all the other clients
+        Ownership,
+    }
+
     /// Component storing metadata about which clients have control over the entity
     ///
     /// This is only used for server to client replication.
@@ -259,6 +350,7 @@ pub(crate) mod send {
         //  should be sent with the same frequency!
         // clear the list of newly connected clients
         connection_manager.new_clients.clear();
+        connection_manager.new_baseline_clients.clear();
     }
 
     /// In HostServer mode, we will add the Predicted/Interpolated components to the server entities
@@ -273,10 +365,13 @@ pub(crate) mod send {
             Option<&PrePredicted>,
         )>,
         connection: Res<ClientConnection>,
+        config: Res<ServerConfig>,
     ) {
         let local_client = connection.id();
         for (entity, replication_target, sync_target, controlled_by, pre_predicted) in query.iter()
         {
+            let sync_target =
+                sync_target.resolve(config.default_sync_target, controlled_by.as_deref());
             // also insert [`Controlled`] on the entity if it's controlled by the local client
             if let Some(controlled_by) = controlled_by {
                 if controlled_by.is_changed() && controlled_by.targets(&local_client) {
@@ -385,13 +480,19 @@ pub(crate) mod send {
         component_registry: Res<ComponentRegistry>,
         mut replicated_archetypes: Local<ServerReplicatedArchetypes>,
         system_ticks: SystemChangeTick,
+        config: Res<ServerConfig>,
         mut set: ParamSet<(&World, ResMut<ConnectionManager>)>,
     ) {
+        let mut sender = std::mem::take(&mut *set.p1());
+        // the default ConnectionManager that replaces the sender is not part of the audit
+        #[cfg(feature = "alloc_audit")]
+        let counter = crate::shared::alloc_audit::AllocationCounter::start();
         // 1. update the list of replicated archetypes
         replicated_archetypes.update(set.p0(), &component_registry);
 
-        let mut sender = std::mem::take(&mut *set.p1());
         let world = set.p0();
+        // clients to which we need to replicate the static baseline entities
+        let baseline_target = sender.baseline_target();
 
         // 2. go through all the archetypes that should be replicated
         for replicated_archetype in replicated_archetypes.archetypes.iter() {
@@ -425,11 +526,17 @@ pub(crate) mod send {
                 let priority = group.map_or(1.0, |g| g.priority());
                 let cached_replication_target = entity_ref.get::<Cached<ReplicationTarget>>();
                 let visibility = entity_ref.get::<CachedNetworkRelevance>();
-                let sync_target = entity_ref.get::<SyncTarget>();
                 let target_entity = entity_ref.get::<TargetEntity>();
                 let controlled_by = entity_ref.get::<ControlledBy>();
+                let sync_target = entity_ref
+                    .get::<SyncTarget>()
+                    .map(|sync| sync.resolve(config.default_sync_target, controlled_by));
+                let sync_target = sync_target.as_deref();
                 let authority_peer = entity_ref.get::<AuthorityPeer>();
                 let initial_replicated = entity_ref.get::<InitialReplicated>();
+                let baseline = entity_ref
+                    .contains::<Baseline>()
+                    .then_some(&baseline_target);
                 // SAFETY: we know that the entity has the ReplicationTarget component
                 // because the archetype is in replicated_archetypes
                 let replication_target =
@@ -478,6 +585,7 @@ pub(crate) mod send {
                     target_entity,
                     authority_peer,
                     visibility,
+                    baseline,
                     &mut sender,
                     &system_ticks,
                 );
@@ -487,6 +595,10 @@ pub(crate) mod send {
                     continue;
                 }
 
+                // The replication of the entity is paused: don't send updates, but still send
+                // the components to the clients that receive the entity spawn
+                let paused = entity_ref.contains::<ReplicationPaused>();
+
                 // d. all components that were added or changed
                 for replicated_component in replicated_archetype.components.iter() {
                     let (data, component_ticks) = unsafe {
@@ -521,6 +633,8 @@ pub(crate) mod send {
                         replicated_component.delta_compression,
                         replicated_component.replicate_once,
                         override_target,
+                        baseline,
+                        paused,
                         &system_ticks,
                         &mut sender,
                     );
@@ -530,6 +644,10 @@ pub(crate) mod send {
             }
         }
 
+        #[cfg(feature = "alloc_audit")]
+        {
+            sender.allocations.replication = counter.finish();
+        }
         *set.p1() = sender;
     }
 
@@ -537,7 +655,8 @@ pub(crate) mod send {
     /// Also handles:
     /// - newly_connected_clients should receive the entity spawn message even if the entity was not just spawned
     /// - adds ControlledBy, ShouldBePredicted, ShouldBeInterpolated component
-    /// - handles TargetEntity if it's a Preexisting entity
+    /// - handles TargetEntity if it's a Preexisting or Stable entity
+    /// - only replicates [`Baseline`] entities to the clients that don't have the static baseline
     pub(crate) fn replicate_entity_spawn(
         component_registry: &ComponentRegistry,
         entity: Entity,
@@ -551,9 +670,16 @@ pub(crate) mod send {
         target_entity: Option<&TargetEntity>,
         authority_peer: Option<&AuthorityPeer>,
         visibility: Option<&CachedNetworkRelevance>,
+        baseline: Option<&NetworkTarget>,
         sender: &mut ConnectionManager,
         system_ticks: &SystemChangeTick,
     ) {
+        // clients that just reported that they don't have the static baseline
+        let new_baseline_clients = if baseline.is_some() {
+            sender.new_baseline_clients.clone()
+        } else {
+            vec![]
+        };
         let mut target = match visibility {
             // for room mode, no need to handle newly-connected clients specially; they just need
             // to be added to the correct room
@@ -572,10 +698,14 @@ pub(crate) mod send {
                                     );
                                     return Some(*client_id);
                                 }
-                                ClientRelevance::Lost => {}
+                                ClientRelevance::Lost
+                                | ClientRelevance::HeldOut
+                                | ClientRelevance::Evicted => {}
                                 ClientRelevance::Maintained => {
                                     // only try to replicate if the replicate component was just added
-                                    if replication_target.is_added() {
+                                    if replication_target.is_added()
+                                        || new_baseline_clients.contains(client_id)
+                                    {
                                         trace!(
                                             ?entity,
                                             ?client_id,
@@ -608,7 +738,12 @@ pub(crate) mod send {
                 }
 
                 // also replicate to the newly connected clients that match the target
-                let new_connected_clients = sender.new_connected_clients();
+                // (for baseline entities, the clients that just reported that they need the baseline)
+                let new_connected_clients = if baseline.is_some() {
+                    new_baseline_clients
+                } else {
+                    sender.new_connected_clients()
+                };
                 if !new_connected_clients.is_empty() {
                     // replicate to the newly connected clients that match our target
                     let mut new_connected_target = NetworkTarget::Only(new_connected_clients);
@@ -627,6 +762,10 @@ pub(crate) mod send {
         if let Some(client_id) = initial_replicated.and_then(|r| r.from) {
             target.exclude(&NetworkTarget::Single(client_id));
         };
+        // baseline entities are only sent to clients that don't have the baseline
+        if let Some(baseline_target) = baseline {
+            target.intersection(baseline_target);
+        }
         if target.is_empty() {
             return;
         }
@@ -676,17 +815,27 @@ pub(crate) mod send {
                     )?;
                 }
 
-                if let Some(TargetEntity::Preexisting(remote_entity)) = target_entity {
-                    sender
-                        .connection_mut(client_id)?
-                        .replication_sender
-                        .prepare_entity_spawn_reuse(entity, group_id, *remote_entity);
-                } else {
-                    sender
-                        .connection_mut(client_id)?
-                        .replication_sender
-                        .prepare_entity_spawn(entity, group_id);
+                match target_entity {
+                    Some(TargetEntity::Preexisting(remote_entity)) => {
+                        sender
+                            .connection_mut(client_id)?
+                            .replication_sender
+                            .prepare_entity_spawn_reuse(entity, group_id, *remote_entity);
+                    }
+                    Some(TargetEntity::Stable(stable_id)) => {
+                        sender
+                            .connection_mut(client_id)?
+                            .replication_sender
+                            .prepare_entity_spawn_stable(entity, group_id, *stable_id);
+                    }
+                    _ => {
+                        sender
+                            .connection_mut(client_id)?
+                            .replication_sender
+                            .prepare_entity_spawn(entity, group_id);
+                    }
                 }
+                sender.connection_mut(client_id)?.world_view.spawn(entity);
 
                 // also set the priority for the group when we spawn it
                 sender
@@ -713,9 +862,10 @@ pub(crate) mod send {
             ),
             With<Replicating>,
         >,
-        // TODO: should we use Option<ResMut> so that this observer doesn't trigger
-        //  when we are not connected?
-        mut sender: ResMut<ConnectionManager>,
+        // the ConnectionManager can be missing if the entity is despawned while it is taken out of the world:
+        // the despawn is then replicated by `reconcile_despawned_entities`
+        sender: Option<ResMut<ConnectionManager>>,
+        mut pending: ResMut<PendingDespawns>,
     ) {
         let entity = trigger.entity();
         if let Ok((replication_group, network_target, cached_relevance)) = query.get(entity) {
@@ -725,13 +875,29 @@ pub(crate) mod send {
             // only send the despawn to clients that had visibility of the entity
             if let Some(network_relevance) = cached_relevance {
                 // TODO: optimize this in cases like All/None/Single/ExceptSingle
+                // (the entities held out by the entity limit were not spawned on the client)
                 target.intersection(&NetworkTarget::Only(
-                    network_relevance.clients_cache.keys().copied().collect(),
+                    network_relevance
+                        .clients_cache
+                        .iter()
+                        .filter(|(_, relevance)| {
+                            !matches!(
+                                relevance,
+                                ClientRelevance::HeldOut | ClientRelevance::Evicted
+                            )
+                        })
+                        .map(|(client_id, _)| *client_id)
+                        .collect(),
                 ))
             }
+            let group_id = replication_group.group_id(Some(entity));
+            let Some(mut sender) = sender else {
+                pending.0.push((entity, group_id, target));
+                return;
+            };
             trace!(?entity, ?target, "send entity despawn");
             let _ = sender
-                .prepare_entity_despawn(entity, replication_group.group_id(Some(entity)), target)
+                .prepare_entity_despawn(entity, group_id, target)
                 // TODO: bubble up errors to user via ConnectionEvents?
                 .inspect_err(|e| {
                     error!("error sending entity despawn: {:?}", e);
@@ -739,6 +905,39 @@ pub(crate) mod send {
         }
     }
 
+    /// Despawns of replicated entities that happened while the [`ConnectionManager`] was taken out of the world
+    #[derive(Resource, Default, Debug)]
+    pub(crate) struct PendingDespawns(Vec<(Entity, ReplicationGroupId, NetworkTarget)>);
+
+    /// Replicate the despawn of the entities that were despawned while the [`ConnectionManager`] was taken
+    /// out of the world, and remove them from the [`ControlledEntities`] of the clients
+    pub(crate) fn reconcile_despawned_entities(
+        mut pending: ResMut<PendingDespawns>,
+        mut sender: ResMut<ConnectionManager>,
+        mut controlled_entities: Query<&mut ControlledEntities>,
+    ) {
+        if pending.0.is_empty() {
+            return;
+        }
+        for (entity, group_id, target) in pending.0.drain(..) {
+            debug!(
+                ?entity,
+                "Replicate the despawn of an entity despawned outside of the ConnectionManager"
+            );
+            let _ = sender
+                .prepare_entity_despawn(entity, group_id, target)
+                .inspect_err(|e| {
+                    error!("error sending entity despawn: {:?}", e);
+                });
+            for mut controlled in controlled_entities.iter_mut() {
+                // check first to not trigger change detection needlessly
+                if controlled.contains(&entity) {
+                    controlled.remove(&entity);
+                }
+            }
+        }
+    }
+
     /// Send entity despawn is:
     /// 1) the client lost visibility of the entity
     /// 2) the replication target was updated and the client is no longer in the ReplicationTarget
@@ -759,7 +958,7 @@ pub(crate) mod send {
                     .iter()
                     .filter_map(|(client_id, visibility)| {
                         if replication_target.target.targets(client_id)
-                            && matches!(visibility, ClientRelevance::Lost) {
+                            && matches!(visibility, ClientRelevance::Lost | ClientRelevance::Evicted) {
                             debug!(
                                 "sending entity despawn for entity: {:?} because ClientVisibility::Lost",
                                 entity
@@ -823,6 +1022,8 @@ pub(crate) mod send {
         delta_compression: bool,
         replicate_once: bool,
         override_target: Option<&NetworkTarget>,
+        baseline: Option<&NetworkTarget>,
+        paused: bool,
         system_ticks: &SystemChangeTick,
         sender: &mut ConnectionManager,
     ) {
@@ -834,6 +1035,12 @@ pub(crate) mod send {
         // TODO: is this correct?
         // to be safe, if the replication target is added, we force an insert
         let force_insert = replication_target.is_changed();
+        // clients that just reported that they don't have the static baseline
+        let new_baseline_clients = if baseline.is_some() {
+            sender.new_baseline_clients.clone()
+        } else {
+            vec![]
+        };
         let (mut insert_target, mut update_target): (NetworkTarget, NetworkTarget) =
             match visibility {
                 Some(visibility) => {
@@ -848,13 +1055,16 @@ pub(crate) mod send {
                                     ClientRelevance::Gained => {
                                         insert_clients.push(*client_id);
                                     }
-                                    ClientRelevance::Lost => {}
+                                    ClientRelevance::Lost
+                                    | ClientRelevance::HeldOut
+                                    | ClientRelevance::Evicted => {}
                                     ClientRelevance::Maintained => {
                                         // send a component_insert for components that were newly added
                                         if component_ticks.is_added(
                                             system_ticks.last_run(),
                                             system_ticks.this_run(),
                                         ) || force_insert
+                                            || new_baseline_clients.contains(client_id)
                                         {
                                             insert_clients.push(*client_id);
                                         } else {
@@ -905,7 +1115,12 @@ pub(crate) mod send {
                         update_target.union(target);
                     }
 
-                    let new_connected_clients = sender.new_connected_clients();
+                    // (for baseline entities, the clients that just reported that they need the baseline)
+                    let new_connected_clients = if baseline.is_some() {
+                        new_baseline_clients
+                    } else {
+                        sender.new_connected_clients()
+                    };
                     // replicate all components to newly connected clients
                     if !new_connected_clients.is_empty() {
                         // replicate to the newly connected clients that match our target
@@ -923,9 +1138,18 @@ pub(crate) mod send {
             insert_target.exclude(&NetworkTarget::Single(*c));
             update_target.exclude(&NetworkTarget::Single(*c));
         }
+        // baseline entities are only sent to clients that don't have the baseline
+        if let Some(baseline_target) = baseline {
+            insert_target.intersection(baseline_target);
+            update_target.intersection(baseline_target);
+        }
 
         // do not send a component as both update and insert
         update_target.exclude(&insert_target);
+        // the entity's replication is paused, only send the components to the clients that don't have them
+        if paused {
+            update_target = NetworkTarget::None;
+        }
 
         if !insert_target.is_empty() || !update_target.is_empty() {
             if !insert_target.is_empty() {
@@ -1035,7 +1259,13 @@ pub(crate) mod send {
                 }
                 let group_id = group.group_id(Some(entity));
                 debug!(?entity, ?kind, "Sending RemoveComponent");
-                let _ = sender.prepare_component_remove(entity, kind, group, target);
+                let _ = sender.prepare_component_remove(
+                    entity,
+                    kind,
+                    ComponentKind::of::<C>(),
+                    group,
+                    target,
+                );
             }
         })
     }
@@ -1064,8 +1294,9 @@ pub(crate) mod send {
         use crate::prelude::client::Confirmed;
         use crate::prelude::server::{ControlledBy, NetConfig, RelevanceManager, Replicate};
         use crate::prelude::{
-            client, server, DeltaCompression, LinkConditionerConfig, ReplicateOnceComponent,
-            Replicated,
+            client, server, Baseline, DeltaCompression, LinkConditionerConfig,
+            ReplicateOnceComponent, Replicated, SharedConfig, StableEntityMap, StableId,
+            StaticBaseline, TickConfig,
         };
         use crate::server::replication::send::SyncTarget;
         use crate::shared::replication::components::{Controlled, ReplicationGroupId};
@@ -1144,6 +1375,73 @@ pub(crate) mod send {
                 .is_some());
         }
 
+        #[test]
+        fn test_default_sync_target_ownership() {
+            let mut stepper = BevyStepper::default();
+            stepper
+                .server_app
+                .world_mut()
+                .resource_mut::<ServerConfig>()
+                .default_sync_target = DefaultSyncTarget::Ownership;
+
+            let controlled = stepper
+                .server_app
+                .world_mut()
+                .spawn(Replicate {
+                    controlled_by: ControlledBy {
+                        target: NetworkTarget::Single(ClientId::Netcode(TEST_CLIENT_ID)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            let uncontrolled = stepper
+                .server_app
+                .world_mut()
+                .spawn(Replicate::default())
+                .id();
+            // an explicit SyncTarget overrides the default
+            let overridden = stepper
+                .server_app
+                .world_mut()
+                .spawn(Replicate {
+                    sync: SyncTarget {
+                        prediction: NetworkTarget::All,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+
+            let confirmed = |server_entity: Entity| {
+                let client_entity = stepper
+                    .client_app
+                    .world()
+                    .resource::<client::ConnectionManager>()
+                    .replication_receiver
+                    .remote_entity_map
+                    .get_local(server_entity)
+                    .expect("entity was not replicated to client");
+                stepper
+                    .client_app
+                    .world()
+                    .get::<Confirmed>(client_entity)
+                    .map(|confirmed| {
+                        (
+                            confirmed.predicted.is_some(),
+                            confirmed.interpolated.is_some(),
+                        )
+                    })
+                    .expect("Confirmed component missing")
+            };
+            // (predicted, interpolated)
+            assert_eq!(confirmed(controlled), (true, false));
+            assert_eq!(confirmed(uncontrolled), (false, true));
+            assert_eq!(confirmed(overridden), (true, false));
+        }
+
         #[test]
         fn test_multi_entity_spawn() {
             let mut stepper = BevyStepper::default();
@@ -1268,6 +1566,234 @@ pub(crate) mod send {
                 .is_some());
         }
 
+        #[test]
+        fn test_entity_spawn_stable_target() {
+            let mut stepper = BevyStepper::default();
+
+            // the client has pre-registered an entity for the stable id
+            let client_entity = stepper
+                .client_app
+                .world_mut()
+                .spawn(ComponentSyncModeFull(1.0))
+                .id();
+            stepper
+                .client_app
+                .world_mut()
+                .resource_mut::<StableEntityMap>()
+                .register(StableId(1), client_entity);
+            stepper.frame_step();
+            let server_entity = stepper
+                .server_app
+                .world_mut()
+                .spawn((
+                    Replicate::default(),
+                    TargetEntity::Stable(StableId(1)),
+                    ComponentSyncModeSimple(2.0),
+                ))
+                .id();
+            // the stable id was not registered on the client, a new entity gets spawned
+            let server_entity_2 = stepper
+                .server_app
+                .world_mut()
+                .spawn((Replicate::default(), TargetEntity::Stable(StableId(2))))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+
+            // check that the entity was replicated on the registered client entity
+            let receiver = &stepper
+                .client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver;
+            assert_eq!(
+                receiver.remote_entity_map.get_local(server_entity).unwrap(),
+                client_entity
+            );
+            let client_entity_2 = receiver
+                .remote_entity_map
+                .get_local(server_entity_2)
+                .unwrap();
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world()
+                    .get::<ComponentSyncModeSimple>(client_entity)
+                    .unwrap(),
+                &ComponentSyncModeSimple(2.0)
+            );
+            assert!(stepper
+                .client_app
+                .world()
+                .get::<Replicated>(client_entity)
+                .is_some());
+            // the newly spawned entity is registered for the next connection
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world()
+                    .resource::<StableEntityMap>()
+                    .get(StableId(2)),
+                Some(client_entity_2)
+            );
+        }
+
+        #[test]
+        fn test_entity_respawn_stable_coalesced() {
+            let mut stepper = BevyStepper::default();
+            let server_entity = stepper
+                .server_app
+                .world_mut()
+                .spawn((
+                    Replicate::default(),
+                    TargetEntity::Stable(StableId(1)),
+                    ComponentSyncModeFull(1.0),
+                    ComponentSyncModeSimple(1.0),
+                ))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = stepper
+                .client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+
+            // the entity is returned to the pool and reused in the same tick
+            stepper.server_app.world_mut().despawn(server_entity);
+            let server_entity_2 = stepper
+                .server_app
+                .world_mut()
+                .spawn((
+                    Replicate::default(),
+                    TargetEntity::Stable(StableId(1)),
+                    ComponentSyncModeFull(2.0),
+                ))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+
+            // the client entity was reset in place instead of being despawned
+            let receiver = &stepper
+                .client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver;
+            assert_eq!(
+                receiver.remote_entity_map.get_local(server_entity_2),
+                Some(client_entity)
+            );
+            assert!(receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .is_none());
+            let client_entity_ref = stepper.client_app.world().entity(client_entity);
+            assert_eq!(
+                client_entity_ref.get::<ComponentSyncModeFull>(),
+                Some(&ComponentSyncModeFull(2.0))
+            );
+            assert!(client_entity_ref.get::<ComponentSyncModeSimple>().is_none());
+
+            // the group of the previous entity is not kept around
+            let previous_group = ReplicationGroupId(server_entity.to_bits());
+            assert!(!receiver.group_channels.contains_key(&previous_group));
+            assert!(!stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .connection(ClientId::Netcode(TEST_CLIENT_ID))
+                .unwrap()
+                .replication_sender
+                .group_channels
+                .contains_key(&previous_group));
+        }
+
+        /// Spawn a baseline entity on the server, then connect a client with the given baseline hash
+        fn baseline_stepper(client_baseline: Option<u64>) -> (BevyStepper, Entity) {
+            let tick_duration = Duration::from_millis(10);
+            let shared_config = SharedConfig {
+                tick: TickConfig::new(tick_duration),
+                ..Default::default()
+            };
+            let mut stepper = BevyStepper::new(
+                shared_config,
+                client::ClientConfig::default(),
+                tick_duration,
+            );
+            stepper.server_app.insert_resource(StaticBaseline::new(1));
+            if let Some(hash) = client_baseline {
+                stepper
+                    .client_app
+                    .insert_resource(StaticBaseline::new(hash));
+            }
+            let server_entity = stepper
+                .server_app
+                .world_mut()
+                .spawn((Replicate::default(), Baseline, ComponentSyncModeFull(1.0)))
+                .id();
+            stepper.init();
+            stepper.frame_step();
+            stepper.frame_step();
+            (stepper, server_entity)
+        }
+
+        #[test]
+        fn test_baseline_matched() {
+            let (mut stepper, server_entity) = baseline_stepper(Some(1));
+            // the client has the same baseline, the entity is not replicated
+            assert!(stepper
+                .client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .is_none());
+
+            // updates are not replicated either
+            stepper
+                .server_app
+                .world_mut()
+                .get_mut::<ComponentSyncModeFull>(server_entity)
+                .unwrap()
+                .0 = 2.0;
+            stepper.frame_step();
+            stepper.frame_step();
+            assert!(stepper
+                .client_app
+                .world_mut()
+                .query::<&ComponentSyncModeFull>()
+                .get_single(stepper.client_app.world())
+                .is_err());
+        }
+
+        #[test]
+        fn test_baseline_mismatched() {
+            for client_baseline in [None, Some(2)] {
+                let (stepper, server_entity) = baseline_stepper(client_baseline);
+                // the client doesn't have the baseline, the entity is replicated
+                let client_entity = stepper
+                    .client_app
+                    .world()
+                    .resource::<client::ConnectionManager>()
+                    .replication_receiver
+                    .remote_entity_map
+                    .get_local(server_entity)
+                    .expect("baseline entity was not replicated");
+                assert_eq!(
+                    stepper
+                        .client_app
+                        .world()
+                        .get::<ComponentSyncModeFull>(client_entity)
+                        .unwrap(),
+                    &ComponentSyncModeFull(1.0)
+                );
+            }
+        }
+
         /// Check that if we change the replication target on an entity that already has one
         /// we spawn the entity for new clients
         #[test]
@@ -1356,6 +1882,62 @@ pub(crate) mod send {
                 .is_none());
         }
 
+        /// The despawn of an entity that is despawned while the ConnectionManager is taken out of the world
+        /// is still replicated
+        #[test]
+        fn test_entity_despawn_outside_connection_manager() {
+            let mut stepper = BevyStepper::default();
+
+            let server_entity = stepper
+                .server_app
+                .world_mut()
+                .spawn(Replicate {
+                    controlled_by: ControlledBy {
+                        target: NetworkTarget::All,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = stepper
+                .client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .expect("entity was not replicated to client");
+
+            stepper
+                .server_app
+                .world_mut()
+                .resource_scope(|world, _: Mut<ConnectionManager>| {
+                    world.despawn(server_entity);
+                });
+            stepper.frame_step();
+            stepper.frame_step();
+
+            assert!(stepper
+                .client_app
+                .world()
+                .get_entity(client_entity)
+                .is_none());
+            let client_metadata = stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .client_entity(ClientId::Netcode(TEST_CLIENT_ID))
+                .unwrap();
+            assert!(stepper
+                .server_app
+                .world()
+                .get::<ControlledEntities>(client_metadata)
+                .unwrap()
+                .is_empty());
+        }
+
         /// Check that if interest management is used, a client losing visibility of an entity
         /// will cause the server to send a despawn-entity message to the client
         #[test]
@@ -2429,12 +3011,12 @@ pub(crate) mod send {
                 .first_mut()
                 .unwrap()
             {
-                io.conditioner = Some(LinkConditionerConfig {
+                io.conditioner = Some(LinkConditionerConfig::new(
                     // the server receives client packets after 3 ticks
-                    incoming_latency: Duration::from_millis(30),
-                    incoming_jitter: Default::default(),
-                    incoming_loss: 0.0,
-                })
+                    Duration::from_millis(30),
+                    Duration::default(),
+                    0.0,
+                ))
             }
             stepper.start();
 
@@ -2601,12 +3183,12 @@ pub(crate) mod send {
                 .first_mut()
                 .unwrap()
             {
-                io.conditioner = Some(LinkConditionerConfig {
+                io.conditioner = Some(LinkConditionerConfig::new(
                     // the server receives client packets after 3 ticks
-                    incoming_latency: Duration::from_millis(30),
-                    incoming_jitter: Default::default(),
-                    incoming_loss: 0.0,
-                })
+                    Duration::from_millis(30),
+                    Duration::default(),
+                    0.0,
+                ))
             }
             stepper.start();
 
@@ -3099,7 +3681,9 @@ pub(crate) mod send {
 
 pub(crate) mod commands {
     use crate::channel::builder::AuthorityChannel;
-    use crate::prelude::server::{ConnectionManager, SyncTarget};
+    use crate::prelude::server::{
+        ConnectionManager, ControlledBy, ReplicationPaused, ServerConfig, SyncTarget,
+    };
     use crate::prelude::{
         ClientId, ComponentRegistry, Replicated, Replicating, ReplicationGroup,
         ServerConnectionManager, ShouldBePredicted,
@@ -3107,11 +3691,14 @@ pub(crate) mod commands {
     use crate::shared::replication::authority::{AuthorityChange, AuthorityPeer, HasAuthority};
     use crate::shared::replication::components::{InitialReplicated, ShouldBeInterpolated};
     use bevy::ecs::system::EntityCommands;
-    use bevy::prelude::{Entity, Mut, World};
+    use bevy::prelude::{DetectChangesMut, Entity, Mut, World};
     use tracing::{error, warn};
 
     pub trait AuthorityCommandExt {
         /// This command is used to transfer the authority of an entity to a different peer.
+        ///
+        /// The transfer is skipped if the entity was despawned or if the new owner is a client that is
+        /// not connected by the time the command is applied.
         fn transfer_authority(&mut self, new_owner: AuthorityPeer);
     }
 
@@ -3119,15 +3706,26 @@ pub(crate) mod commands {
         fn transfer_authority(&mut self, new_owner: AuthorityPeer) {
             self.add(move |entity: Entity, world: &mut World| {
                 // check who the current owner is
-                let current_owner =
-                    world
-                        .get_entity(entity)
-                        .map_or(AuthorityPeer::None, |entity| {
-                            entity
-                                .get::<AuthorityPeer>()
-                                .copied()
-                                .unwrap_or(AuthorityPeer::None)
-                        });
+                let Some(entity_ref) = world.get_entity(entity) else {
+                    error!(
+                        ?entity,
+                        "Could not transfer the authority of an entity that does not exist"
+                    );
+                    return;
+                };
+                let current_owner = entity_ref
+                    .get::<AuthorityPeer>()
+                    .copied()
+                    .unwrap_or(AuthorityPeer::None);
+                if let AuthorityPeer::Client(c) = new_owner {
+                    if let Err(e) = world.resource::<ServerConnectionManager>().connection(c) {
+                        error!(
+                            ?entity,
+                            "Could not transfer the authority to client {c:?}: {e:?}"
+                        );
+                        return;
+                    }
+                }
 
                 // TODO: handle authority transfers in host-server mode!
                 //  when transferring to local-client, we want to transfer to the server instead?
@@ -3142,16 +3740,7 @@ pub(crate) mod commands {
                         world
                             .entity_mut(entity)
                             .insert((AuthorityPeer::Client(c), Replicated { from: Some(c) }));
-                        world
-                            .resource_mut::<ServerConnectionManager>()
-                            .send_message::<AuthorityChannel, _>(
-                                c,
-                                &mut AuthorityChange {
-                                    entity,
-                                    gain_authority: true,
-                                },
-                            )
-                            .expect("could not send message");
+                        send_authority_change(world, c, entity, true);
                     }
                     (AuthorityPeer::Server, AuthorityPeer::None) => {
                         world
@@ -3164,16 +3753,7 @@ pub(crate) mod commands {
                             .entity_mut(entity)
                             .remove::<Replicated>()
                             .insert(AuthorityPeer::None);
-                        world
-                            .resource_mut::<ServerConnectionManager>()
-                            .send_message::<AuthorityChannel, _>(
-                                c,
-                                &mut AuthorityChange {
-                                    entity,
-                                    gain_authority: false,
-                                },
-                            )
-                            .expect("could not send message");
+                        send_authority_change(world, c, entity, false);
                     }
                     (AuthorityPeer::Client(c), AuthorityPeer::Server) => {
                         // TODO: only gain the authority when we have received an ack
@@ -3182,16 +3762,7 @@ pub(crate) mod commands {
                             .entity_mut(entity)
                             .remove::<Replicated>()
                             .insert((HasAuthority, AuthorityPeer::Server));
-                        world
-                            .resource_mut::<ServerConnectionManager>()
-                            .send_message::<AuthorityChannel, _>(
-                                c,
-                                &mut AuthorityChange {
-                                    entity,
-                                    gain_authority: false,
-                                },
-                            )
-                            .expect("could not send message");
+                        send_authority_change(world, c, entity, false);
                         // TODO: this is very flimsy, find a better solution? https://github.com/cBournhonesque/lightyear/issues/639
                         send_sync_components(world, entity, c);
                     }
@@ -3200,41 +3771,14 @@ pub(crate) mod commands {
                             .entity_mut(entity)
                             .remove::<HasAuthority>()
                             .insert((AuthorityPeer::Client(c), Replicated { from: Some(c) }));
-                        world
-                            .resource_mut::<ServerConnectionManager>()
-                            .send_message::<AuthorityChannel, _>(
-                                c,
-                                &mut AuthorityChange {
-                                    entity,
-                                    gain_authority: true,
-                                },
-                            )
-                            .expect("could not send message");
+                        send_authority_change(world, c, entity, true);
                     }
                     (AuthorityPeer::Client(c1), AuthorityPeer::Client(c2)) => {
                         world
                             .entity_mut(entity)
                             .insert((AuthorityPeer::Client(c2), Replicated { from: Some(c2) }));
-                        world
-                            .resource_mut::<ServerConnectionManager>()
-                            .send_message::<AuthorityChannel, _>(
-                                c1,
-                                &mut AuthorityChange {
-                                    entity,
-                                    gain_authority: false,
-                                },
-                            )
-                            .expect("could not send message");
-                        world
-                            .resource_mut::<ServerConnectionManager>()
-                            .send_message::<AuthorityChannel, _>(
-                                c2,
-                                &mut AuthorityChange {
-                                    entity,
-                                    gain_authority: true,
-                                },
-                            )
-                            .expect("could not send message");
+                        send_authority_change(world, c1, entity, false);
+                        send_authority_change(world, c2, entity, true);
                         // TODO: this is very flimsy, find a better solution? https://github.com/cBournhonesque/lightyear/issues/639
                         send_sync_components(world, entity, c1);
                     }
@@ -3244,6 +3788,32 @@ pub(crate) mod commands {
         }
     }
 
+    /// Notify the client that it gained or lost the authority over the entity
+    ///
+    /// The previous owner may have disconnected, in which case there is no one to notify.
+    fn send_authority_change(
+        world: &mut World,
+        client: ClientId,
+        entity: Entity,
+        gain_authority: bool,
+    ) {
+        let _ = world
+            .resource_mut::<ServerConnectionManager>()
+            .send_message::<AuthorityChannel, _>(
+                client,
+                &mut AuthorityChange {
+                    entity,
+                    gain_authority,
+                },
+            )
+            .inspect_err(|e| {
+                error!(
+                    ?entity,
+                    "Could not send the authority change to client {client:?}: {e:?}"
+                )
+            });
+    }
+
     // TODO: this is very flimsy, find a better solution? https://github.com/cBournhonesque/lightyear/issues/639
     // If the client that had authority was the original owner, then we might want
     // to send a message to it to add ShouldBePredicted or ShouldBeInterpolated
@@ -3257,7 +3827,12 @@ pub(crate) mod commands {
             if let Some(rep) = world.entity(entity).get::<InitialReplicated>() {
                 if let Some(from_client) = rep.from {
                     if from_client == client {
-                        if let Some(sync_target) = world.entity(entity).get::<SyncTarget>() {
+                        let default_sync_target = world.resource::<ServerConfig>().default_sync_target;
+                        let entity_ref = world.entity(entity);
+                        if let Some(sync_target) = entity_ref.get::<SyncTarget>().map(|sync| {
+                            sync.resolve(default_sync_target, entity_ref.get::<ControlledBy>())
+                                .into_owned()
+                        }) {
                             if sync_target.prediction.targets(&client) {
                                 warn!("Sending ShouldBePredicted to client {client:?} that lost authority");
                                 let group_id = world
@@ -3336,6 +3911,43 @@ pub(crate) mod commands {
         }
     }
 
+    fn resume_replication(entity: Entity, world: &mut World) {
+        world.resource_scope(|world, registry: Mut<ComponentRegistry>| {
+            let Some(mut entity_mut) = world.get_entity_mut(entity) else {
+                return;
+            };
+            if entity_mut.take::<ReplicationPaused>().is_none() {
+                return;
+            }
+            // the updates that happened while the replication was paused were not sent,
+            // so mark all the replicated components as changed to send their current value
+            for metadata in registry.replication_map.values() {
+                if let Some(mut component) = entity_mut.get_mut_by_id(metadata.component_id) {
+                    component.set_changed();
+                }
+            }
+        });
+    }
+
+    pub trait PauseReplicationCommandExt {
+        /// Stop sending updates for the entity, while keeping it spawned on the clients.
+        ///
+        /// Does nothing if the entity was despawned by the time the command is applied.
+        fn pause_replication(&mut self);
+
+        /// Resume sending updates for the entity. The current value of all the replicated components is sent.
+        fn resume_replication(&mut self);
+    }
+    impl PauseReplicationCommandExt for EntityCommands<'_> {
+        fn pause_replication(&mut self) {
+            self.try_insert(ReplicationPaused);
+        }
+
+        fn resume_replication(&mut self) {
+            self.add(resume_replication);
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         use bevy::prelude::With;
@@ -3409,5 +4021,94 @@ pub(crate) mod commands {
                 .get_single(stepper.client_app.world())
                 .is_ok());
         }
+
+        #[test]
+        fn test_pause_replication() {
+            let mut stepper = BevyStepper::default();
+            let entity = stepper
+                .server_app
+                .world_mut()
+                .spawn((ComponentSyncModeFull(1.0), Replicate::default()))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_value = |stepper: &mut BevyStepper| {
+                stepper
+                    .client_app
+                    .world_mut()
+                    .query::<&ComponentSyncModeFull>()
+                    .single(stepper.client_app.world())
+                    .clone()
+            };
+            assert_eq!(client_value(&mut stepper), ComponentSyncModeFull(1.0));
+
+            // updates are not sent while the replication is paused
+            stepper
+                .server_app
+                .world_mut()
+                .commands()
+                .entity(entity)
+                .pause_replication();
+            stepper.server_app.world_mut().flush();
+            stepper
+                .server_app
+                .world_mut()
+                .entity_mut(entity)
+                .insert(ComponentSyncModeFull(2.0));
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(client_value(&mut stepper), ComponentSyncModeFull(1.0));
+
+            // the current value is sent when the replication is resumed
+            stepper
+                .server_app
+                .world_mut()
+                .commands()
+                .entity(entity)
+                .resume_replication();
+            stepper.server_app.world_mut().flush();
+            stepper.frame_step();
+            stepper.frame_step();
+            assert_eq!(client_value(&mut stepper), ComponentSyncModeFull(2.0));
+
+            // pausing an entity that is despawned before the command is applied does not panic
+            let mut commands = stepper.server_app.world_mut().commands();
+            commands.entity(entity).despawn();
+            commands.entity(entity).pause_replication();
+            stepper.server_app.world_mut().flush();
+            assert!(stepper.server_app.world().get_entity(entity).is_none());
+        }
+
+        /// Clients that receive the spawn of a paused entity also receive its components
+        #[test]
+        fn test_spawn_paused_entity() {
+            let mut stepper = BevyStepper::default();
+            let entity = stepper
+                .server_app
+                .world_mut()
+                .spawn((
+                    ComponentSyncModeFull(1.0),
+                    Replicate::default(),
+                    ReplicationPaused,
+                ))
+                .id();
+            stepper.frame_step();
+            stepper.frame_step();
+            let client_entity = stepper
+                .client_app
+                .world()
+                .resource::<crate::prelude::client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(entity)
+                .expect("entity was not replicated to client");
+            assert_eq!(
+                stepper
+                    .client_app
+                    .world()
+                    .get::<ComponentSyncModeFull>(client_entity),
+                Some(&ComponentSyncModeFull(1.0))
+            );
+        }
     }
 }
@@ -0,0 +1,454 @@
+//! Let clients resume their session after a short disconnection.
+//!
+//! When [`ServerConfig::session_resumption`](crate::prelude::server::ServerConfig::session_resumption) is set,
+//! a client that disconnects is not cleaned up right away: its client entity, the entities that it controls and
+//! its room memberships are kept for [`SessionResumptionConfig::timeout`].
+//!
+//! Each client receives a secret when it connects. If a client with the same [`ClientId`] connects again before
+//! the timeout (for example by re-using the connect token that it got from the backend) and proves that it
+//! owns the previous session by sending back its secret, it gets the same client entity, controlled entities
+//! and rooms back (and therefore the same entity scope), and a [`SessionResumedEvent`] is emitted.
+//! Until then, the reconnected client uses a new client entity; if the secret doesn't match, the previous
+//! session is discarded.
+//!
+//! Otherwise the session expires and the usual disconnection cleanup happens.
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashMap};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, trace, warn};
+
+use crate::channel::builder::ControlChannel;
+use crate::client::session::SessionSecret;
+use crate::connection::id::ClientId;
+use crate::prelude::server::{is_started, MessageEvent, RoomId, RoomManager, ServerConfig};
+use crate::server::clients::ControlledEntities;
+use crate::server::connection::ConnectionManager;
+use crate::server::events::{ConnectEvent, DisconnectEvent};
+use crate::server::replication::send::Lifetime;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+/// Configuration of the resumption of the sessions of disconnected clients
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SessionResumptionConfig {
+    /// How long the state of a disconnected client is kept while waiting for it to reconnect
+    pub timeout: Duration,
+}
+
+impl Default for SessionResumptionConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SessionResumptionConfig {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Bevy [`Event`] emitted on the server when a client reconnects in time to resume its previous session
+#[derive(Event, Debug, Copy, Clone, PartialEq)]
+pub struct SessionResumedEvent {
+    pub client_id: ClientId,
+    /// The client entity of the previous session, which is re-used for the new connection
+    pub entity: Entity,
+}
+
+/// Message sent by a client right after it reconnects, with the secret of its previous session
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ResumeSession {
+    pub(crate) secret: u64,
+}
+
+/// State of a disconnected client that is kept until it reconnects or until the session expires
+#[derive(Debug)]
+pub(crate) struct SuspendedSession {
+    pub(crate) client_entity: Entity,
+    /// Secret that the client must send back to resume the session
+    secret: Option<u64>,
+    /// Rooms that the client was in
+    rooms: Vec<RoomId>,
+    expiration: Timer,
+}
+
+/// Sessions of the disconnected clients that can still be resumed
+#[derive(Resource, Debug, Default)]
+pub(crate) struct SuspendedSessions {
+    sessions: HashMap<ClientId, SuspendedSession>,
+    /// Secrets issued to the connected clients
+    secrets: HashMap<ClientId, u64>,
+}
+
+impl SuspendedSessions {
+    /// Get the session of a client that disconnected, creating it if needed.
+    ///
+    /// Returns None if session resumption is disabled, or if the client disconnected again without
+    /// resuming the session that was still suspended (the previous session is kept until it expires).
+    pub(crate) fn suspend(
+        &mut self,
+        config: &ServerConfig,
+        event: &DisconnectEvent,
+    ) -> Option<&mut SuspendedSession> {
+        let timeout = config.session_resumption?.timeout;
+        let secret = self.secrets.remove(&event.client_id);
+        let session = self
+            .sessions
+            .entry(event.client_id)
+            .or_insert_with(|| SuspendedSession {
+                client_entity: event.entity,
+                secret,
+                rooms: vec![],
+                expiration: Timer::new(timeout, TimerMode::Once),
+            });
+        (session.client_entity == event.entity).then_some(session)
+    }
+
+    /// Remember the rooms that a disconnected client was in
+    pub(crate) fn suspend_rooms(
+        &mut self,
+        config: &ServerConfig,
+        event: &DisconnectEvent,
+        rooms: impl Iterator<Item = RoomId>,
+    ) {
+        if let Some(session) = self.suspend(config, event) {
+            session.rooms.extend(rooms);
+        }
+    }
+}
+
+pub(crate) struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SuspendedSessions>();
+        app.add_event::<SessionResumedEvent>();
+        app.observe(issue_secret);
+        app.add_systems(
+            PreUpdate,
+            (resume_sessions, expire_sessions)
+                .chain()
+                .run_if(is_started)
+                .after(InternalMainSet::<ServerMarker>::EmitEvents),
+        );
+    }
+}
+
+/// Send a secret to each client that connects, that it can use later to resume its session
+fn issue_secret(
+    trigger: Trigger<ConnectEvent>,
+    config: Res<ServerConfig>,
+    mut sessions: ResMut<SuspendedSessions>,
+    mut connection_manager: ResMut<ConnectionManager>,
+) {
+    if config.session_resumption.is_none() {
+        return;
+    }
+    let client_id = trigger.event().client_id;
+    let secret = rand::random::<u64>();
+    sessions.secrets.insert(client_id, secret);
+    let _ = connection_manager
+        .send_message::<ControlChannel, _>(client_id, &mut SessionSecret { secret })
+        .inspect_err(|e| error!(?client_id, "Could not send the session secret: {e:?}"));
+}
+
+/// Give back the client entity, controlled entities and rooms of the previous session to the clients
+/// that sent the matching secret. The session is discarded if the secret doesn't match.
+fn resume_sessions(
+    mut commands: Commands,
+    mut messages: ResMut<Events<MessageEvent<ResumeSession>>>,
+    mut sessions: ResMut<SuspendedSessions>,
+    mut connection_manager: ResMut<ConnectionManager>,
+    mut room_manager: Option<ResMut<RoomManager>>,
+    mut client_query: Query<&mut ControlledEntities>,
+    mut events: EventWriter<SessionResumedEvent>,
+) {
+    for message in messages.drain() {
+        let client_id = message.context;
+        let Some(session) = sessions.sessions.remove(&client_id) else {
+            continue;
+        };
+        if session.secret != Some(message.message.secret) {
+            warn!(
+                ?client_id,
+                "Client sent the wrong secret, discarding its previous session"
+            );
+            despawn_client(
+                &mut commands,
+                client_id,
+                session.client_entity,
+                client_query.get(session.client_entity).ok(),
+            );
+            continue;
+        }
+        let Ok(new_entity) = connection_manager.client_entity(client_id) else {
+            continue;
+        };
+        debug!(?client_id, "Client resumed its session");
+        connection_manager.set_client_entity(client_id, session.client_entity);
+        // keep the entities that the client started to control since it reconnected
+        if let Ok(mut new_controlled) = client_query.get_mut(new_entity) {
+            let new_controlled = std::mem::take(&mut new_controlled.0);
+            if let Ok(mut controlled) = client_query.get_mut(session.client_entity) {
+                controlled.extend(new_controlled);
+            }
+        }
+        if let Some(command) = commands.get_entity(new_entity) {
+            command.despawn_recursive();
+        }
+        if let Some(room_manager) = room_manager.as_mut() {
+            for room_id in session.rooms {
+                room_manager.add_client(client_id, room_id);
+            }
+        }
+        events.send(SessionResumedEvent {
+            client_id,
+            entity: session.client_entity,
+        });
+    }
+}
+
+/// Clean up the sessions that were not resumed in time, like when a client disconnects
+fn expire_sessions(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut sessions: ResMut<SuspendedSessions>,
+    client_query: Query<&ControlledEntities>,
+) {
+    sessions.sessions.retain(|client_id, session| {
+        if !session.expiration.tick(time.delta()).finished() {
+            return true;
+        }
+        debug!(?client_id, "Session expired");
+        despawn_client(
+            &mut commands,
+            *client_id,
+            session.client_entity,
+            client_query.get(session.client_entity).ok(),
+        );
+        false
+    });
+}
+
+/// Despawn a client entity, and the entities that it controls during its session
+pub(crate) fn despawn_client(
+    commands: &mut Commands,
+    client_id: ClientId,
+    client_entity: Entity,
+    controlled_entities: Option<&ControlledEntities>,
+) {
+    if let Some(controlled_entities) = controlled_entities {
+        debug!(
+            "Despawning all entities controlled by disconnected client {:?}",
+            client_id
+        );
+        for (entity, lifetime) in controlled_entities.iter() {
+            if lifetime == &Lifetime::SessionBased {
+                trace!(
+                    "Despawning entity {entity:?} controlled by disconnected client {:?}",
+                    client_id
+                );
+                if let Some(command) = commands.get_entity(*entity) {
+                    command.despawn_recursive();
+                }
+            }
+        }
+    }
+    // despawn the entity itself
+    if let Some(command) = commands.get_entity(client_entity) {
+        command.despawn_recursive();
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::networking::ClientCommands;
+    use crate::client::session::ClientSession;
+    use crate::prelude::server::{ConnectionManager, ControlledBy, Replicate};
+    use crate::prelude::NetworkTarget;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Resumed(Vec<SessionResumedEvent>);
+
+    fn setup(timeout: Duration) -> (BevyStepper, Entity, Entity) {
+        let mut stepper = BevyStepper::default();
+        stepper.stop();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .session_resumption = Some(SessionResumptionConfig::default().with_timeout(timeout));
+        stepper.server_app.init_resource::<Resumed>();
+        stepper.server_app.add_systems(
+            Update,
+            |mut events: EventReader<SessionResumedEvent>, mut resumed: ResMut<Resumed>| {
+                resumed.0.extend(events.read().copied());
+            },
+        );
+        stepper.start();
+
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RoomManager>()
+            .add_client(client_id, RoomId(1));
+        let controlled = stepper
+            .server_app
+            .world_mut()
+            .spawn(Replicate {
+                controlled_by: ControlledBy {
+                    target: NetworkTarget::All,
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        stepper.frame_step();
+        let client_entity = stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .client_entity(client_id)
+            .unwrap();
+
+        stepper
+            .client_app
+            .world_mut()
+            .commands()
+            .disconnect_client();
+        stepper.frame_step();
+        stepper.frame_step();
+        (stepper, client_entity, controlled)
+    }
+
+    /// A client that reconnects before the timeout gets its previous state back
+    #[test]
+    fn test_resume_session() {
+        let (mut stepper, client_entity, controlled) = setup(Duration::from_secs(10));
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        // the state of the client is kept while it is disconnected
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .client_entity(client_id)
+            .is_err());
+        assert!(stepper.server_app.world().get_entity(controlled).is_some());
+        assert!(stepper
+            .server_app
+            .world()
+            .get_entity(client_entity)
+            .is_some());
+
+        stepper.client_app.world_mut().commands().connect_client();
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper.server_app.world().resource::<Resumed>().0,
+            vec![SessionResumedEvent {
+                client_id,
+                entity: client_entity
+            }]
+        );
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .client_entity(client_id)
+                .unwrap(),
+            client_entity
+        );
+        assert!(stepper
+            .server_app
+            .world()
+            .get::<ControlledEntities>(client_entity)
+            .unwrap()
+            .contains(&controlled));
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<RoomManager>()
+                .client_rooms(client_id)
+                .collect::<Vec<_>>(),
+            vec![RoomId(1)]
+        );
+    }
+
+    /// The state of a client that doesn't reconnect in time is cleaned up
+    #[test]
+    fn test_session_expires() {
+        let (mut stepper, client_entity, controlled) = setup(Duration::from_millis(100));
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert!(stepper.server_app.world().get_entity(controlled).is_none());
+        assert!(stepper
+            .server_app
+            .world()
+            .get_entity(client_entity)
+            .is_none());
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<SuspendedSessions>()
+            .sessions
+            .is_empty());
+    }
+
+    /// A client that reconnects with the same id but doesn't know the secret of the session can't resume it
+    #[test]
+    fn test_resume_session_wrong_secret() {
+        let (mut stepper, client_entity, controlled) = setup(Duration::from_secs(10));
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let mut session = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<ClientSession>();
+        session.secret = session.secret.map(|secret| secret.wrapping_add(1));
+
+        stepper.client_app.world_mut().commands().connect_client();
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<Resumed>()
+            .0
+            .is_empty());
+        assert_ne!(
+            stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .client_entity(client_id)
+                .unwrap(),
+            client_entity
+        );
+        // the previous session was discarded
+        assert!(stepper.server_app.world().get_entity(controlled).is_none());
+        assert!(stepper
+            .server_app
+            .world()
+            .get_entity(client_entity)
+            .is_none());
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .resource::<RoomManager>()
+                .client_rooms(client_id)
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Partition the connections between worker shards.
+//!
+//! Preparing the packets of a connection (writing the messages and the replication updates in the channels,
+//! fragmenting them and building the packets) only touches the state of that connection. The
+//! [`ConnectionManager`](crate::prelude::server::ConnectionManager) stores its connections in shards: each shard
+//! owns the connections of its clients, with their channels and the buffers used to serialize their packets.
+//! When [`ServerConfig::worker_shards`](crate::prelude::server::ServerConfig::worker_shards) is set, there are
+//! [`ShardConfig::num_shards`] shards, and the packets of each shard are prepared in parallel on bevy's
+//! [`ComputeTaskPool`]. The main thread only orchestrates the access to the world and writes the prepared packets
+//! to the io.
+//!
+//! The clients are assigned to the shards with rendezvous hashing, so that a client always belongs to the same
+//! shard, and only the clients of one shard in `num_shards` move when the number of shards changes.
+use std::hash::{Hash, Hasher};
+
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use bevy::utils::HashMap;
+
+use crate::connection::id::ClientId;
+use crate::server::connection::Connection;
+
+/// Configuration of the worker shards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardConfig {
+    /// Number of shards between which the connections are partitioned
+    pub num_shards: usize,
+}
+
+impl ShardConfig {
+    pub fn new(num_shards: usize) -> Self {
+        Self { num_shards }
+    }
+
+    /// Shard that owns the connection of the client
+    pub fn shard(&self, client_id: ClientId) -> usize {
+        client_shard(client_id, self.num_shards)
+    }
+}
+
+/// Shard of a client when the connections are partitioned between `num_shards` shards
+pub fn client_shard(client_id: ClientId, num_shards: usize) -> usize {
+    // the client goes to the shard with the highest weight, so adding or removing a shard
+    // only moves the clients that belong to it
+    (0..num_shards)
+        .max_by_key(|shard| {
+            let mut hasher = seahash::SeaHasher::new();
+            client_id.hash(&mut hasher);
+            shard.hash(&mut hasher);
+            hasher.finish()
+        })
+        .unwrap_or_default()
+}
+
+/// Connections of the server, partitioned between the worker shards
+pub(crate) struct ShardedConnections {
+    shards: Vec<HashMap<ClientId, Connection>>,
+}
+
+impl Default for ShardedConnections {
+    fn default() -> Self {
+        Self {
+            shards: vec![HashMap::default()],
+        }
+    }
+}
+
+impl ShardedConnections {
+    pub(crate) fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Change the number of shards. Only the connections whose shard changed are moved
+    pub(crate) fn set_num_shards(&mut self, num_shards: usize) {
+        let num_shards = num_shards.max(1);
+        if num_shards == self.shards.len() {
+            return;
+        }
+        let mut moved = vec![];
+        for (shard, connections) in self.shards.iter_mut().enumerate() {
+            moved.extend(
+                connections
+                    .extract_if(|client_id, _| client_shard(*client_id, num_shards) != shard),
+            );
+        }
+        self.shards.resize_with(num_shards, HashMap::default);
+        for (client_id, connection) in moved {
+            self.insert(client_id, connection);
+        }
+    }
+
+    fn shard(&self, client_id: &ClientId) -> &HashMap<ClientId, Connection> {
+        &self.shards[client_shard(*client_id, self.shards.len())]
+    }
+
+    fn shard_mut(&mut self, client_id: &ClientId) -> &mut HashMap<ClientId, Connection> {
+        let shard = client_shard(*client_id, self.shards.len());
+        &mut self.shards[shard]
+    }
+
+    pub(crate) fn get(&self, client_id: &ClientId) -> Option<&Connection> {
+        self.shard(client_id).get(client_id)
+    }
+
+    pub(crate) fn get_mut(&mut self, client_id: &ClientId) -> Option<&mut Connection> {
+        self.shard_mut(client_id).get_mut(client_id)
+    }
+
+    pub(crate) fn contains_key(&self, client_id: &ClientId) -> bool {
+        self.shard(client_id).contains_key(client_id)
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        client_id: ClientId,
+        connection: Connection,
+    ) -> Option<Connection> {
+        self.shard_mut(&client_id).insert(client_id, connection)
+    }
+
+    pub(crate) fn remove(&mut self, client_id: &ClientId) -> Option<Connection> {
+        self.shard_mut(client_id).remove(client_id)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.shards.iter().map(HashMap::len).sum()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.shards.iter().all(HashMap::is_empty)
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &ClientId> {
+        self.shards.iter().flat_map(HashMap::keys)
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Connection> {
+        self.shards.iter().flat_map(HashMap::values)
+    }
+
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut Connection> {
+        self.shards.iter_mut().flat_map(HashMap::values_mut)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&ClientId, &Connection)> {
+        self.shards.iter().flat_map(HashMap::iter)
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (&ClientId, &mut Connection)> {
+        self.shards.iter_mut().flat_map(HashMap::iter_mut)
+    }
+
+    /// Run `f` on each connection, each shard on its own task. The results are returned grouped by shard,
+    /// and the connections for which `f` returns `None` are skipped
+    pub(crate) fn par_map<R, F>(&mut self, f: F) -> Vec<R>
+    where
+        R: Send + 'static,
+        F: Fn(ClientId, &mut Connection) -> Option<R> + Sync,
+    {
+        if self.shards.len() <= 1 {
+            return self
+                .iter_mut()
+                .filter_map(|(client_id, connection)| f(*client_id, connection))
+                .collect();
+        }
+        let f = &f;
+        ComputeTaskPool::get_or_init(TaskPool::default)
+            .scope(|scope| {
+                for shard in self.shards.iter_mut().filter(|shard| !shard.is_empty()) {
+                    scope.spawn(async move {
+                        shard
+                            .iter_mut()
+                            .filter_map(|(client_id, connection)| f(*client_id, connection))
+                            .collect::<Vec<_>>()
+                    });
+                }
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client;
+    use crate::prelude::server::{ConnectionManager, Replicate, ServerConfig};
+    use crate::tests::multi_stepper::{MultiBevyStepper, TEST_CLIENT_ID_1, TEST_CLIENT_ID_2};
+    use crate::tests::protocol::ComponentSyncModeFull;
+
+    use super::*;
+
+    #[test]
+    fn test_client_shard() {
+        let clients = (0..1000).map(ClientId::Netcode).collect::<Vec<_>>();
+        let shards = clients
+            .iter()
+            .map(|client_id| client_shard(*client_id, 4))
+            .collect::<Vec<_>>();
+        for shard in 0..4 {
+            let count = shards.iter().filter(|s| **s == shard).count();
+            assert!(count > 150 && count < 350, "unbalanced shard: {count}");
+        }
+        // adding a shard only moves clients to the new shard
+        for (client_id, shard) in clients.iter().zip(shards) {
+            let new_shard = client_shard(*client_id, 5);
+            assert!(new_shard == shard || new_shard == 4);
+        }
+    }
+
+    #[test]
+    fn test_sharded_send() {
+        let mut stepper = MultiBevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .worker_shards = Some(ShardConfig::new(2));
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(1.0), Replicate::default()))
+            .id();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        for (client_id, client_app) in [
+            (TEST_CLIENT_ID_1, &stepper.client_app_1),
+            (TEST_CLIENT_ID_2, &stepper.client_app_2),
+        ] {
+            let client_entity = client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(server_entity)
+                .unwrap_or_else(|| panic!("entity was not replicated to client {client_id}"));
+            assert_eq!(
+                client_app
+                    .world()
+                    .get::<ComponentSyncModeFull>(client_entity)
+                    .unwrap(),
+                &ComponentSyncModeFull(1.0)
+            );
+        }
+
+        // each shard owns the connections of its clients, and the connections move when the number of shards changes
+        for num_shards in [2, 3] {
+            stepper
+                .server_app
+                .world_mut()
+                .resource_mut::<ServerConfig>()
+                .worker_shards = Some(ShardConfig::new(num_shards));
+            stepper.frame_step();
+            let connections = &stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .connections;
+            assert_eq!(connections.num_shards(), num_shards);
+            assert_eq!(connections.len(), 2);
+            for (shard, shard_connections) in connections.shards.iter().enumerate() {
+                for client_id in shard_connections.keys() {
+                    assert_eq!(client_shard(*client_id, num_shards), shard);
+                }
+            }
+        }
+    }
+}
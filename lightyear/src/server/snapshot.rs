@@ -0,0 +1,198 @@
+//! Capture the state of the replicated entities of a [`Room`](crate::server::relevance::room::Room).
+//!
+//! A [`RoomSnapshot`] contains the replicated components of all the entities of a room at a given tick,
+//! serialized with the [`ComponentRegistry`]. The snapshot itself can be serialized with `serde`, to persist
+//! a match, or to replay it later by restoring the snapshot in a [`World`].
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::{Entity, EntityRef, Mut, World};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::prelude::server::{RoomId, RoomManager};
+use crate::prelude::{ComponentRegistry, Tick, TickManager};
+use crate::protocol::component::{ComponentError, ComponentKind};
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::shared::events::connection::ConnectionEvents;
+use crate::shared::replication::entity_map::{ReceiveEntityMap, SendEntityMap};
+
+/// The replicated components of the entities of a room at a given tick
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoomSnapshot {
+    /// The server tick at which the snapshot was captured
+    pub tick: Tick,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+/// The replicated components of an entity
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntitySnapshot {
+    /// The entity in the world where the snapshot was captured
+    pub entity: Entity,
+    /// The serialized components, prefixed by their net id
+    pub(crate) components: Vec<Vec<u8>>,
+}
+
+impl EntitySnapshot {
+    /// Number of components of the entity that are in the snapshot
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+/// Serialize the replicated components of an entity, prefixed by their net id
+pub(crate) fn serialize_components(
+    registry: &ComponentRegistry,
+    entity_ref: EntityRef,
+    writer: &mut Writer,
+) -> Vec<(ComponentKind, Vec<u8>)> {
+    // the entities are not mapped, the snapshot refers to the entities of this world
+    let mut entity_map = SendEntityMap::default();
+    registry
+        .replication_map
+        .iter()
+        // skip the delta-compression messages and the disabled components
+        .filter(|(_, metadata)| {
+            metadata.remove.is_some() && !entity_ref.contains_id(metadata.disabled_id)
+        })
+        .filter_map(|(kind, metadata)| {
+            let component = entity_ref.get_by_id(metadata.component_id)?;
+            registry
+                .erased_serialize(component, writer, *kind, Some(&mut entity_map))
+                .inspect_err(|e| {
+                    error!(entity = ?entity_ref.id(), ?kind, "Could not serialize component: {e:?}");
+                })
+                .ok()?;
+            Some((*kind, writer.split().to_vec()))
+        })
+        .collect()
+}
+
+impl RoomSnapshot {
+    /// Capture the replicated components of the entities that are in the room at the current tick.
+    ///
+    /// Returns None if the room does not exist.
+    pub fn capture(world: &World, room_id: RoomId) -> Option<Self> {
+        let room = world.resource::<RoomManager>().get_room(room_id)?;
+        let registry = world.resource::<ComponentRegistry>();
+        let mut room_entities: Vec<Entity> = room.entities.iter().copied().collect();
+        // sort the entities so that the snapshot is deterministic
+        room_entities.sort();
+        let mut writer = Writer::default();
+        let entities = room_entities
+            .into_iter()
+            .filter_map(|entity| world.get_entity(entity))
+            .map(|entity_ref| EntitySnapshot {
+                entity: entity_ref.id(),
+                components: serialize_components(registry, entity_ref, &mut writer)
+                    .into_iter()
+                    .map(|(_, bytes)| bytes)
+                    .collect(),
+            })
+            .collect();
+        Some(Self {
+            tick: world.resource::<TickManager>().tick(),
+            entities,
+        })
+    }
+
+    /// Spawn a copy of the entities of the snapshot in the world.
+    ///
+    /// The references to other entities of the snapshot are mapped to the new entities.
+    /// The new entities are not replicated; you can add a [`Replicate`](crate::prelude::server::Replicate) bundle
+    /// to them if needed.
+    ///
+    /// Returns the mapping from the entities of the snapshot to the new entities.
+    pub fn restore(&self, world: &mut World) -> Result<EntityHashMap<Entity>, ComponentError> {
+        let mut entity_map = ReceiveEntityMap::default();
+        for entity in &self.entities {
+            let new_entity = world.spawn_empty().id();
+            entity_map.insert(entity.entity, new_entity);
+        }
+        world.resource_scope(|world, registry: Mut<ComponentRegistry>| {
+            let mut events = ConnectionEvents::default();
+            for entity in &self.entities {
+                let mut entity_world_mut = world.entity_mut(entity_map[&entity.entity]);
+                for component in &entity.components {
+                    registry.raw_write(
+                        &mut Reader::from(component.clone()),
+                        &mut entity_world_mut,
+                        self.tick,
+                        &mut entity_map,
+                        &mut events,
+                    )?;
+                }
+            }
+            Ok(entity_map.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::Replicate;
+    use crate::tests::protocol::{ComponentMapEntities, ComponentSyncModeFull};
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_room_snapshot() {
+        let mut stepper = BevyStepper::default();
+        let room_id = RoomId(0);
+        let first = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(1.0)))
+            .id();
+        let second = stepper
+            .server_app
+            .world_mut()
+            .spawn((
+                Replicate::default(),
+                ComponentSyncModeFull(2.0),
+                ComponentMapEntities(first),
+            ))
+            .id();
+        // an entity that is not in the room
+        stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(3.0)));
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_entity(first, room_id);
+        room_manager.add_entity(second, room_id);
+        stepper.frame_step();
+
+        let snapshot = RoomSnapshot::capture(stepper.server_app.world(), room_id).unwrap();
+        assert_eq!(snapshot.tick, stepper.server_tick());
+        assert_eq!(snapshot.entities.len(), 2);
+        assert!(RoomSnapshot::capture(stepper.server_app.world(), RoomId(1)).is_none());
+
+        // the snapshot can be serialized
+        let bytes = bincode::serde::encode_to_vec(&snapshot, bincode::config::standard()).unwrap();
+        let (snapshot, _): (RoomSnapshot, _) =
+            bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+
+        let entity_map = snapshot.restore(stepper.server_app.world_mut()).unwrap();
+        let world = stepper.server_app.world();
+        let new_first = entity_map[&first];
+        let new_second = entity_map[&second];
+        assert_eq!(
+            world.get::<ComponentSyncModeFull>(new_first),
+            Some(&ComponentSyncModeFull(1.0))
+        );
+        assert_eq!(
+            world.get::<ComponentSyncModeFull>(new_second),
+            Some(&ComponentSyncModeFull(2.0))
+        );
+        assert_eq!(
+            world.get::<ComponentMapEntities>(new_second),
+            Some(&ComponentMapEntities(new_first))
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Stream the replicated state of a room to external spectator services.
+//!
+//! Instead of connecting each spectator to the game server, an application can subscribe to the
+//! [`SpectatorStreams`] of a room, and forward the [`SpectatorFrame`]s to a broadcast server that re-serves
+//! the match to any number of spectators.
+//!
+//! The first frame of a stream contains all the replicated components of the entities in the room; the
+//! following frames only contain the changes. A [`SpectatorMirror`] applies the frames to a [`World`].
+//!
+//! The frames are produced every replication send interval. Note that computing the changes requires
+//! serializing all the replicated components of the room for each stream.
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use crossbeam_channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::server::{RoomId, RoomManager};
+use crate::prelude::{ComponentRegistry, Tick, TickManager};
+use crate::protocol::component::{ComponentError, ComponentKind, ComponentNetId};
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::server::snapshot::{serialize_components, EntitySnapshot};
+use crate::shared::events::connection::ConnectionEvents;
+use crate::shared::replication::entity_map::ReceiveEntityMap;
+use crate::shared::sets::{InternalReplicationSet, ServerMarker};
+
+/// The changes of the replicated state of a room since the previous frame of the stream
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpectatorFrame {
+    /// The server tick at which the frame was produced
+    pub tick: Tick,
+    /// The components that were inserted or updated, for entities that were added to the room or changed
+    pub updates: Vec<EntitySnapshot>,
+    /// The net ids of the components that were removed from an entity
+    pub removals: Vec<(Entity, Vec<ComponentNetId>)>,
+    /// The entities that were despawned or removed from the room
+    pub despawns: Vec<Entity>,
+}
+
+impl SpectatorFrame {
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty() && self.removals.is_empty() && self.despawns.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct SpectatorStream {
+    room_id: RoomId,
+    sender: Sender<SpectatorFrame>,
+    /// The components that were sent for each entity
+    sent: EntityHashMap<HashMap<ComponentKind, Vec<u8>>>,
+}
+
+/// Streams of the replicated state of rooms to external sinks
+#[derive(Resource, Debug, Default)]
+pub struct SpectatorStreams {
+    streams: Vec<SpectatorStream>,
+}
+
+impl SpectatorStreams {
+    /// Start streaming the state of a room.
+    ///
+    /// The stream stops when the receiver is dropped.
+    pub fn subscribe(&mut self, room_id: RoomId) -> Receiver<SpectatorFrame> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.streams.push(SpectatorStream {
+            room_id,
+            sender,
+            sent: EntityHashMap::default(),
+        });
+        receiver
+    }
+
+    /// Number of active streams
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+}
+
+impl SpectatorStream {
+    fn next_frame(&mut self, world: &World, writer: &mut Writer) -> SpectatorFrame {
+        let registry = world.resource::<ComponentRegistry>();
+        let mut frame = SpectatorFrame {
+            tick: world.resource::<TickManager>().tick(),
+            updates: vec![],
+            removals: vec![],
+            despawns: vec![],
+        };
+        let mut entities: Vec<Entity> = world
+            .resource::<RoomManager>()
+            .get_room(self.room_id)
+            .map(|room| room.entities.iter().copied().collect())
+            .unwrap_or_default();
+        entities.sort();
+        let mut sent = EntityHashMap::default();
+        for entity_ref in entities.into_iter().filter_map(|e| world.get_entity(e)) {
+            let entity = entity_ref.id();
+            let components: HashMap<ComponentKind, Vec<u8>> =
+                serialize_components(registry, entity_ref, writer)
+                    .into_iter()
+                    .collect();
+            let previous = self.sent.remove(&entity).unwrap_or_default();
+            let updated: Vec<Vec<u8>> = components
+                .iter()
+                .filter(|(kind, bytes)| previous.get(*kind) != Some(*bytes))
+                .map(|(_, bytes)| bytes.clone())
+                .collect();
+            if !updated.is_empty() {
+                frame.updates.push(EntitySnapshot {
+                    entity,
+                    components: updated,
+                });
+            }
+            let removed: Vec<ComponentNetId> = previous
+                .keys()
+                .filter(|kind| !components.contains_key(*kind))
+                .filter_map(|kind| registry.kind_map.net_id(kind).copied())
+                .collect();
+            if !removed.is_empty() {
+                frame.removals.push((entity, removed));
+            }
+            sent.insert(entity, components);
+        }
+        // the entities that are not in the room anymore
+        frame.despawns = self.sent.keys().copied().collect();
+        frame.despawns.sort();
+        self.sent = sent;
+        frame
+    }
+}
+
+/// Applies the [`SpectatorFrame`]s of a stream to a [`World`]
+#[derive(Debug, Default)]
+pub struct SpectatorMirror {
+    /// Map from the entities of the server to the entities of the mirror
+    entity_map: ReceiveEntityMap,
+}
+
+impl SpectatorMirror {
+    /// The local entity corresponding to an entity of the server
+    pub fn get(&self, server_entity: Entity) -> Option<Entity> {
+        self.entity_map.get(&server_entity).copied()
+    }
+
+    /// Apply the changes of a frame to the world
+    pub fn apply(
+        &mut self,
+        frame: &SpectatorFrame,
+        world: &mut World,
+    ) -> Result<(), ComponentError> {
+        for entity in &frame.updates {
+            if !self.entity_map.contains_key(&entity.entity) {
+                let local = world.spawn_empty().id();
+                self.entity_map.insert(entity.entity, local);
+            }
+        }
+        world.resource_scope(|world, registry: Mut<ComponentRegistry>| {
+            let mut events = ConnectionEvents::default();
+            for entity in &frame.updates {
+                let mut entity_world_mut = world.entity_mut(self.entity_map[&entity.entity]);
+                for component in &entity.components {
+                    registry.raw_write(
+                        &mut Reader::from(component.clone()),
+                        &mut entity_world_mut,
+                        frame.tick,
+                        &mut self.entity_map,
+                        &mut events,
+                    )?;
+                }
+            }
+            for (entity, net_ids) in &frame.removals {
+                let Some(mut entity_world_mut) = self
+                    .entity_map
+                    .get(entity)
+                    .and_then(|local| world.get_entity_mut(*local))
+                else {
+                    continue;
+                };
+                for net_id in net_ids {
+                    registry.raw_remove(*net_id, &mut entity_world_mut);
+                }
+            }
+            Ok::<(), ComponentError>(())
+        })?;
+        for entity in &frame.despawns {
+            if let Some(local) = self.entity_map.remove(entity) {
+                world.despawn(local);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpectatorStreams>();
+        app.add_systems(
+            PostUpdate,
+            send_spectator_frames
+                // only produce frames every replication send interval
+                .in_set(InternalReplicationSet::<ServerMarker>::AfterBuffer)
+                .run_if(|streams: Res<SpectatorStreams>| !streams.is_empty()),
+        );
+    }
+}
+
+/// Send the changes of the rooms to the spectator streams
+fn send_spectator_frames(world: &mut World) {
+    world.resource_scope(|world, mut streams: Mut<SpectatorStreams>| {
+        let mut writer = Writer::default();
+        streams.streams.retain_mut(|stream| {
+            let frame = stream.next_frame(world, &mut writer);
+            // stop streaming if the receiver was dropped
+            frame.is_empty() || stream.sender.send(frame).is_ok()
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::{ConnectionManager, Replicate};
+    use crate::shared::replication::plugin::send::SendIntervalTimer;
+    use crate::tests::protocol::{ComponentMapEntities, ComponentSyncModeFull};
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_spectator_stream() {
+        let mut stepper = BevyStepper::default();
+        let room_id = RoomId(0);
+        let receiver = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<SpectatorStreams>()
+            .subscribe(room_id);
+        let first = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(1.0)))
+            .id();
+        let second = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentMapEntities(first)))
+            .id();
+        let mut room_manager = stepper.server_app.world_mut().resource_mut::<RoomManager>();
+        room_manager.add_entity(first, room_id);
+        room_manager.add_entity(second, room_id);
+        stepper.frame_step();
+
+        let mut mirror = SpectatorMirror::default();
+        let mut world = World::new();
+        world.insert_resource(
+            stepper
+                .server_app
+                .world()
+                .resource::<ComponentRegistry>()
+                .clone(),
+        );
+        let frame = receiver.try_recv().unwrap();
+        assert_eq!(frame.updates.len(), 2);
+        mirror.apply(&frame, &mut world).unwrap();
+        let mirror_first = mirror.get(first).unwrap();
+        let mirror_second = mirror.get(second).unwrap();
+        assert_eq!(
+            world.get::<ComponentSyncModeFull>(mirror_first),
+            Some(&ComponentSyncModeFull(1.0))
+        );
+        assert_eq!(
+            world.get::<ComponentMapEntities>(mirror_second),
+            Some(&ComponentMapEntities(mirror_first))
+        );
+
+        // no frame is sent if nothing changed
+        stepper.frame_step();
+        assert!(receiver.try_recv().is_err());
+
+        // only the changes are sent
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(first)
+            .insert(ComponentSyncModeFull(2.0));
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(second)
+            .remove::<ComponentMapEntities>();
+        stepper.frame_step();
+        let frame = receiver.try_recv().unwrap();
+        assert_eq!(frame.updates.len(), 1);
+        assert_eq!(frame.removals.len(), 1);
+        mirror.apply(&frame, &mut world).unwrap();
+        assert_eq!(
+            world.get::<ComponentSyncModeFull>(mirror_first),
+            Some(&ComponentSyncModeFull(2.0))
+        );
+        assert!(world.get::<ComponentMapEntities>(mirror_second).is_none());
+
+        // despawns are sent
+        stepper.server_app.world_mut().despawn(first);
+        stepper.frame_step();
+        let frame = receiver.try_recv().unwrap();
+        assert_eq!(frame.despawns, vec![first]);
+        mirror.apply(&frame, &mut world).unwrap();
+        assert!(world.get_entity(mirror_first).is_none());
+
+        // the stream is closed when the receiver is dropped
+        drop(receiver);
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(second)
+            .insert(ComponentSyncModeFull(3.0));
+        stepper.frame_step();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<SpectatorStreams>()
+            .is_empty());
+    }
+
+    /// The frames are only produced every replication send interval
+    #[test]
+    fn test_spectator_stream_send_interval() {
+        let mut stepper = BevyStepper::default();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<SendIntervalTimer<ConnectionManager>>()
+            .timer = Some(Timer::new(stepper.frame_duration * 4, TimerMode::Repeating));
+        let room_id = RoomId(0);
+        let receiver = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<SpectatorStreams>()
+            .subscribe(room_id);
+        let entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(0.0)))
+            .id();
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<RoomManager>()
+            .add_entity(entity, room_id);
+        // the component changes every frame
+        for i in 1..=8 {
+            stepper
+                .server_app
+                .world_mut()
+                .entity_mut(entity)
+                .insert(ComponentSyncModeFull(i as f32));
+            stepper.frame_step();
+        }
+        assert_eq!(receiver.try_iter().count(), 2);
+    }
+}
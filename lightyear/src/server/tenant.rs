@@ -0,0 +1,340 @@
+//! Partition a server between multiple tenants.
+//!
+//! Platforms that host many small games or community servers inside one process can tag clients and rooms
+//! with a [`TenantId`] in the [`TenantManager`]. Each tenant can have a [`TenantBudget`]:
+//! - the connections of a tenant with a bandwidth budget share that quota, instead of using the
+//!   [per-client cap](crate::prelude::server::PacketConfig::per_client_send_bandwidth_cap), so that a busy tenant
+//!   cannot use the bandwidth of the others
+//! - the time spent preparing the packets of a tenant is measured and compared with its send time budget
+//!
+//! A [`TenantOverBudgetEvent`] is emitted every time a tenant exceeds one of its budgets, and the statistics of
+//! each tenant for the last send are available with [`TenantManager::stats`].
+//!
+//! The tags are not removed when clients disconnect, so a client can be assigned to a tenant before it connects
+//! (for example by the backend that generates its connect token) and stays in it if it reconnects.
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashMap};
+use governor::{DefaultDirectRateLimiter, Quota};
+
+use crate::connection::id::ClientId;
+use crate::server::relevance::room::RoomId;
+
+/// Identifier of a tenant of the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Reflect)]
+pub struct TenantId(pub u64);
+
+/// Resources that a tenant can use
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TenantBudget {
+    /// Bandwidth quota shared by all the connections of the tenant. If None, each connection uses
+    /// its own bandwidth cap
+    pub bandwidth: Option<Quota>,
+    /// Time that the server can spend preparing the packets of the tenant on each send
+    pub send_time: Option<Duration>,
+}
+
+impl TenantBudget {
+    pub fn with_bandwidth(mut self, bandwidth: Quota) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Share a bandwidth quota of `bytes_per_second` between the connections of the tenant.
+    /// A quota of 0 is clamped to 1 byte per second
+    pub fn with_bandwidth_bytes_per_second(mut self, bytes_per_second: u32) -> Self {
+        let bytes_per_second = NonZeroU32::new(bytes_per_second).unwrap_or(NonZeroU32::MIN);
+        self.bandwidth = Some(Quota::per_second(bytes_per_second));
+        self
+    }
+
+    pub fn with_send_time(mut self, send_time: Duration) -> Self {
+        self.send_time = Some(send_time);
+        self
+    }
+}
+
+/// Statistics of a tenant, measured during the last send
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TenantStats {
+    /// Number of connected clients of the tenant
+    pub connected_clients: usize,
+    /// Bytes sent to the clients of the tenant
+    pub bytes_sent: usize,
+    /// Packets sent to the clients of the tenant
+    pub packets_sent: usize,
+    /// Number of entities whose replication data was sent to the clients of the tenant
+    pub entities_sent: usize,
+    /// Time spent preparing the packets of the tenant
+    pub send_time: Duration,
+    /// Number of connections that could not send all their messages because the bandwidth quota was reached
+    pub connections_over_budget: usize,
+}
+
+/// Budget of a tenant that was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TenantBudgetKind {
+    Bandwidth,
+    SendTime,
+}
+
+/// Bevy [`Event`] emitted on the server when a tenant exceeds one of its budgets
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct TenantOverBudgetEvent {
+    pub tenant: TenantId,
+    pub budget: TenantBudgetKind,
+}
+
+#[derive(Debug, Default)]
+struct Tenant {
+    budget: TenantBudget,
+    limiter: Option<Arc<DefaultDirectRateLimiter>>,
+    stats: TenantStats,
+}
+
+/// Resource that assigns clients and rooms to tenants, and keeps track of the statistics of each tenant
+#[derive(Resource, Debug, Default)]
+pub struct TenantManager {
+    tenants: HashMap<TenantId, Tenant>,
+    clients: HashMap<ClientId, TenantId>,
+    rooms: HashMap<RoomId, TenantId>,
+}
+
+impl TenantManager {
+    /// Add a tenant, or update the budget of an existing tenant
+    pub fn add_tenant(&mut self, tenant: TenantId, budget: TenantBudget) {
+        let entry = self.tenants.entry(tenant).or_default();
+        entry.budget = budget;
+        entry.limiter = budget
+            .bandwidth
+            .map(|quota| Arc::new(DefaultDirectRateLimiter::direct(quota)));
+    }
+
+    /// Remove a tenant, along with the tags of its clients and rooms
+    pub fn remove_tenant(&mut self, tenant: TenantId) {
+        self.tenants.remove(&tenant);
+        self.clients.retain(|_, t| *t != tenant);
+        self.rooms.retain(|_, t| *t != tenant);
+    }
+
+    /// Assign a client to a tenant. The tenant is created without budget if it doesn't exist
+    pub fn add_client(&mut self, client_id: ClientId, tenant: TenantId) {
+        self.tenants.entry(tenant).or_default();
+        self.clients.insert(client_id, tenant);
+    }
+
+    /// Remove a client from its tenant
+    pub fn remove_client(&mut self, client_id: ClientId) {
+        self.clients.remove(&client_id);
+    }
+
+    /// Assign a room to a tenant. The tenant is created without budget if it doesn't exist
+    pub fn add_room(&mut self, room_id: RoomId, tenant: TenantId) {
+        self.tenants.entry(tenant).or_default();
+        self.rooms.insert(room_id, tenant);
+    }
+
+    /// Remove a room from its tenant
+    pub fn remove_room(&mut self, room_id: RoomId) {
+        self.rooms.remove(&room_id);
+    }
+
+    /// Tenant of a client
+    pub fn client_tenant(&self, client_id: ClientId) -> Option<TenantId> {
+        self.clients.get(&client_id).copied()
+    }
+
+    /// Tenant of a room
+    pub fn room_tenant(&self, room_id: RoomId) -> Option<TenantId> {
+        self.rooms.get(&room_id).copied()
+    }
+
+    /// Clients assigned to a tenant
+    pub fn clients(&self, tenant: TenantId) -> impl Iterator<Item = ClientId> + '_ {
+        self.clients
+            .iter()
+            .filter(move |(_, t)| **t == tenant)
+            .map(|(client_id, _)| *client_id)
+    }
+
+    /// Rooms assigned to a tenant
+    pub fn rooms(&self, tenant: TenantId) -> impl Iterator<Item = RoomId> + '_ {
+        self.rooms
+            .iter()
+            .filter(move |(_, t)| **t == tenant)
+            .map(|(room_id, _)| *room_id)
+    }
+
+    /// List of the tenants
+    pub fn tenants(&self) -> impl Iterator<Item = TenantId> + '_ {
+        self.tenants.keys().copied()
+    }
+
+    /// Budget of a tenant
+    pub fn budget(&self, tenant: TenantId) -> Option<TenantBudget> {
+        self.tenants.get(&tenant).map(|t| t.budget)
+    }
+
+    /// Statistics of a tenant during the last send
+    pub fn stats(&self, tenant: TenantId) -> Option<&TenantStats> {
+        self.tenants.get(&tenant).map(|t| &t.stats)
+    }
+
+    /// Bandwidth quota shared by the connections of the tenant of a client
+    pub(crate) fn client_limiter(
+        &self,
+        client_id: ClientId,
+    ) -> Option<&Arc<DefaultDirectRateLimiter>> {
+        self.tenants
+            .get(self.clients.get(&client_id)?)?
+            .limiter
+            .as_ref()
+    }
+
+    /// Statistics of the tenant of a client, to be updated during the send
+    pub(crate) fn client_stats_mut(&mut self, client_id: ClientId) -> Option<&mut TenantStats> {
+        self.tenants
+            .get_mut(self.clients.get(&client_id)?)
+            .map(|t| &mut t.stats)
+    }
+
+    pub(crate) fn reset_stats(&mut self) {
+        self.tenants
+            .values_mut()
+            .for_each(|tenant| tenant.stats = TenantStats::default());
+    }
+
+    /// Emit an event for each budget that was exceeded during the last send
+    pub(crate) fn check_budgets(&self, events: &mut EventWriter<TenantOverBudgetEvent>) {
+        for (tenant_id, tenant) in self.tenants.iter() {
+            if tenant.limiter.is_some() && tenant.stats.connections_over_budget > 0 {
+                events.send(TenantOverBudgetEvent {
+                    tenant: *tenant_id,
+                    budget: TenantBudgetKind::Bandwidth,
+                });
+            }
+            if tenant
+                .budget
+                .send_time
+                .is_some_and(|budget| tenant.stats.send_time > budget)
+            {
+                events.send(TenantOverBudgetEvent {
+                    tenant: *tenant_id,
+                    budget: TenantBudgetKind::SendTime,
+                });
+            }
+        }
+    }
+}
+
+pub(crate) struct TenantPlugin;
+
+impl Plugin for TenantPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TenantId>();
+        app.init_resource::<TenantManager>();
+        app.add_event::<TenantOverBudgetEvent>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::server::{ConnectionManager, Replicate};
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct OverBudget(Vec<TenantOverBudgetEvent>);
+
+    #[test]
+    fn test_tenant_bandwidth_budget() {
+        let mut stepper = BevyStepper::default();
+        let tenant = TenantId(1);
+        let client_id = ClientId::Netcode(TEST_CLIENT_ID);
+        let mut manager = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<TenantManager>();
+        manager.add_tenant(
+            tenant,
+            TenantBudget::default().with_bandwidth_bytes_per_second(100),
+        );
+        manager.add_client(client_id, tenant);
+        manager.add_room(RoomId(1), tenant);
+        assert_eq!(manager.clients(tenant).collect::<Vec<_>>(), vec![client_id]);
+        assert_eq!(manager.room_tenant(RoomId(1)), Some(tenant));
+        stepper.server_app.init_resource::<OverBudget>();
+        stepper.server_app.add_systems(
+            Last,
+            |mut events: EventReader<TenantOverBudgetEvent>,
+             mut over_budget: ResMut<OverBudget>| {
+                over_budget.0.extend(events.read().copied());
+            },
+        );
+
+        for i in 0..20 {
+            stepper
+                .server_app
+                .world_mut()
+                .spawn((ComponentSyncModeFull(i as f32), Replicate::default()));
+        }
+        stepper.frame_step();
+
+        let stats = stepper
+            .server_app
+            .world()
+            .resource::<TenantManager>()
+            .stats(tenant)
+            .unwrap()
+            .clone();
+        assert_eq!(stats.connected_clients, 1);
+        assert!(stats.bytes_sent > 0);
+        // the replication updates held back by the shared quota are sent later
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .connection(client_id)
+            .unwrap()
+            .replication_sender
+            .is_bandwidth_cap_enabled());
+        assert_eq!(stats.connections_over_budget, 1);
+        assert_eq!(
+            stepper.server_app.world().resource::<OverBudget>().0,
+            vec![TenantOverBudgetEvent {
+                tenant,
+                budget: TenantBudgetKind::Bandwidth
+            }]
+        );
+
+        // the client doesn't share the quota anymore once it leaves the tenant
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<TenantManager>()
+            .remove_tenant(tenant);
+        stepper.frame_step();
+        assert!(stepper
+            .server_app
+            .world()
+            .resource::<TenantManager>()
+            .client_tenant(client_id)
+            .is_none());
+        assert_eq!(
+            stepper.server_app.world().resource::<OverBudget>().0.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_zero_bandwidth_budget() {
+        assert_eq!(
+            TenantBudget::default().with_bandwidth_bytes_per_second(0),
+            TenantBudget::default().with_bandwidth_bytes_per_second(1)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Send transient entities to clients, see [`crate::shared::replication::transient`]
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use tracing::error;
+
+use crate::channel::builder::TransientChannel;
+use crate::prelude::server::{is_started, ConnectionManager};
+use crate::prelude::{ComponentRegistry, NetworkTarget};
+use crate::serialize::writer::Writer;
+use crate::shared::replication::transient::TransientSpawn;
+use crate::shared::sets::{InternalMainSet, ServerMarker};
+
+/// Replicate the entity with a single message when this component is added.
+///
+/// The clients spawn a copy of the entity with all its replicated components, and despawn it after `lifetime`.
+/// Later changes to the entity on the server are not replicated. This should not be used together with
+/// [`Replicate`](crate::prelude::server::Replicate).
+#[derive(Component, Clone, Debug, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct ReplicateTransient {
+    /// Which clients should receive the entity
+    pub target: NetworkTarget,
+    /// How long the entity lives on the clients
+    pub lifetime: Duration,
+}
+
+impl Default for ReplicateTransient {
+    fn default() -> Self {
+        Self {
+            target: NetworkTarget::All,
+            lifetime: Duration::from_secs(1),
+        }
+    }
+}
+
+pub(crate) struct TransientPlugin;
+
+impl Plugin for TransientPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ReplicateTransient>();
+        app.add_systems(
+            PostUpdate,
+            send_transient_spawns
+                .before(InternalMainSet::<ServerMarker>::Send)
+                .run_if(is_started),
+        );
+    }
+}
+
+/// Send the full state of the newly added transient entities
+fn send_transient_spawns(
+    world: &mut World,
+    mut added: Local<Option<QueryState<Entity, Added<ReplicateTransient>>>>,
+) {
+    let query = added.get_or_insert_with(|| world.query_filtered());
+    let entities: Vec<Entity> = query.iter(world).collect();
+    if entities.is_empty() {
+        return;
+    }
+    world.resource_scope(|world, mut manager: Mut<ConnectionManager>| {
+        let registry = world.resource::<ComponentRegistry>();
+        let mut writer = Writer::default();
+        for entity in entities {
+            let entity_ref = world.entity(entity);
+            let transient = entity_ref.get::<ReplicateTransient>().unwrap();
+            // the components that don't contain entities are serialized once for all the clients
+            let mut components = Vec::new();
+            let mut mapped_components = Vec::new();
+            for (kind, metadata) in registry.replication_map.iter() {
+                // skip the delta-compression messages and the disabled components
+                if metadata.remove.is_none() || entity_ref.contains_id(metadata.disabled_id) {
+                    continue;
+                }
+                let Some(component) = entity_ref.get_by_id(metadata.component_id) else {
+                    continue;
+                };
+                if registry.erased_is_map_entities(*kind) {
+                    mapped_components.push((*kind, component));
+                    continue;
+                }
+                match registry.erased_serialize(component, &mut writer, *kind, None) {
+                    Ok(()) => components.push(writer.split().to_vec()),
+                    Err(e) => error!(?entity, "Could not serialize transient component: {e:?}"),
+                }
+            }
+            if mapped_components.is_empty() {
+                let mut message = TransientSpawn {
+                    lifetime: transient.lifetime,
+                    components,
+                };
+                if let Err(e) = manager.send_message_to_target::<TransientChannel, _>(
+                    &mut message,
+                    transient.target.clone(),
+                ) {
+                    error!(?entity, "Could not send transient entity: {e:?}");
+                }
+                continue;
+            }
+            // the components that contain entities must be mapped with the entity map of each client
+            for client_id in manager.connected_targets(transient.target.clone()) {
+                let Ok(connection) = manager.connection_mut(client_id) else {
+                    continue;
+                };
+                let mut client_components = components.clone();
+                for (kind, component) in mapped_components.iter() {
+                    match registry.erased_serialize(
+                        *component,
+                        &mut writer,
+                        *kind,
+                        Some(
+                            &mut connection
+                                .replication_receiver
+                                .remote_entity_map
+                                .local_to_remote,
+                        ),
+                    ) {
+                        Ok(()) => client_components.push(writer.split().to_vec()),
+                        Err(e) => error!(?entity, "Could not serialize transient component: {e:?}"),
+                    }
+                }
+                let mut message = TransientSpawn {
+                    lifetime: transient.lifetime,
+                    components: client_components,
+                };
+                if let Err(e) = manager.send_message_to_target::<TransientChannel, _>(
+                    &mut message,
+                    NetworkTarget::Single(client_id),
+                ) {
+                    error!(?client_id, "Could not send transient entity: {e:?}");
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,247 @@
+//! Snapshot of the world as it was replicated to a single client.
+//!
+//! The server keeps track, for each connection, of the entities that were replicated to the client
+//! and of the last value of each component that was sent. Comparing this view with the server's
+//! world can help diagnose replication issues, for example when a client reports that it sees an entity
+//! that should not exist anymore.
+//!
+//! The view can be accessed with [`ConnectionManager::client_world_view`](crate::prelude::server::ConnectionManager::client_world_view).
+//! It is only recorded if [`ServerConfig::client_world_view`](crate::prelude::server::ServerConfig::client_world_view)
+//! is true, since it keeps a copy of every replicated component for each client.
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::{Component, Entity};
+use bevy::utils::HashMap;
+use bytes::Bytes;
+
+use crate::prelude::{ComponentRegistry, Tick};
+use crate::protocol::component::ComponentKind;
+use crate::serialize::reader::Reader;
+use crate::shared::replication::entity_map::ReceiveEntityMap;
+
+/// Entities and components that were sent to a client.
+///
+/// The entities are the server's local entities.
+#[derive(Debug, Clone, Default)]
+pub struct ClientWorldView {
+    entities: EntityHashMap<EntityView>,
+    /// If false, nothing is recorded
+    enabled: bool,
+}
+
+/// Components of an entity that were sent to a client
+#[derive(Debug, Clone, Default)]
+pub struct EntityView {
+    pub components: HashMap<ComponentKind, ComponentView>,
+}
+
+/// Last value of a component that was sent to a client
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentView {
+    /// Tick at which the value was sent
+    pub tick: Tick,
+    /// Serialized value of the component, as it was sent.
+    ///
+    /// None if the component uses delta-compression, since only diffs are sent in that case.
+    pub value: Option<Bytes>,
+}
+
+impl ComponentView {
+    /// Deserialize the last value of the component that was sent
+    pub fn deserialize<C: Component>(&self, registry: &ComponentRegistry) -> Option<C> {
+        let mut reader = Reader::from(self.value.clone()?);
+        registry
+            .deserialize::<C>(&mut reader, &mut ReceiveEntityMap::default())
+            .ok()
+    }
+}
+
+impl ClientWorldView {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            entities: EntityHashMap::default(),
+            enabled,
+        }
+    }
+
+    /// Returns true if the view is recorded, see
+    /// [`ServerConfig::client_world_view`](crate::prelude::server::ServerConfig::client_world_view)
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Iterate through the entities that are replicated to the client
+    pub fn entities(&self) -> impl Iterator<Item = (Entity, &EntityView)> {
+        self.entities.iter().map(|(entity, view)| (*entity, view))
+    }
+
+    /// Get the view of an entity, if it is replicated to the client
+    pub fn get(&self, entity: Entity) -> Option<&EntityView> {
+        self.entities.get(&entity)
+    }
+
+    /// Returns true if the entity is replicated to the client
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains_key(&entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub(crate) fn spawn(&mut self, entity: Entity) {
+        if !self.enabled {
+            return;
+        }
+        self.entities.entry(entity).or_default();
+    }
+
+    pub(crate) fn despawn(&mut self, entity: Entity) {
+        self.entities.remove(&entity);
+    }
+
+    pub(crate) fn write_component(
+        &mut self,
+        entity: Entity,
+        kind: ComponentKind,
+        tick: Tick,
+        value: Option<Bytes>,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.entities
+            .entry(entity)
+            .or_default()
+            .components
+            .insert(kind, ComponentView { tick, value });
+    }
+
+    pub(crate) fn remove_component(&mut self, entity: Entity, kind: ComponentKind) {
+        if let Some(view) = self.entities.get_mut(&entity) {
+            view.components.remove(&kind);
+        }
+    }
+}
+
+impl EntityView {
+    /// Get the last value of the component `C` that was sent
+    pub fn component<C: Component>(&self) -> Option<&ComponentView> {
+        self.components.get(&ComponentKind::of::<C>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::server::{ConnectionManager, Replicate, ServerConfig};
+    use crate::prelude::{ClientId, SharedConfig, TickConfig};
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    fn view(stepper: &BevyStepper) -> ClientWorldView {
+        stepper
+            .server_app
+            .world()
+            .resource::<ConnectionManager>()
+            .client_world_view(ClientId::Netcode(TEST_CLIENT_ID))
+            .unwrap()
+            .clone()
+    }
+
+    fn setup(client_world_view: bool) -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(shared_config, ClientConfig::default(), frame_duration);
+        stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>()
+            .client_world_view = client_world_view;
+        stepper.init();
+        stepper
+    }
+
+    /// Nothing is recorded unless the view is enabled
+    #[test]
+    fn test_client_world_view_disabled() {
+        let mut stepper = setup(false);
+        stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(1.0)));
+        stepper.frame_step();
+        let world_view = view(&stepper);
+        assert!(!world_view.is_enabled());
+        assert!(world_view.is_empty());
+    }
+
+    #[test]
+    fn test_client_world_view() {
+        let mut stepper = setup(true);
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(1.0)))
+            .id();
+        stepper.frame_step();
+
+        let registry = stepper.server_app.world().resource::<ComponentRegistry>();
+        let world_view = view(&stepper);
+        let component = world_view
+            .get(server_entity)
+            .expect("entity should be in the client's view")
+            .component::<ComponentSyncModeFull>()
+            .expect("component should be in the client's view");
+        assert_eq!(
+            component.deserialize::<ComponentSyncModeFull>(registry),
+            Some(ComponentSyncModeFull(1.0))
+        );
+
+        // updates are tracked
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .insert(ComponentSyncModeFull(2.0));
+        stepper.frame_step();
+        let registry = stepper.server_app.world().resource::<ComponentRegistry>();
+        assert_eq!(
+            view(&stepper)
+                .get(server_entity)
+                .unwrap()
+                .component::<ComponentSyncModeFull>()
+                .unwrap()
+                .deserialize::<ComponentSyncModeFull>(registry),
+            Some(ComponentSyncModeFull(2.0))
+        );
+
+        // removals are tracked
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_entity)
+            .remove::<ComponentSyncModeFull>();
+        stepper.frame_step();
+        assert!(view(&stepper)
+            .get(server_entity)
+            .unwrap()
+            .component::<ComponentSyncModeFull>()
+            .is_none());
+
+        // despawns are tracked
+        stepper.server_app.world_mut().despawn(server_entity);
+        stepper.frame_step();
+        assert!(view(&stepper).is_empty());
+    }
+}
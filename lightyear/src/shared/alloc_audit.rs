@@ -0,0 +1,299 @@
+//! Count the heap allocations of the per-tick networking operations.
+//!
+//! With the `alloc_audit` feature, the server counts the heap allocations performed while processing the
+//! packets received from the clients, while collecting the replication updates and while preparing the packets
+//! to send. The counts of the last frame are available with
+//! [`ConnectionManager::allocations`](crate::prelude::server::ConnectionManager::allocations), so that tests can
+//! lock in the allocation-free paths against regressions with [`AllocationCounts::assert_no_allocations`].
+//!
+//! The allocations that these paths still perform by design are listed in [`AllocationException`]. They are
+//! counted separately, so that they don't hide new allocations, and so that removing one of them is visible
+//! in [`AllocationCounts::exception`].
+//!
+//! The counts rely on the [`CountingAllocator`], which must be installed as the global allocator of the binary:
+//! ```rust,ignore
+//! use std::alloc::System;
+//! use lightyear::shared::alloc_audit::CountingAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator<System> = CountingAllocator::new(System);
+//! ```
+//!
+//! Without the feature, only [`AllocationException`] is available and nothing is counted.
+#[cfg(feature = "alloc_audit")]
+use std::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "alloc_audit")]
+use std::cell::Cell;
+#[cfg(feature = "alloc_audit")]
+use std::ops::AddAssign;
+
+/// Allocations that the audited operations are known to perform.
+///
+/// They are not counted as unexpected allocations; each of them is a candidate for removal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationException {
+    /// The pongs are buffered by the ping manager, and the pings and pongs are buffered in their channels
+    Ping,
+    /// The clients targeted by a message or a replication update are collected in a new list
+    Targets,
+    /// The replication updates of each group are buffered, then written in new messages
+    ReplicationBuffers,
+    /// The messages that fit in the bandwidth are collected in new lists, sorted by priority
+    PriorityFilter,
+    /// Each packet is written in a new buffer, whose ownership is passed to the io
+    PacketBuffers,
+}
+
+impl AllocationException {
+    const COUNT: usize = 5;
+}
+
+/// Run `f`, counting its allocations as the `exception` instead of as unexpected allocations
+#[cfg(feature = "alloc_audit")]
+pub(crate) fn allow_allocations<R>(exception: AllocationException, f: impl FnOnce() -> R) -> R {
+    let previous = EXCEPTION.with(|current| current.replace(Some(exception)));
+    let result = f();
+    EXCEPTION.with(|current| current.set(previous));
+    result
+}
+
+#[cfg(not(feature = "alloc_audit"))]
+#[inline(always)]
+pub(crate) fn allow_allocations<R>(_: AllocationException, f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+#[cfg(feature = "alloc_audit")]
+thread_local! {
+    /// Allocations of the current thread, if they are being counted
+    static COUNTER: Cell<Option<Allocations>> = const { Cell::new(None) };
+    /// Exception that covers the allocations of the current thread, if any
+    static EXCEPTION: Cell<Option<AllocationException>> = const { Cell::new(None) };
+}
+
+/// Global allocator that counts the allocations made inside [`count_allocations`]
+#[cfg(feature = "alloc_audit")]
+pub struct CountingAllocator<A> {
+    inner: A,
+}
+
+#[cfg(feature = "alloc_audit")]
+impl<A> CountingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "alloc_audit")]
+fn record_allocation() {
+    // the counters can be destroyed if the thread is exiting
+    let _ = COUNTER.try_with(|counter| {
+        if let Some(mut allocations) = counter.get() {
+            match EXCEPTION.try_with(Cell::get).ok().flatten() {
+                Some(exception) => allocations.exceptions[exception as usize] += 1,
+                None => allocations.unexpected += 1,
+            }
+            counter.set(Some(allocations));
+        }
+    });
+}
+
+#[cfg(feature = "alloc_audit")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Heap allocations (and reallocations) counted on a thread
+#[cfg(feature = "alloc_audit")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Allocations {
+    /// Allocations that are not covered by an [`AllocationException`]
+    pub unexpected: u64,
+    exceptions: [u64; AllocationException::COUNT],
+}
+
+#[cfg(feature = "alloc_audit")]
+impl Allocations {
+    /// Allocations covered by the `exception`
+    pub fn exception(&self, exception: AllocationException) -> u64 {
+        self.exceptions[exception as usize]
+    }
+}
+
+#[cfg(feature = "alloc_audit")]
+impl AddAssign for Allocations {
+    fn add_assign(&mut self, rhs: Self) {
+        self.unexpected += rhs.unexpected;
+        for (count, other) in self.exceptions.iter_mut().zip(rhs.exceptions) {
+            *count += other;
+        }
+    }
+}
+
+/// Run `f` and return the heap allocations it performed on the current thread
+#[cfg(feature = "alloc_audit")]
+pub fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, Allocations) {
+    let counter = AllocationCounter::start();
+    let result = f();
+    (result, counter.finish())
+}
+
+/// Count the heap allocations performed on the current thread between [`AllocationCounter::start`]
+/// and [`AllocationCounter::finish`], for the code that can't be wrapped in [`count_allocations`]
+#[cfg(feature = "alloc_audit")]
+pub struct AllocationCounter {
+    previous: Option<Allocations>,
+}
+
+#[cfg(feature = "alloc_audit")]
+impl AllocationCounter {
+    pub fn start() -> Self {
+        Self {
+            previous: COUNTER.with(|counter| counter.replace(Some(Allocations::default()))),
+        }
+    }
+
+    /// Stop counting and return the allocations since [`AllocationCounter::start`]
+    pub fn finish(self) -> Allocations {
+        let allocations = COUNTER
+            .with(|counter| counter.replace(self.previous))
+            .unwrap_or_default();
+        // nested counts are included in the outer count
+        if let Some(mut previous) = self.previous {
+            previous += allocations;
+            COUNTER.with(|counter| counter.set(Some(previous)));
+        }
+        allocations
+    }
+}
+
+/// Networking operation whose allocations are audited
+#[cfg(feature = "alloc_audit")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditedOperation {
+    /// Processing the packets received from the clients
+    Receive,
+    /// Preparing the packets to send to the clients
+    Send,
+    /// Collecting the replication updates of the entities and buffering them in the channels
+    Replication,
+}
+
+/// Heap allocations performed by each audited operation during the last frame
+#[cfg(feature = "alloc_audit")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationCounts {
+    pub(crate) receive: Allocations,
+    pub(crate) send: Allocations,
+    pub(crate) replication: Allocations,
+}
+
+#[cfg(feature = "alloc_audit")]
+impl AllocationCounts {
+    pub fn get(&self, operation: AuditedOperation) -> &Allocations {
+        match operation {
+            AuditedOperation::Receive => &self.receive,
+            AuditedOperation::Send => &self.send,
+            AuditedOperation::Replication => &self.replication,
+        }
+    }
+
+    /// Allocations covered by the `exception` during the last frame, in all the operations
+    pub fn exception(&self, exception: AllocationException) -> u64 {
+        [&self.receive, &self.send, &self.replication]
+            .iter()
+            .map(|allocations| allocations.exception(exception))
+            .sum()
+    }
+
+    /// Panic if the operation performed unexpected allocations during the last frame
+    #[track_caller]
+    pub fn assert_no_allocations(&self, operation: AuditedOperation) {
+        self.assert_at_most(operation, 0);
+    }
+
+    /// Panic if the operation performed more than `max` unexpected allocations during the last frame
+    #[track_caller]
+    pub fn assert_at_most(&self, operation: AuditedOperation, max: u64) {
+        let count = self.get(operation).unexpected;
+        assert!(
+            count <= max,
+            "{operation:?} performed {count} unexpected heap allocations during the last frame, the budget is {max}"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "alloc_audit"))]
+mod tests {
+    use crate::prelude::server::{ConnectionManager, Replicate};
+    use crate::tests::protocol::ComponentSyncModeFull;
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_count_allocations() {
+        let (_, allocations) = count_allocations(|| {
+            let (vec, inner) = count_allocations(|| vec![0u8; 16]);
+            assert_eq!(inner.unexpected, 1);
+            vec
+        });
+        assert_eq!(allocations.unexpected, 1);
+        let (_, allocations) = count_allocations(|| 1 + 1);
+        assert_eq!(allocations.unexpected, 0);
+        let (_, allocations) = count_allocations(|| {
+            allow_allocations(AllocationException::PacketBuffers, || vec![0u8; 16])
+        });
+        assert_eq!(allocations.unexpected, 0);
+        assert_eq!(allocations.exception(AllocationException::PacketBuffers), 1);
+    }
+
+    /// In the steady state, with one client and an entity that is updated every frame, the audited operations
+    /// only perform the allocations listed in [`AllocationException`]
+    #[test]
+    fn test_steady_state_allocations() {
+        let mut stepper = BevyStepper::default();
+        let entity = stepper
+            .server_app
+            .world_mut()
+            .spawn((Replicate::default(), ComponentSyncModeFull(0.0)))
+            .id();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        for _ in 0..100 {
+            stepper
+                .server_app
+                .world_mut()
+                .get_mut::<ComponentSyncModeFull>(entity)
+                .unwrap()
+                .0 += 1.0;
+            stepper.frame_step();
+            let counts = stepper
+                .server_app
+                .world()
+                .resource::<ConnectionManager>()
+                .allocations();
+            counts.assert_no_allocations(AuditedOperation::Receive);
+            counts.assert_no_allocations(AuditedOperation::Send);
+            counts.assert_no_allocations(AuditedOperation::Replication);
+        }
+    }
+}
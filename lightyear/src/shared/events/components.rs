@@ -3,19 +3,74 @@
 use std::marker::PhantomData;
 
 use bevy::prelude::{Component, Entity, Event};
+use bytes::Bytes;
 
 use crate::packet::message::Message;
+use crate::protocol::message::TraceId;
+use crate::shared::tick_manager::{Tick, TickManager};
+use crate::shared::time_manager::{TimeManager, WrappedTime};
+
+/// Tick and time of the local peer on the frame where an event was emitted.
+///
+/// The events emitted by lightyear carry it so that the systems that consume them can order them and
+/// compute how long ago they were received, for example with
+/// `time_manager.current_time() - stamp.time`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventStamp {
+    /// Tick of the local peer when the event was emitted
+    pub tick: Tick,
+    /// Time of the local peer when the event was emitted
+    pub time: WrappedTime,
+}
+
+impl EventStamp {
+    pub(crate) fn now(tick_manager: &TickManager, time_manager: &TimeManager) -> Self {
+        Self {
+            tick: tick_manager.tick(),
+            time: time_manager.current_time(),
+        }
+    }
+}
 
 /// This event is emitted whenever we receive a message from the remote
 #[derive(Event, Debug)]
 pub struct MessageEvent<M: Message, Ctx = ()> {
     pub message: M,
     pub context: Ctx,
+    /// The [`TraceId`] that the sender attached to the message
+    trace_id: Option<TraceId>,
+    /// When the message was received
+    stamp: Option<EventStamp>,
 }
 
 impl<M: Message, Ctx> MessageEvent<M, Ctx> {
     pub fn new(message: M, context: Ctx) -> Self {
-        Self { message, context }
+        Self {
+            message,
+            context,
+            trace_id: None,
+            stamp: None,
+        }
+    }
+
+    pub(crate) fn with_trace_id(mut self, trace_id: Option<TraceId>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// The [`TraceId`] that the sender attached to the message, if any
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
+    }
+
+    /// Tick and time at which the message was received
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
     }
 
     pub fn message(&self) -> &M {
@@ -27,6 +82,15 @@ impl<M: Message, Ctx> MessageEvent<M, Ctx> {
     }
 }
 
+/// This event is emitted whenever we receive a [raw datagram](crate::packet::raw) from the remote
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct RawDatagramEvent<Ctx = ()> {
+    /// Kind of the datagram, chosen by the sender
+    pub kind: u8,
+    pub payload: Bytes,
+    pub context: Ctx,
+}
+
 #[derive(Event)]
 /// Event emitted on server every time we receive an event
 pub struct InputEvent<I: crate::inputs::native::UserAction, Ctx = ()> {
@@ -48,16 +112,75 @@ impl<I: crate::inputs::native::UserAction, Ctx> InputEvent<I, Ctx> {
     }
 }
 
+/// Event emitted once all the replication messages of a remote tick that were received have been applied.
+///
+/// The replication actions of a tick are applied in a fixed order: the entity spawns first, then for each
+/// entity the component inserts, updates and removals, and the entity despawns last. The component updates
+/// that don't come with an action are applied after all the actions.
+///
+/// Only one event is emitted per tick, even if the tick contained messages for several replication groups,
+/// so game systems can use it to run exactly once per applied tick. The events of a frame are sorted by tick.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ReplicationAppliedEvent<Ctx = ()> {
+    tick: Tick,
+    context: Ctx,
+    stamp: Option<EventStamp>,
+}
+
+impl<Ctx> ReplicationAppliedEvent<Ctx> {
+    pub fn new(tick: Tick, context: Ctx) -> Self {
+        Self {
+            tick,
+            context,
+            stamp: None,
+        }
+    }
+
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Tick and time at which the replication messages were applied
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+
+    /// The remote tick whose replication messages were applied
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
 #[derive(Event)]
 /// Event emitted whenever we spawn an entity from the remote world
 pub struct EntitySpawnEvent<Ctx = ()> {
     entity: Entity,
     context: Ctx,
+    stamp: Option<EventStamp>,
 }
 
 impl<Ctx> EntitySpawnEvent<Ctx> {
     pub fn new(entity: Entity, context: Ctx) -> Self {
-        Self { entity, context }
+        Self {
+            entity,
+            context,
+            stamp: None,
+        }
+    }
+
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Tick and time at which the entity was spawned
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
     }
 
     pub fn entity(&self) -> Entity {
@@ -74,11 +197,26 @@ impl<Ctx> EntitySpawnEvent<Ctx> {
 pub struct EntityDespawnEvent<Ctx = ()> {
     entity: Entity,
     context: Ctx,
+    stamp: Option<EventStamp>,
 }
 
 impl<Ctx> EntityDespawnEvent<Ctx> {
     pub fn new(entity: Entity, context: Ctx) -> Self {
-        Self { entity, context }
+        Self {
+            entity,
+            context,
+            stamp: None,
+        }
+    }
+
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Tick and time at which the entity was despawned
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
     }
 
     pub fn entity(&self) -> Entity {
@@ -95,6 +233,7 @@ impl<Ctx> EntityDespawnEvent<Ctx> {
 pub struct ComponentUpdateEvent<C: Component, Ctx = ()> {
     entity: Entity,
     context: Ctx,
+    stamp: Option<EventStamp>,
 
     _marker: PhantomData<C>,
 }
@@ -104,10 +243,21 @@ impl<C: Component, Ctx> ComponentUpdateEvent<C, Ctx> {
         Self {
             entity,
             context,
+            stamp: None,
             _marker: PhantomData,
         }
     }
 
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Tick and time at which the component was updated
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+
     pub fn entity(&self) -> Entity {
         self.entity
     }
@@ -122,6 +272,7 @@ impl<C: Component, Ctx> ComponentUpdateEvent<C, Ctx> {
 pub struct ComponentInsertEvent<C: Component, Ctx = ()> {
     entity: Entity,
     context: Ctx,
+    stamp: Option<EventStamp>,
 
     _marker: PhantomData<C>,
 }
@@ -131,10 +282,21 @@ impl<C: Component, Ctx> ComponentInsertEvent<C, Ctx> {
         Self {
             entity,
             context,
+            stamp: None,
             _marker: PhantomData,
         }
     }
 
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Tick and time at which the component was inserted
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+
     pub fn entity(&self) -> Entity {
         self.entity
     }
@@ -149,6 +311,7 @@ impl<C: Component, Ctx> ComponentInsertEvent<C, Ctx> {
 pub struct ComponentRemoveEvent<C: Component, Ctx = ()> {
     entity: Entity,
     context: Ctx,
+    stamp: Option<EventStamp>,
 
     _marker: PhantomData<C>,
 }
@@ -158,10 +321,21 @@ impl<C: Component, Ctx> ComponentRemoveEvent<C, Ctx> {
         Self {
             entity,
             context,
+            stamp: None,
             _marker: PhantomData,
         }
     }
 
+    pub(crate) fn with_stamp(mut self, stamp: EventStamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// Tick and time at which the component was removed
+    pub fn stamp(&self) -> Option<EventStamp> {
+        self.stamp
+    }
+
     pub fn entity(&self) -> Entity {
         self.entity
     }
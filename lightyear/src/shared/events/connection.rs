@@ -36,6 +36,12 @@ pub struct ConnectionEvents {
 
     // How can i easily get the events (inserts/adds/removes) for a given entity? add components on that entity
     // that track that?
+    /// Remote tick of each component insert/update, in the order in which they were applied.
+    /// Only recorded if `record_component_ticks` is true
+    pub(crate) component_ticks: Vec<(Entity, ComponentNetId, Tick)>,
+    pub(crate) record_component_ticks: bool,
+    /// Remote ticks of the replication messages that were applied
+    pub(crate) applied_ticks: Vec<Tick>,
     empty: bool,
 }
 
@@ -50,6 +56,8 @@ impl ClearEvents for ConnectionEvents {
         self.component_inserts.clear();
         self.component_removes.clear();
         self.component_updates.clear();
+        self.component_ticks.clear();
+        self.applied_ticks.clear();
         self.empty = true;
     }
 }
@@ -69,6 +77,9 @@ impl ConnectionEvents {
             component_inserts: Default::default(),
             component_removes: Default::default(),
             component_updates: Default::default(),
+            component_ticks: Vec::new(),
+            record_component_ticks: false,
+            applied_ticks: Vec::new(),
             // bookkeeping
             empty: true,
         }
@@ -110,7 +121,9 @@ impl ConnectionEvents {
             metrics::counter!("component_insert", "kind" => kind.to_string()).increment(1);
         }
         self.component_inserts.entry(kind).or_default().push(entity);
-        // .push((entity, tick));
+        if self.record_component_ticks {
+            self.component_ticks.push((entity, kind, tick));
+        }
         self.empty = false;
     }
 
@@ -130,6 +143,12 @@ impl ConnectionEvents {
         self.empty = false;
     }
 
+    pub(crate) fn push_applied_tick(&mut self, tick: Tick) {
+        trace!(?tick, "Applied replication message");
+        self.applied_ticks.push(tick);
+        self.empty = false;
+    }
+
     // TODO: how do distinguish between multiple updates for the same component/entity? add ticks?
     pub(crate) fn push_update_component(
         &mut self,
@@ -155,7 +174,9 @@ impl ConnectionEvents {
         //     .or_insert(tick);
 
         self.component_updates.entry(kind).or_default().push(entity);
-        // .push((entity, tick));
+        if self.record_component_ticks {
+            self.component_ticks.push((entity, kind, tick));
+        }
         self.empty = false;
     }
 }
@@ -176,6 +197,20 @@ impl IterEntitySpawnEvent for ConnectionEvents {
     }
 }
 
+pub trait IterReplicationAppliedEvent<Ctx: EventContext = ()> {
+    /// The remote ticks whose replication messages were applied, in increasing order
+    fn iter_replication_applied(&mut self) -> Box<dyn Iterator<Item = (Tick, Ctx)> + '_>;
+}
+
+impl IterReplicationAppliedEvent for ConnectionEvents {
+    fn iter_replication_applied(&mut self) -> Box<dyn Iterator<Item = (Tick, ())> + '_> {
+        let mut ticks = std::mem::take(&mut self.applied_ticks);
+        ticks.sort();
+        ticks.dedup();
+        Box::new(ticks.into_iter().map(|tick| (tick, ())))
+    }
+}
+
 pub trait IterEntityDespawnEvent<Ctx: EventContext = ()> {
     fn into_iter_entity_despawn(&mut self) -> Box<dyn Iterator<Item = (Entity, Ctx)> + '_>;
     fn has_entity_despawn(&self) -> bool;
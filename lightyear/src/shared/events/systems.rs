@@ -3,59 +3,74 @@ use bevy::prelude::{Component, EventWriter, Res, ResMut};
 use crate::prelude::ComponentRegistry;
 use crate::shared::events::components::{
     ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, EntityDespawnEvent,
-    EntitySpawnEvent,
+    EntitySpawnEvent, EventStamp, ReplicationAppliedEvent,
 };
 use crate::shared::events::connection::{
     ClearEvents, IterComponentInsertEvent, IterComponentRemoveEvent, IterComponentUpdateEvent,
-    IterEntityDespawnEvent, IterEntitySpawnEvent,
+    IterEntityDespawnEvent, IterEntitySpawnEvent, IterReplicationAppliedEvent,
 };
 use crate::shared::replication::ReplicationReceive;
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::TimeManager;
 
 /// System that gathers the replication events received by the local host and sends them to bevy Events
 pub(crate) fn push_component_events<C: Component, R: ReplicationReceive>(
     component_registry: Res<ComponentRegistry>,
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
     mut connection_manager: ResMut<R>,
     mut component_insert_events: EventWriter<ComponentInsertEvent<C, R::EventContext>>,
     mut component_remove_events: EventWriter<ComponentRemoveEvent<C, R::EventContext>>,
     mut component_update_events: EventWriter<ComponentUpdateEvent<C, R::EventContext>>,
 ) {
+    let stamp = EventStamp::now(&tick_manager, &time_manager);
     component_insert_events.send_batch(
         connection_manager
             .events()
             .iter_component_insert::<C>(component_registry.as_ref())
-            .map(|(entity, ctx)| ComponentInsertEvent::new(entity, ctx)),
+            .map(|(entity, ctx)| ComponentInsertEvent::new(entity, ctx).with_stamp(stamp)),
     );
     component_remove_events.send_batch(
         connection_manager
             .events()
             .iter_component_remove::<C>(component_registry.as_ref())
-            .map(|(entity, ctx)| ComponentRemoveEvent::new(entity, ctx)),
+            .map(|(entity, ctx)| ComponentRemoveEvent::new(entity, ctx).with_stamp(stamp)),
     );
     component_update_events.send_batch(
         connection_manager
             .events()
             .iter_component_update::<C>(component_registry.as_ref())
-            .map(|(entity, ctx)| ComponentUpdateEvent::new(entity, ctx)),
+            .map(|(entity, ctx)| ComponentUpdateEvent::new(entity, ctx).with_stamp(stamp)),
     );
 }
 
 /// System that gathers the replication events received by the local host and sends them to bevy Events
 pub(crate) fn push_entity_events<R: ReplicationReceive>(
+    tick_manager: Res<TickManager>,
+    time_manager: Res<TimeManager>,
     mut connection_manager: ResMut<R>,
     mut entity_spawn_events: EventWriter<EntitySpawnEvent<R::EventContext>>,
     mut entity_despawn_events: EventWriter<EntityDespawnEvent<R::EventContext>>,
+    mut replication_applied_events: EventWriter<ReplicationAppliedEvent<R::EventContext>>,
 ) {
+    let stamp = EventStamp::now(&tick_manager, &time_manager);
     entity_spawn_events.send_batch(
         connection_manager
             .events()
             .into_iter_entity_spawn()
-            .map(|(entity, ctx)| EntitySpawnEvent::new(entity, ctx)),
+            .map(|(entity, ctx)| EntitySpawnEvent::new(entity, ctx).with_stamp(stamp)),
     );
     entity_despawn_events.send_batch(
         connection_manager
             .events()
             .into_iter_entity_despawn()
-            .map(|(entity, ctx)| EntityDespawnEvent::new(entity, ctx)),
+            .map(|(entity, ctx)| EntityDespawnEvent::new(entity, ctx).with_stamp(stamp)),
+    );
+    replication_applied_events.send_batch(
+        connection_manager
+            .events()
+            .iter_replication_applied()
+            .map(|(tick, ctx)| ReplicationAppliedEvent::new(tick, ctx).with_stamp(stamp)),
     );
 }
 
@@ -1,5 +1,7 @@
 //! Shared code between the server and client.
 
+pub mod alloc_audit;
+
 pub mod config;
 
 pub mod events;
@@ -10,6 +12,8 @@ pub mod ping;
 
 pub mod plugin;
 
+pub mod quantize;
+
 pub mod replication;
 
 pub mod sets;
@@ -18,5 +22,6 @@ pub mod tick_manager;
 
 pub mod input;
 pub(crate) mod message;
+pub mod rpc;
 pub mod run_conditions;
 pub mod time_manager;
@@ -5,15 +5,29 @@ use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::utils::Duration;
 
+use crate::client::idle::ServerIdleWarning;
+use crate::client::kick::ServerKick;
+use crate::client::queue::ServerQueueUpdate;
+use crate::client::redirect::ServerRedirect;
+use crate::client::restart::ServerRestart;
+use crate::client::session::SessionSecret;
+use crate::client::shutdown::ServerShutdown;
 use crate::prelude::client::ComponentSyncMode;
 use crate::prelude::{
     AppComponentExt, AppMessageExt, ChannelDirection, ChannelRegistry, ComponentRegistry,
     LinkConditionerConfig, MessageRegistry, Mode, ParentSync, PingConfig, PrePredicted,
     PreSpawnedPlayerObject, ShouldBePredicted, TickConfig,
 };
+use crate::protocol::plugin::ProtocolPlugins;
+use crate::protocol::version::SchemaVersions;
+use crate::server::pacing::RenderRateHint;
+use crate::server::session::ResumeSession;
 use crate::shared::config::SharedConfig;
 use crate::shared::replication::authority::AuthorityChange;
+use crate::shared::replication::baseline::BaselineReport;
 use crate::shared::replication::components::{Controlled, ShouldBeInterpolated};
+use crate::shared::replication::local_write::{LocalWrite, LocalWriteAck};
+use crate::shared::replication::transient::TransientSpawn;
 use crate::shared::tick_manager::TickManagerPlugin;
 use crate::shared::time_manager::TimePlugin;
 use crate::transport::io::{IoState, IoStats};
@@ -156,6 +170,25 @@ impl Plugin for SharedPlugin {
 
         app.register_message::<AuthorityChange>(ChannelDirection::ServerToClient)
             .add_map_entities();
+        app.register_message::<BaselineReport>(ChannelDirection::ClientToServer);
+        app.register_message::<RenderRateHint>(ChannelDirection::ClientToServer);
+        app.register_message::<ServerRedirect>(ChannelDirection::ServerToClient);
+        app.register_message::<ServerRestart>(ChannelDirection::ServerToClient);
+        app.register_message::<ServerKick>(ChannelDirection::ServerToClient);
+        app.register_message::<ServerShutdown>(ChannelDirection::ServerToClient);
+        app.register_message::<ServerQueueUpdate>(ChannelDirection::ServerToClient);
+        app.register_message::<ServerIdleWarning>(ChannelDirection::ServerToClient);
+        app.register_message::<LocalWrite>(ChannelDirection::ClientToServer)
+            .add_map_entities();
+        app.register_message::<LocalWriteAck>(ChannelDirection::ServerToClient)
+            .add_map_entities();
+        app.register_message::<TransientSpawn>(ChannelDirection::ServerToClient);
+        app.register_message::<SessionSecret>(ChannelDirection::ServerToClient);
+        app.register_message::<ResumeSession>(ChannelDirection::ClientToServer);
+        app.register_message::<SchemaVersions>(ChannelDirection::Bidirectional);
+
+        // the protocol plugins are built in a deterministic order, after all the other plugins
+        ProtocolPlugins::build(app);
 
         // check that the protocol was built correctly
         app.world().resource::<ComponentRegistry>().check();
@@ -0,0 +1,375 @@
+//! Compact network representations of rotations and transforms.
+//!
+//! By default a [`Transform`] is serialized as 10 `f32`s (40 bytes). Most games don't need that much precision:
+//! - rotations can use the smallest-three encoding: the largest component of a unit quaternion can be
+//!   recomputed from the three others, which are all in `[-1/√2, 1/√2]`. [`CompressedQuat`] stores the index
+//!   of the largest component and the three others with 10 bits each, in 4 bytes.
+//! - translations and scales can be rounded to a fixed step (1mm by default), and sent as variable-length integers
+//!
+//! The precision is configured with a [`TransformPrecision`]. To replicate a [`Transform`] with it:
+//! ```rust,ignore
+//! use lightyear::prelude::*;
+//! use lightyear::shared::quantize::DefaultTransformPrecision;
+//!
+//! app.register_component_custom_serde::<Transform>(
+//!     ChannelDirection::ServerToClient,
+//!     SerializeFns::<Transform>::quantized::<DefaultTransformPrecision>(),
+//! );
+//! ```
+use bevy::math::{Quat, Vec3, Vec4};
+use bevy::prelude::Transform;
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+use crate::protocol::serialize::SerializeFns;
+use crate::serialize::reader::Reader;
+use crate::serialize::varint::{zigzag_decode, zigzag_encode, VarIntReadExt, VarIntWriteExt};
+use crate::serialize::writer::Writer;
+use crate::serialize::SerializationError;
+
+/// Maximum absolute value of the three smallest components of a unit quaternion
+const SMALLEST_THREE_MAX: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Encode a rotation with the smallest-three encoding, using `bits` bits for each of the three
+/// smallest components. The result uses `2 + 3 * bits` bits
+pub fn encode_quat(quat: Quat, bits: u32) -> u64 {
+    let mut components = quat.normalize().to_array();
+    let (largest, _) = components
+        .iter()
+        .enumerate()
+        .fold((0, -1.0), |(index, max), (i, c)| {
+            if c.abs() > max {
+                (i, c.abs())
+            } else {
+                (index, max)
+            }
+        });
+    // q and -q represent the same rotation, so the largest component can always be made positive
+    if components[largest] < 0.0 {
+        components.iter_mut().for_each(|c| *c = -*c);
+    }
+    // use an even number of steps so that 0.0 is encoded exactly
+    let max = ((1u64 << bits) - 2) as f32;
+    let mut encoded = largest as u64;
+    for (i, c) in components.iter().enumerate() {
+        if i == largest {
+            continue;
+        }
+        let normalized = (c / SMALLEST_THREE_MAX).clamp(-1.0, 1.0) * 0.5 + 0.5;
+        encoded = (encoded << bits) | (normalized * max).round() as u64;
+    }
+    encoded
+}
+
+/// Decode a rotation encoded with [`encode_quat`]
+pub fn decode_quat(encoded: u64, bits: u32) -> Quat {
+    let mask = (1u64 << bits) - 1;
+    let max = (mask - 1) as f32;
+    let largest = ((encoded >> (3 * bits)) & 0b11) as usize;
+    let mut components = [0.0; 4];
+    let mut shift = 3 * bits;
+    let mut sum = 0.0;
+    for (i, c) in components.iter_mut().enumerate() {
+        if i == largest {
+            continue;
+        }
+        shift -= bits;
+        let normalized = ((encoded >> shift) & mask) as f32 / max;
+        *c = (normalized - 0.5) * 2.0 * SMALLEST_THREE_MAX;
+        sum += *c * *c;
+    }
+    components[largest] = (1.0 - sum).max(0.0).sqrt();
+    Quat::from_vec4(Vec4::from_array(components)).normalize()
+}
+
+/// A rotation compressed with the smallest-three encoding, with 10 bits per component (4 bytes).
+///
+/// The maximum error is about 0.15 degree.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompressedQuat(pub u32);
+
+impl CompressedQuat {
+    const BITS: u32 = 10;
+}
+
+impl From<Quat> for CompressedQuat {
+    fn from(quat: Quat) -> Self {
+        Self(encode_quat(quat, Self::BITS) as u32)
+    }
+}
+
+impl From<CompressedQuat> for Quat {
+    fn from(value: CompressedQuat) -> Self {
+        decode_quat(value.0 as u64, CompressedQuat::BITS)
+    }
+}
+
+/// Precision used to serialize a [`Transform`]
+pub trait TransformPrecision: Send + Sync + 'static {
+    /// Step to which the translation is rounded
+    const TRANSLATION_STEP: f32;
+    /// Step to which the scale is rounded
+    const SCALE_STEP: f32;
+    /// Number of bits of each of the three smallest components of the rotation, between 2 and 20.
+    /// Other values fail to compile.
+    const ROTATION_BITS: u32;
+}
+
+/// Checks at compile time that [`TransformPrecision::ROTATION_BITS`] fits in the 64 bits of the encoded rotation
+struct RotationBits<P>(PhantomData<P>);
+
+impl<P: TransformPrecision> RotationBits<P> {
+    const VALUE: u32 = {
+        assert!(
+            P::ROTATION_BITS >= 2 && P::ROTATION_BITS <= 20,
+            "TransformPrecision::ROTATION_BITS must be between 2 and 20"
+        );
+        P::ROTATION_BITS
+    };
+}
+
+/// [`TransformPrecision::ROTATION_BITS`] of `P`, checked at compile time
+pub(crate) const fn rotation_bits<P: TransformPrecision>() -> u32 {
+    RotationBits::<P>::VALUE
+}
+
+/// Millimeter precision for the translation and scale, and 10 bits per component for the rotation
+pub struct DefaultTransformPrecision;
+
+impl TransformPrecision for DefaultTransformPrecision {
+    const TRANSLATION_STEP: f32 = 0.001;
+    const SCALE_STEP: f32 = 0.001;
+    const ROTATION_BITS: u32 = 10;
+}
+
+/// Write a value rounded to a multiple of `step`, as a variable-length integer
+pub(crate) fn write_quantized(
+    value: f32,
+    step: f32,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    writer.write_varint(zigzag_encode((value / step).round() as i64))?;
+    Ok(())
+}
+
+/// Read a value written with [`write_quantized`]
+pub(crate) fn read_quantized(step: f32, reader: &mut Reader) -> Result<f32, SerializationError> {
+    Ok(zigzag_decode(reader.read_varint()?) as f32 * step)
+}
+
+fn write_quantized_vec3(
+    value: Vec3,
+    step: f32,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    for c in value.to_array() {
+        write_quantized(c, step, writer)?;
+    }
+    Ok(())
+}
+
+fn read_quantized_vec3(step: f32, reader: &mut Reader) -> Result<Vec3, SerializationError> {
+    let mut value = [0.0; 3];
+    for c in value.iter_mut() {
+        *c = read_quantized(step, reader)?;
+    }
+    Ok(Vec3::from_array(value))
+}
+
+/// Smallest angle between two `f32` rotations that [`Quat::angle_between`] can tell apart from 0
+const ANGLE_PRECISION: f32 = 0.001;
+
+/// Largest angle between a rotation and its encoding with `bits` bits per component.
+///
+/// Each of the three smallest components is off by at most half a step, the largest component is recomputed
+/// from them, and the angle between two rotations is about twice the distance between their quaternions.
+pub(crate) fn rotation_error(bits: u32) -> f32 {
+    let step = 2.0 * SMALLEST_THREE_MAX / ((1u64 << bits) - 2) as f32;
+    (2.0 * 3f32.sqrt() * step).max(ANGLE_PRECISION)
+}
+
+/// Step of an angle encoded with [`encode_angle`]
+pub(crate) const ANGLE_STEP: f32 = std::f32::consts::TAU / u16::MAX as f32;
+
+/// Encode an angle between `-PI` and `PI` with 16 bits
+pub(crate) fn encode_angle(angle: f32) -> u16 {
+    ((angle + std::f32::consts::PI) / ANGLE_STEP).round() as u16
+}
+
+/// Decode an angle encoded with [`encode_angle`]
+pub(crate) fn decode_angle(encoded: u16) -> f32 {
+    encoded as f32 * ANGLE_STEP - std::f32::consts::PI
+}
+
+/// Number of bytes used by a rotation encoded with `bits` bits per component
+pub(crate) const fn rotation_len(bits: u32) -> usize {
+    (2 + 3 * bits as usize).div_ceil(8)
+}
+
+fn serialize_transform<P: TransformPrecision>(
+    transform: &Transform,
+    writer: &mut Writer,
+) -> Result<(), SerializationError> {
+    write_quantized_vec3(transform.translation, P::TRANSLATION_STEP, writer)?;
+    let bits = rotation_bits::<P>();
+    let rotation = encode_quat(transform.rotation, bits);
+    let len = rotation_len(bits);
+    writer.write_uint::<NetworkEndian>(rotation, len)?;
+    // most transforms are not scaled
+    if transform.scale == Vec3::ONE {
+        writer.write_u8(0)?;
+    } else {
+        writer.write_u8(1)?;
+        write_quantized_vec3(transform.scale, P::SCALE_STEP, writer)?;
+    }
+    Ok(())
+}
+
+fn deserialize_transform<P: TransformPrecision>(
+    reader: &mut Reader,
+) -> Result<Transform, SerializationError> {
+    let translation = read_quantized_vec3(P::TRANSLATION_STEP, reader)?;
+    let bits = rotation_bits::<P>();
+    let len = rotation_len(bits);
+    let rotation = decode_quat(reader.read_uint::<NetworkEndian>(len)?, bits);
+    let scale = match reader.read_u8()? {
+        0 => Vec3::ONE,
+        1 => read_quantized_vec3(P::SCALE_STEP, reader)?,
+        _ => return Err(SerializationError::InvalidValue),
+    };
+    Ok(Transform {
+        translation,
+        rotation,
+        scale,
+    })
+}
+
+impl SerializeFns<Transform> {
+    /// Serialize the [`Transform`] with the precision `P`: the translation and the scale are rounded to a step,
+    /// and the rotation uses the smallest-three encoding
+    pub fn quantized<P: TransformPrecision>() -> Self {
+        Self {
+            serialize: serialize_transform::<P>,
+            deserialize: deserialize_transform::<P>,
+            serialize_map_entities: None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bevy::math::EulerRot;
+
+    use super::*;
+
+    /// Default precision, with `BITS` bits per component of the rotation
+    pub(crate) struct RotationPrecision<const BITS: u32>;
+
+    impl<const BITS: u32> TransformPrecision for RotationPrecision<BITS> {
+        const TRANSLATION_STEP: f32 = DefaultTransformPrecision::TRANSLATION_STEP;
+        const SCALE_STEP: f32 = DefaultTransformPrecision::SCALE_STEP;
+        const ROTATION_BITS: u32 = BITS;
+    }
+
+    /// Run `$check::<RotationPrecision<BITS>>()` for every valid number of rotation bits
+    macro_rules! for_each_rotation_bits {
+        ($check:ident) => {
+            for_each_rotation_bits!($check, 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20)
+        };
+        ($check:ident, $($bits:literal)*) => {
+            $($check::<$crate::shared::quantize::tests::RotationPrecision<$bits>>();)*
+        };
+    }
+    pub(crate) use for_each_rotation_bits;
+
+    /// Rotations that cover the four possible largest components, with both signs
+    pub(crate) fn rotations() -> impl Iterator<Item = Quat> {
+        (0..500).map(|i| {
+            let f = i as f32;
+            Quat::from_euler(
+                EulerRot::XYZ,
+                (f * 0.37).sin() * 3.1,
+                (f * 0.71).cos() * 1.5,
+                f * 0.13,
+            )
+        })
+    }
+
+    #[test]
+    fn test_angle_round_trip() {
+        use std::f32::consts::PI;
+        for angle in [-PI, -PI / 2.0, -0.001, 0.0, 0.001, 1.0, PI / 2.0, PI] {
+            let decoded = decode_angle(encode_angle(angle));
+            assert!(
+                (decoded - angle).abs() <= ANGLE_STEP / 2.0,
+                "{angle} decoded as {decoded}"
+            );
+        }
+        // both ends of the range are encoded exactly
+        assert_eq!(encode_angle(-PI), 0);
+        assert_eq!(encode_angle(PI), u16::MAX);
+    }
+
+    #[test]
+    fn test_rotation_error() {
+        fn check<P: TransformPrecision>() {
+            let bits = rotation_bits::<P>();
+            for quat in rotations() {
+                let decoded = decode_quat(encode_quat(quat, bits), bits);
+                assert!(
+                    quat.angle_between(decoded) <= rotation_error(bits),
+                    "{bits} bits: {quat:?} decoded as {decoded:?}"
+                );
+            }
+        }
+        for_each_rotation_bits!(check);
+    }
+
+    #[test]
+    fn test_compressed_quat() {
+        for (x, y, z) in [
+            (0.0, 0.0, 0.0),
+            (0.3, -1.2, 2.5),
+            (-3.0, 0.7, 1.1),
+            (1.5, 1.5, -1.5),
+        ] {
+            let quat = Quat::from_euler(EulerRot::XYZ, x, y, z);
+            let decoded: Quat = CompressedQuat::from(quat).into();
+            // q and -q represent the same rotation
+            assert!(
+                quat.angle_between(decoded) < 0.15_f32.to_radians(),
+                "{quat:?} decoded as {decoded:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_quantized_transform() {
+        let fns = SerializeFns::<Transform>::quantized::<DefaultTransformPrecision>();
+        for transform in [
+            Transform::from_xyz(1.2345, -20.0, 300.5).with_rotation(Quat::from_euler(
+                EulerRot::XYZ,
+                0.3,
+                -1.2,
+                2.5,
+            )),
+            Transform::from_xyz(0.0, 1.0, 0.0).with_scale(Vec3::new(2.0, 0.5, 1.0)),
+        ] {
+            let mut writer = Writer::default();
+            (fns.serialize)(&transform, &mut writer).unwrap();
+            let bytes = writer.to_bytes();
+            // 40 bytes with the default serialization
+            assert!(bytes.len() <= 20, "{} bytes", bytes.len());
+            let decoded = (fns.deserialize)(&mut Reader::from(bytes)).unwrap();
+            assert!(decoded.translation.abs_diff_eq(
+                transform.translation,
+                DefaultTransformPrecision::TRANSLATION_STEP
+            ));
+            assert!(decoded
+                .scale
+                .abs_diff_eq(transform.scale, DefaultTransformPrecision::SCALE_STEP));
+            assert!(transform.rotation.angle_between(decoded.rotation) < 0.15_f32.to_radians());
+        }
+    }
+}
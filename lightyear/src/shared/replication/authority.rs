@@ -55,6 +55,51 @@ mod tests {
     use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
     use bevy::prelude::{default, Entity};
 
+    /// A transfer to a client that disconnected, or of an entity that was despawned, is skipped
+    /// instead of panicking
+    #[test]
+    fn test_transfer_authority_stale() {
+        let mut stepper = BevyStepper::default();
+        let server_entity = stepper
+            .server_app
+            .world_mut()
+            .spawn(server::Replicate::default())
+            .id();
+        stepper.flush();
+
+        stepper
+            .server_app
+            .world_mut()
+            .commands()
+            .entity(server_entity)
+            .transfer_authority(AuthorityPeer::Client(ClientId::Netcode(999)));
+        stepper.flush();
+        assert!(stepper
+            .server_app
+            .world()
+            .get::<HasAuthority>(server_entity)
+            .is_some());
+        assert_eq!(
+            stepper
+                .server_app
+                .world()
+                .get::<AuthorityPeer>(server_entity),
+            Some(&AuthorityPeer::Server)
+        );
+
+        let mut commands = stepper.server_app.world_mut().commands();
+        commands.entity(server_entity).despawn();
+        commands
+            .entity(server_entity)
+            .transfer_authority(AuthorityPeer::Client(ClientId::Netcode(TEST_CLIENT_ID)));
+        stepper.flush();
+        assert!(stepper
+            .server_app
+            .world()
+            .get_entity(server_entity)
+            .is_none());
+    }
+
     #[test]
     fn test_transfer_authority_server_to_client() {
         // tracing_subscriber::FmtSubscriber::builder()
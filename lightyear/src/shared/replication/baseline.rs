@@ -0,0 +1,57 @@
+//! Skip the replication of static scenery that the client already has baked in.
+//!
+//! Large static scenes (level geometry, furniture, etc.) can take a lot of bandwidth to replicate
+//! when a client joins, even though the client often already has the exact same content locally.
+//!
+//! The server identifies the content of its static scene with a [`StaticBaseline`] hash,
+//! and marks the entities that are part of that scene with the [`Baseline`] component.
+//! When a client connects, it reports the hash of the baseline it has baked (its own [`StaticBaseline`] resource).
+//! - if the hashes match, the [`Baseline`] entities are never replicated to that client
+//! - otherwise, they are replicated as usual once the report is received
+//!
+//! The baseline entities should usually also use [`TargetEntity::Stable`](crate::prelude::TargetEntity::Stable),
+//! so that a client that received them once can register them for the next connections.
+use bevy::prelude::{Component, Reflect, Resource};
+use serde::{Deserialize, Serialize};
+
+/// Hash of the content of the static scene.
+///
+/// On the server, this is the hash of the scene composed by the [`Baseline`] entities.
+/// On the client, this is the hash of the scene that the client has baked locally.
+///
+/// How the hash is computed is left to the application (for example a hash of the level file),
+/// the only requirement is that the server and the client compute it in the same way.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct StaticBaseline {
+    pub hash: u64,
+}
+
+impl StaticBaseline {
+    pub fn new(hash: u64) -> Self {
+        Self { hash }
+    }
+}
+
+/// Marker component for server entities that are part of the [`StaticBaseline`].
+///
+/// These entities are only replicated to clients that reported a different baseline hash.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub struct Baseline;
+
+/// Message sent by the client when it connects, to let the server know which baseline it has
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct BaselineReport {
+    pub hash: Option<u64>,
+}
+
+/// Whether the [`Baseline`] entities should be replicated to a given client
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum BaselineState {
+    /// We haven't received the client's [`BaselineReport`] yet
+    #[default]
+    Pending,
+    /// The client has the same baseline as the server, no need to replicate the [`Baseline`] entities
+    Matched,
+    /// The client's baseline is different, the [`Baseline`] entities must be replicated
+    Required,
+}
@@ -9,6 +9,7 @@ use crate::connection::id::ClientId;
 use crate::serialize::reader::Reader;
 use crate::serialize::{SerializationError, ToBytes};
 use crate::shared::replication::network_target::NetworkTarget;
+use crate::shared::replication::stable::StableId;
 
 /// Marker component that indicates that the entity was initially spawned via replication
 /// (it was being replicated from a remote world)
@@ -93,6 +94,11 @@ pub enum TargetEntity {
     /// Instead of spawning a new entity, we will apply the replication updates
     /// to the existing remote entity
     Preexisting(Entity),
+    /// Apply the replication updates to the remote entity registered with this [`StableId`]
+    /// in the remote's [`StableEntityMap`](crate::prelude::StableEntityMap).
+    ///
+    /// If no entity is registered for that id, a new entity is spawned and registered.
+    Stable(StableId),
 }
 
 /// Component that defines how the hierarchy of an entity (parent/children) should be replicated
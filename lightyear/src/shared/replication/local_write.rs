@@ -0,0 +1,150 @@
+//! Apply component writes on the client immediately, without reapplying them when the server echoes them back.
+//!
+//! A client can modify a component of a server-authoritative entity that it controls with
+//! [`LocalWriteCommandsExt::write_local`](crate::client::local_write::LocalWriteCommandsExt::write_local).
+//! The write is applied locally right away, and sent to the server with a command id.
+//!
+//! The server applies the write if the entity is [`ControlledBy`](crate::prelude::server::ControlledBy) the client,
+//! and acknowledges the command with the tick at which it was applied. Until then, the server updates
+//! for that component are older than the local write, so they are not applied on the client to avoid visible
+//! self-corrections. If the server refuses the write, the acknowledgement contains the server's value of the
+//! component, which is applied on the client.
+use bevy::ecs::entity::MapEntities;
+use bevy::prelude::{Component, Entity, EntityMapper};
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::Tick;
+use crate::protocol::component::ComponentKind;
+
+/// Message sent by the client to write a component on a server entity
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct LocalWrite {
+    pub(crate) entity: Entity,
+    pub(crate) command_id: u32,
+    /// The serialized component, prefixed by its net id
+    pub(crate) component: Vec<u8>,
+}
+
+impl MapEntities for LocalWrite {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.entity = entity_mapper.map_entity(self.entity);
+    }
+}
+
+/// Message sent by the server to acknowledge a [`LocalWrite`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct LocalWriteAck {
+    pub(crate) entity: Entity,
+    pub(crate) command_id: u32,
+    /// Server updates sent for this tick or later include the write
+    pub(crate) tick: Tick,
+    /// False if the server refused the write
+    pub(crate) accepted: bool,
+    /// If the server refused the write, the server's value of the component (prefixed by its net id)
+    pub(crate) correction: Option<Vec<u8>>,
+}
+
+impl MapEntities for LocalWriteAck {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.entity = entity_mapper.map_entity(self.entity);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PendingWrite {
+    command_id: u32,
+    /// Tick at which the server applied the write, once it has been acknowledged
+    ack_tick: Option<Tick>,
+}
+
+/// Component added on the client to entities that have local writes that the server hasn't caught up with yet
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct LocalWrites {
+    next_command_id: u32,
+    pending: HashMap<ComponentKind, PendingWrite>,
+}
+
+impl LocalWrites {
+    /// Returns true if a local write of the component `C` hasn't been caught up by the server updates yet
+    pub fn is_pending<C: Component>(&self) -> bool {
+        self.pending.contains_key(&ComponentKind::of::<C>())
+    }
+
+    /// Record a new local write and return its command id
+    pub(crate) fn push(&mut self, kind: ComponentKind) -> u32 {
+        let command_id = self.next_command_id;
+        self.next_command_id = self.next_command_id.wrapping_add(1);
+        self.pending.insert(
+            kind,
+            PendingWrite {
+                command_id,
+                ack_tick: None,
+            },
+        );
+        command_id
+    }
+
+    /// Handle the server acknowledgement for a write.
+    ///
+    /// Returns false if the acknowledged write has been superseded by a newer local write
+    pub(crate) fn ack(&mut self, command_id: u32, tick: Tick, rejected: bool) -> bool {
+        let Some((kind, pending)) = self
+            .pending
+            .iter_mut()
+            .find(|(_, pending)| pending.command_id == command_id)
+        else {
+            return false;
+        };
+        if rejected {
+            let kind = *kind;
+            self.pending.remove(&kind);
+        } else {
+            pending.ack_tick = Some(tick);
+        }
+        true
+    }
+
+    /// Returns true if a server update for the component at the given tick is older than the local write,
+    /// in which case it should not be applied
+    pub(crate) fn is_stale(&mut self, kind: ComponentKind, tick: Tick) -> bool {
+        let Some(pending) = self.pending.get(&kind) else {
+            return false;
+        };
+        match pending.ack_tick {
+            Some(ack_tick) if tick >= ack_tick => {
+                // the server caught up with the local write
+                self.pending.remove(&kind);
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component)]
+    struct A;
+
+    #[test]
+    fn test_local_writes() {
+        let kind = ComponentKind::of::<A>();
+        let mut writes = LocalWrites::default();
+        let first = writes.push(kind);
+        let second = writes.push(kind);
+        assert!(writes.is_pending::<A>());
+
+        // updates are stale until the latest write is acknowledged
+        assert!(writes.is_stale(kind, Tick(10)));
+        assert!(!writes.ack(first, Tick(10), false));
+        assert!(writes.is_stale(kind, Tick(10)));
+
+        assert!(writes.ack(second, Tick(12), false));
+        assert!(writes.is_stale(kind, Tick(11)));
+        assert!(!writes.is_stale(kind, Tick(12)));
+        assert!(!writes.is_pending::<A>());
+    }
+}
@@ -19,25 +19,30 @@ use crate::serialize::writer::Writer;
 use crate::serialize::{SerializationError, ToBytes};
 use crate::shared::events::connection::{
     ClearEvents, IterComponentInsertEvent, IterComponentRemoveEvent, IterComponentUpdateEvent,
-    IterEntityDespawnEvent, IterEntitySpawnEvent,
+    IterEntityDespawnEvent, IterEntitySpawnEvent, IterReplicationAppliedEvent,
 };
 use crate::shared::replication::components::ReplicationGroupId;
+use crate::shared::replication::stable::StableId;
 
 pub mod components;
 
 pub(crate) mod archetypes;
 pub(crate) mod authority;
+pub mod baseline;
 pub mod delta;
 pub mod entity_map;
 pub mod error;
 pub(crate) mod hierarchy;
+pub mod local_write;
 pub mod network_target;
 pub(crate) mod plugin;
 pub(crate) mod prespawn;
 pub(crate) mod receive;
 pub(crate) mod resources;
 pub(crate) mod send;
+pub mod stable;
 pub(crate) mod systems;
+pub mod transient;
 
 /// Serialize Entity as two varints for the index and generation (because they will probably be low).
 /// Revisit this when relations comes out
@@ -111,6 +116,14 @@ pub(crate) enum SpawnAction {
     Despawn,
     // the u64 is the entity's bits (we cannot use Entity directly because it doesn't implement Encode/Decode)
     Reuse(Entity),
+    /// Spawn the entity on the remote peer, reusing the entity registered for that [`StableId`] if there is one
+    Stable(StableId),
+    /// The `previous` entity with the same [`StableId`] was despawned in the same send interval.
+    /// Instead of despawning it, the remote resets its components in place and reuses it for the new entity
+    Respawn {
+        previous: Entity,
+        stable_id: StableId,
+    },
 }
 
 impl ToBytes for SpawnAction {
@@ -120,6 +133,11 @@ impl ToBytes for SpawnAction {
             SpawnAction::Spawn => 1,
             SpawnAction::Despawn => 1,
             SpawnAction::Reuse(entity) => 1 + entity.len(),
+            SpawnAction::Stable(id) => 1 + id.len(),
+            SpawnAction::Respawn {
+                previous,
+                stable_id,
+            } => 1 + previous.len() + stable_id.len(),
         }
     }
 
@@ -132,6 +150,18 @@ impl ToBytes for SpawnAction {
                 buffer.write_u8(3)?;
                 entity.to_bytes(buffer)?;
             }
+            SpawnAction::Stable(id) => {
+                buffer.write_u8(4)?;
+                id.to_bytes(buffer)?;
+            }
+            SpawnAction::Respawn {
+                previous,
+                stable_id,
+            } => {
+                buffer.write_u8(5)?;
+                previous.to_bytes(buffer)?;
+                stable_id.to_bytes(buffer)?;
+            }
         }
         Ok(())
     }
@@ -145,6 +175,11 @@ impl ToBytes for SpawnAction {
             1 => Ok(SpawnAction::Spawn),
             2 => Ok(SpawnAction::Despawn),
             3 => Ok(SpawnAction::Reuse(Entity::from_bytes(buffer)?)),
+            4 => Ok(SpawnAction::Stable(StableId::from_bytes(buffer)?)),
+            5 => Ok(SpawnAction::Respawn {
+                previous: Entity::from_bytes(buffer)?,
+                stable_id: StableId::from_bytes(buffer)?,
+            }),
             _ => Err(SerializationError::InvalidPacketType),
         }
     }
@@ -307,6 +342,7 @@ pub(crate) trait ReplicationPeer: Resource {
         + IterComponentUpdateEvent<Self::EventContext>
         + IterEntitySpawnEvent<Self::EventContext>
         + IterEntityDespawnEvent<Self::EventContext>
+        + IterReplicationAppliedEvent<Self::EventContext>
         + ClearEvents;
     /// Type of the context associated with the events emitted/received by this replication peer
     type EventContext: EventContext;
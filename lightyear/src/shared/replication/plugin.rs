@@ -20,6 +20,27 @@ pub struct ReplicationConfig {
     ///
     /// Set to `Duration::default()` to send updates every frame.
     pub send_interval: Duration,
+    /// If set, the last update of high-priority replication groups is sent again when the
+    /// connection is losing packets, to keep the remote state fresh.
+    pub update_redundancy: Option<UpdateRedundancyConfig>,
+}
+
+/// Configuration for sending component updates redundantly on lossy connections
+#[derive(Clone, Copy, Debug, Reflect)]
+pub struct UpdateRedundancyConfig {
+    /// Measured packet loss (between 0.0 and 1.0) above which updates are sent redundantly
+    pub loss_threshold: f32,
+    /// Only the replication groups whose base priority is at least this value get redundant updates
+    pub min_priority: f32,
+}
+
+impl Default for UpdateRedundancyConfig {
+    fn default() -> Self {
+        Self {
+            loss_threshold: 0.05,
+            min_priority: 1.0,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Reflect)]
@@ -47,6 +68,7 @@ impl Default for ReplicationConfig {
         Self {
             send_updates_mode: SendUpdatesMode::SinceLastAck,
             send_interval: Duration::default(),
+            update_redundancy: None,
         }
     }
 }
@@ -252,12 +274,14 @@ pub(crate) mod shared {
         ReplicationConfig, ReplicationGroup, ReplicationTarget, ShouldBePredicted, TargetEntity,
     };
     use crate::shared::replication::authority::{AuthorityPeer, HasAuthority};
+    use crate::shared::replication::baseline::{Baseline, StaticBaseline};
     use crate::shared::replication::components::{
         Controlled, Replicating, ReplicationGroupId, ReplicationGroupIdBuilder,
         ShouldBeInterpolated,
     };
     use crate::shared::replication::entity_map::{InterpolatedEntityMap, PredictedEntityMap};
     use crate::shared::replication::network_target::NetworkTarget;
+    use crate::shared::replication::stable::{StableEntityMap, StableId};
     use bevy::prelude::{App, Plugin};
 
     pub(crate) struct SharedPlugin;
@@ -285,7 +309,13 @@ pub(crate) mod shared {
                 .register_type::<PredictedEntityMap>()
                 .register_type::<HasAuthority>()
                 .register_type::<AuthorityPeer>()
-                .register_type::<InterpolatedEntityMap>();
+                .register_type::<InterpolatedEntityMap>()
+                .register_type::<StableId>()
+                .register_type::<StableEntityMap>()
+                .register_type::<StaticBaseline>()
+                .register_type::<Baseline>();
+            // RESOURCES
+            app.init_resource::<StableEntityMap>();
         }
     }
 }
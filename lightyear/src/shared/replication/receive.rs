@@ -1,21 +1,26 @@
 //! General struct handling replication
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use super::entity_map::RemoteEntityMap;
 use super::{EntityActionsMessage, EntityUpdatesMessage, SpawnAction};
 use crate::packet::message::MessageId;
 use crate::prelude::client::Confirmed;
 use crate::prelude::{ClientConnectionManager, ClientId, ServerConnectionManager, Tick};
-use crate::protocol::component::ComponentRegistry;
+use crate::protocol::component::{ComponentNetId, ComponentRegistry};
+use crate::protocol::version::RemoteVersions;
 use crate::serialize::reader::Reader;
+use crate::serialize::ToBytes;
 use crate::shared::events::connection::ConnectionEvents;
 use crate::shared::replication::authority::{AuthorityPeer, HasAuthority};
 use crate::shared::replication::components::{InitialReplicated, Replicated, ReplicationGroupId};
+use crate::shared::replication::stable::StableEntityMap;
 #[cfg(test)]
 use crate::utils::captures::Captures;
 use bevy::ecs::entity::EntityHash;
 use bevy::prelude::{DespawnRecursiveExt, Entity, EntityWorldMut, World};
 use bevy::utils::HashSet;
+use bytes::Bytes;
 use tracing::{debug, error, info, trace, warn};
 #[cfg(feature = "trace")]
 use tracing::{instrument, Level};
@@ -38,6 +43,9 @@ pub struct ReplicationReceiver {
     // BOTH
     /// Buffer to so that we have an ordered receiver per group
     pub(crate) group_channels: EntityHashMap<ReplicationGroupId, GroupChannel>,
+
+    /// Wire format versions of the remote peer, once they have been received
+    pub(crate) remote_versions: Option<Arc<RemoteVersions>>,
 }
 
 /// Get `ConnectionEvents` depending on whether we receive from a client or a server
@@ -69,6 +77,7 @@ impl ReplicationReceiver {
             local_entity_to_group: Default::default(),
             // BOTH
             group_channels: Default::default(),
+            remote_versions: None,
         }
     }
 
@@ -320,7 +329,8 @@ impl ReplicationReceiver {
             for component in actions.insert {
                 // TODO: we allocate a new vector for each component but we should
                 //  be able to re-use the same reader
-                let mut reader = Reader::from(component);
+                let mut reader =
+                    Reader::from(component).with_versions(self.remote_versions.clone());
                 let _ = component_registry
                     .raw_write(
                         &mut reader,
@@ -354,7 +364,8 @@ impl ReplicationReceiver {
             debug!(remote_entity = ?entity, "Received UpdateComponent");
             for component in actions.updates {
                 // TODO: re-use buffers via pool?
-                let mut reader = Reader::from(component);
+                let mut reader =
+                    Reader::from(component).with_versions(self.remote_versions.clone());
                 let _ = component_registry
                     .raw_write(
                         &mut reader,
@@ -401,7 +412,8 @@ impl ReplicationReceiver {
                 continue;
             };
             for component in components {
-                let mut reader = Reader::from(component);
+                let mut reader =
+                    Reader::from(component).with_versions(self.remote_versions.clone());
                 let _ = component_registry
                     .raw_write(
                         &mut reader,
@@ -497,6 +509,7 @@ impl ReplicationReceiver {
         // });
 
         trace!(?current_tick, ?self.group_channels, "applying replication actions messages");
+        let mut respawns = vec![];
         self.group_channels
             .iter_mut()
             .for_each(|(group_id, channel)| {
@@ -533,9 +546,24 @@ impl ReplicationReceiver {
                     message,
                     &mut self.remote_entity_map,
                     &mut self.local_entity_to_group,
+                    &mut respawns,
+                    self.remote_versions.as_ref(),
                     events,
                 );
             });
+        // respawned entities that changed group are not part of their previous group anymore
+        for respawn in respawns {
+            let Some(channel) = self.group_channels.get_mut(&respawn.previous_group) else {
+                continue;
+            };
+            channel.local_entities.remove(&respawn.local_entity);
+            // the previous group only contained the previous entity, it won't be used anymore
+            if respawn.previous_group.0 == respawn.previous.to_bits()
+                && channel.local_entities.is_empty()
+            {
+                self.group_channels.remove(&respawn.previous_group);
+            }
+        }
 
         trace!(?self.group_channels, "applying replication updates messages");
         self.group_channels
@@ -568,12 +596,22 @@ impl ReplicationReceiver {
                         message,
                         events,
                         &mut self.remote_entity_map,
+                        self.remote_versions.as_ref(),
                     );
                 }
             })
     }
 }
 
+/// A local entity that was reset in place for a respawned entity of another replication group
+#[derive(Debug)]
+pub(crate) struct Respawn {
+    local_entity: Entity,
+    /// Remote entity that was replaced by the respawned entity
+    previous: Entity,
+    previous_group: ReplicationGroupId,
+}
+
 /// Channel to keep track of receiving/sending replication messages for a given Group
 #[derive(Debug)]
 pub struct GroupChannel {
@@ -788,7 +826,39 @@ impl GroupChannel {
         }
     }
 
+    /// Remove the replicated components of a respawned entity that won't be inserted again,
+    /// so that the entity ends up in the same state as a freshly spawned one
+    fn reset_components(
+        component_registry: &ComponentRegistry,
+        entity_mut: &mut EntityWorldMut,
+        inserts: &[Bytes],
+        events: &mut ConnectionEvents,
+    ) {
+        let inserted: HashSet<ComponentNetId> = inserts
+            .iter()
+            .filter_map(|data| ComponentNetId::from_bytes(&mut Reader::from(data.clone())).ok())
+            .collect();
+        let removed: Vec<ComponentNetId> = component_registry
+            .replication_map
+            .iter()
+            .filter(|(_, metadata)| {
+                metadata.remove.is_some() && entity_mut.contains_id(metadata.component_id)
+            })
+            .filter_map(|(kind, _)| component_registry.kind_map.net_id(kind).copied())
+            .filter(|net_id| !inserted.contains(net_id))
+            .collect();
+        for net_id in removed {
+            events.push_remove_component(entity_mut.id(), net_id, Tick(0));
+            component_registry.raw_remove(net_id, entity_mut);
+        }
+    }
+
     /// Apply actions for channel
+    ///
+    /// The actions of the message are applied in this order:
+    /// 1. the spawns of all the entities
+    /// 2. for each entity: the component inserts, then the component updates, then the component removals
+    /// 3. the despawns of all the entities
     pub(crate) fn apply_actions_message(
         &mut self,
         world: &mut World,
@@ -798,6 +868,8 @@ impl GroupChannel {
         message: EntityActionsMessage,
         remote_entity_map: &mut RemoteEntityMap,
         local_entity_to_group: &mut EntityHashMap<Entity, ReplicationGroupId>,
+        respawns: &mut Vec<Respawn>,
+        remote_versions: Option<&Arc<RemoteVersions>>,
         events: &mut ConnectionEvents,
     ) {
         let group_id = message.group_id;
@@ -869,27 +941,125 @@ impl GroupChannel {
                     // update the entity mapping
                     remote_entity_map.insert(*remote_entity, local_entity);
                 }
+                SpawnAction::Stable(stable_id) => {
+                    // reuse the entity that was registered for this stable id, if it still exists
+                    let registered = world
+                        .get_resource::<StableEntityMap>()
+                        .and_then(|map| map.get(stable_id))
+                        .filter(|entity| world.get_entity(*entity).is_some());
+                    let local_entity = match registered {
+                        Some(local_entity) => {
+                            world
+                                .entity_mut(local_entity)
+                                .insert(Replicated { from: remote });
+                            debug!(
+                                ?remote_entity,
+                                ?stable_id,
+                                ?local_entity,
+                                "Received spawn for a registered stable entity"
+                            );
+                            local_entity
+                        }
+                        None => {
+                            let local_entity = world
+                                .spawn((
+                                    Replicated { from: remote },
+                                    InitialReplicated { from: remote },
+                                ))
+                                .id();
+                            if let Some(mut map) = world.get_resource_mut::<StableEntityMap>() {
+                                map.register(stable_id, local_entity);
+                            }
+                            debug!(?remote_entity, ?stable_id, "Received stable entity spawn");
+                            events.push_spawn(local_entity);
+                            local_entity
+                        }
+                    };
+                    if let Some(client) = remote {
+                        world
+                            .entity_mut(local_entity)
+                            .insert(AuthorityPeer::Client(client));
+                    }
+                    self.local_entities.insert(local_entity);
+                    local_entity_to_group.insert(local_entity, group_id);
+                    remote_entity_map.insert(*remote_entity, local_entity);
+                }
+                SpawnAction::Respawn {
+                    previous,
+                    stable_id,
+                } => {
+                    // the previous entity is replaced by the new one, but we keep the local entity
+                    let previous_local = remote_entity_map.remove_by_remote(previous);
+                    let existing = previous_local
+                        .or_else(|| {
+                            world
+                                .get_resource::<StableEntityMap>()
+                                .and_then(|map| map.get(stable_id))
+                        })
+                        .filter(|entity| world.get_entity(*entity).is_some());
+                    let local_entity = match existing {
+                        Some(local_entity) => {
+                            let mut entity_mut = world.entity_mut(local_entity);
+                            entity_mut.insert(Replicated { from: remote });
+                            Self::reset_components(
+                                component_registry,
+                                &mut entity_mut,
+                                &actions.insert,
+                                events,
+                            );
+                            debug!(
+                                ?remote_entity,
+                                ?previous,
+                                ?stable_id,
+                                ?local_entity,
+                                "Received respawn for a stable entity"
+                            );
+                            local_entity
+                        }
+                        None => {
+                            let local_entity = world
+                                .spawn((
+                                    Replicated { from: remote },
+                                    InitialReplicated { from: remote },
+                                ))
+                                .id();
+                            debug!(?remote_entity, ?stable_id, "Received stable entity spawn");
+                            events.push_spawn(local_entity);
+                            local_entity
+                        }
+                    };
+                    if let Some(mut map) = world.get_resource_mut::<StableEntityMap>() {
+                        map.register(stable_id, local_entity);
+                    }
+                    if let Some(client) = remote {
+                        world
+                            .entity_mut(local_entity)
+                            .insert(AuthorityPeer::Client(client));
+                    }
+                    self.local_entities.insert(local_entity);
+                    if let Some(previous_group) = local_entity_to_group
+                        .insert(local_entity, group_id)
+                        .filter(|previous_group| *previous_group != group_id)
+                    {
+                        respawns.push(Respawn {
+                            local_entity,
+                            previous,
+                            previous_group,
+                        });
+                    }
+                    remote_entity_map.insert(*remote_entity, local_entity);
+                }
                 _ => {}
             }
         }
 
+        // despawns are applied last, so that the components inserted or updated by this message can
+        // still refer to the entities that are despawned
+        let mut despawns = Vec::new();
         for (entity, actions) in message.actions.into_iter() {
             debug!(remote_entity = ?entity, "Received entity actions");
-
-            // despawn
             if actions.spawn == SpawnAction::Despawn {
-                debug!(remote_entity = ?entity, "Received entity despawn");
-                if let Some(local_entity) = remote_entity_map.remove_by_remote(entity) {
-                    self.local_entities.remove(&local_entity);
-                    // TODO: we despawn all children as well right now, but that might not be what we want?
-                    if let Some(entity_mut) = world.get_entity_mut(local_entity) {
-                        entity_mut.despawn_recursive();
-                    }
-                    events.push_despawn(local_entity);
-                    local_entity_to_group.remove(&local_entity);
-                } else {
-                    error!("Received despawn for an entity that does not exist")
-                }
+                despawns.push(entity);
                 continue;
             }
 
@@ -912,7 +1082,7 @@ impl GroupChannel {
             debug!(remote_entity = ?entity, "Received InsertComponent");
             for component in actions.insert {
                 // TODO: reuse a single reader that reads through the entire message
-                let mut reader = Reader::from(component);
+                let mut reader = Reader::from(component).with_versions(remote_versions.cloned());
                 let _ = component_registry
                     .raw_write(
                         &mut reader,
@@ -935,17 +1105,10 @@ impl GroupChannel {
                 //  to know for which client we should do the pre-prediction
             }
 
-            // removals
-            trace!(remote_entity = ?entity, ?actions.remove, "Received RemoveComponent");
-            for kind in actions.remove {
-                events.push_remove_component(local_entity_mut.id(), kind, Tick(0));
-                component_registry.raw_remove(kind, &mut local_entity_mut);
-            }
-
             // updates
             debug!(remote_entity = ?entity, "Received UpdateComponent");
             for component in actions.updates {
-                let mut reader = Reader::from(component);
+                let mut reader = Reader::from(component).with_versions(remote_versions.cloned());
                 let _ = component_registry
                     .raw_write(
                         &mut reader,
@@ -958,8 +1121,32 @@ impl GroupChannel {
                         error!("could not write the component to the entity: {:?}", e)
                     });
             }
+
+            // removals
+            trace!(remote_entity = ?entity, ?actions.remove, "Received RemoveComponent");
+            for kind in actions.remove {
+                events.push_remove_component(local_entity_mut.id(), kind, Tick(0));
+                component_registry.raw_remove(kind, &mut local_entity_mut);
+            }
         }
 
+        // despawns
+        for entity in despawns {
+            debug!(remote_entity = ?entity, "Received entity despawn");
+            if let Some(local_entity) = remote_entity_map.remove_by_remote(entity) {
+                self.local_entities.remove(&local_entity);
+                // TODO: we despawn all children as well right now, but that might not be what we want?
+                if let Some(entity_mut) = world.get_entity_mut(local_entity) {
+                    entity_mut.despawn_recursive();
+                }
+                events.push_despawn(local_entity);
+                local_entity_to_group.remove(&local_entity);
+            } else {
+                error!("Received despawn for an entity that does not exist")
+            }
+        }
+
+        events.push_applied_tick(remote_tick);
         self.update_confirmed_tick(world, group_id, remote_tick, remote_entity_map);
     }
 
@@ -990,6 +1177,7 @@ impl GroupChannel {
         message: EntityUpdatesMessage,
         events: &mut ConnectionEvents,
         remote_entity_map: &mut RemoteEntityMap,
+        remote_versions: Option<&Arc<RemoteVersions>>,
     ) {
         let group_id = message.group_id;
         debug!(?remote_tick, ?message, "Received replication updates");
@@ -1016,7 +1204,7 @@ impl GroupChannel {
                 continue;
             }
             for component in components {
-                let mut reader = Reader::from(component);
+                let mut reader = Reader::from(component).with_versions(remote_versions.cloned());
                 let _ = component_registry
                     .raw_write(
                         &mut reader,
@@ -1030,6 +1218,7 @@ impl GroupChannel {
                     });
             }
         }
+        events.push_applied_tick(remote_tick);
         self.update_confirmed_tick(world, group_id, remote_tick, remote_entity_map);
     }
 
@@ -1413,4 +1602,77 @@ mod tests {
             local_entity
         );
     }
+
+    #[derive(bevy::prelude::Resource, Default)]
+    struct AppliedTicks(Vec<Tick>);
+
+    /// Components inserted in the same tick as a despawn can still refer to the despawned entity,
+    /// and a single event is emitted for the tick
+    #[test]
+    fn test_actions_order() {
+        use crate::prelude::client::{self, ReplicationAppliedEvent};
+        use crate::prelude::server::Replicate;
+        use crate::prelude::ReplicationGroup;
+        use crate::tests::protocol::{ComponentMapEntities, ComponentSyncModeFull};
+        use crate::tests::stepper::BevyStepper;
+        use bevy::prelude::{EventReader, ResMut, Update};
+
+        let mut stepper = BevyStepper::default();
+        stepper.client_app.init_resource::<AppliedTicks>();
+        stepper.client_app.add_systems(
+            Update,
+            |mut events: EventReader<ReplicationAppliedEvent>, mut ticks: ResMut<AppliedTicks>| {
+                ticks.0.extend(events.read().map(|event| event.tick()));
+            },
+        );
+        let replicate = Replicate {
+            group: ReplicationGroup::new_id(1),
+            ..Default::default()
+        };
+        let server_a = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(1.0), replicate.clone()))
+            .id();
+        let server_b = stepper
+            .server_app
+            .world_mut()
+            .spawn((ComponentSyncModeFull(2.0), replicate))
+            .id();
+        stepper.frame_step();
+        stepper.frame_step();
+        let remote_entity_map = |stepper: &BevyStepper, entity: Entity| {
+            stepper
+                .client_app
+                .world()
+                .resource::<client::ConnectionManager>()
+                .replication_receiver
+                .remote_entity_map
+                .get_local(entity)
+        };
+        let client_a = remote_entity_map(&stepper, server_a).unwrap();
+        let client_b = remote_entity_map(&stepper, server_b).unwrap();
+
+        stepper.server_app.world_mut().despawn(server_a);
+        stepper
+            .server_app
+            .world_mut()
+            .entity_mut(server_b)
+            .insert(ComponentMapEntities(server_a));
+        stepper.frame_step();
+        stepper.frame_step();
+
+        assert!(stepper.client_app.world().get_entity(client_a).is_none());
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .get::<ComponentMapEntities>(client_b),
+            Some(&ComponentMapEntities(client_a))
+        );
+        let ticks = &stepper.client_app.world().resource::<AppliedTicks>().0;
+        assert!(!ticks.is_empty());
+        // one event per tick, in order
+        assert!(ticks.windows(2).all(|w| w[0] < w[1]), "{ticks:?}");
+    }
 }
@@ -22,10 +22,12 @@ use crate::prelude::{
 use crate::protocol::component::{ComponentKind, ComponentNetId};
 use crate::serialize::writer::Writer;
 use crate::serialize::{SerializationError, ToBytes};
+use crate::shared::alloc_audit::{allow_allocations, AllocationException};
 use crate::shared::replication::components::ReplicationGroupId;
 use crate::shared::replication::delta::DeltaManager;
 use crate::shared::replication::error::ReplicationError;
 use crate::shared::replication::plugin::{ReplicationConfig, SendUpdatesMode};
+use crate::shared::replication::stable::StableId;
 #[cfg(test)]
 use {
     super::{EntityActionsMessage, EntityUpdatesMessage},
@@ -47,6 +49,8 @@ pub(crate) struct UpdateMessageMetadata {
     bevy_tick: BevyTick,
     /// The tick at which we buffered the message
     tick: Tick,
+    /// Number of entities included in the message
+    num_entities: usize,
 }
 
 #[derive(Debug)]
@@ -60,12 +64,23 @@ pub(crate) struct ReplicationSender {
     /// when we buffered the message. (so that when it's acked, we know we only need to include updates that happened after that tick,
     /// for that replication group)
     pub(crate) updates_message_id_to_group_id: HashMap<MessageId, UpdateMessageMetadata>,
+    /// Number of update messages of each group in `updates_message_id_to_group_id`, i.e. that were sent
+    /// but not acked or lost yet
+    pending_updates_per_group: EntityHashMap<ReplicationGroupId, usize>,
     /// Group channels that have at least 1 replication update or action buffered
     pub group_with_actions: EntityHashSet<ReplicationGroupId>,
     pub group_with_updates: EntityHashSet<ReplicationGroupId>,
     /// Buffer to so that we have an ordered receiver per group
     pub group_channels: EntityHashMap<ReplicationGroupId, GroupChannel>,
 
+    // RESPAWN COALESCING
+    /// Stable id of the entities that were spawned on the remote with a [`StableId`]
+    stable_entities: EntityHashMap<Entity, StableId>,
+    /// Stable entities that were despawned since the last time we sent actions
+    despawned_stable: HashMap<StableId, (Entity, ReplicationGroupId)>,
+    /// Stable entities that were spawned since the last time we sent actions
+    spawned_stable: HashMap<StableId, (Entity, ReplicationGroupId)>,
+
     // PRIORITY
     /// Get notified whenever a message for a given ReplicationGroup was actually sent
     /// (sometimes they might not be sent because of bandwidth constraints)
@@ -75,6 +90,13 @@ pub(crate) struct ReplicationSender {
 
     replication_config: ReplicationConfig,
     bandwidth_cap_enabled: bool,
+
+    // DIAGNOSTICS
+    /// Number of entities included in the replication messages buffered since the last [`take_frame_stats`](Self::take_frame_stats)
+    entities_considered: usize,
+    /// Number of entities included in the replication messages that were actually sent since the last
+    /// [`take_frame_stats`](Self::take_frame_stats)
+    entities_sent: usize,
 }
 
 impl ReplicationSender {
@@ -90,17 +112,35 @@ impl ReplicationSender {
             updates_ack_receiver,
             updates_nack_receiver,
             updates_message_id_to_group_id: Default::default(),
+            pending_updates_per_group: Default::default(),
             group_with_actions: EntityHashSet::default(),
             group_with_updates: EntityHashSet::default(),
             // pending_unique_components: EntityHashMap::default(),
             group_channels: Default::default(),
+            stable_entities: Default::default(),
+            despawned_stable: Default::default(),
+            spawned_stable: Default::default(),
             replication_config,
             // PRIORITY
             message_send_receiver,
             bandwidth_cap_enabled,
+            // DIAGNOSTICS
+            entities_considered: 0,
+            entities_sent: 0,
         }
     }
 
+    /// Return the number of entities that were considered for replication and the number of entities
+    /// that were actually sent since the last call, and reset the counters.
+    ///
+    /// Entity actions are sent reliably, so they always count as sent.
+    pub(crate) fn take_frame_stats(&mut self) -> (usize, usize) {
+        (
+            std::mem::take(&mut self.entities_considered),
+            std::mem::take(&mut self.entities_sent),
+        )
+    }
+
     /// Keep track of the message_id/bevy_tick/tick where a replication-update message has been sent
     /// for a given group
     #[cfg(test)]
@@ -112,12 +152,13 @@ impl ReplicationSender {
         bevy_tick: BevyTick,
         tick: Tick,
     ) {
-        self.updates_message_id_to_group_id.insert(
+        self.track_update_message(
             message_id,
             UpdateMessageMetadata {
                 group_id,
                 bevy_tick,
                 tick,
+                num_entities: 0,
             },
         );
         // If we don't have a bandwidth cap, buffering a message is equivalent to sending it
@@ -129,6 +170,17 @@ impl ReplicationSender {
         }
     }
 
+    /// If enabled, only update the `send_tick` once the messages are actually sent, because some messages might be
+    /// held back by a bandwidth quota
+    pub(crate) fn set_bandwidth_cap_enabled(&mut self, enabled: bool) {
+        self.bandwidth_cap_enabled = enabled;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_bandwidth_cap_enabled(&self) -> bool {
+        self.bandwidth_cap_enabled
+    }
+
     /// Get the `send_tick` for a given group.
     /// We will send all updates that happened after this bevy tick.
     pub(crate) fn get_send_tick(&self, group_id: ReplicationGroupId) -> Option<BevyTick> {
@@ -150,7 +202,7 @@ impl ReplicationSender {
                 group_id,
                 bevy_tick,
                 ..
-            }) = self.updates_message_id_to_group_id.remove(&message_id)
+            }) = self.untrack_update_message(message_id)
             {
                 if let SendUpdatesMode::SinceLastSend = self.replication_config.send_updates_mode {
                     if let Some(channel) = self.group_channels.get_mut(&group_id) {
@@ -198,9 +250,11 @@ impl ReplicationSender {
             if let Some(UpdateMessageMetadata {
                 group_id,
                 bevy_tick,
+                num_entities,
                 ..
             }) = self.updates_message_id_to_group_id.get(&message_id)
             {
+                self.entities_sent += num_entities;
                 if let Some(channel) = self.group_channels.get_mut(group_id) {
                     // TODO: should we also reset the priority for replication-action messages?
                     // reset the priority
@@ -237,9 +291,18 @@ impl ReplicationSender {
                 group_id,
                 bevy_tick,
                 tick,
-            }) = self.updates_message_id_to_group_id.remove(&message_id)
+                ..
+            }) = self.untrack_update_message(message_id)
             {
                 if let Some(channel) = self.group_channels.get_mut(&group_id) {
+                    // no need to send the update again
+                    if channel
+                        .last_sent_updates
+                        .as_ref()
+                        .is_some_and(|last| last.message_id == message_id)
+                    {
+                        channel.last_sent_updates = None;
+                    }
                     // update the ack tick for the channel
                     debug!(?group_id, ?bevy_tick, ?tick, "Update channel ack_tick");
                     channel.ack_bevy_tick = Some(bevy_tick);
@@ -256,6 +319,28 @@ impl ReplicationSender {
         }
     }
 
+    /// Keep track of an update message that was sent, until it is acked or lost
+    fn track_update_message(&mut self, message_id: MessageId, metadata: UpdateMessageMetadata) {
+        track_update_message(
+            &mut self.updates_message_id_to_group_id,
+            &mut self.pending_updates_per_group,
+            message_id,
+            metadata,
+        );
+    }
+
+    /// Stop tracking an update message that was acked or lost
+    fn untrack_update_message(&mut self, message_id: MessageId) -> Option<UpdateMessageMetadata> {
+        let metadata = self.updates_message_id_to_group_id.remove(&message_id)?;
+        decrement_pending_updates(&mut self.pending_updates_per_group, metadata.group_id);
+        Some(metadata)
+    }
+
+    /// Returns true if some update messages for the group were sent but haven't been acked or lost yet
+    pub(crate) fn has_pending_updates(&self, group_id: ReplicationGroupId) -> bool {
+        self.pending_updates_per_group.contains_key(&group_id)
+    }
+
     /// Do some internal bookkeeping:
     /// - handle tick wrapping
     pub(crate) fn cleanup(&mut self, tick: Tick) {
@@ -334,8 +419,33 @@ impl ReplicationSender {
             .spawn = SpawnAction::Reuse(remote_entity);
     }
 
+    /// Host wants to start replicating an entity that the remote might already know about through its [`StableId`].
+    /// The remote will reuse the entity registered with that id, or spawn a new one.
+    // #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
+    pub(crate) fn prepare_entity_spawn_stable(
+        &mut self,
+        local_entity: Entity,
+        group_id: ReplicationGroupId,
+        stable_id: StableId,
+    ) {
+        self.group_with_actions.insert(group_id);
+        self.group_channels
+            .entry(group_id)
+            .or_default()
+            .pending_actions
+            .entry(local_entity)
+            .or_default()
+            .spawn = SpawnAction::Stable(stable_id);
+        self.stable_entities.insert(local_entity, stable_id);
+        self.spawned_stable
+            .insert(stable_id, (local_entity, group_id));
+    }
+
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub(crate) fn prepare_entity_despawn(&mut self, entity: Entity, group_id: ReplicationGroupId) {
+        if let Some(stable_id) = self.stable_entities.remove(&entity) {
+            self.despawned_stable.insert(stable_id, (entity, group_id));
+        }
         self.group_with_actions.insert(group_id);
         self.group_channels
             .entry(group_id)
@@ -392,14 +502,16 @@ impl ReplicationSender {
         group_id: ReplicationGroupId,
         raw_data: Bytes,
     ) {
-        self.group_with_updates.insert(group_id);
-        self.group_channels
-            .entry(group_id)
-            .or_default()
-            .pending_updates
-            .entry(entity)
-            .or_default()
-            .push(raw_data);
+        allow_allocations(AllocationException::ReplicationBuffers, || {
+            self.group_with_updates.insert(group_id);
+            self.group_channels
+                .entry(group_id)
+                .or_default()
+                .pending_updates
+                .entry(entity)
+                .or_default()
+                .push(raw_data);
+        })
     }
 
     /// Create a component update.
@@ -480,6 +592,7 @@ impl ReplicationSender {
         bevy_tick: BevyTick,
     ) -> Vec<(EntityActionsMessage, f32)> {
         // ) -> impl Iterator<Item = (EntityActionsMessage, f32)> + Captures<&()> {
+        self.coalesce_respawns();
         self.group_with_actions
             .drain()
             .map(|group_id| {
@@ -548,16 +661,67 @@ impl ReplicationSender {
         });
     }
 
+    /// If a stable entity was despawned and another entity with the same [`StableId`] was spawned
+    /// since the last time we sent actions (for example when entities are pooled), we don't send
+    /// the despawn and instead ask the remote to reset its existing entity in place.
+    ///
+    /// This avoids the remote despawning and spawning an entity for what is the same object.
+    fn coalesce_respawns(&mut self) {
+        let spawned_stable = std::mem::take(&mut self.spawned_stable);
+        for (stable_id, (previous, previous_group)) in self.despawned_stable.drain() {
+            let Some((entity, group_id)) = spawned_stable.get(&stable_id).copied() else {
+                continue;
+            };
+            // the new entity must still be spawned with this stable id in the pending actions
+            let Some(actions) = self
+                .group_channels
+                .get_mut(&group_id)
+                .and_then(|channel| channel.pending_actions.get_mut(&entity))
+                .filter(|actions| actions.spawn == SpawnAction::Stable(stable_id))
+            else {
+                continue;
+            };
+            actions.spawn = SpawnAction::Respawn {
+                previous,
+                stable_id,
+            };
+            // remove the despawn of the previous entity
+            let Some(channel) = self.group_channels.get_mut(&previous_group) else {
+                continue;
+            };
+            channel.pending_actions.remove(&previous);
+            if previous_group != group_id && previous_group.0 == previous.to_bits() {
+                // the previous group only contained the previous entity, it won't be used anymore
+                self.group_channels.remove(&previous_group);
+                self.group_with_actions.remove(&previous_group);
+                self.group_with_updates.remove(&previous_group);
+            } else if channel.pending_actions.is_empty() {
+                self.group_with_actions.remove(&previous_group);
+            }
+            trace!(
+                ?previous,
+                ?entity,
+                ?stable_id,
+                "Coalesced the despawn and respawn of a stable entity"
+            );
+        }
+    }
+
     /// Prepare the [`EntityActionsMessage`](super::EntityActionsMessage) messages to send.
+    ///
+    /// If `updates_skipped` is true, the component updates of this frame were not collected, so the send tick
+    /// of the groups is not advanced: the skipped changes are still sent with the next updates.
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub(crate) fn send_actions_messages(
         &mut self,
         tick: Tick,
         bevy_tick: BevyTick,
+        updates_skipped: bool,
         // TODO: this is useful if we write everything in the same buffer?
         writer: &mut Writer,
         message_manager: &mut MessageManager,
     ) -> Result<(), PacketError> {
+        self.coalesce_respawns();
         self.group_with_actions.drain().try_for_each(|group_id| {
             // SAFETY: we know that the group_channel exists since group_with_actions contains the group_id
             let channel = self.group_channels.get_mut(&group_id).unwrap();
@@ -581,7 +745,9 @@ impl ReplicationSender {
             // when an entity is first spawned the send_tick is still None)
             // This is ok to do even if we don't get an actual send notification because EntityActions messages are
             // guaranteed to be sent at some point. (since the actions channel is reliable)
-            channel.send_tick = Some(bevy_tick);
+            if !updates_skipped {
+                channel.send_tick = Some(bevy_tick);
+            }
             //  We can consider that we received an ack for the current tick because the message is sent reliably,
             //  so we know that we should eventually receive an ack.
             //  Updates after this insert only get read if the insert was received, so this doesn't introduce any bad behaviour.
@@ -595,6 +761,8 @@ impl ReplicationSender {
             let message_id = channel.actions_next_send_message_id;
             channel.actions_next_send_message_id += 1;
             channel.last_action_tick = Some(tick);
+            self.entities_considered += actions.len();
+            self.entities_sent += actions.len();
             // we use SendEntityActionsMessage so that we don't have to convert the hashmap into a vec
             let message = SendEntityActionsMessage {
                 sequence_id: message_id,
@@ -658,6 +826,57 @@ impl ReplicationSender {
         // TODO: also return for each message a list of the components that have delta-compression data?
     }
 
+    /// On lossy connections, send again the last update message of the high-priority groups
+    /// that haven't been acked yet and don't have any new update to send.
+    ///
+    /// Since the group didn't change since then, the last update is still the latest state.
+    /// Each update message is sent again at most once.
+    fn send_redundant_updates(
+        &mut self,
+        writer: &mut Writer,
+        message_manager: &mut MessageManager,
+    ) -> Result<(), PacketError> {
+        let Some(config) = self.replication_config.update_redundancy else {
+            return Ok(());
+        };
+        if message_manager.packet_loss() < config.loss_threshold {
+            return Ok(());
+        }
+        for (group_id, channel) in self.group_channels.iter_mut() {
+            if channel.base_priority < config.min_priority
+                || self.group_with_updates.contains(group_id)
+            {
+                continue;
+            }
+            let Some(last) = channel.last_sent_updates.take() else {
+                continue;
+            };
+            let message = SendEntityUpdatesMessage {
+                group_id: *group_id,
+                last_action_tick: channel.last_action_tick,
+                updates: last.updates,
+            };
+            message.to_bytes(writer)?;
+            let message_bytes = writer.split();
+            let message_id = message_manager
+                .buffer_send_with_priority(
+                    message_bytes,
+                    ChannelKind::of::<EntityUpdatesChannel>(),
+                    channel.accumulated_priority,
+                )?
+                .expect("The entity updates channels should always return a message_id");
+            trace!(?group_id, ?message_id, "Send redundant replication update");
+            // an ack for the copy acknowledges the original update
+            track_update_message(
+                &mut self.updates_message_id_to_group_id,
+                &mut self.pending_updates_per_group,
+                message_id,
+                last.metadata,
+            );
+        }
+        Ok(())
+    }
+
     /// Buffer the [`EntityUpdatesMessage`](super::EntityUpdatesMessage) to send in the [`MessageManager`]
     #[cfg_attr(feature = "trace", instrument(level = Level::INFO, skip_all))]
     pub(crate) fn send_updates_messages(
@@ -667,10 +886,18 @@ impl ReplicationSender {
         writer: &mut Writer,
         message_manager: &mut MessageManager,
     ) -> Result<(), PacketError> {
+        self.send_redundant_updates(writer, message_manager)?;
+        let keep_last_sent = self.replication_config.update_redundancy.is_some();
         self.group_with_updates.drain().try_for_each(|group_id| {
             let channel = self.group_channels.get_mut(&group_id).unwrap();
             let updates = std::mem::take(&mut channel.pending_updates);
             trace!(?group_id, "pending updates: {:?}", updates);
+            let num_entities = updates.len();
+            self.entities_considered += num_entities;
+            // If we don't have a bandwidth cap, buffering a message is equivalent to sending it
+            if !self.bandwidth_cap_enabled {
+                self.entities_sent += num_entities;
+            }
             let priority = channel.accumulated_priority;
             let message = SendEntityUpdatesMessage {
                 group_id,
@@ -703,12 +930,15 @@ impl ReplicationSender {
                 ?tick,
                 "Send replication update"
             );
-            self.updates_message_id_to_group_id.insert(
+            track_update_message(
+                &mut self.updates_message_id_to_group_id,
+                &mut self.pending_updates_per_group,
                 message_id,
                 UpdateMessageMetadata {
                     group_id,
                     bevy_tick,
                     tick,
+                    num_entities,
                 },
             );
             // If we don't have a bandwidth cap, buffering a message is equivalent to sending it
@@ -718,6 +948,18 @@ impl ReplicationSender {
                 channel.send_tick = Some(bevy_tick);
             }
 
+            // keep the update around in case we need to send it again
+            channel.last_sent_updates = keep_last_sent.then(|| LastSentUpdates {
+                message_id,
+                updates: message.updates.clone(),
+                metadata: UpdateMessageMetadata {
+                    group_id,
+                    bevy_tick,
+                    tick,
+                    num_entities,
+                },
+            });
+
             // restore the hashmap that we took out, so that we can reuse the allocated memory
             channel.pending_updates = message.updates;
             channel.pending_updates.clear();
@@ -727,6 +969,40 @@ impl ReplicationSender {
     }
 }
 
+/// Keep track of the message_id -> group mapping of an update message, so we can handle receiving an ACK for it later
+fn track_update_message(
+    updates_message_id_to_group_id: &mut HashMap<MessageId, UpdateMessageMetadata>,
+    pending_updates_per_group: &mut EntityHashMap<ReplicationGroupId, usize>,
+    message_id: MessageId,
+    metadata: UpdateMessageMetadata,
+) {
+    *pending_updates_per_group
+        .entry(metadata.group_id)
+        .or_default() += 1;
+    if let Some(previous) = updates_message_id_to_group_id.insert(message_id, metadata) {
+        decrement_pending_updates(pending_updates_per_group, previous.group_id);
+    }
+}
+
+fn decrement_pending_updates(
+    pending_updates_per_group: &mut EntityHashMap<ReplicationGroupId, usize>,
+    group_id: ReplicationGroupId,
+) {
+    if let Some(count) = pending_updates_per_group.get_mut(&group_id) {
+        *count -= 1;
+        if *count == 0 {
+            pending_updates_per_group.remove(&group_id);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LastSentUpdates {
+    message_id: MessageId,
+    updates: EntityHashMap<Entity, Vec<Bytes>>,
+    metadata: UpdateMessageMetadata,
+}
+
 /// Channel to keep track of sending replication messages for a given Group
 #[derive(Debug)]
 pub struct GroupChannel {
@@ -738,6 +1014,9 @@ pub struct GroupChannel {
     pub pending_actions: EntityHashMap<Entity, EntityActions>,
     pub pending_updates: EntityHashMap<Entity, Vec<Bytes>>,
     pub actions_next_send_message_id: MessageId,
+    /// Last update message sent for this group, kept until it's acked so that it can be sent again
+    /// on lossy connections (see [`UpdateRedundancyConfig`](crate::prelude::UpdateRedundancyConfig))
+    last_sent_updates: Option<LastSentUpdates>,
 
     // TODO: maybe also keep track of which Tick this bevy-tick corresponds to? (will enable doing diff-compression)
     /// Bevy Tick when we last sent an update for this group.
@@ -778,6 +1057,7 @@ impl Default for GroupChannel {
             ack_bevy_tick: None,
             ack_tick: None,
             last_action_tick: None,
+            last_sent_updates: None,
             accumulated_priority: 0.0,
             base_priority: 1.0,
         }
@@ -786,13 +1066,19 @@ impl Default for GroupChannel {
 
 #[cfg(test)]
 mod tests {
+    use crate::packet::header::AckBitfieldSize;
+    use crate::packet::mtu::MtuConfig;
+    use crate::packet::priority_manager::PriorityConfig;
     use crate::prelude::server::Replicate;
     use crate::prelude::ClientId;
+    use crate::protocol::channel::ChannelRegistry;
     use crate::server::connection::ConnectionManager;
+    use crate::shared::replication::plugin::UpdateRedundancyConfig;
 
     use crate::tests::protocol::ComponentSyncModeFull;
     use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
     use bevy::prelude::*;
+    use bevy::utils::Duration;
 
     use super::*;
 
@@ -917,6 +1203,38 @@ mod tests {
         assert_eq!(group_channel.ack_tick, Some(server_tick - 1));
     }
 
+    /// The groups with update messages that were neither acked nor lost are tracked
+    #[test]
+    fn test_has_pending_updates() {
+        let component_registry = ComponentRegistry::default();
+        let mut delta_manager = DeltaManager::default();
+        let (tx_ack, rx_ack) = crossbeam_channel::unbounded();
+        let (tx_nack, rx_nack) = crossbeam_channel::unbounded();
+        let (_, rx_send) = crossbeam_channel::unbounded();
+        let mut sender = ReplicationSender::new(
+            rx_ack,
+            rx_nack,
+            rx_send,
+            ReplicationConfig::default(),
+            false,
+        );
+        let group = ReplicationGroupId(0);
+        sender.group_channels.insert(group, GroupChannel::default());
+
+        sender.buffer_replication_update_message(group, MessageId(0), BevyTick::new(0), Tick(0));
+        sender.buffer_replication_update_message(group, MessageId(1), BevyTick::new(1), Tick(1));
+        assert!(sender.has_pending_updates(group));
+        assert!(!sender.has_pending_updates(ReplicationGroupId(1)));
+
+        tx_ack.try_send(MessageId(0)).unwrap();
+        sender.recv_update_acks(&component_registry, &mut delta_manager);
+        assert!(sender.has_pending_updates(group));
+
+        tx_nack.try_send(MessageId(1)).unwrap();
+        sender.update(BevyTick::new(2));
+        assert!(!sender.has_pending_updates(group));
+    }
+
     #[test]
     fn test_send_tick_no_priority() {
         // create fake channels for receiving updates about acks and sends
@@ -958,7 +1276,8 @@ mod tests {
             Some(&UpdateMessageMetadata {
                 group_id: group_1,
                 bevy_tick: bevy_tick_1,
-                tick: tick_1
+                tick: tick_1,
+                num_entities: 0,
             })
         );
         assert_eq!(group.send_tick, Some(bevy_tick_1));
@@ -972,7 +1291,8 @@ mod tests {
             Some(&UpdateMessageMetadata {
                 group_id: group_1,
                 bevy_tick: bevy_tick_2,
-                tick: tick_2
+                tick: tick_2,
+                num_entities: 0,
             })
         );
         assert_eq!(group.send_tick, Some(bevy_tick_2));
@@ -996,7 +1316,8 @@ mod tests {
             Some(&UpdateMessageMetadata {
                 group_id: group_1,
                 bevy_tick: bevy_tick_3,
-                tick: tick_3
+                tick: tick_3,
+                num_entities: 0,
             })
         );
         assert_eq!(group.send_tick, Some(bevy_tick_3));
@@ -1022,6 +1343,93 @@ mod tests {
         assert_eq!(group.ack_bevy_tick, Some(bevy_tick_2));
     }
 
+    #[test]
+    fn test_redundant_updates() {
+        let component_registry = ComponentRegistry::default();
+        let mut delta_manager = DeltaManager::default();
+        let mut message_manager = MessageManager::new(
+            &ChannelRegistry::new(Duration::default()),
+            1.5,
+            AckBitfieldSize::default(),
+            MtuConfig::default(),
+            PriorityConfig::default(),
+        );
+        let mut writer = Writer::default();
+
+        let (tx_ack, rx_ack) = crossbeam_channel::unbounded();
+        let (_, rx_nack) = crossbeam_channel::unbounded();
+        let (_, rx_send) = crossbeam_channel::unbounded();
+        let mut sender = ReplicationSender::new(
+            rx_ack,
+            rx_nack,
+            rx_send,
+            ReplicationConfig {
+                send_updates_mode: SendUpdatesMode::SinceLastSend,
+                // always consider that the connection is lossy
+                update_redundancy: Some(UpdateRedundancyConfig {
+                    loss_threshold: 0.0,
+                    min_priority: 1.0,
+                }),
+                ..default()
+            },
+            false,
+        );
+        let entity = Entity::from_raw(0);
+        let group_1 = ReplicationGroupId(0);
+        // group with a low priority
+        let group_2 = ReplicationGroupId(1);
+        sender
+            .group_channels
+            .insert(group_1, GroupChannel::default());
+        sender.group_channels.insert(
+            group_2,
+            GroupChannel {
+                base_priority: 0.5,
+                ..default()
+            },
+        );
+
+        sender.prepare_component_update(entity, group_1, vec![0].into());
+        sender.prepare_component_update(entity, group_2, vec![0].into());
+        sender
+            .send_updates_messages(Tick(1), BevyTick::new(1), &mut writer, &mut message_manager)
+            .unwrap();
+        assert_eq!(sender.updates_message_id_to_group_id.len(), 2);
+
+        // the unacked update of the high-priority group is sent again once
+        for _ in 0..2 {
+            sender
+                .send_updates_messages(Tick(2), BevyTick::new(2), &mut writer, &mut message_manager)
+                .unwrap();
+            assert_eq!(sender.updates_message_id_to_group_id.len(), 3);
+        }
+        let copies: Vec<_> = sender
+            .updates_message_id_to_group_id
+            .values()
+            .filter(|metadata| metadata.group_id == group_1)
+            .collect();
+        assert_eq!(copies.len(), 2);
+        assert!(copies.iter().all(|metadata| metadata.tick == Tick(1)));
+
+        // acked updates are not sent again
+        sender.prepare_component_update(entity, group_1, vec![1].into());
+        sender
+            .send_updates_messages(Tick(3), BevyTick::new(3), &mut writer, &mut message_manager)
+            .unwrap();
+        let (message_id, _) = sender
+            .updates_message_id_to_group_id
+            .iter()
+            .find(|(_, metadata)| metadata.tick == Tick(3))
+            .unwrap();
+        tx_ack.try_send(*message_id).unwrap();
+        sender.recv_update_acks(&component_registry, &mut delta_manager);
+        let num_messages = sender.updates_message_id_to_group_id.len();
+        sender
+            .send_updates_messages(Tick(4), BevyTick::new(4), &mut writer, &mut message_manager)
+            .unwrap();
+        assert_eq!(sender.updates_message_id_to_group_id.len(), num_messages);
+    }
+
     #[test]
     fn test_send_tick_priority() {
         // create fake channels for receiving updates about acks and sends
@@ -1053,7 +1461,8 @@ mod tests {
             Some(&UpdateMessageMetadata {
                 group_id: group_1,
                 bevy_tick: bevy_tick_1,
-                tick: tick_1
+                tick: tick_1,
+                num_entities: 0,
             })
         );
         assert_eq!(group.send_tick, None);
@@ -0,0 +1,97 @@
+//! Stable network ids for entities that both peers know about ahead of time.
+//!
+//! Some entities (for example static level geometry) are spawned deterministically on both the server
+//! and the client. Instead of spawning a new entity on the remote when they get replicated, the sender can
+//! tag them with [`TargetEntity::Stable`](crate::prelude::TargetEntity::Stable). The receiver looks up the
+//! [`StableId`] in its [`StableEntityMap`] and applies the replication to the pre-registered entity.
+//!
+//! Because the id does not depend on the [`Entity`] allocated by the sender, the mapping survives
+//! server restarts and reconnections.
+use bevy::prelude::{Entity, Reflect, Resource};
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::serialize::reader::Reader;
+use crate::serialize::varint::{varint_len, VarIntReadExt, VarIntWriteExt};
+use crate::serialize::{SerializationError, ToBytes};
+use byteorder::WriteBytesExt;
+
+/// Identifier of an entity that stays the same across connections and server restarts
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Reflect,
+    Serialize,
+    Deserialize,
+)]
+pub struct StableId(pub u64);
+
+impl ToBytes for StableId {
+    fn len(&self) -> usize {
+        varint_len(self.0)
+    }
+
+    fn to_bytes<T: WriteBytesExt>(&self, buffer: &mut T) -> Result<(), SerializationError> {
+        buffer.write_varint(self.0)?;
+        Ok(())
+    }
+
+    fn from_bytes(buffer: &mut Reader) -> Result<Self, SerializationError>
+    where
+        Self: Sized,
+    {
+        Ok(Self(buffer.read_varint()?))
+    }
+}
+
+/// Table of the local entities that have been registered with a [`StableId`].
+///
+/// When we receive a spawn for a [`StableId`] that is present in this table, the replicated components
+/// are applied to the registered entity instead of spawning a new one. Stable entities that are received
+/// without having been registered are spawned normally and added to the table, so that they can be
+/// reused on the next connection.
+#[derive(Resource, Default, Debug, Reflect)]
+pub struct StableEntityMap {
+    stable_to_local: HashMap<StableId, Entity>,
+}
+
+impl StableEntityMap {
+    /// Register a local entity for the given [`StableId`].
+    ///
+    /// Returns the entity that was previously registered for that id, if any.
+    pub fn register(&mut self, id: StableId, entity: Entity) -> Option<Entity> {
+        self.stable_to_local.insert(id, entity)
+    }
+
+    /// Get the local entity registered for the given [`StableId`]
+    pub fn get(&self, id: StableId) -> Option<Entity> {
+        self.stable_to_local.get(&id).copied()
+    }
+
+    /// Remove the entity registered for the given [`StableId`]
+    pub fn remove(&mut self, id: StableId) -> Option<Entity> {
+        self.stable_to_local.remove(&id)
+    }
+
+    /// Iterate through all the registered stable entities
+    pub fn iter(&self) -> impl Iterator<Item = (StableId, Entity)> + '_ {
+        self.stable_to_local
+            .iter()
+            .map(|(id, entity)| (*id, *entity))
+    }
+
+    pub fn len(&self) -> usize {
+        self.stable_to_local.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stable_to_local.is_empty()
+    }
+}
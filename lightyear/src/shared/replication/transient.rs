@@ -0,0 +1,20 @@
+//! Lightweight replication for short-lived entities (projectiles, particles, etc.)
+//!
+//! Entities with the [`ReplicateTransient`](crate::prelude::server::ReplicateTransient) component are not
+//! replicated through the entity channels. Instead the server sends a single message containing the full state
+//! of the entity and its lifetime when the component is added. The client spawns a local copy of the entity,
+//! simulates it locally, and despawns it once its lifetime is over.
+//!
+//! No updates, removals or despawns are sent for transient entities, and they don't allocate
+//! any per-connection replication state.
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Message sent by the server to spawn a transient entity on the client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct TransientSpawn {
+    /// How long the entity should live on the client
+    pub(crate) lifetime: Duration,
+    /// The serialized components, each prefixed by its net id
+    pub(crate) components: Vec<Vec<u8>>,
+}
@@ -0,0 +1,466 @@
+//! Typed request/response messages (RPC).
+//!
+//! A [`Request`] is a [`Message`] that expects a [`Response`](Request::Response) from the remote peer.
+//! Requests are registered with [`register_request`](crate::prelude::AppMessageExt::register_request), which
+//! registers the request in the given direction and the response in the opposite direction:
+//! ```rust,ignore
+//! #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+//! struct GetScore(ClientId);
+//!
+//! impl Request for GetScore {
+//!     type Response = u32;
+//! }
+//!
+//! app.register_request::<GetScore>(ChannelDirection::ClientToServer);
+//! ```
+//!
+//! The sender gets a [`ResponseHandle`] that identifies the request:
+//! ```rust,ignore
+//! let handle = connection_manager.send_request::<Channel1, _>(GetScore(client_id))?;
+//! ```
+//! The receiver reads the [`RequestEvent`]s and answers them with `send_response`, using the [`RequestId`]
+//! of the event. When the response arrives (or when no response arrived after [`Request::TIMEOUT`]),
+//! a [`ResponseEvent`] with the id of the handle is emitted on the sender.
+//!
+//! The responses are sent on the channel chosen by the receiver: use a reliable channel for both the
+//! requests and the responses, or rely on the timeout to detect the lost messages.
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashMap};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::client::config::ClientConfig;
+use crate::prelude::{client, server, AppMessageExt, ChannelDirection, Message};
+use crate::protocol::message::MessageKind;
+use crate::protocol::EventContext;
+use crate::server::config::ServerConfig;
+use crate::shared::events::components::MessageEvent;
+use crate::shared::replication::ReplicationPeer;
+use crate::shared::sets::InternalMainSet;
+
+/// A [`Message`] that expects a response from the remote peer
+pub trait Request: Message + Serialize + DeserializeOwned {
+    type Response: Message + Serialize + DeserializeOwned;
+
+    /// Duration after which a request that didn't get a response fails with [`RpcError::TimedOut`]
+    const TIMEOUT: Duration = Duration::from_secs(5);
+}
+
+/// Identifies a request, to match it with its response
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(pub u64);
+
+/// Handle to the response of a request, returned when the request is sent.
+///
+/// The [`ResponseEvent`] of the request has the same [`RequestId`].
+#[derive(Debug)]
+pub struct ResponseHandle<Resp> {
+    id: RequestId,
+    _marker: PhantomData<fn() -> Resp>,
+}
+
+impl<Resp> Clone for ResponseHandle<Resp> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Resp> Copy for ResponseHandle<Resp> {}
+
+impl<Resp> ResponseHandle<Resp> {
+    pub(crate) fn new(id: RequestId) -> Self {
+        Self {
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> RequestId {
+        self.id
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RpcError {
+    #[error("no response was received before the timeout of the request")]
+    TimedOut,
+}
+
+/// Bevy [`Event`] emitted on the receiver of a request.
+///
+/// The `context` is the [`ClientId`](crate::prelude::ClientId) of the sender on the server, and `()` on the client.
+#[derive(Event, Debug)]
+pub struct RequestEvent<R: Request, Ctx = ()> {
+    pub id: RequestId,
+    pub request: R,
+    pub context: Ctx,
+}
+
+/// Bevy [`Event`] emitted on the sender of a request when the response is received, or when the request
+/// timed out.
+///
+/// The `context` is the [`ClientId`](crate::prelude::ClientId) of the receiver on the server, and `()` on the client.
+#[derive(Event, Debug)]
+pub struct ResponseEvent<R: Request, Ctx = ()> {
+    pub id: RequestId,
+    pub result: Result<R::Response, RpcError>,
+    pub context: Ctx,
+}
+
+/// Message that carries a request along with its id
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct RpcRequest<R> {
+    pub(crate) id: RequestId,
+    pub(crate) request: R,
+}
+
+/// Message that carries the response to the request with the same id.
+///
+/// It is generic over the request so that several requests can use the same response type.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(bound = "")]
+pub(crate) struct RpcResponse<R: Request> {
+    pub(crate) id: RequestId,
+    pub(crate) response: R::Response,
+}
+
+#[derive(Debug)]
+struct PendingRequest<Ctx> {
+    kind: MessageKind,
+    /// Peer that the request was sent to
+    context: Ctx,
+    timer: Timer,
+}
+
+/// Requests that were sent and are waiting for a response
+#[derive(Debug)]
+pub(crate) struct RequestTracker<Ctx> {
+    next_id: u64,
+    pending: HashMap<RequestId, PendingRequest<Ctx>>,
+}
+
+impl<Ctx> Default for RequestTracker<Ctx> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            pending: HashMap::default(),
+        }
+    }
+}
+
+impl<Ctx: PartialEq> RequestTracker<Ctx> {
+    pub(crate) fn next_id(&mut self) -> RequestId {
+        let id = RequestId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    /// Start waiting for the response to a request that was sent to `context`
+    pub(crate) fn track<R: Request>(&mut self, id: RequestId, context: Ctx) {
+        self.pending.insert(
+            id,
+            PendingRequest {
+                kind: MessageKind::of::<R>(),
+                context,
+                timer: Timer::new(R::TIMEOUT, TimerMode::Once),
+            },
+        );
+    }
+
+    /// Stop waiting for the response to a request. Returns false if the request is unknown, if it timed out,
+    /// or if the response didn't come from the peer that the request was sent to
+    fn complete<R: Request>(&mut self, id: RequestId, context: &Ctx) -> bool {
+        let matches = self.pending.get(&id).is_some_and(|pending| {
+            pending.kind == MessageKind::of::<R>() && pending.context == *context
+        });
+        if matches {
+            self.pending.remove(&id);
+        }
+        matches
+    }
+
+    /// Advance the timers of the requests of type `R`, and remove the ones that timed out
+    fn expire<R: Request>(&mut self, delta: Duration) -> Vec<(RequestId, Ctx)> {
+        let kind = MessageKind::of::<R>();
+        let expired = self
+            .pending
+            .iter_mut()
+            .filter(|(_, pending)| pending.kind == kind)
+            .filter_map(|(id, pending)| pending.timer.tick(delta).finished().then_some(*id))
+            .collect::<Vec<_>>();
+        expired
+            .into_iter()
+            .filter_map(|id| {
+                self.pending
+                    .remove(&id)
+                    .map(|pending| (id, pending.context))
+            })
+            .collect()
+    }
+}
+
+/// Connection managers that can send requests
+pub(crate) trait RequestSend: ReplicationPeer<EventContext: Clone + PartialEq> {
+    fn requests(&mut self) -> &mut RequestTracker<Self::EventContext>;
+}
+
+impl RequestSend for server::ConnectionManager {
+    fn requests(&mut self) -> &mut RequestTracker<Self::EventContext> {
+        &mut self.requests
+    }
+}
+
+impl RequestSend for client::ConnectionManager {
+    fn requests(&mut self) -> &mut RequestTracker<Self::EventContext> {
+        &mut self.requests
+    }
+}
+
+/// Register the messages, events and systems of the request `R`
+pub(crate) fn register_request<R: Request>(app: &mut App, direction: ChannelDirection) {
+    let response_direction = match direction {
+        ChannelDirection::ClientToServer => ChannelDirection::ServerToClient,
+        ChannelDirection::ServerToClient => ChannelDirection::ClientToServer,
+        ChannelDirection::Bidirectional => ChannelDirection::Bidirectional,
+    };
+    app.register_message::<RpcRequest<R>>(direction);
+    app.register_message::<RpcResponse<R>>(response_direction);
+    let is_client = app.world().get_resource::<ClientConfig>().is_some();
+    let is_server = app.world().get_resource::<ServerConfig>().is_some();
+    let client_sends = direction != ChannelDirection::ServerToClient;
+    let server_sends = direction != ChannelDirection::ClientToServer;
+    if is_client && client_sends {
+        add_sender_systems::<R, client::ConnectionManager>(app);
+    }
+    if is_client && server_sends {
+        add_receiver_systems::<R, client::ConnectionManager>(app);
+    }
+    if is_server && server_sends {
+        add_sender_systems::<R, server::ConnectionManager>(app);
+    }
+    if is_server && client_sends {
+        add_receiver_systems::<R, server::ConnectionManager>(app);
+    }
+}
+
+fn add_sender_systems<R: Request, S: RequestSend>(app: &mut App) {
+    app.add_event::<ResponseEvent<R, S::EventContext>>();
+    app.add_systems(
+        PreUpdate,
+        receive_responses::<R, S>.after(InternalMainSet::<S::SetMarker>::EmitEvents),
+    );
+}
+
+fn add_receiver_systems<R: Request, S: ReplicationPeer>(app: &mut App) {
+    app.add_event::<RequestEvent<R, S::EventContext>>();
+    app.add_systems(
+        PreUpdate,
+        receive_requests::<R, S::EventContext>.after(InternalMainSet::<S::SetMarker>::EmitEvents),
+    );
+}
+
+fn receive_requests<R: Request, Ctx: EventContext>(
+    mut messages: ResMut<Events<MessageEvent<RpcRequest<R>, Ctx>>>,
+    mut events: EventWriter<RequestEvent<R, Ctx>>,
+) {
+    events.send_batch(messages.drain().map(|message| RequestEvent {
+        id: message.message.id,
+        request: message.message.request,
+        context: message.context,
+    }));
+}
+
+/// Emit a [`ResponseEvent`] for the responses that were received and for the requests that timed out
+fn receive_responses<R: Request, S: RequestSend>(
+    mut messages: ResMut<Events<MessageEvent<RpcResponse<R>, S::EventContext>>>,
+    mut events: EventWriter<ResponseEvent<R, S::EventContext>>,
+    mut manager: ResMut<S>,
+    time: Res<Time>,
+) {
+    let requests = manager.requests();
+    for message in messages.drain() {
+        let RpcResponse { id, response } = message.message;
+        if !requests.complete::<R>(id, &message.context) {
+            debug!(?id, "ignored the response to an unknown or expired request");
+            continue;
+        }
+        events.send(ResponseEvent {
+            id,
+            result: Ok(response),
+            context: message.context,
+        });
+    }
+    events.send_batch(
+        requests
+            .expire::<R>(time.delta())
+            .into_iter()
+            .map(|(id, context)| ResponseEvent {
+                id,
+                result: Err(RpcError::TimedOut),
+                context,
+            }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::{ClientId, SharedConfig, TickConfig};
+    use crate::tests::protocol::Channel1;
+    use crate::tests::stepper::{BevyStepper, TEST_CLIENT_ID};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Double(u32);
+
+    impl Request for Double {
+        type Response = u32;
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Unanswered;
+
+    impl Request for Unanswered {
+        type Response = ();
+        const TIMEOUT: Duration = Duration::from_millis(50);
+    }
+
+    #[derive(Resource)]
+    struct Responses<R: Request>(Vec<(RequestId, Result<R::Response, RpcError>)>);
+
+    impl<R: Request> Default for Responses<R> {
+        fn default() -> Self {
+            Self(Vec::new())
+        }
+    }
+
+    fn record_responses<R: Request, Ctx: EventContext>(
+        mut events: EventReader<ResponseEvent<R, Ctx>>,
+        mut responses: ResMut<Responses<R>>,
+    ) where
+        R::Response: Clone,
+    {
+        for event in events.read() {
+            responses.0.push((event.id, event.result.clone()));
+        }
+    }
+
+    fn stepper() -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            ClientConfig::default(),
+            frame_duration,
+        );
+        for app in [&mut stepper.client_app, &mut stepper.server_app] {
+            app.register_request::<Double>(ChannelDirection::Bidirectional);
+            app.register_request::<Unanswered>(ChannelDirection::ClientToServer);
+            app.init_resource::<Responses<Double>>();
+            app.init_resource::<Responses<Unanswered>>();
+        }
+        stepper.client_app.add_systems(
+            Update,
+            (
+                |mut requests: EventReader<client::RequestEvent<Double>>,
+                 mut manager: ResMut<client::ConnectionManager>| {
+                    for event in requests.read() {
+                        manager
+                            .send_response::<Channel1, Double>(event.id, event.request.0 * 2)
+                            .unwrap();
+                    }
+                },
+                record_responses::<Double, ()>,
+                record_responses::<Unanswered, ()>,
+            ),
+        );
+        stepper.server_app.add_systems(
+            Update,
+            (
+                |mut requests: EventReader<server::RequestEvent<Double>>,
+                 mut manager: ResMut<server::ConnectionManager>| {
+                    for event in requests.read() {
+                        manager
+                            .send_response::<Channel1, Double>(
+                                event.context,
+                                event.id,
+                                event.request.0 * 2,
+                            )
+                            .unwrap();
+                    }
+                },
+                record_responses::<Double, ClientId>,
+            ),
+        );
+        stepper.init();
+        stepper
+    }
+
+    #[test]
+    fn test_request_response() {
+        let mut stepper = stepper();
+        let client_handle = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ConnectionManager>()
+            .send_request::<Channel1, _>(Double(2))
+            .unwrap();
+        let server_handle = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<server::ConnectionManager>()
+            .send_request::<Channel1, _>(ClientId::Netcode(TEST_CLIENT_ID), Double(5))
+            .unwrap();
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+
+        assert_eq!(
+            stepper.client_app.world().resource::<Responses<Double>>().0,
+            vec![(client_handle.id(), Ok(4))]
+        );
+        assert_eq!(
+            stepper.server_app.world().resource::<Responses<Double>>().0,
+            vec![(server_handle.id(), Ok(10))]
+        );
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let mut stepper = stepper();
+        let handle = stepper
+            .client_app
+            .world_mut()
+            .resource_mut::<client::ConnectionManager>()
+            .send_request::<Channel1, _>(Unanswered)
+            .unwrap();
+        for _ in 0..3 {
+            stepper.frame_step();
+        }
+        assert!(stepper
+            .client_app
+            .world()
+            .resource::<Responses<Unanswered>>()
+            .0
+            .is_empty());
+
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper
+                .client_app
+                .world()
+                .resource::<Responses<Unanswered>>()
+                .0,
+            vec![(handle.id(), Err(RpcError::TimedOut))]
+        );
+    }
+}
@@ -30,6 +30,9 @@ impl MapEntities for EntityMessage {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Reflect)]
+pub struct TracedMessage(pub String);
+
 // Components
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
 pub struct ComponentSyncModeFull(pub f32);
@@ -70,6 +73,10 @@ pub(crate) fn deserialize_component2(
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
 pub struct ComponentSyncModeOnce(pub f32);
 
+/// Component whose value is adapted to the serialization context of each client
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+pub struct ComponentContextTransform(pub f32);
+
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
 pub struct ComponentMapEntities(pub Entity);
 
@@ -195,6 +202,12 @@ pub struct Channel1;
 #[derive(ChannelInternal, Reflect)]
 pub struct Channel2;
 
+#[derive(ChannelInternal, Reflect)]
+pub struct ReliableChannel;
+
+#[derive(ChannelInternal, Reflect)]
+pub struct HistoryChannel;
+
 // Protocol
 
 pub(crate) struct ProtocolPlugin;
@@ -204,6 +217,8 @@ impl Plugin for ProtocolPlugin {
         app.register_message::<StringMessage>(ChannelDirection::Bidirectional);
         app.register_message::<EntityMessage>(ChannelDirection::Bidirectional)
             .add_map_entities();
+        app.register_message::<TracedMessage>(ChannelDirection::Bidirectional)
+            .add_trace_id();
         // inputs
         app.add_plugins(InputPlugin::<MyInput>::default());
         // components
@@ -242,6 +257,9 @@ impl Plugin for ProtocolPlugin {
 
         app.add_rollback::<ComponentRollback>();
 
+        app.register_component::<ComponentContextTransform>(ChannelDirection::ServerToClient)
+            .add_context_transform(|component, context| component.0 += context.flags as f32);
+
         // resources
         app.register_resource::<Resource1>(ChannelDirection::ServerToClient);
         app.register_resource_custom_serde::<Resource2>(
@@ -261,5 +279,14 @@ impl Plugin for ProtocolPlugin {
             mode: ChannelMode::UnorderedUnreliableWithAcks,
             ..default()
         });
+        app.add_channel::<ReliableChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            ..default()
+        });
+        app.add_channel::<HistoryChannel>(ChannelSettings {
+            mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+            replay_on_join: Some(2),
+            ..default()
+        });
     }
 }
@@ -60,17 +60,9 @@ impl BevyStepper {
 
         // Use local channels instead of UDP for testing
         let addr = LOCAL_SOCKET;
-        // channels to receive a message from/to server
-        let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
-        let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
-        let mut client_io = client::IoConfig::from_transport(ClientTransport::LocalChannel {
-            send: to_server_send,
-            recv: from_server_recv,
-        });
-
-        let mut server_io = server::IoConfig::from_transport(ServerTransport::Channels {
-            channels: vec![(addr, to_server_recv, from_server_send)],
-        });
+        let (server_transport, mut client_transports) = ServerTransport::loopback(1);
+        let mut client_io = client::IoConfig::from_transport(client_transports.remove(0));
+        let mut server_io = server::IoConfig::from_transport(server_transport);
 
         let NetConfig::Netcode { io, .. } = client_config.net else {
             panic!("Only Netcode transport is supported in tests");
@@ -1,4 +1,6 @@
 use crate::transport::middleware::compression::CompressionConfig;
+#[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+use crate::transport::middleware::compression::PayloadCaptureConfig;
 use crate::transport::middleware::conditioner::LinkConditionerConfig;
 use bevy::prelude::Reflect;
 
@@ -9,6 +11,9 @@ pub struct SharedIoConfig<T> {
     pub transport: T,
     pub conditioner: Option<LinkConditionerConfig>,
     pub compression: CompressionConfig,
+    /// Capture the payloads sent by the io, to train a compression dictionary
+    #[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+    pub capture: Option<PayloadCaptureConfig>,
 }
 
 impl<T> SharedIoConfig<T> {
@@ -17,6 +22,8 @@ impl<T> SharedIoConfig<T> {
             transport,
             conditioner: None,
             compression: CompressionConfig::default(),
+            #[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+            capture: None,
         }
     }
     pub fn with_conditioner(mut self, conditioner_config: LinkConditionerConfig) -> Self {
@@ -28,4 +35,10 @@ impl<T> SharedIoConfig<T> {
         self.compression = compression_config;
         self
     }
+
+    #[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+    pub fn with_capture(mut self, capture_config: PayloadCaptureConfig) -> Self {
+        self.capture = Some(capture_config);
+        self
+    }
 }
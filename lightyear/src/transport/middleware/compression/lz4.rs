@@ -126,18 +126,15 @@ mod tests {
         let (send, recv) = crossbeam_channel::unbounded();
 
         let config = ClientTransport::LocalChannel { send, recv };
-        let io_config = SharedIoConfig::<ClientTransport> {
-            transport: config,
-            conditioner: None,
-            compression: CompressionConfig::Lz4,
-        };
+        let io_config =
+            SharedIoConfig::from_transport(config).with_compression(CompressionConfig::Lz4);
         let mut io = io_config.connect().unwrap();
         let msg = b"hello world".as_slice();
         // send data
-        io.sender.send(&msg, &LOCAL_SOCKET).unwrap();
+        io.sender.send(msg, &LOCAL_SOCKET).unwrap();
 
         // receive data
         let (data, addr) = io.receiver.recv().unwrap().unwrap();
-        assert_eq!(data.as_ref(), msg);
+        assert_eq!(data, msg);
     }
 }
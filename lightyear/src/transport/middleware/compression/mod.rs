@@ -1,18 +1,175 @@
 use bevy::prelude::Reflect;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+use crate::packet::packet::MAX_MESSAGE_SIZE;
+use crate::transport::error::Result;
+
 #[cfg(feature = "zstd")]
 pub(crate) mod zstd;
+#[cfg(feature = "zstd")]
+pub use zstd::dictionary::ZstdDictionary;
+#[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+pub use zstd::capture::PayloadCaptureConfig;
+#[cfg(all(feature = "zstd", not(target_family = "wasm")))]
+pub use zstd::dictionary::train_dictionary;
 
 #[cfg(feature = "lz4")]
 pub(crate) mod lz4;
 
-#[derive(Clone, Copy, Debug, Default, Reflect, Serialize, Deserialize)]
+/// Compression algorithm to use.
+///
+/// It can be applied to entire packets via the io config, or to the messages of a single
+/// channel via [`ChannelSettings`](crate::prelude::ChannelSettings).
+#[derive(Clone, Debug, Default, PartialEq, Reflect, Serialize, Deserialize)]
 pub enum CompressionConfig {
     #[default]
     None,
     #[cfg(feature = "zstd")]
     Zstd { level: i32 },
+    /// Zstd compression with a dictionary trained on samples of the game's payloads, which compresses
+    /// small payloads much better. Both peers must use the same dictionary.
+    #[cfg(feature = "zstd")]
+    ZstdDictionary {
+        level: i32,
+        dictionary: ZstdDictionary,
+    },
     #[cfg(feature = "lz4")]
     Lz4,
 }
+
+impl CompressionConfig {
+    /// Compress a single message.
+    ///
+    /// The output contains everything needed to decompress it (for example the uncompressed size),
+    /// since a message can be bigger than a packet.
+    pub(crate) fn compress(&self, data: Bytes) -> Result<Bytes> {
+        match self {
+            CompressionConfig::None => Ok(data),
+            #[cfg(feature = "zstd")]
+            CompressionConfig::Zstd { level } => {
+                Ok(::zstd::encode_all(data.as_ref(), *level)?.into())
+            }
+            #[cfg(feature = "zstd")]
+            CompressionConfig::ZstdDictionary { level, dictionary } => {
+                dictionary.compress(&data, *level)
+            }
+            #[cfg(feature = "lz4")]
+            CompressionConfig::Lz4 => Ok(lz4_flex::block::compress_prepend_size(&data).into()),
+        }
+    }
+
+    /// Decompress a single message that was compressed with [`CompressionConfig::compress`]
+    ///
+    /// The payload comes from the remote peer, so the output is limited to the maximum message size:
+    /// otherwise a tiny packet could decompress to gigabytes.
+    pub(crate) fn decompress(&self, data: Bytes) -> Result<Bytes> {
+        match self {
+            CompressionConfig::None => Ok(data),
+            #[cfg(feature = "zstd")]
+            CompressionConfig::Zstd { .. } => {
+                read_bounded(::zstd::stream::read::Decoder::new(data.as_ref())?)
+            }
+            #[cfg(feature = "zstd")]
+            CompressionConfig::ZstdDictionary { dictionary, .. } => dictionary.decompress(&data),
+            #[cfg(feature = "lz4")]
+            CompressionConfig::Lz4 => {
+                let (size, _) = lz4_flex::block::uncompressed_size(&data)?;
+                if size > MAX_MESSAGE_SIZE {
+                    return Err(too_large().into());
+                }
+                Ok(lz4_flex::block::decompress_size_prepended(&data)?.into())
+            }
+        }
+    }
+}
+
+/// Error returned when a message decompresses to more than [`MAX_MESSAGE_SIZE`] bytes
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+fn too_large() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("decompressed message is bigger than {MAX_MESSAGE_SIZE} bytes"),
+    )
+}
+
+/// Read a decompression stream to the end, failing if it produces more than [`MAX_MESSAGE_SIZE`] bytes
+#[cfg(feature = "zstd")]
+pub(crate) fn read_bounded(decoder: impl std::io::Read) -> Result<Bytes> {
+    use std::io::Read;
+
+    let mut decompressed = Vec::new();
+    decoder
+        .take(MAX_MESSAGE_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() > MAX_MESSAGE_SIZE {
+        return Err(too_large().into());
+    }
+    Ok(decompressed.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Messages can be bigger than a packet, check that they can be compressed/decompressed on their own
+    fn check_message_roundtrip(config: CompressionConfig) {
+        let message = Bytes::from(
+            std::iter::repeat(b"some bulky json-like data ".as_slice())
+                .take(200)
+                .flatten()
+                .copied()
+                .collect::<Vec<u8>>(),
+        );
+        let compressed = config.compress(message.clone()).unwrap();
+        assert!(compressed.len() < message.len());
+        assert_eq!(config.decompress(compressed).unwrap(), message);
+    }
+
+    /// A small payload that decompresses to more than the maximum message size must be rejected
+    #[cfg(any(feature = "zstd", feature = "lz4"))]
+    fn check_decompression_bomb(config: CompressionConfig) {
+        let message = Bytes::from(vec![0; MAX_MESSAGE_SIZE + 1]);
+        let compressed = config.compress(message).unwrap();
+        assert!(compressed.len() < 2000);
+        assert!(config.decompress(compressed).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_decompression_bomb() {
+        check_decompression_bomb(CompressionConfig::Zstd { level: 3 });
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_decompression_bomb() {
+        check_decompression_bomb(CompressionConfig::Lz4);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_message_roundtrip() {
+        check_message_roundtrip(CompressionConfig::Zstd { level: 3 });
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_dictionary_message_roundtrip() {
+        let samples = (0..1000)
+            .map(|i| format!("{{\"player\": {i}, \"position\": [{i}, 0, {}]}}", i * 2).into_bytes())
+            .collect::<Vec<_>>();
+        let dictionary = ZstdDictionary::train(&samples, 1024).unwrap();
+        check_message_roundtrip(CompressionConfig::ZstdDictionary {
+            level: 3,
+            dictionary,
+        });
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4_message_roundtrip() {
+        check_message_roundtrip(CompressionConfig::Lz4);
+    }
+}
@@ -4,7 +4,166 @@ use crate::connection::netcode::MAX_PKT_BUF_SIZE;
 use crate::transport::error::{Error, Result};
 use std::net::SocketAddr;
 
+pub mod dictionary {
+    use std::io::Write;
+    use std::sync::{Arc, OnceLock};
+
+    use bevy::prelude::{Reflect, ReflectDefault};
+    use bevy::utils::HashMap;
+    use bytes::Bytes;
+    use parking_lot::RwLock;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use zstd::dict::{DecoderDictionary, EncoderDictionary};
+    use zstd::zstd_safe::{CDict, DDict};
+
+    use crate::transport::error::Result;
+    use crate::transport::middleware::compression::read_bounded;
+
+    /// Dictionary used by [`CompressionConfig::ZstdDictionary`](crate::prelude::CompressionConfig::ZstdDictionary).
+    ///
+    /// It is usually trained offline on payloads captured with a
+    /// [`PayloadCaptureConfig`](crate::transport::middleware::compression::PayloadCaptureConfig), and shipped with
+    /// the game: `ZstdDictionary::from(include_bytes!("payloads.dict").as_slice())`
+    ///
+    /// Clones share the dictionary, and the dictionary prepared for the compression and decompression of the
+    /// messages, which is only computed once.
+    #[derive(Clone, Default, Reflect)]
+    #[reflect_value(Debug, PartialEq, Default)]
+    pub struct ZstdDictionary {
+        bytes: Arc<[u8]>,
+        prepared: Arc<PreparedDictionary>,
+    }
+
+    /// Dictionaries digested by zstd, created the first time a message is compressed or decompressed
+    #[derive(Default)]
+    struct PreparedDictionary {
+        decoder: OnceLock<Option<DecoderDictionary<'static>>>,
+        /// The dictionary is prepared for a compression level
+        encoders: RwLock<HashMap<i32, Option<Arc<EncoderDictionary<'static>>>>>,
+    }
+
+    impl ZstdDictionary {
+        /// Train a dictionary of at most `max_size` bytes on sample payloads
+        pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Self> {
+            Ok(Self::from(::zstd::dict::from_samples(samples, max_size)?))
+        }
+
+        pub fn as_bytes(&self) -> &[u8] {
+            &self.bytes
+        }
+
+        pub(crate) fn encoder(&self, level: i32) -> Result<Arc<EncoderDictionary<'static>>> {
+            if let Some(encoder) = self.prepared.encoders.read().get(&level) {
+                return encoder.clone().ok_or_else(invalid);
+            }
+            self.prepared
+                .encoders
+                .write()
+                .entry(level)
+                .or_insert_with(|| {
+                    // `EncoderDictionary::copy` panics if the dictionary is invalid
+                    CDict::try_create(&self.bytes, level)
+                        .map(|_| Arc::new(EncoderDictionary::copy(&self.bytes, level)))
+                })
+                .clone()
+                .ok_or_else(invalid)
+        }
+
+        fn decoder(&self) -> Result<&DecoderDictionary<'static>> {
+            self.prepared
+                .decoder
+                .get_or_init(|| {
+                    // `DecoderDictionary::copy` panics if the dictionary is invalid
+                    DDict::try_create(&self.bytes).map(|_| DecoderDictionary::copy(&self.bytes))
+                })
+                .as_ref()
+                .ok_or_else(invalid)
+        }
+
+        pub(crate) fn compress(&self, data: &[u8], level: i32) -> Result<Bytes> {
+            let dictionary = self.encoder(level)?;
+            let mut encoder =
+                ::zstd::stream::write::Encoder::with_prepared_dictionary(Vec::new(), &dictionary)?;
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?.into())
+        }
+
+        /// Decompress a message, failing if it is bigger than the maximum message size
+        pub(crate) fn decompress(&self, data: &[u8]) -> Result<Bytes> {
+            read_bounded(::zstd::stream::read::Decoder::with_prepared_dictionary(
+                data,
+                self.decoder()?,
+            )?)
+        }
+    }
+
+    fn invalid() -> crate::transport::error::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid zstd dictionary").into()
+    }
+
+    impl std::fmt::Debug for ZstdDictionary {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_tuple("ZstdDictionary")
+                .field(&format_args!("{} bytes", self.bytes.len()))
+                .finish()
+        }
+    }
+
+    impl PartialEq for ZstdDictionary {
+        fn eq(&self, other: &Self) -> bool {
+            self.bytes == other.bytes
+        }
+    }
+
+    impl Eq for ZstdDictionary {}
+
+    impl From<Vec<u8>> for ZstdDictionary {
+        fn from(value: Vec<u8>) -> Self {
+            Self {
+                bytes: value.into(),
+                prepared: Arc::default(),
+            }
+        }
+    }
+
+    impl From<&[u8]> for ZstdDictionary {
+        fn from(value: &[u8]) -> Self {
+            Self {
+                bytes: value.into(),
+                prepared: Arc::default(),
+            }
+        }
+    }
+
+    impl Serialize for ZstdDictionary {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            self.bytes.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ZstdDictionary {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> std::result::Result<Self, D::Error> {
+            Vec::<u8>::deserialize(deserializer).map(Self::from)
+        }
+    }
+
+    /// Train a dictionary of at most `max_size` bytes on all the samples captured in `directory`
+    #[cfg(not(target_family = "wasm"))]
+    pub fn train_dictionary(
+        directory: impl AsRef<std::path::Path>,
+        max_size: usize,
+    ) -> Result<ZstdDictionary> {
+        let samples = std::fs::read_dir(directory)?
+            .map(|entry| std::fs::read(entry?.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        ZstdDictionary::train(&samples, max_size)
+    }
+}
+
 pub(crate) mod compression {
+    use super::dictionary::ZstdDictionary;
     use super::*;
     use crate::transport::middleware::PacketSenderWrapper;
     use crate::transport::PacketSender;
@@ -23,10 +182,17 @@ pub(crate) mod compression {
             }
         }
 
+        pub fn with_dictionary(level: i32, dictionary: &ZstdDictionary) -> Result<Self> {
+            Ok(ZstdCompressor {
+                result: Vec::with_capacity(MAX_PKT_BUF_SIZE),
+                compressor: Compressor::with_dictionary(level, dictionary.as_bytes())?,
+            })
+        }
+
         pub fn compress(&mut self, data: &[u8]) -> Result<&[u8]> {
             self.compressor
                 .compress_to_buffer(data, &mut self.result)
-                .map_err(|e| Error::Io(e))?;
+                .map_err(Error::Io)?;
             Ok(&self.result)
         }
     }
@@ -54,6 +220,7 @@ pub(crate) mod compression {
 }
 
 pub(crate) mod decompression {
+    use super::dictionary::ZstdDictionary;
     use super::*;
     use crate::transport::middleware::PacketReceiverWrapper;
     use crate::transport::PacketReceiver;
@@ -72,10 +239,17 @@ pub(crate) mod decompression {
             }
         }
 
+        pub fn with_dictionary(dictionary: &ZstdDictionary) -> Result<Self> {
+            Ok(ZstdDecompressor {
+                result: Vec::with_capacity(MAX_PKT_BUF_SIZE),
+                decompressor: Decompressor::with_dictionary(dictionary.as_bytes())?,
+            })
+        }
+
         pub fn decompress(&mut self, data: &[u8]) -> Result<&mut [u8]> {
             self.decompressor
                 .decompress_to_buffer(data, &mut self.result)
-                .map_err(|e| Error::Io(e))?;
+                .map_err(Error::Io)?;
             Ok(&mut self.result)
         }
     }
@@ -106,29 +280,240 @@ pub(crate) mod decompression {
     }
 }
 
+/// Write the sent payloads to sample files, to train a dictionary
+#[cfg(not(target_family = "wasm"))]
+pub(crate) mod capture {
+    use std::path::{Path, PathBuf};
+
+    use bevy::prelude::Reflect;
+    use crossbeam_channel::{Sender, TrySendError};
+    use serde::{Deserialize, Serialize};
+    use tracing::{error, warn};
+
+    use super::*;
+    use crate::transport::middleware::PacketSenderWrapper;
+    use crate::transport::PacketSender;
+
+    /// Maximum number of samples waiting to be written to their files
+    const MAX_PENDING_SAMPLES: usize = 256;
+
+    /// Write the first `max_samples` payloads sent by an io to files in `directory`, to
+    /// [train](crate::transport::middleware::compression::train_dictionary) a
+    /// [`ZstdDictionary`](crate::prelude::ZstdDictionary) offline
+    ///
+    /// The files are written by a background thread, and the samples are dropped if it can't keep up.
+    #[derive(Clone, Debug, PartialEq, Reflect, Serialize, Deserialize)]
+    pub struct PayloadCaptureConfig {
+        pub directory: PathBuf,
+        pub max_samples: usize,
+    }
+
+    /// Sends the payloads to the thread that writes them to files
+    pub(crate) struct PayloadCapture {
+        samples: Sender<Vec<u8>>,
+        /// Number of payloads captured by this io
+        captured: usize,
+        max_samples: usize,
+    }
+
+    impl PayloadCapture {
+        pub(crate) fn start(config: PayloadCaptureConfig) -> Result<Self> {
+            std::fs::create_dir_all(&config.directory)?;
+            let (samples, receiver) = crossbeam_channel::bounded::<Vec<u8>>(MAX_PENDING_SAMPLES);
+            // the random prefix avoids overwriting the samples of the other ios and of previous runs
+            let prefix = format!("{}-{:08x}", std::process::id(), rand::random::<u32>());
+            std::thread::Builder::new()
+                .name("lightyear-payload-capture".to_string())
+                .spawn(move || {
+                    for (index, payload) in receiver.into_iter().enumerate() {
+                        if let Err(e) = write_sample(&config.directory, &prefix, index, &payload) {
+                            error!("Could not capture a payload sample: {e:?}");
+                        }
+                    }
+                })?;
+            Ok(Self {
+                samples,
+                captured: 0,
+                max_samples: config.max_samples,
+            })
+        }
+
+        fn capture(&mut self, payload: &[u8]) {
+            if self.captured >= self.max_samples {
+                return;
+            }
+            match self.samples.try_send(payload.to_vec()) {
+                Ok(()) => self.captured += 1,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Dropped a payload sample, the capture thread is busy")
+                }
+                Err(TrySendError::Disconnected(_)) => {}
+            }
+        }
+    }
+
+    fn write_sample(directory: &Path, prefix: &str, index: usize, payload: &[u8]) -> Result<()> {
+        std::fs::write(directory.join(format!("{prefix}-{index}.bin")), payload)?;
+        Ok(())
+    }
+
+    struct CapturePacketSender<T: PacketSender> {
+        inner: T,
+        capture: PayloadCapture,
+    }
+
+    impl<T: PacketSender> PacketSender for CapturePacketSender<T> {
+        fn send(&mut self, payload: &[u8], address: &SocketAddr) -> Result<()> {
+            self.capture.capture(payload);
+            self.inner.send(payload, address)
+        }
+    }
+
+    impl<T: PacketSender> PacketSenderWrapper<T> for PayloadCapture {
+        fn wrap(self, sender: T) -> impl PacketSender {
+            CapturePacketSender {
+                inner: sender,
+                capture: self,
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::prelude::{SharedIoConfig, TransportConfig};
-    use crate::transport::middleware::compression::CompressionConfig;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+
+    use crate::client::io::config::ClientTransport;
+    use crate::transport::config::SharedIoConfig;
+    use crate::transport::middleware::compression::{
+        train_dictionary, CompressionConfig, PayloadCaptureConfig, ZstdDictionary,
+    };
     use crate::transport::LOCAL_SOCKET;
 
-    #[test]
-    fn test_compression() {
+    fn local_io_config(compression: CompressionConfig) -> SharedIoConfig<ClientTransport> {
         let (send, recv) = crossbeam_channel::unbounded();
+        SharedIoConfig::from_transport(ClientTransport::LocalChannel { recv, send })
+            .with_compression(compression)
+    }
 
-        let config = TransportConfig::LocalChannel { send, recv };
-        let io_config = SharedIoConfig {
-            transport: config,
-            conditioner: None,
-            compression: CompressionConfig::Zstd { level: 0 },
-        };
-        let mut io = io_config.connect().unwrap();
+    #[test]
+    fn test_compression() {
+        let mut io = local_io_config(CompressionConfig::Zstd { level: 0 })
+            .connect()
+            .unwrap();
         let msg = b"hello world".as_slice();
         // send data
-        io.sender.send(&msg, &LOCAL_SOCKET).unwrap();
+        io.sender.send(msg, &LOCAL_SOCKET).unwrap();
 
         // receive data
-        let (data, addr) = io.receiver.recv().unwrap().unwrap();
-        assert_eq!(data.as_ref(), msg);
+        let (data, _) = io.receiver.recv().unwrap().unwrap();
+        assert_eq!(data, msg);
+    }
+
+    #[test]
+    fn test_invalid_dictionary() {
+        let dictionary = ZstdDictionary::from(b"\x37\xa4\x30\xecnot a dictionary".as_slice());
+        assert!(local_io_config(CompressionConfig::ZstdDictionary {
+            level: 3,
+            dictionary,
+        })
+        .connect()
+        .is_err());
+    }
+
+    /// The dictionary is prepared once per compression level and shared by the clones of the config
+    #[test]
+    fn test_prepared_dictionary() {
+        let samples = (0..1000)
+            .map(|i| format!("{{\"player\": {i}, \"position\": [{i}, 0, {}]}}", i * 2).into_bytes())
+            .collect::<Vec<_>>();
+        let dictionary = ZstdDictionary::train(&samples, 1024).unwrap();
+        let config = CompressionConfig::ZstdDictionary {
+            level: 3,
+            dictionary: dictionary.clone(),
+        };
+        let message = Bytes::from_static(b"{\"player\": 7, \"position\": [7, 0, 14]}");
+        let compressed = config.compress(message.clone()).unwrap();
+        assert_eq!(config.clone().decompress(compressed).unwrap(), message);
+        assert!(Arc::ptr_eq(
+            &dictionary.encoder(3).unwrap(),
+            &config_dictionary(&config.clone()).encoder(3).unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            &dictionary.encoder(3).unwrap(),
+            &dictionary.encoder(5).unwrap()
+        ));
+
+        // the dictionary survives a serialization round-trip
+        let serialized =
+            bincode::serde::encode_to_vec(&config, bincode::config::standard()).unwrap();
+        let (deserialized, _): (CompressionConfig, _) =
+            bincode::serde::decode_from_slice(&serialized, bincode::config::standard()).unwrap();
+        assert_eq!(deserialized, config);
+        let compressed = deserialized.compress(message.clone()).unwrap();
+        assert_eq!(config.decompress(compressed).unwrap(), message);
+    }
+
+    /// An invalid dictionary returns an error when a message is compressed, instead of panicking
+    #[test]
+    fn test_invalid_dictionary_message() {
+        let config = CompressionConfig::ZstdDictionary {
+            level: 3,
+            dictionary: ZstdDictionary::from(b"\x37\xa4\x30\xecnot a dictionary".as_slice()),
+        };
+        assert!(config.compress(Bytes::from_static(b"hello")).is_err());
+        assert!(config.decompress(Bytes::from_static(b"hello")).is_err());
+    }
+
+    fn config_dictionary(config: &CompressionConfig) -> &ZstdDictionary {
+        match config {
+            CompressionConfig::ZstdDictionary { dictionary, .. } => dictionary,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_capture_and_train_dictionary() {
+        let directory =
+            std::env::temp_dir().join(format!("lightyear-capture-{}", std::process::id()));
+        let mut io = local_io_config(CompressionConfig::None)
+            .with_capture(PayloadCaptureConfig {
+                directory: directory.clone(),
+                max_samples: 200,
+            })
+            .connect()
+            .unwrap();
+        for i in 0..300 {
+            let sample = format!("{{\"player\": {i}, \"position\": [{i}, 0, {}]}}", i * 2);
+            io.sender.send(sample.as_bytes(), &LOCAL_SOCKET).unwrap();
+            // don't overflow the queue of the capture thread
+            if i % 100 == 99 {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+        drop(io);
+        // the samples are written in the background
+        let start = Instant::now();
+        while std::fs::read_dir(&directory).unwrap().count() < 200 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 200);
+        let dictionary = train_dictionary(&directory, 1024).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let mut io = local_io_config(CompressionConfig::ZstdDictionary {
+            level: 3,
+            dictionary,
+        })
+        .connect()
+        .unwrap();
+        let msg = b"{\"player\": 7, \"position\": [7, 0, 14]}".as_slice();
+        io.sender.send(msg, &LOCAL_SOCKET).unwrap();
+        let (data, _) = io.receiver.recv().unwrap().unwrap();
+        assert_eq!(data, msg);
     }
 }
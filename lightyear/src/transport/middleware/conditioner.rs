@@ -32,6 +32,9 @@ pub struct LinkConditionerConfig {
     /// The % chance that an incoming packet will be dropped.
     /// Represented as a value between 0 and 1
     pub incoming_loss: f32,
+    /// The % chance that an incoming packet will be received twice.
+    /// Represented as a value between 0 and 1. Set with [`with_duplication`](Self::with_duplication)
+    incoming_duplication: f32,
 }
 
 pub(crate) type PacketLinkConditioner = LinkConditioner<(SocketAddr, Box<[u8]>)>;
@@ -51,12 +54,26 @@ impl<P: Eq> LinkConditioner<P> {
         }
     }
 
-    /// Add latency/jitter/loss to a packet
-    fn condition_packet(&mut self, packet: P) {
+    /// Add latency/jitter/loss/duplication to a packet
+    fn condition_packet(&mut self, packet: P)
+    where
+        P: Clone,
+    {
         let mut rng = thread_rng();
         if rng.gen_range(0.0..1.0) <= self.config.incoming_loss {
             return;
         }
+        if rng.gen_range(0.0..1.0) < self.config.incoming_duplication {
+            // the duplicate gets its own latency, so it can arrive before the original packet
+            let packet_timestamp = self.packet_timestamp(&mut rng);
+            self.time_queue.push(packet_timestamp, packet.clone());
+        }
+        let packet_timestamp = self.packet_timestamp(&mut rng);
+        self.time_queue.push(packet_timestamp, packet);
+    }
+
+    /// Compute the time at which a packet received now should be returned
+    fn packet_timestamp(&self, rng: &mut impl Rng) -> Instant {
         let mut latency: i32 = self.config.incoming_latency.as_millis() as i32;
         // TODO: how can i use the virtual time here?
         let mut packet_timestamp = Instant::now();
@@ -67,7 +84,7 @@ impl<P: Eq> LinkConditioner<P> {
         if latency > 0 {
             packet_timestamp += Duration::from_millis(latency as u64);
         }
-        self.time_queue.push(packet_timestamp, packet);
+        packet_timestamp
     }
 
     /// Check if a packet is ready to be returned
@@ -88,7 +105,7 @@ impl<T: PacketReceiver> PacketReceiverWrapper<T> for LinkConditioner<(SocketAddr
 }
 
 /// A wrapper around a packet receiver that simulates network conditions
-/// by adding latency, jitter and packet loss to incoming packets.
+/// by adding latency, jitter, packet loss and duplication to incoming packets.
 pub struct ConditionedPacketReceiver<T: PacketReceiver, P: Eq> {
     packet_receiver: T,
     conditioner: LinkConditioner<P>,
@@ -129,9 +146,21 @@ impl LinkConditionerConfig {
             incoming_latency,
             incoming_jitter,
             incoming_loss,
+            incoming_duplication: 0.0,
         }
     }
 
+    /// Set the % chance that an incoming packet will be received twice
+    pub fn with_duplication(mut self, incoming_duplication: f32) -> Self {
+        self.incoming_duplication = incoming_duplication;
+        self
+    }
+
+    /// The % chance that an incoming packet will be received twice
+    pub fn incoming_duplication(&self) -> f32 {
+        self.incoming_duplication
+    }
+
     /// Creates a new LinkConditioner that simulates a connection which is in a
     /// good condition
     pub fn good_condition() -> Self {
@@ -139,6 +168,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(40),
             incoming_jitter: Duration::from_millis(6),
             incoming_loss: 0.002,
+            incoming_duplication: 0.0,
         }
     }
 
@@ -149,6 +179,7 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(170),
             incoming_jitter: Duration::from_millis(45),
             incoming_loss: 0.02,
+            incoming_duplication: 0.0,
         }
     }
 
@@ -159,6 +190,24 @@ impl LinkConditionerConfig {
             incoming_latency: Duration::from_millis(300),
             incoming_jitter: Duration::from_millis(84),
             incoming_loss: 0.04,
+            incoming_duplication: 0.0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplication() {
+        let mut conditioner = LinkConditioner::new(
+            LinkConditionerConfig::new(Duration::default(), Duration::default(), 0.0)
+                .with_duplication(1.0),
+        );
+        conditioner.condition_packet(1);
+        assert_eq!(conditioner.pop_packet(), Some(1));
+        assert_eq!(conditioner.pop_packet(), Some(1));
+        assert_eq!(conditioner.pop_packet(), None);
+    }
+}
@@ -191,11 +191,11 @@ mod tests {
         let server_addr = server_socket.local_addr();
         let (_, server_receiver) = server_socket.split();
 
-        let mut conditioned_server_receiver = LinkConditioner::new(LinkConditionerConfig {
-            incoming_latency: Duration::from_millis(100),
-            incoming_jitter: Duration::from_millis(0),
-            incoming_loss: 0.0,
-        })
+        let mut conditioned_server_receiver = LinkConditioner::new(LinkConditionerConfig::new(
+            Duration::from_millis(100),
+            Duration::from_millis(0),
+            0.0,
+        ))
         .wrap(server_receiver);
 
         let msg = b"hello world";
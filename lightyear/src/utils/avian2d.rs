@@ -1,10 +1,27 @@
 //! Implement lightyear traits for some common bevy types
-use crate::prelude::client::{InterpolationSet, PredictionSet};
+//!
+//! [`PhysicsReplicationPlugin`] registers the physics components of avian in the protocol, to replicate the
+//! rigid bodies simulated by the server.
+// the casts are needed when avian uses `f64` scalars
+#![allow(clippy::unnecessary_cast)]
+use std::marker::PhantomData;
+
+use crate::prelude::client::{ComponentSyncMode, InterpolationSet, PredictionSet};
+use crate::prelude::{AppComponentExt, ChannelDirection};
+use crate::protocol::serialize::SerializeFns;
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::serialize::SerializationError;
+use crate::shared::quantize::{
+    decode_angle, encode_angle, read_quantized, write_quantized, DefaultTransformPrecision,
+    TransformPrecision, ANGLE_STEP,
+};
 use crate::shared::replication::delta::Diffable;
 use crate::shared::sets::{ClientMarker, InternalReplicationSet, ServerMarker};
-use avian2d::math::Scalar;
+use avian2d::math::{Scalar, Vector};
 use avian2d::prelude::*;
 use bevy::prelude::{App, FixedPostUpdate, IntoSystemSetConfigs, Plugin};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 use tracing::trace;
 
 pub(crate) struct Avian2dPlugin;
@@ -37,9 +54,119 @@ impl Plugin for Avian2dPlugin {
     }
 }
 
+/// Replicate the rigid bodies simulated by the server.
+///
+/// The plugin registers [`Position`], [`Rotation`], [`LinearVelocity`] and [`AngularVelocity`] in the protocol:
+/// - the position is rounded to [`TransformPrecision::TRANSLATION_STEP`] and the rotation is sent with 16 bits.
+///   Both are predicted and interpolated, and the differences caused by the rounding don't trigger rollbacks.
+/// - the velocities are predicted, so that the predicted bodies keep moving between two updates.
+///
+/// Whether a body is predicted or interpolated on a client depends on the
+/// [`SyncTarget`](crate::prelude::server::SyncTarget) of the entity:
+/// [`SyncTarget::from_ownership`](crate::prelude::server::SyncTarget::from_ownership) predicts the body of the
+/// local player and interpolates the bodies of the other players.
+///
+/// The plugin is part of the protocol, so it must be added to both the client and the server:
+/// ```rust,ignore
+/// app.add_plugins(PhysicsReplicationPlugin::<DefaultTransformPrecision>::default());
+/// ```
+pub struct PhysicsReplicationPlugin<P = DefaultTransformPrecision> {
+    /// Direction in which the physics components are replicated
+    pub direction: ChannelDirection,
+    _marker: PhantomData<P>,
+}
+
+impl<P> PhysicsReplicationPlugin<P> {
+    pub fn new(direction: ChannelDirection) -> Self {
+        Self {
+            direction,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P> Default for PhysicsReplicationPlugin<P> {
+    fn default() -> Self {
+        Self::new(ChannelDirection::ServerToClient)
+    }
+}
+
+impl<P: TransformPrecision> Plugin for PhysicsReplicationPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.register_component_custom_serde::<Position>(self.direction, position::quantized::<P>())
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(position::lerp)
+            .add_correction_fn(position::lerp)
+            .add_should_rollback(position::should_rollback::<P>);
+
+        app.register_component_custom_serde::<Rotation>(self.direction, rotation::quantized())
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(rotation::lerp)
+            .add_correction_fn(rotation::lerp)
+            .add_should_rollback(rotation::should_rollback);
+
+        app.register_component_custom_serde::<LinearVelocity>(
+            self.direction,
+            linear_velocity::serialize_fns(),
+        )
+        .add_prediction(ComponentSyncMode::Full);
+
+        app.register_component_custom_serde::<AngularVelocity>(
+            self.direction,
+            angular_velocity::serialize_fns(),
+        )
+        .add_prediction(ComponentSyncMode::Full);
+    }
+}
+
+fn write_vector(value: Vector, writer: &mut Writer) -> Result<(), SerializationError> {
+    writer.write_f32::<NetworkEndian>(value.x as f32)?;
+    writer.write_f32::<NetworkEndian>(value.y as f32)?;
+    Ok(())
+}
+
+fn read_vector(reader: &mut Reader) -> Result<Vector, SerializationError> {
+    let x = reader.read_f32::<NetworkEndian>()?;
+    let y = reader.read_f32::<NetworkEndian>()?;
+    Ok(Vector::new(x as Scalar, y as Scalar))
+}
+
 pub mod position {
     use super::*;
 
+    fn serialize<P: TransformPrecision>(
+        position: &Position,
+        writer: &mut Writer,
+    ) -> Result<(), SerializationError> {
+        write_quantized(position.x as f32, P::TRANSLATION_STEP, writer)?;
+        write_quantized(position.y as f32, P::TRANSLATION_STEP, writer)?;
+        Ok(())
+    }
+
+    fn deserialize<P: TransformPrecision>(
+        reader: &mut Reader,
+    ) -> Result<Position, SerializationError> {
+        let x = read_quantized(P::TRANSLATION_STEP, reader)?;
+        let y = read_quantized(P::TRANSLATION_STEP, reader)?;
+        Ok(Position::new(Vector::new(x as Scalar, y as Scalar)))
+    }
+
+    /// Serialize the position rounded to [`TransformPrecision::TRANSLATION_STEP`]
+    pub fn quantized<P: TransformPrecision>() -> SerializeFns<Position> {
+        SerializeFns {
+            serialize: serialize::<P>,
+            deserialize: deserialize::<P>,
+            serialize_map_entities: None,
+        }
+    }
+
+    /// Rollback only if the positions differ by more than the rounding of the serialization
+    pub fn should_rollback<P: TransformPrecision>(this: &Position, that: &Position) -> bool {
+        this.distance(that.0) > P::TRANSLATION_STEP as Scalar
+    }
+
     pub fn lerp(start: &Position, other: &Position, t: f32) -> Position {
         let u = Scalar::from(t);
         let res = Position::new(start.0 * (1.0 - u) + other.0 * u);
@@ -73,6 +200,31 @@ pub mod position {
 pub mod rotation {
     use super::*;
 
+    fn serialize(rotation: &Rotation, writer: &mut Writer) -> Result<(), SerializationError> {
+        // the angle is between -PI and PI
+        writer.write_u16::<NetworkEndian>(encode_angle(rotation.as_radians() as f32))?;
+        Ok(())
+    }
+
+    fn deserialize(reader: &mut Reader) -> Result<Rotation, SerializationError> {
+        let angle = decode_angle(reader.read_u16::<NetworkEndian>()?);
+        Ok(Rotation::radians(angle as Scalar))
+    }
+
+    /// Serialize the angle of the rotation with 16 bits
+    pub fn quantized() -> SerializeFns<Rotation> {
+        SerializeFns {
+            serialize,
+            deserialize,
+            serialize_map_entities: None,
+        }
+    }
+
+    /// Rollback only if the rotations differ by more than the rounding of the serialization
+    pub fn should_rollback(this: &Rotation, that: &Rotation) -> bool {
+        this.angle_between(*that).abs() > ANGLE_STEP as Scalar
+    }
+
     pub fn lerp(start: &Rotation, other: &Rotation, t: f32) -> Rotation {
         let u = Scalar::from(t);
         let shortest_angle =
@@ -98,6 +250,23 @@ pub mod rotation {
 pub mod linear_velocity {
     use super::*;
 
+    fn serialize(velocity: &LinearVelocity, writer: &mut Writer) -> Result<(), SerializationError> {
+        write_vector(velocity.0, writer)
+    }
+
+    fn deserialize(reader: &mut Reader) -> Result<LinearVelocity, SerializationError> {
+        Ok(LinearVelocity(read_vector(reader)?))
+    }
+
+    /// Serialize the velocity as `f32`s, without requiring the `serialize` feature of avian
+    pub fn serialize_fns() -> SerializeFns<LinearVelocity> {
+        SerializeFns {
+            serialize,
+            deserialize,
+            serialize_map_entities: None,
+        }
+    }
+
     pub fn lerp(start: &LinearVelocity, other: &LinearVelocity, t: f32) -> LinearVelocity {
         let u = Scalar::from(t);
         let res = LinearVelocity(start.0 * (1.0 - u) + other.0 * u);
@@ -115,6 +284,29 @@ pub mod linear_velocity {
 pub mod angular_velocity {
     use super::*;
 
+    fn serialize(
+        velocity: &AngularVelocity,
+        writer: &mut Writer,
+    ) -> Result<(), SerializationError> {
+        writer.write_f32::<NetworkEndian>(velocity.0 as f32)?;
+        Ok(())
+    }
+
+    fn deserialize(reader: &mut Reader) -> Result<AngularVelocity, SerializationError> {
+        Ok(AngularVelocity(
+            reader.read_f32::<NetworkEndian>()? as Scalar
+        ))
+    }
+
+    /// Serialize the velocity as a `f32`, without requiring the `serialize` feature of avian
+    pub fn serialize_fns() -> SerializeFns<AngularVelocity> {
+        SerializeFns {
+            serialize,
+            deserialize,
+            serialize_map_entities: None,
+        }
+    }
+
     pub fn lerp(start: &AngularVelocity, other: &AngularVelocity, t: f32) -> AngularVelocity {
         let u = Scalar::from(t);
         let res = AngularVelocity(start.0 * (1.0 - u) + other.0 * u);
@@ -128,3 +320,63 @@ pub mod angular_velocity {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn round_trip<C>(fns: SerializeFns<C>, value: &C) -> C {
+        let mut writer = Writer::default();
+        (fns.serialize)(value, &mut writer).unwrap();
+        (fns.deserialize)(&mut Reader::from(writer.to_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_quantized_position() {
+        for (x, y) in [
+            (0.0, 0.0),
+            (1.2345, -20.0),
+            (-300.5, 0.0004),
+            (1000.0, 1000.0),
+        ] {
+            let position = Position::new(Vector::new(x, y));
+            let decoded = round_trip(
+                position::quantized::<DefaultTransformPrecision>(),
+                &position,
+            );
+            assert!(
+                !position::should_rollback::<DefaultTransformPrecision>(&position, &decoded),
+                "{position:?} decoded as {decoded:?}"
+            );
+        }
+        let position = Position::new(Vector::new(1.0, 2.0));
+        assert!(position::should_rollback::<DefaultTransformPrecision>(
+            &position,
+            &Position::new(Vector::new(1.0, 2.1))
+        ));
+    }
+
+    #[test]
+    fn test_quantized_rotation() {
+        for angle in [-PI, -PI / 2.0, -0.001, 0.0, 0.001, 1.0, PI / 2.0, PI] {
+            let rotation = Rotation::radians(angle as Scalar);
+            let decoded = round_trip(rotation::quantized(), &rotation);
+            assert!(
+                !rotation::should_rollback(&rotation, &decoded),
+                "{angle} decoded as {}",
+                decoded.as_radians()
+            );
+        }
+        // -PI and PI are the same rotation
+        assert!(!rotation::should_rollback(
+            &Rotation::radians(-PI as Scalar),
+            &Rotation::radians(PI as Scalar)
+        ));
+        assert!(rotation::should_rollback(
+            &Rotation::radians(0.0),
+            &Rotation::radians((2.0 * ANGLE_STEP) as Scalar)
+        ));
+    }
+}
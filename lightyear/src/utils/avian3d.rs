@@ -1,11 +1,28 @@
 //! Implement lightyear traits for some common bevy types
-use crate::prelude::client::{InterpolationSet, PredictionSet};
+//!
+//! [`PhysicsReplicationPlugin`] registers the physics components of avian in the protocol, to replicate the
+//! rigid bodies simulated by the server.
+// the casts are needed when avian uses `f64` scalars
+#![allow(clippy::unnecessary_cast)]
+use std::marker::PhantomData;
+
+use crate::prelude::client::{ComponentSyncMode, InterpolationSet, PredictionSet};
+use crate::prelude::{AppComponentExt, ChannelDirection};
+use crate::protocol::serialize::SerializeFns;
+use crate::serialize::reader::Reader;
+use crate::serialize::writer::Writer;
+use crate::serialize::SerializationError;
+use crate::shared::quantize::{
+    decode_quat, encode_quat, read_quantized, rotation_bits, rotation_error, rotation_len,
+    write_quantized, DefaultTransformPrecision, TransformPrecision,
+};
 use crate::shared::replication::delta::Diffable;
 use crate::shared::sets::{ClientMarker, InternalReplicationSet, ServerMarker};
-use avian3d::math::Scalar;
+use avian3d::math::{Scalar, Vector};
 use avian3d::prelude::*;
 use bevy::app::{App, FixedPostUpdate, Plugin};
 use bevy::prelude::IntoSystemSetConfigs;
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 use tracing::trace;
 
 pub(crate) struct Avian3dPlugin;
@@ -37,9 +54,129 @@ impl Plugin for Avian3dPlugin {
     }
 }
 
+/// Replicate the rigid bodies simulated by the server.
+///
+/// The plugin registers [`Position`], [`Rotation`], [`LinearVelocity`] and [`AngularVelocity`] in the protocol:
+/// - the position is rounded to [`TransformPrecision::TRANSLATION_STEP`] and the rotation is compressed with
+///   [`TransformPrecision::ROTATION_BITS`] bits per component. Both are predicted and interpolated, and the
+///   differences caused by the compression don't trigger rollbacks.
+/// - the velocities are predicted, so that the predicted bodies keep moving between two updates.
+///
+/// Whether a body is predicted or interpolated on a client depends on the
+/// [`SyncTarget`](crate::prelude::server::SyncTarget) of the entity:
+/// [`SyncTarget::from_ownership`](crate::prelude::server::SyncTarget::from_ownership) predicts the body of the
+/// local player and interpolates the bodies of the other players.
+///
+/// The plugin is part of the protocol, so it must be added to both the client and the server:
+/// ```rust,ignore
+/// app.add_plugins(PhysicsReplicationPlugin::<DefaultTransformPrecision>::default());
+/// ```
+pub struct PhysicsReplicationPlugin<P = DefaultTransformPrecision> {
+    /// Direction in which the physics components are replicated
+    pub direction: ChannelDirection,
+    _marker: PhantomData<P>,
+}
+
+impl<P> PhysicsReplicationPlugin<P> {
+    pub fn new(direction: ChannelDirection) -> Self {
+        Self {
+            direction,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P> Default for PhysicsReplicationPlugin<P> {
+    fn default() -> Self {
+        Self::new(ChannelDirection::ServerToClient)
+    }
+}
+
+impl<P: TransformPrecision> Plugin for PhysicsReplicationPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.register_component_custom_serde::<Position>(self.direction, position::quantized::<P>())
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(position::lerp)
+            .add_correction_fn(position::lerp)
+            .add_should_rollback(position::should_rollback::<P>);
+
+        app.register_component_custom_serde::<Rotation>(self.direction, rotation::quantized::<P>())
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Full)
+            .add_interpolation_fn(rotation::lerp)
+            .add_correction_fn(rotation::lerp)
+            .add_should_rollback(rotation::should_rollback::<P>);
+
+        app.register_component_custom_serde::<LinearVelocity>(
+            self.direction,
+            linear_velocity::serialize_fns(),
+        )
+        .add_prediction(ComponentSyncMode::Full);
+
+        app.register_component_custom_serde::<AngularVelocity>(
+            self.direction,
+            angular_velocity::serialize_fns(),
+        )
+        .add_prediction(ComponentSyncMode::Full);
+    }
+}
+
+fn write_vector(value: Vector, writer: &mut Writer) -> Result<(), SerializationError> {
+    writer.write_f32::<NetworkEndian>(value.x as f32)?;
+    writer.write_f32::<NetworkEndian>(value.y as f32)?;
+    writer.write_f32::<NetworkEndian>(value.z as f32)?;
+    Ok(())
+}
+
+fn read_vector(reader: &mut Reader) -> Result<Vector, SerializationError> {
+    let x = reader.read_f32::<NetworkEndian>()?;
+    let y = reader.read_f32::<NetworkEndian>()?;
+    let z = reader.read_f32::<NetworkEndian>()?;
+    Ok(Vector::new(x as Scalar, y as Scalar, z as Scalar))
+}
+
 pub mod position {
     use super::*;
 
+    fn serialize<P: TransformPrecision>(
+        position: &Position,
+        writer: &mut Writer,
+    ) -> Result<(), SerializationError> {
+        write_quantized(position.x as f32, P::TRANSLATION_STEP, writer)?;
+        write_quantized(position.y as f32, P::TRANSLATION_STEP, writer)?;
+        write_quantized(position.z as f32, P::TRANSLATION_STEP, writer)?;
+        Ok(())
+    }
+
+    fn deserialize<P: TransformPrecision>(
+        reader: &mut Reader,
+    ) -> Result<Position, SerializationError> {
+        let x = read_quantized(P::TRANSLATION_STEP, reader)?;
+        let y = read_quantized(P::TRANSLATION_STEP, reader)?;
+        let z = read_quantized(P::TRANSLATION_STEP, reader)?;
+        Ok(Position::new(Vector::new(
+            x as Scalar,
+            y as Scalar,
+            z as Scalar,
+        )))
+    }
+
+    /// Serialize the position rounded to [`TransformPrecision::TRANSLATION_STEP`]
+    pub fn quantized<P: TransformPrecision>() -> SerializeFns<Position> {
+        SerializeFns {
+            serialize: serialize::<P>,
+            deserialize: deserialize::<P>,
+            serialize_map_entities: None,
+        }
+    }
+
+    /// Rollback only if the positions differ by more than the rounding of the serialization
+    pub fn should_rollback<P: TransformPrecision>(this: &Position, that: &Position) -> bool {
+        // each of the 3 components is off by at most half a step, so the distance is less than a step
+        this.distance(that.0) > P::TRANSLATION_STEP as Scalar
+    }
+
     pub fn lerp(start: &Position, other: &Position, t: f32) -> Position {
         let u = Scalar::from(t);
         let res = Position::new(start.0 * (1.0 - u) + other.0 * u);
@@ -73,16 +210,75 @@ pub mod position {
 pub mod rotation {
     use super::*;
     use avian3d::math::Quaternion;
-    use bevy::prelude::Animatable;
+    use bevy::prelude::Quat;
+
+    fn serialize<P: TransformPrecision>(
+        rotation: &Rotation,
+        writer: &mut Writer,
+    ) -> Result<(), SerializationError> {
+        let quat = Quat::from_xyzw(
+            rotation.x as f32,
+            rotation.y as f32,
+            rotation.z as f32,
+            rotation.w as f32,
+        );
+        let bits = rotation_bits::<P>();
+        writer.write_uint::<NetworkEndian>(encode_quat(quat, bits), rotation_len(bits))?;
+        Ok(())
+    }
+
+    fn deserialize<P: TransformPrecision>(
+        reader: &mut Reader,
+    ) -> Result<Rotation, SerializationError> {
+        let bits = rotation_bits::<P>();
+        let quat = decode_quat(reader.read_uint::<NetworkEndian>(rotation_len(bits))?, bits);
+        Ok(Rotation(Quaternion::from_xyzw(
+            quat.x as Scalar,
+            quat.y as Scalar,
+            quat.z as Scalar,
+            quat.w as Scalar,
+        )))
+    }
+
+    /// Serialize the rotation with [`TransformPrecision::ROTATION_BITS`] bits per component
+    pub fn quantized<P: TransformPrecision>() -> SerializeFns<Rotation> {
+        SerializeFns {
+            serialize: serialize::<P>,
+            deserialize: deserialize::<P>,
+            serialize_map_entities: None,
+        }
+    }
+
+    /// Rollback only if the rotations differ by more than the rounding of the serialization
+    pub fn should_rollback<P: TransformPrecision>(this: &Rotation, that: &Rotation) -> bool {
+        this.0.angle_between(that.0) > rotation_error(rotation_bits::<P>()) as Scalar
+    }
 
     pub fn lerp(start: &Rotation, other: &Rotation, t: f32) -> Rotation {
-        Rotation(Quaternion::interpolate(&start.0, &other.0, t))
+        Rotation(start.0.slerp(other.0, t as Scalar))
     }
 }
 
 pub mod linear_velocity {
     use super::*;
 
+    fn serialize(velocity: &LinearVelocity, writer: &mut Writer) -> Result<(), SerializationError> {
+        write_vector(velocity.0, writer)
+    }
+
+    fn deserialize(reader: &mut Reader) -> Result<LinearVelocity, SerializationError> {
+        Ok(LinearVelocity(read_vector(reader)?))
+    }
+
+    /// Serialize the velocity as `f32`s, without requiring the `serialize` feature of avian
+    pub fn serialize_fns() -> SerializeFns<LinearVelocity> {
+        SerializeFns {
+            serialize,
+            deserialize,
+            serialize_map_entities: None,
+        }
+    }
+
     pub fn lerp(start: &LinearVelocity, other: &LinearVelocity, t: f32) -> LinearVelocity {
         let u = Scalar::from(t);
         let res = LinearVelocity(start.0 * (1.0 - u) + other.0 * u);
@@ -100,6 +296,26 @@ pub mod linear_velocity {
 pub mod angular_velocity {
     use super::*;
 
+    fn serialize(
+        velocity: &AngularVelocity,
+        writer: &mut Writer,
+    ) -> Result<(), SerializationError> {
+        write_vector(velocity.0, writer)
+    }
+
+    fn deserialize(reader: &mut Reader) -> Result<AngularVelocity, SerializationError> {
+        Ok(AngularVelocity(read_vector(reader)?))
+    }
+
+    /// Serialize the velocity as `f32`s, without requiring the `serialize` feature of avian
+    pub fn serialize_fns() -> SerializeFns<AngularVelocity> {
+        SerializeFns {
+            serialize,
+            deserialize,
+            serialize_map_entities: None,
+        }
+    }
+
     pub fn lerp(start: &AngularVelocity, other: &AngularVelocity, t: f32) -> AngularVelocity {
         let u = Scalar::from(t);
         let res = AngularVelocity(start.0 * (1.0 - u) + other.0 * u);
@@ -113,3 +329,75 @@ pub mod angular_velocity {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use avian3d::math::Quaternion;
+    use bevy::prelude::Quat;
+
+    use crate::shared::quantize::tests::{for_each_rotation_bits, rotations};
+
+    use super::*;
+
+    fn round_trip<C>(fns: SerializeFns<C>, value: &C) -> C {
+        let mut writer = Writer::default();
+        (fns.serialize)(value, &mut writer).unwrap();
+        (fns.deserialize)(&mut Reader::from(writer.to_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_quantized_position() {
+        for (x, y, z) in [
+            (0.0, 0.0, 0.0),
+            (1.2345, -20.0, 300.5),
+            (-300.5, 0.0004, -0.0006),
+            (1000.0, 1000.0, 1000.0),
+        ] {
+            let position = Position::new(Vector::new(x, y, z));
+            let decoded = round_trip(
+                position::quantized::<DefaultTransformPrecision>(),
+                &position,
+            );
+            assert!(
+                !position::should_rollback::<DefaultTransformPrecision>(&position, &decoded),
+                "{position:?} decoded as {decoded:?}"
+            );
+        }
+        let position = Position::new(Vector::new(1.0, 2.0, 3.0));
+        assert!(position::should_rollback::<DefaultTransformPrecision>(
+            &position,
+            &Position::new(Vector::new(1.0, 2.0, 3.1))
+        ));
+    }
+
+    #[test]
+    fn test_quantized_rotation() {
+        fn check<P: TransformPrecision>() {
+            for quat in rotations() {
+                let rotation = Rotation(Quaternion::from_xyzw(
+                    quat.x as Scalar,
+                    quat.y as Scalar,
+                    quat.z as Scalar,
+                    quat.w as Scalar,
+                ));
+                let decoded = round_trip(rotation::quantized::<P>(), &rotation);
+                // the compression of the rotation doesn't trigger a rollback
+                assert!(
+                    !rotation::should_rollback::<P>(&rotation, &decoded),
+                    "{rotation:?} decoded as {decoded:?}"
+                );
+            }
+            let quat = Quat::from_rotation_y(3.0);
+            assert!(rotation::should_rollback::<P>(
+                &Rotation::default(),
+                &Rotation(Quaternion::from_xyzw(
+                    quat.x as Scalar,
+                    quat.y as Scalar,
+                    quat.z as Scalar,
+                    quat.w as Scalar,
+                ))
+            ));
+        }
+        for_each_rotation_bits!(check);
+    }
+}
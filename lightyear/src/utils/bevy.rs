@@ -1,9 +1,19 @@
 //! Implement lightyear traits for some common bevy types
+//!
+//! [`TransformReplicationPlugin`] registers the [`Transform`] in the protocol, to replicate the bodies of the
+//! physics engines that sync their simulation with the [`Transform`], such as bevy_rapier.
+use std::marker::PhantomData;
 
-use bevy::prelude::{Quat, Transform};
+use bevy::prelude::{App, Plugin, Quat, Transform};
 use tracing::trace;
 
 use crate::client::components::LerpFn;
+use crate::prelude::client::ComponentSyncMode;
+use crate::prelude::{AppComponentExt, ChannelDirection};
+use crate::protocol::serialize::SerializeFns;
+use crate::shared::quantize::{
+    rotation_bits, rotation_error, DefaultTransformPrecision, TransformPrecision,
+};
 
 pub struct TransformLinearInterpolation;
 
@@ -36,3 +46,103 @@ impl LerpFn<Quat> for QuatSphericalLinearInterpolation {
         start.slerp(*other, t)
     }
 }
+
+/// Replicate the [`Transform`] of the entities simulated by the server.
+///
+/// The [`Transform`] is serialized with [`SerializeFns::quantized`], predicted and interpolated, and the
+/// differences caused by the quantization don't trigger rollbacks. This works with any physics engine that
+/// syncs its rigid bodies with the [`Transform`], such as bevy_rapier; the velocities of the engine can be
+/// registered with [`AppComponentExt::register_component`] to predict them as well. The avian integrations
+/// replicate the physics components of avian directly, with their own `PhysicsReplicationPlugin`.
+///
+/// The plugin is part of the protocol, so it must be added to both the client and the server:
+/// ```rust,ignore
+/// app.add_plugins(TransformReplicationPlugin::<DefaultTransformPrecision>::default());
+/// ```
+pub struct TransformReplicationPlugin<P = DefaultTransformPrecision> {
+    /// Direction in which the [`Transform`] is replicated
+    pub direction: ChannelDirection,
+    _marker: PhantomData<P>,
+}
+
+impl<P> TransformReplicationPlugin<P> {
+    pub fn new(direction: ChannelDirection) -> Self {
+        Self {
+            direction,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P> Default for TransformReplicationPlugin<P> {
+    fn default() -> Self {
+        Self::new(ChannelDirection::ServerToClient)
+    }
+}
+
+impl<P: TransformPrecision> Plugin for TransformReplicationPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.register_component_custom_serde::<Transform>(
+            self.direction,
+            SerializeFns::<Transform>::quantized::<P>(),
+        )
+        .add_prediction(ComponentSyncMode::Full)
+        .add_interpolation(ComponentSyncMode::Full)
+        .add_interpolation_fn(TransformLinearInterpolation::lerp)
+        .add_correction_fn(TransformLinearInterpolation::lerp)
+        .add_should_rollback(transform_should_rollback::<P>);
+    }
+}
+
+/// Rollback only if the transforms differ by more than the rounding of [`SerializeFns::quantized`]
+pub fn transform_should_rollback<P: TransformPrecision>(
+    this: &Transform,
+    that: &Transform,
+) -> bool {
+    // each of the 3 components is off by at most half a step, so the distance is less than a step
+    this.translation.distance(that.translation) > P::TRANSLATION_STEP
+        || this.scale.distance(that.scale) > P::SCALE_STEP
+        || this.rotation.angle_between(that.rotation) > rotation_error(rotation_bits::<P>())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+
+    use crate::serialize::reader::Reader;
+    use crate::serialize::writer::Writer;
+    use crate::shared::quantize::tests::{for_each_rotation_bits, rotations};
+
+    use super::*;
+
+    #[test]
+    fn test_transform_should_rollback() {
+        fn check<P: TransformPrecision>() {
+            let fns = SerializeFns::<Transform>::quantized::<P>();
+            for (i, rotation) in rotations().enumerate() {
+                let f = i as f32;
+                let transform = Transform::from_xyz(f * 0.1234, -f * 3.21, 12.3456)
+                    .with_rotation(rotation)
+                    .with_scale(Vec3::splat(1.0 + f * 0.0123));
+                let mut writer = Writer::default();
+                (fns.serialize)(&transform, &mut writer).unwrap();
+                let decoded = (fns.deserialize)(&mut Reader::from(writer.to_bytes())).unwrap();
+                // the rounding of the serialization doesn't trigger a rollback
+                assert!(
+                    !transform_should_rollback::<P>(&transform, &decoded),
+                    "{transform:?} decoded as {decoded:?}"
+                );
+            }
+            let transform = Transform::from_xyz(1.0, 2.0, 3.0);
+            assert!(transform_should_rollback::<P>(
+                &transform,
+                &transform.with_translation(Vec3::new(1.0, 2.0, 3.1))
+            ));
+            assert!(transform_should_rollback::<P>(
+                &transform,
+                &transform.with_rotation(Quat::from_rotation_y(3.0))
+            ));
+        }
+        for_each_rotation_bits!(check);
+    }
+}
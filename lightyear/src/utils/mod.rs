@@ -18,4 +18,5 @@ pub mod avian3d;
 
 pub(crate) mod captures;
 pub(crate) mod pool;
+pub mod synthetic;
 pub mod wrapping_id;
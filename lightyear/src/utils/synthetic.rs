@@ -0,0 +1,302 @@
+//! Synthetic protocols and worlds, to estimate the replication cost of a game before building it.
+//!
+//! A [`SyntheticWorldConfig`] describes the shape of the replicated world: the number of component kinds and the
+//! size of each of them, the number of entities, how often the components change and how often the entities are
+//! despawned and replaced. The [`SyntheticProtocolPlugin`] registers the component kinds (it must be added to both
+//! the client and the server), and the [`SyntheticWorldPlugin`] spawns and updates the entities on the server:
+//! ```rust,ignore
+//! use lightyear::utils::synthetic::*;
+//!
+//! let config = SyntheticWorldConfig {
+//!     component_sizes: vec![12, 12, 64, 256],
+//!     entities: 1000,
+//!     components_per_entity: 2,
+//!     update_rate: 0.5,
+//!     churn_rate: 0.01,
+//!     ..default()
+//! };
+//! client_app.add_plugins(SyntheticProtocolPlugin::new(&config));
+//! server_app.add_plugins((SyntheticProtocolPlugin::new(&config), SyntheticWorldPlugin::new(config)));
+//! ```
+//! The bandwidth and CPU usage can then be measured with the usual tools (io statistics, metrics, benchmarks).
+//!
+//! The payloads of the components are random bytes, so compression doesn't reduce their size: the estimations are
+//! an upper bound for games whose data compresses well.
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::server::{is_started, Replicate};
+use crate::prelude::{AppComponentExt, ChannelDirection};
+
+/// Maximum number of component kinds of a synthetic protocol
+pub const MAX_COMPONENT_KINDS: usize = 16;
+
+/// Component of kind `K` of a synthetic protocol, with an opaque payload
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SyntheticComponent<const K: usize>(pub Vec<u8>);
+
+/// Shape of a synthetic world
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct SyntheticWorldConfig {
+    /// Size in bytes of the payload of each component kind. The number of sizes is the number of
+    /// component kinds, at most [`MAX_COMPONENT_KINDS`]
+    pub component_sizes: Vec<usize>,
+    /// Number of replicated entities
+    pub entities: usize,
+    /// Number of (distinct) component kinds of each entity
+    pub components_per_entity: usize,
+    /// Probability that a component is modified on a given tick
+    pub update_rate: f32,
+    /// Probability that an entity is despawned (and replaced by a new entity) on a given tick
+    pub churn_rate: f32,
+    /// Seed of the random generator, so that the runs can be reproduced
+    pub seed: u64,
+}
+
+impl Default for SyntheticWorldConfig {
+    fn default() -> Self {
+        Self {
+            component_sizes: vec![12; 4],
+            entities: 100,
+            components_per_entity: 2,
+            update_rate: 0.1,
+            churn_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl SyntheticWorldConfig {
+    /// Number of component kinds of the protocol
+    pub fn component_kinds(&self) -> usize {
+        self.component_sizes.len().min(MAX_COMPONENT_KINDS)
+    }
+}
+
+/// Functions to manipulate the component of one kind, without knowing its type
+#[derive(Clone, Copy)]
+struct KindFns {
+    register: fn(&mut App),
+    insert: fn(&mut EntityWorldMut, Vec<u8>),
+    mutate: fn(&mut EntityWorldMut, &mut StdRng),
+}
+
+impl KindFns {
+    const fn of<const K: usize>() -> Self {
+        Self {
+            register: register::<K>,
+            insert: insert::<K>,
+            mutate: mutate::<K>,
+        }
+    }
+}
+
+fn register<const K: usize>(app: &mut App) {
+    app.register_component::<SyntheticComponent<K>>(ChannelDirection::ServerToClient);
+}
+
+fn insert<const K: usize>(entity: &mut EntityWorldMut, payload: Vec<u8>) {
+    entity.insert(SyntheticComponent::<K>(payload));
+}
+
+fn mutate<const K: usize>(entity: &mut EntityWorldMut, rng: &mut StdRng) {
+    if let Some(mut component) = entity.get_mut::<SyntheticComponent<K>>() {
+        rng.fill(component.0.as_mut_slice());
+    }
+}
+
+const KINDS: [KindFns; MAX_COMPONENT_KINDS] = [
+    KindFns::of::<0>(),
+    KindFns::of::<1>(),
+    KindFns::of::<2>(),
+    KindFns::of::<3>(),
+    KindFns::of::<4>(),
+    KindFns::of::<5>(),
+    KindFns::of::<6>(),
+    KindFns::of::<7>(),
+    KindFns::of::<8>(),
+    KindFns::of::<9>(),
+    KindFns::of::<10>(),
+    KindFns::of::<11>(),
+    KindFns::of::<12>(),
+    KindFns::of::<13>(),
+    KindFns::of::<14>(),
+    KindFns::of::<15>(),
+];
+
+/// Registers the component kinds of a synthetic protocol. Must be added to both the client and the server
+pub struct SyntheticProtocolPlugin {
+    pub component_kinds: usize,
+}
+
+impl SyntheticProtocolPlugin {
+    pub fn new(config: &SyntheticWorldConfig) -> Self {
+        Self {
+            component_kinds: config.component_kinds(),
+        }
+    }
+}
+
+impl Plugin for SyntheticProtocolPlugin {
+    fn build(&self, app: &mut App) {
+        for kind in KINDS.iter().take(self.component_kinds) {
+            (kind.register)(app);
+        }
+    }
+}
+
+/// Spawns and updates the entities of a synthetic world on the server
+pub struct SyntheticWorldPlugin {
+    pub config: SyntheticWorldConfig,
+}
+
+impl SyntheticWorldPlugin {
+    pub fn new(config: SyntheticWorldConfig) -> Self {
+        Self { config }
+    }
+}
+
+/// State of the synthetic world
+#[derive(Resource)]
+struct SyntheticWorld {
+    rng: StdRng,
+    /// The entities of the world, with their component kinds
+    entities: Vec<(Entity, Vec<usize>)>,
+}
+
+impl Plugin for SyntheticWorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone());
+        app.insert_resource(SyntheticWorld {
+            rng: StdRng::seed_from_u64(self.config.seed),
+            entities: Vec::new(),
+        });
+        app.add_systems(FixedUpdate, update_synthetic_world.run_if(is_started));
+    }
+}
+
+fn spawn_entity(
+    world: &mut World,
+    config: &SyntheticWorldConfig,
+    rng: &mut StdRng,
+) -> (Entity, Vec<usize>) {
+    let component_kinds = config.component_kinds();
+    let kinds = sample(
+        rng,
+        component_kinds,
+        config.components_per_entity.min(component_kinds),
+    )
+    .into_vec();
+    let mut entity = world.spawn(Replicate::default());
+    for kind in kinds.iter() {
+        let mut payload = vec![0; config.component_sizes[*kind]];
+        rng.fill(payload.as_mut_slice());
+        (KINDS[*kind].insert)(&mut entity, payload);
+    }
+    (entity.id(), kinds)
+}
+
+/// Replace the entities that churned, spawn the missing entities and modify the components
+fn update_synthetic_world(world: &mut World) {
+    world.resource_scope(|world, mut state: Mut<SyntheticWorld>| {
+        let config = world.resource::<SyntheticWorldConfig>().clone();
+        let SyntheticWorld { rng, entities } = state.as_mut();
+        entities.retain(|(entity, _)| {
+            let churned = rng.gen_bool(config.churn_rate.clamp(0.0, 1.0) as f64);
+            if churned {
+                world.despawn(*entity);
+            }
+            !churned
+        });
+        while entities.len() < config.entities {
+            let spawned = spawn_entity(world, &config, rng);
+            entities.push(spawned);
+        }
+        for (entity, kinds) in entities.iter() {
+            let Some(mut entity) = world.get_entity_mut(*entity) else {
+                continue;
+            };
+            for kind in kinds {
+                if rng.gen_bool(config.update_rate.clamp(0.0, 1.0) as f64) {
+                    (KINDS[*kind].mutate)(&mut entity, rng);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use crate::prelude::client;
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::stepper::BevyStepper;
+
+    use super::*;
+
+    #[test]
+    fn test_synthetic_world() {
+        let config = SyntheticWorldConfig {
+            component_sizes: vec![4, 32, 128],
+            entities: 20,
+            components_per_entity: 2,
+            update_rate: 0.5,
+            churn_rate: 0.1,
+            seed: 1,
+        };
+        let frame_duration = Duration::from_millis(10);
+        let mut stepper = BevyStepper::new(
+            SharedConfig {
+                tick: TickConfig::new(frame_duration),
+                ..default()
+            },
+            ClientConfig::default(),
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .add_plugins(SyntheticProtocolPlugin::new(&config));
+        stepper.server_app.add_plugins((
+            SyntheticProtocolPlugin::new(&config),
+            SyntheticWorldPlugin::new(config.clone()),
+        ));
+        stepper.init();
+        for _ in 0..20 {
+            stepper.frame_step();
+        }
+
+        let state = stepper.server_app.world().resource::<SyntheticWorld>();
+        assert_eq!(state.entities.len(), config.entities);
+        let receiver = &stepper
+            .client_app
+            .world()
+            .resource::<client::ConnectionManager>()
+            .replication_receiver;
+        // the entities that were just spawned might not be replicated yet
+        let replicated = state
+            .entities
+            .iter()
+            .filter_map(|(entity, kinds)| {
+                let client_entity = receiver.remote_entity_map.get_local(*entity)?;
+                Some((client_entity, kinds))
+            })
+            .collect::<Vec<_>>();
+        assert!(replicated.len() > config.entities / 2);
+        for (client_entity, kinds) in replicated {
+            assert_eq!(kinds.len(), config.components_per_entity);
+            if kinds.contains(&2) {
+                let component = stepper
+                    .client_app
+                    .world()
+                    .get::<SyntheticComponent<2>>(client_entity)
+                    .unwrap();
+                assert_eq!(component.0.len(), 128);
+            }
+        }
+    }
+}